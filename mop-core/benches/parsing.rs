@@ -0,0 +1,91 @@
+//! Parsing cost of what discovery and browsing read off the network: device
+//! descriptions fetched for every SSDP response, and Browse responses.
+//!
+//! Run with `cargo bench -p mop-core`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use mop_core::upnp::{self, UpnpDevice};
+use std::hint::black_box;
+
+/// A MiniDLNA-style description with the ContentDirectory listed last.
+fn device_description() -> String {
+    let services: String = ["ConnectionManager", "X_MS_MediaReceiverRegistrar", "ContentDirectory"]
+        .iter()
+        .map(|service| {
+            format!(
+                "<service><serviceType>urn:schemas-upnp-org:service:{0}:1</serviceType>\
+                 <serviceId>urn:upnp-org:serviceId:{0}</serviceId>\
+                 <controlURL>/ctl/{0}</controlURL><eventSubURL>/evt/{0}</eventSubURL>\
+                 <SCPDURL>/{0}.xml</SCPDURL></service>",
+                service
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><root xmlns="urn:schemas-upnp-org:device-1-0"><specVersion><major>1</major><minor>0</minor></specVersion>
+<device><deviceType>urn:schemas-upnp-org:device:MediaServer:1</deviceType><friendlyName>nas: minidlna</friendlyName>
+<manufacturer>Justin Maggard</manufacturer><modelName>Windows Media Connect compatible (MiniDLNA)</modelName>
+<UDN>uuid:4d696e69-444c-164e-9d41-b827eb5a1c2e</UDN><serviceList>{}</serviceList></device></root>"#,
+        services
+    )
+}
+
+/// A Browse response with `count` children, escaped into the SOAP envelope
+/// the way servers send it.
+fn browse_response(count: usize) -> String {
+    let mut didl = String::from(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">"#,
+    );
+    for i in 0..count {
+        didl.push_str(&format!(
+            r#"<item id="64$1${i}" parentID="64$1" restricted="1"><dc:title>Track {i} &amp; more</dc:title><upnp:class>object.item.audioItem.musicTrack</upnp:class><res size="{size}" duration="0:04:{s:02}.000" protocolInfo="http-get:*:audio/flac:DLNA.ORG_OP=01;DLNA.ORG_CI=0">http://192.168.1.31:8200/MediaItems/{i}.flac</res></item>"#,
+            size = 20_000_000 + i,
+            s = i % 60
+        ));
+    }
+    didl.push_str("</DIDL-Lite>");
+    let escaped = didl.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:BrowseResponse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1"><Result>{}</Result><NumberReturned>{count}</NumberReturned><TotalMatches>{count}</TotalMatches><UpdateID>1</UpdateID></u:BrowseResponse></s:Body></s:Envelope>"#,
+        escaped
+    )
+}
+
+fn server() -> UpnpDevice {
+    UpnpDevice {
+        name: "nas".to_string(),
+        location: "http://192.168.1.31:8200/rootDesc.xml".to_string(),
+        base_url: "http://192.168.1.31:8200".to_string(),
+        device_client: None,
+        content_directory_url: Some("http://192.168.1.31:8200/ctl/ContentDir".to_string()),
+        udn: None,
+        token: None,
+        api_key: None,
+        tls: Default::default(),
+        credentials: None,
+        bypass_proxy: false,
+        headers: Default::default(),
+    }
+}
+
+fn description(c: &mut Criterion) {
+    let desc = device_description();
+    let location = "http://192.168.1.31:8200/rootDesc.xml";
+    c.bench_function("description/content_directory", |b| {
+        b.iter(|| upnp::find_service(black_box(&desc), location, |t| t.contains("ContentDirectory")))
+    });
+    c.bench_function("description/udn", |b| b.iter(|| upnp::extract_xml_value(black_box(&desc), "UDN")));
+}
+
+fn browse(c: &mut Criterion) {
+    let server = server();
+    for count in [200, 5000] {
+        let response = browse_response(count);
+        c.bench_function(&format!("browse/{}_items", count), |b| {
+            b.iter(|| upnp::parse_browse_response(black_box(&response), &server).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, description, browse);
+criterion_main!(benches);
@@ -0,0 +1,326 @@
+//! Hearing devices come and go between searches.
+//!
+//! Devices multicast a NOTIFY when they come on (`ssdp:alive`, repeated every
+//! so often while they stay up) and when they shut down cleanly
+//! (`ssdp:byebye`). [`listen`] stays joined to the SSDP group and turns those
+//! for the configured search targets into [`DiscoveryMessage`]s. A device's
+//! description is fetched the first time it's heard, and again if it turns up
+//! at another LOCATION; one that can't be fetched yet (a device still
+//! starting up) is tried again at its next announcement. Devices the search
+//! already found are passed in with [`Listener::found`] so their
+//! announcements aren't news. A device that stops announcing without a
+//! byebye is taken to have left once its CACHE-CONTROL max-age runs out.
+
+use crate::cancel::CancelToken;
+use crate::capture::{RECEIVE_POLL, SSDP_GROUP, SsdpKind, SsdpMessage};
+use crate::discovery::DiscoveryConfig;
+use crate::upnp::DiscoveryMessage;
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+/// How long an announcement lasts when it doesn't say, the least UPnP
+/// allows.
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(1800);
+
+/// A running [`listen`].
+pub struct Listener {
+    cancel: CancelToken,
+    found: Sender<(String, String)>,
+}
+
+impl Listener {
+    /// Count the device `udn` at `location` as already heard, as one a
+    /// search found.
+    pub fn found(&self, udn: &str, location: &str) {
+        let _ = self.found.send((udn.to_string(), location.to_string()));
+    }
+
+    /// Stop listening.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Listen for announcements until cancelled, reporting each device that
+/// arrives or leaves. Fails if the SSDP port can't be shared or the group
+/// can't be joined.
+pub fn listen(settings: DiscoveryConfig) -> Result<(Listener, Receiver<DiscoveryMessage>), String> {
+    let socket = join()?;
+    let (tx, rx) = mpsc::channel();
+    let (found, found_rx) = mpsc::channel();
+    let cancel = CancelToken::new();
+    let listener = Listener { cancel: cancel.clone(), found };
+    std::thread::spawn(move || run(&socket, &settings, &cancel, &found_rx, &tx));
+    Ok((listener, rx))
+}
+
+/// A socket on the SSDP port, shared with anything else listening there,
+/// in the group on every LAN interface.
+fn join() -> Result<UdpSocket, String> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(|e| e.to_string())?;
+    socket.set_reuse_address(true).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true).map_err(|e| e.to_string())?;
+    let port = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SSDP_GROUP.1);
+    socket.bind(&port.into()).map_err(|e| format!("Cannot listen on the SSDP port: {}", e))?;
+    let socket = UdpSocket::from(socket);
+
+    let mut joined = 0;
+    for interface in crate::network_interfaces::lan_interfaces() {
+        match socket.join_multicast_v4(&SSDP_GROUP.0, &interface.ip) {
+            Ok(()) => joined += 1,
+            Err(e) => tracing::debug!(target: "mop::ssdp", "Not listening on {}: {}", interface.name, e),
+        }
+    }
+    if joined == 0 {
+        socket
+            .join_multicast_v4(&SSDP_GROUP.0, &Ipv4Addr::UNSPECIFIED)
+            .map_err(|e| format!("Cannot join the SSDP group: {}", e))?;
+    }
+    socket.set_read_timeout(Some(RECEIVE_POLL)).map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+fn run(
+    socket: &UdpSocket,
+    settings: &DiscoveryConfig,
+    cancel: &CancelToken,
+    found: &Receiver<(String, String)>,
+    tx: &Sender<DiscoveryMessage>,
+) {
+    tracing::info!(target: "mop::ssdp", "Listening for devices announcing themselves");
+    let timeout = Duration::from_secs(settings.description_timeout_secs);
+    let mut devices = Devices::default();
+    let mut buffer = [0u8; 8192];
+    while !cancel.is_cancelled() {
+        for (udn, location) in found.try_iter() {
+            devices.found(udn, location);
+        }
+        for (udn, location) in devices.lapsed(Instant::now()) {
+            tracing::info!(target: "mop::ssdp", "{} stopped announcing itself", udn);
+            if tx.send(DiscoveryMessage::DeviceLeft { udn, location: Some(location) }).is_err() {
+                return;
+            }
+        }
+        let (length, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                tracing::warn!(target: "mop::ssdp", "Stopped listening for announcements: {}", e);
+                return;
+            }
+        };
+        let raw = String::from_utf8_lossy(&buffer[..length]);
+        let Some(message) = crate::capture::parse_ssdp_response(&raw) else { continue };
+        let sent = match devices.hear(&message, &settings.ssdp.search_targets, Instant::now()) {
+            Some(Change::Arrived { udn, location }) => {
+                let connection = settings.connection_for(&location, "");
+                let description = match describe(&connection, timeout) {
+                    Ok(description) => description,
+                    Err(e) => {
+                        tracing::debug!(target: "mop::ssdp", "No description yet from {}: {}", location, e);
+                        devices.forget(&udn);
+                        continue;
+                    }
+                };
+                let device = crate::capture::heard_device(&message, &location, &from.to_string(), Some(&description));
+                if crate::upnp::is_blocked(&device, &settings.blocklist) {
+                    continue;
+                }
+                tracing::info!(target: "mop::ssdp", "{} announced itself at {}", device.name, location);
+                tx.send(DiscoveryMessage::DeviceAnnounced(Box::new(device)))
+            }
+            Some(Change::Left { udn, location }) => {
+                tracing::info!(target: "mop::ssdp", "{} is leaving", udn);
+                tx.send(DiscoveryMessage::DeviceLeft { udn, location })
+            }
+            None => Ok(()),
+        };
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+fn describe(device: &crate::upnp::UpnpDevice, timeout: Duration) -> Result<String, String> {
+    let client = device.blocking_client_builder()?.timeout(timeout).build().map_err(|e| e.to_string())?;
+    let request = client.get(&device.location);
+    let response = crate::auth::send_blocking(&client, request, device.credentials.as_ref())?;
+    response.error_for_status().and_then(|response| response.text()).map_err(|e| e.to_string())
+}
+
+/// A device arriving or leaving, as far as announcements tell.
+#[derive(Debug, PartialEq)]
+enum Change {
+    Arrived { udn: String, location: String },
+    Left { udn: String, location: Option<String> },
+}
+
+/// What's been heard, so the announcements a device repeats (one per
+/// device and service type, and again every few minutes) count once.
+#[derive(Default)]
+struct Devices {
+    /// Each device that arrived, by UDN.
+    known: HashMap<String, Known>,
+    /// Devices that said they're leaving and haven't come back.
+    gone: HashSet<String>,
+}
+
+struct Known {
+    location: String,
+    /// When its last announcement runs out; none for a device the search
+    /// found that hasn't announced itself since.
+    expires: Option<Instant>,
+}
+
+impl Devices {
+    fn hear(&mut self, message: &SsdpMessage, targets: &[String], now: Instant) -> Option<Change> {
+        let target = message.target()?;
+        if !targets.iter().any(|t| t == target || t == "ssdp:all") {
+            return None;
+        }
+        let udn = message.header("USN")?.split("::").next()?.trim();
+        if udn.is_empty() {
+            return None;
+        }
+        match message.kind {
+            SsdpKind::Alive => {
+                let location = message.location()?;
+                let expires = Some(now + max_age(message));
+                let known = Known { location: location.to_string(), expires };
+                self.gone.remove(udn);
+                match self.known.insert(udn.to_string(), known) {
+                    Some(before) if before.location == location => None,
+                    _ => Some(Change::Arrived { udn: udn.to_string(), location: location.to_string() }),
+                }
+            }
+            SsdpKind::Byebye if self.gone.insert(udn.to_string()) => {
+                let location = self.known.remove(udn).map(|known| known.location);
+                Some(Change::Left { udn: udn.to_string(), location })
+            }
+            SsdpKind::Byebye | SsdpKind::Response => None,
+        }
+    }
+
+    /// Count `udn` at `location` as arrived without an announcement.
+    fn found(&mut self, udn: String, location: String) {
+        self.gone.remove(&udn);
+        self.known.entry(udn).or_insert(Known { location, expires: None });
+    }
+
+    /// Take the devices whose last announcement ran out by `now` as gone,
+    /// giving their UDNs and locations.
+    fn lapsed(&mut self, now: Instant) -> Vec<(String, String)> {
+        let lapsed: Vec<String> = self
+            .known
+            .iter()
+            .filter(|(_, known)| known.expires.is_some_and(|expires| expires <= now))
+            .map(|(udn, _)| udn.clone())
+            .collect();
+        lapsed
+            .into_iter()
+            .filter_map(|udn| {
+                let known = self.known.remove(&udn)?;
+                self.gone.insert(udn.clone());
+                Some((udn, known.location))
+            })
+            .collect()
+    }
+
+    /// Hear `udn`'s next announcement as if it were the first.
+    fn forget(&mut self, udn: &str) {
+        self.known.remove(udn);
+    }
+}
+
+/// How long an announcement lasts, from "CACHE-CONTROL: max-age=1800".
+fn max_age(message: &SsdpMessage) -> Duration {
+    let seconds = message.header("CACHE-CONTROL").and_then(|value| {
+        value.split(',').find_map(|directive| {
+            let (name, seconds) = directive.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("max-age").then(|| seconds.trim().parse().ok())?
+        })
+    });
+    seconds.map(Duration::from_secs).unwrap_or(DEFAULT_MAX_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::parse_ssdp_response;
+
+    fn notify(nts: &str, nt: &str, location: &str) -> SsdpMessage {
+        let raw = format!(
+            "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nCACHE-CONTROL: max-age=1800\r\nLOCATION: {}\r\n\
+             NT: {}\r\nNTS: {}\r\nUSN: uuid:4d696e69::{}\r\n\r\n",
+            location, nt, nts, nt
+        );
+        parse_ssdp_response(&raw).unwrap()
+    }
+
+    #[test]
+    fn repeated_announcements_count_once_and_byebye_removes_what_arrived() {
+        let targets = DiscoveryConfig::default().ssdp.search_targets;
+        let mut devices = Devices::default();
+        let now = Instant::now();
+        let mut hear = |nts: &str, nt: &str, location: &str| devices.hear(&notify(nts, nt, location), &targets, now);
+        let server = "urn:schemas-upnp-org:device:MediaServer:1";
+        let (first, moved) = ("http://10.0.0.5:8200/desc.xml", "http://10.0.0.9:8200/desc.xml");
+        let arrived = |location: &str| Some(Change::Arrived { udn: "uuid:4d696e69".into(), location: location.into() });
+
+        assert_eq!(hear("ssdp:alive", server, first), arrived(first));
+        // The same device announcing its root and its services, then again later
+        assert_eq!(hear("ssdp:alive", "upnp:rootdevice", first), None);
+        assert_eq!(hear("ssdp:alive", "urn:schemas-upnp-org:service:ContentDirectory:1", first), None);
+        assert_eq!(hear("ssdp:alive", server, first), None);
+        // A new address is a new description to fetch
+        assert_eq!(hear("ssdp:alive", server, moved), arrived(moved));
+
+        let left = Change::Left { udn: "uuid:4d696e69".into(), location: Some(moved.into()) };
+        assert_eq!(hear("ssdp:byebye", server, ""), Some(left));
+        assert_eq!(hear("ssdp:byebye", "upnp:rootdevice", ""), None);
+        assert_eq!(hear("ssdp:alive", server, moved), arrived(moved));
+        // Renderers aren't searched for by default
+        assert_eq!(hear("ssdp:alive", "urn:schemas-upnp-org:device:MediaRenderer:1", moved), None);
+
+        // A device whose description couldn't be fetched is heard afresh
+        devices.forget("uuid:4d696e69");
+        assert_eq!(devices.hear(&notify("ssdp:alive", server, moved), &targets, now), arrived(moved));
+    }
+
+    #[test]
+    fn devices_the_search_found_are_not_news_and_silent_ones_lapse() {
+        let targets = DiscoveryConfig::default().ssdp.search_targets;
+        let server = "urn:schemas-upnp-org:device:MediaServer:1";
+        let location = "http://10.0.0.5:8200/desc.xml";
+        let start = Instant::now();
+        let mut devices = Devices::default();
+
+        devices.found("uuid:4d696e69".to_string(), location.to_string());
+        // Found by the search and never announced: nothing to run out
+        assert!(devices.lapsed(start + Duration::from_secs(7200)).is_empty());
+        assert_eq!(devices.hear(&notify("ssdp:alive", server, location), &targets, start), None);
+
+        // max-age=1800, renewed by the next announcement
+        let later = start + Duration::from_secs(1000);
+        assert_eq!(devices.hear(&notify("ssdp:alive", "upnp:rootdevice", location), &targets, later), None);
+        assert!(devices.lapsed(start + Duration::from_secs(2000)).is_empty());
+        let lapsed = devices.lapsed(later + Duration::from_secs(1800));
+        assert_eq!(lapsed, vec![("uuid:4d696e69".to_string(), location.to_string())]);
+        assert!(devices.lapsed(later + Duration::from_secs(3600)).is_empty());
+        // Back again, it arrives afresh
+        let back = Some(Change::Arrived { udn: "uuid:4d696e69".into(), location: location.into() });
+        assert_eq!(devices.hear(&notify("ssdp:alive", server, location), &targets, later), back);
+
+        let message = |cache_control: &str| {
+            let raw = format!("NOTIFY * HTTP/1.1\r\nCACHE-CONTROL: {}\r\nNTS: ssdp:alive\r\n\r\n", cache_control);
+            parse_ssdp_response(&raw).unwrap()
+        };
+        assert_eq!(max_age(&message("no-cache, max-age = 120")), Duration::from_secs(120));
+        assert_eq!(max_age(&message("no-cache")), DEFAULT_MAX_AGE);
+    }
+}
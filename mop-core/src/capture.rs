@@ -0,0 +1,470 @@
+//! Recording what SSDP discovery sees on a network and replaying it later.
+//!
+//! A [`Capture`] holds the raw SSDP responses to MOP's searches and the
+//! device descriptions their LOCATIONs pointed to. Replaying one runs them
+//! through the same device building, blocklist and deduplication as live
+//! discovery, without a network, so a capture sent in with a bug report
+//! shows exactly what MOP made of that network.
+
+use crate::discovery::DiscoveryConfig;
+use crate::upnp::{DiscoveryMessage, UpnpDevice};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub(crate) const SSDP_GROUP: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 1900);
+
+/// How long a single receive waits before the deadline is looked at again.
+pub(crate) const RECEIVE_POLL: Duration = Duration::from_millis(250);
+
+/// Room for an SSDP packet; devices send a few hundred bytes. One that
+/// fills it was likely cut short by the receive.
+pub(crate) const MAX_PACKET: usize = 8192;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Capture {
+    /// Unix time the capture started.
+    pub recorded_at: u64,
+    /// The search targets that were sent.
+    pub search_targets: Vec<String>,
+    /// Every response, in the order it arrived.
+    pub responses: Vec<SsdpResponse>,
+    /// Device description XML by LOCATION; missing where the fetch failed.
+    pub descriptions: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsdpResponse {
+    /// Address the response came from.
+    pub from: String,
+    /// The response exactly as received.
+    pub raw: String,
+}
+
+impl SsdpResponse {
+    /// The response as parsed by [`parse_ssdp_response`].
+    pub fn parse(&self) -> Option<SsdpMessage> {
+        parse_ssdp_response(&self.raw)
+    }
+}
+
+/// What an SSDP packet says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SsdpKind {
+    /// An answer to an M-SEARCH.
+    Response,
+    /// A NOTIFY announcing a device, `ssdp:alive` or `ssdp:update`.
+    Alive,
+    /// A NOTIFY saying the device is going away.
+    Byebye,
+}
+
+/// An SSDP search response or NOTIFY, read by [`parse_ssdp_response`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsdpMessage {
+    pub kind: SsdpKind,
+    /// Header names as sent, with values trimmed and unfolded.
+    pub headers: Vec<(String, String)>,
+}
+
+impl SsdpMessage {
+    /// The value of header `name`, matched case-insensitively; the first
+    /// one when a device repeats it.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.header("LOCATION").filter(|location| !location.is_empty())
+    }
+
+    /// The search target answered, or for a NOTIFY the type announced.
+    /// Devices that leave both out still name it at the end of the USN,
+    /// "uuid:...::urn:schemas-upnp-org:device:MediaServer:1".
+    pub fn target(&self) -> Option<&str> {
+        let header = |name| self.header(name).filter(|value| !value.is_empty());
+        header("ST")
+            .or_else(|| header("NT"))
+            .or_else(|| header("USN")?.split_once("::").map(|(_, target)| target))
+            .filter(|target| !target.is_empty())
+    }
+}
+
+/// Read an SSDP search response or NOTIFY packet, taking what devices get
+/// wrong in their stride: any HTTP version in the status line, a missing or
+/// odd reason phrase, lower-case header names, spaces before the colon,
+/// values folded onto indented continuation lines and bare LF line endings.
+/// None for anything else: M-SEARCHes, error statuses, and what isn't SSDP.
+pub fn parse_ssdp_response(raw: &str) -> Option<SsdpMessage> {
+    let mut lines = raw.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line));
+    let status = lines.by_ref().map(str::trim).find(|line| !line.is_empty())?;
+    let mut words = status.split_whitespace();
+    let first = words.next()?;
+    let notify = first.eq_ignore_ascii_case("NOTIFY");
+    if notify {
+        // "NOTIFY * HTTP/1.1"
+        words.next()?;
+        if !is_http_version(words.next()?) {
+            return None;
+        }
+    } else if !is_http_version(first) || words.next()? != "200" {
+        return None;
+    }
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else { continue };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut message = SsdpMessage { kind: SsdpKind::Response, headers };
+    if notify {
+        let byebye = message.header("NTS").is_some_and(|nts| nts.eq_ignore_ascii_case("ssdp:byebye"));
+        message.kind = if byebye { SsdpKind::Byebye } else { SsdpKind::Alive };
+    }
+    Some(message)
+}
+
+/// A search response heard on the network, when discovery can go on from
+/// it: whole and UTF-8, a 200 answer, and with a LOCATION that is an
+/// http(s) URL to fetch the description from.
+pub(crate) fn live_response(packet: &[u8]) -> Option<SsdpMessage> {
+    if packet.len() >= MAX_PACKET {
+        return None;
+    }
+    let message = parse_ssdp_response(std::str::from_utf8(packet).ok()?)?;
+    let location = url::Url::parse(message.location()?).ok()?;
+    let fetchable = matches!(location.scheme(), "http" | "https") && location.host_str().is_some();
+    (message.kind == SsdpKind::Response && fetchable).then_some(message)
+}
+
+fn is_http_version(word: &str) -> bool {
+    word.get(..5).is_some_and(|prefix| prefix.eq_ignore_ascii_case("HTTP/"))
+}
+
+/// Search for `settings.ssdp_timeout_secs` and fetch the description of
+/// every device that answered. Blocks until both are done.
+pub fn record(settings: &DiscoveryConfig) -> Result<Capture, String> {
+    let mut capture = Capture {
+        recorded_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()),
+        search_targets: settings.ssdp.search_targets.clone(),
+        ..Default::default()
+    };
+    capture.responses = search(&settings.ssdp.search_targets, Duration::from_secs(settings.ssdp_timeout_secs))?;
+    tracing::info!(target: "mop::ssdp", "Captured {} SSDP responses", capture.responses.len());
+
+    let client = crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)?
+        .timeout(Duration::from_secs(settings.description_timeout_secs))
+        .build()
+        .map_err(|e| e.to_string())?;
+    for location in locations(&capture.responses) {
+        let fetched = client
+            .get(&location)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text());
+        match fetched {
+            Ok(description) => {
+                capture.descriptions.insert(location, description);
+            }
+            Err(e) => tracing::warn!(target: "mop::upnp", "No description from {}: {}", location, e),
+        }
+    }
+    Ok(capture)
+}
+
+/// Send an M-SEARCH for each of `search_targets` and collect every response
+/// that arrives within `timeout`.
+pub fn search(search_targets: &[String], timeout: Duration) -> Result<Vec<SsdpResponse>, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|e| format!("Cannot open SSDP socket: {}", e))?;
+    socket.set_read_timeout(Some(RECEIVE_POLL)).map_err(|e| e.to_string())?;
+
+    let mx = timeout.as_secs().clamp(1, 5);
+    for target in search_targets {
+        let search = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+            mx, target
+        );
+        socket
+            .send_to(search.as_bytes(), SSDP_GROUP)
+            .map_err(|e| format!("Cannot send SSDP search: {}", e))?;
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut responses = Vec::new();
+    let mut buffer = [0u8; MAX_PACKET];
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buffer) {
+            Ok((length, from)) => responses.push(SsdpResponse {
+                from: from.to_string(),
+                raw: String::from_utf8_lossy(&buffer[..length]).into_owned(),
+            }),
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+            Err(e) => return Err(format!("SSDP receive failed: {}", e)),
+        }
+    }
+    Ok(responses)
+}
+
+/// Run a capture through discovery: the devices it describes arrive as
+/// `DiscoveryMessage`s, like a live search that heard those responses.
+/// Only responses to the search targets in `settings` count, as they would
+/// live.
+pub fn replay(capture: Capture, settings: DiscoveryConfig) -> Receiver<DiscoveryMessage> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        tx.send(DiscoveryMessage::Started).ok();
+        let mut devices: Vec<UpnpDevice> = Vec::new();
+        for device in replayed_devices(&capture, &settings) {
+            if devices.iter().any(|known| known.location == device.location) {
+                continue;
+            }
+            tx.send(DiscoveryMessage::DeviceFound(Box::new(device.clone()))).ok();
+            devices.push(device);
+        }
+        tx.send(DiscoveryMessage::Phase1Complete).ok();
+        tx.send(DiscoveryMessage::Phase2Complete).ok();
+        tx.send(DiscoveryMessage::Phase3Complete).ok();
+        tx.send(DiscoveryMessage::AllComplete(devices)).ok();
+    });
+    rx
+}
+
+fn replayed_devices(capture: &Capture, settings: &DiscoveryConfig) -> Vec<UpnpDevice> {
+    let searched = capture
+        .responses
+        .iter()
+        .filter_map(|response| Some((response, response.parse()?)))
+        .filter(|(_, message)| message.kind != SsdpKind::Byebye)
+        .filter(|(_, message)| message.target().is_some_and(|st| settings.ssdp.search_targets.iter().any(|t| t == st)));
+
+    let mut devices = Vec::new();
+    for (response, message) in searched {
+        let Some(location) = message.location() else { continue };
+        let description = capture.descriptions.get(location).map(String::as_str);
+        let device = heard_device(&message, location, &response.from, description);
+        if crate::upnp::is_blocked(&device, &settings.blocklist) {
+            tracing::debug!(target: "mop::upnp", "Skipping blocklisted device: {}", device.name);
+            continue;
+        }
+        devices.push(device);
+    }
+    devices
+}
+
+/// The device an SSDP message from `from` is about, named by its
+/// description when there is one: its friendlyName, or its modelName for
+/// the devices that leave that out.
+pub(crate) fn heard_device(message: &SsdpMessage, location: &str, from: &str, description: Option<&str>) -> UpnpDevice {
+    let named = |tag| description.and_then(|desc| crate::upnp::extract_xml_value(desc, tag));
+    let friendly_name = named("friendlyName")
+        .filter(|name| !name.trim().is_empty())
+        .or_else(|| named("modelName"))
+        .unwrap_or_else(|| from.to_string());
+    let device_type = description
+        .and_then(|desc| crate::upnp::extract_xml_value(desc, "deviceType"))
+        .or_else(|| message.target().map(str::to_string))
+        .unwrap_or_default();
+    crate::upnp::described_device(location.to_string(), &friendly_name, device_type, description)
+}
+
+/// Each distinct LOCATION, in the order first heard.
+fn locations(responses: &[SsdpResponse]) -> Vec<String> {
+    let mut locations: Vec<String> = Vec::new();
+    for message in responses.iter().filter_map(SsdpResponse::parse) {
+        let Some(location) = message.location() else { continue };
+        if !locations.iter().any(|known| known == location) {
+            locations.push(location.to_string());
+        }
+    }
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_responses_with_their_descriptions() {
+        let response = |st: &str| SsdpResponse {
+            from: "192.168.1.5:1900".to_string(),
+            raw: format!(
+                "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nLocation: http://192.168.1.5:8200/desc.xml\r\nST: {}\r\nUSN: uuid:1234::{}\r\n\r\n",
+                st, st
+            ),
+        };
+        let mut capture = Capture {
+            responses: vec![
+                response("upnp:rootdevice"),
+                response("urn:schemas-upnp-org:device:MediaServer:1"),
+                response("urn:schemas-upnp-org:device:InternetGatewayDevice:1"),
+            ],
+            ..Default::default()
+        };
+        capture.descriptions.insert(
+            "http://192.168.1.5:8200/desc.xml".to_string(),
+            "<root><device><deviceType>urn:schemas-upnp-org:device:MediaServer:1</deviceType>\
+             <friendlyName>NAS</friendlyName><UDN>uuid:1234</UDN><serviceList><service>\
+             <serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType>\
+             <controlURL>/ctl/ContentDir</controlURL></service></serviceList></device></root>"
+                .to_string(),
+        );
+
+        let messages: Vec<DiscoveryMessage> = replay(capture, DiscoveryConfig::default()).into_iter().collect();
+        let Some(DiscoveryMessage::AllComplete(devices)) = messages.last() else { panic!("no AllComplete") };
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "NAS [urn:schemas-upnp-org:device:MediaServer:1]");
+        assert_eq!(devices[0].udn.as_deref(), Some("uuid:1234"));
+        assert_eq!(devices[0].content_directory_url.as_deref(), Some("http://192.168.1.5:8200/ctl/ContentDir"));
+
+        let message = parse_ssdp_response(&response("upnp:rootdevice").raw).unwrap();
+        let location = "http://192.168.1.5:8200/desc.xml";
+        let unnamed = "<root><device><friendlyName></friendlyName><modelName>ReadyDLNA</modelName></device></root>";
+        assert!(heard_device(&message, location, "192.168.1.5:1900", Some(unnamed)).name.starts_with("ReadyDLNA ["));
+        assert!(heard_device(&message, location, "192.168.1.5:1900", None).name.starts_with("192.168.1.5:1900 ["));
+    }
+
+    /// Responses in the shape common devices send them, headers and quirks
+    /// as they come: (device, raw response, LOCATION, ST).
+    const DEVICES: &[(&str, &str, &str, &str)] = &[
+        (
+            "MiniDLNA",
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1810\r\nDATE: Fri, 16 Oct 2026 09:12:44 GMT\r\n\
+             ST: urn:schemas-upnp-org:device:MediaServer:1\r\n\
+             USN: uuid:4d696e69-444c-164e-9d41-b827eb5a1c2e::urn:schemas-upnp-org:device:MediaServer:1\r\n\
+             EXT:\r\nSERVER: Debian DLNADOC/1.50 UPnP/1.0 MiniDLNA/1.3.3\r\n\
+             LOCATION: http://192.168.1.5:8200/rootDesc.xml\r\nContent-Length: 0\r\n\r\n",
+            "http://192.168.1.5:8200/rootDesc.xml",
+            "urn:schemas-upnp-org:device:MediaServer:1",
+        ),
+        (
+            "Plex Media Server",
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age = 1800\r\nEXT:\r\n\
+             LOCATION: http://192.168.1.10:32469/DeviceDescription.xml\r\n\
+             SERVER: Linux/5.15.0 UPnP/1.0 Plex Media Server/1.40.2\r\n\
+             ST: urn:schemas-upnp-org:device:MediaServer:1\r\n\
+             USN: uuid:a1b2c3d4-0000-1111-2222-333344445555::urn:schemas-upnp-org:device:MediaServer:1\r\n\r\n",
+            "http://192.168.1.10:32469/DeviceDescription.xml",
+            "urn:schemas-upnp-org:device:MediaServer:1",
+        ),
+        (
+            // Sonos adds its own headers, one of them starting with LOCATION
+            "Sonos",
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age = 1800\r\nEXT:\r\n\
+             LOCATION.SMARTSPEAKER.AUDIO: lc_4f8a2b\r\n\
+             LOCATION: http://192.168.1.31:1400/xml/device_description.xml\r\n\
+             SERVER: Linux UPnP/1.0 Sonos/79.1-52020 (ZPS23)\r\nST: urn:schemas-upnp-org:device:ZonePlayer:1\r\n\
+             USN: uuid:RINCON_48A6B8E1F2A401400::urn:schemas-upnp-org:device:ZonePlayer:1\r\n\
+             X-RINCON-HOUSEHOLD: Sonos_vJ1rk7YVSFQ0aQ9u\r\nX-RINCON-BOOTSEQ: 84\r\nBOOTID.UPNP.ORG: 84\r\n\r\n",
+            "http://192.168.1.31:1400/xml/device_description.xml",
+            "urn:schemas-upnp-org:device:ZonePlayer:1",
+        ),
+        (
+            "Samsung TV",
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nDATE: Fri, 16 Oct 2026 09:12:45 GMT\r\nEXT:\r\n\
+             LOCATION: http://192.168.1.40:9197/dmr\r\nSERVER: SHP, UPnP/1.0, Samsung UPnP SDK/1.0\r\n\
+             ST: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+             USN: uuid:0a1c3e2b-00fa-1000-b3c4-8c71f8d5e6a7::urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+             Content-Length: 0\r\nBOOTID.UPNP.ORG: 16\r\n\r\n",
+            "http://192.168.1.40:9197/dmr",
+            "urn:schemas-upnp-org:device:MediaRenderer:1",
+        ),
+        (
+            // Lower-case names and a bare LF, as small embedded stacks send
+            "Router",
+            "HTTP/1.1 200 OK\ncache-control: max-age=120\nst: upnp:rootdevice\n\
+             usn: uuid:upnp-InternetGatewayDevice-1_0-a0b1c2d3e4f5::upnp:rootdevice\next:\n\
+             server: Linux/4.4 UPnP/1.0 miniupnpd/2.2\nlocation: http://192.168.1.1:5000/rootDesc.xml\n\n",
+            "http://192.168.1.1:5000/rootDesc.xml",
+            "upnp:rootdevice",
+        ),
+    ];
+
+    #[test]
+    fn parses_the_responses_of_common_devices() {
+        for (device, raw, location, target) in DEVICES {
+            let message = parse_ssdp_response(raw).unwrap_or_else(|| panic!("{} wasn't parsed", device));
+            assert_eq!(message.kind, SsdpKind::Response, "{}", device);
+            assert_eq!(message.location(), Some(*location), "{}", device);
+            assert_eq!(message.target(), Some(*target), "{}", device);
+        }
+    }
+
+    #[test]
+    fn tolerates_what_devices_get_wrong() {
+        // A version other than 1.1, no reason phrase, a lower-case status
+        for status in ["HTTP/1.0 200 OK", "HTTP/1.1 200", "http/1.1 200 ok", "HTTP/1.1 200 Okay"] {
+            let raw = format!("{}\r\nLOCATION: http://nas/desc.xml\r\nST: upnp:rootdevice\r\n\r\n", status);
+            let location = parse_ssdp_response(&raw).and_then(|message| message.location().map(str::to_string));
+            assert_eq!(location.as_deref(), Some("http://nas/desc.xml"), "{}", status);
+        }
+
+        // A value folded onto the next line, spaces before the colon, no ST
+        let folded = "HTTP/1.1 200 OK\r\nSERVER: Linux/3.10 UPnP/1.0\r\n  Portable SDK for UPnP devices/1.6.19\r\n\
+                      LOCATION : http://192.168.1.7:49152/description.xml\r\n\
+                      USN: uuid:8a7b6c5d::urn:schemas-upnp-org:device:MediaServer:1\r\n\r\n";
+        let message = parse_ssdp_response(folded).unwrap();
+        assert_eq!(message.header("server"), Some("Linux/3.10 UPnP/1.0 Portable SDK for UPnP devices/1.6.19"));
+        assert_eq!(message.location(), Some("http://192.168.1.7:49152/description.xml"));
+        assert_eq!(message.target(), Some("urn:schemas-upnp-org:device:MediaServer:1"));
+
+        // NOTIFY packets name what they announce in NT
+        let notify = |nts: &str| {
+            format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nCACHE-CONTROL: max-age=1800\r\n\
+                 LOCATION: http://192.168.1.5:8200/rootDesc.xml\r\nNT: urn:schemas-upnp-org:device:MediaServer:1\r\n\
+                 NTS: {}\r\nUSN: uuid:4d69::urn:schemas-upnp-org:device:MediaServer:1\r\n\r\n",
+                nts
+            )
+        };
+        let alive = parse_ssdp_response(&notify("ssdp:alive")).unwrap();
+        assert_eq!(alive.kind, SsdpKind::Alive);
+        assert_eq!(alive.target(), Some("urn:schemas-upnp-org:device:MediaServer:1"));
+        assert_eq!(parse_ssdp_response(&notify("ssdp:byebye")).unwrap().kind, SsdpKind::Byebye);
+
+        // Searches, errors and junk aren't responses
+        let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\n\r\n";
+        assert_eq!(parse_ssdp_response(search), None);
+        assert_eq!(parse_ssdp_response("HTTP/1.1 404 Not Found\r\n\r\n"), None);
+        assert_eq!(parse_ssdp_response("\u{0}\u{1}garbage"), None);
+        assert_eq!(parse_ssdp_response(""), None);
+    }
+
+    #[test]
+    fn packets_off_the_network_have_to_be_whole_and_point_somewhere_fetchable() {
+        let response = |location: &str| {
+            format!("HTTP/1.1 200 OK\r\nLOCATION: {}\r\nST: upnp:rootdevice\r\nUSN: uuid:1234\r\n\r\n", location)
+        };
+        let heard = live_response(response("http://192.168.1.5:8200/desc.xml").as_bytes()).unwrap();
+        assert_eq!(heard.location(), Some("http://192.168.1.5:8200/desc.xml"));
+
+        assert_eq!(live_response(response("file:///etc/passwd").as_bytes()), None);
+        assert_eq!(live_response(response("http://").as_bytes()), None);
+        assert_eq!(live_response(response("not a url").as_bytes()), None);
+        assert_eq!(live_response(b"HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\n\r\n"), None);
+        // Cut short by the receive, or not text
+        let padded = format!("{}X-PADDING: {}\r\n\r\n", response("http://192.168.1.5/d.xml"), "x".repeat(MAX_PACKET));
+        assert_eq!(live_response(&padded.as_bytes()[..MAX_PACKET]), None);
+        let mut garbled = response("http://192.168.1.5/d.xml").into_bytes();
+        garbled[20] = 0xff;
+        assert_eq!(live_response(&garbled), None);
+        // An announcement isn't an answer to the search
+        let notify = concat!(
+            "NOTIFY * HTTP/1.1\r\nLOCATION: http://192.168.1.5/d.xml\r\n",
+            "NT: upnp:rootdevice\r\nNTS: ssdp:alive\r\n\r\n",
+        );
+        assert_eq!(live_response(notify.as_bytes()), None);
+    }
+}
@@ -0,0 +1,105 @@
+//! ObjectIDs of containers by title path, for callers that address folders
+//! as "/Music/Albums". Bounded: the least recently used paths are forgotten
+//! first, and a forgotten path is found again by browsing its parent.
+
+use std::collections::HashMap;
+
+/// Paths remembered by [`ContainerIds::default`].
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// The root container, which every ContentDirectory has.
+const ROOT: &str = "0";
+
+#[derive(Debug, Clone)]
+pub struct ContainerIds {
+    capacity: usize,
+    /// ObjectID and last use of each path.
+    ids: HashMap<Vec<String>, (String, u64)>,
+    clock: u64,
+}
+
+impl Default for ContainerIds {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ContainerIds {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), ids: HashMap::new(), clock: 0 }
+    }
+
+    /// The ObjectID of the container at `path`, marking it as used. The
+    /// root is always known.
+    pub fn get(&mut self, path: &[String]) -> Option<String> {
+        if path.is_empty() {
+            return Some(ROOT.to_string());
+        }
+        self.clock += 1;
+        let clock = self.clock;
+        self.ids.get_mut(path).map(|(id, used)| {
+            *used = clock;
+            id.clone()
+        })
+    }
+
+    pub fn contains(&self, path: &[String]) -> bool {
+        path.is_empty() || self.ids.contains_key(path)
+    }
+
+    /// Remember `id` for `path`, forgetting the least recently used path if
+    /// that goes over capacity.
+    pub fn insert(&mut self, path: Vec<String>, id: String) {
+        if path.is_empty() {
+            return;
+        }
+        self.clock += 1;
+        self.ids.insert(path, (id, self.clock));
+        if self.ids.len() > self.capacity
+            && let Some(oldest) = self.ids.iter().min_by_key(|(_, (_, used))| *used).map(|(path, _)| path.clone())
+        {
+            self.ids.remove(&oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Every remembered (path, ObjectID), least recently used first;
+    /// inserting them in this order into a new map keeps their recency.
+    pub fn entries(&self) -> Vec<(&[String], &str)> {
+        let mut entries: Vec<_> = self.ids.iter().collect();
+        entries.sort_by_key(|(_, (_, used))| *used);
+        entries.into_iter().map(|(path, (id, _))| (path.as_slice(), id.as_str())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[&str]) -> Vec<String> {
+        segments.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn forgets_the_least_recently_used_path() {
+        let mut ids = ContainerIds::new(2);
+        ids.insert(path(&["Music"]), "1".to_string());
+        ids.insert(path(&["Films"]), "2".to_string());
+        assert_eq!(ids.get(&path(&["Music"])).as_deref(), Some("1"));
+        ids.insert(path(&["Photos"]), "3".to_string());
+
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&path(&["Films"])));
+        assert_eq!(ids.get(&path(&["Music"])).as_deref(), Some("1"));
+        assert_eq!(ids.get(&[]).as_deref(), Some("0"));
+        let order: Vec<&str> = ids.entries().into_iter().map(|(_, id)| id).collect();
+        assert_eq!(order, ["3", "1"]);
+    }
+}
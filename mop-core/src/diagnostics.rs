@@ -0,0 +1,220 @@
+//! Network health checks: can this machine see a LAN, may it use
+//! multicast, does anything answer SSDP, and can each known device be
+//! reached and talked to. Each result says what to try when it fails.
+
+use crate::network_interfaces;
+use crate::permissions::{self, PermissionState};
+use crate::upnp::UpnpDevice;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+/// How long the SSDP check listens for answers.
+const SSDP_LISTEN: Duration = Duration::from_secs(2);
+
+/// Description fetches slower than this are worth a warning.
+const SLOW_DESCRIPTION: Duration = Duration::from_millis(1500);
+
+/// SOAP round trips slower than this make browsing feel sluggish.
+const SLOW_SOAP: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// Works, but not as well as it should.
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: String,
+    /// Name of the device checked, for per-device checks.
+    pub device: Option<String>,
+    pub outcome: Outcome,
+    pub detail: String,
+    /// What to try, for anything but a pass.
+    pub suggestion: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self { name: name.into(), device: None, outcome: Outcome::Pass, detail: detail.into(), suggestion: None }
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            device: None,
+            outcome: Outcome::Warn,
+            detail: detail.into(),
+            suggestion: Some(suggestion.into()),
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            device: None,
+            outcome: Outcome::Fail,
+            detail: detail.into(),
+            suggestion: Some(suggestion.into()),
+        }
+    }
+
+    fn of(self, device: &UpnpDevice) -> Self {
+        Self { device: Some(device.name.clone()), ..self }
+    }
+}
+
+pub enum DiagnosticsMessage {
+    Check(CheckResult),
+    Done,
+}
+
+/// Run every check on a background thread, network first, then each of
+/// `devices`. Results arrive as they finish.
+pub fn start(devices: Vec<UpnpDevice>) -> Receiver<DiagnosticsMessage> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _span = tracing::info_span!(target: "mop::net", "diagnostics").entered();
+        run(&devices, &tx);
+        tx.send(DiagnosticsMessage::Done).ok();
+    });
+    rx
+}
+
+fn run(devices: &[UpnpDevice], tx: &Sender<DiagnosticsMessage>) {
+    let send = |result: CheckResult| {
+        let device = result.device.as_deref().map(|device| format!("{} ", device)).unwrap_or_default();
+        tracing::info!(target: "mop::net", "{}{}: {:?}, {}", device, result.name, result.outcome, result.detail);
+        tx.send(DiagnosticsMessage::Check(result)).is_ok()
+    };
+
+    if !send(interface_check()) || !send(permission_check()) || !send(ssdp_check()) {
+        return;
+    }
+    for device in devices {
+        let reachable = description_check(device).of(device);
+        let reached = reachable.outcome != Outcome::Fail;
+        if !send(reachable) {
+            return;
+        }
+        if reached
+            && device.content_directory_url.is_some()
+            && !crate::jellyfin::handles(device)
+            && !send(soap_check(device).of(device))
+        {
+            return;
+        }
+    }
+}
+
+fn interface_check() -> CheckResult {
+    let name = "Network interface";
+    match network_interfaces::lan_interfaces().first() {
+        Some(lan) if lan.ip.is_private() => CheckResult::pass(name, format!("{} {}/{}", lan.name, lan.ip, lan.prefix)),
+        Some(lan) => CheckResult::warn(
+            name,
+            format!("{} {}/{} is not a private address", lan.name, lan.ip, lan.prefix),
+            "The port scan only covers private networks; add servers by URL or list their ports in [discovery.port_scan]",
+        ),
+        None => CheckResult::fail(
+            name,
+            "No interface on a local network",
+            "Connect to Wi-Fi or Ethernet; VPN and container interfaces don't count",
+        ),
+    }
+}
+
+fn permission_check() -> CheckResult {
+    let name = "Multicast access";
+    match permissions::check_local_network() {
+        PermissionState::Granted => CheckResult::pass(name, "Searches can be sent"),
+        PermissionState::Denied => CheckResult::fail(name, "Blocked by the system", permissions::LOCAL_NETWORK_HINT),
+        PermissionState::NetworkDown => CheckResult::fail(name, "No network to send on", permissions::NETWORK_DOWN_HINT),
+        PermissionState::Unknown => CheckResult::warn(
+            name,
+            "Could not tell whether multicast is allowed",
+            "Run with -vv to see why the probe failed",
+        ),
+    }
+}
+
+fn ssdp_check() -> CheckResult {
+    let name = "SSDP replies";
+    match crate::capture::search(&["ssdp:all".to_string()], SSDP_LISTEN) {
+        Ok(responses) if responses.is_empty() => CheckResult::warn(
+            name,
+            format!("Nothing answered within {}s", SSDP_LISTEN.as_secs()),
+            permissions::empty_network_hint().unwrap_or(
+                "A firewall may be dropping incoming UDP, or the router filters multicast between Wi-Fi and Ethernet; the port scan can still find servers",
+            ),
+        ),
+        Ok(responses) => {
+            let mut senders: Vec<&str> = responses
+                .iter()
+                .map(|response| response.from.rsplit_once(':').map_or(response.from.as_str(), |(host, _)| host))
+                .collect();
+            senders.sort_unstable();
+            senders.dedup();
+            let hosts = if senders.len() == 1 { "host" } else { "hosts" };
+            CheckResult::pass(name, format!("{} answers from {} {}", responses.len(), senders.len(), hosts))
+        }
+        Err(e) => CheckResult::fail(name, e, "Another program may hold the socket, or the network went away"),
+    }
+}
+
+fn description_check(device: &UpnpDevice) -> CheckResult {
+    let name = "Description";
+    let client = device
+        .blocking_client_builder()
+        .and_then(|builder| builder.timeout(Duration::from_secs(5)).build().map_err(|e| e.to_string()));
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => return CheckResult::fail(name, e, "Check the TLS settings for this server in [servers]"),
+    };
+
+    let started = Instant::now();
+    let fetched = crate::auth::send_blocking(&client, client.get(&device.location), device.credentials.as_ref());
+    let elapsed = started.elapsed();
+    match fetched {
+        Ok(response) if response.status().is_success() && elapsed > SLOW_DESCRIPTION => CheckResult::warn(
+            name,
+            format!("Reachable, but took {} ms", elapsed.as_millis()),
+            "The device may be waking from standby, or the network is congested",
+        ),
+        Ok(response) if response.status().is_success() => {
+            CheckResult::pass(name, format!("{} in {} ms", response.status(), elapsed.as_millis()))
+        }
+        Ok(response) => CheckResult::fail(
+            name,
+            format!("{} from {}", response.status(), device.location),
+            "The server is up but refused the request; check its sharing settings or credentials in [servers]",
+        ),
+        Err(e) => CheckResult::fail(
+            name,
+            e,
+            "The device may be off, asleep or on another subnet; if its address changed, rescan",
+        ),
+    }
+}
+
+fn soap_check(device: &UpnpDevice) -> CheckResult {
+    let name = "SOAP round trip";
+    let started = Instant::now();
+    let answered = crate::upnp::system_update_id(device);
+    let elapsed = started.elapsed();
+    match answered {
+        Ok(_) if elapsed > SLOW_SOAP => CheckResult::warn(
+            name,
+            format!("Round trip took {} ms", elapsed.as_millis()),
+            "Large folders will load slowly; the server may be busy indexing",
+        ),
+        Ok(_) => CheckResult::pass(name, format!("Round trip {} ms", elapsed.as_millis())),
+        Err(e) => CheckResult::fail(
+            name,
+            e,
+            "The description loads but ContentDirectory doesn't answer; the SOAP inspector shows the exchange",
+        ),
+    }
+}
@@ -0,0 +1,125 @@
+// Discovery settings: how long SSDP listens, which search targets it sends,
+// and which hosts and ports the fallback scan probes. Front-ends usually
+// load these from their own config file.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryConfig {
+    #[serde(default = "default_ssdp_timeout_secs")]
+    pub ssdp_timeout_secs: u64,
+    #[serde(default = "default_description_timeout_secs")]
+    pub description_timeout_secs: u64,
+    #[serde(default = "default_probe_timeout_ms")]
+    pub probe_timeout_ms: u64,
+    /// Devices to hide: matched against the name (substring), host or location.
+    #[serde(default)]
+    pub blocklist: Vec<String>,
+    #[serde(default)]
+    pub ssdp: SsdpConfig,
+    #[serde(default)]
+    pub port_scan: PortScanConfig,
+    /// How to fetch device descriptions (TLS, credentials, headers and
+    /// proxy), by the `[servers]` pattern they're for. Filled in by the
+    /// front-end, not read from `[discovery]`.
+    #[serde(skip)]
+    pub connections: Vec<(String, crate::upnp::UpnpDevice)>,
+}
+
+impl DiscoveryConfig {
+    /// What to reach a device at `location` calling itself `name` with,
+    /// `name` being empty when only its address is known.
+    pub fn connection_for(&self, location: &str, name: &str) -> crate::upnp::UpnpDevice {
+        let connection = self
+            .connections
+            .iter()
+            .find(|(pattern, _)| crate::upnp::location_matches(name, location, pattern))
+            .map(|(_, connection)| connection.clone())
+            .unwrap_or_default();
+        crate::upnp::UpnpDevice { location: location.to_string(), ..connection }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsdpConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_search_targets")]
+    pub search_targets: Vec<String>,
+    /// Keep listening after the search for devices announcing themselves
+    /// or saying they're leaving.
+    #[serde(default = "default_true")]
+    pub listen: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortScanConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_scan_ports")]
+    pub ports: Vec<u16>,
+    /// IPv4 CIDR ranges to scan. Empty scans the local /24.
+    #[serde(default)]
+    pub ranges: Vec<String>,
+}
+
+fn default_ssdp_timeout_secs() -> u64 {
+    5
+}
+
+fn default_description_timeout_secs() -> u64 {
+    10
+}
+
+fn default_probe_timeout_ms() -> u64 {
+    500
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_search_targets() -> Vec<String> {
+    vec![
+        "upnp:rootdevice".to_string(),
+        "urn:schemas-upnp-org:device:MediaServer:1".to_string(),
+    ]
+}
+
+fn default_scan_ports() -> Vec<u16> {
+    vec![32469, 32400, 8096, 8920]
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            ssdp_timeout_secs: default_ssdp_timeout_secs(),
+            description_timeout_secs: default_description_timeout_secs(),
+            probe_timeout_ms: default_probe_timeout_ms(),
+            blocklist: Vec::new(),
+            ssdp: SsdpConfig::default(),
+            port_scan: PortScanConfig::default(),
+            connections: Vec::new(),
+        }
+    }
+}
+
+impl Default for SsdpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            search_targets: default_search_targets(),
+            listen: true,
+        }
+    }
+}
+
+impl Default for PortScanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ports: default_scan_ports(),
+            ranges: Vec::new(),
+        }
+    }
+}
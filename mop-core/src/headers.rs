@@ -0,0 +1,68 @@
+// Per-server HTTP headers, for servers that answer only some clients: a
+// User-Agent they know, or a header their reverse proxy wants. Sent as
+// default headers by every client that talks to such a device, and with
+// the description fetch during discovery, before the device is known.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::BTreeMap;
+
+/// Header name to value, as in `[servers] headers`.
+pub type Headers = BTreeMap<String, String>;
+
+fn header_map(headers: &Headers) -> Result<HeaderMap, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| format!("'{}' is not an HTTP header name", name))?;
+            let value = HeaderValue::from_str(value.trim())
+                .map_err(|_| format!("Invalid value for the {} header", name))?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Check a `[servers] headers` table from the config.
+pub fn validate(headers: &Headers) -> Result<(), String> {
+    header_map(headers).map(|_| ())
+}
+
+/// Send `headers` with every request from an async client builder.
+pub fn configure(builder: reqwest::ClientBuilder, headers: &Headers) -> Result<reqwest::ClientBuilder, String> {
+    if headers.is_empty() {
+        return Ok(builder);
+    }
+    Ok(builder.default_headers(header_map(headers)?))
+}
+
+/// Blocking version of `configure`.
+pub fn configure_blocking(
+    builder: reqwest::blocking::ClientBuilder,
+    headers: &Headers,
+) -> Result<reqwest::blocking::ClientBuilder, String> {
+    if headers.is_empty() {
+        return Ok(builder);
+    }
+    Ok(builder.default_headers(header_map(headers)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_names_and_values_are_checked() {
+        let mut headers = Headers::new();
+        headers.insert("User-Agent".to_string(), "Kodi/21.0 (X11; Linux x86_64)".to_string());
+        headers.insert("X-Forwarded-For ".to_string(), " 192.168.1.20".to_string());
+        let map = header_map(&headers).unwrap();
+        assert_eq!(map["user-agent"], "Kodi/21.0 (X11; Linux x86_64)");
+        assert_eq!(map["x-forwarded-for"], "192.168.1.20");
+
+        headers.insert("Bad Name".to_string(), "x".to_string());
+        assert_eq!(validate(&headers).unwrap_err(), "'Bad Name' is not an HTTP header name");
+        headers.remove("Bad Name");
+        headers.insert("X-Token".to_string(), "line\nbreak".to_string());
+        assert_eq!(validate(&headers).unwrap_err(), "Invalid value for the x-token header");
+    }
+}
@@ -0,0 +1,353 @@
+// Native browsing for Jellyfin and Emby through their REST API, for servers
+// found by the port scan (they don't expose a ContentDirectory by default).
+// Both servers speak the same dialect for everything used here.
+//
+// Authentication is a static API key from `[servers]`. API keys aren't tied
+// to a user, so libraries are listed as the server's first administrator,
+// and playback is reported as theirs too: start, progress and stop, so
+// watched status and resume points match what other clients show.
+
+use crate::{DirectoryItem, FileMetadata};
+use crate::upnp::UpnpDevice;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct User {
+    id: String,
+    #[serde(default)]
+    policy: Policy,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Policy {
+    #[serde(default)]
+    is_administrator: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ItemsResponse {
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Item {
+    id: String,
+    name: String,
+    #[serde(default)]
+    is_folder: bool,
+    media_type: Option<String>,
+    container: Option<String>,
+    /// In 100ns ticks.
+    run_time_ticks: Option<u64>,
+    #[serde(default)]
+    media_sources: Vec<MediaSource>,
+    width: Option<u32>,
+    height: Option<u32>,
+    #[serde(rename = "Type")]
+    kind: Option<String>,
+    album: Option<String>,
+    album_artist: Option<String>,
+    #[serde(default)]
+    artists: Vec<String>,
+    index_number: Option<u32>,
+    /// Image types the item has ("Primary", "Thumb") and their tags.
+    #[serde(default)]
+    image_tags: std::collections::HashMap<String, String>,
+}
+
+impl Item {
+    /// The UPnP class of the same kind of object, so music libraries read
+    /// the same whichever way they are browsed.
+    fn upnp_class(&self) -> Option<String> {
+        let class = match self.kind.as_deref()? {
+            "MusicArtist" => "object.container.person.musicArtist",
+            "MusicAlbum" => "object.container.album.musicAlbum",
+            "MusicGenre" => "object.container.genre.musicGenre",
+            "Audio" => "object.item.audioItem.musicTrack",
+            "Movie" => "object.item.videoItem.movie",
+            "Episode" | "Video" | "MusicVideo" => "object.item.videoItem",
+            "Photo" => "object.item.imageItem.photo",
+            _ if self.is_folder => "object.container.storageFolder",
+            _ => return None,
+        };
+        Some(class.to_string())
+    }
+
+    fn music_tags(&self) -> Option<crate::MusicTags> {
+        let tags = crate::MusicTags {
+            artist: self.artists.first().or(self.album_artist.as_ref()).cloned(),
+            album: self.album.clone(),
+            track: self.index_number.filter(|_| self.kind.as_deref() == Some("Audio")),
+        };
+        (tags != crate::MusicTags::default()).then_some(tags)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MediaSource {
+    size: Option<u64>,
+}
+
+/// Whether browsing this device goes through the native API: it needs an API
+/// key, and devices with a ContentDirectory keep using UPnP.
+pub fn handles(device: &UpnpDevice) -> bool {
+    device.api_key.is_some() && device.content_directory_url.is_none()
+}
+
+struct Client<'a> {
+    device: &'a UpnpDevice,
+    api_key: &'a str,
+    http: reqwest::blocking::Client,
+}
+
+impl<'a> Client<'a> {
+    fn new(device: &'a UpnpDevice) -> Result<Self, String> {
+        let api_key = device.api_key.as_deref().ok_or("No API key configured for this server")?;
+        let http = device
+            .blocking_client_builder()?
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self { device, api_key, http })
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<T, String> {
+        let (url, response) = self.send(reqwest::Method::GET, path, query)?;
+        response.json().map_err(|e| format!("Unexpected answer from {}: {}", url, e))
+    }
+
+    fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<(String, reqwest::blocking::Response), String> {
+        let url = format!("{}{}", self.device.base_url.trim_end_matches('/'), path);
+        tracing::debug!(target: "mop::net", "{} {}", method, url);
+        let response = self
+            .http
+            .request(method, &url)
+            .header("X-Emby-Token", self.api_key)
+            .query(query)
+            .send()
+            .map_err(|e| format!("Request to {} failed: {}", self.device.base_url, e))?;
+
+        match response.status().as_u16() {
+            200..=299 => Ok((url, response)),
+            401 | 403 => Err("The server rejected the API key".to_string()),
+            _ => Err(format!("{} returned {}", url, response.status())),
+        }
+    }
+
+    fn user_id(&self) -> Result<String, String> {
+        let users: Vec<User> = self.get("/Users", &[])?;
+        users
+            .iter()
+            .find(|user| user.policy.is_administrator)
+            .or(users.first())
+            .map(|user| user.id.clone())
+            .ok_or_else(|| "The server has no users".to_string())
+    }
+
+    fn to_directory_item(&self, item: Item) -> DirectoryItem {
+        let class = item.upnp_class();
+        let music = item.music_tags();
+        if item.is_folder {
+            return DirectoryItem {
+                id: Some(item.id),
+                name: item.name,
+                is_directory: true,
+                url: None,
+                metadata: None,
+                class,
+                music,
+            };
+        }
+        DirectoryItem {
+            id: Some(item.id.clone()),
+            url: Some(stream_url(&self.device.base_url, &item, self.api_key)),
+            metadata: Some(FileMetadata {
+                size: item.media_sources.first().and_then(|source| source.size),
+                duration: item.run_time_ticks.map(format_ticks),
+                format: mime_for(&item),
+                resolution: item.width.zip(item.height).map(|(width, height)| format!("{}x{}", width, height)),
+                thumbnail: thumbnail_url(&self.device.base_url, &item, self.api_key),
+                dlna_flags: None,
+                resources: Vec::new(),
+            }),
+            name: item.name,
+            is_directory: false,
+            class,
+            music,
+        }
+    }
+}
+
+/// Children of a container. "0" is the root, which lists the user's
+/// libraries.
+pub fn browse(device: &UpnpDevice, container_id: &str) -> Result<Vec<DirectoryItem>, String> {
+    let client = Client::new(device)?;
+    let user_id = client.user_id()?;
+
+    let response: ItemsResponse = if container_id == "0" {
+        client.get(&format!("/Users/{}/Views", user_id), &[])?
+    } else {
+        client.get(
+            &format!("/Users/{}/Items", user_id),
+            &[("ParentId", container_id), ("SortBy", "SortName"), ("Fields", "MediaSources")],
+        )?
+    };
+
+    Ok(response.items.into_iter().map(|item| client.to_directory_item(item)).collect())
+}
+
+/// Playable items whose name contains `query`, anywhere on the server.
+pub fn search(device: &UpnpDevice, query: &str) -> Result<Vec<DirectoryItem>, String> {
+    let client = Client::new(device)?;
+    let user_id = client.user_id()?;
+    let response: ItemsResponse = client.get(
+        &format!("/Users/{}/Items", user_id),
+        &[
+            ("SearchTerm", query),
+            ("Recursive", "true"),
+            ("IncludeItemTypes", "Movie,Episode,Audio,MusicVideo,Video"),
+            ("Fields", "MediaSources"),
+        ],
+    )?;
+    Ok(response.items.into_iter().map(|item| client.to_directory_item(item)).collect())
+}
+
+/// One playback of an item, as the server is told about it.
+pub struct PlaybackReport {
+    device: UpnpDevice,
+    user_id: String,
+    item_id: String,
+    /// Ties the progress and stop reports to the start.
+    session_id: String,
+}
+
+impl PlaybackReport {
+    /// Tell the server `item_id` started playing.
+    pub fn start(device: &UpnpDevice, item_id: &str) -> Result<Self, String> {
+        let client = Client::new(device)?;
+        let user_id = client.user_id()?;
+        let started = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let session_id = format!("mop-{}-{}", std::process::id(), started.as_millis());
+        let report = Self { device: device.clone(), user_id, item_id: item_id.to_string(), session_id };
+        report.send(
+            reqwest::Method::POST,
+            "",
+            &[("CanSeek", "true".to_string()), ("PlayMethod", "DirectPlay".to_string())],
+        )?;
+        Ok(report)
+    }
+
+    /// How far into the item the player is.
+    pub fn progress(&self, position: Duration, paused: bool) -> Result<(), String> {
+        let query = [("PositionTicks", ticks(position).to_string()), ("IsPaused", paused.to_string())];
+        self.send(reqwest::Method::POST, "/Progress", &query)
+    }
+
+    /// Playback ended at `position`. The server marks the item played when
+    /// that's near the end, and otherwise keeps it as the resume point.
+    pub fn stop(&self, position: Duration) -> Result<(), String> {
+        self.send(reqwest::Method::DELETE, "", &[("PositionTicks", ticks(position).to_string())])
+    }
+
+    fn send(&self, method: reqwest::Method, suffix: &str, query: &[(&str, String)]) -> Result<(), String> {
+        let client = Client::new(&self.device)?;
+        let path = format!("/Users/{}/PlayingItems/{}{}", self.user_id, self.item_id, suffix);
+        let mut query: Vec<(&str, &str)> = query.iter().map(|(name, value)| (*name, value.as_str())).collect();
+        query.push(("PlaySessionId", &self.session_id));
+        client.send(method, &path, &query).map(|_| ())
+    }
+}
+
+/// A duration in the server's 100ns ticks.
+fn ticks(duration: Duration) -> u64 {
+    duration.as_micros() as u64 * 10
+}
+
+/// A direct-play URL the player can open without any further auth.
+fn stream_url(base_url: &str, item: &Item, api_key: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let path = match item.media_type.as_deref() {
+        Some("Video") => format!("/Videos/{}/stream", item.id),
+        Some("Audio") => format!("/Audio/{}/stream", item.id),
+        _ => format!("/Items/{}/Download", item.id),
+    };
+    let mut url = url::Url::parse(&format!("{}{}", base_url, path)).expect("base URL comes from discovery");
+    if !path.ends_with("/Download") {
+        url.query_pairs_mut().append_pair("static", "true");
+    }
+    url.query_pairs_mut().append_pair("api_key", api_key);
+    url.to_string()
+}
+
+/// The item's poster or cover, scaled down by the server, if it has one.
+fn thumbnail_url(base_url: &str, item: &Item, api_key: &str) -> Option<String> {
+    let tag = item.image_tags.get("Primary")?;
+    let path = format!("{}/Items/{}/Images/Primary", base_url.trim_end_matches('/'), item.id);
+    let mut url = url::Url::parse(&path).ok()?;
+    url.query_pairs_mut().append_pair("maxWidth", "320").append_pair("tag", tag).append_pair("api_key", api_key);
+    Some(url.to_string())
+}
+
+fn mime_for(item: &Item) -> Option<String> {
+    // Containers are reported like "mov,mp4,m4a,3gp"; the first one is enough
+    let container = item.container.as_deref()?.split(',').next()?;
+    crate::mime::for_path(std::path::Path::new(&format!("file.{}", container)))
+        .map(str::to_string)
+        .or_else(|| item.media_type.as_deref().map(|kind| format!("{}/{}", kind.to_lowercase(), container)))
+}
+
+/// Ticks to the "H:MM:SS.mmm" durations UPnP servers report.
+fn format_ticks(ticks: u64) -> String {
+    let millis = ticks / 10_000;
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_items_into_playable_entries() {
+        let response: ItemsResponse = serde_json::from_str(
+            r#"{"Items":[
+                {"Id":"f1","Name":"Movies","IsFolder":true,"Type":"CollectionFolder"},
+                {"Id":"a1b2","Name":"Blade Runner","IsFolder":false,"MediaType":"Video",
+                 "Container":"mkv","RunTimeTicks":70260000000,"MediaSources":[{"Size":4096}],
+                 "ImageTags":{"Primary":"9f3c"}}
+            ],"TotalRecordCount":2}"#,
+        )
+        .unwrap();
+        let item = &response.items[1];
+
+        assert!(response.items[0].is_folder);
+        assert_eq!(
+            stream_url("http://192.168.1.5:8096/", item, "k3y"),
+            "http://192.168.1.5:8096/Videos/a1b2/stream?static=true&api_key=k3y"
+        );
+        assert_eq!(mime_for(item).as_deref(), Some("video/x-matroska"));
+        assert_eq!(
+            thumbnail_url("http://192.168.1.5:8096/", item, "k3y").as_deref(),
+            Some("http://192.168.1.5:8096/Items/a1b2/Images/Primary?maxWidth=320&tag=9f3c&api_key=k3y")
+        );
+        assert_eq!(thumbnail_url("http://192.168.1.5:8096/", &response.items[0], "k3y"), None);
+        assert_eq!(format_ticks(70_260_000_000), "1:57:06.000");
+        assert_eq!(ticks(Duration::from_millis(7_026_000)), 70_260_000_000);
+    }
+}
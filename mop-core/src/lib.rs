@@ -0,0 +1,125 @@
+//! UPnP/DLNA media server discovery and browsing, without any UI.
+//!
+//! Discovery runs SSDP searches plus an optional port scan and streams
+//! [`upnp::DiscoveryMessage`]s as devices turn up. Found servers are browsed
+//! through their ContentDirectory service (or natively, for Jellyfin and
+//! Emby), yielding [`DirectoryItem`]s with playable URLs.
+//!
+//! ```no_run
+//! use mop_core::discovery::DiscoveryConfig;
+//! use mop_core::upnp::{self, DiscoveryMessage};
+//! use mop_core::container_ids::ContainerIds;
+//!
+//! for message in upnp::start_discovery(DiscoveryConfig::default()) {
+//!     if let DiscoveryMessage::AllComplete(servers) = message {
+//!         for server in &servers {
+//!             let (items, error) = upnp::browse_directory(server, &[], &mut ContainerIds::default());
+//!             println!("{}: {} items, error: {:?}", server.name, items.len(), error);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! HTTP clients honour the process-wide proxy from [`proxy::set`] and each
+//! device's TLS, credentials and proxy bypass settings.
+
+pub mod announcements;
+pub mod auth;
+pub mod cancel;
+pub mod capture;
+pub mod container_ids;
+pub mod diagnostics;
+pub mod discovery;
+pub mod headers;
+pub mod jellyfin;
+pub mod metrics;
+pub mod mime;
+pub mod network_interfaces;
+pub mod permissions;
+pub mod proxy;
+pub mod soap_log;
+pub mod stream;
+pub mod tls;
+pub mod upnp;
+
+/// One entry in a browsed container: a sub-container or a playable file.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct DirectoryItem {
+    /// ContentDirectory ObjectID (or the native API's id); containers are
+    /// browsed by it.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    pub is_directory: bool,
+    /// Stream URL for files, with any server token already applied.
+    pub url: Option<String>,
+    pub metadata: Option<FileMetadata>,
+    /// The UPnP class, "object.item.audioItem.musicTrack" and the like.
+    /// Native APIs map their own item types onto these.
+    #[serde(default)]
+    pub class: Option<String>,
+    /// Artist, album and track number, for music tracks and albums.
+    #[serde(default)]
+    pub music: Option<MusicTags>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MusicTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct FileMetadata {
+    pub size: Option<u64>,
+    /// As reported by the server, usually "H:MM:SS(.fff)".
+    pub duration: Option<String>,
+    /// DLNA protocolInfo or a plain MIME type.
+    pub format: Option<String>,
+    /// Video or image size, "1920x1080".
+    #[serde(default)]
+    pub resolution: Option<String>,
+    /// A small picture of the item: a video frame, cover art.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    /// The DLNA flags from the protocolInfo, "DLNA.ORG_PN=...;DLNA.ORG_OP=01".
+    #[serde(default)]
+    pub dlna_flags: Option<String>,
+    /// Every playable `<res>` of the file when the server lists more than
+    /// one, the one described above first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<Resource>,
+}
+
+impl FileMetadata {
+    /// The protocolInfo to hand a renderer along with the URL.
+    pub fn protocol_info(&self) -> Option<String> {
+        Some(protocol_info(self.format.as_deref()?, self.dlna_flags.as_deref()))
+    }
+}
+
+/// One `<res>` of a file: the same content at another quality or in
+/// another format, as servers that transcode list it.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Resource {
+    pub url: String,
+    pub size: Option<u64>,
+    /// The MIME type.
+    pub format: Option<String>,
+    pub resolution: Option<String>,
+    /// Bytes per second, as DIDL-Lite gives it.
+    pub bitrate: Option<u64>,
+    pub dlna_flags: Option<String>,
+}
+
+impl Resource {
+    pub fn protocol_info(&self) -> Option<String> {
+        Some(protocol_info(self.format.as_deref()?, self.dlna_flags.as_deref()))
+    }
+}
+
+/// "http-get:*:video/mp4:DLNA.ORG_OP=01", from the MIME type and flags.
+fn protocol_info(mime: &str, dlna_flags: Option<&str>) -> String {
+    format!("http-get:*:{}:{}", mime, dlna_flags.unwrap_or("*"))
+}
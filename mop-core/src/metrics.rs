@@ -0,0 +1,171 @@
+//! How long discovery and browsing took: each SSDP search and port scan,
+//! each device description fetch and each Browse page, kept for the
+//! process's lifetime. Summaries per operation show where a slow network
+//! loses its time, and the JSON export lets two networks (or two routers)
+//! be compared side by side.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Samples kept; older ones are dropped.
+pub const SAMPLES_KEPT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// One SSDP search target, from sending to the end of listening.
+    Ssdp,
+    PortScan,
+    /// Fetching a device description.
+    Description,
+    /// One page of a container.
+    Browse,
+}
+
+impl Operation {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Operation::Ssdp => "SSDP search",
+            Operation::PortScan => "Port scan",
+            Operation::Description => "Description",
+            Operation::Browse => "Browse",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub operation: Operation,
+    /// The search target, device location, or "location#container".
+    pub subject: String,
+    pub millis: u64,
+    pub ok: bool,
+    /// Unix time it finished.
+    pub at: u64,
+}
+
+/// Every sample of one operation on one subject.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub operation: Operation,
+    pub subject: String,
+    pub count: usize,
+    pub failures: usize,
+    pub last_ms: u64,
+    pub min_ms: u64,
+    pub median_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Export<'a> {
+    exported_at: u64,
+    /// The LAN the samples were taken on, as "interface address/prefix".
+    network: Option<String>,
+    summaries: Vec<Summary>,
+    samples: &'a [Sample],
+}
+
+fn samples() -> &'static Mutex<VecDeque<Sample>> {
+    static SAMPLES: OnceLock<Mutex<VecDeque<Sample>>> = OnceLock::new();
+    SAMPLES.get_or_init(Default::default)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+pub fn record(operation: Operation, subject: impl Into<String>, duration: Duration, ok: bool) {
+    let sample = Sample { operation, subject: subject.into(), millis: duration.as_millis() as u64, ok, at: unix_now() };
+    let outcome = if ok { "" } else { ", failed" };
+    tracing::debug!(target: "mop::net", "{} {}: {} ms{}", operation.label(), sample.subject, sample.millis, outcome);
+    let Ok(mut samples) = samples().lock() else { return };
+    if samples.len() >= SAMPLES_KEPT {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+/// The subject of a Browse sample.
+pub fn browse_subject(location: &str, container: &str) -> String {
+    format!("{}#{}", location, container)
+}
+
+/// Everything recorded so far, oldest first.
+pub fn snapshot() -> Vec<Sample> {
+    samples().lock().map(|samples| samples.iter().cloned().collect()).unwrap_or_default()
+}
+
+/// `samples` grouped by operation and subject, in that order.
+pub fn summarize(samples: &[Sample]) -> Vec<Summary> {
+    let mut groups: Vec<((Operation, &str), Vec<&Sample>)> = Vec::new();
+    for sample in samples {
+        let key = (sample.operation, sample.subject.as_str());
+        match groups.iter_mut().find(|(group, _)| *group == key) {
+            Some((_, members)) => members.push(sample),
+            None => groups.push((key, vec![sample])),
+        }
+    }
+    groups.sort_by_key(|(key, _)| *key);
+
+    groups
+        .into_iter()
+        .map(|((operation, subject), members)| {
+            let mut millis: Vec<u64> = members.iter().map(|sample| sample.millis).collect();
+            millis.sort_unstable();
+            Summary {
+                operation,
+                subject: subject.to_string(),
+                count: members.len(),
+                failures: members.iter().filter(|sample| !sample.ok).count(),
+                last_ms: members.last().map_or(0, |sample| sample.millis),
+                min_ms: millis[0],
+                median_ms: millis[millis.len() / 2],
+                max_ms: millis[millis.len() - 1],
+            }
+        })
+        .collect()
+}
+
+/// Every sample and its summary as pretty-printed JSON.
+pub fn export_json() -> Result<String, String> {
+    let samples = snapshot();
+    let export = Export {
+        exported_at: unix_now(),
+        network: crate::network_interfaces::lan_interfaces()
+            .first()
+            .map(|lan| format!("{} {}/{}", lan.name, lan.ip, lan.prefix)),
+        summaries: summarize(&samples),
+        samples: &samples,
+    };
+    serde_json::to_string_pretty(&export).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_summarised_per_operation_and_subject() {
+        let sample =
+            |operation, subject: &str, millis, ok| Sample { operation, subject: subject.to_string(), millis, ok, at: 0 };
+        let nas = "http://192.168.1.5:8200/rootDesc.xml";
+        let samples = vec![
+            sample(Operation::Browse, &browse_subject(nas, "64"), 120, true),
+            sample(Operation::Description, nas, 35, true),
+            sample(Operation::Browse, &browse_subject(nas, "64"), 80, true),
+            sample(Operation::Ssdp, "ssdp:all", 3000, true),
+            sample(Operation::Browse, &browse_subject(nas, "64"), 900, false),
+        ];
+
+        let summaries = summarize(&samples);
+        let operations: Vec<Operation> = summaries.iter().map(|summary| summary.operation).collect();
+        assert_eq!(operations, [Operation::Ssdp, Operation::Description, Operation::Browse]);
+        let browse = &summaries[2];
+        assert_eq!(browse.subject, "http://192.168.1.5:8200/rootDesc.xml#64");
+        assert_eq!((browse.count, browse.failures), (3, 1));
+        assert_eq!((browse.min_ms, browse.median_ms, browse.max_ms, browse.last_ms), (80, 120, 900, 900));
+    }
+}
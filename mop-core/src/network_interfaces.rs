@@ -0,0 +1,114 @@
+//! The IPv4 interfaces a media server could be behind: up, not loopback or
+//! link-local, not a point-to-point tunnel, and not one of the platform's
+//! pseudo-interfaces (Docker bridges, pf logging, VPNs).
+//!
+//! getifaddrs differs between systems in ways that matter here. The BSDs
+//! can hand back a netmask with no address family, which reads as /0; an
+//! unreadable mask is taken as /24. Tunnels report a /32 with no broadcast
+//! address on every system and are skipped.
+
+use std::net::Ipv4Addr;
+
+/// An interface on a local network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanInterface {
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub prefix: u8,
+}
+
+/// Prefix assumed when the system gives no usable netmask.
+const DEFAULT_PREFIX: u8 = 24;
+
+#[cfg(target_os = "linux")]
+const VIRTUAL_PREFIXES: &[&str] = &["lo", "docker", "br-", "veth", "virbr", "tun", "wg", "tailscale", "zt"];
+
+#[cfg(target_os = "macos")]
+const VIRTUAL_PREFIXES: &[&str] = &["lo", "utun", "awdl", "llw", "gif", "stf", "bridge", "wg"];
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly"))]
+const VIRTUAL_PREFIXES: &[&str] = &["lo", "pflog", "pfsync", "enc", "tun", "gif", "gre", "stf", "ipsec", "wg"];
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+const VIRTUAL_PREFIXES: &[&str] = &["lo", "tun", "wg", "vEthernet"];
+
+/// Local network interfaces, private addresses first.
+pub fn lan_interfaces() -> Vec<LanInterface> {
+    let interfaces = match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            tracing::warn!(target: "mop::net", "Could not list network interfaces: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut lan: Vec<LanInterface> = interfaces
+        .iter()
+        .filter_map(|iface| match &iface.addr {
+            if_addrs::IfAddr::V4(v4) => lan_interface(&iface.name, v4, iface.is_oper_up()),
+            if_addrs::IfAddr::V6(_) => None,
+        })
+        .collect();
+    // Stable, so the system's own order decides between equals
+    lan.sort_by_key(|iface| !iface.ip.is_private());
+    lan
+}
+
+fn lan_interface(name: &str, addr: &if_addrs::Ifv4Addr, up: bool) -> Option<LanInterface> {
+    if addr.ip.is_loopback() || addr.ip.is_link_local() || addr.ip.is_unspecified() {
+        return None;
+    }
+    // Windows reports most adapters as up without the RUNNING flag set
+    if !up && !cfg!(windows) {
+        tracing::trace!(target: "mop::net", "Skipping {}: not running", name);
+        return None;
+    }
+    if VIRTUAL_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+        tracing::trace!(target: "mop::net", "Skipping {}: virtual interface", name);
+        return None;
+    }
+    if addr.prefixlen >= 31 && addr.broadcast.is_none() {
+        tracing::trace!(target: "mop::net", "Skipping {}: point-to-point", name);
+        return None;
+    }
+
+    let prefix = match addr.prefixlen {
+        0 => DEFAULT_PREFIX,
+        prefix => prefix,
+    };
+    Some(LanInterface { name: name.to_string(), ip: addr.ip, prefix })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(ip: [u8; 4], prefixlen: u8, broadcast: Option<[u8; 4]>) -> if_addrs::Ifv4Addr {
+        if_addrs::Ifv4Addr {
+            ip: ip.into(),
+            netmask: Ipv4Addr::from(u32::MAX.checked_shl(32 - prefixlen as u32).unwrap_or(0)),
+            prefixlen,
+            broadcast: broadcast.map(Ipv4Addr::from),
+        }
+    }
+
+    #[test]
+    fn keeps_only_interfaces_on_a_lan() {
+        let lan = lan_interface("em0", &v4([192, 168, 1, 20], 24, Some([192, 168, 1, 255])), true).unwrap();
+        assert_eq!(lan.prefix, 24);
+
+        // A netmask the system couldn't give a family for
+        assert_eq!(lan_interface("em0", &v4([10, 0, 0, 7], 0, None), true).unwrap().prefix, DEFAULT_PREFIX);
+
+        assert!(lan_interface("ppp0", &v4([10, 64, 0, 2], 32, None), true).is_none());
+        assert!(lan_interface("wg0", &v4([10, 8, 0, 2], 24, Some([10, 8, 0, 255])), true).is_none());
+        assert!(lan_interface("lo0", &v4([127, 0, 0, 1], 8, None), true).is_none());
+        assert!(lan_interface("em1", &v4([169, 254, 3, 4], 16, Some([169, 254, 255, 255])), true).is_none());
+    }
+}
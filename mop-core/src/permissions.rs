@@ -0,0 +1,139 @@
+//! macOS (14 and later) asks the user before a program may talk to the local
+//! network. Until it is allowed, multicast sends fail and SSDP discovery
+//! quietly finds nothing, which looks exactly like an empty network.
+//!
+//! Windows lets the search out, but its firewall drops the replies coming
+//! back unless MOP is allowed on the current network. That can't be probed
+//! for without admin rights, so it is only offered as a likely cause when
+//! discovery comes back empty.
+
+/// Whether discovery can reach the local network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PermissionState {
+    Granted,
+    Denied,
+    /// There is no network to reach: no interface is up or nothing routes
+    /// beyond this machine.
+    NetworkDown,
+    /// The probe failed for some other reason.
+    Unknown,
+}
+
+/// What to tell the user when [`check_local_network`] says `Denied`.
+pub const LOCAL_NETWORK_HINT: &str = "Local network access is blocked. Allow it in System Settings > Privacy & Security > Local Network";
+
+/// What to tell the user when [`check_local_network`] says `NetworkDown`.
+pub const NETWORK_DOWN_HINT: &str = "Not connected to a network; discovery won't find anything until this machine is online";
+
+/// The Local Network pane of System Settings.
+pub const LOCAL_NETWORK_SETTINGS_URL: &str = "x-apple.systempreferences:com.apple.preference.security?Privacy_LocalNetwork";
+
+/// Send one SSDP search to the multicast group and see whether the system
+/// lets it out. The first attempt is also what makes macOS show its prompt.
+///
+/// macOS has no API that reports this permission, and a blocked send fails
+/// with the same "host unreachable" as a send with no network under it. So
+/// a failure only counts as `Denied` when the machine can route somewhere
+/// off the local network, which the permission doesn't cover.
+#[cfg(target_os = "macos")]
+pub fn check_local_network() -> PermissionState {
+    use std::net::{Ipv4Addr, UdpSocket};
+
+    let probe = || -> std::io::Result<()> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nST: upnp:rootdevice\r\nMX: 1\r\n\r\n";
+        socket.send_to(search.as_bytes(), (Ipv4Addr::new(239, 255, 255, 250), 1900))?;
+        Ok(())
+    };
+
+    let state = classify(probe(), network_is_up());
+    match state {
+        PermissionState::Denied => tracing::warn!(target: "mop::net", "Local network access denied"),
+        state => tracing::debug!(target: "mop::net", "Local network probe: {:?}", state),
+    }
+    state
+}
+
+/// Other systems don't gate local network access per program.
+#[cfg(not(target_os = "macos"))]
+pub fn check_local_network() -> PermissionState {
+    PermissionState::Granted
+}
+
+/// Whether this machine has an address it would send off-network traffic
+/// from. Connecting a UDP socket only picks a route; nothing is sent.
+#[cfg(target_os = "macos")]
+fn network_is_up() -> bool {
+    use std::net::{Ipv4Addr, UdpSocket};
+
+    // TEST-NET-1: never answered, and only reachable through a default route
+    let local = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).map(|()| socket))
+        .and_then(|socket| socket.local_addr());
+    matches!(local, Ok(address) if !address.ip().is_unspecified() && !address.ip().is_loopback())
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn classify(probe: std::io::Result<()>, network_is_up: bool) -> PermissionState {
+    use std::io::ErrorKind;
+
+    match probe {
+        Ok(()) => PermissionState::Granted,
+        Err(_) if !network_is_up => PermissionState::NetworkDown,
+        // A denied program gets EHOSTUNREACH on multicast, not EPERM
+        Err(e) if matches!(e.kind(), ErrorKind::HostUnreachable | ErrorKind::PermissionDenied) => {
+            PermissionState::Denied
+        }
+        Err(e) if matches!(e.kind(), ErrorKind::NetworkUnreachable | ErrorKind::NetworkDown) => {
+            PermissionState::NetworkDown
+        }
+        Err(e) => {
+            tracing::debug!(target: "mop::net", "Multicast probe failed: {}", e);
+            PermissionState::Unknown
+        }
+    }
+}
+
+/// Open System Settings at the Local Network pane, where MOP (or the
+/// terminal running it) can be allowed.
+#[cfg(target_os = "macos")]
+pub fn open_local_network_settings() -> Result<(), String> {
+    let status = std::process::Command::new("open")
+        .arg(LOCAL_NETWORK_SETTINGS_URL)
+        .status()
+        .map_err(|e| format!("Failed to open System Settings: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err("Failed to open System Settings".to_string())
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn open_local_network_settings() -> Result<(), String> {
+    Err("Local network permission is only a macOS setting".to_string())
+}
+
+/// What to tell the user on Windows when discovery found nothing.
+pub const FIREWALL_HINT: &str = "Windows Firewall may be blocking replies from media servers. Allow mop.exe on private networks under Windows Security > Firewall & network protection, and make sure this network is set to Private, then rescan";
+
+/// A likely reason, on this system, for discovery finding no devices at all.
+pub fn empty_network_hint() -> Option<&'static str> {
+    cfg!(windows).then_some(FIREWALL_HINT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn unreachable_is_only_denied_with_a_network() {
+        let unreachable = || Err(Error::from(ErrorKind::HostUnreachable));
+        assert_eq!(classify(unreachable(), true), PermissionState::Denied);
+        assert_eq!(classify(unreachable(), false), PermissionState::NetworkDown);
+        assert_eq!(classify(Err(Error::from(ErrorKind::NetworkUnreachable)), true), PermissionState::NetworkDown);
+        assert_eq!(classify(Err(Error::from(ErrorKind::TimedOut)), true), PermissionState::Unknown);
+        assert_eq!(classify(Ok(()), false), PermissionState::Granted);
+    }
+}
@@ -0,0 +1,77 @@
+// Proxy selection for every HTTP client MOP builds. reqwest already honours
+// HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY; `[network] proxy` takes
+// precedence over them, and servers marked `bypass_proxy` always connect
+// directly (a corporate proxy can't reach the NAS at home).
+
+use std::sync::RwLock;
+
+/// `[network] proxy` from the loaded config. Set wherever the config is
+/// (re)loaded, so clients built afterwards pick up changes.
+static CONFIGURED: RwLock<Option<String>> = RwLock::new(None);
+
+/// Use `proxy` for clients built from now on; empty falls back to the
+/// environment.
+pub fn set(proxy: &str) {
+    let proxy = Some(proxy.trim()).filter(|proxy| !proxy.is_empty()).map(str::to_string);
+    if let Ok(mut configured) = CONFIGURED.write() {
+        *configured = proxy;
+    }
+}
+
+/// The configured proxy, honouring NO_PROXY for the hosts it lists.
+fn configured() -> Result<Option<reqwest::Proxy>, String> {
+    let Some(url) = CONFIGURED.read().ok().and_then(|configured| configured.clone()) else {
+        return Ok(None);
+    };
+    reqwest::Proxy::all(&url)
+        .map(|proxy| Some(proxy.no_proxy(reqwest::NoProxy::from_env())))
+        .map_err(|e| format!("Invalid proxy '{}': {}", url, e))
+}
+
+/// Check a proxy URL from the config or the settings editor.
+pub fn validate(url: &str) -> Result<(), String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Ok(());
+    }
+    reqwest::Proxy::all(url).map(|_| ()).map_err(|_| format!("'{}' is not a proxy URL", url))
+}
+
+/// Apply the proxy settings to an async client builder; `bypass` connects
+/// directly.
+pub fn configure(builder: reqwest::ClientBuilder, bypass: bool) -> Result<reqwest::ClientBuilder, String> {
+    if bypass {
+        return Ok(builder.no_proxy());
+    }
+    Ok(match configured()? {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    })
+}
+
+/// Blocking version of `configure`.
+pub fn configure_blocking(
+    builder: reqwest::blocking::ClientBuilder,
+    bypass: bool,
+) -> Result<reqwest::blocking::ClientBuilder, String> {
+    if bypass {
+        return Ok(builder.no_proxy());
+    }
+    Ok(match configured()? {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_proxy_urls() {
+        assert!(validate("").is_ok());
+        assert!(validate("http://proxy:3128").is_ok());
+        assert!(validate(" socks5://127.0.0.1:1080 ").is_ok());
+        assert_eq!(validate("not a url").unwrap_err(), "'not a url' is not a proxy URL");
+    }
+}
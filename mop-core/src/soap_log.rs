@@ -0,0 +1,105 @@
+//! The last few SOAP exchanges with each server, kept in full for the TUI's
+//! SOAP inspector. A fault says little on one line; the request that caused
+//! it and the whole response usually say what went wrong.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// Exchanges kept per server; older ones are dropped.
+pub const EXCHANGES_PER_SERVER: usize = 25;
+
+#[derive(Debug, Clone)]
+pub struct SoapExchange {
+    pub at: SystemTime,
+    pub control_url: String,
+    pub action: String,
+    /// The request envelope as sent.
+    pub request: String,
+    /// HTTP status, or `None` if no response arrived.
+    pub status: Option<u16>,
+    /// The response body, or why there was none.
+    pub response: String,
+    pub duration: Duration,
+}
+
+impl SoapExchange {
+    /// The fault in the response, if the server sent one.
+    pub fn fault(&self) -> Option<String> {
+        fault_summary(&self.response)
+    }
+}
+
+fn exchanges() -> &'static Mutex<HashMap<String, VecDeque<SoapExchange>>> {
+    static EXCHANGES: OnceLock<Mutex<HashMap<String, VecDeque<SoapExchange>>>> = OnceLock::new();
+    EXCHANGES.get_or_init(Default::default)
+}
+
+/// Exchanges are kept by host and port, so a device's description URL finds
+/// the calls made to any of its services.
+fn server_key(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(url) => format!("{}:{}", url.host_str().unwrap_or_default(), url.port_or_known_default().unwrap_or(0)),
+        Err(_) => url.to_string(),
+    }
+}
+
+pub fn record(exchange: SoapExchange) {
+    let Ok(mut exchanges) = exchanges().lock() else { return };
+    let kept = exchanges.entry(server_key(&exchange.control_url)).or_default();
+    if kept.len() >= EXCHANGES_PER_SERVER {
+        kept.pop_front();
+    }
+    kept.push_back(exchange);
+}
+
+/// The exchanges with the server at `url` (any URL on it), oldest first.
+pub fn exchanges_with(url: &str) -> Vec<SoapExchange> {
+    exchanges()
+        .lock()
+        .ok()
+        .and_then(|exchanges| exchanges.get(&server_key(url)).map(|kept| kept.iter().cloned().collect()))
+        .unwrap_or_default()
+}
+
+/// "701 No such object" from a UPnPError, or the faultstring of any other
+/// SOAP fault.
+pub fn fault_summary(response: &str) -> Option<String> {
+    if !response.contains("Fault>") {
+        return None;
+    }
+    let value = |tag| crate::upnp::extract_xml_value(response, tag).filter(|value| !value.trim().is_empty());
+    match (value("errorCode"), value("errorDescription")) {
+        (Some(code), Some(description)) => Some(format!("{} {}", code.trim(), description.trim())),
+        (Some(code), None) => Some(format!("UPnP error {}", code.trim())),
+        _ => Some(value("faultstring").unwrap_or_else(|| "SOAP fault".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faults_are_summarised_and_exchanges_kept_per_server() {
+        let fault = r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>701</errorCode><errorDescription>No such object</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"#;
+        assert_eq!(fault_summary(fault).as_deref(), Some("701 No such object"));
+        assert_eq!(fault_summary("<s:Envelope><s:Body><u:BrowseResponse/></s:Body></s:Envelope>"), None);
+
+        for n in 0..EXCHANGES_PER_SERVER + 2 {
+            record(SoapExchange {
+                at: SystemTime::now(),
+                control_url: "http://10.9.9.9:8200/ctl/ContentDir".to_string(),
+                action: format!("Browse{}", n),
+                request: String::new(),
+                status: Some(500),
+                response: fault.to_string(),
+                duration: Duration::from_millis(40),
+            });
+        }
+        let kept = exchanges_with("http://10.9.9.9:8200/rootDesc.xml");
+        assert_eq!(kept.len(), EXCHANGES_PER_SERVER);
+        assert_eq!(kept[0].action, "Browse2");
+        assert!(exchanges_with("http://10.9.9.9:9000/rootDesc.xml").is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! Checking a media URL before it goes to the player. Handed a URL the
+//! server won't serve, most players exit without saying why (mpv just
+//! quits), so the server is asked first and its answer reported instead.
+//! Also how fast a file arrives, against how fast it has to for playback.
+
+use crate::upnp::UpnpDevice;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, HeaderMap, RANGE};
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+/// What the server said about a media URL.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamInfo {
+    pub content_type: Option<String>,
+    /// Size in bytes, when the server gives it.
+    pub length: Option<u64>,
+}
+
+/// Ask `device` for `url` without downloading it. HEAD first, then the
+/// first byte with a ranged GET, as some DLNA servers refuse HEAD or answer
+/// it with nothing.
+pub fn check(device: &UpnpDevice, url: &str, timeout: Duration) -> Result<StreamInfo, String> {
+    let client = device.blocking_client_builder()?.timeout(timeout).build().map_err(|e| e.to_string())?;
+    let credentials = device.credentials.as_ref();
+    let response = match crate::auth::send_blocking(&client, client.head(url), credentials) {
+        Ok(response) if response.status().is_success() => response,
+        _ => crate::auth::send_blocking(&client, client.get(url).header(RANGE, "bytes=0-0"), credentials)
+            .map_err(|e| format!("no answer for media URL: {}", e))?,
+    };
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("server returned {} for media URL", status.as_u16()));
+    }
+    Ok(info(response.headers()))
+}
+
+/// How fast a file came from the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throughput {
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    pub fn bits_per_sec(&self) -> u64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { (self.bytes as f64 * 8.0 / secs) as u64 } else { 0 }
+    }
+}
+
+/// Download `url` from `device` for about `sample`, or until it ends, and
+/// time it. The clock starts at the first byte of the body, so connecting
+/// and the server's time to respond don't count against the rate.
+pub fn measure(device: &UpnpDevice, url: &str, sample: Duration) -> Result<Throughput, String> {
+    let client = device
+        .blocking_client_builder()?
+        .timeout(sample + Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut response = crate::auth::send_blocking(&client, client.get(url), device.credentials.as_ref())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("server returned {} for media URL", status.as_u16()));
+    }
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut bytes = 0;
+    let mut started = None;
+    loop {
+        let read = response.read(&mut buffer).map_err(|e| format!("download failed: {}", e))?;
+        let started = *started.get_or_insert_with(Instant::now);
+        bytes += read as u64;
+        if read == 0 || started.elapsed() >= sample {
+            return Ok(Throughput { bytes, elapsed: started.elapsed() });
+        }
+    }
+}
+
+/// Whether a connection doing `throughput` bits per second keeps up with a
+/// file averaging `bitrate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Smooth,
+    /// Fast enough on average, but not by enough to ride out the peaks of
+    /// variable bitrate video, which run well above its average.
+    Marginal,
+    TooSlow,
+}
+
+impl Verdict {
+    pub fn of(throughput: u64, bitrate: u64) -> Self {
+        if throughput >= bitrate.saturating_mul(3) / 2 {
+            Verdict::Smooth
+        } else if throughput >= bitrate {
+            Verdict::Marginal
+        } else {
+            Verdict::TooSlow
+        }
+    }
+}
+
+/// Type and size from response headers. A ranged answer's Content-Length is
+/// that of the range; the whole size is after the slash in Content-Range.
+fn info(headers: &HeaderMap) -> StreamInfo {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let total = header(CONTENT_RANGE).and_then(|range| range.rsplit_once('/')?.1.parse().ok());
+    StreamInfo {
+        content_type: header(CONTENT_TYPE).map(str::to_string),
+        length: total.or_else(|| header(CONTENT_LENGTH)?.parse().ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_size_comes_from_the_range_when_there_is_one() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "video/x-matroska".parse().unwrap());
+        headers.insert(CONTENT_LENGTH, "1".parse().unwrap());
+        headers.insert(CONTENT_RANGE, "bytes 0-0/4404019200".parse().unwrap());
+        assert_eq!(
+            info(&headers),
+            StreamInfo { content_type: Some("video/x-matroska".to_string()), length: Some(4_404_019_200) }
+        );
+
+        headers.insert(CONTENT_RANGE, "bytes 0-0/*".parse().unwrap());
+        assert_eq!(info(&headers).length, Some(1));
+        headers.remove(CONTENT_RANGE);
+        headers.insert(CONTENT_LENGTH, "62914560".parse().unwrap());
+        assert_eq!(info(&headers).length, Some(62_914_560));
+    }
+
+    #[test]
+    fn a_connection_needs_headroom_over_the_average_bitrate() {
+        let throughput = Throughput { bytes: 50_000_000, elapsed: Duration::from_secs(5) };
+        assert_eq!(throughput.bits_per_sec(), 80_000_000);
+        assert_eq!(Throughput { bytes: 0, elapsed: Duration::ZERO }.bits_per_sec(), 0);
+
+        // A 4K remux averages around 60 Mbit/s
+        assert_eq!(Verdict::of(throughput.bits_per_sec(), 60_000_000), Verdict::Marginal);
+        assert_eq!(Verdict::of(throughput.bits_per_sec(), 40_000_000), Verdict::Smooth);
+        assert_eq!(Verdict::of(20_000_000, 60_000_000), Verdict::TooSlow);
+    }
+}
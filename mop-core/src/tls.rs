@@ -0,0 +1,72 @@
+// Per-server TLS settings for devices behind HTTPS, which at home usually
+// means a self-signed certificate. Applied to every HTTP client that talks
+// to such a device: SOAP, the native backends and downloads.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsOptions {
+    /// Skip certificate validation entirely.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub accept_invalid_certs: bool,
+    /// Trust only this PEM certificate (pinning it), whatever the host name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn pinned(&self) -> Result<Option<reqwest::Certificate>, String> {
+        let Some(path) = &self.certificate else { return Ok(None) };
+        let pem = std::fs::read(path).map_err(|e| format!("Cannot read certificate {}: {}", path.display(), e))?;
+        reqwest::Certificate::from_pem(&pem)
+            .map(Some)
+            .map_err(|e| format!("Invalid certificate {}: {}", path.display(), e))
+    }
+}
+
+/// Apply `tls` to an async client builder.
+pub fn configure(builder: reqwest::ClientBuilder, tls: &TlsOptions) -> Result<reqwest::ClientBuilder, String> {
+    let mut builder = builder.danger_accept_invalid_certs(tls.accept_invalid_certs);
+    if let Some(certificate) = tls.pinned()? {
+        // Self-signed certificates rarely name the address they're used on
+        builder = builder
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(certificate)
+            .danger_accept_invalid_hostnames(true);
+    }
+    Ok(builder)
+}
+
+/// Apply `tls` to a blocking client builder.
+pub fn configure_blocking(
+    builder: reqwest::blocking::ClientBuilder,
+    tls: &TlsOptions,
+) -> Result<reqwest::blocking::ClientBuilder, String> {
+    let mut builder = builder.danger_accept_invalid_certs(tls.accept_invalid_certs);
+    if let Some(certificate) = tls.pinned()? {
+        builder = builder
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(certificate)
+            .danger_accept_invalid_hostnames(true);
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_stay_out_of_serialized_devices() {
+        assert_eq!(serde_json::to_string(&TlsOptions::default()).unwrap(), "{}");
+
+        let missing = TlsOptions { certificate: Some(PathBuf::from("/nonexistent/nas.pem")), ..Default::default() };
+        let error = configure(reqwest::Client::builder(), &missing).err().unwrap();
+        assert!(error.starts_with("Cannot read certificate /nonexistent/nas.pem"), "{}", error);
+    }
+}
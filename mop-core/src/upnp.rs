@@ -0,0 +1,2223 @@
+use crate::DirectoryItem;
+use crate::cancel::CancelToken;
+use crate::container_ids::ContainerIds;
+use crate::discovery::{DiscoveryConfig, SsdpConfig};
+use crate::permissions::{self, PermissionState};
+use crate::soap_log::{self, SoapExchange};
+use rupnp::ssdp::SearchTarget;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::Instrument;
+
+/// A media server found by discovery (or a router, renderer, ... found by
+/// the `upnp:rootdevice` search).
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct UpnpDevice {
+    pub name: String,
+    pub location: String,
+    pub base_url: String,
+    pub device_client: Option<String>,
+    pub content_directory_url: Option<String>,
+    /// "uuid:..." from the device description, stable across address
+    /// changes. Unknown for devices that were only port-scanned or added by hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udn: Option<String>,
+    /// Plex token from `[servers]`, sent with every request to this device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Jellyfin/Emby API key from `[servers]`, for the native backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Certificate handling from `[servers]`, for devices served over HTTPS.
+    #[serde(default, skip_serializing_if = "crate::tls::TlsOptions::is_default")]
+    pub tls: crate::tls::TlsOptions,
+    /// Username and password from `[servers]`. Never serialized, so they
+    /// stay out of the daemon socket and `--json` output.
+    #[serde(skip)]
+    pub credentials: Option<crate::auth::Credentials>,
+    /// Connect directly even when a proxy is configured.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bypass_proxy: bool,
+    /// Extra HTTP headers from `[servers]`, User-Agent included. Not
+    /// serialized, as they may carry a key; they're applied again from the
+    /// config wherever a device is read back.
+    #[serde(skip)]
+    pub headers: crate::headers::Headers,
+}
+
+impl UpnpDevice {
+    /// A client builder with this device's TLS, header and proxy settings.
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder, String> {
+        let builder = crate::tls::configure(reqwest::Client::builder(), &self.tls)?;
+        let builder = crate::headers::configure(builder, &self.headers)?;
+        crate::proxy::configure(builder, self.bypass_proxy)
+    }
+
+    /// Blocking version of `client_builder`.
+    pub fn blocking_client_builder(&self) -> Result<reqwest::blocking::ClientBuilder, String> {
+        let builder = crate::tls::configure_blocking(reqwest::blocking::Client::builder(), &self.tls)?;
+        let builder = crate::headers::configure_blocking(builder, &self.headers)?;
+        crate::proxy::configure_blocking(builder, self.bypass_proxy)
+    }
+}
+
+pub type PlexServer = UpnpDevice;
+
+/// Progress from `start_discovery`, ending with `AllComplete`.
+#[derive(Debug)]
+pub enum DiscoveryMessage {
+    Started,
+    /// The system blocks local network access; discovery goes on regardless.
+    PermissionDenied(String),
+    /// There's no network to discover on; discovery goes on regardless.
+    NetworkDown(String),
+    DeviceFound(Box<UpnpDevice>),
+    /// A device announced itself (`ssdp:alive`) to `announcements::listen`.
+    DeviceAnnounced(Box<UpnpDevice>),
+    /// A device said it's going away (`ssdp:byebye`): its UDN, and where it
+    /// was if it announced itself while listening.
+    DeviceLeft {
+        udn: String,
+        location: Option<String>,
+    },
+    Phase1Complete, // SSDP discovery complete
+    Phase2Complete, // Extended discovery complete
+    Phase3Complete, // Port scan complete
+    AllComplete(Vec<UpnpDevice>),
+}
+
+/// Progress from `stream_container_page` and `start_browse`, ending with
+/// `PageComplete`.
+#[derive(Debug)]
+pub enum BrowseMessage {
+    /// The next child of the container, as soon as it has been parsed.
+    ItemFound(Box<DirectoryItem>),
+    /// Every child of the page has been sent: `found` of them, out of the
+    /// container's `total` if the server said.
+    PageComplete {
+        found: usize,
+        total: Option<usize>,
+        error: Option<BrowseError>,
+    },
+}
+
+/// Run SSDP discovery and the port scan on a background thread. Devices are
+/// reported as they answer, then once more as the final deduplicated list.
+pub fn start_discovery(settings: DiscoveryConfig) -> Receiver<DiscoveryMessage> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        tx.send(DiscoveryMessage::Started).ok();
+        match permissions::check_local_network() {
+            PermissionState::Denied => {
+                tx.send(DiscoveryMessage::PermissionDenied(permissions::LOCAL_NETWORK_HINT.to_string())).ok();
+            }
+            PermissionState::NetworkDown => {
+                tx.send(DiscoveryMessage::NetworkDown(permissions::NETWORK_DOWN_HINT.to_string())).ok();
+            }
+            PermissionState::Granted | PermissionState::Unknown => {}
+        }
+
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+        rt.block_on(discover_all(tx, &settings).instrument(tracing::info_span!(target: "mop::upnp", "discovery")));
+    });
+
+    rx
+}
+
+async fn discover_all(sender: Sender<DiscoveryMessage>, settings: &DiscoveryConfig) {
+    tracing::info!(target: "mop::upnp", "Starting UPnP discovery (SSDP + port scan in parallel)");
+    let mut devices = Vec::new();
+
+    // Run SSDP discovery and port scan in PARALLEL
+    let ssdp_sender = sender.clone();
+
+    let (ssdp_result, port_scan_result) = tokio::join!(
+        ssdp_discovery(ssdp_sender, settings).instrument(tracing::info_span!(target: "mop::ssdp", "ssdp")),
+        targeted_port_scan_parallel(settings).instrument(tracing::info_span!(target: "mop::net", "port_scan"))
+    );
+
+    // Collect SSDP devices
+    if let Ok(ssdp_devices) = ssdp_result {
+        for device in ssdp_devices {
+            if !devices
+                .iter()
+                .any(|d: &UpnpDevice| d.location == device.location)
+            {
+                devices.push(device);
+            }
+        }
+    }
+
+    sender.send(DiscoveryMessage::Phase1Complete).ok();
+    sender.send(DiscoveryMessage::Phase2Complete).ok();
+
+    // Collect port scan devices
+    if let Ok(scan_devices) = port_scan_result {
+        tracing::info!(target: "mop::upnp", "Port scan found {} devices", scan_devices.len());
+        for device in scan_devices {
+            if is_blocked(&device, &settings.blocklist) {
+                tracing::debug!(target: "mop::upnp", "Skipping blocklisted device: {}", device.name);
+                continue;
+            }
+            if !devices
+                .iter()
+                .any(|d| is_same_discovered_device(d, &device))
+            {
+                sender
+                    .send(DiscoveryMessage::DeviceFound(Box::new(device.clone())))
+                    .ok();
+                devices.push(device);
+            }
+        }
+    }
+
+    tracing::info!(target: "mop::upnp", "Discovery complete: {} total devices", devices.len());
+    sender.send(DiscoveryMessage::Phase3Complete).ok();
+    sender.send(DiscoveryMessage::AllComplete(devices)).ok();
+}
+
+async fn ssdp_discovery(
+    sender: Sender<DiscoveryMessage>,
+    settings: &DiscoveryConfig,
+) -> Result<Vec<UpnpDevice>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut devices = Vec::new();
+    if !settings.ssdp.enabled {
+        tracing::info!(target: "mop::upnp", "SSDP discovery disabled in config");
+        return Ok(devices);
+    }
+
+    let timeout = Duration::from_secs(settings.ssdp_timeout_secs);
+    let description_timeout = Duration::from_secs(settings.description_timeout_secs);
+
+    for search_target in ssdp_search_targets(&settings.ssdp) {
+        tracing::debug!(target: "mop::upnp", "SSDP discovery started, target={}, timeout={}s", search_target, timeout.as_secs());
+
+        let started = Instant::now();
+        let searched = search_responses(&search_target.to_string(), &settings.ssdp.search_targets, timeout).await;
+        let ok = searched.is_ok();
+        match searched {
+            Ok(responses) => {
+                use futures_util::StreamExt;
+
+                // Descriptions are fetched while the search keeps listening
+                let mut described = Box::pin(
+                    responses
+                        .take(MAX_DEVICES_PER_TARGET)
+                        .map(|(message, from)| {
+                            let location = message.location().unwrap_or_default().to_string();
+                            let span = tracing::debug_span!(target: "mop::upnp", "describe", url = %location);
+                            let connection = settings.connection_for(&location, "");
+                            describe_ssdp_device(message, from, description_timeout, connection).instrument(span)
+                        })
+                        .buffer_unordered(MAX_CONCURRENT_DESCRIPTIONS),
+                );
+
+                while let Some(upnp_device) = described.next().await {
+                    if is_blocked(&upnp_device, &settings.blocklist) {
+                        tracing::debug!(target: "mop::upnp", "Skipping blocklisted device: {}", upnp_device.name);
+                        continue;
+                    }
+
+                    sender
+                        .send(DiscoveryMessage::DeviceFound(Box::new(upnp_device.clone())))
+                        .ok();
+                    if !devices
+                        .iter()
+                        .any(|d: &UpnpDevice| d.location == upnp_device.location)
+                    {
+                        devices.push(upnp_device);
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!(target: "mop::upnp", "SSDP discovery failed for {}: {}", search_target, e);
+            }
+        }
+        crate::metrics::record(crate::metrics::Operation::Ssdp, search_target.to_string(), started.elapsed(), ok);
+    }
+
+    Ok(devices)
+}
+
+/// Send an M-SEARCH for `target` and yield, as they arrive within
+/// `timeout`, the responses to any of `wanted` that discovery can use, one
+/// per LOCATION. Each goes through the same lenient reading as a capture,
+/// with what a packet off the network has to pass on top.
+async fn search_responses(
+    target: &str,
+    wanted: &[String],
+    timeout: Duration,
+) -> Result<impl futures_util::Stream<Item = (crate::capture::SsdpMessage, String)> + use<>, String> {
+    let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| format!("Cannot open SSDP socket: {}", e))?;
+    let mx = timeout.as_secs().clamp(1, 5);
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        mx, target
+    );
+    socket
+        .send_to(search.as_bytes(), crate::capture::SSDP_GROUP)
+        .await
+        .map_err(|e| format!("Cannot send SSDP search: {}", e))?;
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let wanted = wanted.to_vec();
+    let heard = (socket, std::collections::HashSet::new());
+    Ok(futures_util::stream::unfold(heard, move |(socket, mut locations)| {
+        let wanted = wanted.clone();
+        async move {
+            let mut buffer = vec![0u8; crate::capture::MAX_PACKET];
+            loop {
+                let received = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await.ok()?;
+                let (length, from) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        tracing::warn!(target: "mop::ssdp", "SSDP receive failed: {}", e);
+                        return None;
+                    }
+                };
+                let Some(message) = crate::capture::live_response(&buffer[..length]) else {
+                    tracing::debug!(target: "mop::ssdp", "Ignoring an unusable SSDP packet from {}", from);
+                    continue;
+                };
+                let answers = message.target().is_some_and(|st| wanted.iter().any(|t| t == st));
+                let location = message.location().unwrap_or_default().to_string();
+                if answers && locations.insert(location) {
+                    return Some(((message, from.to_string()), (socket, locations)));
+                }
+            }
+        }
+    }))
+}
+
+async fn describe_ssdp_device(
+    message: crate::capture::SsdpMessage,
+    from: String,
+    description_timeout: Duration,
+    connection: UpnpDevice,
+) -> UpnpDevice {
+    let device_url = connection.location.clone();
+    let description = match fetch_device_description(&connection, description_timeout).await {
+        Ok(desc) => Some(desc),
+        Err(e) => {
+            tracing::warn!(target: "mop::upnp", "No description from {}: {}", device_url, e);
+            None
+        }
+    };
+    let device = crate::capture::heard_device(&message, &device_url, &from, description.as_deref());
+    tracing::info!(target: "mop::upnp", "SSDP found: {} ({})", device.name, device_url);
+    device
+}
+
+/// A device found over SSDP, from its LOCATION and (when it could be
+/// fetched) its description.
+pub fn described_device(
+    device_url: String,
+    friendly_name: &str,
+    device_type: String,
+    description: Option<&str>,
+) -> UpnpDevice {
+    let base_url = if friendly_name.to_lowercase().contains("plex")
+        || device_type.contains("plex")
+    {
+        if let Ok(url) = url::Url::parse(&device_url) {
+            if let Some(host) = url.host_str() {
+                format!("http://{}:32400", host)
+            } else {
+                extract_base_url(&device_url)
+            }
+        } else {
+            extract_base_url(&device_url)
+        }
+    } else {
+        extract_base_url(&device_url)
+    };
+
+    let (content_directory_url, udn) = match description {
+        Some(desc) => (
+            parse_content_directory_url(desc, &device_url),
+            extract_xml_value(desc, "UDN").map(|udn| udn.trim().to_string()),
+        ),
+        None => (None, None),
+    };
+
+    UpnpDevice {
+        name: format!("{} [{}]", friendly_name, device_type),
+        location: device_url,
+        base_url,
+        device_client: Some(device_type),
+        content_directory_url,
+        udn,
+        token: None,
+        api_key: None,
+        tls: Default::default(),
+        credentials: None,
+        bypass_proxy: false,
+        headers: Default::default(),
+    }
+}
+
+fn ssdp_search_targets(settings: &SsdpConfig) -> Vec<SearchTarget> {
+    settings
+        .search_targets
+        .iter()
+        .filter_map(|target| match SearchTarget::from_str(target) {
+            Ok(target) => Some(target),
+            Err(e) => {
+                tracing::warn!(target: "mop::ssdp", "Ignoring invalid search target '{}': {}", target, e);
+                None
+            }
+        })
+        .collect()
+}
+
+async fn targeted_port_scan_parallel(settings: &DiscoveryConfig) -> Result<Vec<UpnpDevice>, Box<dyn std::error::Error + Send + Sync>> {
+    if !settings.port_scan.enabled {
+        tracing::info!(target: "mop::upnp", "Port scan disabled in config");
+        return Ok(Vec::new());
+    }
+
+    tracing::debug!(target: "mop::upnp", "Starting parallel port scan");
+
+    let promising_ips = port_scan_hosts(&settings.port_scan.ranges);
+    let media_ports = settings.port_scan.ports.clone();
+
+    // Create all scan tasks
+    tracing::info!(target: "mop::upnp", "Port scan: scanning {} IPs × {} ports = {} endpoints",
+        promising_ips.len(), media_ports.len(), promising_ips.len() * media_ports.len());
+
+    let started = Instant::now();
+    let endpoints = promising_ips.len() * media_ports.len();
+    let probe_timeout = Duration::from_millis(settings.probe_timeout_ms);
+    // Large CIDR ranges would otherwise open thousands of sockets at once
+    let permits = Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_PROBES));
+    let mut tasks = Vec::new();
+    for ip in &promising_ips {
+        let ip = ip.to_string();
+        for &port in &media_ports {
+            tracing::debug!(target: "mop::upnp", "Queuing scan: {}:{}", ip, port);
+            let ip_clone = ip.clone();
+            let permits = permits.clone();
+            let connection = settings.connection_for(&format!("http://{}:{}/", ip, port), "");
+            tasks.push(tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await.ok()?;
+                let result = scan_single_endpoint(&ip_clone, port, probe_timeout, &connection).await;
+                if result.is_some() {
+                    tracing::debug!(target: "mop::upnp", "Scan hit: {}:{}", ip_clone, port);
+                }
+                result
+            }));
+        }
+    }
+
+    // Run all scans in parallel and collect results
+    tracing::debug!(target: "mop::upnp", "Port scan: waiting for {} parallel scans", tasks.len());
+    let results = futures_util::future::join_all(tasks).await;
+    tracing::debug!(target: "mop::upnp", "Port scan: all scans complete");
+
+    let mut devices = Vec::new();
+    for device in results.into_iter().flatten().flatten() {
+        if !devices
+            .iter()
+            .any(|d: &UpnpDevice| is_same_discovered_device(d, &device))
+        {
+            tracing::info!(target: "mop::upnp", "Port scan found: {}", device.name);
+            devices.push(device);
+        }
+    }
+
+    tracing::info!(target: "mop::upnp", "Port scan complete: {} devices found", devices.len());
+    if endpoints > 0 {
+        let subject = format!("{} endpoints", endpoints);
+        crate::metrics::record(crate::metrics::Operation::PortScan, subject, started.elapsed(), true);
+    }
+    Ok(devices)
+}
+
+async fn scan_single_endpoint(ip: &str, port: u16, timeout: Duration, connection: &UpnpDevice) -> Option<UpnpDevice> {
+    let url = format!("http://{}:{}", ip, port);
+
+    let client = connection
+        .client_builder()
+        .ok()?
+        .timeout(timeout)
+        .build()
+        .ok()?;
+
+    // For Plex DLNA port, try to get device description directly
+    if port == 32469 {
+        let desc_url = format!("{}/DeviceDescription.xml", url);
+        if let Ok(response) = client.get(&desc_url).send().await
+            && response.status().is_success()
+            && let Ok(desc_text) = response.text().await
+        {
+            // Parse device description for name and ContentDirectory URL
+            let friendly_name = extract_xml_value(&desc_text, "friendlyName")
+                .unwrap_or_else(|| format!("Plex DLNA ({})", ip));
+            let content_dir_url = parse_content_directory_url(&desc_text, &desc_url);
+            let base_url = dlna_device_base_url(ip, &url, &friendly_name, &desc_text);
+
+            tracing::info!(target: "mop::upnp", "Found Plex DLNA at {}: {}", url, friendly_name);
+            return Some(UpnpDevice {
+                name: format!("{} [MediaServer:1]", friendly_name),
+                location: desc_url,
+                base_url,
+                device_client: Some("Plex DLNA".to_string()),
+                content_directory_url: content_dir_url,
+                udn: extract_xml_value(&desc_text, "UDN").map(|udn| udn.trim().to_string()),
+                token: None,
+                api_key: None,
+                tls: Default::default(),
+                credentials: None,
+                bypass_proxy: false,
+                headers: Default::default(),
+            });
+        }
+        return None;
+    }
+
+    // For other ports, probe standard endpoints
+    let endpoints = vec!["/", "/status", "/identity"];
+
+    for endpoint in endpoints {
+        let test_url = format!("{}{}", url, endpoint);
+        if let Ok(response) = client.get(&test_url).send().await {
+            let status = response.status();
+            // Accept success OR 401 Unauthorized (Plex returns 401 when not authenticated)
+            if status.is_success() || status.as_u16() == 401 {
+                let server_name = match port {
+                    32400 => format!("Plex Server ({}:{})", ip, port),
+                    8096 => format!("Jellyfin Server ({}:{})", ip, port),
+                    8920 => format!("Emby Server ({}:{})", ip, port),
+                    _ => format!("Media Server ({}:{})", ip, port),
+                };
+
+                return Some(UpnpDevice {
+                    name: server_name,
+                    location: url.clone(),
+                    base_url: url,
+                    device_client: Some("DirectScan".to_string()),
+                    content_directory_url: None,
+                    udn: None,
+                    token: None,
+                    api_key: None,
+                    tls: Default::default(),
+                    credentials: None,
+                    bypass_proxy: false,
+                    headers: Default::default(),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// The raw text of the first `<tag>` element, without unescaping.
+pub fn extract_xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open_tag = format!("<{}>", tag);
+    let close_tag = format!("</{}>", tag);
+    if let Some(start) = xml.find(&open_tag) {
+        let value_start = start + open_tag.len();
+        if let Some(end) = xml[value_start..].find(&close_tag) {
+            return Some(xml[value_start..value_start + end].to_string());
+        }
+    }
+    None
+}
+
+/// The description at `device.location`, fetched with the device's TLS,
+/// credentials, headers and proxy settings.
+async fn fetch_device_description(
+    device: &UpnpDevice,
+    timeout: Duration,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    let fetched = request_device_description(device, timeout).await;
+    let location = device.location.as_str();
+    crate::metrics::record(crate::metrics::Operation::Description, location, started.elapsed(), fetched.is_ok());
+    fetched
+}
+
+async fn request_device_description(
+    device: &UpnpDevice,
+    timeout: Duration,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let client = device.client_builder()?.build()?;
+    let request = client.get(&device.location).timeout(timeout);
+    let response = crate::auth::send(request, device.credentials.as_ref()).await?;
+
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch device description: {}", response.status()).into());
+    }
+
+    Ok(response.text().await?)
+}
+
+fn parse_content_directory_url(device_desc: &str, device_url: &str) -> Option<String> {
+    find_service(device_desc, device_url, |service_type| service_type.contains("ContentDirectory"))
+        .map(|(_, control_url)| control_url)
+}
+
+/// The first service in a device description (embedded devices included)
+/// whose type satisfies `matches`, as (service type, absolute control URL).
+pub fn find_service(
+    device_desc: &str,
+    device_url: &str,
+    matches: impl Fn(&str) -> bool,
+) -> Option<(String, String)> {
+    use quick_xml::Reader;
+    use quick_xml::events::Event;
+
+    let mut reader = Reader::from_str(device_desc);
+    reader.config_mut().trim_text(true);
+
+    let mut in_service_type = false;
+    let mut in_control_url = false;
+    let mut current_service_type = String::new();
+    let mut current_control_url = String::new();
+
+    // Parse the device URL to get base URL for relative paths
+    let base_url = if let Ok(url) = url::Url::parse(device_url) {
+        format!(
+            "{}://{}:{}",
+            url.scheme(),
+            url.host_str().unwrap_or(""),
+            url.port().unwrap_or(80)
+        )
+    } else {
+        return None;
+    };
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) => match e.name().as_ref() {
+                b"service" => {
+                    current_service_type.clear();
+                    current_control_url.clear();
+                }
+                b"serviceType" => in_service_type = true,
+                b"controlURL" => in_control_url = true,
+                _ => {}
+            },
+            Ok(Event::Text(e)) if in_service_type || in_control_url => {
+                let text = e.unescape().unwrap_or_default().into_owned();
+                if in_service_type {
+                    current_service_type = text;
+                } else {
+                    current_control_url = text;
+                }
+            }
+            Ok(Event::End(ref e)) => {
+                match e.name().as_ref() {
+                    b"service" if matches(&current_service_type) && !current_control_url.is_empty() => {
+                        // Resolve relative URL
+                        let full_url = if current_control_url.starts_with("http") {
+                            current_control_url
+                        } else {
+                            format!("{}{}", base_url, current_control_url)
+                        };
+                        return Some((current_service_type, full_url));
+                    }
+                    b"serviceType" => in_service_type = false,
+                    b"controlURL" => in_control_url = false,
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                tracing::warn!(target: "mop::xml", "Error parsing device description: {}", e);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Every service type in a device description, embedded devices included,
+/// each once and in document order.
+pub fn service_types(device_desc: &str) -> Vec<String> {
+    let mut types: Vec<String> = Vec::new();
+    let mut rest = device_desc;
+    while let Some(start) = rest.find("<serviceType>") {
+        rest = &rest[start + "<serviceType>".len()..];
+        let Some(end) = rest.find("</serviceType>") else { break };
+        let service_type = rest[..end].trim();
+        if !service_type.is_empty() && !types.iter().any(|known| known == service_type) {
+            types.push(service_type.to_string());
+        }
+        rest = &rest[end..];
+    }
+    types
+}
+
+fn extract_base_url(device_url: &str) -> String {
+    if let Ok(url) = url::Url::parse(device_url) {
+        if let Some(host) = url.host_str() {
+            let port = url
+                .port()
+                .unwrap_or(if url.scheme() == "https" { 443 } else { 80 });
+            format!("{}://{}:{}", url.scheme(), host, port)
+        } else {
+            device_url.to_string()
+        }
+    } else {
+        device_url.to_string()
+    }
+}
+
+/// The first three octets of the LAN this machine is on, for scanning its /24.
+fn get_local_network() -> Option<String> {
+    let interfaces = crate::network_interfaces::lan_interfaces();
+    let Some(iface) = interfaces.iter().find(|iface| iface.ip.is_private()) else {
+        tracing::warn!(target: "mop::upnp", "Could not determine local network");
+        return None;
+    };
+    let octets = iface.ip.octets();
+    let network = format!("{}.{}.{}", octets[0], octets[1], octets[2]);
+    tracing::debug!(target: "mop::upnp", "Local network from {}: {}.x", iface.name, network);
+    Some(network)
+}
+
+fn port_scan_host_suffixes() -> Vec<u8> {
+    (1..=254).collect()
+}
+
+const MAX_CONCURRENT_PROBES: usize = 256;
+/// Description fetches in flight at once per SSDP search.
+const MAX_CONCURRENT_DESCRIPTIONS: usize = 8;
+/// Answers taken per search target; busy networks can have hundreds.
+const MAX_DEVICES_PER_TARGET: usize = 20;
+
+/// Smallest prefix accepted for a scan range (/20 = 4094 hosts).
+const MIN_CIDR_PREFIX: u8 = 20;
+
+/// Hosts to probe: the configured CIDR ranges, or the local /24 when none
+/// are configured.
+fn port_scan_hosts(ranges: &[String]) -> Vec<Ipv4Addr> {
+    if ranges.is_empty() {
+        let Some(network_base) = get_local_network() else {
+            return Vec::new();
+        };
+        tracing::debug!(target: "mop::upnp", "Port scan using network {}.x", network_base);
+        return port_scan_host_suffixes()
+            .into_iter()
+            .filter_map(|suffix| format!("{}.{}", network_base, suffix).parse().ok())
+            .collect();
+    }
+
+    let mut hosts = Vec::new();
+    for range in ranges {
+        match parse_cidr(range) {
+            Ok(range_hosts) => {
+                for host in range_hosts {
+                    if !hosts.contains(&host) {
+                        hosts.push(host);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(target: "mop::upnp", "Skipping scan range '{}': {}", range, e),
+        }
+    }
+    hosts
+}
+
+/// Expand an IPv4 CIDR range ("192.168.1.0/24") into its host addresses.
+/// A bare address is treated as /32. Network and broadcast addresses are
+/// left out for prefixes shorter than /31.
+pub fn parse_cidr(range: &str) -> Result<Vec<Ipv4Addr>, String> {
+    let (addr, prefix) = match range.trim().split_once('/') {
+        Some((addr, prefix)) => (
+            addr,
+            prefix
+                .parse::<u8>()
+                .map_err(|_| format!("invalid prefix '{}'", prefix))?,
+        ),
+        None => (range.trim(), 32),
+    };
+    let addr: Ipv4Addr = addr
+        .parse()
+        .map_err(|_| format!("invalid IPv4 address '{}'", addr))?;
+
+    if prefix > 32 {
+        return Err(format!("invalid prefix '{}'", prefix));
+    }
+    if prefix < MIN_CIDR_PREFIX {
+        return Err(format!("prefix /{} is too large to scan (minimum /{})", prefix, MIN_CIDR_PREFIX));
+    }
+
+    let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+    let network = u32::from(addr) & mask;
+    let broadcast = network | !mask;
+    let (first, last) = if prefix >= 31 {
+        (network, broadcast)
+    } else {
+        (network + 1, broadcast - 1)
+    };
+
+    Ok((first..=last).map(Ipv4Addr::from).collect())
+}
+
+/// Device patterns (blocklist entries, `[servers]` keys) match by
+/// case-insensitive name substring, or exactly by host or location URL.
+pub fn device_matches(device: &UpnpDevice, pattern: &str) -> bool {
+    location_matches(&device.name, &device.location, pattern)
+}
+
+/// `device_matches` for a device not described yet, known by its location
+/// and the name it advertised (if any).
+pub fn location_matches(name: &str, location: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return false;
+    }
+
+    let host = url::Url::parse(location)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+
+    name.to_lowercase().contains(&pattern.to_lowercase())
+        || host.as_deref() == Some(pattern)
+        || location == pattern
+}
+
+pub(crate) fn is_blocked(device: &UpnpDevice, blocklist: &[String]) -> bool {
+    blocklist.iter().any(|entry| device_matches(device, entry))
+}
+
+fn is_same_discovered_device(left: &UpnpDevice, right: &UpnpDevice) -> bool {
+    left.location == right.location || left.base_url == right.base_url
+}
+
+fn dlna_device_base_url(
+    ip: &str,
+    dlna_url: &str,
+    friendly_name: &str,
+    device_description: &str,
+) -> String {
+    if friendly_name.to_lowercase().contains("plex")
+        || device_description.to_lowercase().contains("plex")
+    {
+        format!("http://{}:32400", ip)
+    } else {
+        dlna_url.to_string()
+    }
+}
+
+/// Children asked for per Browse request.
+pub const BROWSE_PAGE_SIZE: usize = 200;
+
+/// Why browsing a container failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrowseError {
+    /// The server answered with UPnP error `code`, and said this about it.
+    Fault(u32, String),
+    /// The browse was cancelled before it finished.
+    Cancelled,
+    /// Anything else: no answer, or one that made no sense.
+    Failed(String),
+}
+
+impl BrowseError {
+    /// Whether the server has no object by the ID browsed: UPnP errors 701
+    /// (No such object) and 710 (No such container), which is what
+    /// ObjectIDs kept from before a server rescanned its library get.
+    pub fn is_no_such_object(&self) -> bool {
+        matches!(self, BrowseError::Fault(701 | 710, _))
+    }
+
+    /// What a failed Browse request comes to: the UPnP fault if the server
+    /// sent one.
+    fn from_request(e: &(dyn std::error::Error + 'static)) -> Self {
+        match e.downcast_ref::<SoapFault>() {
+            Some(SoapFault { code: Some(code), summary, .. }) => BrowseError::Fault(*code, summary.clone()),
+            _ => BrowseError::Failed(format!("UPnP ContentDirectory failed: {}", e)),
+        }
+    }
+}
+
+impl std::fmt::Display for BrowseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BrowseError::Fault(_, summary) => write!(f, "UPnP ContentDirectory failed: UPnP Browse fault: {}", summary),
+            BrowseError::Cancelled => f.write_str("Browse cancelled"),
+            BrowseError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<String> for BrowseError {
+    fn from(e: String) -> Self {
+        BrowseError::Failed(e)
+    }
+}
+
+/// One Browse request's worth of a container.
+#[derive(Debug, Clone, Default)]
+pub struct BrowsePage {
+    pub items: Vec<DirectoryItem>,
+    /// Children in the whole container, if the server said.
+    pub total: Option<usize>,
+    pub error: Option<BrowseError>,
+}
+
+impl BrowsePage {
+    /// Whether a page starting at `start` with these items was the last one.
+    pub fn is_last(&self, start: usize) -> bool {
+        self.error.is_some() || is_last_page(start, self.items.len(), self.total)
+    }
+}
+
+/// Whether a page starting at `start` that had `found` children was the last
+/// one of a container with `total` children (if known).
+pub fn is_last_page(start: usize, found: usize, total: Option<usize>) -> bool {
+    found == 0
+        || match total {
+            Some(total) => start + found >= total,
+            None => found < BROWSE_PAGE_SIZE,
+        }
+}
+
+/// Browse the whole container at `path` (titles from the root), page by
+/// page. `container_ids` remembers the ObjectIDs of containers seen so far
+/// and has to be kept between calls. Returns the items and a message if
+/// browsing failed.
+pub fn browse_directory(
+    server: &PlexServer,
+    path: &[String],
+    container_ids: &mut ContainerIds,
+) -> (Vec<DirectoryItem>, Option<BrowseError>) {
+    let mut items = Vec::new();
+    loop {
+        let start = items.len();
+        let page = browse_directory_page(server, path, container_ids, start, &CancelToken::new());
+        let last = page.is_last(start);
+        items.extend(page.items);
+        if last {
+            return (items, page.error);
+        }
+    }
+}
+
+/// Every child of container `object_id`, page by page. Returns the items
+/// and a message if browsing failed partway.
+pub fn browse_container(
+    server: &PlexServer,
+    object_id: &str,
+    cancel: &CancelToken,
+) -> (Vec<DirectoryItem>, Option<BrowseError>) {
+    let mut items = Vec::new();
+    loop {
+        let start = items.len();
+        let page = browse_container_page(server, object_id, start, cancel);
+        let last = page.is_last(start);
+        items.extend(page.items);
+        if last {
+            return (items, page.error);
+        }
+    }
+}
+
+/// The ObjectIDs of the containers along `path` (titles from the root),
+/// found again by browsing down from the root and matching each title, for
+/// when the ones kept for it have stopped working.
+pub fn resolve_path(server: &PlexServer, path: &[String], cancel: &CancelToken) -> Result<Vec<String>, String> {
+    let mut ids: Vec<String> = Vec::with_capacity(path.len());
+    for (depth, name) in path.iter().enumerate() {
+        let parent = ids.last().map_or("0", String::as_str);
+        let (items, error) = browse_container(server, parent, cancel);
+        if let Some(error) = error {
+            return Err(error.to_string());
+        }
+        let Some(id) = find_container(&items, name) else {
+            return Err(format!("No folder '{}' in /{}", name, path[..depth].join("/")));
+        };
+        ids.push(id.to_string());
+    }
+    Ok(ids)
+}
+
+/// The ObjectID of the container titled `name` among `items`: the exact
+/// title first, then one that differs only in case or surrounding spaces.
+fn find_container<'a>(items: &'a [DirectoryItem], name: &str) -> Option<&'a str> {
+    let containers = || items.iter().filter(|item| item.is_directory && item.id.is_some());
+    containers()
+        .find(|item| item.name == name)
+        .or_else(|| containers().find(|item| item.name.trim().eq_ignore_ascii_case(name.trim())))
+        .and_then(|item| item.id.as_deref())
+}
+
+/// Up to [`BROWSE_PAGE_SIZE`] children of the container at `path`, from
+/// index `start`, remembering the sub-containers' ObjectIDs. A path whose
+/// ObjectID isn't known (never browsed, or forgotten since) is an error, not
+/// the root.
+pub fn browse_directory_page(
+    server: &PlexServer,
+    path: &[String],
+    container_ids: &mut ContainerIds,
+    start: usize,
+    cancel: &CancelToken,
+) -> BrowsePage {
+    let Some(object_id) = container_ids.get(path) else {
+        let error = format!("No known folder at /{}; browse its parent first", path.join("/"));
+        return BrowsePage { error: Some(error.into()), ..Default::default() };
+    };
+    let page = browse_container_page(server, &object_id, start, cancel);
+    for item in page.items.iter().filter(|item| item.is_directory) {
+        if let Some(id) = &item.id {
+            let mut child = path.to_vec();
+            child.push(item.name.clone());
+            container_ids.insert(child, id.clone());
+        }
+    }
+    page
+}
+
+/// Up to [`BROWSE_PAGE_SIZE`] children of container `object_id`, from index
+/// `start`. Servers with a native API return everything at once.
+/// Cancelling `cancel` abandons the request and returns an empty page.
+pub fn browse_container_page(server: &PlexServer, object_id: &str, start: usize, cancel: &CancelToken) -> BrowsePage {
+    let mut page = BrowsePage::default();
+    stream_container_page(server, object_id, start, cancel, |message| match message {
+        BrowseMessage::ItemFound(item) => page.items.push(*item),
+        BrowseMessage::PageComplete { total, error, .. } => {
+            page.total = total;
+            page.error = error;
+        }
+    });
+    page
+}
+
+/// [`browse_container_page`] that hands each child to `send` as it is
+/// parsed out of the response, then `PageComplete`. A response that breaks
+/// off partway still delivers the children before the break.
+pub fn stream_container_page(
+    server: &PlexServer,
+    object_id: &str,
+    start: usize,
+    cancel: &CancelToken,
+    send: impl FnMut(BrowseMessage),
+) {
+    stream_sorted_container_page(server, object_id, start, "", cancel, send)
+}
+
+/// [`stream_container_page`] with the server sorting the whole container
+/// by `sort`, SortCriteria such as "+dc:title,-dc:date"; empty leaves the
+/// server's own order. Only properties in [`Capabilities::sort`] may be
+/// given. Jellyfin and Emby ignore it.
+pub fn stream_sorted_container_page(
+    server: &PlexServer,
+    object_id: &str,
+    start: usize,
+    sort: &str,
+    cancel: &CancelToken,
+    mut send: impl FnMut(BrowseMessage),
+) {
+    let _span = tracing::info_span!(target: "mop::soap", "browse", container = object_id, start).entered();
+    let started = Instant::now();
+    // Time every page that was answered; a cancelled one says nothing about the server
+    let mut send = |message: BrowseMessage| {
+        if let BrowseMessage::PageComplete { error, .. } = &message
+            && *error != Some(BrowseError::Cancelled)
+        {
+            let subject = crate::metrics::browse_subject(&server.location, object_id);
+            crate::metrics::record(crate::metrics::Operation::Browse, subject, started.elapsed(), error.is_none());
+        }
+        send(message)
+    };
+    let complete = |found, total, error: Option<BrowseError>| BrowseMessage::PageComplete { found, total, error };
+    let cancelled = || Some(BrowseError::Cancelled);
+    if cancel.is_cancelled() {
+        return send(complete(0, None, cancelled()));
+    }
+    if crate::jellyfin::handles(server) {
+        if start > 0 {
+            return send(complete(0, Some(start), None));
+        }
+        return match crate::jellyfin::browse(server, object_id) {
+            Ok(items) => {
+                let found = items.len();
+                for item in items {
+                    send(BrowseMessage::ItemFound(Box::new(item)));
+                }
+                send(complete(found, Some(found), None));
+            }
+            Err(e) => {
+                tracing::error!(target: "mop::net", "Native browse failed for container {}: {}", object_id, e);
+                send(complete(0, None, Some(e.into())));
+            }
+        };
+    }
+
+    tracing::debug!(target: "mop::upnp", "Browsing container {} from {}", object_id, start);
+    let Some(content_dir_url) = &server.content_directory_url else {
+        let error_msg = "No UPnP ContentDirectory service available".to_string();
+        tracing::warn!(target: "mop::upnp", "{}", error_msg);
+        return send(complete(0, None, Some(error_msg.into())));
+    };
+
+    tracing::debug!(target: "mop::soap", "SOAP Browse request to {} for container {}", content_dir_url, object_id);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let response = rt.block_on(async {
+        tokio::select! {
+            response = browse_request(content_dir_url, object_id, start, sort, server) => Some(response),
+            _ = cancel.cancelled() => None,
+        }
+    });
+    let parsed = match response {
+        Some(Ok(response)) => extract_didl_from_soap(&response).map(|didl| (didl, total_matches(&response))),
+        Some(Err(e)) => Err(e),
+        None => {
+            tracing::debug!(target: "mop::upnp", "Browse of container {} cancelled", object_id);
+            return send(complete(0, None, cancelled()));
+        }
+    };
+    let (didl, total) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            tracing::error!(target: "mop::soap", "Browse failed for container {}: {}", object_id, e);
+            return send(complete(0, None, Some(BrowseError::from_request(e.as_ref()))));
+        }
+    };
+
+    let mut found = 0;
+    for item in DidlItems::new(&didl) {
+        if cancel.is_cancelled() {
+            return send(complete(found, total, cancelled()));
+        }
+        match item {
+            Ok(item) => {
+                found += 1;
+                send(BrowseMessage::ItemFound(Box::new(to_directory_item(item, server))));
+            }
+            Err(e) => {
+                tracing::error!(target: "mop::soap", "Browse response for container {} broke off: {}", object_id, e);
+                let error = format!("UPnP ContentDirectory failed: {}", e);
+                return send(complete(found, total, Some(error.into())));
+            }
+        }
+    }
+    tracing::info!(target: "mop::upnp", "Browse returned {} items of {:?}", found, total);
+    send(complete(found, total, None));
+}
+
+/// Stream one page of container `object_id` on a background thread, like
+/// `start_discovery` does for devices.
+pub fn start_browse(server: PlexServer, object_id: String, start: usize, cancel: CancelToken) -> Receiver<BrowseMessage> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        stream_container_page(&server, &object_id, start, &cancel, |message| {
+            let _ = sender.send(message);
+        });
+    });
+    receiver
+}
+
+/// Browse a container by its ContentDirectory ObjectID.
+pub fn browse_object(server: &PlexServer, object_id: &str) -> Result<Vec<DirectoryItem>, String> {
+    if crate::jellyfin::handles(server) {
+        return crate::jellyfin::browse(server, object_id);
+    }
+    let content_dir_url = server
+        .content_directory_url
+        .as_deref()
+        .ok_or("No UPnP ContentDirectory service available")?;
+
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    rt.block_on(async {
+        let mut items = Vec::new();
+        loop {
+            let start = items.len();
+            let page = browse_upnp_content_directory_with_id(content_dir_url, object_id, start, server)
+                .await
+                .map_err(|e| format!("UPnP ContentDirectory failed: {}", e))?;
+            let last = page.is_last(start);
+            items.extend(page.items);
+            if last {
+                return Ok(items);
+            }
+        }
+    })
+}
+
+/// What a ContentDirectory says it searches and sorts by, from
+/// GetSearchCapabilities and GetSortCapabilities: property names such as
+/// "dc:title", or "*" for any. Empty when it can't.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub search: Vec<String>,
+    pub sort: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn can_search(&self, property: &str) -> bool {
+        allows(&self.search, property)
+    }
+
+    pub fn can_sort(&self, property: &str) -> bool {
+        allows(&self.sort, property)
+    }
+}
+
+fn allows(properties: &[String], property: &str) -> bool {
+    properties.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(property))
+}
+
+/// Ask `server` what it searches and sorts by. A server that answers
+/// neither question is taken to do neither; Jellyfin and Emby, browsed
+/// through their API, are asked nothing.
+pub fn capabilities(server: &PlexServer) -> Result<Capabilities, String> {
+    if crate::jellyfin::handles(server) {
+        return Ok(Capabilities::default());
+    }
+    let content_dir_url = server
+        .content_directory_url
+        .as_deref()
+        .ok_or("No UPnP ContentDirectory service available")?;
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let ask = |action: &'static str, tag: &'static str| {
+        let response = rt.block_on(content_directory_action(content_dir_url, action, "", server));
+        response.map(|response| capability_list(&response, tag)).map_err(|e| e.to_string())
+    };
+    let (search, sort) = (ask("GetSearchCapabilities", "SearchCaps"), ask("GetSortCapabilities", "SortCaps"));
+    if let (Err(e), Err(_)) = (&search, &sort) {
+        return Err(format!("UPnP ContentDirectory failed: {}", e));
+    }
+    let (search, sort) = (search.unwrap_or_default(), sort.unwrap_or_default());
+    let (searches, sorts) = (search.join(","), sort.join(","));
+    tracing::info!(target: "mop::upnp", "{} searches by [{}], sorts by [{}]", server.name, searches, sorts);
+    Ok(Capabilities { search, sort })
+}
+
+/// The comma-separated properties in `tag` of a capabilities response.
+fn capability_list(response: &str, tag: &str) -> Vec<String> {
+    let list = extract_xml_value(response, tag).unwrap_or_default();
+    list.split(',').map(str::trim).filter(|property| !property.is_empty()).map(str::to_string).collect()
+}
+
+/// How many containers `search_titles` looks through on a server that
+/// can't search by title.
+const SEARCH_BROWSE_LIMIT: usize = 500;
+
+/// Search a server for items whose title contains `query`: with Search
+/// when the server can search by title, otherwise by looking through its
+/// folders from the root, at most [`SEARCH_BROWSE_LIMIT`] of them.
+/// `capabilities` is what the server said it can do, when that's already
+/// known; otherwise it's asked.
+pub fn search_titles(
+    server: &PlexServer,
+    query: &str,
+    capabilities: Option<&Capabilities>,
+) -> Result<Vec<DirectoryItem>, String> {
+    if crate::jellyfin::handles(server) {
+        return crate::jellyfin::search(server, query);
+    }
+    let content_dir_url = server
+        .content_directory_url
+        .as_deref()
+        .ok_or("No UPnP ContentDirectory service available")?;
+    let asked = match capabilities {
+        Some(known) => Ok(known.clone()),
+        None => self::capabilities(server),
+    };
+    match asked {
+        Ok(capabilities) if !capabilities.can_search("dc:title") => {
+            tracing::info!(target: "mop::upnp", "{} can't search by title, looking through its folders", server.name);
+            return search_by_browsing(server, query);
+        }
+        Ok(_) => {}
+        Err(e) => tracing::debug!(target: "mop::upnp", "No capabilities from {}, searching anyway: {}", server.name, e),
+    }
+    let criteria = format!("dc:title contains \"{}\"", query.replace('"', "\\\""));
+
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    rt.block_on(search_upnp_content_directory(content_dir_url, "0", &criteria, server))
+        .map(|items| items.into_iter().map(|item| to_directory_item(item, server)).collect())
+        .map_err(|e| format!("UPnP Search failed: {}", e))
+}
+
+/// [`search_titles`] for servers without Search: every container and item
+/// whose title contains `query`, case aside, breadth first from the root.
+fn search_by_browsing(server: &PlexServer, query: &str) -> Result<Vec<DirectoryItem>, String> {
+    let query = query.to_lowercase();
+    let cancel = CancelToken::new();
+    let mut pending = std::collections::VecDeque::from(["0".to_string()]);
+    let mut browsed = 0;
+    let mut matches = Vec::new();
+    while let Some(object_id) = pending.pop_front()
+        && browsed < SEARCH_BROWSE_LIMIT
+    {
+        browsed += 1;
+        let (items, error) = browse_container(server, &object_id, &cancel);
+        if let Some(error) = error {
+            if browsed == 1 {
+                return Err(error.to_string());
+            }
+            tracing::debug!(target: "mop::upnp", "Skipping container {} in the search: {}", object_id, error);
+        }
+        for item in items {
+            if item.is_directory
+                && let Some(id) = &item.id
+            {
+                pending.push_back(id.clone());
+            }
+            if item.name.to_lowercase().contains(&query) {
+                matches.push(item);
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Blocking SOAP call for control points (routers, renderers), which unlike
+/// media servers don't need tokens or credentials. `args` are escaped here.
+pub fn soap_call(
+    client: &reqwest::blocking::Client,
+    control_url: &str,
+    service_type: &str,
+    action: &str,
+    args: &[(&str, &str)],
+) -> Result<String, String> {
+    let args: String = args
+        .iter()
+        .map(|(name, value)| format!("<{name}>{}</{name}>", quick_xml::escape::escape(value)))
+        .collect();
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+    <s:Body><u:{action} xmlns:u="{service_type}">{args}</u:{action}></s:Body>
+</s:Envelope>"#
+    );
+
+    tracing::debug!(target: "mop::soap", "{} -> {}", action, control_url);
+    let started = Instant::now();
+    let outcome = client
+        .post(control_url)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header("SOAPAction", format!("\"{}#{}\"", service_type, action))
+        .body(body.clone())
+        .send()
+        .and_then(|response| {
+            let status = response.status();
+            response.text().map(|text| (status, text))
+        })
+        .map_err(|e| e.to_string());
+    record_exchange(control_url, action, body, &outcome, started);
+
+    let (status, text) = outcome.map_err(|e| format!("{} failed: {}", action, e))?;
+    if let Some(fault) = soap_log::fault_summary(&text) {
+        return Err(format!("{} failed: {}", action, fault));
+    }
+    if !status.is_success() {
+        return Err(format!("{} failed: {}", action, status));
+    }
+    Ok(text)
+}
+
+/// What `server` can stream, from ConnectionManager::GetProtocolInfo: one
+/// `protocol:network:mime:extra` entry per source format.
+pub fn protocol_info(server: &PlexServer) -> Result<Vec<String>, String> {
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    rt.block_on(async {
+        let client = server.client_builder()?.timeout(Duration::from_secs(10)).build().map_err(|e| e.to_string())?;
+        let description = crate::auth::send(client.get(&server.location), server.credentials.as_ref())
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+        let (service_type, control_url) = find_service(&description, &server.location, |service_type| {
+            service_type.contains("ConnectionManager")
+        })
+        .ok_or("No ConnectionManager service")?;
+
+        let response = soap_action(&control_url, &service_type, "GetProtocolInfo", "", server)
+            .await
+            .map_err(|e| format!("GetProtocolInfo failed: {}", e))?;
+        let source = extract_xml_value(&response, "Source").unwrap_or_default();
+        let source = quick_xml::escape::unescape(&source).map(|source| source.into_owned()).unwrap_or(source);
+        Ok(source.split(',').map(str::trim).filter(|entry| !entry.is_empty()).map(str::to_string).collect())
+    })
+}
+
+/// ContentDirectory::GetSystemUpdateID, the cheapest call a media server
+/// answers: a counter that changes whenever its content does.
+pub fn system_update_id(server: &PlexServer) -> Result<String, String> {
+    let content_dir_url = server
+        .content_directory_url
+        .as_deref()
+        .ok_or("No UPnP ContentDirectory service available")?;
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let response = rt
+        .block_on(content_directory_action(content_dir_url, "GetSystemUpdateID", "", server))
+        .map_err(|e| e.to_string())?;
+    Ok(extract_xml_value(&response, "Id").unwrap_or_default())
+}
+
+/// The distinct MIME types in GetProtocolInfo entries, in the server's order.
+pub fn protocol_mime_types(protocol_info: &[String]) -> Vec<String> {
+    let mut mime_types: Vec<String> = Vec::new();
+    for entry in protocol_info {
+        if let Some(mime) = entry.split(':').nth(2)
+            && mime != "*"
+            && !mime_types.iter().any(|known| known == mime)
+        {
+            mime_types.push(mime.to_string());
+        }
+    }
+    mime_types
+}
+
+fn to_directory_item(item: UpnpItem, server: &PlexServer) -> DirectoryItem {
+    DirectoryItem {
+        id: Some(item.id),
+        name: item.title,
+        is_directory: item.is_container,
+        url: item
+            .resource_url
+            .map(|url| with_token(url, server.token.as_deref())),
+        metadata: if item.is_container {
+            None
+        } else {
+            Some(crate::FileMetadata {
+                size: item.size,
+                duration: item.duration,
+                format: item.format,
+                resolution: item.resolution,
+                thumbnail: item.thumbnail.map(|url| with_token(url, server.token.as_deref())),
+                dlna_flags: item.dlna_flags,
+                resources: if item.resources.len() > 1 {
+                    let token = server.token.as_deref();
+                    item.resources
+                        .into_iter()
+                        .map(|resource| crate::Resource { url: with_token(resource.url, token), ..resource })
+                        .collect()
+                } else {
+                    Vec::new()
+                },
+            })
+        },
+        class: item.class,
+        music: (item.music != crate::MusicTags::default()).then_some(item.music),
+    }
+}
+
+/// Add `X-Plex-Token` to a resource URL so players can fetch it without
+/// their own Plex login.
+fn with_token(url: String, token: Option<&str>) -> String {
+    let Some(token) = token else { return url };
+    match url::Url::parse(&url) {
+        Ok(mut parsed) => {
+            parsed.query_pairs_mut().append_pair("X-Plex-Token", token);
+            parsed.to_string()
+        }
+        Err(_) => url,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UpnpItem {
+    id: String,
+    title: String,
+    is_container: bool,
+    resource_url: Option<String>,
+    size: Option<u64>,
+    duration: Option<String>,
+    format: Option<String>,
+    resolution: Option<String>,
+    thumbnail: Option<String>,
+    dlna_flags: Option<String>,
+    /// Every `<res>` that isn't a thumbnail; the fields above are the first's.
+    resources: Vec<crate::Resource>,
+    class: Option<String>,
+    music: crate::MusicTags,
+    /// The artist so far is dc:creator's, to be replaced by a upnp:artist.
+    artist_from_creator: bool,
+}
+
+/// The DIDL-Lite elements whose text is kept.
+#[derive(Clone, Copy, PartialEq)]
+enum DidlText {
+    Title,
+    Resource,
+    /// `upnp:albumArtURI`, or an image `<res>` of something that isn't one.
+    Thumbnail,
+    Class,
+    Artist,
+    Creator,
+    Album,
+    TrackNumber,
+}
+
+impl DidlText {
+    fn of(name: &[u8]) -> Option<Self> {
+        Some(match name {
+            b"dc:title" => DidlText::Title,
+            b"res" => DidlText::Resource,
+            b"upnp:albumArtURI" => DidlText::Thumbnail,
+            b"upnp:class" => DidlText::Class,
+            b"upnp:artist" => DidlText::Artist,
+            b"dc:creator" => DidlText::Creator,
+            b"upnp:album" => DidlText::Album,
+            b"upnp:originalTrackNumber" => DidlText::TrackNumber,
+            _ => return None,
+        })
+    }
+
+    fn store(self, text: String, item: &mut UpnpItem) {
+        let music = &mut item.music;
+        match self {
+            DidlText::Title => item.title = text,
+            DidlText::Resource => {
+                if let Some(resource) = item.resources.last_mut() {
+                    resource.url = text.clone();
+                }
+                item.resource_url.get_or_insert(text);
+            }
+            DidlText::Thumbnail => {
+                item.thumbnail.get_or_insert(text);
+            }
+            DidlText::Class => item.class = Some(text),
+            // Servers list every artist role; the first is the main one.
+            // dc:creator stands in when there's no upnp:artist at all.
+            DidlText::Artist if music.artist.is_none() || item.artist_from_creator => {
+                music.artist = Some(text);
+                item.artist_from_creator = false;
+            }
+            DidlText::Creator if music.artist.is_none() => {
+                music.artist = Some(text);
+                item.artist_from_creator = true;
+            }
+            DidlText::Artist | DidlText::Creator => {}
+            DidlText::Album => music.album = Some(text),
+            DidlText::TrackNumber => music.track = text.parse().ok(),
+        }
+    }
+}
+
+/// One page of children, with the container's TotalMatches when given.
+async fn browse_upnp_content_directory_with_id(
+    content_dir_url: &str,
+    container_id: &str,
+    start: usize,
+    server: &UpnpDevice,
+) -> Result<BrowsePage, Box<dyn std::error::Error>> {
+    let response_text = browse_request(content_dir_url, container_id, start, "", server).await?;
+    Ok(parse_browse_response(&response_text, server)?)
+}
+
+/// The raw response to a BrowseDirectChildren request for one page.
+async fn browse_request(
+    content_dir_url: &str,
+    container_id: &str,
+    start: usize,
+    sort: &str,
+    server: &UpnpDevice,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let arguments = format!(
+        r#"<ObjectID>{}</ObjectID>
+            <BrowseFlag>BrowseDirectChildren</BrowseFlag>
+            <Filter>*</Filter>
+            <StartingIndex>{}</StartingIndex>
+            <RequestedCount>{}</RequestedCount>
+            <SortCriteria>{}</SortCriteria>"#,
+        container_id,
+        start,
+        BROWSE_PAGE_SIZE,
+        quick_xml::escape::escape(sort)
+    );
+    content_directory_action(content_dir_url, "Browse", &arguments, server).await
+}
+
+/// The children and TotalMatches in the body of a Browse response from
+/// `server`, whose token goes on the item URLs.
+pub fn parse_browse_response(response: &str, server: &PlexServer) -> Result<BrowsePage, String> {
+    let items = parse_didl_response(response).map_err(|e| format!("Malformed Browse response: {}", e))?;
+    Ok(BrowsePage {
+        items: items.into_iter().map(|item| to_directory_item(item, server)).collect(),
+        total: total_matches(response),
+        error: None,
+    })
+}
+
+/// TotalMatches from a Browse response. 0 means "don't know" per the spec.
+fn total_matches(response: &str) -> Option<usize> {
+    extract_xml_value(response, "TotalMatches")
+        .and_then(|total| total.trim().parse().ok())
+        .filter(|&total| total > 0)
+}
+
+async fn search_upnp_content_directory(
+    content_dir_url: &str,
+    container_id: &str,
+    criteria: &str,
+    server: &UpnpDevice,
+) -> Result<Vec<UpnpItem>, Box<dyn std::error::Error>> {
+    let arguments = format!(
+        r#"<ContainerID>{}</ContainerID>
+            <SearchCriteria>{}</SearchCriteria>
+            <Filter>*</Filter>
+            <StartingIndex>0</StartingIndex>
+            <RequestedCount>100</RequestedCount>
+            <SortCriteria></SortCriteria>"#,
+        quick_xml::escape::escape(container_id),
+        quick_xml::escape::escape(criteria)
+    );
+    let response_text = content_directory_action(content_dir_url, "Search", &arguments, server).await?;
+    parse_didl_response(&response_text)
+}
+
+/// POST a ContentDirectory SOAP action and return the raw response body.
+async fn content_directory_action(
+    content_dir_url: &str,
+    action: &str,
+    arguments: &str,
+    server: &UpnpDevice,
+) -> Result<String, Box<dyn std::error::Error>> {
+    soap_action(content_dir_url, "urn:schemas-upnp-org:service:ContentDirectory:1", action, arguments, server).await
+}
+
+/// A SOAP fault in answer to `action`, with the UPnP error code when the
+/// server gave one.
+#[derive(Debug)]
+struct SoapFault {
+    action: String,
+    code: Option<u32>,
+    summary: String,
+}
+
+impl std::fmt::Display for SoapFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UPnP {} fault: {}", self.action, self.summary)
+    }
+}
+
+impl std::error::Error for SoapFault {}
+
+/// POST a SOAP action to one of `server`'s services and return the raw
+/// response body.
+async fn soap_action(
+    control_url: &str,
+    service_type: &str,
+    action: &str,
+    arguments: &str,
+    server: &UpnpDevice,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let client = server
+        .client_builder()?
+        .timeout(Duration::from_secs(10))
+        .build()?;
+
+    let soap_action = format!("{}#{}", service_type, action);
+    let soap_body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+    <s:Body>
+        <u:{action} xmlns:u="{service_type}">
+            {arguments}
+        </u:{action}>
+    </s:Body>
+</s:Envelope>"#
+    );
+
+    let mut request = client
+        .post(control_url)
+        .header("Content-Type", "text/xml; charset=utf-8")
+        .header("SOAPAction", format!("\"{}\"", soap_action))
+        .header("User-Agent", "MOP/1.0");
+    if let Some(token) = &server.token {
+        request = request.header("X-Plex-Token", token);
+    }
+
+    let started = Instant::now();
+    let outcome: Result<(reqwest::StatusCode, String), String> = async {
+        let response = crate::auth::send(request.body(soap_body.clone()), server.credentials.as_ref())
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        Ok((status, text))
+    }
+    .await;
+    record_exchange(control_url, action, soap_body, &outcome, started);
+
+    let (status, response_text) = outcome?;
+    if let Some(summary) = soap_log::fault_summary(&response_text) {
+        let code = extract_xml_value(&response_text, "errorCode").and_then(|code| code.trim().parse().ok());
+        return Err(Box::new(SoapFault { action: action.to_string(), code, summary }));
+    }
+    if !status.is_success() {
+        return Err(format!("UPnP {} failed with status {}", action, status).into());
+    }
+    Ok(response_text)
+}
+
+/// Keep a SOAP exchange for the inspector, with the response body or why
+/// there wasn't one.
+fn record_exchange(
+    control_url: &str,
+    action: &str,
+    request: String,
+    outcome: &Result<(reqwest::StatusCode, String), String>,
+    started: Instant,
+) {
+    let (status, response) = match outcome {
+        Ok((status, text)) => (Some(status.as_u16()), text.clone()),
+        Err(e) => (None, e.clone()),
+    };
+    soap_log::record(SoapExchange {
+        at: SystemTime::now(),
+        control_url: control_url.to_string(),
+        action: action.to_string(),
+        request,
+        status,
+        response,
+        duration: started.elapsed(),
+    });
+}
+
+fn extract_didl_from_soap(soap_xml: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use quick_xml::Reader;
+    use quick_xml::events::Event;
+
+    let mut reader = Reader::from_str(soap_xml);
+    reader.config_mut().trim_text(true);
+
+    let mut in_result = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e)) if e.name().as_ref() == b"Result" => {
+                in_result = true;
+            }
+            Ok(Event::Text(e)) if in_result => {
+                // Unescape the XML entities
+                return Ok(e.unescape()?.into_owned());
+            }
+            Ok(Event::End(ref e)) if e.name().as_ref() == b"Result" => {
+                in_result = false;
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(Box::new(e)),
+            _ => {}
+        }
+    }
+
+    Err("No Result element found in SOAP response".into())
+}
+
+fn parse_didl_response(xml: &str) -> Result<Vec<UpnpItem>, Box<dyn std::error::Error>> {
+    // First, extract the DIDL-Lite XML from the SOAP response
+    let didl_xml = extract_didl_from_soap(xml)?;
+    Ok(DidlItems::new(&didl_xml).collect::<Result<_, _>>()?)
+}
+
+/// The containers and items of a DIDL-Lite document, parsed as they are
+/// pulled: the first entries of a response with thousands of them are
+/// usable before the rest has been read.
+struct DidlItems<'a> {
+    reader: quick_xml::Reader<&'a [u8]>,
+    done: bool,
+}
+
+impl<'a> DidlItems<'a> {
+    fn new(didl_xml: &'a str) -> Self {
+        let mut reader = quick_xml::Reader::from_str(didl_xml);
+        reader.config_mut().trim_text(true);
+        Self { reader, done: false }
+    }
+}
+
+impl Iterator for DidlItems<'_> {
+    type Item = Result<UpnpItem, quick_xml::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use quick_xml::events::Event;
+
+        let mut current_item: Option<UpnpItem> = None;
+        let mut reading: Option<DidlText> = None;
+
+        // Events borrow from the document, so only the values kept are copied
+        while !self.done {
+            match self.reader.read_event() {
+                Ok(Event::Start(e)) => match e.name().as_ref() {
+                    name @ (b"container" | b"item") => {
+                        current_item = Some(UpnpItem {
+                            id: get_attribute_value(&e, b"id").unwrap_or_default(),
+                            title: String::new(),
+                            is_container: name == b"container",
+                            resource_url: None,
+                            size: None,
+                            duration: None,
+                            format: None,
+                            resolution: None,
+                            thumbnail: None,
+                            dlna_flags: None,
+                            resources: Vec::new(),
+                            class: None,
+                            music: crate::MusicTags::default(),
+                            artist_from_creator: false,
+                        });
+                    }
+                    name => {
+                        reading = DidlText::of(name);
+                        if let (Some(DidlText::Resource), Some(item)) = (reading, current_item.as_mut())
+                            && !read_resource_attributes(&e, item)
+                        {
+                            reading = Some(DidlText::Thumbnail);
+                        }
+                    }
+                },
+                Ok(Event::Text(e)) => {
+                    if let (Some(field), Some(item)) = (reading, current_item.as_mut()) {
+                        field.store(e.unescape().unwrap_or_default().into_owned(), item);
+                    }
+                }
+                Ok(Event::CData(e)) => {
+                    if let (Some(field), Some(item)) = (reading, current_item.as_mut()) {
+                        field.store(String::from_utf8_lossy(&e).into_owned(), item);
+                    }
+                }
+                Ok(Event::End(e)) => match e.name().as_ref() {
+                    b"container" | b"item" => {
+                        if let Some(item) = current_item.take() {
+                            return Some(Ok(item));
+                        }
+                    }
+                    _ => reading = None,
+                },
+                Ok(Event::Eof) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Size, duration, MIME type and resolution from a `<res>`, kept as one
+/// of the item's resources and, for its first, as the item's own. An image
+/// `<res>` of a video or a track is its thumbnail rather than the file, and
+/// leaves the item as it was: false for those.
+fn read_resource_attributes(element: &quick_xml::events::BytesStart, item: &mut UpnpItem) -> bool {
+    let image = |mime: &str| mime.starts_with("image/");
+    let protocol_info = get_attribute_value(element, b"protocolInfo");
+    let mime = protocol_info.as_deref().and_then(|info| info.split(':').nth(2));
+    if mime.is_some_and(image)
+        && (item.class.as_deref().is_some_and(|class| !class.starts_with("object.item.imageItem"))
+            || item.format.as_deref().is_some_and(|format| !image(format)))
+    {
+        return false;
+    }
+
+    let mut resource = crate::Resource::default();
+    let mut duration = None;
+    for attr in element.attributes().with_checks(false).flatten() {
+        let value = String::from_utf8_lossy(&attr.value);
+        match attr.key.as_ref() {
+            b"size" => resource.size = value.parse().ok(),
+            b"duration" => duration = Some(value.into_owned()),
+            // "http-get:*:audio/flac:DLNA.ORG_OP=01"
+            b"protocolInfo" => {
+                let mut fields = value.split(':').skip(2);
+                resource.format = fields.next().map(str::to_string);
+                resource.dlna_flags = fields.next().filter(|flags| *flags != "*").map(str::to_string);
+            }
+            b"resolution" => resource.resolution = Some(value.into_owned()),
+            b"bitrate" => resource.bitrate = value.parse().ok(),
+            _ => {}
+        }
+    }
+    if item.resources.is_empty() {
+        item.size = resource.size;
+        item.duration = duration;
+        item.format = resource.format.clone();
+        item.resolution = resource.resolution.clone();
+        item.dlna_flags = resource.dlna_flags.clone();
+    }
+    item.resources.push(resource);
+    true
+}
+
+fn get_attribute_value(
+    element: &quick_xml::events::BytesStart,
+    attr_name: &[u8],
+) -> Option<String> {
+    element.attributes().with_checks(false).find_map(|a| match a {
+        Ok(attr) if attr.key.as_ref() == attr_name => {
+            Some(String::from_utf8_lossy(&attr.value).to_string())
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn soap_response_with_result(result: &str) -> String {
+        format!(
+            r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+    <s:Body>
+        <u:BrowseResponse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1">
+            <Result>{}</Result>
+        </u:BrowseResponse>
+    </s:Body>
+</s:Envelope>"#,
+            result
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        )
+    }
+
+    #[test]
+    fn parses_non_ascii_title_from_cdata() {
+        let didl = r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <container id="series-aeon">
+        <dc:title><![CDATA[Æon Flux]]></dc:title>
+    </container>
+</DIDL-Lite>"#;
+
+        let items = parse_didl_response(&soap_response_with_result(didl)).unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, "Æon Flux");
+        assert_eq!(items[0].id, "series-aeon");
+        assert!(items[0].is_container);
+    }
+
+    #[test]
+    fn didl_entries_come_out_before_the_rest_is_read() {
+        let didl = r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <item id="1"><dc:title>One</dc:title><res size="10" resolution="1920x1080">http://nas/1.mkv</res></item>
+    <item id="2"><dc:title>Two</dc:title></broken>"#;
+
+        let mut entries = DidlItems::new(didl);
+        let first = entries.next().unwrap().unwrap();
+        assert_eq!((first.id.as_str(), first.title.as_str()), ("1", "One"));
+        assert_eq!(first.resource_url.as_deref(), Some("http://nas/1.mkv"));
+        assert_eq!(first.size, Some(10));
+        assert_eq!(first.resolution.as_deref(), Some("1920x1080"));
+        assert!(entries.next().unwrap().is_err());
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn thumbnails_come_from_album_art_and_image_resources_of_other_items() {
+        let didl = r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+    <item id="1"><dc:title>Heat</dc:title><upnp:class>object.item.videoItem</upnp:class>
+        <res protocolInfo="http-get:*:video/x-matroska:*" size="10">http://nas/1.mkv</res>
+        <res protocolInfo="http-get:*:image/jpeg:DLNA.ORG_PN=JPEG_TN" size="2">http://nas/1.jpg</res></item>
+    <item id="2"><dc:title>Ronin</dc:title><upnp:albumArtURI>http://nas/2.jpg</upnp:albumArtURI>
+        <res protocolInfo="http-get:*:video/mp4:DLNA.ORG_OP=01">http://nas/2.mp4</res></item>
+    <item id="3"><dc:title>Beach</dc:title><upnp:class>object.item.imageItem.photo</upnp:class>
+        <res protocolInfo="http-get:*:image/jpeg:*">http://nas/3.jpg</res></item>
+</DIDL-Lite>"#;
+
+        let items: Vec<UpnpItem> = DidlItems::new(didl).collect::<Result<_, _>>().unwrap();
+        // The thumbnail listed after the video doesn't take its place
+        assert_eq!(items[0].resource_url.as_deref(), Some("http://nas/1.mkv"));
+        assert_eq!((items[0].size, items[0].format.as_deref()), (Some(10), Some("video/x-matroska")));
+        assert_eq!(items[0].dlna_flags, None);
+        assert_eq!(items[0].thumbnail.as_deref(), Some("http://nas/1.jpg"));
+        assert_eq!(items[1].thumbnail.as_deref(), Some("http://nas/2.jpg"));
+        assert_eq!(items[1].dlna_flags.as_deref(), Some("DLNA.ORG_OP=01"));
+        assert_eq!(items[2].resource_url.as_deref(), Some("http://nas/3.jpg"));
+        assert_eq!(items[2].thumbnail, None);
+    }
+
+    #[test]
+    fn every_res_of_a_file_is_kept_when_there_is_more_than_one() {
+        let didl = r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+    <item id="1"><dc:title>Heat</dc:title><upnp:class>object.item.videoItem</upnp:class>
+        <res protocolInfo="http-get:*:video/x-matroska:*" size="8500000000" resolution="1920x800"
+            bitrate="1250000" duration="2:50:12">http://nas/1.mkv</res>
+        <res protocolInfo="http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_MP_SD_AAC_MULT5;DLNA.ORG_OP=01"
+            resolution="720x300" bitrate="187500">http://nas/1.mp4?transcode=sd</res>
+        <res protocolInfo="http-get:*:image/jpeg:DLNA.ORG_PN=JPEG_TN">http://nas/1.jpg</res></item>
+    <item id="2"><dc:title>Ronin</dc:title><res protocolInfo="http-get:*:video/mp4:*">http://nas/2.mp4</res></item>
+</DIDL-Lite>"#;
+
+        let mut server = described_device("http://nas:8200/desc.xml".to_string(), "NAS", String::new(), None);
+        server.token = Some("t0k".to_string());
+        let items: Vec<DirectoryItem> =
+            DidlItems::new(didl).map(|item| to_directory_item(item.unwrap(), &server)).collect();
+        let metadata = items[0].metadata.as_ref().unwrap();
+        assert_eq!(items[0].url.as_deref(), Some("http://nas/1.mkv?X-Plex-Token=t0k"));
+        assert_eq!((metadata.size, metadata.resolution.as_deref()), (Some(8_500_000_000), Some("1920x800")));
+        assert_eq!(metadata.duration.as_deref(), Some("2:50:12"));
+        assert_eq!(metadata.thumbnail.as_deref(), Some("http://nas/1.jpg?X-Plex-Token=t0k"));
+        assert_eq!(metadata.resources.len(), 2);
+        let sd = &metadata.resources[1];
+        assert_eq!(sd.url, "http://nas/1.mp4?transcode=sd&X-Plex-Token=t0k");
+        assert_eq!((sd.format.as_deref(), sd.bitrate, sd.size), (Some("video/mp4"), Some(187_500), None));
+        assert_eq!(
+            sd.protocol_info().as_deref(),
+            Some("http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_MP_SD_AAC_MULT5;DLNA.ORG_OP=01")
+        );
+        assert!(items[1].metadata.as_ref().unwrap().resources.is_empty());
+    }
+
+    #[test]
+    fn music_tracks_carry_their_class_artist_album_and_number() {
+        let didl = r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+    <item id="64$1"><dc:title>Sinnerman</dc:title><dc:creator>Various</dc:creator>
+        <upnp:artist role="Performer">Nina Simone</upnp:artist><upnp:artist role="Composer">Trad.</upnp:artist>
+        <upnp:album>Pastel Blues</upnp:album><upnp:originalTrackNumber>9</upnp:originalTrackNumber>
+        <upnp:class>object.item.audioItem.musicTrack</upnp:class></item>
+    <container id="7"><dc:title>Pastel Blues</dc:title><dc:creator>Nina Simone</dc:creator>
+        <upnp:class>object.container.album.musicAlbum</upnp:class></container>
+    <item id="8"><dc:title>Clip</dc:title><upnp:class>object.item.videoItem</upnp:class></item>
+</DIDL-Lite>"#;
+
+        let server = described_device("http://nas:8200/desc.xml".to_string(), "NAS", String::new(), None);
+        let items: Vec<DirectoryItem> =
+            DidlItems::new(didl).map(|item| to_directory_item(item.unwrap(), &server)).collect();
+        assert_eq!(items[0].class.as_deref(), Some("object.item.audioItem.musicTrack"));
+        assert_eq!(
+            items[0].music,
+            Some(crate::MusicTags {
+                artist: Some("Nina Simone".to_string()),
+                album: Some("Pastel Blues".to_string()),
+                track: Some(9),
+            })
+        );
+        assert_eq!(items[1].music.as_ref().and_then(|music| music.artist.as_deref()), Some("Nina Simone"));
+        assert_eq!(items[2].music, None);
+    }
+
+    #[test]
+    fn pages_until_total_matches_is_reached() {
+        let page = |count: usize, total: Option<usize>| BrowsePage {
+            items: (0..count)
+                .map(|i| DirectoryItem {
+                    name: i.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            total,
+            error: None,
+        };
+        assert!(!page(BROWSE_PAGE_SIZE, Some(450)).is_last(0));
+        assert!(page(50, Some(450)).is_last(400));
+        // Without a total only a short page says the container is done
+        assert!(!page(BROWSE_PAGE_SIZE, None).is_last(0));
+        assert!(page(3, None).is_last(BROWSE_PAGE_SIZE));
+
+        assert_eq!(total_matches("<NumberReturned>200</NumberReturned><TotalMatches>10412</TotalMatches>"), Some(10412));
+        assert_eq!(total_matches("<TotalMatches>0</TotalMatches>"), None);
+    }
+
+    #[test]
+    fn containers_are_found_again_by_title_after_a_rescan() {
+        let entry = |id: &str, name: &str, is_directory: bool| DirectoryItem {
+            id: Some(id.to_string()),
+            name: name.to_string(),
+            is_directory,
+            ..Default::default()
+        };
+        let items = [entry("64$1", "Films", false), entry("64$2", "films", true), entry("64$3", "Films", true)];
+        assert_eq!(find_container(&items, "Films"), Some("64$3"));
+        assert_eq!(find_container(&items[..2], "Films"), Some("64$2"));
+        assert_eq!(find_container(&items, "TV"), None);
+
+        let fault = |code, summary: &str| BrowseError::Fault(code, summary.to_string());
+        assert!(fault(701, "701 No such object").is_no_such_object());
+        assert!(fault(710, "710 No such container").is_no_such_object());
+        assert!(!fault(501, "501 Action failed").is_no_such_object());
+        // Said in words, 701 is just text
+        assert!(!BrowseError::Failed("fault: 701".to_string()).is_no_such_object());
+        let summary = "701 No such object".to_string();
+        let error = BrowseError::from_request(&SoapFault { action: "Browse".to_string(), code: Some(701), summary });
+        assert_eq!(error, fault(701, "701 No such object"));
+        assert_eq!(error.to_string(), "UPnP ContentDirectory failed: UPnP Browse fault: 701 No such object");
+    }
+
+    #[test]
+    fn capabilities_come_from_comma_separated_lists() {
+        let response = soap_response_with_result("").replace(
+            "<Result></Result>",
+            "<SortCaps>dc:title, dc:date,upnp:originalTrackNumber</SortCaps><SearchCaps></SearchCaps>",
+        );
+        let capabilities = Capabilities {
+            search: capability_list(&response, "SearchCaps"),
+            sort: capability_list(&response, "SortCaps"),
+        };
+        assert!(capabilities.search.is_empty());
+        assert!(!capabilities.can_search("dc:title"));
+        assert!(capabilities.can_sort("dc:date"));
+        assert!(capabilities.can_sort("upnp:originalTrackNumber"));
+        assert!(!capabilities.can_sort("res@size"));
+        let any = Capabilities { search: vec!["*".to_string()], sort: Vec::new() };
+        assert!(any.can_search("dc:title"));
+    }
+
+    #[test]
+    fn cancelling_abandons_a_browse_the_server_never_answers() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // Accept and hold the connection without ever responding
+        std::thread::spawn(move || {
+            let _connection = listener.accept();
+            std::thread::sleep(Duration::from_secs(30));
+        });
+        let server = UpnpDevice {
+            name: "Slow".to_string(),
+            location: format!("http://{}/desc.xml", address),
+            base_url: format!("http://{}", address),
+            content_directory_url: Some(format!("http://{}/ctl", address)),
+            bypass_proxy: true,
+            ..Default::default()
+        };
+
+        let cancel = CancelToken::new();
+        let canceller = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        let started = std::time::Instant::now();
+        let page = browse_directory_page(&server, &[], &mut Default::default(), 0, &cancel);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(page.items.is_empty());
+        assert_eq!(page.error, Some(BrowseError::Cancelled));
+    }
+
+    #[test]
+    fn port_scan_candidates_cover_full_private_subnet() {
+        let candidates = port_scan_host_suffixes();
+
+        assert!(candidates.contains(&31));
+        assert!(candidates.contains(&1));
+        assert!(candidates.contains(&254));
+        assert!(!candidates.contains(&0));
+        assert!(!candidates.contains(&255));
+        assert_eq!(candidates.len(), 254);
+    }
+
+    #[test]
+    fn ssdp_search_targets_include_media_servers() {
+        let targets: Vec<String> = ssdp_search_targets(&SsdpConfig::default())
+            .into_iter()
+            .map(|target| target.to_string())
+            .collect();
+
+        assert_eq!(
+            targets,
+            vec![
+                "upnp:rootdevice".to_string(),
+                "urn:schemas-upnp-org:device:MediaServer:1".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn cidr_ranges_expand_to_host_addresses() {
+        let hosts = parse_cidr("192.168.1.17/30").unwrap();
+        assert_eq!(
+            hosts,
+            vec![Ipv4Addr::new(192, 168, 1, 17), Ipv4Addr::new(192, 168, 1, 18)]
+        );
+
+        assert_eq!(parse_cidr("10.0.0.0/24").unwrap().len(), 254);
+        assert_eq!(parse_cidr("10.0.0.5").unwrap(), vec![Ipv4Addr::new(10, 0, 0, 5)]);
+        assert!(parse_cidr("10.0.0.0/8").is_err());
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+        assert!(parse_cidr("nas.local/24").is_err());
+    }
+
+    #[test]
+    fn service_types_include_embedded_devices_once() {
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:ConnectionManager:1</serviceType></service>\
+            </serviceList><deviceList><device><serviceList>\
+            <service><serviceType> urn:schemas-upnp-org:service:ConnectionManager:1 </serviceType></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType></service>\
+            </serviceList></device></deviceList></device></root>";
+        assert_eq!(
+            service_types(description),
+            [
+                "urn:schemas-upnp-org:service:ContentDirectory:1",
+                "urn:schemas-upnp-org:service:ConnectionManager:1",
+                "urn:schemas-upnp-org:service:AVTransport:1",
+            ]
+        );
+    }
+
+    #[test]
+    fn blocklist_matches_name_host_or_location() {
+        let device = UpnpDevice {
+            name: "Living Room TV [MediaRenderer:1]".to_string(),
+            location: "http://192.168.1.40:9197/dmr".to_string(),
+            base_url: "http://192.168.1.40:9197".to_string(),
+            ..Default::default()
+        };
+
+        assert!(is_blocked(&device, &["living room".to_string()]));
+        assert!(is_blocked(&device, &["192.168.1.40".to_string()]));
+        assert!(is_blocked(&device, &["http://192.168.1.40:9197/dmr".to_string()]));
+        assert!(!is_blocked(&device, &["192.168.1.4".to_string()]));
+        assert!(!is_blocked(&device, &["".to_string()]));
+    }
+
+    #[test]
+    fn deduplicates_dlna_and_direct_plex_entries_by_base_url() {
+        let dlna = UpnpDevice {
+            name: "Plex Media Server: nasuntu".to_string(),
+            location: "http://192.168.1.31:32469/DeviceDescription.xml".to_string(),
+            base_url: "http://192.168.1.31:32400".to_string(),
+            device_client: Some("urn:schemas-upnp-org:device:MediaServer:1".to_string()),
+            content_directory_url: Some(
+                "http://192.168.1.31:32469/ContentDirectory/control.xml".to_string(),
+            ),
+            ..Default::default()
+        };
+        let direct = UpnpDevice {
+            name: "Plex Server (192.168.1.31:32400)".to_string(),
+            location: "http://192.168.1.31:32400".to_string(),
+            base_url: "http://192.168.1.31:32400".to_string(),
+            device_client: Some("DirectScan".to_string()),
+            ..Default::default()
+        };
+
+        assert!(is_same_discovered_device(&dlna, &direct));
+    }
+
+    #[test]
+    fn plex_dlna_scan_entries_use_plex_http_base_url() {
+        let friendly_name = "Plex Media Server: nasuntu";
+        let desc_text = "<manufacturer>Plex, Inc.</manufacturer>";
+        let ip = "192.168.1.31";
+        let dlna_url = format!("http://{}:32469", ip);
+        let base_url = dlna_device_base_url(ip, &dlna_url, friendly_name, desc_text);
+
+        assert_eq!(base_url, "http://192.168.1.31:32400");
+    }
+
+    #[test]
+    fn protocol_info_lists_distinct_mime_types() {
+        let info = [
+            "http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_BL_CIF15_AAC_520",
+            "http-get:*:video/mp4:DLNA.ORG_PN=AVC_MP4_MP_SD_AAC_MULT5",
+            "http-get:*:audio/mpeg:*",
+            "rtsp-rtp-udp:*:*:*",
+        ]
+        .map(str::to_string);
+        assert_eq!(protocol_mime_types(&info), vec!["video/mp4", "audio/mpeg"]);
+    }
+}
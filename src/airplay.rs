@@ -0,0 +1,206 @@
+// AirPlay (v1) video casting for Apple TVs. Receivers are found over mDNS
+// as `_airplay._tcp` services and driven with plain HTTP: /play hands over a
+// URL, /rate pauses and resumes, /scrub seeks and reports the position.
+//
+// The receiver ends playback when the connection that started it goes away,
+// so a `Session` keeps its HTTP client (and with it the connection) alive.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+const SERVICE_TYPE: &str = "_airplay._tcp.local.";
+/// Bit 0 of the `features` TXT record: the receiver plays video URLs.
+const FEATURE_VIDEO: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirPlayDevice {
+    pub name: String,
+    pub host: IpAddr,
+    pub port: u16,
+    pub model: Option<String>,
+}
+
+impl AirPlayDevice {
+    fn url(&self, path: &str) -> String {
+        match self.host {
+            IpAddr::V6(host) => format!("http://[{}]:{}{}", host, self.port, path),
+            IpAddr::V4(host) => format!("http://{}:{}{}", host, self.port, path),
+        }
+    }
+}
+
+/// Browse mDNS for AirPlay receivers that can play video, for `timeout`.
+pub fn discover(timeout: Duration) -> Result<Vec<AirPlayDevice>, String> {
+    use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+    let mdns = ServiceDaemon::new().map_err(|e| format!("mDNS unavailable: {}", e))?;
+    let events = mdns.browse(SERVICE_TYPE).map_err(|e| format!("mDNS browse failed: {}", e))?;
+
+    let mut devices: Vec<AirPlayDevice> = Vec::new();
+    let deadline = Instant::now() + timeout;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = events.recv_timeout(remaining) else { break };
+        let ServiceEvent::ServiceResolved(info) = event else { continue };
+
+        if !info.get_property_val_str("features").is_none_or(supports_video) {
+            tracing::debug!(target: "mop::net", "Skipping {}: no AirPlay video", info.get_fullname());
+            continue;
+        }
+        // Prefer IPv4, the receivers answer on both
+        let Some(host) = info
+            .get_addresses()
+            .iter()
+            .copied()
+            .min_by_key(|address| address.is_ipv6())
+        else {
+            continue;
+        };
+        let name = info
+            .get_fullname()
+            .strip_suffix(&format!(".{}", SERVICE_TYPE))
+            .unwrap_or(info.get_fullname())
+            .to_string();
+        if devices.iter().any(|device| device.name == name) {
+            continue;
+        }
+
+        tracing::info!(target: "mop::net", "Found AirPlay receiver {} at {}", name, host);
+        devices.push(AirPlayDevice {
+            name,
+            host,
+            port: info.get_port(),
+            model: info.get_property_val_str("model").map(str::to_string),
+        });
+    }
+
+    let _ = mdns.shutdown();
+    Ok(devices)
+}
+
+/// `features` is a hex bitmask, sometimes as two comma-separated halves.
+fn supports_video(features: &str) -> bool {
+    let low = features.split(',').next().unwrap_or_default().trim();
+    match u64::from_str_radix(low.trim_start_matches("0x").trim_start_matches("0X"), 16) {
+        Ok(bits) => bits & FEATURE_VIDEO != 0,
+        // Unknown format: let the receiver decide
+        Err(_) => true,
+    }
+}
+
+/// Playback on one receiver.
+pub struct Session {
+    device: AirPlayDevice,
+    client: reqwest::blocking::Client,
+    session_id: String,
+}
+
+impl Session {
+    /// Ask the receiver to play `url` from the start.
+    pub fn play(device: AirPlayDevice, url: &str) -> Result<Self, String> {
+        let client = crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)?
+            .timeout(Duration::from_secs(5))
+            .pool_idle_timeout(None)
+            .user_agent("MediaControl/1.0")
+            .build()
+            .map_err(|e| e.to_string())?;
+        let session = Self { device, client, session_id: session_id() };
+
+        session.post(
+            "/play",
+            Some(format!("Content-Location: {}\nStart-Position: 0\n", url)),
+        )?;
+        tracing::info!(target: "mop::net", "AirPlay: playing {} on {}", url, session.device.name);
+        Ok(session)
+    }
+
+    pub fn device(&self) -> &AirPlayDevice {
+        &self.device
+    }
+
+    /// 0.0 pauses, 1.0 plays.
+    pub fn set_rate(&self, rate: f32) -> Result<(), String> {
+        self.post(&format!("/rate?value={:.6}", rate), None)
+    }
+
+    pub fn seek(&self, position: f64) -> Result<(), String> {
+        self.post(&format!("/scrub?position={:.6}", position.max(0.0)), None)
+    }
+
+    /// (duration, position) in seconds.
+    pub fn progress(&self) -> Result<(f64, f64), String> {
+        let response = self
+            .client
+            .get(self.device.url("/scrub"))
+            .header("X-Apple-Session-ID", &self.session_id)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| format!("{} did not answer: {}", self.device.name, e))?;
+        parse_progress(&response).ok_or_else(|| format!("Unexpected progress from {}", self.device.name))
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        self.post("/stop", None)
+    }
+
+    fn post(&self, path: &str, body: Option<String>) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(self.device.url(path))
+            .header("X-Apple-Session-ID", &self.session_id);
+        request = match body {
+            Some(body) => request.header(reqwest::header::CONTENT_TYPE, "text/parameters").body(body),
+            None => request.header(reqwest::header::CONTENT_LENGTH, 0),
+        };
+
+        let response = request
+            .send()
+            .map_err(|e| format!("{} did not answer: {}", self.device.name, e))?;
+        match response.status().as_u16() {
+            200..=299 => Ok(()),
+            // Newer receivers want pairing before they accept anything
+            403 | 470 => Err(format!("{} requires AirPlay pairing, which MOP can't do", self.device.name)),
+            _ => Err(format!("{} refused {}: {}", self.device.name, path, response.status())),
+        }
+    }
+}
+
+/// Receivers want a UUID; it only has to be unique per session.
+fn session_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let hex = format!("{:032x}", nanos ^ ((std::process::id() as u128) << 64));
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// GET /scrub answers with "duration: 83.124794\nposition: 14.467000".
+fn parse_progress(text: &str) -> Option<(f64, f64)> {
+    let mut duration = None;
+    let mut position = None;
+    for line in text.lines() {
+        let (key, value) = line.split_once(':')?;
+        match key.trim() {
+            "duration" => duration = value.trim().parse().ok(),
+            "position" => position = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    Some((duration?, position?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_receiver_features_and_progress() {
+        assert!(supports_video("0x5A7FFFF7,0x1E"));
+        assert!(supports_video("0x77"));
+        assert!(!supports_video("0x4A7FCA00,0xBC354BD0"));
+
+        assert_eq!(parse_progress("duration: 83.124794\nposition: 14.467000\n"), Some((83.124794, 14.467)));
+        assert_eq!(parse_progress("duration: 83.1\n"), None);
+    }
+}
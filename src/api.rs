@@ -0,0 +1,368 @@
+// A local HTTP API over the daemon, for web dashboards, Stream Deck plugins
+// and anything else that would rather speak HTTP than the daemon's socket.
+// It only runs when `mop daemon run --http ADDR` asks for it, and answers
+// with the JSON the headless commands print with --json (see
+// docs/json-output.md).
+//
+// Every request needs the token made up when the API starts, as
+// `Authorization: Bearer TOKEN`. Browsers get no further than that: a
+// request with an Origin header is refused, so a web page can't post to
+// it, and so is one whose Host is a name other than localhost, which is
+// what a DNS rebinding page would send. Still, give it a loopback address
+// unless the whole network may browse and play.
+
+use crate::cast::Session;
+use crate::config::Config;
+use crate::headless::{Error, ErrorKind};
+use crate::output;
+use crate::upnp::UpnpDevice;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tiny_http::{Header, Request, Response};
+
+/// Every endpoint, with the method it takes.
+const ENDPOINTS: [(&str, &str); 6] = [
+    ("GET", "/devices"),
+    ("GET", "/browse"),
+    ("POST", "/play"),
+    ("POST", "/cast"),
+    ("POST", "/cast/stop"),
+    ("POST", "/refresh"),
+];
+
+/// What the API needs from the daemon.
+pub struct Backend {
+    /// The cached devices with `[servers]` applied, once the first discovery
+    /// round is done.
+    pub devices: Box<dyn Fn() -> Vec<UpnpDevice> + Send + Sync>,
+    /// Start a new discovery round now.
+    pub refresh: Box<dyn Fn() + Send + Sync>,
+    /// The config the daemon read for its latest round.
+    pub config: Box<dyn Fn() -> Config + Send + Sync>,
+}
+
+struct Api {
+    backend: Backend,
+    /// What requests have to bring in their Authorization header.
+    token: String,
+    /// The address listened on, which a Host header may name.
+    bound: SocketAddr,
+    /// The cast started last. One cast at a time, as in the TUI: starting
+    /// another stops it.
+    casting: Mutex<Option<Session>>,
+}
+
+/// A status code and the JSON to send with it, if any.
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    body: Option<String>,
+}
+
+impl Reply {
+    fn json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => Self { status: 200, body: Some(body) },
+            Err(e) => Self::error(500, format!("Failed to serialize the answer: {}", e)),
+        }
+    }
+
+    fn empty() -> Self {
+        Self { status: 204, body: None }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self { status, body: serde_json::to_string(&output::Error::new(message)).ok() }
+    }
+}
+
+impl From<Error> for Reply {
+    fn from(error: Error) -> Self {
+        let status = match error.kind {
+            ErrorKind::Other => 400,
+            ErrorKind::NotFound => 404,
+            // Nothing to look in yet, rather than a name that's wrong
+            ErrorKind::NoDevices => 503,
+            ErrorKind::Network => 502,
+        };
+        Self::error(status, error.message)
+    }
+}
+
+/// Listen on `address` ("127.0.0.1:8780") and answer requests from
+/// background threads for as long as the process runs. Returns the token
+/// requests have to bring.
+pub fn serve(address: &str, backend: Backend) -> Result<String, String> {
+    let server = tiny_http::Server::http(address).map_err(|e| format!("Cannot listen on {}: {}", address, e))?;
+    let bound = server.server_addr().to_ip().ok_or_else(|| format!("Cannot listen on {}", address))?;
+    let token = new_token()?;
+    let api = Arc::new(Api { backend, token: token.clone(), bound, casting: Mutex::new(None) });
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let api = api.clone();
+            std::thread::spawn(move || answer(request, &api));
+        }
+    });
+    Ok(token)
+}
+
+/// 128 random bits, in hex.
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Cannot make up an API token: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn answer(request: Request, api: &Api) {
+    let header = |name: &str| {
+        let header = request.headers().iter().find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name));
+        header.map(|header| header.value.as_str())
+    };
+    let refused = api.refuse(header("Host"), header("Origin"), header("Authorization"));
+    let reply = refused.unwrap_or_else(|| api.handle(request.method().as_str(), request.url()));
+    tracing::info!(target: "mop::net", "API {} {}: {}", request.method(), request.url(), reply.status);
+
+    let mut response = Response::from_string(reply.body.unwrap_or_default()).with_status_code(reply.status);
+    if reply.status != 204 {
+        response.add_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("valid header"));
+    }
+    if let Err(e) = request.respond(response) {
+        tracing::debug!(target: "mop::net", "API client went away: {}", e);
+    }
+}
+
+impl Api {
+    /// Why a request with these headers isn't answered, if it isn't.
+    fn refuse(&self, host: Option<&str>, origin: Option<&str>, authorization: Option<&str>) -> Option<Reply> {
+        if origin.is_some() {
+            return Some(Reply::error(403, "Requests from web pages are not accepted"));
+        }
+        if !host.is_some_and(|host| self.host_allowed(host)) {
+            return Some(Reply::error(403, "The Host header has to be localhost or the address listened on"));
+        }
+        let token = authorization.and_then(|value| value.strip_prefix("Bearer ")).map(str::trim);
+        if token != Some(self.token.as_str()) {
+            return Some(Reply::error(401, "Missing or wrong token; send it as Authorization: Bearer TOKEN"));
+        }
+        None
+    }
+
+    /// Loopback, or the address listened on. Listening on every address,
+    /// any address will do; names other than localhost never do.
+    fn host_allowed(&self, host: &str) -> bool {
+        let name = match host.rsplit_once(':') {
+            // "[::1]:8780", "127.0.0.1:8780"
+            Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) && !name.ends_with(':') => name,
+            _ => host,
+        };
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        if name.eq_ignore_ascii_case("localhost") {
+            return true;
+        }
+        match name.parse::<IpAddr>() {
+            Ok(ip) => ip.is_loopback() || ip == self.bound.ip() || self.bound.ip().is_unspecified(),
+            Err(_) => false,
+        }
+    }
+
+    fn handle(&self, method: &str, url: &str) -> Reply {
+        let Ok(url) = url::Url::parse(&format!("http://localhost{}", url)) else {
+            return Reply::error(400, format!("Bad request URL {}", url));
+        };
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let result = match (method, url.path()) {
+            ("GET", "/devices") => {
+                let devices = (self.backend.devices)();
+                Ok(Reply::json(&devices.iter().map(output::Device::from).collect::<Vec<_>>()))
+            }
+            ("GET", "/browse") => self.browse(&query),
+            ("POST", "/play") => self.play(&query),
+            ("POST", "/cast") => self.cast(&query),
+            ("POST", "/cast/stop") => self.stop_cast(),
+            ("POST", "/refresh") => {
+                (self.backend.refresh)();
+                Ok(Reply::empty())
+            }
+            (_, path) => {
+                return match ENDPOINTS.iter().find(|(_, endpoint)| *endpoint == path) {
+                    Some((allowed, _)) => Reply::error(405, format!("{} takes {} requests", path, allowed)),
+                    None => Reply::error(404, format!("No such endpoint: {}", path)),
+                };
+            }
+        };
+        result.unwrap_or_else(Reply::from)
+    }
+
+    fn device(&self, query: &HashMap<String, String>) -> Result<UpnpDevice, Error> {
+        let name = required(query, "device")?;
+        let devices = (self.backend.devices)();
+        if devices.is_empty() {
+            return Err(Error::no_devices());
+        }
+        devices
+            .into_iter()
+            .find(|device| crate::upnp::device_matches(device, name))
+            .ok_or_else(|| Error::not_found(format!("No device matching '{}' found", name)))
+    }
+
+    fn browse(&self, query: &HashMap<String, String>) -> Result<Reply, Error> {
+        let device = self.device(query)?;
+        let path = query.get("path").map_or("/", String::as_str);
+        let items = crate::headless::list(&device, path)?;
+        Ok(Reply::json(&items.iter().map(output::Item::from).collect::<Vec<_>>()))
+    }
+
+    fn play(&self, query: &HashMap<String, String>) -> Result<Reply, Error> {
+        let device = self.device(query)?;
+        let items = crate::headless::resolve_playable(&device, required(query, "path")?)?;
+        let config = (self.backend.config)();
+        let player = crate::headless::play(&config, &device, &items)?;
+        Ok(Reply::json(&output::Played {
+            player: player.to_string(),
+            items: items.iter().map(output::Item::from).collect(),
+        }))
+    }
+
+    /// Cast the first file `path` resolves to on the receiver whose name
+    /// contains `renderer`, or whose UDN it is.
+    fn cast(&self, query: &HashMap<String, String>) -> Result<Reply, Error> {
+        let device = self.device(query)?;
+        let wanted = required(query, "renderer")?;
+        let items = crate::headless::resolve_playable(&device, required(query, "path")?)?;
+        let item = &items[0];
+        let url = item.url.as_deref().ok_or_else(|| Error::not_found(format!("{} has no URL", item.name)))?;
+
+        let targets = crate::cast::discover_targets()
+            .recv()
+            .map_err(|_| Error::network("Receiver discovery stopped"))?
+            .map_err(Error::network)?;
+        let lowercase = wanted.to_lowercase();
+        let target = targets
+            .iter()
+            .find(|target| target.matches_udn(wanted) || target.name().to_lowercase().contains(&lowercase))
+            .ok_or_else(|| Error::not_found(format!("No receiver matching '{}' found", wanted)))?;
+
+        let mut casting = self.casting.lock().unwrap();
+        if let Some(previous) = casting.take() {
+            let _ = previous.stop();
+        }
+        let protocol_info = item.metadata.as_ref().and_then(|metadata| metadata.protocol_info());
+        let session = Session::start(target, url, &item.name, protocol_info.as_deref()).map_err(Error::network)?;
+        let reply = Reply::json(&output::Cast {
+            renderer: session.target_name().to_string(),
+            items: vec![output::Item::from(item)],
+        });
+        *casting = Some(session);
+        Ok(reply)
+    }
+
+    fn stop_cast(&self) -> Result<Reply, Error> {
+        let session = self.casting.lock().unwrap().take();
+        let session = session.ok_or_else(|| Error::not_found("Nothing is being cast"))?;
+        session.stop().map_err(Error::network)?;
+        Ok(Reply::empty())
+    }
+}
+
+fn required<'a>(query: &'a HashMap<String, String>, name: &str) -> Result<&'a str, Error> {
+    match query.get(name).map(|value| value.trim()) {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(Error::from(format!("Missing the {} parameter", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// An API over the devices given as JSON, with the token "s3cret",
+    /// listening on 127.0.0.1:8780.
+    fn test_api(devices: Vec<serde_json::Value>, refresh: impl Fn() + Send + Sync + 'static) -> Api {
+        let devices: Vec<UpnpDevice> =
+            devices.into_iter().map(|device| serde_json::from_value(device).unwrap()).collect();
+        Api {
+            backend: Backend {
+                devices: Box::new(move || devices.clone()),
+                refresh: Box::new(refresh),
+                config: Box::new(Config::default),
+            },
+            token: "s3cret".to_string(),
+            bound: "127.0.0.1:8780".parse().unwrap(),
+            casting: Mutex::new(None),
+        }
+    }
+
+    #[test]
+    fn requests_are_routed_and_failures_answered_as_json() {
+        let refreshed = Arc::new(AtomicBool::new(false));
+        let flag = refreshed.clone();
+        let nas = serde_json::json!({
+            "name": "NAS",
+            "location": "http://127.0.0.1:9/rootDesc.xml",
+            "base_url": "http://127.0.0.1:9",
+            "device_client": null,
+            "content_directory_url": null,
+        });
+        let api = test_api(vec![nas], move || flag.store(true, Ordering::SeqCst));
+        let body = |reply: &Reply| serde_json::from_str::<serde_json::Value>(reply.body.as_deref().unwrap()).unwrap();
+
+        let devices = api.handle("GET", "/devices");
+        assert_eq!(devices.status, 200);
+        assert_eq!(body(&devices)[0]["name"], "NAS");
+
+        let missing = api.handle("GET", "/browse?path=/Movies");
+        assert_eq!(missing.status, 400);
+        assert_eq!(body(&missing)["error"]["message"], "Missing the device parameter");
+        let unknown = api.handle("GET", "/browse?device=tv");
+        assert_eq!(unknown.status, 404);
+        assert_eq!(body(&unknown)["error"]["message"], "No device matching 'tv' found");
+        assert_eq!(api.handle("POST", "/play?device=nas").status, 400);
+        assert_eq!(api.handle("POST", "/cast/stop").status, 404);
+        assert_eq!(api.handle("GET", "/play").status, 405);
+        assert_eq!(api.handle("GET", "/nowhere").status, 404);
+
+        assert_eq!(api.handle("POST", "/refresh").status, 204);
+        assert!(refreshed.load(Ordering::SeqCst));
+
+        // No devices at all isn't the same as a name that matches none
+        let empty = test_api(Vec::new(), || {});
+        assert_eq!(empty.handle("GET", "/browse?device=tv").status, 503);
+    }
+
+    #[test]
+    fn only_requests_with_the_token_from_outside_a_browser_are_answered() {
+        let api = test_api(Vec::new(), || {});
+        let refused = |host, origin, authorization| api.refuse(host, origin, authorization).map(|reply| reply.status);
+        let token = Some("Bearer s3cret");
+        assert_eq!(refused(Some("127.0.0.1:8780"), None, token), None);
+        assert_eq!(refused(Some("localhost:8780"), None, token), None);
+        assert_eq!(refused(Some("[::1]:8780"), None, token), None);
+
+        // The token missing, wrong, or not as a bearer token
+        assert_eq!(refused(Some("127.0.0.1:8780"), None, None), Some(401));
+        assert_eq!(refused(Some("127.0.0.1:8780"), None, Some("Bearer guess")), Some(401));
+        assert_eq!(refused(Some("127.0.0.1:8780"), None, Some("Bearer ")), Some(401));
+        assert_eq!(refused(Some("127.0.0.1:8780"), None, Some("s3cret")), Some(401));
+        assert_eq!(refused(Some("127.0.0.1:8780"), None, Some("Basic s3cret")), Some(401));
+
+        // Any web page, even one on localhost, and even with the token
+        assert_eq!(refused(Some("127.0.0.1:8780"), Some("https://evil.example"), token), Some(403));
+        assert_eq!(refused(Some("127.0.0.1:8780"), Some("http://localhost:8780"), token), Some(403));
+        assert_eq!(refused(Some("127.0.0.1:8780"), Some("null"), token), Some(403));
+
+        // A DNS rebinding page sends its own name; other addresses aren't this one
+        assert_eq!(refused(Some("rebound.evil.example:8780"), None, token), Some(403));
+        assert_eq!(refused(Some("rebound.evil.example"), None, token), Some(403));
+        assert_eq!(refused(Some("localhost.evil.example:8780"), None, token), Some(403));
+        assert_eq!(refused(Some("192.168.1.5:8780"), None, token), Some(403));
+        assert_eq!(refused(None, None, token), Some(403));
+
+        // Listening on every address, any address is this one, but still no names
+        let everywhere = Api { bound: "0.0.0.0:8780".parse().unwrap(), ..test_api(Vec::new(), || {}) };
+        assert_eq!(everywhere.refuse(Some("192.168.1.5:8780"), None, token).map(|reply| reply.status), None);
+        assert_eq!(everywhere.refuse(Some("nas.local:8780"), None, token).map(|reply| reply.status), Some(403));
+    }
+}
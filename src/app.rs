@@ -1,9 +1,24 @@
 use crate::logger::LogBuffer;
-use crate::upnp::{PlexServer, DiscoveryMessage};
+use crate::upnp::{BrowseMessage, PlexServer, DiscoveryMessage};
 use crate::config::Config;
-use std::sync::mpsc::Receiver;
-use std::collections::HashMap;
+use crate::event::{AppEvent, Browse, Events};
+use crate::workers::Workers;
+use crate::cache::{Cache, LastLocation};
+use mop_core::cancel::CancelToken;
+use mop_core::diagnostics::{CheckResult, DiagnosticsMessage, Outcome};
+use mop_core::soap_log::SoapExchange;
+use mop_core::stream::{Throughput, Verdict};
+use crate::duplicates::Duplicates;
+use crate::hide::HideList;
+use crate::hooks::Hook;
+use crate::keymap::{Action, Keymap};
+use crate::settings::ConfigEditor;
+use crate::sort::Sort;
 use tui_input::Input;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+pub use mop_core::DirectoryItem;
 
 
 #[derive(Debug, Clone)]
@@ -17,17 +32,147 @@ pub struct App {
     pub servers: Vec<PlexServer>,
     pub selected_server: Option<usize>,
     pub current_directory: Vec<String>,
+    /// ObjectID of each container in `current_directory`; browsing goes by
+    /// these, so the titles are only for show.
+    directory_ids: Vec<String>,
+    /// What of `listing` is on screen: all of it, less whatever `[hide]`
+    /// leaves out unless `show_hidden` and whatever `filter` doesn't match.
     pub directory_contents: Vec<DirectoryItem>,
+    /// The browsed container's children as loaded so far, hidden ones too.
+    listing: Vec<DirectoryItem>,
+    /// Where in `listing` each ObjectID is, the first time it came.
+    listed: HashMap<String, usize>,
+    hide: HideList,
+    /// Entries of `listing` that `hide` hides and aren't collapsed copies,
+    /// counted as the listing changes rather than on every frame.
+    hidden: usize,
+    /// Hidden entries are being shown anyway.
+    pub show_hidden: bool,
+    /// The order asked for; the server's own when None.
+    sort: Option<Sort>,
+    /// What each server, by location, sorts by; None while it's being asked.
+    capabilities: HashMap<String, Option<crate::upnp::Capabilities>>,
+    /// Files in `listing` more than once.
+    duplicates: Duplicates,
+    /// Only titles containing this, lowercased, are shown; empty for all.
+    filter: String,
+    /// Children in the browsed container, when the server said.
+    pub directory_total: Option<usize>,
+    /// The last page of the container has been loaded.
+    directory_complete: bool,
     pub selected_item: Option<usize>,
+    /// The ".." row above the listing is highlighted rather than `selected_item`.
+    pub parent_selected: bool,
+    /// First listing row on screen, kept between frames by the renderer.
+    pub directory_scroll: usize,
     pub last_error: Option<String>,
     pub discovery_errors: Vec<String>,
-    discovery_receiver: Option<Receiver<DiscoveryMessage>>,
     pub is_discovering: bool,
+    /// A browse of `current_directory` is on its way.
+    pub is_browsing: bool,
+    /// The rows on screen are still the folder left behind, until the
+    /// first page of the one opened arrives.
+    stale_listing: bool,
+    /// Cancels the browse in flight when the user moves elsewhere.
+    browse_cancel: CancelToken,
+    /// Counts `load_page` calls; only the latest one's results are shown.
+    browse_generation: u64,
+    /// Children received so far of the page being loaded.
+    page_found: usize,
+    /// The folder last found again by title after its ObjectIDs stopped
+    /// working, so a server that still refuses it isn't walked again.
+    resolved_path: Option<Vec<String>>,
+    /// Servers and listings from earlier runs; `None` until `open_cache`.
+    cache: Option<Cache>,
+    /// Locations of servers loaded from the cache that discovery hasn't
+    /// found again yet.
+    cached_servers: HashSet<String>,
+    /// Whether each server answered its last check, by location.
+    pub health: HashMap<String, crate::health::Health>,
+    /// Locations of servers whose check hasn't come back yet.
+    checking_health: HashSet<String>,
+    /// Where the last run quit, to be reopened once discovery finds its
+    /// server (`mop.restore_last_location`).
+    pending_location: Option<LastLocation>,
+    /// When the listing on screen was cached, while it hasn't been
+    /// replaced by a fresh browse.
+    pub listing_cached_at: Option<i64>,
     pub show_help: bool,
     pub show_config: bool,
+    /// The paste-a-URL dialog, while it is open.
+    pub url_dialog: Option<InputDialog>,
+    /// The go-to-ObjectID prompt, while it is open.
+    pub object_id_dialog: Option<InputDialog>,
+    pub sleep_dialog: Option<InputDialog>,
+    pub filter_dialog: Option<FilterDialog>,
+    pub palette: Option<Palette>,
+    /// Letter keys jump to the first entry starting with them.
+    pub letter_jump: bool,
+    /// When the sleep timer goes off, if it's set.
+    pub sleep_at: Option<std::time::Instant>,
+    /// Counts sleep timers set; only the latest one goes off.
+    sleep_timer: u64,
+    /// Plex account linking for a server, while the dialog is open.
+    pub plex_link: Option<PlexLinkDialog>,
+    /// Picking a receiver for the selected item, then controlling playback.
+    pub cast: Option<CastDialog>,
+    /// Network details of a router, opened instead of browsing it.
+    pub gateway: Option<GatewayDialog>,
+    pub qr: Option<QrDialog>,
+    pub variants: Option<VariantDialog>,
+    /// The file last played, until the player is seen to keep running, for
+    /// trying the file's other variants if it doesn't.
+    recovery: Option<Recovery>,
+    /// What played on each server in place of variants picked that didn't,
+    /// by server key.
+    worked: HashMap<String, Vec<crate::quality::Worked>>,
+    /// Direct play or a transcode of a Plex video, while the picker is open.
+    pub plex_streams: Option<PlexStreamDialog>,
+    pub soap_log: Option<SoapLogDialog>,
+    pub diagnostics: Option<DiagnosticsDialog>,
+    /// Numbers diagnostics runs, so a closed one's results are dropped.
+    diagnostics_runs: u64,
+    /// Local network access is blocked; open until it's allowed or dismissed.
+    pub permission: Option<PermissionDialog>,
+    /// Permission came through while discovery was running without it.
+    rescan_pending: bool,
+    /// What the Flatpak or Snap sandbox is missing to reach the network.
+    network_restriction: Option<String>,
+    /// GetProtocolInfo per server location, fetched when a server is highlighted.
+    pub protocol_info: HashMap<String, ProtocolInfo>,
+    /// Thumbnails by URL, fetched when a file that has one is highlighted.
+    pub thumbnails: HashMap<String, Thumbnail>,
+    /// The last thumbnail drawn, as `thumbnail::rows` gave it, by URL and
+    /// the cells it was scaled to.
+    thumbnail_rows: Option<((String, u16, u16), Vec<ratatui::text::Line<'static>>)>,
+    /// Files downloading, waiting to, and done since MOP started.
+    pub downloads: crate::downloads::Downloads,
+    pub show_downloads: bool,
+    /// What each player command plays, once asked (`[players] unplayable`).
+    player_support: Option<HashMap<String, crate::playable::Support>>,
+    /// Why the player can't play files of `listing`, by URL, for those it
+    /// can't; worked out as they're listed rather than on every frame.
+    unplayable: HashMap<String, String>,
+    /// The players are being asked what they play.
+    probing_players: bool,
+    /// Listening for SSDP announcements, while it is.
+    announcements: Option<mop_core::announcements::Listener>,
+    /// The open server's ContentDirectory events, while subscribed.
+    container_watch: Option<crate::gena::Watch>,
+    /// Update counts of the open server's containers, by ObjectID.
+    pub container_updates: HashMap<String, ContainerUpdate>,
     pub should_quit: bool,
-    pub container_id_map: HashMap<Vec<String>, String>,
     pub config: Config,
+    pub config_warnings: Vec<String>,
+    /// Active network profile, if any.
+    pub profile: Option<String>,
+    /// The profile asked for at startup, re-resolved when the network changes.
+    requested_profile: Option<String>,
+    /// Ask a running daemon for devices before discovering directly.
+    pub use_daemon: bool,
+    /// Running with `--demo`: discovery finds only this server.
+    pub demo: Option<crate::demo::DemoServer>,
+    pub keymap: Keymap,
     pub config_editor: ConfigEditor,
     pub log_buffer: LogBuffer,
     pub log_pane_state: LogPaneState,
@@ -36,18 +181,341 @@ pub struct App {
     pub log_filter_input: String,
     pub log_filter_active: bool,
     pub log_auto_scroll: bool,
+    pub events: Events,
+    workers: Workers,
+    /// Something on screen changed since the last frame.
+    pub needs_redraw: bool,
+    /// `logger::entries_logged()` as of the last frame.
+    logs_drawn: usize,
+    /// `sleep_minutes_left()` as of the last frame.
+    sleep_drawn: Option<u64>,
 }
 
-pub struct ConfigEditor {
-    pub run_input: Input,
-    pub auto_close: bool,
-    pub selected_field: ConfigField,
+/// A container's update count from the server's events.
+#[derive(Debug)]
+pub struct ContainerUpdate {
+    pub count: u32,
+    /// The count when it was last opened, here or in an earlier run; none
+    /// for a container first heard of changing.
+    pub seen: Option<u32>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ConfigField {
-    Run,
-    AutoClose,
+/// A one-line text prompt: the paste-a-URL and go-to-ObjectID dialogs.
+#[derive(Debug, Default)]
+pub struct InputDialog {
+    pub input: Input,
+    pub error: Option<String>,
+}
+
+/// The listing filter prompt, which narrows the listing as it's typed.
+/// Up and Down step through the queries used on the server before.
+#[derive(Debug, Default)]
+pub struct FilterDialog {
+    pub input: Input,
+    /// Earlier queries, the latest first.
+    history: Vec<String>,
+    /// Which of them is in `input`, while stepping through them.
+    recalled: Option<usize>,
+    /// What was typed before stepping back, for stepping forward to.
+    draft: String,
+    /// The filter before the prompt opened, for Esc to go back to.
+    previous: String,
+}
+
+impl FilterDialog {
+    fn older(&mut self) {
+        let index = self.recalled.map_or(0, |index| index + 1);
+        let Some(query) = self.history.get(index) else { return };
+        if self.recalled.is_none() {
+            self.draft = self.input.value().to_string();
+        }
+        self.input = Input::default().with_value(query.clone());
+        self.recalled = Some(index);
+    }
+
+    fn newer(&mut self) {
+        let Some(index) = self.recalled else { return };
+        let query = match index.checked_sub(1) {
+            Some(newer) => self.history[newer].clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.input = Input::default().with_value(query);
+        self.recalled = index.checked_sub(1);
+    }
+}
+
+/// The command palette: every action by name, narrowed to those with all
+/// the typed words in them, for running one without knowing its key.
+#[derive(Debug, Default)]
+pub struct Palette {
+    pub input: Input,
+    /// Index into `commands()` of the highlighted one.
+    pub selected: usize,
+}
+
+impl Palette {
+    /// The actions matching what's typed, in keymap order. Moving around
+    /// and the palette itself are left out.
+    pub fn commands(&self) -> Vec<Action> {
+        let typed = self.input.value().to_lowercase();
+        Action::ALL
+            .into_iter()
+            .filter(|action| {
+                !matches!(action, Action::Up | Action::Down | Action::Select | Action::Back | Action::Palette)
+            })
+            .filter(|action| {
+                let name = action.name().replace('_', " ");
+                typed.split_whitespace().all(|word| name.contains(word))
+            })
+            .collect()
+    }
+}
+
+pub struct PlexLinkDialog {
+    pub server_name: String,
+    /// The server being linked, for finding its `[servers]` entry.
+    device: crate::upnp::UpnpDevice,
+    pub code: Option<String>,
+    pub error: Option<String>,
+    /// Held so closing the dialog stops the polling.
+    _link: crate::plex::Link,
+}
+
+pub struct CastDialog {
+    pub item_name: String,
+    /// The file or folder being cast, as listed; which of a file's
+    /// resources is sent depends on the receivers.
+    item: DirectoryItem,
+    /// A folder's files, cast one after another; empty for a file, and
+    /// until they've been read.
+    queue: Vec<DirectoryItem>,
+    /// The folder's files are still being read.
+    reading_queue: bool,
+    /// What was sent of `queue`, once casting started.
+    queued: Vec<crate::cast_queue::Queued>,
+    /// Which of `queued` a renderer that's being followed through them is on.
+    pub queue_position: Option<usize>,
+    /// Keeps the renderer going through `queued`.
+    follow: Option<crate::cast_queue::Follow>,
+    /// Cast to the target with this UDN as soon as it shows up.
+    auto_target: Option<String>,
+    pub targets: Vec<crate::cast::Target>,
+    pub selected: usize,
+    /// Targets marked to be cast to together, by index, in list order.
+    pub marked: Vec<usize>,
+    searching: bool,
+    /// Playback is being started on the selected target.
+    pub starting: bool,
+    /// Set once playback started on the selected target.
+    /// Shared with the workers sending it commands.
+    pub session: Option<Arc<crate::cast::Session>>,
+    pub paused: bool,
+    /// (duration, position) in seconds, as of the last seek.
+    pub progress: Option<(f64, f64)>,
+    pub notice: Option<String>,
+    pub error: Option<String>,
+}
+
+impl CastDialog {
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
+    /// What's being cast: the file, or the folder and how many files it has.
+    pub fn title(&self) -> String {
+        match (self.item.is_directory, self.reading_queue) {
+            (false, _) => self.item_name.clone(),
+            (true, true) => format!("{} (reading…)", self.item_name),
+            (true, false) => format!("{} ({} files)", self.item_name, self.queue.len()),
+        }
+    }
+
+    /// (position from 1, count, title) of the file a renderer followed
+    /// through a folder is on.
+    pub fn now_playing(&self) -> Option<(usize, usize, &str)> {
+        let position = self.queue_position?;
+        Some((position + 1, self.queued.len(), self.queued.get(position)?.title.as_str()))
+    }
+}
+
+/// The files of a folder, in order, ready to hand to the player.
+#[derive(Debug)]
+pub struct Playlist {
+    pub folder: String,
+    pub urls: Vec<String>,
+    /// Format of the first file, which picks the player.
+    pub format: Option<String>,
+    /// The server's `[servers] headers`, for the player.
+    pub headers: mop_core::headers::Headers,
+}
+
+impl Playlist {
+    /// The playable children of `folder` on `server`; sub-folders and items
+    /// without a stream URL are skipped.
+    fn of(folder: String, items: &[DirectoryItem], server: &PlexServer) -> Self {
+        let files: Vec<_> = items.iter().filter(|item| !item.is_directory).collect();
+        let urls = files
+            .iter()
+            .filter_map(|item| item.url.as_deref())
+            .map(|url| crate::auth::player_url(url, &server.location, server.credentials.as_ref()))
+            .collect();
+        let format = files
+            .iter()
+            .find(|item| item.url.is_some())
+            .and_then(|item| item.metadata.as_ref()?.format.clone());
+        Self { folder, urls, format, headers: server.headers.clone() }
+    }
+}
+
+/// A file about to be played, waiting on the server's answer about it.
+pub struct PendingPlay {
+    pub name: String,
+    pub player: String,
+    pub url: String,
+    /// Quit once the player is running.
+    pub quit: bool,
+    /// What the play hooks are told about the item.
+    pub hook: crate::hooks::Vars,
+    /// Reporting the playback to a Jellyfin or Emby server.
+    pub report: Option<crate::reporting::Report>,
+    pub checked: Result<mop_core::stream::StreamInfo, String>,
+}
+
+/// How long the server gets to answer for a file before it is reported as
+/// not answering.
+const STREAM_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A player exiting with an error within this long of starting couldn't
+/// play the file, rather than having been closed.
+const PLAYER_FAILURE_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A file started in the player, with what's left to try if the player
+/// gives up on it straight away.
+struct Recovery {
+    /// As listed, with all its resources.
+    item: DirectoryItem,
+    /// The variant picked first, to play through the relay.
+    first: DirectoryItem,
+    /// The variant playing now.
+    current: DirectoryItem,
+    /// `server_key` of the server it's on.
+    server: String,
+    /// URLs of the resources tried so far.
+    tried: Vec<String>,
+    /// It went through the relay.
+    relayed: bool,
+    /// The player failed on the variant picked first.
+    retried: bool,
+    /// The URL the player was given, once it's started.
+    playing: Option<String>,
+}
+
+/// How much of a file the stream test downloads.
+const STREAM_TEST_SAMPLE: std::time::Duration = std::time::Duration::from_secs(5);
+
+pub enum ProtocolInfo {
+    Loading,
+    Loaded(Vec<String>),
+    Failed(String),
+}
+
+pub enum Thumbnail {
+    Loading,
+    Loaded(image::RgbImage),
+    /// Logged; the panel goes without.
+    Failed,
+}
+
+/// Thumbnails kept before the lot is dropped and fetched again as needed.
+const THUMBNAILS_KEPT: usize = 200;
+
+pub struct PermissionDialog {
+    pub hint: String,
+    pub checking: bool,
+    /// Set when a re-check found access still blocked.
+    pub still_denied: bool,
+}
+
+pub struct GatewayDialog {
+    pub server_name: String,
+    location: String,
+    pub info: Option<crate::igd::GatewayInfo>,
+    /// First port mapping shown.
+    pub scroll: usize,
+    pub error: Option<String>,
+}
+
+/// A file's stream URL as a QR code, for a phone to scan.
+pub struct QrDialog {
+    pub item_name: String,
+    pub url: String,
+    /// Rows of the code from `qr::rows`.
+    pub rows: Vec<String>,
+}
+
+/// The copies of a file to choose between, when duplicates are listed once.
+pub struct VariantDialog {
+    pub name: String,
+    pub copies: Vec<DirectoryItem>,
+    pub selected: usize,
+    /// Quit once the player starts, as `play_file` was asked to.
+    quit: bool,
+}
+
+/// The ways Plex offers to play a file.
+pub struct PlexStreamDialog {
+    item: DirectoryItem,
+    /// None while Plex is being asked.
+    pub streams: Option<Result<crate::plex::Streams, String>>,
+    pub selected: usize,
+}
+
+impl PlexStreamDialog {
+    pub fn name(&self) -> &str {
+        &self.item.name
+    }
+}
+
+/// Network health checks and how they came out.
+pub struct DiagnosticsDialog {
+    run: u64,
+    pub results: Vec<CheckResult>,
+    pub running: bool,
+    /// How long discovery and browsing have taken this session, as of the
+    /// last run.
+    pub timings: Vec<mop_core::metrics::Summary>,
+    /// First line shown.
+    pub scroll: usize,
+    /// Where the timings were exported to, or why they couldn't be.
+    pub notice: Option<String>,
+}
+
+/// The SOAP inspector: recent requests to one server and their responses.
+pub struct SoapLogDialog {
+    pub server_name: String,
+    location: String,
+    pub exchanges: Vec<SoapExchange>,
+    /// Index into `exchanges` of the one shown in full.
+    pub selected: usize,
+    /// First line of the selected exchange shown.
+    pub scroll: usize,
+}
+
+impl SoapLogDialog {
+    /// The selected exchange as plain text, for pasting into a bug report.
+    pub fn selected_text(&self) -> Option<String> {
+        let exchange = self.exchanges.get(self.selected)?;
+        let status = exchange.status.map_or("no response".to_string(), |status| status.to_string());
+        Some(format!(
+            "POST {} ({})\n\n{}\n\n{} in {} ms\n\n{}",
+            exchange.control_url,
+            exchange.action,
+            exchange.request,
+            status,
+            exchange.duration.as_millis(),
+            exchange.response
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -67,42 +535,105 @@ impl LogPaneState {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DirectoryItem {
-    pub name: String,
-    pub is_directory: bool,
-    pub url: Option<String>,
-    pub metadata: Option<FileMetadata>,
-}
-
-#[derive(Debug, Clone)]
-pub struct FileMetadata {
-    pub size: Option<u64>,
-    pub duration: Option<String>,
-    pub format: Option<String>,
-}
-
 impl App {
     pub fn new(log_buffer: LogBuffer) -> Self {
-        let config = Config::load();
+        let (config, config_warnings) = Config::load();
+        Self::with_config(log_buffer, config, config_warnings)
+    }
+
+    /// An app running on `config` rather than the file on disk, which also
+    /// sets the process-wide proxy, hooks and rate limits from it.
+    pub fn with_config(log_buffer: LogBuffer, config: Config, config_warnings: Vec<String>) -> Self {
+        apply_process_settings(&config);
+        for warning in &config_warnings {
+            tracing::warn!(target: "mop::config", "{}", warning);
+        }
+        let (keymap, _) = Keymap::from_config(&config.keys);
+        let (hide, _) = HideList::from_config(&config.hide);
         let config_editor = ConfigEditor::new(&config);
 
-        let mut app = Self {
+        let events = Events::new();
+        let workers = Workers::new(crate::workers::WORKERS, events.sender());
+
+        Self {
             state: AppState::ServerList,
             servers: Vec::new(),
             selected_server: None,
             current_directory: Vec::new(),
+            directory_ids: Vec::new(),
             directory_contents: Vec::new(),
+            listing: Vec::new(),
+            listed: HashMap::new(),
+            hide,
+            hidden: 0,
+            show_hidden: false,
+            sort: None,
+            capabilities: HashMap::new(),
+            duplicates: Duplicates::default(),
+            filter: String::new(),
+            directory_total: None,
+            directory_complete: true,
             selected_item: None,
+            parent_selected: false,
+            directory_scroll: 0,
             last_error: None,
             discovery_errors: Vec::new(),
-            discovery_receiver: None,
             is_discovering: false,
+            is_browsing: false,
+            stale_listing: false,
+            browse_cancel: CancelToken::new(),
+            browse_generation: 0,
+            page_found: 0,
+            resolved_path: None,
+            cache: None,
+            pending_location: None,
+            cached_servers: HashSet::new(),
+            health: HashMap::new(),
+            checking_health: HashSet::new(),
+            listing_cached_at: None,
             show_help: false,
             show_config: false,
+            url_dialog: None,
+            object_id_dialog: None,
+            sleep_dialog: None,
+            palette: None,
+            filter_dialog: None,
+            letter_jump: false,
+            sleep_at: None,
+            sleep_timer: 0,
+            plex_link: None,
+            cast: None,
+            gateway: None,
+            qr: None,
+            variants: None,
+            recovery: None,
+            worked: HashMap::new(),
+            plex_streams: None,
+            soap_log: None,
+            diagnostics: None,
+            diagnostics_runs: 0,
+            permission: None,
+            rescan_pending: false,
+            network_restriction: crate::sandbox::network_restriction(),
+            protocol_info: HashMap::new(),
+            thumbnails: HashMap::new(),
+            thumbnail_rows: None,
+            downloads: crate::downloads::Downloads::new(events.sender()),
+            show_downloads: false,
+            player_support: None,
+            unplayable: HashMap::new(),
+            probing_players: false,
+            announcements: None,
+            container_watch: None,
+            container_updates: HashMap::new(),
             should_quit: false,
-            container_id_map: HashMap::new(),
             config,
+            config_warnings,
+            profile: None,
+            requested_profile: None,
+            use_daemon: true,
+            demo: None,
+            keymap,
             config_editor,
             log_buffer,
             log_pane_state: LogPaneState::Hidden,
@@ -111,80 +642,555 @@ impl App {
             log_filter_input: String::new(),
             log_filter_active: false,
             log_auto_scroll: true,
+            events,
+            workers,
+            needs_redraw: true,
+            logs_drawn: 0,
+            sleep_drawn: None,
+        }
+    }
+    
+    /// Activate a network profile by name, or auto-detect one when `None`.
+    pub fn select_profile(&mut self, requested: Option<&str>) -> Result<(), String> {
+        self.requested_profile = requested.map(str::to_string);
+        self.profile = crate::profile::resolve_profile(&self.config, requested)?;
+        match &self.profile {
+            Some(name) => tracing::info!(target: "mop::config", "Using network profile '{}'", name),
+            None => tracing::debug!(target: "mop::config", "No network profile active"),
+        }
+        Ok(())
+    }
+
+    /// Open the on-disk cache and list the servers seen on earlier runs
+    /// until discovery finds them again. MOP works without it.
+    pub fn open_cache(&mut self) {
+        let cache = match Cache::open_default() {
+            Ok(cache) => cache,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Running without a cache: {}", e);
+                return;
+            }
         };
+        match cache.devices_seen() {
+            Ok(devices) => {
+                for (mut device, seen_at) in devices {
+                    self.config.apply_server_config(self.profile.as_deref(), &mut device);
+                    if !self.servers.iter().any(|d| d.location == device.location) {
+                        let seen_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(seen_at.max(0) as u64);
+                        self.health.entry(device.location.clone()).or_default().last_seen = Some(seen_at);
+                        self.cached_servers.insert(device.location.clone());
+                        self.servers.push(device);
+                    }
+                }
+                tracing::info!(target: "mop::app", "Loaded {} servers from the cache", self.cached_servers.len());
+                self.sort_pinned();
+            }
+            Err(e) => tracing::warn!(target: "mop::app", "Failed to read cached servers: {}", e),
+        }
+        match cache.worked_variants() {
+            Ok(worked) => self.worked = worked,
+            Err(e) => tracing::warn!(target: "mop::app", "{}", e),
+        }
+        if self.config.mop.restore_last_location {
+            match cache.last_location() {
+                Ok(location) => self.pending_location = location,
+                Err(e) => tracing::warn!(target: "mop::app", "Failed to read the last location: {}", e),
+            }
+        }
+        self.cache = Some(cache);
+    }
 
-        // Initialize with root container ID
-        app.container_id_map.insert(Vec::new(), "0".to_string());
-        app
+    /// The server came from the cache and hasn't been seen on the network yet.
+    pub fn is_cached_only(&self, server: &PlexServer) -> bool {
+        self.cached_servers.contains(&server.location)
     }
-    
+
     pub fn start_discovery(&mut self) {
         // Don't start if already running
-        if self.discovery_receiver.is_some() {
-            log::debug!(target: "mop::app", "Discovery already in progress, skipping");
+        if self.is_discovering {
+            tracing::debug!(target: "mop::app", "Discovery already in progress, skipping");
             return;
         }
 
-        log::info!(target: "mop::app", "Starting device discovery");
-        // Use the new simplified discovery system
-        let receiver = crate::upnp::start_discovery();
-        self.discovery_receiver = Some(receiver);
+        tracing::info!(target: "mop::app", "Starting device discovery");
+        let settings = self.config.discovery_settings(self.profile.as_deref());
+        let receiver = match &self.demo {
+            Some(demo) => mop_core::capture::replay(demo.capture(&settings.ssdp.search_targets), settings),
+            None => crate::daemon::start_discovery(settings, self.profile.as_deref(), self.use_daemon),
+        };
+        self.events.forward(receiver, AppEvent::Discovery);
         self.is_discovering = true;
+        if self.demo.is_none() {
+            self.import_servers();
+        }
     }
-    
-    pub fn check_discovery_updates(&mut self) {
-        let mut should_clear_receiver = false;
-        
-        if let Some(ref receiver) = self.discovery_receiver {
-            while let Ok(message) = receiver.try_recv() {
-                match message {
-                    DiscoveryMessage::Started => {
-                        self.is_discovering = true;
-                        self.discovery_errors.clear();
-                    }
-                    DiscoveryMessage::DeviceFound(device) => {
-                        // Add device immediately for responsive UI with proper deduplication
-                        if !self.servers.iter().any(|d| d.location == device.location) {
-                            log::info!(target: "mop::app", "Device added to list: {}", device.name);
-                            self.servers.push(device);
-                        }
-                    }
-                    DiscoveryMessage::Phase1Complete => {
-                        // SSDP discovery phase complete
-                    }
-                    DiscoveryMessage::Phase2Complete => {
-                        // Extended discovery phase complete
-                    }
-                    DiscoveryMessage::Phase3Complete => {
-                        // Port scan phase complete
+
+    /// List the servers in `[mop] servers_file`. They are built on the
+    /// worker pool, since UPnP ones need their description, and arrive like
+    /// any device discovery finds.
+    fn import_servers(&mut self) {
+        let servers = match crate::import::load(&self.config) {
+            Ok(servers) => servers,
+            Err(e) => {
+                tracing::warn!(target: "mop::config", "{}", e);
+                self.last_error = Some(e);
+                return;
+            }
+        };
+        let settings = self.config.discovery_for(self.profile.as_deref());
+        let timeout = std::time::Duration::from_secs(settings.description_timeout_secs);
+        for server in servers {
+            let config = self.config.clone();
+            let profile = self.profile.clone();
+            self.workers.run(move || {
+                let device = crate::import::device(&server, &config, profile.as_deref(), timeout);
+                AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device)))
+            });
+        }
+    }
+
+    /// Rescan whenever the network changes (`mop.watch_network`). The demo
+    /// server is on loopback, which no network change affects.
+    pub fn watch_network(&self) {
+        if self.config.mop.watch_network && self.demo.is_none() {
+            self.events.forward(crate::netwatch::watch(crate::netwatch::POLL_INTERVAL), |_| AppEvent::NetworkChanged);
+        }
+    }
+
+    /// Hear devices announce themselves or leave after the search
+    /// (`[discovery.ssdp] listen`), joining the SSDP group afresh on each
+    /// call for the interfaces there are now.
+    pub fn listen_for_announcements(&mut self) {
+        if let Some(listening) = self.announcements.take() {
+            listening.cancel();
+        }
+        let settings = self.config.discovery_settings(self.profile.as_deref());
+        if self.demo.is_some() || !settings.ssdp.enabled || !settings.ssdp.listen {
+            return;
+        }
+        match mop_core::announcements::listen(settings) {
+            Ok((listener, announcements)) => {
+                self.events.forward(announcements, AppEvent::Discovery);
+                // What's found already needn't be described again when it announces itself
+                let found = self.servers.iter().filter(|server| !self.cached_servers.contains(&server.location));
+                for server in found {
+                    if let Some(udn) = &server.udn {
+                        listener.found(udn, &server.location);
                     }
-                    DiscoveryMessage::AllComplete(final_devices) => {
-                        // Merge final devices with existing ones, avoiding duplicates
-                        for device in final_devices {
-                            if !self.servers.iter().any(|d| d.location == device.location) {
-                                self.servers.push(device);
-                            }
-                        }
-                        self.is_discovering = false;
-                        should_clear_receiver = true;
-                        log::info!(target: "mop::app", "Discovery complete: {} devices total", self.servers.len());
-
-                        if self.servers.is_empty() {
-                            log::warn!(target: "mop::app", "No UPnP devices found");
-                            self.last_error = Some("No UPnP devices found".to_string());
-                        } else {
-                            self.last_error = None;
-                        }
+                }
+                self.announcements = Some(listener);
+            }
+            Err(e) => tracing::warn!(target: "mop::net", "Not listening for devices coming and going: {}", e),
+        }
+    }
+
+    /// Check every listed server now and then (`mop.health_check_secs`).
+    pub fn watch_health(&self) {
+        let interval = self.config.mop.health_check_secs;
+        if interval > 0 && self.demo.is_none() {
+            let ticks = crate::health::schedule(std::time::Duration::from_secs(interval));
+            self.events.forward(ticks, |_| AppEvent::CheckHealth);
+        }
+    }
+
+    /// Check the servers not still being checked from last time.
+    fn check_health(&mut self) {
+        let servers: Vec<PlexServer> = self
+            .servers
+            .iter()
+            .filter(|server| self.checking_health.insert(server.location.clone()))
+            .cloned()
+            .collect();
+        if servers.is_empty() {
+            return;
+        }
+        let checks = crate::health::check(servers);
+        self.events.forward(checks, |(location, answered)| AppEvent::ServerHealth { location, answered });
+    }
+
+    fn on_server_health(&mut self, location: String, answered: Option<std::time::Duration>) {
+        self.checking_health.remove(&location);
+        self.health.entry(location.clone()).or_default().record(answered, std::time::SystemTime::now());
+        // Keep the cache's last-seen time current for the next run
+        if answered.is_some()
+            && let Some(cache) = &self.cache
+            && let Some(server) = self.servers.iter().find(|server| server.location == location)
+            && let Err(e) = cache.store_device(server)
+        {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+    }
+
+    fn on_network_changed(&mut self) {
+        tracing::info!(target: "mop::app", "Rescanning for the new network");
+        let requested = self.requested_profile.clone();
+        if let Err(e) = self.select_profile(requested.as_deref()) {
+            tracing::warn!(target: "mop::config", "{}", e);
+            self.last_error = Some(e);
+        }
+        // Everything listed was found on the old network. It stays, shown
+        // like servers from the cache, until this network's discovery finds
+        // it again.
+        self.cached_servers.extend(self.servers.iter().map(|server| server.location.clone()));
+        // So was everything a daemon would answer with
+        self.use_daemon = false;
+        if self.is_discovering {
+            self.rescan_pending = true;
+        } else {
+            self.start_discovery();
+        }
+        self.listen_for_announcements();
+    }
+
+    /// Apply one event from the input thread or a background worker. Keys
+    /// are dispatched in main.rs and never reach this.
+    pub fn update(&mut self, event: AppEvent) {
+        if let AppEvent::Tick = event {
+            // A running sleep timer counts down in the title, a minute at a time
+            self.needs_redraw |= self.sleep_minutes_left() != self.sleep_drawn
+                || self.log_pane_state != LogPaneState::Hidden && crate::logger::entries_logged() != self.logs_drawn;
+            return;
+        }
+        // Progress of a download only shows on the downloads screen
+        let hidden = !self.show_downloads
+            && matches!(&event, AppEvent::Download { message: crate::downloads::Message::Progress(_), .. });
+        self.needs_redraw |= !hidden;
+        match event {
+            AppEvent::Key(_) | AppEvent::Resize | AppEvent::Tick => {}
+            AppEvent::Discovery(message) => self.on_discovery(message),
+            AppEvent::Browse(browse) => self.on_browse(browse),
+            AppEvent::PlexLink(event) => self.on_plex_link(event),
+            AppEvent::PlexStreams { url, result } => self.on_plex_streams(url, result),
+            AppEvent::CastTargets(result) => self.on_cast_targets(result),
+            AppEvent::CastFolder(result) => self.on_cast_folder(result),
+            AppEvent::CastQueue(message) => self.on_cast_queue(message),
+            AppEvent::CastControl(control) => self.on_cast_control(control),
+            AppEvent::Playback { started, queued, notice } => self.on_playback(started, queued, notice),
+            AppEvent::Gateway { location, result } => self.on_gateway_info(&location, result),
+            AppEvent::Permission(state) => self.on_permission(state),
+            AppEvent::Diagnostics { run, message } => self.on_diagnostics(run, message),
+            AppEvent::NetworkChanged => self.on_network_changed(),
+            AppEvent::CheckHealth => self.check_health(),
+            AppEvent::SleepTimer(timer) => self.on_sleep_timer(timer),
+            AppEvent::ServerHealth { location, answered } => self.on_server_health(location, answered),
+            AppEvent::PlayAll(result) => self.on_play_all(result),
+            AppEvent::Slideshow(result) => self.on_slideshow(result),
+            AppEvent::StreamChecked(pending) => self.on_stream_checked(*pending),
+            AppEvent::PlayerChecked { url, failure } => self.on_player_checked(url, failure),
+            AppEvent::StreamTest { name, bitrate, result } => self.on_stream_test(name, bitrate, result),
+            AppEvent::ContainerUpdates { location, changes } => self.on_container_updates(&location, changes),
+            AppEvent::Download { id, message } => self.on_download(id, message),
+            AppEvent::DownloadFolder(batch) => self.on_download_folder(*batch),
+            AppEvent::PathResolved { generation, path, ids } => self.on_path_resolved(generation, path, ids),
+            AppEvent::Capabilities { location, result } => self.on_capabilities(location, result),
+            AppEvent::PlayerSupport(support) => {
+                self.probing_players = false;
+                self.player_support = Some(support);
+                self.judge_listing();
+                self.filter_listing();
+            }
+            AppEvent::ProtocolInfo { location, result } => {
+                let info = match result {
+                    Ok(info) => ProtocolInfo::Loaded(info),
+                    Err(e) => ProtocolInfo::Failed(e),
+                };
+                self.protocol_info.insert(location, info);
+            }
+            AppEvent::Thumbnail { url, result } => {
+                let thumbnail = match result {
+                    Ok(image) => Thumbnail::Loaded(image),
+                    Err(e) => {
+                        let masked = crate::cache::mask_secrets(&url);
+                        tracing::info!(target: "mop::net", "No thumbnail from {}: {}", masked, e);
+                        Thumbnail::Failed
                     }
+                };
+                self.thumbnails.insert(url, thumbnail);
+            }
+        }
+    }
+
+    fn on_discovery(&mut self, message: DiscoveryMessage) {
+        match message {
+            DiscoveryMessage::Started => {
+                self.is_discovering = true;
+                self.discovery_errors.clear();
+                if let Some(restriction) = &self.network_restriction
+                    && self.demo.is_none()
+                {
+                    tracing::warn!(target: "mop::net", "{}", restriction);
+                    self.discovery_errors.push(restriction.clone());
+                }
+            }
+            DiscoveryMessage::PermissionDenied(hint) => {
+                tracing::warn!(target: "mop::app", "{}", hint);
+                self.discovery_errors.push(hint.clone());
+                if matches!(self.state, AppState::ServerList) {
+                    self.permission = Some(PermissionDialog { hint, checking: false, still_denied: false });
+                }
+            }
+            DiscoveryMessage::NetworkDown(hint) => {
+                tracing::warn!(target: "mop::app", "{}", hint);
+                self.discovery_errors.push(hint);
+            }
+            DiscoveryMessage::DeviceFound(device) => {
+                // Add device immediately for responsive UI
+                self.add_server(*device);
+                self.reopen_last_location();
+            }
+            DiscoveryMessage::DeviceAnnounced(device) => {
+                self.add_server(*device);
+                self.reopen_last_location();
+            }
+            DiscoveryMessage::DeviceLeft { udn, location } => self.remove_server(&udn, location.as_deref()),
+            DiscoveryMessage::Phase1Complete => {
+                // SSDP discovery phase complete
+            }
+            DiscoveryMessage::Phase2Complete => {
+                // Extended discovery phase complete
+            }
+            DiscoveryMessage::Phase3Complete => {
+                // Port scan phase complete
+            }
+            DiscoveryMessage::AllComplete(final_devices) => {
+                // Merge final devices with existing ones, avoiding duplicates
+                for device in final_devices {
+                    self.add_server(device);
                 }
+                self.reopen_last_location();
+                self.is_discovering = false;
+                tracing::info!(target: "mop::app", "Discovery complete: {} devices total", self.servers.len());
+                if std::mem::take(&mut self.rescan_pending) {
+                    self.start_discovery();
+                    return;
+                }
+                if self.pending_location.take().is_some() {
+                    tracing::info!(target: "mop::app", "The server open at the last quit wasn't found");
+                }
+
+                if self.servers.len() == self.cached_servers.len() {
+                    tracing::warn!(target: "mop::app", "No UPnP devices found");
+                    self.last_error = Some(match mop_core::permissions::empty_network_hint() {
+                        Some(hint) => format!("No UPnP devices found. {}", hint),
+                        None => "No UPnP devices found".to_string(),
+                    });
+                } else {
+                    self.last_error = None;
+                    self.open_lone_server();
+                }
+            }
+        }
+    }
+
+    /// List a discovered server, taking the place of its cached entry (the
+    /// same UDN may come back at a new address) and skipping duplicates.
+    fn add_server(&mut self, mut device: PlexServer) {
+        if let Some(listener) = &self.announcements
+            && let Some(udn) = &device.udn
+        {
+            listener.found(udn, &device.location);
+        }
+        self.config.apply_server_config(self.profile.as_deref(), &mut device);
+        self.health.entry(device.location.clone()).or_default().discovered(std::time::SystemTime::now());
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.store_device(&device)
+        {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+        let cached = self.servers.iter().position(|d| {
+            self.cached_servers.contains(&d.location)
+                && (d.location == device.location || (d.udn.is_some() && d.udn == device.udn))
+        });
+        if let Some(idx) = cached {
+            self.cached_servers.remove(&self.servers[idx].location);
+            tracing::info!(target: "mop::app", "Cached device is back: {}", device.name);
+            crate::hooks::run(Hook::DeviceFound, &crate::hooks::Vars::device(&device));
+            self.servers[idx] = device;
+        } else if !self.servers.iter().any(|d| d.location == device.location) {
+            tracing::info!(target: "mop::app", "Device added to list: {}", device.name);
+            crate::hooks::run(Hook::DeviceFound, &crate::hooks::Vars::device(&device));
+            self.servers.push(device);
+        }
+        self.sort_pinned();
+    }
+
+    /// Take a server that said it's leaving off the list. The one being
+    /// browsed stays, shown like a server from the cache, until it's back.
+    fn remove_server(&mut self, udn: &str, location: Option<&str>) {
+        let Some(idx) = self
+            .servers
+            .iter()
+            .position(|d| d.udn.as_deref() == Some(udn) || location.is_some_and(|location| d.location == location))
+        else {
+            return;
+        };
+        let name = self.servers[idx].name.clone();
+        if !matches!(self.state, AppState::ServerList) && self.selected_server == Some(idx) {
+            tracing::info!(target: "mop::app", "{} is going away while open", name);
+            self.cached_servers.insert(self.servers[idx].location.clone());
+            self.last_error = Some(format!("{} said it's going away", name));
+            return;
+        }
+        tracing::info!(target: "mop::app", "Device removed from list: {}", name);
+        let removed = self.servers.remove(idx);
+        self.cached_servers.remove(&removed.location);
+        self.selected_server = match self.selected_server {
+            _ if self.servers.is_empty() => None,
+            Some(selected) if selected > idx => Some(selected - 1),
+            Some(selected) => Some(selected.min(self.servers.len() - 1)),
+            None => None,
+        };
+    }
+
+    pub fn is_pinned(&self, server: &PlexServer) -> bool {
+        pin_id(server).is_some_and(|id| self.config.mop.pinned_servers.iter().any(|pinned| pinned == id))
+    }
+
+    /// Move pinned servers to the top, in the order of `mop.pinned_servers`,
+    /// keeping the selection on the same server.
+    fn sort_pinned(&mut self) {
+        let selected = self.selected_server.and_then(|idx| self.servers.get(idx)).map(|s| s.location.clone());
+        let pinned = &self.config.mop.pinned_servers;
+        self.servers.sort_by_key(|server| {
+            pin_id(server).and_then(|id| pinned.iter().position(|p| p == id)).unwrap_or(usize::MAX)
+        });
+        if let Some(location) = selected {
+            self.selected_server = self.servers.iter().position(|server| server.location == location);
+        }
+    }
+
+    /// Pin the highlighted server to the top of the list, or unpin it, and
+    /// save the config.
+    pub fn toggle_pin(&mut self) {
+        if !matches!(self.state, AppState::ServerList) {
+            return;
+        }
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)) else { return };
+        let Some(id) = pin_id(server).map(str::to_string) else {
+            self.last_error = Some(format!("{} has no UPnP id to pin it by", server.name));
+            return;
+        };
+        let name = server.name.clone();
+        let pinned = &mut self.config.mop.pinned_servers;
+        let now_pinned = match pinned.iter().position(|p| *p == id) {
+            Some(idx) => {
+                pinned.remove(idx);
+                false
+            }
+            None => {
+                pinned.push(id);
+                true
             }
+        };
+        self.sort_pinned();
+        match Config::save_setting(&["mop", "pinned_servers"], &self.config.mop.pinned_servers) {
+            Ok(()) => {
+                tracing::info!(target: "mop::config", "{} {}", if now_pinned { "Pinned" } else { "Unpinned" }, name)
+            }
+            Err(e) => self.last_error = Some(format!("Saving the pinned servers failed: {}", e)),
+        }
+    }
+
+    /// Switch between the music view of listings and the plain one until
+    /// MOP quits; `[mop] music_view` is the one it starts with.
+    pub fn toggle_music_view(&mut self) {
+        let music_view = !self.config.mop.music_view;
+        self.config.mop.music_view = music_view;
+        self.last_error = Some(format!("Music view {}", if music_view { "on" } else { "off" }));
+    }
+
+    /// With `mop.auto_open_server`, go straight into the only media server
+    /// discovery found, unless the user has already picked something or has
+    /// a dialog open.
+    fn open_lone_server(&mut self) {
+        if !self.config.mop.auto_open_server
+            || !matches!(self.state, AppState::ServerList)
+            || self.selected_server.is_some()
+            || self.dialog_open()
+        {
+            return;
         }
-        
-        if should_clear_receiver {
-            self.discovery_receiver = None;
+        let mut media_servers = self.servers.iter().enumerate().filter(|(_, server)| {
+            !self.is_cached_only(server)
+                && (server.content_directory_url.is_some() || mop_core::jellyfin::handles(server))
+        });
+        let (Some((idx, server)), None) = (media_servers.next(), media_servers.next()) else { return };
+        tracing::info!(target: "mop::app", "Opening the only media server: {}", server.name);
+        self.selected_server = Some(idx);
+        self.select();
+    }
+
+    /// Go back to the server and folder open at the last quit, once
+    /// discovery has found that server, unless the user has moved first.
+    fn reopen_last_location(&mut self) {
+        let Some(location) = self.pending_location.take() else { return };
+        if !matches!(self.state, AppState::ServerList) || self.selected_server.is_some() {
+            return;
+        }
+        let found = self
+            .servers
+            .iter()
+            .position(|server| !self.is_cached_only(server) && crate::cache::server_key(server) == location.server);
+        let Some(idx) = found.filter(|_| !self.dialog_open()) else {
+            self.pending_location = Some(location);
+            return;
+        };
+        tracing::info!(
+            target: "mop::app",
+            "Reopening {} at /{}",
+            self.servers[idx].name,
+            location.path.join("/")
+        );
+        self.selected_server = Some(idx);
+        self.state = AppState::DirectoryBrowser;
+        self.current_directory = location.path;
+        self.directory_ids = location.object_ids;
+        self.clear_listing();
+        self.directory_total = None;
+        self.selected_item = None;
+        self.load_directory();
+    }
+
+    /// Keep the server and folder on screen for `reopen_last_location` on
+    /// the next start, or forget them when no server is open.
+    pub fn remember_location(&self) {
+        let Some(cache) = &self.cache else { return };
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx));
+        let location = match (&self.state, server) {
+            (AppState::DirectoryBrowser, Some(server)) => Some(LastLocation {
+                server: crate::cache::server_key(server),
+                path: self.current_directory.clone(),
+                object_ids: self.directory_ids.clone(),
+            }),
+            _ => None,
+        };
+        if let Err(e) = cache.store_last_location(location.as_ref()) {
+            tracing::warn!(target: "mop::app", "{}", e);
         }
     }
 
+    /// A dialog or the help screen has the keyboard.
+    fn dialog_open(&self) -> bool {
+        self.show_help
+            || self.show_config
+            || self.url_dialog.is_some()
+            || self.object_id_dialog.is_some()
+            || self.sleep_dialog.is_some()
+            || self.filter_dialog.is_some()
+            || self.palette.is_some()
+            || self.letter_jump
+            || self.plex_link.is_some()
+            || self.permission.is_some()
+            || self.gateway.is_some()
+            || self.qr.is_some()
+            || self.diagnostics.is_some()
+            || self.soap_log.is_some()
+            || self.cast.is_some()
+            || self.variants.is_some()
+            || self.plex_streams.is_some()
+            || self.show_downloads
+    }
+
     pub fn previous(&mut self) {
         match self.state {
             AppState::ServerList => {
@@ -197,172 +1203,2360 @@ impl App {
                 }
             },
             AppState::DirectoryBrowser => {
-                if !self.directory_contents.is_empty() {
-                    self.selected_item = match self.selected_item {
-                        Some(i) if i > 0 => Some(i - 1),
-                        Some(_) => Some(self.directory_contents.len() - 1),
+                // ".." sits above the first entry, and wraps round to the last
+                let last = self.directory_contents.len().checked_sub(1);
+                match (self.parent_selected, self.selected_item) {
+                    (true, _) => {
+                        if let Some(last) = last {
+                            self.parent_selected = false;
+                            self.selected_item = Some(last);
+                        }
+                    }
+                    (false, Some(i)) if i > 0 => self.selected_item = Some(i - 1),
+                    (false, _) => self.parent_selected = true,
+                }
+            },
+        }
+    }
+
+    pub fn next(&mut self) {
+        match self.state {
+            AppState::ServerList => {
+                if !self.servers.is_empty() {
+                    self.selected_server = match self.selected_server {
+                        Some(i) if i < self.servers.len() - 1 => Some(i + 1),
+                        Some(_) => Some(0),
                         None => Some(0),
                     };
                 }
             },
+            AppState::DirectoryBrowser => {
+                if self.parent_selected {
+                    if !self.directory_contents.is_empty() {
+                        self.parent_selected = false;
+                        self.selected_item = Some(0);
+                    }
+                } else if !self.directory_contents.is_empty() {
+                    match self.selected_item {
+                        Some(i) if i < self.directory_contents.len() - 1 => self.selected_item = Some(i + 1),
+                        // Wait at the bottom for the next page rather than wrapping
+                        Some(_) if self.has_more_pages() => {}
+                        Some(_) => self.parent_selected = true,
+                        None => self.selected_item = Some(0),
+                    }
+                    self.load_next_page_if_near_end();
+                } else {
+                    self.parent_selected = true;
+                }
+            },
+        }
+    }
+
+    pub fn select(&mut self) {
+        match self.state {
+            AppState::ServerList => {
+                if let Some(server_idx) = self.selected_server
+                    && server_idx < self.servers.len()
+                {
+                    // Routers have nothing to browse
+                    if crate::igd::is_gateway(&self.servers[server_idx]) {
+                        self.open_gateway_info(server_idx);
+                        return;
+                    }
+                    self.state = AppState::DirectoryBrowser;
+                    self.current_directory.clear();
+                    self.directory_ids.clear();
+                    self.clear_listing();
+                    self.directory_total = None;
+                    self.selected_item = None;
+                    self.load_directory();
+                }
+            },
+            AppState::DirectoryBrowser => {
+                if self.parent_selected {
+                    self.go_back();
+                    return;
+                }
+                // The rows are the last folder's, about to be replaced
+                if self.is_browsing && self.stale_listing {
+                    self.last_error = Some("Still opening the folder; press Enter once it's listed".to_string());
+                    return;
+                }
+                if let Some(item_idx) = self.selected_item
+                    && item_idx < self.directory_contents.len()
+                {
+                    let item = &self.directory_contents[item_idx];
+                    if item.is_directory {
+                        let Some(id) = item.id.clone() else {
+                            self.last_error = Some(format!("The server gave no id for {}", item.name));
+                            return;
+                        };
+                        self.current_directory.push(item.name.clone());
+                        self.directory_ids.push(id);
+                        self.load_directory();
+                    } else {
+                        // For files, try to play with mpv
+                        if let Err(e) = self.play_selected_file() {
+                            self.last_error = Some(format!("Failed to play file: {}", e));
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    pub fn go_back(&mut self) {
+        if let AppState::DirectoryBrowser = self.state {
+            if self.current_directory.is_empty() {
+                self.state = AppState::ServerList;
+                self.cancel_browse();
+                self.container_watch = None;
+                self.container_updates.clear();
+            } else {
+                self.current_directory.pop();
+                self.directory_ids.pop();
+                self.load_directory();
+            }
+        }
+    }
+
+    /// Back to the server's root container from however deep the listing is.
+    pub fn go_to_root(&mut self) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        if self.current_directory.is_empty() {
+            return;
+        }
+        self.current_directory.clear();
+        self.directory_ids.clear();
+        self.load_directory();
+    }
+
+    /// The highlighted entry of the listing; none while ".." is.
+    pub fn selected_entry(&self) -> Option<&DirectoryItem> {
+        if self.parent_selected {
+            return None;
+        }
+        self.selected_item.and_then(|idx| self.directory_contents.get(idx))
+    }
+
+    /// The highlighted row of the listing as drawn, with ".." as row 0.
+    pub fn selected_row(&self) -> Option<usize> {
+        if self.parent_selected { Some(0) } else { self.selected_item.map(|idx| idx + 1) }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+    }
+
+    /// Browse the first page of `current_directory` in the background; the
+    /// listing arrives as an `AppEvent::Browse`.
+    fn load_directory(&mut self) {
+        // A filter is for the listing it was typed in
+        self.filter.clear();
+        self.parent_selected = false;
+        self.load_page(0);
+        // Unless its cached listing went up in their place
+        self.stale_listing = self.is_browsing && self.listing_cached_at.is_none() && !self.listing.is_empty();
+    }
+
+    fn load_page(&mut self, start: usize) {
+        if let Some(server_idx) = self.selected_server
+            && server_idx < self.servers.len()
+        {
+            let server = self.servers[server_idx].clone();
+            let object_id = self.object_id().to_string();
+            if start == 0 {
+                self.probe_players();
+                self.ask_capabilities(&server);
+                self.show_cached_listing(&server);
+                self.watch_containers(&server);
+                self.container_seen(&server, &object_id);
+            }
+            self.cancel_browse();
+            let cancel = self.browse_cancel.clone();
+            self.is_browsing = true;
+            self.browse_generation += 1;
+            self.page_found = 0;
+            let generation = self.browse_generation;
+            let events = self.events.sender();
+            let sort = self.sort_criteria();
+            self.workers.run(move || {
+                // Children go out as they're parsed, the end of the page as the job's own event
+                let mut complete = None;
+                crate::upnp::stream_sorted_container_page(&server, &object_id, start, &sort, &cancel, |message| {
+                    match message {
+                        BrowseMessage::ItemFound(_) => {
+                            let _ = events.send(AppEvent::Browse(Browse { generation, start, message }));
+                        }
+                        BrowseMessage::PageComplete { .. } => complete = Some(message),
+                    }
+                });
+                let message = complete.unwrap_or(BrowseMessage::PageComplete { found: 0, total: None, error: None });
+                AppEvent::Browse(Browse { generation, start, message })
+            });
+        }
+    }
+
+    /// Ask `server` what it searches and sorts by, once a run.
+    fn ask_capabilities(&mut self, server: &PlexServer) {
+        if self.capabilities.contains_key(&server.location) {
+            return;
+        }
+        self.capabilities.insert(server.location.clone(), None);
+        let server = server.clone();
+        self.workers.run(move || AppEvent::Capabilities {
+            result: crate::upnp::capabilities(&server),
+            location: server.location,
+        });
+    }
+
+    fn on_capabilities(&mut self, location: String, result: Result<crate::upnp::Capabilities, String>) {
+        let capabilities = result.unwrap_or_else(|e| {
+            tracing::warn!(target: "mop::net", "No search or sort capabilities from {}: {}", location, e);
+            crate::upnp::Capabilities::default()
+        });
+        let open = self.selected_server.and_then(|idx| self.servers.get(idx)).is_some_and(|s| s.location == location);
+        let before = self.sort_criteria();
+        self.capabilities.insert(location, Some(capabilities));
+        // A sort asked for before the server answered is now the server's to do
+        if open && matches!(self.state, AppState::DirectoryBrowser) && self.sort_criteria() != before {
+            self.load_page(0);
+        }
+    }
+
+    fn server_capabilities(&self) -> Option<&crate::upnp::Capabilities> {
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx))?;
+        self.capabilities.get(&server.location)?.as_ref()
+    }
+
+    /// SortCriteria for browsing: the sort asked for, if the server can do it.
+    fn sort_criteria(&self) -> String {
+        match self.sort {
+            Some(sort) if sort.by_server(self.server_capabilities()) => sort.criteria(),
+            _ => String::new(),
+        }
+    }
+
+    /// The order the listing is in, when the server or MOP can put it in the
+    /// one asked for.
+    pub fn sorted_by(&self) -> Option<Sort> {
+        self.sort.filter(|sort| sort.local() || sort.by_server(self.server_capabilities()))
+    }
+
+    /// Sort by the next thing the server or MOP can sort by, and after the
+    /// last go back to the server's own order. The server sorts the whole
+    /// container when it says it can; otherwise what's loaded is sorted here.
+    pub fn cycle_sort(&mut self) {
+        if !matches!(self.state, AppState::DirectoryBrowser) {
+            return;
+        }
+        let before = self.sort_criteria();
+        self.sort = Sort::next(self.sort, self.server_capabilities());
+        let criteria = self.sort_criteria();
+        if criteria != before {
+            self.load_page(0);
+        } else {
+            self.filter_listing();
+        }
+        self.last_error = Some(match self.sort {
+            None => "In the server's order".to_string(),
+            Some(sort) if !criteria.is_empty() => format!("Sorted by {}", sort.name()),
+            Some(sort) => format!("Sorted by {}; the server can't, so only what's loaded is", sort.name()),
+        });
+    }
+
+    /// Ask the players what they play, when `[players] unplayable` needs to
+    /// know and they haven't been asked since they were set.
+    fn probe_players(&mut self) {
+        if self.config.players.unplayable == "show" || self.player_support.is_some() || self.probing_players {
+            return;
+        }
+        self.probing_players = true;
+        let config = self.config.clone();
+        self.workers.run(move || AppEvent::PlayerSupport(crate::playable::probe(&config)));
+    }
+
+    /// Why the player for `item`, an entry of the listing, can't play it,
+    /// when `[players] unplayable` is on and the player said what it plays.
+    pub fn refuses(&self, item: &DirectoryItem) -> Option<&str> {
+        self.unplayable.get(item.url.as_deref()?).map(String::as_str)
+    }
+
+    /// Work out `refuses` again for the whole listing, after the players or
+    /// what they were asked changed.
+    fn judge_listing(&mut self) {
+        self.unplayable.clear();
+        for index in 0..self.listing.len() {
+            self.judge(index);
+        }
+    }
+
+    /// Work out `refuses` for the entry at `index` of the listing.
+    fn judge(&mut self, index: usize) {
+        let item = &self.listing[index];
+        if let Some(url) = &item.url
+            && let Some(reason) = self.unplayable_reason(item)
+        {
+            self.unplayable.insert(url.clone(), reason);
+        }
+    }
+
+    /// Why the player for `item` can't play it, asked afresh.
+    fn unplayable_reason(&self, item: &DirectoryItem) -> Option<String> {
+        if item.is_directory || self.config.players.unplayable == "show" {
+            return None;
+        }
+        let item = crate::quality::choose(&self.config.quality, item, &[]);
+        let mime = item.metadata.as_ref().and_then(|metadata| metadata.format.as_deref());
+        let player = self.config.players.for_mime(mime, &self.config.mop.run);
+        self.player_support.as_ref()?.get(player)?.refuses(&item)
+    }
+
+    /// Subscribe to `server`'s changes, unless that's already being done.
+    /// Jellyfin and Emby don't event; they're browsed through their API.
+    fn watch_containers(&mut self, server: &PlexServer) {
+        if self.container_watch.as_ref().is_some_and(|watch| watch.location == server.location) {
+            return;
+        }
+        self.container_watch = None;
+        self.container_updates.clear();
+        if server.content_directory_url.is_none() || mop_core::jellyfin::handles(server) {
+            return;
+        }
+        let seen = match self.cache.as_ref().map(|cache| cache.containers_seen(server)) {
+            Some(Ok(seen)) => seen,
+            Some(Err(e)) => {
+                tracing::warn!(target: "mop::app", "{}", e);
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        };
+        self.container_updates =
+            seen.into_iter().map(|(id, seen)| (id, ContainerUpdate { count: seen, seen: Some(seen) })).collect();
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.container_watch = Some(crate::gena::watch(server.clone(), tx));
+        let location = server.location.clone();
+        self.events.forward(rx, move |changes| AppEvent::ContainerUpdates { location: location.clone(), changes });
+    }
+
+    /// Note the counts the server sent, against those the containers had
+    /// when last opened.
+    fn on_container_updates(&mut self, location: &str, changes: crate::gena::Changes) {
+        if self.container_watch.as_ref().is_none_or(|watch| watch.location != location) {
+            return;
+        }
+        let Some(server) = self.servers.iter().find(|server| server.location == location).cloned() else { return };
+        let open = self.object_id().to_string();
+        for (id, count) in changes.containers {
+            let known = self.container_updates.contains_key(&id);
+            let update = self.container_updates.entry(id.clone()).or_insert(ContainerUpdate { count, seen: None });
+            update.count = count;
+            if id == open || !known && changes.initial {
+                // Open, or first heard of in how things stand: nothing new to show
+                self.container_seen(&server, &id);
+            } else if self.container_changed_id(&id) {
+                tracing::info!(target: "mop::net", "Container {} changed on the server", id);
+            }
+        }
+    }
+
+    /// Take container `id`'s count as seen, here and in the cache.
+    fn container_seen(&mut self, server: &PlexServer, id: &str) {
+        let Some(update) = self.container_updates.get_mut(id) else { return };
+        if update.seen == Some(update.count) {
+            return;
+        }
+        update.seen = Some(update.count);
+        if let Some(Err(e)) = self.cache.as_ref().map(|cache| cache.store_container_seen(server, id, update.count)) {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+    }
+
+    fn container_changed_id(&self, id: &str) -> bool {
+        self.container_updates.get(id).is_some_and(|update| update.seen != Some(update.count))
+    }
+
+    /// Whether `item` is a container that changed since it was last opened.
+    pub fn container_changed(&self, item: &DirectoryItem) -> bool {
+        item.is_directory && item.id.as_deref().is_some_and(|id| self.container_changed_id(id))
+    }
+
+    /// ObjectID of the container being browsed.
+    fn object_id(&self) -> &str {
+        self.directory_ids.last().map_or("0", String::as_str)
+    }
+
+    /// Put the cached listing of `current_directory` on screen while the
+    /// fresh one loads, or clear the stale marker if there is none.
+    fn show_cached_listing(&mut self, server: &PlexServer) {
+        self.listing_cached_at = None;
+        let Some(cache) = &self.cache else { return };
+        let listing = match cache.listing(server, self.object_id()) {
+            Ok(Some(listing)) => listing,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Failed to read cached listing: {}", e);
+                return;
+            }
+        };
+        self.show_listing(listing.items, listing.total, listing.fetched_at);
+    }
+
+    /// Show a complete listing kept from `fetched_at` (Unix time).
+    pub fn show_listing(&mut self, items: Vec<DirectoryItem>, total: Option<usize>, fetched_at: i64) {
+        self.listing = items;
+        self.judge_listing();
+        self.listed.clear();
+        for (index, item) in self.listing.iter().enumerate() {
+            if let Some(id) = &item.id {
+                self.listed.entry(id.clone()).or_insert(index);
+            }
+        }
+        self.duplicates = Duplicates::of(&self.listing);
+        self.count_hidden();
+        self.directory_contents = self.shown_entries();
+        self.directory_total = total;
+        self.directory_complete = true;
+        self.selected_item = if self.directory_contents.is_empty() { None } else { Some(0) };
+        self.directory_scroll = 0;
+        self.listing_cached_at = Some(fetched_at);
+    }
+
+    /// Forget the listing on screen, for another container's.
+    fn clear_listing(&mut self) {
+        self.directory_contents.clear();
+        self.listing.clear();
+        self.stale_listing = false;
+        self.unplayable.clear();
+        self.listed.clear();
+        self.duplicates.clear();
+        self.hidden = 0;
+    }
+
+    /// Whether the entry at `index` of the listing goes on screen.
+    fn shown(&self, index: usize, item: &DirectoryItem) -> bool {
+        (self.show_hidden || !self.hide.hides(item))
+            && !self.collapsed(index, item)
+            && (self.filter.is_empty() || item.name.to_lowercase().contains(&self.filter))
+            && (self.config.players.unplayable != "hide" || self.refuses(item).is_none())
+    }
+
+    /// The entry at `index` is a copy left out by `collapse_duplicates`.
+    fn collapsed(&self, index: usize, item: &DirectoryItem) -> bool {
+        self.config.mop.collapse_duplicates && self.duplicates.is_repeat(index, item)
+    }
+
+    fn shown_entries(&self) -> Vec<DirectoryItem> {
+        let shown = self.listing.iter().enumerate().filter(|&(index, item)| self.shown(index, item));
+        let mut entries: Vec<_> = shown.map(|(_, item)| item.clone()).collect();
+        if let Some(sort) = self.sort
+            && self.sort_criteria().is_empty()
+        {
+            sort.apply(&mut entries);
+        }
+        entries
+    }
+
+    /// Entries loaded so far, shown or not.
+    pub fn loaded(&self) -> usize {
+        self.listing.len()
+    }
+
+    /// Entries of the listing that `[hide]` keeps off screen.
+    pub fn hidden_count(&self) -> usize {
+        if self.show_hidden { 0 } else { self.hidden }
+    }
+
+    /// Count `hidden` again for the whole listing.
+    fn count_hidden(&mut self) {
+        let hidden = self.listing.iter().enumerate().filter(|(_, item)| self.hide.hides(item));
+        self.hidden = hidden.filter(|&(index, item)| !self.collapsed(index, item)).count();
+    }
+
+    /// What the listing is filtered by, if it is.
+    pub fn filter(&self) -> Option<&str> {
+        Some(self.filter.as_str()).filter(|filter| !filter.is_empty())
+    }
+
+    /// Ask for text to narrow the listing to, starting from the filter
+    /// already on it.
+    pub fn open_filter_dialog(&mut self) {
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx));
+        let (AppState::DirectoryBrowser, Some(server)) = (&self.state, server) else {
+            self.last_error = Some("Open a server to filter its listings".to_string());
+            return;
+        };
+        let history = match self.cache.as_ref().map(|cache| cache.searches(server)) {
+            Some(Ok(history)) => history,
+            Some(Err(e)) => {
+                tracing::warn!(target: "mop::app", "{}", e);
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        self.filter_dialog = Some(FilterDialog {
+            input: Input::default().with_value(self.filter.clone()),
+            history,
+            previous: self.filter.clone(),
+            ..FilterDialog::default()
+        });
+    }
+
+    /// Put back the filter from before the prompt opened.
+    pub fn cancel_filter(&mut self) {
+        let Some(dialog) = self.filter_dialog.take() else { return };
+        self.set_filter(&dialog.previous);
+    }
+
+    /// Keep the filter typed, and remember it for this server.
+    pub fn confirm_filter(&mut self) {
+        let Some(dialog) = self.filter_dialog.take() else { return };
+        let query = dialog.input.value().trim();
+        self.set_filter(query);
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx));
+        if let (Some(cache), Some(server), false) = (&self.cache, server, query.is_empty())
+            && let Err(e) = cache.store_search(server, query)
+        {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+    }
+
+    /// The prompt's text changed: filter by what it says now.
+    pub fn filter_edited(&mut self) {
+        let Some(dialog) = self.filter_dialog.as_mut() else { return };
+        dialog.recalled = None;
+        let query = dialog.input.value().to_string();
+        self.set_filter(&query);
+    }
+
+    pub fn filter_history_older(&mut self) {
+        let Some(dialog) = self.filter_dialog.as_mut() else { return };
+        dialog.older();
+        let query = dialog.input.value().to_string();
+        self.set_filter(&query);
+    }
+
+    pub fn filter_history_newer(&mut self) {
+        let Some(dialog) = self.filter_dialog.as_mut() else { return };
+        dialog.newer();
+        let query = dialog.input.value().to_string();
+        self.set_filter(&query);
+    }
+
+    fn set_filter(&mut self, query: &str) {
+        self.filter = query.trim().to_lowercase();
+        self.filter_listing();
+    }
+
+    /// Take letter keys as jumps through the listing until Esc.
+    pub fn open_letter_jump(&mut self) {
+        if !matches!(self.state, AppState::DirectoryBrowser) || self.directory_contents.is_empty() {
+            self.last_error = Some("Nothing here to jump through".to_string());
+            return;
+        }
+        self.letter_jump = true;
+    }
+
+    /// Which of [`JUMP_LETTERS`] the listing's entries start with.
+    pub fn initials(&self) -> HashSet<char> {
+        self.directory_contents.iter().map(|item| initial(&item.name)).collect()
+    }
+
+    /// Select the first entry starting with `letter`, or the next one after
+    /// the selection when that starts with it too, so pressing it again
+    /// steps through them. Digits and anything else not A to Z go under '#'.
+    pub fn jump_to_letter(&mut self, letter: char) {
+        let letter = if letter.is_ascii_alphabetic() { letter.to_ascii_uppercase() } else { '#' };
+        let starts = |item: &DirectoryItem| initial(&item.name) == letter;
+        let from = match self.selected_entry() {
+            Some(item) if starts(item) => self.selected_item.map_or(0, |idx| idx + 1),
+            _ => 0,
+        };
+        let next = self.directory_contents.iter().skip(from).position(starts).map(|pos| from + pos);
+        match next.or_else(|| self.directory_contents.iter().position(starts)) {
+            Some(idx) => {
+                self.parent_selected = false;
+                self.selected_item = Some(idx);
+                self.load_next_page_if_near_end();
+            }
+            None => self.last_error = Some(format!("Nothing here starts with {}", letter)),
+        }
+    }
+
+    /// How many times `item` is in the listing; 1 for a file listed once.
+    pub fn copies(&self, item: &DirectoryItem) -> usize {
+        self.duplicates.copies(item).len().max(1)
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.filter_listing();
+        self.last_error = Some(match (self.show_hidden, self.hidden) {
+            (_, 0) => "Nothing here is hidden".to_string(),
+            (true, n) => format!("Showing {} hidden", n),
+            (false, n) => format!("Hiding {}", n),
+        });
+    }
+
+    /// Refill `directory_contents` from `listing`, keeping the selection on
+    /// the same entry, or on the next one shown if it's now hidden.
+    fn filter_listing(&mut self) {
+        let selected = self.selected_item.and_then(|idx| self.directory_contents.get(idx)).cloned();
+        let same = |item: &DirectoryItem| selected.as_ref().is_some_and(|s| item.id == s.id && item.name == s.name);
+        let pos = (selected.as_ref().and_then(|s| s.id.as_ref()))
+            .and_then(|id| self.listed.get(id).copied())
+            .filter(|&index| same(&self.listing[index]))
+            .or_else(|| self.listing.iter().position(same));
+        self.count_hidden();
+        self.directory_contents = self.shown_entries();
+        self.selected_item = match pos {
+            _ if self.directory_contents.is_empty() => None,
+            // Still shown, and perhaps sorted elsewhere
+            Some(_) if self.directory_contents.iter().any(same) => self.directory_contents.iter().position(same),
+            Some(pos) => {
+                let before = (0..pos).filter(|&index| self.shown(index, &self.listing[index])).count();
+                Some(before.min(self.directory_contents.len() - 1))
+            }
+            None => Some(0),
+        };
+    }
+
+    /// Keep what's loaded of `current_directory` for next time.
+    fn store_listing(&self) {
+        let (Some(cache), Some(server)) = (&self.cache, self.selected_server.and_then(|idx| self.servers.get(idx)))
+        else {
+            return;
+        };
+        if let Err(e) = cache.store_listing(server, self.object_id(), &self.listing, self.directory_total) {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+    }
+
+    /// Abandon the browse in flight, if any; whatever it returns late is
+    /// dropped by `on_browse`.
+    fn cancel_browse(&mut self) {
+        if self.is_browsing {
+            self.browse_cancel.cancel();
+            self.browse_cancel = CancelToken::new();
+            self.is_browsing = false;
+        }
+    }
+
+    /// Which `load_page` call a `Browse` event must belong to.
+    pub fn browse_generation(&self) -> u64 {
+        self.browse_generation
+    }
+
+    pub fn has_more_pages(&self) -> bool {
+        !self.directory_complete
+    }
+
+    /// Fetch the next page once the selection gets within a screenful or so
+    /// of the last loaded row.
+    fn load_next_page_if_near_end(&mut self) {
+        const PREFETCH_ROWS: usize = 50;
+        let selected = self.selected_item.unwrap_or_default();
+        if !self.is_browsing && self.has_more_pages() && selected + PREFETCH_ROWS >= self.directory_contents.len() {
+            self.load_page(self.listing.len());
+        }
+    }
+
+    fn on_browse(&mut self, browse: Browse) {
+        if browse.generation != self.browse_generation {
+            tracing::debug!(target: "mop::app", "Dropping stale listing from {}", browse.start);
+            return;
+        }
+        match browse.message {
+            BrowseMessage::ItemFound(item) => {
+                if browse.start == 0 && self.page_found == 0 {
+                    // The fresh listing replaces whatever was on screen
+                    self.listing_cached_at = None;
+                    self.clear_listing();
+                    self.selected_item = None;
+                    self.directory_scroll = 0;
+                }
+                self.page_found += 1;
+                let index = self.listing.len();
+                if let Some(id) = &item.id {
+                    self.listed.entry(id.clone()).or_insert(index);
+                }
+                self.duplicates.add(index, &item);
+                self.listing.push(*item);
+                self.judge(index);
+                let item = &self.listing[index];
+                if self.hide.hides(item) && !self.collapsed(index, item) {
+                    self.hidden += 1;
+                }
+                if self.shown(index, item) {
+                    self.directory_contents.push(item.clone());
+                    self.selected_item.get_or_insert(0);
+                }
+            }
+            BrowseMessage::PageComplete { found, total, error } => self.on_page_complete(browse.start, found, total, error),
+        }
+    }
+
+    fn on_page_complete(
+        &mut self,
+        start: usize,
+        found: usize,
+        total: Option<usize>,
+        error: Option<crate::upnp::BrowseError>,
+    ) {
+        self.is_browsing = false;
+        let error = error.map(|error| (error.to_string(), error)).filter(|(text, _)| !text.trim().is_empty());
+        if let Some((_, error)) = &error
+            && start == 0
+            && self.resolve_path(error)
+        {
+            return;
+        }
+        let error = error.map(|(text, _)| text);
+        // Out of reach: the cached listing is better than nothing
+        if let (Some(error), Some(fetched_at)) = (&error, self.listing_cached_at) {
+            let when = chrono::DateTime::from_timestamp(fetched_at, 0)
+                .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            self.last_error = Some(format!("{} (showing the listing cached {})", error, when));
+            return;
+        }
+        if start == 0 && found == 0 {
+            self.listing_cached_at = None;
+            self.clear_listing();
+            self.selected_item = None;
+            self.directory_scroll = 0;
+        }
+        self.directory_complete = error.is_some() || crate::upnp::is_last_page(start, found, total);
+        self.directory_total = total;
+        if self.sort.is_some() && self.sort_criteria().is_empty() {
+            self.filter_listing();
+        }
+        self.last_error = error;
+        if self.last_error.is_none() {
+            self.resolved_path = None;
+            self.store_listing();
+        }
+        self.load_next_page_if_near_end();
+    }
+
+    /// Servers renumber their containers when they rescan their library,
+    /// and the ObjectIDs kept for the open folder then get `error` 701. Find
+    /// the folder again by its titles from the root in the background.
+    /// False when that's not the trouble or was already tried.
+    fn resolve_path(&mut self, error: &crate::upnp::BrowseError) -> bool {
+        if !error.is_no_such_object() || self.current_directory.is_empty() {
+            return false;
+        }
+        if self.resolved_path.take().as_ref() == Some(&self.current_directory) {
+            return false;
+        }
+        // A folder jumped to by ObjectID has no titles to go by
+        let mut levels = self.current_directory.iter().zip(&self.directory_ids);
+        if levels.any(|(name, id)| *name == format!("#{}", id)) {
+            return false;
+        }
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned() else { return false };
+        let path = self.current_directory.clone();
+        tracing::info!(target: "mop::app", "{} no longer knows /{} by its ObjectIDs", server.name, path.join("/"));
+        self.last_error = Some(format!("{} renumbered its folders, finding /{} again…", server.name, path.join("/")));
+        self.resolved_path = Some(path.clone());
+        self.is_browsing = true;
+        let generation = self.browse_generation;
+        let cancel = self.browse_cancel.clone();
+        self.workers.run(move || {
+            let ids = crate::upnp::resolve_path(&server, &path, &cancel);
+            AppEvent::PathResolved { generation, path, ids }
+        });
+        true
+    }
+
+    fn on_path_resolved(&mut self, generation: u64, path: Vec<String>, ids: Result<Vec<String>, String>) {
+        if generation != self.browse_generation || path != self.current_directory {
+            return;
+        }
+        self.is_browsing = false;
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned() else { return };
+        let ids = match ids {
+            Ok(ids) => ids,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Couldn't find /{} again: {}", path.join("/"), e);
+                self.resolved_path = None;
+                self.directory_complete = true;
+                self.last_error = Some(format!("{} no longer has /{}: {}", server.name, path.join("/"), e));
+                return;
+            }
+        };
+        tracing::info!(target: "mop::app", "Found /{} again at ObjectID {}", path.join("/"), ids.join(" > "));
+        // Every ObjectID kept for the server is suspect now
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.renumbered(&server, &path, &ids)
+        {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+        self.directory_ids = ids;
+        self.remember_location();
+        self.last_error = None;
+        self.load_page(0);
+    }
+
+    /// Play the highlighted file, then quit or stay as `quit` says instead
+    /// of as `mop.auto_close` does.
+    pub fn play_selected_file_then(&mut self, quit: bool) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        if let Err(e) = self.play_file(quit) {
+            self.last_error = Some(format!("Failed to play file: {}", e));
+        }
+    }
+
+    pub fn play_selected_file(&mut self) -> Result<(), String> {
+        self.play_file(self.config.mop.auto_close)
+    }
+
+    fn play_file(&mut self, quit: bool) -> Result<(), String> {
+        let Some(item) = self.selected_entry() else {
+            return Err("No file selected".to_string());
+        };
+        if self.config.mop.collapse_duplicates && !item.is_directory {
+            let copies = self.duplicates.copies(item);
+            if copies.len() > 1 {
+                let copies = copies.iter().map(|&index| self.listing[index].clone()).collect();
+                self.variants = Some(VariantDialog { name: item.name.clone(), copies, selected: 0, quit });
+                return Ok(());
+            }
+        }
+        let item = item.clone();
+        self.play_item(&item, quit)
+    }
+
+    fn play_item(&mut self, item: &DirectoryItem, quit: bool) -> Result<(), String> {
+        if item.is_directory {
+            return Err("Cannot play a directory".to_string());
+        }
+        if let Some(reason) = self.unplayable_reason(item) {
+            return Err(reason);
+        }
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx));
+        let key = server.map(crate::cache::server_key);
+        let worked = key.as_ref().and_then(|key| self.worked.get(key)).into_iter().flatten().find_map(|worked| {
+            crate::quality::remembered(&self.config.quality, item, worked).map(|resource| (resource, worked.relayed))
+        });
+        let chosen = match worked {
+            Some((resource, _)) => crate::quality::with_resource(item, resource),
+            None => crate::quality::choose(&self.config.quality, item, &[]),
+        };
+        // Once MOP quits there's nothing to try again with
+        self.recovery = key.filter(|_| !quit).map(|server| Recovery {
+            item: item.clone(),
+            first: chosen.clone(),
+            current: chosen.clone(),
+            server,
+            tried: chosen.url.iter().cloned().collect(),
+            relayed: false,
+            retried: false,
+            playing: None,
+        });
+        self.launch_item(&chosen, quit, worked.is_some_and(|(_, relayed)| relayed))
+    }
+
+    /// Play `item` as it is, through the relay if `relay`.
+    fn launch_item(&mut self, item: &DirectoryItem, quit: bool, relay: bool) -> Result<(), String> {
+        let Some(url) = &item.url else {
+            tracing::warn!(target: "mop::app", "No URL available for file: {}", item.name);
+            return Err("No URL available for this file".to_string());
+        };
+        tracing::info!(target: "mop::app", "Playing file: {}", item.name);
+        let mime = item.metadata.as_ref().and_then(|m| m.format.as_deref());
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned();
+        let headers = server.as_ref().map(|server| server.headers.clone()).unwrap_or_default();
+        let player = player_command(self.config.players.for_mime(mime, &self.config.mop.run), &headers);
+        // Credentials go in the player's URL; the check sends them as headers
+        let checked_url = url.clone();
+        let url = match &server {
+            Some(server) => crate::auth::player_url(url, &server.location, server.credentials.as_ref()),
+            None => url.clone(),
+        };
+        // Seeking through the relay only works while MOP runs
+        let relayed = match server.as_ref().filter(|_| !quit) {
+            Some(server) => match crate::relay::for_item(server, item)? {
+                None if relay => Some(crate::relay::through(server, item)?),
+                relayed => relayed,
+            },
+            None => None,
+        };
+        if let Some(recovery) = self.recovery.as_mut() {
+            recovery.relayed |= relayed.is_some();
+        }
+        let url = relayed.unwrap_or(url);
+        let hook = crate::hooks::Vars::item(item).server(server.as_ref());
+        let report = crate::reporting::Report::of(&self.config, server.as_ref(), item);
+        let Some(server) = server.filter(|_| self.config.mop.check_streams) else {
+            let exited = launch_player(&player, &[url.as_str()], &hook, report)?;
+            self.watch_player(url, exited);
+            self.last_error = None;
+            if quit {
+                tracing::info!(target: "mop::app", "Player started, quitting");
+                self.should_quit = true;
+            }
+            return Ok(());
+        };
+
+        // The player gets the URL once the server has said it will serve it
+        let name = item.name.clone();
+        self.last_error = Some(format!("Checking {}…", name));
+        self.workers.run(move || {
+            let checked = mop_core::stream::check(&server, &checked_url, STREAM_CHECK_TIMEOUT);
+            AppEvent::StreamChecked(Box::new(PendingPlay { name, player, url, quit, hook, report, checked }))
+        });
+        Ok(())
+    }
+
+    pub fn variant_previous(&mut self) {
+        if let Some(dialog) = self.variants.as_mut() {
+            dialog.selected = dialog.selected.saturating_sub(1);
+        }
+    }
+
+    pub fn variant_next(&mut self) {
+        if let Some(dialog) = self.variants.as_mut()
+            && dialog.selected + 1 < dialog.copies.len()
+        {
+            dialog.selected += 1;
+        }
+    }
+
+    /// Play the copy highlighted in the picker.
+    pub fn play_variant(&mut self) {
+        let Some(dialog) = self.variants.take() else { return };
+        if let Err(e) = self.play_item(&dialog.copies[dialog.selected], dialog.quit) {
+            self.last_error = Some(format!("Failed to play file: {}", e));
+        }
+    }
+
+    pub fn close_variants(&mut self) {
+        self.variants = None;
+    }
+
+    /// Ask the Plex server being browsed how it can play the highlighted
+    /// video, and offer direct play and transcodes to choose from.
+    pub fn open_plex_streams(&mut self) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned() else { return };
+        let Some(item) = self.selected_entry().filter(|item| !item.is_directory).cloned() else { return };
+        if !crate::plex::is_plex(&server) {
+            self.last_error = Some(format!("{} is not a Plex server", server.name));
+            return;
+        }
+        tracing::info!(target: "mop::app", "Asking Plex how to play {}", item.name);
+        self.plex_streams = Some(PlexStreamDialog { item: item.clone(), streams: None, selected: 0 });
+        self.workers.run(move || {
+            let result = crate::plex::streams(&server, &item);
+            AppEvent::PlexStreams { url: item.url.unwrap_or_default(), result }
+        });
+    }
+
+    fn on_plex_streams(&mut self, url: String, result: Result<crate::plex::Streams, String>) {
+        let Some(dialog) = self.plex_streams.as_mut() else { return };
+        if dialog.item.url.as_deref().unwrap_or_default() != url {
+            return;
+        }
+        if let Err(e) = &result {
+            tracing::warn!(target: "mop::net", "Plex can't say how to play {}: {}", dialog.item.name, e);
+        }
+        dialog.streams = Some(result);
+    }
+
+    pub fn plex_stream_previous(&mut self) {
+        if let Some(dialog) = self.plex_streams.as_mut() {
+            dialog.selected = dialog.selected.saturating_sub(1);
+        }
+    }
+
+    pub fn plex_stream_next(&mut self) {
+        if let Some(dialog) = self.plex_streams.as_mut()
+            && let Some(Ok(streams)) = &dialog.streams
+            && dialog.selected + 1 < streams.streams.len()
+        {
+            dialog.selected += 1;
+        }
+    }
+
+    /// Play the stream highlighted in the picker, quitting after if `quit`.
+    pub fn play_plex_stream(&mut self, quit: bool) {
+        let Some(dialog) = self.plex_streams.as_ref() else { return };
+        let Some(Ok(streams)) = &dialog.streams else { return };
+        let stream = &streams.streams[dialog.selected];
+        let item = &dialog.item;
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx));
+        let headers = server.map(|server| server.headers.clone()).unwrap_or_default();
+        let mime = item.metadata.as_ref().and_then(|m| m.format.as_deref());
+        let player = player_command(self.config.players.for_mime(mime, &self.config.mop.run), &headers);
+        let hook = crate::hooks::Vars::item(item).server(server);
+        tracing::info!(target: "mop::app", "Playing {} from Plex: {}", item.name, stream.label);
+        if let Err(e) = launch_player(&player, &[stream.url.as_str()], &hook, None) {
+            self.last_error = Some(format!("Failed to play file: {}", e));
+            return;
+        }
+        self.last_error = Some(format!("Playing {} ({})", item.name, stream.label));
+        self.plex_streams = None;
+        if quit {
+            tracing::info!(target: "mop::app", "Player started, quitting");
+            self.should_quit = true;
+        }
+    }
+
+    pub fn close_plex_streams(&mut self) {
+        self.plex_streams = None;
+    }
+
+    fn on_stream_checked(&mut self, pending: PendingPlay) {
+        let info = match pending.checked {
+            Ok(info) => info,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Not playing {}: {}", pending.name, e);
+                self.last_error = Some(format!("Failed to play file: {}", e));
+                return;
+            }
+        };
+        match launch_player(&pending.player, &[pending.url.as_str()], &pending.hook, pending.report) {
+            Ok(exited) => self.watch_player(pending.url.clone(), exited),
+            Err(e) => {
+                self.last_error = Some(format!("Failed to play file: {}", e));
+                return;
+            }
+        }
+        let details: Vec<String> =
+            info.content_type.into_iter().chain(info.length.map(crate::download::format_bytes)).collect();
+        tracing::info!(target: "mop::app", "Playing {} ({})", pending.name, details.join(", "));
+        self.last_error = Some(if details.is_empty() {
+            format!("Playing {}", pending.name)
+        } else {
+            format!("Playing {} ({})", pending.name, details.join(", "))
+        });
+        if pending.quit {
+            tracing::info!(target: "mop::app", "Player started, quitting");
+            self.should_quit = true;
+        }
+    }
+
+    /// Hear whether the player given `url` is still running a moment after
+    /// starting, when there's a file to try again with if it isn't.
+    fn watch_player(&mut self, url: String, exited: std::sync::mpsc::Receiver<Option<i32>>) {
+        let Some(recovery) = self.recovery.as_mut() else { return };
+        recovery.playing = Some(url.clone());
+        let events = self.events.sender();
+        std::thread::spawn(move || {
+            // A player that can't be waited on counts as running
+            let failure = match exited.recv_timeout(PLAYER_FAILURE_WINDOW) {
+                Ok(Some(0)) | Err(_) => None,
+                Ok(Some(code)) => Some(format!("exited with {}", code)),
+                Ok(None) => Some("was killed".to_string()),
+            };
+            let _ = events.send(AppEvent::PlayerChecked { url, failure });
+        });
+    }
+
+    /// Remember what played if it took more than one go, or go on to the
+    /// file's next variant, then to playing it through the relay.
+    fn on_player_checked(&mut self, url: String, failure: Option<String>) {
+        let Some(mut recovery) = self.recovery.take_if(|recovery| recovery.playing.as_ref() == Some(&url)) else {
+            return;
+        };
+        let name = recovery.item.name.clone();
+        let Some(failure) = failure else {
+            if let Some(variant) = crate::quality::variant(&recovery.current).filter(|_| recovery.retried)
+                && let Some(failed) = crate::quality::variant(&recovery.first)
+            {
+                let worked = crate::quality::Worked { failed, variant, relayed: recovery.relayed };
+                tracing::info!(target: "mop::app", "{} played as {:?}, trying that instead from now on", name, worked);
+                if let Some(cache) = &self.cache
+                    && let Err(e) = cache.store_worked_variant(&recovery.server, &worked)
+                {
+                    tracing::warn!(target: "mop::app", "{}", e);
+                }
+                let known = self.worked.entry(recovery.server).or_default();
+                known.retain(|known| known.failed != worked.failed);
+                known.push(worked);
+            }
+            return;
+        };
+        tracing::warn!(target: "mop::app", "The player {} on {}", failure, name);
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx)).map(crate::cache::server_key);
+        let (next, relay, how) = match crate::quality::untried(&recovery.item, &recovery.tried) {
+            Some(resource) if server.as_ref() == Some(&recovery.server) => {
+                recovery.tried.push(resource.url.clone());
+                let how = format!("the {} variant", crate::quality::describe(resource, None));
+                (crate::quality::with_resource(&recovery.item, resource), false, how)
+            }
+            None if !recovery.relayed && server.as_ref() == Some(&recovery.server) => {
+                (recovery.first.clone(), true, "it through MOP's relay".to_string())
+            }
+            _ => {
+                self.last_error = Some(format!("The player {} on {}, with nothing else to try", failure, name));
+                return;
+            }
+        };
+        tracing::info!(target: "mop::app", "Trying {} for {}", how, name);
+        recovery.current = next.clone();
+        recovery.retried = true;
+        recovery.playing = None;
+        self.recovery = Some(recovery);
+        match self.launch_item(&next, false, relay) {
+            Ok(()) => self.last_error = Some(format!("The player {} on {}, trying {}", failure, name, how)),
+            Err(e) => {
+                self.recovery = None;
+                self.last_error = Some(format!("Failed to play file: {}", e));
+            }
+        }
+    }
+
+    /// Download a few seconds of the highlighted file and say whether the
+    /// connection keeps up with its bitrate.
+    pub fn test_stream(&mut self) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned() else { return };
+        let Some(item) = self.selected_entry() else { return };
+        if item.is_directory {
+            self.last_error = Some("The stream test works on a file".to_string());
+            return;
+        }
+        let Some(url) = item.url.clone() else {
+            self.last_error = Some("No URL available for this file".to_string());
+            return;
+        };
+
+        let (name, bitrate) = (item.name.clone(), bitrate(item));
+        tracing::info!(target: "mop::app", "Testing the stream of {}", name);
+        self.last_error = Some(format!("Testing the stream of {} for {}s…", name, STREAM_TEST_SAMPLE.as_secs()));
+        self.workers.run(move || {
+            let result = mop_core::stream::measure(&server, &url, STREAM_TEST_SAMPLE);
+            AppEvent::StreamTest { name, bitrate, result }
+        });
+    }
+
+    fn on_stream_test(&mut self, name: String, bitrate: Option<u64>, result: Result<Throughput, String>) {
+        let throughput = match result {
+            Ok(throughput) => throughput,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Stream test of {} failed: {}", name, e);
+                self.last_error = Some(format!("Stream test of {} failed: {}", name, e));
+                return;
+            }
+        };
+        let rate = throughput.bits_per_sec();
+        let message = match bitrate {
+            Some(bitrate) => {
+                let verdict = match Verdict::of(rate, bitrate) {
+                    Verdict::Smooth => "should stream smoothly",
+                    Verdict::Marginal => "may stall where its bitrate peaks",
+                    Verdict::TooSlow => "unlikely to stream smoothly over this connection",
+                };
+                format!("{}: {} for a file averaging {}, {}", name, format_rate(rate), format_rate(bitrate), verdict)
+            }
+            None => format!("{}: {}; the server gave no size or length to compare with", name, format_rate(rate)),
+        };
+        tracing::info!(target: "mop::app", "{} ({} bytes in {:?})", message, throughput.bytes, throughput.elapsed);
+        self.last_error = Some(message);
+    }
+
+    /// Queue every file in the highlighted folder in the player, in order.
+    /// The whole folder is fetched first, however many pages it takes.
+    pub fn play_all_selected(&mut self) {
+        self.read_folder(false);
+    }
+
+    /// Show the highlighted folder's images one by one in the image viewer.
+    pub fn slideshow_selected(&mut self) {
+        self.read_folder(true);
+    }
+
+    /// Read all of the highlighted folder on a worker: its files for play
+    /// all, or only the images for a slideshow.
+    fn read_folder(&mut self, slideshow: bool) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned() else { return };
+        let Some(item) = self.selected_entry() else { return };
+        if !item.is_directory {
+            let action = if slideshow { "A slideshow" } else { "Play all" };
+            self.last_error = Some(format!("{} works on a folder", action));
+            return;
+        }
+        let Some(object_id) = item.id.clone() else {
+            self.last_error = Some(format!("{} can't be browsed", item.name));
+            return;
+        };
+
+        let folder = item.name.clone();
+        // What's hidden from the listing stays out of the playlist too
+        let hide = if self.show_hidden { HideList::default() } else { self.hide.clone() };
+        let quality = self.config.quality.clone();
+        tracing::info!(target: "mop::app", "Queueing everything in {}", folder);
+        self.last_error = Some(format!("Loading {}…", folder));
+        self.workers.run(move || {
+            let (mut items, error) = crate::upnp::browse_container(&server, &object_id, &CancelToken::new());
+            items.retain(|item| !hide.hides(item));
+            if slideshow {
+                items.retain(is_image);
+            }
+            let items: Vec<DirectoryItem> =
+                items.iter().map(|item| crate::quality::choose(&quality, item, &[])).collect();
+            let playlist = match error {
+                Some(e) => Err(format!("Couldn't read all of {}: {}", folder, e)),
+                None => Ok(Playlist::of(folder, &items, &server)),
+            };
+            if slideshow { AppEvent::Slideshow(playlist) } else { AppEvent::PlayAll(playlist) }
+        });
+    }
+
+    /// Queue the highlighted file for download, or every file below the
+    /// highlighted folder once they've been read.
+    pub fn download_selected(&mut self) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)).cloned() else { return };
+        let Some(item) = self.selected_entry() else { return };
+        if !item.is_directory {
+            let item = crate::quality::choose(&self.config.quality, item, &[]);
+            let dest = self.config.downloads.resolved_dir().join(crate::download::file_name(&item));
+            self.last_error = Some(if self.downloads.add(item.clone(), server, dest) {
+                format!("Downloading {} (v for downloads)", item.name)
+            } else {
+                format!("{} is already downloading", item.name)
+            });
+            return;
+        }
+        let Some(object_id) = item.id.clone() else {
+            self.last_error = Some(format!("{} can't be browsed", item.name));
+            return;
+        };
+        let folder = item.name.clone();
+        let hide = if self.show_hidden { HideList::default() } else { self.hide.clone() };
+        let quality = self.config.quality.clone();
+        self.last_error = Some(format!("Reading {} to download it…", folder));
+        self.workers.run(move || {
+            let dir = std::path::Path::new(&crate::download::sanitize(&folder)).to_path_buf();
+            let files = crate::downloads::collect(&server, &object_id, &dir, &hide, 0).map(|files| {
+                let chosen = files.into_iter().map(|(path, item)| (path, crate::quality::choose(&quality, &item, &[])));
+                chosen.collect()
+            });
+            AppEvent::DownloadFolder(Box::new(crate::downloads::Batch { folder, server, files }))
+        });
+    }
+
+    fn on_download_folder(&mut self, batch: crate::downloads::Batch) {
+        let files = match batch.files {
+            Ok(files) => files,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Couldn't read {} to download it: {}", batch.folder, e);
+                self.last_error = Some(format!("Couldn't read {}: {}", batch.folder, e));
+                return;
+            }
+        };
+        let dir = self.config.downloads.resolved_dir();
+        let count = files.len();
+        let queued = files
+            .into_iter()
+            .filter(|(path, item)| self.downloads.add(item.clone(), batch.server.clone(), dir.join(path)))
+            .count();
+        tracing::info!(target: "mop::app", "Queued {} of {} files in {} to download", queued, count, batch.folder);
+        self.last_error = Some(match queued {
+            0 if count == 0 => format!("Nothing to download in {}", batch.folder),
+            0 => format!("Everything in {} is already downloading", batch.folder),
+            n => format!("Downloading {} files from {} (v for downloads)", n, batch.folder),
+        });
+    }
+
+    fn on_download(&mut self, id: u64, message: crate::downloads::Message) {
+        use crate::downloads::{Message, Status};
+
+        let downloaded = match &message {
+            Message::Finished(Ok(crate::download::Outcome::Downloaded(bytes))) => Some(*bytes),
+            _ => None,
+        };
+        let Some(transfer) = self.downloads.update(id, message) else { return };
+        match &transfer.status {
+            Status::Done => {
+                tracing::info!(target: "mop::app", "Downloaded {} to {}", transfer.item.name, transfer.dest.display());
+                self.last_error = Some(format!("Downloaded {}", transfer.item.name));
+            }
+            Status::Failed(e) => {
+                tracing::warn!(target: "mop::app", "Downloading {} failed: {}", transfer.item.name, e);
+                self.last_error = Some(format!("Downloading {} failed: {}", transfer.item.name, e));
+            }
+            _ => {}
+        }
+        if let Some(bytes) = downloaded {
+            let hook = crate::hooks::Vars::item(&transfer.item)
+                .server(Some(&transfer.server))
+                .with("MOP_FILE", transfer.dest.display())
+                .with("MOP_BYTES", bytes);
+            crate::hooks::run(crate::hooks::Hook::DownloadComplete, &hook);
+        }
+    }
+
+    pub fn open_downloads(&mut self) {
+        self.show_downloads = true;
+    }
+
+    /// Raise or lower the rate limit all downloads share.
+    pub fn step_download_limit(&mut self, faster: bool) {
+        let limit = crate::downloads::step_rate_limit(faster);
+        self.last_error = Some(match limit {
+            0 => "Downloads are not limited".to_string(),
+            kb => format!("Downloads limited to {}/s", crate::download::format_bytes(kb * 1024)),
+        });
+    }
+
+    /// The playlist read by `read_folder`, or None once the status line
+    /// says why there is nothing to start.
+    fn loaded_playlist(&mut self, result: Result<Playlist, String>, nothing: &str) -> Option<Playlist> {
+        match result {
+            Ok(playlist) if playlist.urls.is_empty() => {
+                self.last_error = Some(format!("{} in {}", nothing, playlist.folder));
+                None
+            }
+            Ok(playlist) => Some(playlist),
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "{}", e);
+                self.last_error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn on_play_all(&mut self, result: Result<Playlist, String>) {
+        let Some(playlist) = self.loaded_playlist(result, "Nothing to play") else { return };
+
+        let player = self.config.players.for_mime(playlist.format.as_deref(), &self.config.mop.run);
+        let player = player_command(player, &playlist.headers);
+        let urls: Vec<&str> = playlist.urls.iter().map(String::as_str).collect();
+        if let Err(e) = launch_player(&player, &urls, &self.playlist_hook(&playlist), None) {
+            self.last_error = Some(e);
+            return;
+        }
+        tracing::info!(target: "mop::app", "Queued {} files from {}", urls.len(), playlist.folder);
+        self.last_error = Some(format!("Playing {} files from {}", urls.len(), playlist.folder));
+        if self.config.mop.auto_close {
+            tracing::info!(target: "mop::app", "Auto-close enabled, quitting");
+            self.should_quit = true;
+        }
+    }
+
+    fn on_slideshow(&mut self, result: Result<Playlist, String>) {
+        let Some(mut playlist) = self.loaded_playlist(result, "No images") else { return };
+        let settings = &self.config.slideshow;
+        if settings.shuffle {
+            shuffle(&mut playlist.urls);
+        }
+        let viewer = self.config.players.for_mime(Some("image/*"), &self.config.mop.run);
+        let command = slideshow_command(&player_command(viewer, &playlist.headers), settings.interval_secs);
+        let urls: Vec<&str> = playlist.urls.iter().map(String::as_str).collect();
+        if let Err(e) = launch_player(&command, &urls, &self.playlist_hook(&playlist), None) {
+            self.last_error = Some(e);
+            return;
+        }
+        tracing::info!(target: "mop::app", "Slideshow of {} images from {}", urls.len(), playlist.folder);
+        self.last_error = Some(format!("Showing {} images from {}", urls.len(), playlist.folder));
+        if self.config.mop.auto_close {
+            tracing::info!(target: "mop::app", "Auto-close enabled, quitting");
+            self.should_quit = true;
+        }
+    }
+
+    /// What the play hooks are told about a folder played: its title and
+    /// first file.
+    fn playlist_hook(&self, playlist: &Playlist) -> crate::hooks::Vars {
+        let server = self.selected_server.and_then(|idx| self.servers.get(idx));
+        let first = playlist.urls.first().map_or("", String::as_str);
+        crate::hooks::Vars::default().with("MOP_TITLE", &playlist.folder).with("MOP_URL", first).server(server)
+    }
+
+    pub fn open_url_dialog(&mut self) {
+        self.url_dialog = Some(InputDialog::default());
+    }
+
+    pub fn close_url_dialog(&mut self) {
+        self.url_dialog = None;
+    }
+
+    /// Play whatever URL was typed or pasted into the dialog. Errors stay in
+    /// the dialog so the URL can be fixed.
+    pub fn play_url_from_dialog(&mut self) {
+        let Some(dialog) = self.url_dialog.as_mut() else { return };
+        let url = match parse_stream_url(dialog.input.value()) {
+            Ok(url) => url,
+            Err(e) => {
+                dialog.error = Some(e);
+                return;
+            }
+        };
+
+        tracing::info!(target: "mop::app", "Playing URL: {}", url);
+        let player = player_for_url(&self.config, &url);
+        if let Err(e) = launch_player(player, &[url.as_str()], &crate::hooks::Vars::url(&url), None) {
+            dialog.error = Some(e);
+            return;
+        }
+        self.url_dialog = None;
+        if self.config.mop.auto_close {
+            tracing::info!(target: "mop::app", "Auto-close enabled, quitting");
+            self.should_quit = true;
+        }
+    }
+
+    /// Ask for a ContentDirectory ObjectID to open, in the server being
+    /// browsed or the highlighted one.
+    pub fn open_object_id_dialog(&mut self) {
+        match self.selected_server.and_then(|idx| self.servers.get(idx)) {
+            Some(server) if crate::igd::is_gateway(server) => {
+                self.last_error = Some(format!("{} has no ContentDirectory", server.name));
+            }
+            Some(_) => self.object_id_dialog = Some(InputDialog::default()),
+            None => self.last_error = Some("Highlight a server first".to_string()),
+        }
+    }
+
+    pub fn close_object_id_dialog(&mut self) {
+        self.object_id_dialog = None;
+    }
+
+    /// Browse the container whose ObjectID was typed into the dialog. Its
+    /// title isn't known, so the path shows the id; going back returns to
+    /// where the jump was made from.
+    pub fn jump_to_object_id(&mut self) {
+        let Some(dialog) = self.object_id_dialog.as_mut() else { return };
+        let object_id = dialog.input.value().trim().to_string();
+        if object_id.is_empty() {
+            dialog.error = Some("Enter an ObjectID, such as 0 for the root".to_string());
+            return;
+        }
+        self.object_id_dialog = None;
+
+        tracing::info!(target: "mop::app", "Jumping to ObjectID {}", object_id);
+        if let AppState::ServerList = self.state {
+            self.state = AppState::DirectoryBrowser;
+            self.current_directory.clear();
+            self.directory_ids.clear();
+        }
+        self.clear_listing();
+        self.directory_total = None;
+        self.selected_item = None;
+        self.current_directory.push(format!("#{}", object_id));
+        self.directory_ids.push(object_id);
+        self.load_directory();
+    }
+
+    pub fn open_palette(&mut self) {
+        self.palette = Some(Palette::default());
+    }
+
+    pub fn close_palette(&mut self) {
+        self.palette = None;
+    }
+
+    /// Move the palette's highlight, wrapping at either end.
+    pub fn palette_step(&mut self, delta: isize) {
+        let Some(palette) = self.palette.as_mut() else { return };
+        let count = palette.commands().len();
+        if count > 0 {
+            palette.selected = (palette.selected as isize + delta).rem_euclid(count as isize) as usize;
+        }
+    }
+
+    /// Close the palette, returning the highlighted action to run.
+    pub fn take_palette_command(&mut self) -> Option<Action> {
+        let palette = self.palette.take()?;
+        palette.commands().get(palette.selected).copied()
+    }
+
+    pub fn open_sleep_dialog(&mut self) {
+        self.sleep_dialog = Some(InputDialog::default());
+    }
+
+    pub fn close_sleep_dialog(&mut self) {
+        self.sleep_dialog = None;
+    }
+
+    pub fn set_sleep_timer_from_dialog(&mut self) {
+        let Some(dialog) = self.sleep_dialog.as_ref() else { return };
+        let text = dialog.input.value().trim();
+        let minutes = if text.is_empty() { Ok(0) } else { text.parse::<u64>() };
+        let set = match minutes {
+            Ok(minutes) => self.set_sleep_timer(minutes),
+            Err(_) => Err("Enter a number of minutes, or 0 to turn the timer off".to_string()),
+        };
+        match set {
+            Ok(()) => self.sleep_dialog = None,
+            Err(e) => {
+                if let Some(dialog) = self.sleep_dialog.as_mut() {
+                    dialog.error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Stop playback in `minutes`, replacing any timer already running;
+    /// 0 just turns it off.
+    pub fn set_sleep_timer(&mut self, minutes: u64) -> Result<(), String> {
+        if minutes == 0 {
+            self.sleep_timer += 1;
+            self.sleep_at = None;
+            self.last_error = Some("Sleep timer off".to_string());
+            return Ok(());
+        }
+        let after = minutes.checked_mul(60).map(std::time::Duration::from_secs);
+        let at = after.and_then(|after| std::time::Instant::now().checked_add(after));
+        let (Some(after), Some(at)) = (after, at) else {
+            return Err(format!("{} minutes is longer than a timer can run", minutes));
+        };
+        self.sleep_timer += 1;
+        self.sleep_at = Some(at);
+        let timer = self.sleep_timer;
+        let events = self.events.sender();
+        std::thread::spawn(move || {
+            std::thread::sleep(after);
+            let _ = events.send(AppEvent::SleepTimer(timer));
+        });
+        tracing::info!(target: "mop::app", "Sleep timer set for {} minutes", minutes);
+        let quitting = if self.config.mop.sleep_quits { " and quitting" } else { "" };
+        self.last_error = Some(format!("Stopping playback{} in {} min", quitting, minutes));
+        Ok(())
+    }
+
+    /// Whole minutes until the sleep timer goes off, as the title shows them.
+    pub fn sleep_minutes_left(&self) -> Option<u64> {
+        let at = self.sleep_at?;
+        Some(at.saturating_duration_since(std::time::Instant::now()).as_secs().div_ceil(60))
+    }
+
+    /// Stop whatever is playing: every mpv started, over their IPC sockets,
+    /// and the cast in progress, with AVTransport Stop or its equivalent.
+    fn on_sleep_timer(&mut self, timer: u64) {
+        if timer != self.sleep_timer || self.sleep_at.take().is_none() {
+            return;
+        }
+        let mut stopped = Vec::new();
+        if crate::mpv::quit_all() > 0 {
+            stopped.push("mpv".to_string());
+        }
+        let casting = self.cast.as_ref().and_then(|dialog| dialog.session.as_ref());
+        if let Some(target) = casting.map(|session| session.target_name().to_string()) {
+            self.close_cast_dialog();
+            stopped.push(target);
+        }
+        tracing::info!(target: "mop::app", "Sleep timer went off, stopped {:?}", stopped);
+        self.last_error = Some(if stopped.is_empty() {
+            "Sleep timer: nothing was playing".to_string()
+        } else {
+            format!("Sleep timer: stopped {}", stopped.join(" and "))
+        });
+        if self.config.mop.sleep_quits {
+            tracing::info!(target: "mop::app", "Sleep timer quitting");
+            self.should_quit = true;
+        }
+    }
+
+    /// Start linking the highlighted server to a Plex account.
+    pub fn start_plex_link(&mut self) {
+        let AppState::ServerList = self.state else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)) else {
+            return;
+        };
+        if !crate::plex::is_plex(server) {
+            self.last_error = Some(format!("{} is not a Plex server", server.name));
+            return;
+        }
+
+        self.plex_link = Some(PlexLinkDialog {
+            server_name: server.name.clone(),
+            device: server.clone(),
+            code: None,
+            error: None,
+            _link: crate::plex::start_link({
+                let tx = self.events.sender();
+                move |event| {
+                    tx.send(AppEvent::PlexLink(event)).ok();
+                }
+            }),
+        });
+    }
+
+    pub fn cancel_plex_link(&mut self) {
+        self.plex_link = None;
+    }
+
+    fn on_plex_link(&mut self, event: crate::plex::LinkEvent) {
+        let Some(dialog) = self.plex_link.as_mut() else { return };
+        let token = match event {
+            crate::plex::LinkEvent::Code(code) => {
+                dialog.code = Some(code);
+                return;
+            }
+            crate::plex::LinkEvent::Failed(e) => {
+                dialog.error = Some(e);
+                return;
+            }
+            crate::plex::LinkEvent::Linked(token) => token,
+        };
+
+        // Where the server's settings are read from, so the token is used
+        let profile = self.profile.as_deref().filter(|name| self.config.profiles.contains_key(*name));
+        let section = match profile {
+            Some(profile) => vec!["profiles", profile, "servers"],
+            None => vec!["servers"],
+        };
+        let servers = profile.map_or(&self.config.servers, |name| &self.config.profiles[name].servers);
+        let existed = servers.keys().any(|pattern| crate::upnp::device_matches(&dialog.device, pattern));
+        let new_key = crate::plex::server_key(&dialog.device);
+        let (key, entry) = self.config.server_entry_mut(profile, &dialog.device, &new_key);
+        entry.token = token;
+        // Only the token in an entry that was there, all of one made for it
+        let mut path = section.clone();
+        path.push(&key);
+        let saved = if existed {
+            path.push("token");
+            Config::save_setting(&path, &entry.token)
+        } else {
+            Config::save_setting(&path, &*entry)
+        };
+        if let Err(e) = saved {
+            dialog.error = Some(format!("Linked, but saving the token failed: {}", e));
+            return;
+        }
+        tracing::info!(target: "mop::config", "Saved Plex token for [{}.\"{}\"]", section.join("."), key);
+
+        for server in &mut self.servers {
+            self.config.apply_server_config(self.profile.as_deref(), server);
+        }
+        self.plex_link = None;
+        self.last_error = None;
+    }
+
+    /// Ask the highlighted UPnP server what it can stream, once per server.
+    pub fn request_protocol_info(&mut self) {
+        let AppState::ServerList = self.state else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)) else { return };
+        if server.content_directory_url.is_none() || self.protocol_info.contains_key(&server.location) {
+            return;
+        }
+        let device = server.clone();
+        self.workers.run(move || {
+            let result = crate::upnp::protocol_info(&device);
+            AppEvent::ProtocolInfo { location: device.location, result }
+        });
+        self.protocol_info.insert(server.location.clone(), ProtocolInfo::Loading);
+    }
+
+    /// Fetch the highlighted file's thumbnail, once per URL.
+    pub fn request_thumbnail(&mut self) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        if !self.config.mop.thumbnails {
+            return;
+        }
+        let Some(item) = self.selected_entry() else { return };
+        let Some(url) = item.metadata.as_ref().and_then(|metadata| metadata.thumbnail.clone()) else { return };
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)) else { return };
+        if self.thumbnails.contains_key(&url) {
+            return;
+        }
+        if self.thumbnails.len() >= THUMBNAILS_KEPT {
+            self.thumbnails.clear();
+        }
+        let device = server.clone();
+        let fetched = url.clone();
+        self.workers.run(move || {
+            let result = crate::thumbnail::fetch(&device, &fetched);
+            AppEvent::Thumbnail { url: fetched, result }
+        });
+        self.thumbnails.insert(url, Thumbnail::Loading);
+    }
+
+    /// The highlighted file's thumbnail in `width` cells by `height` rows,
+    /// once it's loaded. Scaled again only when the file or the space
+    /// changes, not on every frame.
+    pub fn thumbnail_rows(&mut self, width: u16, height: u16) -> Vec<ratatui::text::Line<'static>> {
+        let url = self.selected_entry().and_then(|item| item.metadata.as_ref()?.thumbnail.clone());
+        let Some(Thumbnail::Loaded(image)) = url.as_ref().and_then(|url| self.thumbnails.get(url)) else {
+            return Vec::new();
+        };
+        let key = (url.unwrap_or_default(), width, height);
+        match &self.thumbnail_rows {
+            Some((drawn, rows)) if *drawn == key => rows.clone(),
+            _ => {
+                let rows = crate::thumbnail::rows(image, width, height);
+                self.thumbnail_rows = Some((key, rows.clone()));
+                rows
+            }
+        }
+    }
+
+    fn open_gateway_info(&mut self, server_idx: usize) {
+        let server = &self.servers[server_idx];
+        let location = server.location.clone();
+        let device = server.clone();
+        self.workers.run(move || AppEvent::Gateway { location: device.location.clone(), result: crate::igd::query(&device) });
+        self.gateway = Some(GatewayDialog {
+            server_name: server.name.clone(),
+            location,
+            info: None,
+            scroll: 0,
+            error: None,
+        });
+    }
+
+    fn on_gateway_info(&mut self, location: &str, result: Result<crate::igd::GatewayInfo, String>) {
+        let Some(dialog) = self.gateway.as_mut().filter(|dialog| dialog.location == location) else { return };
+        match result {
+            Ok(info) => dialog.info = Some(info),
+            Err(e) => dialog.error = Some(e),
+        }
+    }
+
+    pub fn gateway_scroll(&mut self, delta: isize) {
+        if let Some(dialog) = self.gateway.as_mut() {
+            let mappings = dialog.info.as_ref().map_or(0, |info| info.mappings.len());
+            dialog.scroll = dialog.scroll.saturating_add_signed(delta).min(mappings.saturating_sub(1));
+        }
+    }
+
+    pub fn close_gateway_info(&mut self) {
+        self.gateway = None;
+    }
+
+    /// Show the highlighted file's URL as a QR code. It is the URL the
+    /// player would get, credentials included, so the phone can play it.
+    pub fn open_qr_code(&mut self) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        let Some(item) = self.selected_entry() else { return };
+        let Some(url) = &item.url else {
+            self.last_error = Some(format!("{} has no stream URL", item.name));
+            return;
+        };
+        let url = match self.selected_server.and_then(|idx| self.servers.get(idx)) {
+            Some(server) => crate::auth::player_url(url, &server.location, server.credentials.as_ref()),
+            None => url.clone(),
+        };
+        match crate::qr::rows(&url) {
+            Ok(rows) => self.qr = Some(QrDialog { item_name: item.name.clone(), url, rows }),
+            Err(e) => self.last_error = Some(e),
+        }
+    }
+
+    pub fn close_qr_code(&mut self) {
+        self.qr = None;
+    }
+
+    pub fn open_diagnostics(&mut self) {
+        self.diagnostics = Some(DiagnosticsDialog {
+            run: 0,
+            results: Vec::new(),
+            running: false,
+            timings: Vec::new(),
+            scroll: 0,
+            notice: None,
+        });
+        self.run_diagnostics();
+    }
+
+    /// Check the network and every known server, replacing earlier results.
+    pub fn run_diagnostics(&mut self) {
+        let Some(dialog) = self.diagnostics.as_mut() else { return };
+        if dialog.running {
+            return;
+        }
+        self.diagnostics_runs += 1;
+        let run = self.diagnostics_runs;
+        *dialog = DiagnosticsDialog {
+            run,
+            results: Vec::new(),
+            running: true,
+            timings: mop_core::metrics::summarize(&mop_core::metrics::snapshot()),
+            scroll: 0,
+            notice: None,
+        };
+        // The sandbox is known up front and explains every failure after it
+        if let Some(restriction) = &self.network_restriction {
+            dialog.results.push(CheckResult {
+                name: "Sandbox".to_string(),
+                device: None,
+                outcome: Outcome::Fail,
+                detail: "Network access is not granted".to_string(),
+                suggestion: Some(restriction.clone()),
+            });
+        }
+        let receiver = mop_core::diagnostics::start(self.servers.clone());
+        self.events.forward(receiver, move |message| AppEvent::Diagnostics { run, message });
+    }
+
+    fn on_diagnostics(&mut self, run: u64, message: DiagnosticsMessage) {
+        let Some(dialog) = self.diagnostics.as_mut().filter(|dialog| dialog.run == run) else { return };
+        match message {
+            DiagnosticsMessage::Check(result) => dialog.results.push(result),
+            DiagnosticsMessage::Done => {
+                dialog.running = false;
+                dialog.timings = mop_core::metrics::summarize(&mop_core::metrics::snapshot());
+            }
+        }
+    }
+
+    pub fn diagnostics_scroll(&mut self, delta: isize) {
+        if let Some(dialog) = self.diagnostics.as_mut() {
+            let lines = dialog.results.len() + dialog.timings.len();
+            dialog.scroll = dialog.scroll.saturating_add_signed(delta).min(lines.saturating_sub(1));
+        }
+    }
+
+    /// Write every timing recorded this session to a JSON file in the cache
+    /// directory, for comparing against another network.
+    pub fn export_metrics(&mut self) {
+        let exported = mop_core::metrics::export_json().and_then(|json| {
+            let dir = dirs::cache_dir().ok_or_else(|| "Could not find cache directory".to_string())?.join("mop");
+            std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache directory: {}", e))?;
+            let path = dir.join(format!("metrics-{}.json", chrono::Local::now().format("%Y-%m-%d-%H%M%S")));
+            std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            Ok(path)
+        });
+        let notice = match exported {
+            Ok(path) => {
+                tracing::info!(target: "mop::app", "Exported timings to {}", path.display());
+                format!("Timings exported to {}", path.display())
+            }
+            Err(e) => {
+                tracing::error!(target: "mop::app", "Failed to export timings: {}", e);
+                e
+            }
+        };
+        if let Some(dialog) = self.diagnostics.as_mut() {
+            dialog.notice = Some(notice);
+        }
+    }
+
+    pub fn close_diagnostics(&mut self) {
+        self.diagnostics = None;
+    }
+
+    /// Show the SOAP requests made to the selected server, newest selected.
+    /// Open the highlighted server's web interface, for the administration
+    /// Plex, Jellyfin or a router only offer there.
+    pub fn open_in_browser(&mut self) {
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)) else {
+            self.last_error = Some("Highlight a server to open its web interface".to_string());
+            return;
+        };
+        self.last_error = Some(match web_ui_url(&server.base_url).and_then(|url| open_in_browser(&url).map(|()| url)) {
+            Ok(url) => format!("Opened {} in the browser", url),
+            Err(e) => e,
+        });
+    }
+
+    pub fn open_soap_log(&mut self) {
+        let Some(server) = self.selected_server.and_then(|idx| self.servers.get(idx)) else {
+            self.last_error = Some("Select a server to inspect its SOAP requests".to_string());
+            return;
+        };
+        self.soap_log = Some(SoapLogDialog {
+            server_name: server.name.clone(),
+            location: server.location.clone(),
+            exchanges: Vec::new(),
+            selected: 0,
+            scroll: 0,
+        });
+        self.refresh_soap_log();
+    }
+
+    /// Pick up exchanges made since the inspector opened.
+    pub fn refresh_soap_log(&mut self) {
+        let Some(dialog) = self.soap_log.as_mut() else { return };
+        dialog.exchanges = mop_core::soap_log::exchanges_with(&dialog.location);
+        dialog.selected = dialog.exchanges.len().saturating_sub(1);
+        dialog.scroll = 0;
+    }
+
+    pub fn soap_log_select(&mut self, delta: isize) {
+        if let Some(dialog) = self.soap_log.as_mut() {
+            let last = dialog.exchanges.len().saturating_sub(1);
+            dialog.selected = dialog.selected.saturating_add_signed(delta).min(last);
+            dialog.scroll = 0;
+        }
+    }
+
+    pub fn soap_log_scroll(&mut self, delta: isize) {
+        if let Some(dialog) = self.soap_log.as_mut() {
+            dialog.scroll = dialog.scroll.saturating_add_signed(delta);
+        }
+    }
+
+    pub fn close_soap_log(&mut self) {
+        self.soap_log = None;
+    }
+
+    /// Probe local network access again, for a user who has just allowed it
+    /// in System Settings.
+    pub fn recheck_permission(&mut self) {
+        let Some(dialog) = self.permission.as_mut() else { return };
+        if dialog.checking {
+            return;
+        }
+        dialog.checking = true;
+        self.workers.run(|| AppEvent::Permission(mop_core::permissions::check_local_network()));
+    }
+
+    fn on_permission(&mut self, state: mop_core::permissions::PermissionState) {
+        let Some(dialog) = self.permission.as_mut() else { return };
+        dialog.checking = false;
+        if state != mop_core::permissions::PermissionState::Granted {
+            dialog.still_denied = true;
+            return;
+        }
+
+        tracing::info!(target: "mop::app", "Local network access allowed, rescanning");
+        let hint = std::mem::take(&mut dialog.hint);
+        self.discovery_errors.retain(|error| *error != hint);
+        self.permission = None;
+        // The running discovery sent its searches while they were blocked
+        if self.is_discovering {
+            self.rescan_pending = true;
+        } else {
+            self.start_discovery();
+        }
+        self.listen_for_announcements();
+    }
+
+    pub fn open_permission_settings(&mut self) {
+        if let Err(e) = mop_core::permissions::open_local_network_settings() {
+            tracing::warn!(target: "mop::app", "{}", e);
+            self.last_error = Some(e);
+        }
+    }
+
+    pub fn close_permission_dialog(&mut self) {
+        self.permission = None;
+    }
+
+    /// Open the cast picker for the selected file and look for receivers.
+    pub fn open_cast_dialog(&mut self) {
+        self.open_cast(None);
+    }
+
+    /// Cast the selected file straight to `[cast] default_renderer`.
+    pub fn send_to_default_renderer(&mut self) {
+        let udn = self.config.cast.default_renderer.trim().to_string();
+        if udn.is_empty() {
+            self.last_error = Some(format!(
+                "No default renderer: open the cast picker ({}) and press d on one",
+                self.keymap.label(Action::Cast)
+            ));
+            return;
+        }
+        self.open_cast(Some(udn));
+    }
+
+    fn open_cast(&mut self, auto_target: Option<String>) {
+        let AppState::DirectoryBrowser = self.state else { return };
+        let Some(item) = self.selected_entry() else {
+            return;
+        };
+        if (item.is_directory && item.id.is_none()) || (!item.is_directory && item.url.is_none()) {
+            self.last_error = Some(format!("{} can't be cast", item.name));
+            return;
+        }
+        let item = item.clone();
+        if item.is_directory
+            && let (Some(server), Some(object_id)) =
+                (self.selected_server.and_then(|idx| self.servers.get(idx)).cloned(), item.id.clone())
+        {
+            // What's hidden from the listing isn't cast either
+            let hide = if self.show_hidden { HideList::default() } else { self.hide.clone() };
+            let folder = item.name.clone();
+            self.workers.run(move || {
+                let (mut items, error) = crate::upnp::browse_container(&server, &object_id, &CancelToken::new());
+                items.retain(|item| !item.is_directory && item.url.is_some() && !hide.hides(item));
+                AppEvent::CastFolder(match error {
+                    Some(e) => Err(format!("Couldn't read all of {}: {}", folder, e)),
+                    None if items.is_empty() => Err(format!("{} has no files to cast", folder)),
+                    None => Ok(items),
+                })
+            });
+        }
+
+        self.cast = Some(CastDialog {
+            item_name: item.name.clone(),
+            reading_queue: item.is_directory,
+            item,
+            queue: Vec::new(),
+            queued: Vec::new(),
+            queue_position: None,
+            follow: None,
+            auto_target,
+            targets: Vec::new(),
+            selected: 0,
+            marked: Vec::new(),
+            searching: true,
+            starting: false,
+            session: None,
+            paused: false,
+            progress: None,
+            notice: None,
+            error: None,
+        });
+        self.events.forward(crate::cast::discover_targets(), AppEvent::CastTargets);
+    }
+
+    fn on_cast_targets(&mut self, result: Result<Vec<crate::cast::Target>, String>) {
+        let Some(dialog) = self.cast.as_mut().filter(|dialog| dialog.searching) else { return };
+        dialog.searching = false;
+        match result {
+            Ok(targets) if targets.is_empty() => dialog.error = Some("No receivers found".to_string()),
+            Ok(targets) => dialog.targets = targets,
+            Err(e) => dialog.error = Some(e),
+        }
+
+        if let Some(udn) = dialog.auto_target.take()
+            && !dialog.targets.is_empty()
+        {
+            match dialog.targets.iter().position(|target| target.matches_udn(&udn)) {
+                Some(index) => {
+                    dialog.selected = index;
+                    self.cast_to_selected();
+                }
+                None => dialog.error = Some(format!("Default renderer {} was not found", udn)),
+            }
+        }
+    }
+
+    fn on_cast_folder(&mut self, result: Result<Vec<DirectoryItem>, String>) {
+        let Some(dialog) = self.cast.as_mut().filter(|dialog| dialog.reading_queue) else { return };
+        dialog.reading_queue = false;
+        match result {
+            Ok(files) => dialog.queue = files,
+            Err(e) => {
+                dialog.starting = false;
+                dialog.error = Some(e);
+                return;
+            }
+        }
+        // A receiver was picked while the folder was being read
+        if dialog.starting {
+            dialog.starting = false;
+            self.cast_to_selected();
+        }
+    }
+
+    fn on_cast_queue(&mut self, message: crate::cast_queue::Message) {
+        let Some(dialog) = self.cast.as_mut().filter(|dialog| dialog.follow.is_some()) else { return };
+        match message {
+            crate::cast_queue::Message::Playing(index) => {
+                dialog.queue_position = Some(index);
+                dialog.progress = None;
+            }
+            crate::cast_queue::Message::Finished => {
+                dialog.follow = None;
+                dialog.notice = Some(format!("Played all of {}", dialog.item_name));
+            }
+            crate::cast_queue::Message::Failed(e) => {
+                dialog.follow = None;
+                dialog.error = Some(e);
+            }
+        }
+    }
+
+    pub fn cast_previous(&mut self) {
+        if let Some(dialog) = self.cast.as_mut()
+            && dialog.session.is_none()
+            && !dialog.starting
+        {
+            dialog.selected = dialog.selected.saturating_sub(1);
+        }
+    }
+
+    pub fn cast_next(&mut self) {
+        if let Some(dialog) = self.cast.as_mut()
+            && dialog.session.is_none()
+            && !dialog.starting
+            && dialog.selected + 1 < dialog.targets.len()
+        {
+            dialog.selected += 1;
+        }
+    }
+
+    /// Mark the highlighted receiver for a group cast, or unmark it.
+    pub fn cast_toggle_mark(&mut self) {
+        let Some(dialog) = self.cast.as_mut() else { return };
+        if dialog.session.is_some() || dialog.starting || dialog.selected >= dialog.targets.len() {
+            return;
+        }
+        match dialog.marked.binary_search(&dialog.selected) {
+            Ok(index) => {
+                dialog.marked.remove(index);
+            }
+            Err(index) => dialog.marked.insert(index, dialog.selected),
         }
     }
 
-    pub fn next(&mut self) {
-        match self.state {
-            AppState::ServerList => {
-                if !self.servers.is_empty() {
-                    self.selected_server = match self.selected_server {
-                        Some(i) if i < self.servers.len() - 1 => Some(i + 1),
-                        Some(_) => Some(0),
-                        None => Some(0),
-                    };
-                }
-            },
-            AppState::DirectoryBrowser => {
-                if !self.directory_contents.is_empty() {
-                    self.selected_item = match self.selected_item {
-                        Some(i) if i < self.directory_contents.len() - 1 => Some(i + 1),
-                        Some(_) => Some(0),
-                        None => Some(0),
-                    };
-                }
-            },
+    /// Start playback on the marked receivers, or the highlighted one if
+    /// none are marked, in the background.
+    pub fn cast_to_selected(&mut self) {
+        let Some(dialog) = self.cast.as_mut() else { return };
+        if dialog.session.is_some() || dialog.starting {
+            return;
+        }
+        let targets: Vec<crate::cast::Target> = if dialog.marked.is_empty() {
+            dialog.targets.get(dialog.selected).cloned().into_iter().collect()
+        } else {
+            dialog.marked.iter().filter_map(|&index| dialog.targets.get(index).cloned()).collect()
+        };
+        if targets.is_empty() {
+            return;
+        }
+        if dialog.item.is_directory {
+            if dialog.reading_queue {
+                // Started once the files are in
+                dialog.starting = true;
+                return;
+            }
+            if dialog.queue.is_empty() {
+                return;
+            }
+            if targets.len() > 1 {
+                dialog.error = Some("A folder is cast to one receiver at a time".to_string());
+                return;
+            }
         }
+        dialog.starting = true;
+        dialog.error = None;
+        dialog.notice = None;
+
+        // Asking the renderers what they play is a SOAP call each
+        let files = if dialog.item.is_directory { dialog.queue.clone() } else { vec![dialog.item.clone()] };
+        let (is_directory, title) = (dialog.item.is_directory, dialog.item_name.clone());
+        let quality = self.config.quality.clone();
+        self.workers.run(move || {
+            let sinks: Vec<Option<Vec<String>>> = targets.iter().map(crate::cast::sink_protocols).collect();
+            let known: Vec<Vec<String>> = sinks.iter().flatten().cloned().collect();
+            let files: Vec<DirectoryItem> =
+                files.iter().map(|file| crate::quality::choose(&quality, file, &known)).collect();
+            let queued: Vec<crate::cast_queue::Queued> = files
+                .iter()
+                .map(|file| crate::cast_queue::Queued {
+                    url: file.url.clone().unwrap_or_default(),
+                    title: file.name.clone(),
+                    protocol_info: file.metadata.as_ref().and_then(|metadata| metadata.protocol_info()),
+                })
+                .collect();
+            let first = &queued[0];
+            let warnings: Vec<String> = targets
+                .iter()
+                .zip(&sinks)
+                .filter_map(|(target, sink)| {
+                    crate::cast::compatibility_warning(target, sink.as_deref()?, first.protocol_info.as_deref())
+                })
+                .collect();
+            let protocol_info = first.protocol_info.as_deref();
+            let started = match (targets.as_slice(), is_directory) {
+                ([target], true) => crate::cast::Session::start_queue(target, &queued),
+                ([target], false) => crate::cast::Session::start(target, &first.url, &title, protocol_info),
+                (targets, _) => crate::cast::Session::start_group(targets, &first.url, &title, protocol_info),
+            };
+            let notice = (!warnings.is_empty()).then(|| warnings.join("; "));
+            AppEvent::Playback { started, queued, notice }
+        });
     }
 
-    pub fn select(&mut self) {
-        match self.state {
-            AppState::ServerList => {
-                if let Some(server_idx) = self.selected_server {
-                    if server_idx < self.servers.len() {
-                        self.state = AppState::DirectoryBrowser;
-                        self.current_directory.clear();
-                        self.load_directory();
-                    }
+    fn on_playback(
+        &mut self,
+        result: Result<crate::cast::Session, String>,
+        queued: Vec<crate::cast_queue::Queued>,
+        notice: Option<String>,
+    ) {
+        let Some(dialog) = self.cast.as_mut().filter(|dialog| dialog.starting) else {
+            // The dialog was closed while the receiver was starting up
+            if let Ok(session) = result
+                && let Err(e) = session.stop()
+            {
+                tracing::warn!(target: "mop::app", "Failed to stop playback nobody is watching: {}", e);
+            }
+            return;
+        };
+        dialog.starting = false;
+        dialog.queued = queued;
+        dialog.notice = notice;
+        match result {
+            Ok(session) => {
+                tracing::info!(target: "mop::app", "Casting {} to {}", dialog.item_name, session.target_name());
+                if let Some(warning) = &dialog.notice {
+                    tracing::warn!(target: "mop::app", "{}", warning);
                 }
-            },
-            AppState::DirectoryBrowser => {
-                if let Some(item_idx) = self.selected_item {
-                    if item_idx < self.directory_contents.len() {
-                        let item = &self.directory_contents[item_idx];
-                        if item.is_directory {
-                            self.current_directory.push(item.name.clone());
-                            self.load_directory();
-                        } else {
-                            // For files, try to play with mpv
-                            match self.play_selected_file() {
-                                Ok(_) => {
-                                    // mpv started successfully, clear any previous errors
-                                    self.last_error = None;
-                                }
-                                Err(e) => {
-                                    // mpv failed, show error
-                                    self.last_error = Some(format!("Failed to play file: {}", e));
-                                }
-                            }
+                if dialog.queued.len() > 1 {
+                    match session.renderer() {
+                        Some(renderer) => {
+                            let (tx, rx) = std::sync::mpsc::channel();
+                            let queue = dialog.queued.clone();
+                            dialog.follow = Some(crate::cast_queue::follow(renderer.clone(), queue, tx));
+                            dialog.queue_position = Some(0);
+                            self.events.forward(rx, AppEvent::CastQueue);
+                        }
+                        None => {
+                            let (rest, target) = (dialog.queued.len() - 1, session.target_name());
+                            dialog.notice = Some(format!("The other {} files are in {}'s queue", rest, target));
                         }
                     }
                 }
-            },
+                dialog.session = Some(Arc::new(session));
+            }
+            Err(e) => {
+                dialog.notice = None;
+                dialog.error = Some(e);
+            }
         }
     }
 
-    pub fn go_back(&mut self) {
-        match self.state {
-            AppState::DirectoryBrowser => {
-                if self.current_directory.is_empty() {
-                    self.state = AppState::ServerList;
-                } else {
-                    self.current_directory.pop();
-                    self.load_directory();
-                }
-            },
-            _ => {}
+    /// Make the highlighted target the one the send-to-TV key uses.
+    pub fn cast_set_default(&mut self) {
+        let Some(dialog) = self.cast.as_mut() else { return };
+        if dialog.session.is_some() {
+            return;
+        }
+        let Some(target) = dialog.targets.get(dialog.selected) else { return };
+        let Some(udn) = target.udn() else {
+            dialog.error = Some(format!("{} has no UPnP id to remember", target.name()));
+            return;
+        };
+        self.config.cast.default_renderer = udn.to_string();
+        match self.config.save() {
+            Ok(()) => {
+                tracing::info!(target: "mop::config", "Default renderer is now {} ({})", target.name(), udn);
+                dialog.notice = Some(format!("{} is now the default renderer", target.name()));
+                dialog.error = None;
+            }
+            Err(e) => dialog.error = Some(format!("Saving the default renderer failed: {}", e)),
         }
     }
 
-    pub fn toggle_help(&mut self) {
-        self.show_help = !self.show_help;
+    /// Add the item to the highlighted target's queue, closing the picker
+    /// once it's there.
+    pub fn cast_enqueue_selected(&mut self) {
+        let Some(dialog) = self.cast.as_mut() else { return };
+        if dialog.session.is_some() {
+            return;
+        }
+        let Some(target) = dialog.targets.get(dialog.selected).cloned() else { return };
+        if dialog.reading_queue {
+            dialog.error = Some(format!("Still reading {}", dialog.item_name));
+            return;
+        }
+        let files = if dialog.item.is_directory { dialog.queue.clone() } else { vec![dialog.item.clone()] };
+        let quality = self.config.quality.clone();
+        let item = dialog.item_name.clone();
+        dialog.notice = Some(format!("Adding {} to the queue on {}…", item, target.name()));
+        dialog.error = None;
+        self.workers.run(move || {
+            let files: Vec<(String, String)> = files
+                .iter()
+                .map(|file| crate::quality::choose(&quality, file, &[]))
+                .map(|file| (file.url.unwrap_or_default(), file.name))
+                .collect();
+            let result = crate::cast::enqueue(&target, &files);
+            AppEvent::CastControl(crate::cast::Control::Queued { target: target.name().to_string(), item, result })
+        });
     }
 
-    fn load_directory(&mut self) {
-        if let Some(server_idx) = self.selected_server {
-            if server_idx < self.servers.len() {
-                let server = &self.servers[server_idx];
-                let (contents, error) = crate::upnp::browse_directory(server, &self.current_directory, &mut self.container_id_map);
-                self.directory_contents = contents;
-                self.last_error = error.filter(|error| !error.trim().is_empty());
-                self.selected_item = if self.directory_contents.is_empty() { None } else { Some(0) };
-            }
-        }
+    pub fn cast_toggle_pause(&mut self) {
+        let Some(dialog) = self.cast.as_ref() else { return };
+        let Some(session) = dialog.session.clone() else { return };
+        let paused = !dialog.paused;
+        self.workers.run(move || {
+            AppEvent::CastControl(crate::cast::Control::Paused(session.set_paused(paused).map(|()| paused)))
+        });
     }
 
-    pub fn play_selected_file(&mut self) -> Result<(), String> {
-        if let Some(item_idx) = self.selected_item {
-            if item_idx < self.directory_contents.len() {
-                let item = &self.directory_contents[item_idx];
-                if !item.is_directory {
-                    if let Some(url) = &item.url {
-                        log::info!(target: "mop::app", "Playing file: {}", item.name);
-                        let result = self.invoke_player(url);
-                        if result.is_ok() && self.config.mop.auto_close {
-                            log::info!(target: "mop::app", "Auto-close enabled, quitting");
-                            self.should_quit = true;
-                        }
-                        return result;
-                    } else {
-                        log::warn!(target: "mop::app", "No URL available for file: {}", item.name);
-                        return Err("No URL available for this file".to_string());
-                    }
-                } else {
-                    return Err("Cannot play a directory".to_string());
+    pub fn cast_seek(&mut self, delta: f64) {
+        let Some(session) = self.cast.as_ref().and_then(|dialog| dialog.session.clone()) else { return };
+        self.workers.run(move || AppEvent::CastControl(crate::cast::Control::Seeked(session.seek_by(delta))));
+    }
+
+    /// Close the dialog, stopping playback on the receiver if it started.
+    pub fn close_cast_dialog(&mut self) {
+        let Some(session) = self.cast.take().and_then(|dialog| dialog.session) else { return };
+        self.workers.run(move || {
+            let target = session.target_name().to_string();
+            AppEvent::CastControl(crate::cast::Control::Stopped { target, result: session.stop() })
+        });
+    }
+
+    fn on_cast_control(&mut self, control: crate::cast::Control) {
+        use crate::cast::Control;
+        // What's left to report once the dialog has gone
+        let control = match control {
+            Control::Stopped { target, result: Err(e) } => {
+                tracing::warn!(target: "mop::app", "Stopping playback on {} failed: {}", target, e);
+                return;
+            }
+            Control::Stopped { .. } => return,
+            Control::Queued { target, item, result: Ok(added) } => {
+                tracing::info!(target: "mop::app", "Queued {} files of {} on {}", added, item, target);
+                self.last_error = Some(match added {
+                    1 => format!("Added {} to the queue on {}", item, target),
+                    added => format!("Added {} files of {} to the queue on {}", added, item, target),
+                });
+                if self.cast.as_ref().is_some_and(|dialog| dialog.session.is_none()) {
+                    self.cast = None;
                 }
+                return;
             }
+            control => control,
+        };
+        let Some(dialog) = self.cast.as_mut() else { return };
+        match control {
+            Control::Paused(Ok(paused)) if dialog.session.is_some() => dialog.paused = paused,
+            Control::Seeked(Ok(progress)) if dialog.session.is_some() => {
+                dialog.progress = Some(progress);
+                dialog.error = None;
+            }
+            Control::Paused(Err(e)) | Control::Seeked(Err(e)) | Control::Queued { result: Err(e), .. } => {
+                dialog.notice = None;
+                dialog.error = Some(e);
+            }
+            _ => {}
         }
-        Err("No file selected".to_string())
     }
 
-    fn invoke_player(&self, url: &str) -> Result<(), String> {
-        use std::process::Command;
-
-        let player = &self.config.mop.run;
-        log::debug!(target: "mop::app", "Invoking player: {} with URL: {}", player, url);
-
-        // Use setsid with nohup for complete session detachment
-        // This ensures the player runs completely independently of MOP
-        let cmd_str = format!("setsid nohup {} '{}' </dev/null >/dev/null 2>&1 &", player, url);
-        let status = Command::new("sh")
-            .arg("-c")
-            .arg(&cmd_str)
-            .status()
-            .map_err(|e| {
-                log::error!(target: "mop::app", "Failed to start {}: {}", player, e);
-                format!("Failed to start {}: {}", player, e)
-            })?;
+    /// Call after drawing a frame.
+    pub fn mark_drawn(&mut self) {
+        self.needs_redraw = false;
+        self.logs_drawn = crate::logger::entries_logged();
+        self.sleep_drawn = self.sleep_minutes_left();
+    }
 
-        if status.success() {
-            log::info!(target: "mop::app", "Player started successfully");
-            Ok(())
+    /// Ticks only matter while the log pane can show new entries.
+    pub fn tick_rate(&self) -> std::time::Duration {
+        if self.log_pane_state == LogPaneState::Hidden {
+            std::time::Duration::from_secs(2)
         } else {
-            log::error!(target: "mop::app", "Player command failed");
-            Err(format!("Failed to start {} command", player))
+            std::time::Duration::from_millis(250)
         }
     }
-    
+
     pub fn open_config_editor(&mut self) {
         self.show_config = true;
         self.config_editor = ConfigEditor::new(&self.config);
     }
 
     pub fn save_config(&mut self) -> Result<(), String> {
-        // Update config from editor
-        self.config.mop.run = self.config_editor.run_input.value().to_string();
-        self.config.mop.auto_close = self.config_editor.auto_close;
-        
+        // Update config from editor, keeping the modal open on invalid input
+        let mut config = self.config.clone();
+        self.config_editor.apply(&mut config)?;
+        self.config = config;
+
         // Save to file
         match self.config.save() {
             Ok(_) => {
+                self.keymap = Keymap::from_config(&self.config.keys).0;
+                self.hide = HideList::from_config(&self.config.hide).0;
+                // The players may have changed
+                self.player_support = None;
+                self.judge_listing();
+                self.probe_players();
+                self.filter_listing();
+                apply_process_settings(&self.config);
+                self.config_warnings = self.config.validate();
                 self.show_config = false;
                 self.last_error = None;
                 Ok(())
@@ -499,58 +3693,956 @@ impl App {
     }
 }
 
-impl ConfigEditor {
-    pub fn new(config: &Config) -> Self {
-        let mut run_input = Input::default();
-        run_input = run_input.with_value(config.mop.run.clone());
-        
-        Self {
-            run_input,
-            auto_close: config.mop.auto_close,
-            selected_field: ConfigField::Run,
+/// The settings kept process-wide rather than in the app: the proxy every
+/// client goes through, the hooks, and the download and relay rate limits.
+fn apply_process_settings(config: &Config) {
+    crate::proxy::set(&config.network.proxy);
+    crate::hooks::set(&config.hooks);
+    crate::download::set_rate_limit(config.downloads.rate_limit.saturating_mul(1024));
+    crate::relay::set_rate_limit(config.network.relay_rate_limit.saturating_mul(1024));
+}
+
+/// What a pin is kept under: the UDN without its "uuid:" prefix, as for
+/// `[cast] default_renderer`.
+fn pin_id(server: &PlexServer) -> Option<&str> {
+    let udn = server.udn.as_deref()?;
+    Some(udn.strip_prefix("uuid:").unwrap_or(udn))
+}
+
+/// Check a URL typed, pasted or piped in by the user. Anything with a scheme
+/// and a host is accepted, so players can deal with rtsp://, rtmp:// and so on.
+pub fn parse_stream_url(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("No URL given".to_string());
+    }
+    let url = url::Url::parse(text).map_err(|e| format!("Invalid URL '{}': {}", text, e))?;
+    if !url.has_host() {
+        return Err(format!("Invalid URL '{}': no host", text));
+    }
+    Ok(url.to_string())
+}
+
+/// The configured player for a URL that didn't come from a server listing,
+/// going by the file extension in its path.
+pub fn player_for_url<'a>(config: &'a Config, url: &str) -> &'a str {
+    let mime = url::Url::parse(url)
+        .ok()
+        .and_then(|url| mop_core::mime::for_path(std::path::Path::new(url.path())));
+    config.players.for_mime(mime, &config.mop.run)
+}
+
+/// Start `player` with the given URLs, detached from MOP so it keeps playing
+/// after MOP exits. The command may carry its own arguments ("mpv --fs");
+/// quote a program path that has spaces in it. The play hooks run with
+/// `hook` as it starts and exits, and the playback is reported to the
+/// server if `report`. What comes back gets the player's exit code when it
+/// exits (None if a signal ended it).
+pub fn launch_player(
+    player: &str,
+    urls: &[&str],
+    hook: &crate::hooks::Vars,
+    report: Option<crate::reporting::Report>,
+) -> Result<std::sync::mpsc::Receiver<Option<i32>>, String> {
+    use std::process::{Command, Stdio};
+
+    let redacted: Vec<String> = urls.iter().map(|url| crate::auth::redact(url)).collect();
+    tracing::debug!(target: "mop::app", "Invoking player: {} with URLs: {:?}", player, redacted);
+
+    let mut words = split_command(player).into_iter();
+    let program = words.next().ok_or_else(|| "No player command configured".to_string())?;
+    let hook = hook.clone().with("MOP_PLAYER", program_name(player));
+    let (exit, exited) = std::sync::mpsc::channel();
+    if program == crate::termux::ANDROID_PLAYER {
+        crate::termux::open(urls)?;
+        crate::hooks::run(Hook::PlayStart, &hook);
+        return Ok(exited);
+    }
+    let mut command = Command::new(&program);
+    command.args(words);
+    // So the sleep timer can stop it and the playback can be reported
+    let socket = (program_name(player) == "mpv").then(crate::mpv::new_socket);
+    if let Some(socket) = &socket {
+        command.arg(crate::mpv::ipc_option(socket));
+    }
+    command
+        .args(urls)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    detach(&mut command);
+
+    let mut child = command.spawn().map_err(|e| {
+        tracing::error!(target: "mop::app", "Failed to start {}: {}", program, e);
+        format!("Failed to start {}: {}", program, e)
+    })?;
+    crate::hooks::run(Hook::PlayStart, &hook);
+    let (ended, player_ended) = std::sync::mpsc::channel();
+    // Only mpv can say where it is
+    if let (Some(report), Some(socket)) = (report, socket) {
+        report.run(socket, player_ended);
+    }
+    // Collect its exit status so it doesn't linger as a zombie while MOP runs
+    std::thread::spawn(move || {
+        let code = child.wait().ok().and_then(|status| status.code());
+        let _ = ended.send(());
+        let _ = exit.send(code);
+        let code = code.map(|code| code.to_string());
+        crate::hooks::run(Hook::PlayEnd, &hook.with("MOP_EXIT_CODE", code.unwrap_or_default()));
+    });
+    tracing::info!(target: "mop::app", "Player started successfully");
+    Ok(exited)
+}
+
+/// A file's average bitrate in bits per second, from its size and length.
+fn bitrate(item: &DirectoryItem) -> Option<u64> {
+    let size = item.metadata.as_ref()?.size?;
+    let secs = crate::music::duration_secs(item).filter(|&secs| secs > 0)?;
+    Some(size * 8 / secs)
+}
+
+/// "38.4 Mbit/s", or kbit/s below a megabit.
+fn format_rate(bits_per_sec: u64) -> String {
+    if bits_per_sec >= 1_000_000 {
+        format!("{:.1} Mbit/s", bits_per_sec as f64 / 1e6)
+    } else {
+        format!("{} kbit/s", bits_per_sec / 1000)
+    }
+}
+
+/// What the letter jump offers, '#' for titles not starting with A to Z.
+pub const JUMP_LETTERS: &str = "#ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Where `name` goes in [`JUMP_LETTERS`], going by its first letter or digit.
+pub fn initial(name: &str) -> char {
+    match name.chars().find(|c| c.is_alphanumeric()) {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase(),
+        _ => '#',
+    }
+}
+
+/// Whether `item` is a picture, by the type the server gave or else by its
+/// URL's extension.
+fn is_image(item: &DirectoryItem) -> bool {
+    if item.is_directory {
+        return false;
+    }
+    let mime = match item.metadata.as_ref().and_then(|m| m.format.as_deref()) {
+        Some(format) => Some(format),
+        None => item
+            .url
+            .as_deref()
+            .and_then(|url| url::Url::parse(url).ok())
+            .and_then(|url| mop_core::mime::for_path(std::path::Path::new(url.path()))),
+    };
+    mime.is_some_and(|mime| mime.starts_with("image/"))
+}
+
+/// The program a player command runs, lowercased: its file name without
+/// ".exe", from a Unix or a Windows path.
+pub fn program_name(command: &str) -> String {
+    let program = split_command(command).into_iter().next().unwrap_or_default().to_lowercase();
+    let name = program.rsplit(['/', '\\']).next().unwrap_or_default();
+    name.strip_suffix(".exe").unwrap_or(name).to_string()
+}
+
+/// `player` with the server's `[servers] headers` as options, for the
+/// players that take them: mpv any header, VLC the User-Agent and Referer.
+fn player_command(player: &str, headers: &mop_core::headers::Headers) -> String {
+    let quoted = |word: String| if word.contains('"') { format!("'{}'", word) } else { format!("\"{}\"", word) };
+    let mut options = Vec::new();
+    let program = program_name(player);
+    for (name, value) in headers {
+        let (name, value) = (name.trim(), value.trim().to_string());
+        let user_agent = name.eq_ignore_ascii_case("user-agent");
+        match program.as_str() {
+            "mpv" if user_agent => options.push(format!("--user-agent={}", quoted(value))),
+            "mpv" => options.push(format!("--http-header-fields-append={}", quoted(format!("{}: {}", name, value)))),
+            "vlc" | "cvlc" if user_agent => options.push(format!("--http-user-agent={}", quoted(value))),
+            "vlc" | "cvlc" if name.eq_ignore_ascii_case("referer") => {
+                options.push(format!("--http-referrer={}", quoted(value)))
+            }
+            _ => {}
+        }
+    }
+    std::iter::once(player.to_string()).chain(options).collect::<Vec<_>>().join(" ")
+}
+
+/// `viewer` with the option that sets how long it shows each image, for
+/// the viewers that take one on the command line.
+fn slideshow_command(viewer: &str, interval_secs: u64) -> String {
+    match program_name(viewer).as_str() {
+        "mpv" => format!("{} --image-display-duration={}", viewer, interval_secs),
+        "feh" => format!("{} --slideshow-delay={}", viewer, interval_secs),
+        "vlc" | "cvlc" => format!("{} --image-duration={}", viewer, interval_secs),
+        _ => viewer.to_string(),
+    }
+}
+
+/// Put `urls` in random order (Fisher-Yates, seeded from the hasher keys
+/// std already randomizes per process).
+fn shuffle(urls: &mut [String]) {
+    use std::hash::{BuildHasher, RandomState};
+
+    let mut state = RandomState::new().hash_one(std::time::SystemTime::now()) | 1;
+    for i in (1..urls.len()).rev() {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        urls.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// The page a server's web interface is at: its base URL, if that is one a
+/// browser can open.
+fn web_ui_url(base_url: &str) -> Result<String, String> {
+    let url = url::Url::parse(base_url).map_err(|e| format!("Invalid server URL '{}': {}", base_url, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("{} has no web interface", base_url));
+    }
+    Ok(url.to_string())
+}
+
+/// Open `url` in the desktop's default browser, or whichever Android app
+/// takes web pages.
+fn open_in_browser(url: &str) -> Result<(), String> {
+    use std::process::{Command, Stdio};
+
+    let mut command = if crate::termux::is_termux() {
+        Command::new(crate::termux::ANDROID_PLAYER)
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // `start` is a cmd builtin; its first quoted argument is a window title
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(url).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    detach(&mut command);
+
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command.spawn().map_err(|e| format!("Failed to start {}: {}", program, e))?;
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    tracing::info!(target: "mop::app", "Opened {} with {}", crate::auth::redact(url), program);
+    Ok(())
+}
+
+/// Keep the player out of MOP's process group, so Ctrl-C or closing the
+/// terminal doesn't take it down too.
+#[cfg(unix)]
+pub fn detach(command: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+/// Give the player no console of its own (a GUI player would otherwise
+/// open an empty window) and its own group for Ctrl-C.
+#[cfg(windows)]
+pub fn detach(command: &mut std::process::Command) {
+    use std::os::windows::process::CommandExt;
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn detach(_command: &mut std::process::Command) {}
+
+/// Split a player command into words. Single or double quotes keep spaces
+/// inside a word; backslashes are literal, so Windows paths work as typed.
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in command.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// A page as the worker sends it: each child, then the end of the page.
+    fn browse(generation: u64, names: &[&str]) -> Vec<AppEvent> {
+        let event = |message| AppEvent::Browse(Browse { generation, start: 0, message });
+        let mut events: Vec<AppEvent> = names
+            .iter()
+            .map(|name| {
+                event(BrowseMessage::ItemFound(Box::new(DirectoryItem {
+                    id: Some(name.to_lowercase()),
+                    name: name.to_string(),
+                    is_directory: true,
+                    ..Default::default()
+                })))
+            })
+            .collect();
+        events.push(event(BrowseMessage::PageComplete { found: names.len(), total: Some(names.len()), error: None }));
+        events
+    }
+
+    #[test]
+    fn listings_for_somewhere_else_are_dropped() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.servers.push(crate::upnp::UpnpDevice {
+            name: "nas".to_string(),
+            location: "http://192.168.1.31:8200/rootDesc.xml".to_string(),
+            base_url: "http://192.168.1.31:8200".to_string(),
+            ..Default::default()
+        });
+        app.selected_server = Some(0);
+        app.state = AppState::DirectoryBrowser;
+        app.current_directory = vec!["Video".to_string()];
+        app.directory_ids = vec!["64".to_string()];
+        app.is_browsing = true;
+        app.browse_generation = 2;
+
+        // The user went into Video before the root listing came back
+        for event in browse(1, &["Music", "Video"]) {
+            app.update(event);
+        }
+        assert!(app.is_browsing);
+        assert!(app.directory_contents.is_empty());
+
+        let mut events = browse(2, &["Films", "Series"]);
+        let complete = events.pop().unwrap();
+        for event in events {
+            app.update(event);
+        }
+        // Shown while the rest of the page is still coming
+        assert!(app.is_browsing);
+        assert_eq!(app.directory_contents[0].name, "Films");
+        assert_eq!(app.selected_item, Some(0));
+        app.update(complete);
+        assert!(!app.is_browsing);
+        assert_eq!(app.directory_contents.len(), 2);
+
+        // Into Films by its id, not its title
+        app.select();
+        assert_eq!(app.directory_ids, ["64", "films"]);
+        assert_eq!(app.current_directory, ["Video", "Films"]);
+
+        // Video's rows are still up while Films loads: Enter is refused, and says so
+        app.selected_item = Some(1);
+        app.select();
+        assert_eq!(app.current_directory, ["Video", "Films"]);
+        assert!(app.last_error.as_deref().is_some_and(|error| error.starts_with("Still opening")));
+
+        // A later page on its way doesn't get in the way
+        let generation = app.browse_generation;
+        for event in browse(generation, &["Heat", "Ronin"]) {
+            app.update(event);
         }
+        app.is_browsing = true;
+        app.selected_item = Some(0);
+        app.select();
+        assert_eq!(app.current_directory, ["Video", "Films", "Heat"]);
     }
 
-    pub fn next_field(&mut self) {
-        self.selected_field = match self.selected_field {
-            ConfigField::Run => ConfigField::AutoClose,
-            ConfigField::AutoClose => ConfigField::Run,
+    #[test]
+    fn parent_entry_sits_above_the_listing_and_goes_back() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.state = AppState::DirectoryBrowser;
+        app.browse_generation = 1;
+        for event in browse(1, &["Heat.mkv", "Ronin.mkv"]) {
+            app.update(event);
+        }
+        assert_eq!(app.selected_row(), Some(1));
+        assert_eq!(app.selected_entry().map(|item| item.name.as_str()), Some("Heat.mkv"));
+
+        // Up from the first entry is "..", and up again wraps to the last
+        app.previous();
+        assert!(app.parent_selected);
+        assert_eq!(app.selected_row(), Some(0));
+        assert!(app.selected_entry().is_none());
+        app.previous();
+        assert_eq!(app.selected_entry().map(|item| item.name.as_str()), Some("Ronin.mkv"));
+        app.next();
+        assert_eq!(app.selected_row(), Some(0));
+        app.next();
+        assert_eq!(app.selected_item, Some(0));
+
+        // ".." of the root listing is the server list
+        app.previous();
+        app.select();
+        assert!(matches!(app.state, AppState::ServerList));
+    }
+
+    #[test]
+    fn hidden_entries_are_left_out_until_revealed() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        let patterns = vec!["*.nfo".to_string(), "*sample*".to_string()];
+        app.hide = HideList::from_config(&crate::config::HideConfig { patterns }).0;
+        app.show_hidden = false;
+        app.state = AppState::DirectoryBrowser;
+        app.browse_generation = 1;
+        for event in browse(1, &["Heat.nfo", "Heat.mkv", "Heat-sample.mkv", "Ronin.mkv"]) {
+            app.update(event);
+        }
+        let names = |app: &App| app.directory_contents.iter().map(|item| item.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&app), ["Heat.mkv", "Ronin.mkv"]);
+        assert_eq!(app.hidden_count(), 2);
+
+        // The selection stays on Ronin either way
+        app.selected_item = Some(1);
+        app.toggle_hidden();
+        assert_eq!(names(&app).len(), 4);
+        assert_eq!((app.hidden_count(), app.selected_item), (0, Some(3)));
+        app.selected_item = Some(2);
+        app.toggle_hidden();
+        assert_eq!(app.selected_item, Some(1));
+    }
+
+    #[test]
+    fn letters_jump_to_the_entries_starting_with_them() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.state = AppState::DirectoryBrowser;
+        app.browse_generation = 1;
+        for event in browse(1, &["Alien", "[2001] Odyssey", "Heat", "heat 2", "Ronin", "Ärger"]) {
+            app.update(event);
+        }
+        assert_eq!(app.initials(), HashSet::from(['A', '#', 'H', 'R']));
+        app.jump_to_letter('h');
+        assert_eq!(app.selected_item, Some(2));
+        app.jump_to_letter('H');
+        assert_eq!(app.selected_item, Some(3));
+        // Past the last, back to the first
+        app.jump_to_letter('h');
+        assert_eq!(app.selected_item, Some(2));
+        app.jump_to_letter('2');
+        assert_eq!(app.selected_item, Some(1));
+        app.jump_to_letter('#');
+        assert_eq!(app.selected_item, Some(5));
+        app.jump_to_letter('z');
+        assert_eq!(app.selected_item, Some(5));
+        assert_eq!(app.last_error.as_deref(), Some("Nothing here starts with Z"));
+    }
+
+    #[test]
+    fn filters_narrow_the_listing_and_are_recalled_per_server() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.cache = Some(Cache::in_memory().unwrap());
+        app.servers.push(crate::upnp::UpnpDevice {
+            name: "nas".to_string(),
+            location: "http://192.168.1.31:8200/rootDesc.xml".to_string(),
+            base_url: "http://192.168.1.31:8200".to_string(),
+            udn: Some("uuid:nas".to_string()),
+            ..Default::default()
+        });
+        app.selected_server = Some(0);
+        app.state = AppState::DirectoryBrowser;
+        app.browse_generation = 1;
+        for event in browse(1, &["The Wire", "Heat", "Wired", "Ronin"]) {
+            app.update(event);
+        }
+        let names = |app: &App| app.directory_contents.iter().map(|item| item.name.clone()).collect::<Vec<_>>();
+        let typed = |app: &mut App, text: &str| {
+            app.filter_dialog.as_mut().unwrap().input = Input::default().with_value(text.to_string());
+            app.filter_edited();
         };
+
+        app.open_filter_dialog();
+        typed(&mut app, "WIRE");
+        assert_eq!(names(&app), ["The Wire", "Wired"]);
+        app.confirm_filter();
+        assert_eq!(app.filter(), Some("wire"));
+        app.open_filter_dialog();
+        typed(&mut app, "ronin");
+        app.confirm_filter();
+        app.open_filter_dialog();
+        typed(&mut app, "he");
+        app.cancel_filter();
+        assert_eq!(names(&app), ["Ronin"]);
+
+        // Up steps back through what was used before, Down returns to what was typed
+        app.open_filter_dialog();
+        typed(&mut app, "");
+        typed(&mut app, "hea");
+        app.filter_history_older();
+        app.filter_history_older();
+        assert_eq!(names(&app), ["The Wire", "Wired"]);
+        app.filter_history_older();
+        assert_eq!(app.filter_dialog.as_ref().unwrap().input.value(), "WIRE");
+        app.filter_history_newer();
+        app.filter_history_newer();
+        assert_eq!(app.filter_dialog.as_ref().unwrap().input.value(), "hea");
+        assert_eq!(names(&app), ["Heat"]);
+        app.cancel_filter();
+        assert_eq!(app.filter(), Some("ronin"));
     }
 
-    pub fn previous_field(&mut self) {
-        self.selected_field = match self.selected_field {
-            ConfigField::Run => ConfigField::AutoClose,
-            ConfigField::AutoClose => ConfigField::Run,
+    #[test]
+    fn collapsed_duplicates_ask_which_copy_to_play() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.state = AppState::DirectoryBrowser;
+        let file = |id: &str, name: &str| DirectoryItem {
+            id: Some(id.to_string()),
+            name: name.to_string(),
+            url: Some(format!("http://192.168.1.5:8200/MediaItems/{}.mkv", id)),
+            metadata: Some(mop_core::FileMetadata {
+                size: Some(8_500_000_000),
+                ..Default::default()
+            }),
+            ..Default::default()
         };
+        let listing = vec![file("12", "Heat"), file("13", "Ronin"), file("97", "Heat")];
+
+        app.config.mop.collapse_duplicates = false;
+        app.show_listing(listing.clone(), Some(3), 0);
+        assert_eq!(app.directory_contents.len(), 3);
+        assert_eq!(app.copies(&app.directory_contents[2]), 2);
+
+        app.config.mop.collapse_duplicates = true;
+        app.show_listing(listing, Some(3), 0);
+        let names: Vec<_> = app.directory_contents.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["Heat", "Ronin"]);
+        assert_eq!(app.hidden_count(), 0);
+        app.play_file(true).unwrap();
+        let dialog = app.variants.as_ref().unwrap();
+        let ids: Vec<_> = dialog.copies.iter().filter_map(|copy| copy.id.as_deref()).collect();
+        assert_eq!((ids, dialog.quit), (vec!["12", "97"], true));
     }
 
-    pub fn toggle_auto_close(&mut self) {
-        if self.selected_field == ConfigField::AutoClose {
-            self.auto_close = !self.auto_close;
-        }
+    #[test]
+    fn only_changes_ask_for_a_redraw() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.mark_drawn();
+        app.update(AppEvent::Tick);
+        assert!(!app.needs_redraw);
+        assert_eq!(app.tick_rate(), std::time::Duration::from_secs(2));
+
+        app.update(AppEvent::Resize);
+        assert!(app.needs_redraw);
+        // Progress off screen changes nothing, and keeps what's still to draw
+        let progress = || crate::downloads::Message::Progress(crate::download::Progress { downloaded: 1, total: None });
+        app.update(AppEvent::Download { id: 1, message: progress() });
+        assert!(app.needs_redraw);
+        app.mark_drawn();
+        app.update(AppEvent::Download { id: 1, message: progress() });
+        assert!(!app.needs_redraw);
+        app.open_downloads();
+        app.update(AppEvent::Download { id: 1, message: progress() });
+        assert!(app.needs_redraw);
+        app.mark_drawn();
+        app.toggle_log_pane();
+        assert_eq!(app.tick_rate(), std::time::Duration::from_millis(250));
     }
 
-    pub fn handle_key(&mut self, key: ratatui::crossterm::event::KeyEvent) -> bool {
-        use ratatui::crossterm::event::{KeyCode, Event};
-        use tui_input::backend::crossterm::EventHandler;
-        
-        match self.selected_field {
-            ConfigField::Run => {
-                // Convert KeyEvent to Event for tui-input
-                let event = Event::Key(key);
-                self.run_input.handle_event(&event);
-                true
-            }
-            ConfigField::AutoClose => {
-                match key.code {
-                    KeyCode::Char(' ') | KeyCode::Enter => {
-                        self.toggle_auto_close();
-                        true
-                    }
-                    _ => false
-                }
-            }
+    #[test]
+    fn containers_are_new_against_the_count_when_last_opened_even_after_a_restart() {
+        let server = crate::upnp::UpnpDevice {
+            name: "nas".to_string(),
+            location: "http://127.0.0.1:9/desc.xml".to_string(),
+            content_directory_url: Some("http://127.0.0.1:9/cd".to_string()),
+            udn: Some("uuid:nas".to_string()),
+            ..Default::default()
+        };
+        let start = |cache: Cache| {
+            let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+            app.cache = Some(cache);
+            app.servers.push(server.clone());
+            app.watch_containers(&server);
+            app
+        };
+        let changes = |initial, containers: &[(&str, u32)]| crate::gena::Changes {
+            initial,
+            containers: containers.iter().map(|(id, count)| (id.to_string(), *count)).collect(),
+        };
+
+        let mut app = start(Cache::in_memory().unwrap());
+        app.on_container_updates(&server.location, changes(true, &[("64", 3), ("65", 1)]));
+        assert!(!app.container_changed_id("64") && !app.container_changed_id("65"));
+        app.on_container_updates(&server.location, changes(false, &[("64", 4), ("70", 1)]));
+        assert!(app.container_changed_id("64") && app.container_changed_id("70"));
+
+        // A later run hears how things stand, and 64 is still new
+        let mut app = start(app.cache.take().unwrap());
+        app.on_container_updates(&server.location, changes(true, &[("64", 4), ("65", 1)]));
+        assert!(app.container_changed_id("64") && !app.container_changed_id("65"));
+        app.container_seen(&server, "64");
+
+        let mut app = start(app.cache.take().unwrap());
+        app.on_container_updates(&server.location, changes(true, &[("64", 4)]));
+        assert!(!app.container_changed_id("64"));
+    }
+
+    #[test]
+    fn a_sleep_timer_too_long_to_run_is_refused() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.open_sleep_dialog();
+        app.sleep_dialog.as_mut().unwrap().input = Input::default().with_value(u64::MAX.to_string());
+        app.set_sleep_timer_from_dialog();
+        let dialog = app.sleep_dialog.as_ref().expect("still open");
+        assert!(dialog.error.as_deref().unwrap().contains("longer than a timer can run"));
+        assert!(app.sleep_at.is_none());
+
+        app.set_sleep_timer(90).unwrap();
+        assert_eq!(app.sleep_minutes_left(), Some(90));
+        // The countdown is redrawn when the minute shown changes, not every tick
+        app.mark_drawn();
+        app.update(AppEvent::Tick);
+        assert!(!app.needs_redraw);
+        app.sleep_at = Some(std::time::Instant::now() + std::time::Duration::from_secs(60));
+        app.update(AppEvent::Tick);
+        assert!(app.needs_redraw);
+    }
+
+    #[test]
+    fn the_palette_narrows_to_actions_with_every_word_typed() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.open_palette();
+        let palette = app.palette.as_mut().unwrap();
+        assert!(palette.commands().contains(&Action::SleepTimer));
+        assert!(!palette.commands().contains(&Action::Down));
+        palette.input = Input::default().with_value("play a".to_string());
+        assert_eq!(palette.commands(), [Action::PlayAll, Action::PlayAndQuit, Action::PlayAndStay]);
+
+        app.palette_step(-1);
+        assert_eq!(app.take_palette_command(), Some(Action::PlayAndStay));
+        assert!(app.palette.is_none());
+    }
+
+    #[test]
+    fn player_commands_split_into_words() {
+        assert_eq!(split_command("mpv --fs  --volume=50"), ["mpv", "--fs", "--volume=50"]);
+        assert_eq!(
+            split_command(r#""C:\Program Files\VideoLAN\VLC\vlc.exe" --fullscreen"#),
+            [r"C:\Program Files\VideoLAN\VLC\vlc.exe", "--fullscreen"]
+        );
+        assert_eq!(split_command("mpv --title='' -"), ["mpv", "--title=", "-"]);
+        assert!(split_command("  ").is_empty());
+    }
+
+    #[test]
+    fn slideshows_keep_the_images_and_set_each_viewers_interval() {
+        let file = |name: &str, format: Option<&str>| DirectoryItem {
+            name: name.to_string(),
+            url: Some(format!("http://192.168.1.5:8200/{}", name)),
+            metadata: Some(mop_core::FileMetadata {
+                format: format.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(is_image(&file("beach", Some("image/jpeg"))));
+        assert!(is_image(&file("beach.PNG", None)));
+        assert!(!is_image(&file("beach.mp4", None)));
+        assert!(!is_image(&file("beach.jpg", Some("video/mp4"))));
+
+        assert_eq!(slideshow_command("mpv --fs", 8), "mpv --fs --image-display-duration=8");
+        assert_eq!(slideshow_command(r"C:\Tools\feh.exe", 3), r"C:\Tools\feh.exe --slideshow-delay=3");
+        assert_eq!(slideshow_command("eog", 3), "eog");
+
+        let mut urls: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+        shuffle(&mut urls);
+        urls.sort_by_key(|url| url.parse::<u32>().unwrap());
+        assert_eq!(urls, (0..50).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bitrates_come_from_the_size_and_length() {
+        let remux = DirectoryItem {
+            name: "Dune (2021).mkv".to_string(),
+            url: Some("http://192.168.1.5:8200/MediaItems/41.mkv".to_string()),
+            metadata: Some(mop_core::FileMetadata {
+                size: Some(70_875_000_000),
+                duration: Some("2:35:00.000".to_string()),
+                format: Some("video/x-matroska".to_string()),
+                resolution: Some("3840x2160".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(bitrate(&remux), Some(60_967_741));
+        assert_eq!(format_rate(60_967_741), "61.0 Mbit/s");
+        assert_eq!(format_rate(320_000), "320 kbit/s");
+
+        let live = DirectoryItem { metadata: None, ..remux };
+        assert_eq!(bitrate(&live), None);
+    }
+
+    #[test]
+    fn server_headers_go_to_the_players_that_take_them() {
+        let mut headers = mop_core::headers::Headers::new();
+        headers.insert("User-Agent".to_string(), "Kodi/21.0 (X11)".to_string());
+        headers.insert("X-Emby-Token".to_string(), "abc".to_string());
+        assert_eq!(
+            player_command("mpv --fs", &headers),
+            r#"mpv --fs --user-agent="Kodi/21.0 (X11)" --http-header-fields-append="X-Emby-Token: abc""#
+        );
+        assert_eq!(
+            split_command(&player_command("mpv", &headers)),
+            ["mpv", "--user-agent=Kodi/21.0 (X11)", "--http-header-fields-append=X-Emby-Token: abc"]
+        );
+        let vlc = player_command(r"C:\VLC\vlc.exe", &headers);
+        assert_eq!(vlc, r#"C:\VLC\vlc.exe --http-user-agent="Kodi/21.0 (X11)""#);
+        assert_eq!(player_command("celluloid", &headers), "celluloid");
+        assert_eq!(player_command("mpv", &Default::default()), "mpv");
+    }
+
+    #[test]
+    fn only_http_servers_have_a_web_interface() {
+        assert_eq!(web_ui_url("http://192.168.1.5:8096").unwrap(), "http://192.168.1.5:8096/");
+        assert_eq!(web_ui_url("https://media.home/jellyfin").unwrap(), "https://media.home/jellyfin");
+        assert!(web_ui_url("rtsp://192.168.1.9:554").is_err());
+        assert!(web_ui_url("192.168.1.5").is_err());
+    }
+
+    #[test]
+    fn allowing_local_network_access_rescans() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.is_discovering = true;
+        app.update(AppEvent::Discovery(DiscoveryMessage::PermissionDenied("blocked".to_string())));
+        assert!(app.permission.is_some());
+
+        app.update(AppEvent::Permission(mop_core::permissions::PermissionState::Denied));
+        assert!(app.permission.as_ref().unwrap().still_denied);
+
+        app.update(AppEvent::Permission(mop_core::permissions::PermissionState::Granted));
+        assert!(app.permission.is_none());
+        assert!(app.discovery_errors.is_empty());
+        // Rescans once the discovery that ran without access is done
+        assert!(app.rescan_pending);
+    }
+
+    #[test]
+    fn jumping_to_an_object_id_opens_it_and_back_returns() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.servers.push(crate::upnp::UpnpDevice {
+            name: "NAS".to_string(),
+            location: "http://127.0.0.1:9/rootDesc.xml".to_string(),
+            base_url: "http://127.0.0.1:9".to_string(),
+            content_directory_url: Some("http://127.0.0.1:9/ctl".to_string()),
+            ..Default::default()
+        });
+        app.selected_server = Some(0);
+
+        app.open_object_id_dialog();
+        app.jump_to_object_id();
+        assert!(app.object_id_dialog.as_ref().unwrap().error.is_some());
+
+        app.object_id_dialog.as_mut().unwrap().input = Input::default().with_value(" 64$3 ".to_string());
+        app.jump_to_object_id();
+        assert!(app.object_id_dialog.is_none());
+        assert!(matches!(app.state, AppState::DirectoryBrowser));
+        assert_eq!(app.current_directory, ["#64$3"]);
+        assert_eq!(app.object_id(), "64$3");
+
+        app.go_back();
+        assert_eq!(app.object_id(), "0");
+        app.go_back();
+        assert!(matches!(app.state, AppState::ServerList));
+    }
+
+    #[test]
+    fn a_folder_playlist_has_its_files_in_order() {
+        let item = |name: &str, url: Option<&str>, format: Option<&str>| DirectoryItem {
+            id: Some(name.to_string()),
+            name: name.to_string(),
+            url: url.map(str::to_string),
+            metadata: Some(mop_core::FileMetadata {
+                format: format.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let items = vec![
+            DirectoryItem { is_directory: true, url: None, ..item("Extras", None, None) },
+            item("cover.jpg", None, Some("image/jpeg")),
+            item("01", Some("http://nas:8200/MediaItems/1.flac"), Some("http-get:*:audio/flac:*")),
+            item("02", Some("http://nas:8200/MediaItems/2.flac"), Some("http-get:*:audio/flac:*")),
+        ];
+        let server = crate::upnp::UpnpDevice {
+            name: "NAS".to_string(),
+            location: "http://nas:8200/rootDesc.xml".to_string(),
+            base_url: "http://nas:8200".to_string(),
+            ..Default::default()
+        };
+
+        let playlist = Playlist::of("Album".to_string(), &items, &server);
+        assert_eq!(playlist.urls, ["http://nas:8200/MediaItems/1.flac", "http://nas:8200/MediaItems/2.flac"]);
+        assert_eq!(playlist.format.as_deref(), Some("http-get:*:audio/flac:*"));
+    }
+
+    #[test]
+    fn a_network_change_rescans_and_keeps_old_servers_until_found_again() {
+        let device = |name: &str| crate::upnp::UpnpDevice {
+            name: name.to_string(),
+            location: format!("http://192.168.1.5:8200/{}.xml", name),
+            base_url: "http://192.168.1.5:8200".to_string(),
+            content_directory_url: Some("http://192.168.1.5:8200/ctl".to_string()),
+            ..Default::default()
+        };
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.is_discovering = true;
+        app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device("nas")))));
+        app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device("tv")))));
+
+        app.update(AppEvent::NetworkChanged);
+        assert!(app.rescan_pending);
+        assert!(!app.use_daemon);
+        assert!(app.servers.iter().all(|server| app.is_cached_only(server)));
+
+        app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device("tv")))));
+        let fresh: Vec<&str> =
+            app.servers.iter().filter(|server| !app.is_cached_only(server)).map(|s| s.name.as_str()).collect();
+        assert_eq!(fresh, ["tv"]);
+    }
+
+    #[test]
+    fn a_lone_media_server_is_opened_when_asked_to() {
+        let device = |name: &str, device_type: &str, content_directory: bool| crate::upnp::UpnpDevice {
+            name: name.to_string(),
+            location: format!("http://127.0.0.1:9/{}.xml", name),
+            base_url: "http://127.0.0.1:9".to_string(),
+            device_client: Some(device_type.to_string()),
+            content_directory_url: content_directory.then(|| "http://127.0.0.1:9/ctl".to_string()),
+            ..Default::default()
+        };
+        let discover = |app: &mut App, devices: Vec<crate::upnp::UpnpDevice>| {
+            app.is_discovering = true;
+            app.update(AppEvent::Discovery(DiscoveryMessage::AllComplete(devices)));
+        };
+        let nas = device("nas", "urn:schemas-upnp-org:device:MediaServer:1", true);
+        let router = device("router", "urn:schemas-upnp-org:device:InternetGatewayDevice:1", false);
+
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.config.mop.auto_open_server = true;
+        discover(&mut app, vec![router.clone(), nas.clone()]);
+        assert!(matches!(app.state, AppState::DirectoryBrowser));
+        assert_eq!(app.selected_server, Some(1));
+
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.config.mop.auto_open_server = true;
+        discover(&mut app, vec![nas.clone(), device("tv", "urn:schemas-upnp-org:device:MediaServer:1", true)]);
+        assert!(matches!(app.state, AppState::ServerList));
+
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.config.mop.auto_open_server = false;
+        discover(&mut app, vec![nas]);
+        assert!(matches!(app.state, AppState::ServerList));
+        assert_eq!(app.selected_server, None);
+    }
+
+    #[test]
+    fn the_last_location_is_reopened_once_its_server_is_found() {
+        let device = |name: &str| crate::upnp::UpnpDevice {
+            name: name.to_string(),
+            location: format!("http://127.0.0.1:9/{}.xml", name),
+            base_url: "http://127.0.0.1:9".to_string(),
+            device_client: Some("urn:schemas-upnp-org:device:MediaServer:1".to_string()),
+            content_directory_url: Some("http://127.0.0.1:9/ctl".to_string()),
+            udn: Some(format!("uuid:{}", name)),
+            ..Default::default()
+        };
+        let found = |app: &mut App, name: &str| {
+            app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device(name)))));
+        };
+
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.cache = Some(Cache::in_memory().unwrap());
+        found(&mut app, "nas");
+        app.selected_server = Some(0);
+        app.state = AppState::DirectoryBrowser;
+        app.current_directory = vec!["Music".to_string(), "Jazz".to_string()];
+        app.directory_ids = vec!["1".to_string(), "1$7".to_string()];
+        app.remember_location();
+
+        let cache = app.cache.take().unwrap();
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.pending_location = cache.last_location().unwrap();
+        app.cache = Some(cache);
+        found(&mut app, "tv");
+        assert!(matches!(app.state, AppState::ServerList));
+        found(&mut app, "nas");
+        assert!(matches!(app.state, AppState::DirectoryBrowser));
+        assert_eq!(app.selected_server, Some(1));
+        assert_eq!(app.current_directory, ["Music", "Jazz"]);
+        assert_eq!(app.object_id(), "1$7");
+
+        // Gone from the network: the server list it is
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.pending_location = Some(LastLocation {
+            server: "uuid:nas".to_string(),
+            path: Vec::new(),
+            object_ids: Vec::new(),
+        });
+        app.is_discovering = true;
+        app.update(AppEvent::Discovery(DiscoveryMessage::AllComplete(vec![device("tv")])));
+        assert!(matches!(app.state, AppState::ServerList));
+        assert!(app.pending_location.is_none());
+    }
+
+    #[test]
+    fn pinned_servers_stay_on_top() {
+        let device = |name: &str| crate::upnp::UpnpDevice {
+            name: name.to_string(),
+            location: format!("http://127.0.0.1:9/{}.xml", name),
+            base_url: "http://127.0.0.1:9".to_string(),
+            udn: Some(format!("uuid:{}", name)),
+            ..Default::default()
+        };
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.config.mop.pinned_servers = vec!["tv".to_string(), "nas".to_string()];
+        for name in ["router", "nas", "printer"] {
+            app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device(name)))));
         }
+        app.selected_server = Some(2);
+        app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device("tv")))));
+
+        let names: Vec<&str> = app.servers.iter().map(|server| server.name.as_str()).collect();
+        assert_eq!(names, ["tv", "nas", "router", "printer"]);
+        assert_eq!(app.selected_server, Some(3));
+        assert!(app.is_pinned(&app.servers[1]));
+        assert!(!app.is_pinned(&app.servers[2]));
+    }
+
+    #[test]
+    fn diagnostics_from_an_earlier_run_are_dropped() {
+        let mut app = App::new(Arc::new(Mutex::new(VecDeque::new())));
+        app.diagnostics = Some(DiagnosticsDialog {
+            run: 2,
+            results: Vec::new(),
+            running: true,
+            timings: Vec::new(),
+            scroll: 0,
+            notice: None,
+        });
+        let check = |name: &str| CheckResult {
+            name: name.to_string(),
+            device: None,
+            outcome: Outcome::Pass,
+            detail: String::new(),
+            suggestion: None,
+        };
+
+        app.update(AppEvent::Diagnostics { run: 1, message: DiagnosticsMessage::Check(check("stale")) });
+        app.update(AppEvent::Diagnostics { run: 2, message: DiagnosticsMessage::Check(check("SSDP replies")) });
+        app.update(AppEvent::Diagnostics { run: 1, message: DiagnosticsMessage::Done });
+        let dialog = app.diagnostics.as_ref().unwrap();
+        assert_eq!(dialog.results.len(), 1);
+        assert!(dialog.running);
+
+        app.update(AppEvent::Diagnostics { run: 2, message: DiagnosticsMessage::Done });
+        assert!(!app.diagnostics.as_ref().unwrap().running);
     }
 }
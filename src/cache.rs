@@ -0,0 +1,527 @@
+// What MOP has seen before, kept in an SQLite file in the user's cache
+// directory: the servers it discovered and the container listings browsed
+// on them. Known servers are listed before discovery gets to them, and a
+// server that is off or out of reach can still be looked through as it was.
+//
+// Servers are keyed by UDN when the description had one and by location
+// otherwise; listings by server key and ObjectID, and the title paths that
+// lead to containers (for `mop browse nas /Music/...`) by server key.
+// One more row holds where the TUI was when it last quit, and each server
+// has the listing filters typed in it, for recalling them, and the variant
+// of a file that played when the one picked for it didn't, and the
+// ContainerUpdateID each container had when it was last opened.
+// Secrets stay out of the file: device settings from `[servers]` aren't
+// stored, and tokens in item URLs are blanked on the way in and filled from
+// the config on the way out.
+
+use crate::quality::Worked;
+use crate::upnp::PlexServer;
+use mop_core::DirectoryItem;
+use mop_core::container_ids::ContainerIds;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Bumped when the tables change. It's only a cache: an older layout is
+/// dropped, not migrated.
+const SCHEMA_VERSION: i64 = 4;
+
+/// Query parameters that carry a server's token or API key.
+const SECRET_PARAMS: [&str; 2] = ["X-Plex-Token", "api_key"];
+
+/// Filter queries kept per server.
+const SEARCHES_KEPT: usize = 50;
+
+pub struct Cache {
+    db: Connection,
+}
+
+/// A container as it was last browsed.
+#[derive(Debug)]
+pub struct CachedListing {
+    pub items: Vec<DirectoryItem>,
+    pub total: Option<usize>,
+    /// Unix time of the browse.
+    pub fetched_at: i64,
+}
+
+/// The server and folder open when the TUI last quit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastLocation {
+    /// `server_key` of the server.
+    pub server: String,
+    /// Container titles from the root down.
+    pub path: Vec<String>,
+    /// ObjectID of each container in `path`.
+    pub object_ids: Vec<String>,
+}
+
+impl Cache {
+    /// Open (or create) `mop/cache.sqlite` in the user's cache directory.
+    pub fn open_default() -> Result<Self, String> {
+        let dir = dirs::cache_dir()
+            .ok_or_else(|| "Could not find cache directory".to_string())?
+            .join("mop");
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache directory: {}", e))?;
+        Self::open(&dir.join("cache.sqlite"))
+    }
+
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let db = Connection::open(path).map_err(|e| format!("Failed to open cache {}: {}", path.display(), e))?;
+        Self::init(db)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self, String> {
+        Self::init(Connection::open_in_memory().map_err(|e| e.to_string())?)
+    }
+
+    fn init(db: Connection) -> Result<Self, String> {
+        let version: i64 = db
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| format!("Failed to read cache: {}", e))?;
+        if version != SCHEMA_VERSION {
+            db.execute_batch(&format!(
+                "DROP TABLE IF EXISTS devices;
+                 DROP TABLE IF EXISTS listings;
+                 DROP TABLE IF EXISTS container_paths;
+                 DROP TABLE IF EXISTS last_location;
+                 DROP TABLE IF EXISTS searches;
+                 DROP TABLE IF EXISTS variants;
+                 DROP TABLE IF EXISTS container_updates;
+                 PRAGMA user_version = {};",
+                SCHEMA_VERSION
+            ))
+            .map_err(|e| format!("Failed to reset cache: {}", e))?;
+        }
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS devices (
+                 key TEXT PRIMARY KEY,
+                 device TEXT NOT NULL,
+                 seen_at INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS listings (
+                 server TEXT NOT NULL,
+                 object_id TEXT NOT NULL,
+                 items TEXT NOT NULL,
+                 total INTEGER,
+                 fetched_at INTEGER NOT NULL,
+                 PRIMARY KEY (server, object_id)
+             );
+             CREATE TABLE IF NOT EXISTS container_paths (
+                 server TEXT NOT NULL,
+                 path TEXT NOT NULL,
+                 object_id TEXT NOT NULL,
+                 recency INTEGER NOT NULL,
+                 PRIMARY KEY (server, path)
+             );
+             CREATE TABLE IF NOT EXISTS last_location (
+                 id INTEGER PRIMARY KEY CHECK (id = 0),
+                 server TEXT NOT NULL,
+                 path TEXT NOT NULL,
+                 object_ids TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS searches (
+                 server TEXT NOT NULL,
+                 query TEXT NOT NULL,
+                 PRIMARY KEY (server, query)
+             );
+             CREATE TABLE IF NOT EXISTS variants (
+                 server TEXT NOT NULL,
+                 failed TEXT NOT NULL,
+                 variant TEXT NOT NULL,
+                 relayed INTEGER NOT NULL,
+                 PRIMARY KEY (server, failed)
+             );
+             CREATE TABLE IF NOT EXISTS container_updates (
+                 server TEXT NOT NULL,
+                 object_id TEXT NOT NULL,
+                 seen INTEGER NOT NULL,
+                 PRIMARY KEY (server, object_id)
+             );",
+        )
+        .map_err(|e| format!("Failed to set up cache: {}", e))?;
+        Ok(Self { db })
+    }
+
+    /// Remember a discovered server, as discovery described it.
+    pub fn store_device(&self, device: &PlexServer) -> Result<(), String> {
+        let discovered = PlexServer {
+            token: None,
+            api_key: None,
+            tls: Default::default(),
+            credentials: None,
+            bypass_proxy: false,
+            headers: Default::default(),
+            ..device.clone()
+        };
+        let json = serde_json::to_string(&discovered).map_err(|e| e.to_string())?;
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO devices (key, device, seen_at) VALUES (?1, ?2, ?3)",
+                params![server_key(device), json, now()],
+            )
+            .map_err(|e| format!("Failed to cache {}: {}", device.name, e))?;
+        Ok(())
+    }
+
+    /// Every server seen before with the Unix time it was last seen, most
+    /// recently seen first.
+    pub fn devices_seen(&self) -> Result<Vec<(PlexServer, i64)>, String> {
+        let mut statement = self
+            .db
+            .prepare("SELECT device, seen_at FROM devices ORDER BY seen_at DESC")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+            .map_err(|e| e.to_string())?;
+        let mut devices = Vec::new();
+        for row in rows {
+            let (json, seen_at) = row.map_err(|e| e.to_string())?;
+            match serde_json::from_str(&json) {
+                Ok(device) => devices.push((device, seen_at)),
+                Err(e) => tracing::warn!(target: "mop::app", "Skipping unreadable cached device: {}", e),
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Replace what's kept for container `object_id` on `server`.
+    pub fn store_listing(
+        &self,
+        server: &PlexServer,
+        object_id: &str,
+        items: &[DirectoryItem],
+        total: Option<usize>,
+    ) -> Result<(), String> {
+        let items: Vec<DirectoryItem> = items
+            .iter()
+            .map(|item| DirectoryItem { url: item.url.as_deref().map(blank_secrets), ..item.clone() })
+            .collect();
+        let items = serde_json::to_string(&items).map_err(|e| e.to_string())?;
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO listings (server, object_id, items, total, fetched_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![server_key(server), object_id, items, total.map(|t| t as i64), now()],
+            )
+            .map_err(|e| format!("Failed to cache listing: {}", e))?;
+        Ok(())
+    }
+
+    /// The last listing of container `object_id` on `server`, with item URLs
+    /// carrying the server's current token.
+    pub fn listing(&self, server: &PlexServer, object_id: &str) -> Result<Option<CachedListing>, String> {
+        let row = self
+            .db
+            .query_row(
+                "SELECT items, total, fetched_at FROM listings WHERE server = ?1 AND object_id = ?2",
+                params![server_key(server), object_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<i64>>(1)?,
+                        row.get::<_, i64>(2)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((items, total, fetched_at)) = row else { return Ok(None) };
+        let mut items: Vec<DirectoryItem> = serde_json::from_str(&items).map_err(|e| e.to_string())?;
+        for item in &mut items {
+            item.url = item.url.take().map(|url| fill_secrets(&url, server));
+        }
+        Ok(Some(CachedListing {
+            items,
+            total: total.map(|t| t as usize),
+            fetched_at,
+        }))
+    }
+
+    /// The container paths learned on `server` in earlier runs, in the
+    /// order they were last used.
+    pub fn container_ids(&self, server: &PlexServer) -> Result<ContainerIds, String> {
+        let mut statement = self
+            .db
+            .prepare("SELECT path, object_id FROM container_paths WHERE server = ?1 ORDER BY recency")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![server_key(server)], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| e.to_string())?;
+        let mut ids = ContainerIds::default();
+        for row in rows {
+            let (path, object_id) = row.map_err(|e| e.to_string())?;
+            if let Ok(path) = serde_json::from_str(&path) {
+                ids.insert(path, object_id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Replace the container paths kept for `server` with `ids`.
+    pub fn store_container_ids(&self, server: &PlexServer, ids: &ContainerIds) -> Result<(), String> {
+        let key = server_key(server);
+        let store = || -> rusqlite::Result<()> {
+            let transaction = self.db.unchecked_transaction()?;
+            transaction.execute("DELETE FROM container_paths WHERE server = ?1", params![key])?;
+            for (recency, (path, object_id)) in ids.entries().into_iter().enumerate() {
+                let path = serde_json::to_string(path).unwrap_or_default();
+                transaction.execute(
+                    "INSERT INTO container_paths (server, path, object_id, recency) VALUES (?1, ?2, ?3, ?4)",
+                    params![key, path, object_id, recency as i64],
+                )?;
+            }
+            transaction.commit()
+        };
+        store().map_err(|e| format!("Failed to cache folder ids: {}", e))
+    }
+
+    /// `server` renumbered its containers: forget the listings and folder
+    /// IDs kept for it, all of which may now be wrong, except for `path`'s
+    /// fresh `object_ids`.
+    pub fn renumbered(&self, server: &PlexServer, path: &[String], object_ids: &[String]) -> Result<(), String> {
+        let key = server_key(server);
+        self.db
+            .execute("DELETE FROM listings WHERE server = ?1", params![key])
+            .map_err(|e| format!("Failed to forget renumbered listings: {}", e))?;
+        let mut ids = ContainerIds::default();
+        for (depth, object_id) in object_ids.iter().enumerate().take(path.len()) {
+            ids.insert(path[..=depth].to_vec(), object_id.clone());
+        }
+        self.store_container_ids(server, &ids)
+    }
+
+    /// Where the TUI was when it last quit, if it was in a server.
+    pub fn last_location(&self) -> Result<Option<LastLocation>, String> {
+        let row = self
+            .db
+            .query_row("SELECT server, path, object_ids FROM last_location WHERE id = 0", [], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((server, path, object_ids)) = row else { return Ok(None) };
+        let path: Vec<String> = serde_json::from_str(&path).map_err(|e| e.to_string())?;
+        let object_ids: Vec<String> = serde_json::from_str(&object_ids).map_err(|e| e.to_string())?;
+        if path.len() != object_ids.len() {
+            return Err("Cached last location is inconsistent".to_string());
+        }
+        Ok(Some(LastLocation { server, path, object_ids }))
+    }
+
+    /// Keep `location` for the next start; `None` forgets the last one.
+    pub fn store_last_location(&self, location: Option<&LastLocation>) -> Result<(), String> {
+        let stored = match location {
+            Some(location) => self.db.execute(
+                "INSERT OR REPLACE INTO last_location (id, server, path, object_ids) VALUES (0, ?1, ?2, ?3)",
+                params![
+                    location.server,
+                    serde_json::to_string(&location.path).unwrap_or_default(),
+                    serde_json::to_string(&location.object_ids).unwrap_or_default()
+                ],
+            ),
+            None => self.db.execute("DELETE FROM last_location", []),
+        };
+        stored.map(|_| ()).map_err(|e| format!("Failed to cache last location: {}", e))
+    }
+
+    /// Filter queries used on `server`, the latest first.
+    pub fn searches(&self, server: &PlexServer) -> Result<Vec<String>, String> {
+        let read = || {
+            let mut statement = self.db.prepare("SELECT query FROM searches WHERE server = ?1 ORDER BY rowid DESC")?;
+            let rows = statement.query_map(params![server_key(server)], |row| row.get(0))?;
+            rows.collect::<Result<Vec<String>, _>>()
+        };
+        read().map_err(|e| format!("Failed to read search history: {}", e))
+    }
+
+    /// Note `query` as the latest used on `server`, forgetting the oldest
+    /// beyond `SEARCHES_KEPT`.
+    pub fn store_search(&self, server: &PlexServer, query: &str) -> Result<(), String> {
+        let key = server_key(server);
+        // Replacing the row gives it the highest rowid, which orders the history
+        let store = || -> rusqlite::Result<()> {
+            self.db.execute("INSERT OR REPLACE INTO searches (server, query) VALUES (?1, ?2)", params![key, query])?;
+            self.db.execute(
+                "DELETE FROM searches WHERE server = ?1 AND rowid NOT IN
+                     (SELECT rowid FROM searches WHERE server = ?1 ORDER BY rowid DESC LIMIT ?2)",
+                params![key, SEARCHES_KEPT as i64],
+            )?;
+            Ok(())
+        };
+        store().map_err(|e| format!("Failed to save search history: {}", e))
+    }
+
+    /// The ContainerUpdateID of each container on `server` as it was when
+    /// last opened, by ObjectID.
+    pub fn containers_seen(&self, server: &PlexServer) -> Result<HashMap<String, u32>, String> {
+        let read = || {
+            let mut statement = self.db.prepare("SELECT object_id, seen FROM container_updates WHERE server = ?1")?;
+            let rows = statement.query_map(params![server_key(server)], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<HashMap<String, u32>, _>>()
+        };
+        read().map_err(|e| format!("Failed to read container updates: {}", e))
+    }
+
+    pub fn store_container_seen(&self, server: &PlexServer, object_id: &str, seen: u32) -> Result<(), String> {
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO container_updates (server, object_id, seen) VALUES (?1, ?2, ?3)",
+                params![server_key(server), object_id, seen],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save container update: {}", e))
+    }
+
+    /// The variants that last played on each server in place of ones
+    /// picked that didn't, by server key.
+    pub fn worked_variants(&self) -> Result<HashMap<String, Vec<Worked>>, String> {
+        let read = || {
+            let mut statement = self.db.prepare("SELECT server, failed, variant, relayed FROM variants")?;
+            let rows = statement.query_map([], |row| {
+                let worked = Worked { failed: row.get(1)?, variant: row.get(2)?, relayed: row.get(3)? };
+                Ok((row.get::<_, String>(0)?, worked))
+            })?;
+            let mut worked: HashMap<String, Vec<Worked>> = HashMap::new();
+            for row in rows {
+                let (server, variant) = row?;
+                worked.entry(server).or_default().push(variant);
+            }
+            Ok::<_, rusqlite::Error>(worked)
+        };
+        read().map_err(|e| format!("Failed to read the variants that played: {}", e))
+    }
+
+    pub fn store_worked_variant(&self, server: &str, worked: &Worked) -> Result<(), String> {
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO variants (server, failed, variant, relayed) VALUES (?1, ?2, ?3, ?4)",
+                params![server, worked.failed, worked.variant, worked.relayed],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save the variant that played: {}", e))
+    }
+}
+
+/// What a server is stored under: its UDN, which survives address changes,
+/// or its location when it has none.
+pub fn server_key(server: &PlexServer) -> String {
+    server.udn.clone().unwrap_or_else(|| server.location.clone())
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Empty the value of any token parameter, keeping its name so
+/// `fill_secrets` knows where one belongs.
+fn blank_secrets(url: &str) -> String {
+    rewrite_secrets(url, |_| Some(String::new()))
+}
+
+/// `url` with its token, API key and password masked, for sharing.
+pub fn mask_secrets(url: &str) -> String {
+    crate::auth::redact(&rewrite_secrets(url, |_| Some("***".to_string())))
+}
+
+fn fill_secrets(url: &str, server: &PlexServer) -> String {
+    rewrite_secrets(url, |name| match name {
+        "X-Plex-Token" => server.token.clone(),
+        _ => server.api_key.clone(),
+    })
+}
+
+/// Set each secret parameter to `value(name)`, dropping it on `None`.
+fn rewrite_secrets(url: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else { return url.to_string() };
+    if !parsed.query_pairs().any(|(name, _)| SECRET_PARAMS.contains(&name.as_ref())) {
+        return url.to_string();
+    }
+    let pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter_map(|(name, current)| {
+            if SECRET_PARAMS.contains(&name.as_ref()) {
+                value(&name).map(|secret| (name.into_owned(), secret))
+            } else {
+                Some((name.into_owned(), current.into_owned()))
+            }
+        })
+        .collect();
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    parsed.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listings_come_back_without_the_stored_token() {
+        let cache = Cache::in_memory().unwrap();
+        let mut server: PlexServer = serde_json::from_value(serde_json::json!({
+            "name": "Plex",
+            "location": "http://192.168.1.5:32469/desc.xml",
+            "base_url": "http://192.168.1.5:32469",
+            "device_client": null,
+            "content_directory_url": null,
+            "udn": "uuid:1234",
+            "token": "old",
+        }))
+        .unwrap();
+        cache.store_device(&server).unwrap();
+        let item = DirectoryItem {
+            id: Some("12$1".to_string()),
+            name: "Film.mkv".to_string(),
+            url: Some("http://192.168.1.5:32400/file.mkv?X-Plex-Token=old".to_string()),
+            ..Default::default()
+        };
+        cache.store_listing(&server, "12", &[item], Some(1)).unwrap();
+
+        let devices = cache.devices_seen().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].0.token, None);
+
+        server.token = Some("new".to_string());
+        let listing = cache.listing(&server, "12").unwrap().unwrap();
+        assert_eq!(listing.items[0].url.as_deref(), Some("http://192.168.1.5:32400/file.mkv?X-Plex-Token=new"));
+        assert_eq!(listing.total, Some(1));
+        assert!(cache.listing(&server, "0").unwrap().is_none());
+
+        let mut ids = ContainerIds::default();
+        ids.insert(vec!["Films".to_string()], "12".to_string());
+        ids.insert(vec!["Music".to_string()], "13".to_string());
+        cache.store_container_ids(&server, &ids).unwrap();
+        assert_eq!(cache.container_ids(&server).unwrap().get(&["Films".to_string()]).as_deref(), Some("12"));
+
+        // After a rescan only the path found again is kept
+        let path = ["Films".to_string(), "Heat".to_string()];
+        cache.renumbered(&server, &path, &["40".to_string(), "41".to_string()]).unwrap();
+        assert!(cache.listing(&server, "12").unwrap().is_none());
+        let mut ids = cache.container_ids(&server).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids.get(&path[..1]).as_deref(), Some("40"));
+        assert_eq!(ids.get(&path).as_deref(), Some("41"));
+
+        assert_eq!(cache.last_location().unwrap(), None);
+        let location = LastLocation {
+            server: server_key(&server),
+            path: vec!["Films".to_string()],
+            object_ids: vec!["12".to_string()],
+        };
+        cache.store_last_location(Some(&location)).unwrap();
+        assert_eq!(cache.last_location().unwrap(), Some(location));
+        cache.store_last_location(None).unwrap();
+        assert_eq!(cache.last_location().unwrap(), None);
+
+        for query in ["heat", "ronin", "heat"] {
+            cache.store_search(&server, query).unwrap();
+        }
+        assert_eq!(cache.searches(&server).unwrap(), ["heat", "ronin"]);
+        for n in 0..SEARCHES_KEPT {
+            cache.store_search(&server, &n.to_string()).unwrap();
+        }
+        let searches = cache.searches(&server).unwrap();
+        assert_eq!((searches.len(), searches[0].as_str()), (SEARCHES_KEPT, "49"));
+    }
+}
@@ -0,0 +1,436 @@
+// Sending the selected item to another device instead of the local player.
+// Every backend's receivers show up as `Target`s in one picker; the picker
+// doesn't care how a target is reached once playback has started.
+
+use crate::airplay::{self, AirPlayDevice};
+use crate::cast_queue::Queued;
+use crate::sonos::{self, SonosZone};
+use crate::upnp_avtransport::{self, Renderer};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// How long the picker listens for receivers.
+const SEARCH_TIME: Duration = Duration::from_secs(3);
+/// Step for the seek keys in the cast dialog.
+pub const SEEK_STEP: f64 = 30.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    AirPlay(AirPlayDevice),
+    Sonos(SonosZone),
+    Renderer(Renderer),
+}
+
+impl Target {
+    pub fn name(&self) -> &str {
+        match self {
+            Target::AirPlay(device) => &device.name,
+            Target::Sonos(zone) => &zone.name,
+            Target::Renderer(renderer) => &renderer.name,
+        }
+    }
+
+    /// The UPnP device id without its "uuid:" prefix, for `[cast] default_renderer`.
+    pub fn udn(&self) -> Option<&str> {
+        match self {
+            Target::AirPlay(_) => None,
+            Target::Sonos(zone) => Some(&zone.uuid),
+            Target::Renderer(renderer) => Some(renderer.udn.trim_start_matches("uuid:")),
+        }
+    }
+
+    pub fn matches_udn(&self, udn: &str) -> bool {
+        self.udn().is_some_and(|own| own.eq_ignore_ascii_case(udn.trim().trim_start_matches("uuid:")))
+    }
+
+    /// Whether the target keeps a queue that items can be added to.
+    pub fn has_queue(&self) -> bool {
+        matches!(self, Target::Sonos(_))
+    }
+
+    /// Shown next to the name in the picker.
+    pub fn kind(&self) -> String {
+        match self {
+            Target::AirPlay(device) => match &device.model {
+                Some(model) => format!("AirPlay, {}", model),
+                None => "AirPlay".to_string(),
+            },
+            Target::Sonos(_) => "Sonos".to_string(),
+            Target::Renderer(_) => "DLNA renderer".to_string(),
+        }
+    }
+}
+
+/// Look for receivers of every backend in the background. The result comes
+/// as one list once all backends are done.
+pub fn discover_targets() -> Receiver<Result<Vec<Target>, String>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let backends: Vec<std::thread::JoinHandle<Result<Vec<Target>, String>>> = vec![
+            std::thread::spawn(|| upnp_avtransport::discover(SEARCH_TIME).map(|found| found.into_iter().map(Target::Renderer).collect())),
+            std::thread::spawn(|| sonos::discover(SEARCH_TIME).map(|found| found.into_iter().map(Target::Sonos).collect())),
+            std::thread::spawn(|| airplay::discover(SEARCH_TIME).map(|found| found.into_iter().map(Target::AirPlay).collect())),
+        ];
+
+        // One backend failing shouldn't hide the others' receivers
+        let mut targets = Vec::new();
+        let mut errors = Vec::new();
+        for backend in backends {
+            match backend.join() {
+                Ok(Ok(found)) => targets.extend(found),
+                Ok(Err(e)) => errors.push(e),
+                Err(_) => errors.push("Receiver discovery crashed".to_string()),
+            }
+        }
+        let result = if targets.is_empty() && !errors.is_empty() { Err(errors.join("; ")) } else { Ok(targets) };
+        tx.send(result).ok();
+    });
+    rx
+}
+
+/// What `target` says it plays, as GetProtocolInfo Sink entries; None for
+/// receivers other than renderers, and renderers that won't say.
+pub fn sink_protocols(target: &Target) -> Option<Vec<String>> {
+    let Target::Renderer(renderer) = target else { return None };
+    match upnp_avtransport::Session::new(renderer.clone()).and_then(|session| session.sink_protocols()) {
+        Ok(sink) => Some(sink),
+        Err(e) => {
+            tracing::debug!(target: "mop::net", "No protocol info from {}: {}", renderer.name, e);
+            None
+        }
+    }
+}
+
+/// Why `target`, which plays what `sink` lists, may not play an item with
+/// `protocol_info`, if it says so.
+pub fn compatibility_warning(target: &Target, sink: &[String], protocol_info: Option<&str>) -> Option<String> {
+    let mime = protocol_info.and_then(upnp_avtransport::protocol_mime)?;
+    (!upnp_avtransport::accepts(sink, mime)).then(|| format!("{} doesn't list {} as playable", target.name(), mime))
+}
+
+/// What a command from the cast dialog came to, once the target answered.
+pub enum Control {
+    /// Whether playback is paused now.
+    Paused(Result<bool, String>),
+    /// (duration, position) in seconds after a seek.
+    Seeked(Result<(f64, f64), String>),
+    /// This many files of `item` went into `target`'s queue, or didn't.
+    Queued {
+        target: String,
+        item: String,
+        result: Result<usize, String>,
+    },
+    /// Playback on `target` was stopped as the dialog closed, or wasn't.
+    Stopped {
+        target: String,
+        result: Result<(), String>,
+    },
+}
+
+/// Append `files`, (URL, title) pairs, to the target's queue without
+/// touching what is playing, returning how many went in. A failure partway
+/// says how many had.
+pub fn enqueue(target: &Target, files: &[(String, String)]) -> Result<usize, String> {
+    let Target::Sonos(zone) = target else { return Err(format!("{} has no queue", target.name())) };
+    let session = sonos::Session::new(zone.clone())?;
+    for (added, (url, title)) in files.iter().enumerate() {
+        if let Err(e) = session.enqueue(url, title, false) {
+            return Err(match added {
+                0 => e,
+                _ => format!("Added {} of {} files, then: {}", added, files.len(), e),
+            });
+        }
+    }
+    Ok(files.len())
+}
+
+pub enum Session {
+    AirPlay(airplay::Session),
+    Sonos(sonos::Session),
+    Renderer(upnp_avtransport::Session),
+    /// The same stream on several targets at once.
+    Group(Group),
+}
+
+/// Targets cast to together, party mode for whole-home audio.
+pub struct Group {
+    name: String,
+    pub members: Vec<Member>,
+}
+
+pub struct Member {
+    pub name: String,
+    /// Playback on this target, or why it didn't start.
+    pub session: Result<Session, String>,
+}
+
+impl Group {
+    fn playing(&self) -> impl Iterator<Item = &Session> {
+        self.members.iter().filter_map(|member| member.session.as_ref().ok())
+    }
+
+    /// Run `action` on every member that's playing, failing with what went
+    /// wrong on any of them.
+    fn each(&self, action: impl Fn(&Session) -> Result<(), String>) -> Result<(), String> {
+        let errors: Vec<String> = self.playing().filter_map(|session| action(session).err()).collect();
+        if errors.is_empty() { Ok(()) } else { Err(errors.join("; ")) }
+    }
+}
+
+impl Session {
+    /// Start playing `url` on `target`. `protocol_info` is the item's DLNA
+    /// resource description, if the server gave one.
+    pub fn start(target: &Target, url: &str, title: &str, protocol_info: Option<&str>) -> Result<Self, String> {
+        match target {
+            Target::Renderer(renderer) => {
+                let session = upnp_avtransport::Session::new(renderer.clone())?;
+                session.play(url, title, protocol_info.unwrap_or("http-get:*:*:*"))?;
+                Ok(Session::Renderer(session))
+            }
+            Target::AirPlay(device) => airplay::Session::play(device.clone(), url).map(Session::AirPlay),
+            Target::Sonos(zone) => sonos::Session::play_now(zone.clone(), url, title).map(Session::Sonos),
+        }
+    }
+
+    /// Start `url` on all of `targets` together. Renderers are handed the
+    /// URL one by one, then every target is told to play at the same time,
+    /// so they start as close together as the network allows. Fails only if
+    /// none of them started.
+    pub fn start_group(
+        targets: &[Target],
+        url: &str,
+        title: &str,
+        protocol_info: Option<&str>,
+    ) -> Result<Self, String> {
+        let protocol_info = protocol_info.unwrap_or("http-get:*:*:*");
+        let loaded: Vec<Option<Result<upnp_avtransport::Session, String>>> = targets
+            .iter()
+            .map(|target| match target {
+                Target::Renderer(renderer) => Some(upnp_avtransport::Session::new(renderer.clone()).and_then(|session| {
+                    session.load(url, title, protocol_info)?;
+                    Ok(session)
+                })),
+                _ => None,
+            })
+            .collect();
+
+        let members: Vec<Member> = std::thread::scope(|scope| {
+            let started: Vec<_> = targets
+                .iter()
+                .zip(loaded)
+                .map(|(target, loaded)| {
+                    scope.spawn(move || match (target, loaded) {
+                        (_, Some(loaded)) => loaded.and_then(|session| {
+                            session.set_paused(false)?;
+                            Ok(Session::Renderer(session))
+                        }),
+                        (target, None) => Session::start(target, url, title, Some(protocol_info)),
+                    })
+                })
+                .collect();
+            targets
+                .iter()
+                .zip(started)
+                .map(|(target, started)| Member {
+                    name: target.name().to_string(),
+                    session: started.join().unwrap_or_else(|_| Err("Casting crashed".to_string())),
+                })
+                .collect()
+        });
+
+        let name = members.iter().map(|member| member.name.as_str()).collect::<Vec<_>>().join(", ");
+        let group = Group { name, members };
+        if group.playing().next().is_none() {
+            let errors: Vec<String> = group.members.into_iter().filter_map(|member| member.session.err()).collect();
+            return Err(errors.join("; "));
+        }
+        tracing::info!(target: "mop::net", "Group: playing {} on {}", url, group.name);
+        Ok(Session::Group(group))
+    }
+
+    /// Start the first of `queue` on `target` and line the rest up after
+    /// it: in a Sonos's own queue, or, on a renderer, by following it with
+    /// [`crate::cast_queue::follow`] once this returns. AirPlay receivers
+    /// take one stream at a time.
+    pub fn start_queue(target: &Target, queue: &[Queued]) -> Result<Self, String> {
+        let first = queue.first().ok_or("Nothing to cast")?;
+        match target {
+            Target::Renderer(_) => Session::start(target, &first.url, &first.title, first.protocol_info.as_deref()),
+            Target::Sonos(zone) => {
+                let session = sonos::Session::play_now(zone.clone(), &first.url, &first.title)?;
+                // Each goes straight after the one playing, so the last goes in first
+                for item in queue[1..].iter().rev() {
+                    session.enqueue(&item.url, &item.title, true)?;
+                }
+                Ok(Session::Sonos(session))
+            }
+            Target::AirPlay(device) => Err(format!("{} plays one file at a time", device.name)),
+        }
+    }
+
+    /// The renderer playing, for a session on one.
+    pub fn renderer(&self) -> Option<&Renderer> {
+        match self {
+            Session::Renderer(session) => Some(session.renderer()),
+            _ => None,
+        }
+    }
+
+    pub fn target_name(&self) -> &str {
+        match self {
+            Session::AirPlay(session) => &session.device().name,
+            Session::Sonos(session) => &session.zone().name,
+            Session::Renderer(session) => &session.renderer().name,
+            Session::Group(group) => &group.name,
+        }
+    }
+
+    pub fn set_paused(&self, paused: bool) -> Result<(), String> {
+        match self {
+            Session::AirPlay(session) => session.set_rate(if paused { 0.0 } else { 1.0 }),
+            Session::Sonos(session) => session.set_paused(paused),
+            Session::Renderer(session) => session.set_paused(paused),
+            Session::Group(group) => group.each(|session| session.set_paused(paused)),
+        }
+    }
+
+    /// Move the playhead by `delta` seconds, returning (duration, position).
+    /// A group goes by its first member, and all of it seeks to the same
+    /// place, which also brings members that drifted back together.
+    pub fn seek_by(&self, delta: f64) -> Result<(f64, f64), String> {
+        let (duration, position) = self.progress()?;
+        let target = (position + delta).clamp(0.0, duration.max(0.0));
+        self.seek(target)?;
+        Ok((duration, target))
+    }
+
+    fn progress(&self) -> Result<(f64, f64), String> {
+        match self {
+            Session::AirPlay(session) => session.progress(),
+            Session::Sonos(session) => session.progress(),
+            Session::Renderer(session) => session.progress(),
+            Session::Group(group) => group.playing().next().ok_or("Nothing in the group is playing")?.progress(),
+        }
+    }
+
+    fn seek(&self, position: f64) -> Result<(), String> {
+        match self {
+            Session::AirPlay(session) => session.seek(position),
+            Session::Sonos(session) => session.seek(position),
+            Session::Renderer(session) => session.seek(position),
+            Session::Group(group) => group.each(|session| session.seek(position)),
+        }
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        match self {
+            Session::AirPlay(session) => session.stop(),
+            Session::Sonos(session) => session.stop(),
+            Session::Renderer(session) => session.stop(),
+            Session::Group(group) => group.each(Session::stop),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// A renderer that answers every SOAP action, noting which it was sent.
+    fn stub_renderer(name: &'static str, log: Arc<Mutex<Vec<String>>>) -> Target {
+        let (address, _) = stub_service(name, log);
+        Target::Renderer(Renderer::at(name, &format!("http://{}/avt", address)))
+    }
+
+    /// Answers every SOAP action on any path, noting which it was sent, and
+    /// counts the connections made to it.
+    fn stub_service(name: &'static str, log: Arc<Mutex<Vec<String>>>) -> (std::net::SocketAddr, Arc<AtomicUsize>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counted = connections.clone();
+        std::thread::spawn(move || {
+            for connection in listener.incoming().flatten() {
+                counted.fetch_add(1, Ordering::SeqCst);
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(connection.try_clone().unwrap());
+                    let mut connection = connection;
+                    loop {
+                        let (mut action, mut length) = (String::new(), 0);
+                        loop {
+                            let mut line = String::new();
+                            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            if line.trim_end().is_empty() {
+                                break;
+                            }
+                            // The request line has no colon, so it goes with the headers that are ignored
+                            let (header, value) = line.trim_end().split_once(':').unwrap_or_default();
+                            match header.to_lowercase().as_str() {
+                                "content-length" => length = value.trim().parse().unwrap(),
+                                "soapaction" => action = value.rsplit('#').next().unwrap().replace('"', ""),
+                                _ => {}
+                            }
+                        }
+                        if reader.by_ref().take(length).read_to_end(&mut Vec::new()).is_err() {
+                            return;
+                        }
+                        log.lock().unwrap().push(format!("{} {}", name, action));
+                        // Where Sonos says an enqueued track went; other actions ignore it
+                        let body =
+                            "<s:Envelope><s:Body><FirstTrackNumberEnqueued>1</FirstTrackNumberEnqueued></s:Body></s:Envelope>";
+                        let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+                        if connection.write_all(response.as_bytes()).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (address, connections)
+    }
+
+    #[test]
+    fn a_folder_goes_into_a_sonos_queue_over_one_session() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (address, connections) = stub_service("den", log.clone());
+        let base_url = format!("http://{}", address);
+        let zone = SonosZone { name: "Den".to_string(), base_url, uuid: "RINCON_1".to_string() };
+        let files: Vec<(String, String)> =
+            (1..=3).map(|n| (format!("http://192.168.1.5:8200/{}.flac", n), format!("Track {}", n))).collect();
+        assert_eq!(enqueue(&Target::Sonos(zone), &files), Ok(3));
+        assert_eq!(*log.lock().unwrap(), ["den AddURIToQueue"; 3]);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn groups_load_every_renderer_before_playing_any() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let targets = [
+            stub_renderer("kitchen", log.clone()),
+            Target::Renderer(Renderer::at("garage", &format!("http://{}/avt", closed))),
+            stub_renderer("lounge", log.clone()),
+        ];
+        let url = "http://192.168.1.5:8200/MediaItems/7.flac";
+        let Ok(Session::Group(group)) = Session::start_group(&targets, url, "Blue Train", None) else {
+            panic!("the group didn't start");
+        };
+        assert_eq!(group.name, "kitchen, garage, lounge");
+        let started: Vec<bool> = group.members.iter().map(|member| member.session.is_ok()).collect();
+        assert_eq!(started, [true, false, true]);
+
+        let log = log.lock().unwrap().clone();
+        assert_eq!(&log[..2], ["kitchen SetAVTransportURI", "lounge SetAVTransportURI"]);
+        let mut plays = log[2..].to_vec();
+        plays.sort();
+        assert_eq!(plays, ["kitchen Play", "lounge Play"]);
+
+        assert!(Session::start_group(&targets[1..2], url, "Blue Train", None).is_err());
+    }
+}
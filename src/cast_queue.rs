@@ -0,0 +1,223 @@
+// Casting a folder to a DLNA renderer, which plays one URI at a time. While
+// a file plays, the next is handed over with SetNextAVTransportURI, so a
+// renderer that supports it goes on without a gap and its track URI says
+// when it did. Renderers that don't are given the next file when they
+// report STOPPED. What they report comes from their AVTransport events, or,
+// from renderers that don't event, from asking every couple of seconds.
+
+use crate::upnp_avtransport::{Renderer, Session, Transport};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::time::Duration;
+
+/// How often a renderer without events is asked what it's doing.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How long a renderer that took the next file gets to start it after
+/// reporting STOPPED, before it's told to.
+const GAPLESS_GRACE: Duration = Duration::from_millis(1500);
+
+/// One file of a queue cast to a receiver.
+#[derive(Debug, Clone)]
+pub struct Queued {
+    pub url: String,
+    pub title: String,
+    pub protocol_info: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// The renderer is on the queue's file at this index.
+    Playing(usize),
+    /// The last file finished.
+    Finished,
+    /// The queue stopped going on.
+    Failed(String),
+}
+
+/// Keeps a renderer going through a queue until this is dropped.
+pub struct Follow {
+    _stop: Sender<()>,
+}
+
+/// Follow `renderer` through `queue`, whose first file it was just told
+/// to play, handing it each next one and reporting to `messages`.
+pub fn follow(renderer: Renderer, queue: Vec<Queued>, messages: Sender<Message>) -> Follow {
+    let (stop, stopped) = mpsc::channel();
+    std::thread::spawn(move || {
+        let name = renderer.name.clone();
+        if let Err(e) = run(renderer, &queue, &messages, stopped) {
+            tracing::warn!(target: "mop::net", "The queue on {} stopped: {}", name, e);
+            let _ = messages.send(Message::Failed(e));
+        }
+    });
+    Follow { _stop: stop }
+}
+
+fn run(renderer: Renderer, queue: &[Queued], messages: &Sender<Message>, stopped: Receiver<()>) -> Result<(), String> {
+    let session = Session::new(renderer.clone())?;
+    let gapless = queue.get(1).is_some_and(|next| preload(&session, next));
+    tracing::info!(
+        target: "mop::net",
+        "Following {} through {} files, {}",
+        renderer.name,
+        queue.len(),
+        if gapless { "each preloaded" } else { "each started when the one before stops" }
+    );
+    let mut follower = Follower::new(queue.iter().map(|item| item.url.clone()).collect(), gapless);
+
+    let (updates, updated) = mpsc::channel();
+    let subscription = renderer.events.clone().and_then(|event_url| {
+        let client = crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)
+            .and_then(|builder| builder.timeout(Duration::from_secs(10)).build().map_err(|e| e.to_string()));
+        let subscribed = client.and_then(|client| {
+            crate::gena::Subscription::start(client, event_url, &renderer.name, move |_, body| {
+                let _ = updates.send(crate::upnp_avtransport::last_change(body));
+            })
+        });
+        subscribed.map_err(|e| tracing::info!(target: "mop::net", "Asking {} instead: {}", renderer.name, e)).ok()
+    });
+
+    loop {
+        let transport = if subscription.is_some() {
+            updated.recv_timeout(POLL_INTERVAL).unwrap_or_default()
+        } else {
+            match stopped.recv_timeout(POLL_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => query(&session),
+                _ => return Ok(()),
+            }
+        };
+        if !matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+            return Ok(());
+        }
+        // A renderer that took the next file may report STOPPED between the
+        // two before starting the next by itself
+        let transport = if follower.gapless && transport.state.as_deref() == Some("STOPPED") {
+            std::thread::sleep(GAPLESS_GRACE);
+            query(&session)
+        } else {
+            transport
+        };
+        for step in follower.update(&transport) {
+            match step {
+                Step::Preload(index) => follower.gapless = preload(&session, &queue[index]),
+                Step::Play(index) => {
+                    let item = &queue[index];
+                    session.play(&item.url, &item.title, item.protocol_info.as_deref().unwrap_or("http-get:*:*:*"))?;
+                }
+                Step::Playing(index) => {
+                    tracing::info!(target: "mop::net", "{} is on {}", renderer.name, queue[index].title);
+                    let _ = messages.send(Message::Playing(index));
+                }
+                Step::Finished => {
+                    let _ = messages.send(Message::Finished);
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Hand the renderer `next` to go on to; false if it won't take it.
+fn preload(session: &Session, next: &Queued) -> bool {
+    let protocol_info = next.protocol_info.as_deref().unwrap_or("http-get:*:*:*");
+    match session.load_next(&next.url, &next.title, protocol_info) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::debug!(target: "mop::net", "No SetNextAVTransportURI: {}", e);
+            false
+        }
+    }
+}
+
+fn query(session: &Session) -> Transport {
+    Transport { state: session.transport_state().ok(), uri: session.track_uri().ok() }
+}
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    Preload(usize),
+    Play(usize),
+    Playing(usize),
+    Finished,
+}
+
+/// Where the renderer is in the queue, from what it reports.
+struct Follower {
+    urls: Vec<String>,
+    current: usize,
+    /// The renderer took the next file and will go on to it by itself.
+    gapless: bool,
+    /// The current file has been seen playing, so STOPPED means it ended
+    /// rather than that it hasn't started yet.
+    started: bool,
+}
+
+impl Follower {
+    fn new(urls: Vec<String>, gapless: bool) -> Self {
+        Self { urls, current: 0, gapless, started: false }
+    }
+
+    fn update(&mut self, transport: &Transport) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let next = self.urls.get(self.current + 1);
+        if let Some(uri) = &transport.uri
+            && next.is_some_and(|next| next == uri.trim())
+        {
+            // Gone on to the file handed over
+            self.current += 1;
+            self.started = true;
+            steps.push(Step::Playing(self.current));
+            if self.gapless && self.current + 1 < self.urls.len() {
+                steps.push(Step::Preload(self.current + 1));
+            }
+            return steps;
+        }
+        match transport.state.as_deref() {
+            Some("PLAYING" | "PAUSED_PLAYBACK") => self.started = true,
+            Some("STOPPED" | "NO_MEDIA_PRESENT") if self.started => {
+                self.started = false;
+                if self.current + 1 < self.urls.len() {
+                    self.current += 1;
+                    steps.push(Step::Play(self.current));
+                    steps.push(Step::Playing(self.current));
+                    if self.gapless && self.current + 1 < self.urls.len() {
+                        steps.push(Step::Preload(self.current + 1));
+                    }
+                } else {
+                    steps.push(Step::Finished);
+                }
+            }
+            _ => {}
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport(state: Option<&str>, uri: Option<&str>) -> Transport {
+        Transport { state: state.map(str::to_string), uri: uri.map(str::to_string) }
+    }
+
+    #[test]
+    fn goes_on_by_the_renderer_s_track_uri_or_when_it_stops() {
+        let urls = ["http://nas/1.flac", "http://nas/2.flac", "http://nas/3.flac"].map(String::from).to_vec();
+
+        // Preloaded: the renderer moves on by itself and says so
+        let mut gapless = Follower::new(urls.clone(), true);
+        assert!(gapless.update(&transport(Some("PLAYING"), Some("http://nas/1.flac"))).is_empty());
+        let steps = gapless.update(&transport(Some("PLAYING"), Some("http://nas/2.flac")));
+        assert_eq!(steps, [Step::Playing(1), Step::Preload(2)]);
+        assert_eq!(gapless.update(&transport(None, Some("http://nas/3.flac"))), [Step::Playing(2)]);
+        assert_eq!(gapless.update(&transport(Some("STOPPED"), None)), [Step::Finished]);
+
+        // Not preloaded: STOPPED before the file started is the renderer loading it
+        let mut stepped = Follower::new(urls, false);
+        assert!(stepped.update(&transport(Some("STOPPED"), None)).is_empty());
+        assert!(stepped.update(&transport(Some("TRANSITIONING"), None)).is_empty());
+        assert!(stepped.update(&transport(Some("PLAYING"), None)).is_empty());
+        assert_eq!(stepped.update(&transport(Some("STOPPED"), None)), [Step::Play(1), Step::Playing(1)]);
+        assert!(stepped.update(&transport(Some("STOPPED"), None)).is_empty());
+    }
+}
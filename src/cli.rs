@@ -0,0 +1,502 @@
+use crate::app::DirectoryItem;
+use crate::download::{self, Outcome};
+use crate::headless::{Context, Error, ErrorKind};
+use crate::output;
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+/// Browse and play media from UPnP/DLNA servers on the local network.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    /// Browse a built-in demo server instead of the network (TUI only)
+    #[arg(long)]
+    pub demo: bool,
+
+    /// Record keys, screens and (redacted) network replies to FILE, to
+    /// attach to a bug report (TUI only)
+    #[arg(long, value_name = "FILE")]
+    pub record_session: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Options accepted by the TUI and every subcommand.
+#[derive(clap::Args, Debug)]
+pub struct GlobalArgs {
+    /// Network profile from [profiles] to use instead of auto-detecting one
+    #[arg(long, short, global = true)]
+    pub profile: Option<String>,
+
+    /// Print machine-readable JSON instead of text (see docs/json-output.md)
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Discover devices directly even if `mop daemon run` is running
+    #[arg(long, global = true)]
+    pub no_daemon: bool,
+
+    /// Log to stderr (-v info, -vv debug, -vvv everything)
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Run discovery and list every device found
+    Devices,
+    /// Export every known device for an inventory or other tools
+    ///
+    /// Lists the devices discovery finds now and those remembered from
+    /// earlier runs, with UDN, URLs, services and when each was last seen.
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Write to FILE instead of stdout
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// List the children of a container
+    ///
+    /// Prints one tab-separated line per item: kind (dir/file), name, size in
+    /// bytes, duration and resource URL, with "-" for missing values.
+    Browse {
+        /// Device name (substring), host, location URL or configured alias
+        device: String,
+        /// Title path such as "/Music/Albums", or an ObjectID (no leading slash)
+        #[arg(default_value = "/")]
+        target: String,
+    },
+    /// Resolve a single item and start the configured player with it
+    ///
+    /// A path to a container plays every file in it. With "-" instead of a
+    /// device, plays the URLs read from stdin, one per line.
+    Play {
+        /// Device name (substring), host, location URL or configured alias,
+        /// or "-" to read URLs from stdin
+        device: String,
+        /// Title path such as "/Music/Radio", or a title to search for
+        target: Option<String>,
+    },
+    /// Download a file, or a whole folder with --recursive
+    ///
+    /// Interrupted downloads are kept as *.part files and resumed on the
+    /// next run, as long as the file on the server hasn't changed; files
+    /// that already exist are skipped.
+    Download {
+        /// Device name (substring), host, location URL or configured alias
+        device: String,
+        /// Title path such as "/Music/Albums/Blue Train"
+        path: String,
+        /// Download folders and everything below them
+        #[arg(long, short)]
+        recursive: bool,
+        /// Directory to save into (default: downloads.dir)
+        #[arg(long)]
+        dest: Option<PathBuf>,
+        /// Fail files whose size differs from what the server reported
+        #[arg(long)]
+        verify: bool,
+        /// Expected SHA-256 of the file (single files only)
+        #[arg(long, value_name = "HEX")]
+        sha256: Option<String>,
+    },
+    /// Record what discovery sees, or replay a recording without a network
+    ///
+    /// A capture holds the raw SSDP responses and the device descriptions
+    /// they pointed to, as JSON. Attach one to a discovery bug report.
+    Capture {
+        #[command(subcommand)]
+        command: CaptureCommand,
+    },
+    /// Keep discovery warm in the background so MOP starts instantly
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommand,
+    },
+    /// Share a local directory as a DLNA media server until interrupted
+    ///
+    /// Announces itself over SSDP so TVs and other renderers on the network
+    /// can browse and stream the media files below DIR.
+    Serve {
+        /// Directory to share
+        dir: PathBuf,
+        /// Name shown to other devices
+        #[arg(long, default_value = "MOP")]
+        name: String,
+        /// HTTP port for descriptions, control and streaming (0 picks a free one)
+        #[arg(long, default_value_t = 8200)]
+        port: u16,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CaptureCommand {
+    /// Search the network and save everything that answered to FILE
+    Record {
+        file: PathBuf,
+    },
+    /// List the devices discovery finds in a capture, like `mop devices`
+    Replay {
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DaemonCommand {
+    /// Run the daemon in the foreground (e.g. from a systemd user unit)
+    Run {
+        /// Seconds between discovery rounds
+        #[arg(long, default_value_t = 300)]
+        refresh: u64,
+        /// Also answer JSON requests over HTTP on ADDR, e.g. 127.0.0.1:8780
+        #[arg(long, value_name = "ADDR")]
+        http: Option<String>,
+    },
+    /// Show whether a daemon is running and what it has cached
+    Status,
+    /// Ask the daemon to run discovery again now
+    Refresh,
+    /// Stop the running daemon
+    Stop,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Write a commented mop.toml listing every option and its default
+    Init {
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+        /// Write to this path instead of the default config location
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+}
+
+/// Run a subcommand without starting the TUI. The error's kind decides the
+/// exit code.
+pub fn run(command: Command, global: &GlobalArgs) -> Result<(), Error> {
+    let profile = global.profile.as_deref();
+    let json = global.json;
+    let use_daemon = !global.no_daemon;
+    match command {
+        Command::Config { command: ConfigCommand::Init { force, path } } => {
+            let path = path.unwrap_or_else(crate::config::get_config_path);
+            // Refusing to overwrite is a usage error; anything else is the filesystem
+            let kind = if path.exists() && !force { ErrorKind::Other } else { ErrorKind::Network };
+            crate::config::write_sample_config(&path, force).map_err(|e| Error::new(kind, e))?;
+            if json {
+                output::print(&output::ConfigWritten { path: path.display().to_string() });
+            } else {
+                println!("Wrote {}", path.display());
+            }
+            Ok(())
+        }
+        Command::Devices => {
+            let context = Context::load(profile, use_daemon)?;
+            print_devices(&context.discover_all(), json)
+        }
+        Command::Export { format, output } => {
+            let context = Context::load(profile, use_daemon)?;
+            let entries = crate::inventory::collect(&context);
+            if entries.is_empty() {
+                return Err(Error::no_devices());
+            }
+            let text = match format {
+                ExportFormat::Json => serde_json::to_string_pretty(&entries).map_err(|e| e.to_string())? + "\n",
+                ExportFormat::Csv => crate::inventory::to_csv(&entries),
+            };
+            let Some(path) = output else {
+                print!("{}", text);
+                return Ok(());
+            };
+            std::fs::write(&path, text)
+                .map_err(|e| Error::network(format!("Failed to write {}: {}", path.display(), e)))?;
+            if json {
+                output::print(&output::ExportWritten { path: path.display().to_string(), devices: entries.len() });
+            } else {
+                println!("Wrote {} devices to {}", entries.len(), path.display());
+            }
+            Ok(())
+        }
+        Command::Capture { command: CaptureCommand::Record { file } } => {
+            let context = Context::load(profile, use_daemon)?;
+            let settings = context.config.discovery_for(context.profile.as_deref());
+            let capture = mop_core::capture::record(settings).map_err(Error::network)?;
+            let text = serde_json::to_string_pretty(&capture).map_err(|e| e.to_string())?;
+            std::fs::write(&file, text)
+                .map_err(|e| Error::network(format!("Failed to write {}: {}", file.display(), e)))?;
+            if json {
+                output::print(&output::CaptureWritten {
+                    path: file.display().to_string(),
+                    responses: capture.responses.len(),
+                    descriptions: capture.descriptions.len(),
+                });
+            } else {
+                println!(
+                    "Wrote {} SSDP responses and {} descriptions to {}",
+                    capture.responses.len(),
+                    capture.descriptions.len(),
+                    file.display()
+                );
+            }
+            Ok(())
+        }
+        Command::Capture { command: CaptureCommand::Replay { file } } => {
+            let context = Context::load(profile, use_daemon)?;
+            let text = std::fs::read_to_string(&file)
+                .map_err(|e| Error::network(format!("Failed to read {}: {}", file.display(), e)))?;
+            let capture = serde_json::from_str(&text)
+                .map_err(|e| Error::from(format!("{} is not a capture: {}", file.display(), e)))?;
+            print_devices(&context.replay(capture), json)
+        }
+        Command::Browse { device, target } => {
+            let context = Context::load(profile, use_daemon)?;
+            let device = context.find_device(&device)?;
+            let items = crate::headless::list(&device, &target)?;
+            if json {
+                output::print(&items.iter().map(output::Item::from).collect::<Vec<_>>());
+            } else {
+                for item in &items {
+                    println!("{}", crate::headless::format_item(item));
+                }
+            }
+            Ok(())
+        }
+        Command::Play { device, target: None } if device == "-" => {
+            let config = Context::load(profile, use_daemon)?.config;
+            let urls = read_urls(std::io::stdin().lock())?;
+            let player = crate::app::player_for_url(&config, &urls[0]);
+            let refs: Vec<&str> = urls.iter().map(String::as_str).collect();
+            crate::app::launch_player(player, &refs, &crate::hooks::Vars::url(&urls[0]), None).map_err(Error::network)?;
+
+            if json {
+                output::print(&output::Played {
+                    player: player.to_string(),
+                    items: urls.iter().map(|url| output::Item::from_url(url)).collect(),
+                });
+            } else {
+                match urls.as_slice() {
+                    [url] => println!("Playing {}", url),
+                    urls => println!("Playing {} URLs", urls.len()),
+                }
+            }
+            Ok(())
+        }
+        Command::Play { target: None, .. } => {
+            Err("Missing what to play: give a path or search, or use '-' to read URLs from stdin"
+                .to_string()
+                .into())
+        }
+        Command::Play { device, target: Some(target) } => {
+            let context = Context::load(profile, use_daemon)?;
+            let device = context.find_device(&device)?;
+            let items = crate::headless::resolve_playable(&device, &target)?;
+
+            let player = crate::headless::play(&context.config, &device, &items)?;
+
+            if json {
+                output::print(&output::Played {
+                    player: player.to_string(),
+                    items: items.iter().map(output::Item::from).collect(),
+                });
+            } else {
+                match items.as_slice() {
+                    [item] => println!("Playing {}", item.name),
+                    items => println!("Playing {} items from {}", items.len(), target),
+                }
+            }
+            Ok(())
+        }
+        Command::Download { device, path, recursive, dest, verify, sha256 } => {
+            let context = Context::load(profile, use_daemon)?;
+            let device = context.find_device(&device)?;
+            let dest = dest.unwrap_or_else(|| context.config.downloads.resolved_dir());
+            let files = crate::headless::download_plan(&device, &path, recursive)?;
+            if sha256.is_some() && files.len() > 1 {
+                return Err(format!("--sha256 needs a single file, {} matches {}", path, files.len()).into());
+            }
+            let results = download_files(&files, &dest, &device, verify, sha256.as_deref(), !json);
+            let failed = results.iter().filter(|r| r.status == "failed").count();
+            if json {
+                output::print(&results);
+            }
+            if failed > 0 {
+                return Err(Error::network(format!("{} of {} downloads failed", failed, files.len())));
+            }
+            Ok(())
+        }
+        Command::Daemon { command: DaemonCommand::Run { refresh, http } } => {
+            crate::daemon::run(global.profile.clone(), std::time::Duration::from_secs(refresh), http)
+                .map_err(Error::network)
+        }
+        Command::Daemon { command } => {
+            let request = match command {
+                DaemonCommand::Status => crate::daemon::Request::Status,
+                DaemonCommand::Refresh => crate::daemon::Request::Refresh,
+                _ => crate::daemon::Request::Stop,
+            };
+            match crate::daemon::request(&request).map_err(Error::network)? {
+                crate::daemon::Response::Status(status) if json => output::print(&status),
+                crate::daemon::Response::Status(status) => {
+                    println!("pid\t{}", status.pid);
+                    println!("profile\t{}", status.profile.as_deref().unwrap_or("-"));
+                    println!("devices\t{}", status.devices);
+                    println!(
+                        "refreshed\t{}",
+                        match (status.refreshing, status.refreshed_secs_ago) {
+                            (true, _) => "in progress".to_string(),
+                            (false, Some(secs)) => format!("{}s ago", secs),
+                            (false, None) => "never".to_string(),
+                        }
+                    );
+                }
+                crate::daemon::Response::Error { message } => return Err(message.into()),
+                _ => {}
+            }
+            Ok(())
+        }
+        Command::Serve { dir, name, port } => {
+            if !dir.is_dir() {
+                return Err(Error::not_found(format!("{} is not a directory", dir.display())));
+            }
+            crate::dlna_server::serve(crate::dlna_server::ServeOptions { dir, name, port })
+                .map_err(Error::network)
+        }
+    }
+}
+
+/// URLs piped into `mop play -`, one per line. Blank lines and lines
+/// starting with '#' (as in .m3u playlists) are skipped.
+fn read_urls(input: impl std::io::BufRead) -> Result<Vec<String>, Error> {
+    let mut urls = Vec::new();
+    for line in input.lines() {
+        let line = line.map_err(|e| Error::network(format!("Failed to read stdin: {}", e)))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        urls.push(crate::app::parse_stream_url(line)?);
+    }
+    if urls.is_empty() {
+        return Err("No URLs on stdin".to_string().into());
+    }
+    Ok(urls)
+}
+
+fn download_files(
+    files: &[(PathBuf, DirectoryItem)],
+    dest: &Path,
+    device: &crate::upnp::UpnpDevice,
+    verify_size: bool,
+    sha256: Option<&str>,
+    show_progress: bool,
+) -> Vec<output::DownloadResult> {
+    let mut results = Vec::new();
+    for (index, (relative, item)) in files.iter().enumerate() {
+        let Some(url) = item.url.as_deref() else { continue };
+        let target = dest.join(relative);
+        let label = format!("[{}/{}] {}", index + 1, files.len(), relative.display());
+
+        let verify = download::Verify {
+            size: item.metadata.as_ref().and_then(|m| m.size).filter(|_| verify_size),
+            sha256: sha256.map(str::to_string),
+        };
+
+        let mut last_reported = 0;
+        let result = download::download(url, &target, &verify, device, |progress| {
+            // Redraw at most every 256 KB to keep the terminal responsive
+            if !show_progress
+                || (progress.downloaded - last_reported < 256 * 1024
+                    && Some(progress.downloaded) != progress.total)
+            {
+                return;
+            }
+            last_reported = progress.downloaded;
+            let amount = match progress.total {
+                Some(total) if total > 0 => format!(
+                    "{:>3}%  {} / {}",
+                    progress.downloaded * 100 / total,
+                    download::format_bytes(progress.downloaded),
+                    download::format_bytes(total)
+                ),
+                _ => download::format_bytes(progress.downloaded),
+            };
+            eprint!("\r{}  {}\x1b[K", label, amount);
+        });
+
+        let (status, bytes, error) = match result {
+            Ok(Outcome::Downloaded(bytes)) => ("downloaded", Some(bytes), None),
+            Ok(Outcome::AlreadyPresent) => ("present", None, None),
+            Err(e) => ("failed", None, Some(e)),
+        };
+        if let Some(bytes) = bytes {
+            let hook = crate::hooks::Vars::item(item)
+                .server(Some(device))
+                .with("MOP_FILE", target.display())
+                .with("MOP_BYTES", bytes);
+            crate::hooks::run(crate::hooks::Hook::DownloadComplete, &hook);
+        }
+        if show_progress {
+            match (&bytes, &error) {
+                (Some(bytes), _) => eprintln!("\r{}  done ({})\x1b[K", label, download::format_bytes(*bytes)),
+                (_, Some(error)) => eprintln!("\r{}  failed: {}\x1b[K", label, error),
+                _ => eprintln!("\r{}  already present\x1b[K", label),
+            }
+        }
+        results.push(output::DownloadResult {
+            path: target.display().to_string(),
+            url: url.to_string(),
+            status,
+            bytes,
+            error,
+        });
+    }
+    results
+}
+
+fn print_devices(devices: &[crate::upnp::UpnpDevice], json: bool) -> Result<(), Error> {
+    if devices.is_empty() {
+        return Err(Error::no_devices());
+    }
+    if json {
+        output::print(&devices.iter().map(output::Device::from).collect::<Vec<_>>());
+    } else {
+        for device in devices {
+            println!("{}\t{}", device.name, device.location);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_urls_skipping_comments_and_blanks() {
+        let input = "#EXTM3U\n\nhttp://radio.example/stream.mp3\n  rtsp://cam.local/live  \n";
+        assert_eq!(
+            read_urls(input.as_bytes()).unwrap(),
+            vec!["http://radio.example/stream.mp3", "rtsp://cam.local/live"]
+        );
+        assert!(read_urls("not a url\n".as_bytes()).is_err());
+        assert!(read_urls("# nothing\n".as_bytes()).is_err());
+    }
+}
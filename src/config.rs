@@ -1,9 +1,43 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub use mop_core::discovery::DiscoveryConfig;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub mop: MopConfig,
+    #[serde(default)]
+    pub players: PlayersConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    #[serde(default)]
+    pub keys: KeysConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub downloads: DownloadsConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub cast: CastConfig,
+    #[serde(default)]
+    pub quality: QualityConfig,
+    #[serde(default)]
+    pub slideshow: SlideshowConfig,
+    #[serde(default)]
+    pub columns: ColumnsConfig,
+    #[serde(default)]
+    pub hide: HideConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Per-server settings keyed by a device pattern (name, host or location).
+    #[serde(default)]
+    pub servers: BTreeMap<String, ServerConfig>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,52 +46,658 @@ pub struct MopConfig {
     pub run: String,
     #[serde(default)]
     pub auto_close: bool,
+    /// Open the server straight away when discovery finds only one.
+    #[serde(default)]
+    pub auto_open_server: bool,
+    /// Reopen the server and folder that were open when MOP last quit.
+    #[serde(default)]
+    pub restore_last_location: bool,
+    /// UDNs (without "uuid:") of servers listed first, in this order.
+    #[serde(default)]
+    pub pinned_servers: Vec<String>,
+    /// JSON or TOML file of servers to list at startup, for networks where
+    /// discovery can't find them. Empty imports nothing.
+    #[serde(default)]
+    pub servers_file: String,
+    /// Rescan when the network changes or the machine wakes from sleep.
+    #[serde(default = "default_true")]
+    pub watch_network: bool,
+    /// How often to check that each listed server still answers, in
+    /// seconds; 0 never does.
+    #[serde(default = "default_health_check_secs")]
+    pub health_check_secs: u64,
+    /// Ask the server for a file before starting the player on it.
+    #[serde(default = "default_true")]
+    pub check_streams: bool,
+    /// Tell Jellyfin and Emby servers what is played and where it stopped.
+    #[serde(default = "default_true")]
+    pub report_playback: bool,
+    /// List artists, albums and tracks as such rather than as folders and
+    /// files.
+    #[serde(default)]
+    pub music_view: bool,
+    /// List each file once when the listing has it more than once, and
+    /// pick between the copies when playing it.
+    #[serde(default)]
+    pub collapse_duplicates: bool,
+    /// Show the highlighted file's thumbnail in the File Info panel, when
+    /// the server has one.
+    #[serde(default = "default_true")]
+    pub thumbnails: bool,
+    /// Quit MOP as well when the sleep timer stops playback.
+    #[serde(default)]
+    pub sleep_quits: bool,
+    /// Network profile to use when none is given on the command line.
+    /// Empty means auto-detect from the current network.
+    #[serde(default)]
+    pub profile: String,
+}
+
+/// Per-media-type player commands. An empty command falls back to `mop.run`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayersConfig {
+    #[serde(default)]
+    pub video: String,
+    #[serde(default)]
+    pub audio: String,
+    #[serde(default)]
+    pub image: String,
+    /// What listings do with files the player can't play: "show", "dim"
+    /// or "hide"; see `crate::playable`.
+    #[serde(default = "default_unplayable")]
+    pub unplayable: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeysConfig {
+    #[serde(default = "default_keymap_preset")]
+    pub preset: String,
+    /// Overrides on top of the preset: action name -> list of keys.
+    #[serde(default)]
+    pub bindings: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThemeConfig {
+    #[serde(default = "default_accent_color")]
+    pub accent: String,
+    #[serde(default = "default_highlight_color")]
+    pub highlight: String,
+    #[serde(default = "default_selection_color")]
+    pub selection: String,
+    #[serde(default = "default_muted_color")]
+    pub muted: String,
+    #[serde(default = "default_error_color")]
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownloadsConfig {
+    /// Empty means the platform download directory.
+    #[serde(default)]
+    pub dir: String,
+    /// KB/s all downloads share; 0 for no limit.
+    #[serde(default)]
+    pub rate_limit: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CastConfig {
+    /// UDN of the renderer the send-to-TV key plays on.
+    #[serde(default)]
+    pub default_renderer: String,
+}
+
+/// Which `<res>` of a file to play or cast when the server lists several;
+/// see `crate::quality`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityConfig {
+    /// "highest", or "compatible" to prefer what a renderer says it plays.
+    #[serde(default = "default_quality_preference")]
+    pub prefer: String,
+    /// Pass over variants above this many Mbit/s; 0 for no cap.
+    #[serde(default)]
+    pub max_mbps: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlideshowConfig {
+    /// How long each image stays up, for viewers MOP knows how to tell.
+    #[serde(default = "default_slideshow_interval")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub shuffle: bool,
+}
+
+/// Widths, in characters, of the columns music tracks are listed in. 0
+/// hides a column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnsConfig {
+    #[serde(default = "default_track_column")]
+    pub track: u16,
+    #[serde(default = "default_artist_column")]
+    pub artist: u16,
+    #[serde(default = "default_album_column")]
+    pub album: u16,
+}
+
+/// Entries left out of listings; see `crate::hide` for the pattern syntax.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HideConfig {
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// Commands run on events; see `crate::hooks`. Empty runs nothing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub on_device_found: String,
+    #[serde(default)]
+    pub on_play_start: String,
+    #[serde(default)]
+    pub on_play_end: String,
+    #[serde(default)]
+    pub on_download_complete: String,
+    /// URL every event is POSTed to as JSON. Empty sends nothing.
+    #[serde(default)]
+    pub webhook: String,
+    /// Events the webhook gets, by name ("play_start"); empty for all.
+    #[serde(default)]
+    pub webhook_events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Proxy URL for all HTTP traffic. Empty uses HTTP(S)_PROXY/ALL_PROXY.
+    #[serde(default)]
+    pub proxy: String,
+    /// KB/s the seek relay passes on; 0 for no limit.
+    #[serde(default)]
+    pub relay_rate_limit: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Name shown instead of the advertised friendly name.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alias: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub password: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub token: String,
+    /// Jellyfin/Emby API key (Dashboard > API Keys) for browsing without a login.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_key: String,
+    /// For HTTPS servers with a self-signed certificate: skip validation.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub accept_invalid_certs: bool,
+    /// Path to a PEM certificate to pin instead of trusting the system roots.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub certificate: String,
+    /// Connect directly even when a proxy is configured.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bypass_proxy: bool,
+    /// Extra HTTP headers (User-Agent included) for servers that only answer
+    /// clients they know.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Settings that apply on one network only, e.g. `[profiles.home]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    #[serde(default)]
+    pub detect: ProfileDetect,
+    /// Replaces `[discovery]` entirely while the profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery: Option<DiscoveryConfig>,
+    /// Merged over the top-level `[servers]`.
+    #[serde(default)]
+    pub servers: BTreeMap<String, ServerConfig>,
+}
+
+/// A profile is picked automatically when any of these match the current network.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileDetect {
+    #[serde(default)]
+    pub ssid: Vec<String>,
+    #[serde(default)]
+    pub gateway_mac: Vec<String>,
+}
+
+fn default_health_check_secs() -> u64 {
+    60
 }
 
 fn default_run() -> String {
-    "mpv".to_string()
+    if crate::termux::is_termux() {
+        crate::termux::ANDROID_PLAYER.to_string()
+    } else {
+        "mpv".to_string()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_keymap_preset() -> String {
+    "default".to_string()
+}
+
+fn default_accent_color() -> String {
+    "cyan".to_string()
+}
+
+fn default_highlight_color() -> String {
+    "yellow".to_string()
+}
+
+fn default_selection_color() -> String {
+    "darkgray".to_string()
 }
 
-impl Default for Config {
+fn default_muted_color() -> String {
+    "gray".to_string()
+}
+
+fn default_error_color() -> String {
+    "red".to_string()
+}
+
+impl Default for MopConfig {
     fn default() -> Self {
         Self {
-            mop: MopConfig::default(),
+            run: default_run(),
+            auto_close: false,
+            auto_open_server: false,
+            restore_last_location: false,
+            pinned_servers: Vec::new(),
+            servers_file: String::new(),
+            watch_network: true,
+            health_check_secs: default_health_check_secs(),
+            check_streams: true,
+            report_playback: true,
+            music_view: false,
+            collapse_duplicates: false,
+            thumbnails: true,
+            sleep_quits: false,
+            profile: String::new(),
         }
     }
 }
 
-impl Default for MopConfig {
+fn default_unplayable() -> String {
+    "show".to_string()
+}
+
+impl Default for PlayersConfig {
     fn default() -> Self {
         Self {
-            run: default_run(),
-            auto_close: false,
+            video: String::new(),
+            audio: String::new(),
+            image: String::new(),
+            unplayable: default_unplayable(),
+        }
+    }
+}
+
+fn default_quality_preference() -> String {
+    "highest".to_string()
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            prefer: default_quality_preference(),
+            max_mbps: 0,
+        }
+    }
+}
+
+fn default_slideshow_interval() -> u64 {
+    5
+}
+
+impl Default for SlideshowConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_slideshow_interval(),
+            shuffle: false,
+        }
+    }
+}
+
+fn default_track_column() -> u16 {
+    3
+}
+
+fn default_artist_column() -> u16 {
+    20
+}
+
+fn default_album_column() -> u16 {
+    24
+}
+
+impl Default for ColumnsConfig {
+    fn default() -> Self {
+        Self {
+            track: default_track_column(),
+            artist: default_artist_column(),
+            album: default_album_column(),
+        }
+    }
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            preset: default_keymap_preset(),
+            bindings: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            accent: default_accent_color(),
+            highlight: default_highlight_color(),
+            selection: default_selection_color(),
+            muted: default_muted_color(),
+            error: default_error_color(),
+        }
+    }
+}
+
+impl DownloadsConfig {
+    /// The configured directory with `~` expanded, or the platform download
+    /// directory when unset.
+    pub fn resolved_dir(&self) -> PathBuf {
+        let dir = self.dir.trim();
+        if dir.is_empty() {
+            return dirs::download_dir()
+                .or_else(|| dirs::home_dir().map(|home| home.join("Downloads")))
+                .unwrap_or_else(|| PathBuf::from("."));
         }
+        expand_home(dir)
+    }
+}
+
+/// A path from the config with a leading `~` expanded.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => dirs::home_dir().unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl ServerConfig {
+    /// Set this entry's alias and credentials on `device`.
+    pub fn apply(&self, device: &mut crate::upnp::UpnpDevice) {
+        if !self.token.trim().is_empty() {
+            device.token = Some(self.token.trim().to_string());
+        }
+        if !self.api_key.trim().is_empty() {
+            device.api_key = Some(self.api_key.trim().to_string());
+        }
+        if !self.username.trim().is_empty() {
+            device.credentials = Some(crate::auth::Credentials {
+                username: self.username.trim().to_string(),
+                password: self.password.clone(),
+            });
+        }
+        device.bypass_proxy = self.bypass_proxy;
+        device.headers = self.headers.clone();
+        device.tls = crate::tls::TlsOptions {
+            accept_invalid_certs: self.accept_invalid_certs,
+            certificate: Some(self.certificate.trim())
+                .filter(|path| !path.is_empty())
+                .map(expand_home),
+        };
+        if !self.alias.trim().is_empty() {
+            device.name = self.alias.trim().to_string();
+        }
+    }
+}
+
+impl PlayersConfig {
+    /// Pick the player for a MIME type, falling back to `default` when no
+    /// type-specific command is configured.
+    pub fn for_mime<'a>(&'a self, mime: Option<&str>, default: &'a str) -> &'a str {
+        let specific = match mime.and_then(|m| m.split('/').next()) {
+            Some("video") => &self.video,
+            Some("audio") => &self.audio,
+            Some("image") => &self.image,
+            _ => return default,
+        };
+        if specific.trim().is_empty() { default } else { specific }
     }
 }
 
 impl Config {
-    pub fn load() -> Self {
+    pub fn load() -> (Self, Vec<String>) {
         let config_path = get_config_path();
 
         if config_path.exists() {
             match std::fs::read_to_string(&config_path) {
-                Ok(content) => {
-                    toml::from_str(&content).unwrap_or_else(|e| {
-                        eprintln!("Warning: Invalid config file: {}, using defaults", e);
-                        Self::default()
-                    })
-                }
-                Err(_) => Self::default(),
+                Ok(content) => Self::parse(&content),
+                Err(e) => (
+                    Self::default(),
+                    vec![format!("Failed to read {}: {}", config_path.display(), e)],
+                ),
             }
         } else {
-            // Create default config file
-            let default_config = Self::default();
-            let _ = std::fs::create_dir_all(config_path.parent().unwrap());
-            if let Ok(toml_str) = toml::to_string_pretty(&default_config) {
-                let _ = std::fs::write(&config_path, toml_str);
+            // Create a commented default config file
+            let _ = write_sample_config(&config_path, false);
+            (Self::default(), Vec::new())
+        }
+    }
+
+    /// Parse a config file, collecting warnings for unknown keys and invalid
+    /// values instead of failing. A file that cannot be parsed at all yields
+    /// the defaults.
+    pub fn parse(content: &str) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+
+        let deserializer = match toml::Deserializer::parse(content) {
+            Ok(deserializer) => deserializer,
+            Err(e) => {
+                warnings.push(format!("Invalid config file: {}, using defaults", e));
+                return (Self::default(), warnings);
+            }
+        };
+
+        let mut unknown = Vec::new();
+        let config: Self = match serde_ignored::deserialize(deserializer, |path| {
+            unknown.push(path.to_string())
+        }) {
+            Ok(config) => config,
+            Err(e) => {
+                warnings.push(format!("Invalid config file: {}, using defaults", e));
+                return (Self::default(), warnings);
             }
-            default_config
+        };
+
+        unknown.sort();
+        for key in unknown {
+            warnings.push(format!("Unknown config key '{}' ignored", key));
         }
+        warnings.extend(config.validate());
+
+        (config, warnings)
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        for (name, value) in [
+            ("accent", &self.theme.accent),
+            ("highlight", &self.theme.highlight),
+            ("selection", &self.theme.selection),
+            ("muted", &self.theme.muted),
+            ("error", &self.theme.error),
+        ] {
+            if ratatui::style::Color::from_str(value).is_err() {
+                warnings.push(format!("Invalid color '{}' for theme.{}", value, name));
+            }
+        }
+
+        let (_, keymap_warnings) = crate::keymap::Keymap::from_config(&self.keys);
+        warnings.extend(keymap_warnings);
+        let (_, hide_warnings) = crate::hide::HideList::from_config(&self.hide);
+        warnings.extend(hide_warnings);
+
+        let webhook = self.hooks.webhook.trim();
+        if !webhook.is_empty() && reqwest::Url::parse(webhook).is_err() {
+            warnings.push(format!("hooks.webhook '{}' is not a URL", webhook));
+        }
+        for event in &self.hooks.webhook_events {
+            if !crate::hooks::Hook::ALL.iter().any(|hook| hook.name() == event) {
+                warnings.push(format!("Unknown event '{}' in hooks.webhook_events", event));
+            }
+        }
+
+        if !crate::playable::MODES.contains(&self.players.unplayable.as_str()) {
+            warnings.push(format!("Unknown players.unplayable '{}', showing everything", self.players.unplayable));
+        }
+
+        if !crate::quality::PREFERENCES.contains(&self.quality.prefer.as_str()) {
+            warnings.push(format!("Unknown quality.prefer '{}', using highest", self.quality.prefer));
+        }
+
+        if self.discovery.ssdp_timeout_secs == 0 {
+            warnings.push("discovery.ssdp_timeout_secs must be greater than 0".to_string());
+        }
+
+        for target in &self.discovery.ssdp.search_targets {
+            if rupnp::ssdp::SearchTarget::from_str(target).is_err() {
+                warnings.push(format!("Invalid SSDP search target '{}'", target));
+            }
+        }
+
+        for range in &self.discovery.port_scan.ranges {
+            if let Err(e) = crate::upnp::parse_cidr(range) {
+                warnings.push(format!("Invalid discovery.port_scan range '{}': {}", range, e));
+            }
+        }
+
+        if let Err(e) = crate::proxy::validate(&self.network.proxy) {
+            warnings.push(format!("network.proxy: {}", e));
+        }
+
+        let profile_servers = self.profiles.values().flat_map(|profile| profile.servers.iter());
+        for (pattern, server) in self.servers.iter().chain(profile_servers) {
+            if let Err(e) = mop_core::headers::validate(&server.headers) {
+                warnings.push(format!("servers.\"{}\".headers: {}", pattern, e));
+            }
+        }
+
+        if !self.mop.profile.is_empty() && !self.profiles.contains_key(&self.mop.profile) {
+            warnings.push(format!("mop.profile '{}' is not defined in [profiles]", self.mop.profile));
+        }
+
+        warnings
+    }
+
+    /// Discovery settings for the active profile, falling back to `[discovery]`.
+    pub fn discovery_for(&self, profile: Option<&str>) -> &DiscoveryConfig {
+        profile
+            .and_then(|name| self.profiles.get(name))
+            .and_then(|profile| profile.discovery.as_ref())
+            .unwrap_or(&self.discovery)
+    }
+
+    /// `discovery_for` with the `[servers]` settings to fetch descriptions
+    /// with, for starting discovery.
+    pub fn discovery_settings(&self, profile: Option<&str>) -> DiscoveryConfig {
+        let mut settings = self.discovery_for(profile).clone();
+        let profile_servers = profile.and_then(|name| self.profiles.get(name)).map(|profile| &profile.servers);
+        settings.connections = profile_servers
+            .into_iter()
+            .chain(std::iter::once(&self.servers))
+            .flat_map(|servers| servers.iter())
+            .map(|(pattern, server)| {
+                let mut connection = crate::upnp::UpnpDevice::default();
+                server.apply(&mut connection);
+                (pattern.clone(), connection)
+            })
+            .collect();
+        settings
+    }
+
+    /// Server settings for a device, preferring entries from the active profile.
+    pub fn server_for(
+        &self,
+        profile: Option<&str>,
+        device: &crate::upnp::UpnpDevice,
+    ) -> Option<&ServerConfig> {
+        let profile_servers = profile
+            .and_then(|name| self.profiles.get(name))
+            .map(|profile| &profile.servers);
+
+        profile_servers
+            .into_iter()
+            .chain(std::iter::once(&self.servers))
+            .flat_map(|servers| servers.iter())
+            .find(|(pattern, _)| crate::upnp::device_matches(device, pattern))
+            .map(|(_, server)| server)
+    }
+
+    /// The `[servers]` entry `server_for` reads for `device`, to change, and
+    /// its key. While a profile is active that's one in the profile's own
+    /// section, copied from the top-level entry if only that matched so
+    /// nothing set there is lost; failing both, a new one under `key`.
+    pub fn server_entry_mut(
+        &mut self,
+        profile: Option<&str>,
+        device: &crate::upnp::UpnpDevice,
+        key: &str,
+    ) -> (String, &mut ServerConfig) {
+        let matching = |servers: &BTreeMap<String, ServerConfig>| {
+            servers.keys().find(|pattern| crate::upnp::device_matches(device, pattern)).cloned()
+        };
+        let inherited = matching(&self.servers).map(|key| (key.clone(), self.servers[&key].clone()));
+        let servers = match profile.and_then(|name| self.profiles.get_mut(name)) {
+            Some(profile) => &mut profile.servers,
+            None => &mut self.servers,
+        };
+        let key = match matching(servers) {
+            Some(key) => key,
+            None => {
+                let (key, entry) = inherited.unwrap_or_else(|| (key.to_string(), ServerConfig::default()));
+                servers.insert(key.clone(), entry);
+                key
+            }
+        };
+        (key.clone(), servers.entry(key).or_default())
+    }
+
+    /// The `[servers]` entry under exactly `key`, preferring the active
+    /// profile's.
+    pub fn server_named(&self, profile: Option<&str>, key: &str) -> Option<&ServerConfig> {
+        profile
+            .and_then(|name| self.profiles.get(name))
+            .and_then(|profile| profile.servers.get(key))
+            .or_else(|| self.servers.get(key))
+    }
+
+    /// Apply `[servers]` settings to a discovered device: its alias and the
+    /// credentials to authenticate with.
+    pub fn apply_server_config(&self, profile: Option<&str>, device: &mut crate::upnp::UpnpDevice) {
+        if let Some(server) = self.server_for(profile, device) {
+            server.apply(device);
+        }
+    }
+
+    /// The `[mop] servers_file` path, if one is set.
+    pub fn servers_file(&self) -> Option<PathBuf> {
+        Some(self.mop.servers_file.trim()).filter(|path| !path.is_empty()).map(expand_home)
     }
 
     pub fn save(&self) -> Result<(), String> {
@@ -71,17 +711,568 @@ impl Config {
         let toml_str = toml::to_string_pretty(self)
             .map_err(|e| format!("Failed to serialize config: {}", e))?;
 
-        std::fs::write(&config_path, toml_str)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
+        write_private(&config_path, &toml_str)
+    }
+
+    /// Set the one setting at `path` (`["mop", "pinned_servers"]`) in
+    /// mop.toml to `value`, leaving the rest of the file, comments and all,
+    /// as the user wrote it.
+    pub fn save_setting<T: Serialize>(path: &[&str], value: &T) -> Result<(), String> {
+        let config_path = get_config_path();
+        let content = match std::fs::read_to_string(&config_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", config_path.display(), e)),
+        };
+        let content = set_setting(&content, path, value)
+            .map_err(|e| format!("Failed to update {}: {}", config_path.display(), e))?;
+        write_private(&config_path, &content)
+    }
+}
+
+/// `content` with the setting at `path` set to `value` and nothing else
+/// changed. Tables along the way are made when missing.
+fn set_setting<T: Serialize>(content: &str, path: &[&str], value: &T) -> Result<String, String> {
+    let mut document: toml_edit::DocumentMut = content.parse().map_err(|e| format!("{}", e))?;
+    let Some((name, parents)) = path.split_last() else { return Err("No setting named".to_string()) };
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for parent in parents {
+        let entry = table.entry(parent).or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        });
+        table = entry.as_table_like_mut().ok_or_else(|| format!("{} isn't a table", parent))?;
+    }
+    // Through a document of its own, so a struct comes out as a table
+    let wrapped = toml::to_string(&BTreeMap::from([(*name, value)])).map_err(|e| e.to_string())?;
+    let mut wrapped: toml_edit::DocumentMut = wrapped.parse().map_err(|e| format!("{}", e))?;
+    let mut item = wrapped.remove(name).unwrap_or_default();
+    if let Some(new) = item.as_table_mut() {
+        new.set_position(None);
+        if !content.trim().is_empty() {
+            new.decor_mut().set_prefix("\n");
+        }
+    }
+    table.insert(name, item);
+    Ok(document.to_string())
+}
+
+/// Write mop.toml readable by the user alone, as it holds tokens and
+/// passwords.
+fn write_private(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to a file being created
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+        }
+    }
+    options
+        .open(path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, content.as_bytes()))
+        .map_err(|e| format!("Failed to write config file: {}", e))
+}
+
+/// A fully commented mop.toml listing every option with its default value.
+pub fn sample_config() -> String {
+    fn value<T: Serialize>(value: &T) -> String {
+        toml::Value::try_from(value)
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    }
+
+    let defaults = Config::default();
+    let mop = &defaults.mop;
+    let players = &defaults.players;
+    let discovery = &defaults.discovery;
+    let keys = &defaults.keys;
+    let theme = &defaults.theme;
+
+    format!(
+        r##"# MOP configuration
+#
+# Every option is listed with its default value. Delete anything you don't
+# change; missing keys fall back to the defaults shown here.
+
+[mop]
+# Command run with the media URL when a file is selected.
+run = {run}
+# Quit as soon as the player has been started.
+auto_close = {auto_close}
+# Open the server's root folder as soon as discovery finds exactly one media
+# server, skipping the server list.
+auto_open_server = {auto_open_server}
+# Reopen the server and folder that were open when MOP last quit, once
+# discovery finds that server again.
+restore_last_location = {restore_last_location}
+# Servers always listed first, in this order, by UDN. Press f on a server to
+# pin or unpin it.
+pinned_servers = {pinned_servers}
+# JSON or TOML file of servers to list at startup even when discovery can't
+# find them: [[servers]] entries with name, url, backend ("upnp", "jellyfin"
+# or "emby") and optionally credentials, the key of a [servers] entry below.
+servers_file = {servers_file}
+# Rescan when an interface comes up or goes down, the default route or Wi-Fi
+# network changes, or the machine wakes from sleep.
+watch_network = {watch_network}
+# Check every listed server still answers this often, in seconds, marking it
+# online, slow or offline in the server list. 0 turns the checks off.
+health_check_secs = {health_check_secs}
+# Ask the server for a file before starting the player, so a missing file or
+# a refused request is reported here rather than the player just exiting.
+check_streams = {check_streams}
+# Tell Jellyfin and Emby servers browsed with an API key when a file starts
+# playing in mpv, how far it has got and where it stopped, so watched status
+# and resume points match their other clients.
+report_playback = {report_playback}
+# Show music libraries by artist, album and track, with track numbers and
+# running times. Press m in a listing to switch until MOP quits.
+music_view = {music_view}
+# Files listed more than once (same title and size, or same URL) are marked
+# with how many copies there are. This lists them once instead, with a choice
+# of copy when one is played.
+collapse_duplicates = {collapse_duplicates}
+# Show the highlighted file's thumbnail or cover art above its details, drawn
+# in colored half blocks. Needs a terminal with 24-bit color.
+thumbnails = {thumbnails}
+# When the sleep timer (z) runs out, it stops mpv and any cast; this quits
+# MOP too.
+sleep_quits = {sleep_quits}
+# Network profile to use when --profile is not given. Empty auto-detects
+# one from [profiles.*.detect].
+profile = {profile}
+
+[players]
+# Per-media-type player commands. Empty uses mop.run.
+video = {video}
+audio = {audio}
+image = {image}
+# Files mpv has no decoder for, going by their DLNA profile or type:
+# "show" lists them as usual, "dim" grays them out and "hide" leaves them
+# out. Only mpv can be asked; with other players everything is shown.
+unplayable = {unplayable}
+
+[discovery]
+# Seconds to wait for SSDP replies per search target.
+ssdp_timeout_secs = {ssdp_timeout}
+# Seconds to wait when fetching a device's description XML.
+description_timeout_secs = {description_timeout}
+# Milliseconds to wait for each host:port probe during the port scan.
+probe_timeout_ms = {probe_timeout}
+# Devices to hide. Entries match a name substring, a host or a location URL.
+blocklist = {blocklist}
+
+[discovery.ssdp]
+enabled = {ssdp_enabled}
+# SSDP search targets, e.g. "ssdp:all" or a device/service URN.
+search_targets = {search_targets}
+# Keep listening after the search, so devices switched on later show up and
+# those switched off are taken off the list.
+listen = {ssdp_listen}
+
+[discovery.port_scan]
+enabled = {port_scan_enabled}
+ports = {ports}
+# IPv4 CIDR ranges to scan, e.g. ["192.168.1.0/24"]. Empty scans the local /24.
+ranges = {ranges}
+
+[keys]
+# Base key bindings: "default" or "vim".
+preset = {preset}
 
-        Ok(())
+[keys.bindings]
+# Overrides on top of the preset, action = ["key", ...]. Actions: {actions}.
+# quit = ["q", "ctrl+c"]
+
+[theme]
+# Color names (e.g. "cyan", "darkgray") or "#rrggbb".
+accent = {accent}
+highlight = {highlight}
+selection = {selection}
+muted = {muted}
+error = {error}
+
+[downloads]
+# Where downloads are saved. Empty uses the system download folder.
+dir = {download_dir}
+# KB/s all downloads share, so they leave room for whatever is playing.
+# 0 for no limit; + and - on the downloads screen change it until MOP quits.
+rate_limit = {download_rate_limit}
+
+[network]
+# Proxy for all HTTP traffic, e.g. "http://proxy:3128" or "socks5://host:1080".
+# Empty uses HTTP_PROXY/HTTPS_PROXY/ALL_PROXY; NO_PROXY is honoured either way.
+proxy = {proxy}
+# KB/s the local relay for files servers only seek in by time passes on.
+# 0 for no limit.
+relay_rate_limit = {relay_rate_limit}
+
+[cast]
+# Renderer (TV, Sonos zone) the send_to_tv key plays on, by UDN.
+# Press d on a receiver in the cast picker to set it.
+default_renderer = {default_renderer}
+
+[quality]
+# Servers that transcode list a file more than once, at other qualities or
+# in other formats. "highest" plays the best of them; "compatible" first
+# narrows them to those the renderer cast to says it plays.
+prefer = {quality_prefer}
+# Leave out variants above this many Mbit/s (a weak renderer, hotel Wi-Fi),
+# falling back to the smallest when none fit. 0 for no cap.
+max_mbps = {max_mbps}
+
+[slideshow]
+# Seconds each image is shown when s plays a folder of images. Passed to mpv,
+# feh and VLC; other viewers use their own setting.
+interval_secs = {slideshow_interval}
+# Show the images in random order instead of as listed.
+shuffle = {slideshow_shuffle}
+
+[columns]
+# Widths of the track number, artist and album columns music tracks are
+# listed with, in characters; the title gets the rest. 0 hides a column.
+track = {track_column}
+artist = {artist_column}
+album = {album_column}
+
+[hide]
+# Entries left out of listings. Each is a glob on the title ("*.nfo",
+# "*sample*"), "re:" and a regular expression, or either after "class:" to
+# match the UPnP class instead. Press . in a listing to show them anyway.
+patterns = {hide_patterns}
+
+[hooks]
+# Commands run when something happens, with the details in environment
+# variables: MOP_EVENT always, MOP_DEVICE_NAME, _LOCATION, _UDN and _TYPE for
+# a device, and MOP_TITLE, MOP_URL, MOP_MIME, MOP_SIZE, MOP_DURATION and
+# MOP_SERVER_NAME for an item. MOP doesn't wait for them. Empty runs nothing.
+# A server or renderer discovered
+on_device_found = {on_device_found}
+# A file started in the player (MOP_PLAYER), and the player exiting while
+# MOP is still running (MOP_EXIT_CODE)
+on_play_start = {on_play_start}
+on_play_end = {on_play_end}
+# `mop download` finished a file (MOP_FILE, MOP_BYTES)
+on_download_complete = {on_download_complete}
+# URL each event is POSTed to as JSON: "event", "message" and the variables
+# above, named without MOP_ and lowercased ("title", "device_name"), with
+# tokens in URLs masked. For Home Assistant, ntfy and the like.
+webhook = {webhook}
+# Which events the webhook gets: "device_found", "play_start", "play_end" or
+# "download_complete". Empty sends all of them.
+webhook_events = {webhook_events}
+
+# Per-server settings, keyed by a name substring, host or location URL.
+# [servers."192.168.1.31"]
+# alias = "NAS"
+# Login for servers behind HTTP Basic/Digest auth
+# username = ""
+# password = ""
+# Plex token; press p on a Plex server in the TUI to link your account.
+# token = ""
+# Jellyfin/Emby API key, to browse those servers without a login.
+# api_key = ""
+# HTTPS with a self-signed certificate: pin it, or skip validation.
+# certificate = "~/.config/mop/nas.pem"
+# accept_invalid_certs = false
+# Connect directly when [network] proxy is set
+# bypass_proxy = false
+# Extra HTTP headers for servers that only answer clients they know, sent
+# with every request and passed on to mpv and VLC
+# headers = {{ "User-Agent" = "Kodi/21.0 (X11; Linux x86_64)" }}
+
+# Network profiles. A profile is picked when its SSID or gateway MAC
+# matches, and can replace [discovery] and add [servers] entries.
+# [profiles.home.detect]
+# ssid = ["Home 5G"]
+# gateway_mac = ["a4:91:b1:00:01:02"]
+#
+# [profiles.home.discovery.port_scan]
+# ranges = ["192.168.1.0/24"]
+#
+# [profiles.home.servers."nas"]
+# alias = "Living room NAS"
+"##,
+        run = value(&mop.run),
+        auto_close = value(&mop.auto_close),
+        auto_open_server = value(&mop.auto_open_server),
+        restore_last_location = value(&mop.restore_last_location),
+        pinned_servers = value(&mop.pinned_servers),
+        servers_file = value(&mop.servers_file),
+        watch_network = value(&mop.watch_network),
+        health_check_secs = value(&mop.health_check_secs),
+        check_streams = value(&mop.check_streams),
+        report_playback = value(&mop.report_playback),
+        music_view = value(&mop.music_view),
+        collapse_duplicates = value(&mop.collapse_duplicates),
+        thumbnails = value(&mop.thumbnails),
+        sleep_quits = value(&mop.sleep_quits),
+        profile = value(&mop.profile),
+        video = value(&players.video),
+        audio = value(&players.audio),
+        image = value(&players.image),
+        unplayable = value(&players.unplayable),
+        ssdp_timeout = value(&discovery.ssdp_timeout_secs),
+        description_timeout = value(&discovery.description_timeout_secs),
+        probe_timeout = value(&discovery.probe_timeout_ms),
+        blocklist = value(&discovery.blocklist),
+        ssdp_enabled = value(&discovery.ssdp.enabled),
+        search_targets = value(&discovery.ssdp.search_targets),
+        ssdp_listen = value(&discovery.ssdp.listen),
+        port_scan_enabled = value(&discovery.port_scan.enabled),
+        ports = value(&discovery.port_scan.ports),
+        ranges = value(&discovery.port_scan.ranges),
+        preset = value(&keys.preset),
+        actions = crate::keymap::Action::ALL.map(|a| a.name()).join(", "),
+        accent = value(&theme.accent),
+        highlight = value(&theme.highlight),
+        selection = value(&theme.selection),
+        muted = value(&theme.muted),
+        error = value(&theme.error),
+        download_dir = value(&defaults.downloads.dir),
+        download_rate_limit = value(&defaults.downloads.rate_limit),
+        proxy = value(&defaults.network.proxy),
+        relay_rate_limit = value(&defaults.network.relay_rate_limit),
+        default_renderer = value(&defaults.cast.default_renderer),
+        quality_prefer = value(&defaults.quality.prefer),
+        max_mbps = value(&defaults.quality.max_mbps),
+        slideshow_interval = value(&defaults.slideshow.interval_secs),
+        slideshow_shuffle = value(&defaults.slideshow.shuffle),
+        track_column = value(&defaults.columns.track),
+        artist_column = value(&defaults.columns.artist),
+        album_column = value(&defaults.columns.album),
+        hide_patterns = value(&defaults.hide.patterns),
+        on_device_found = value(&defaults.hooks.on_device_found),
+        on_play_start = value(&defaults.hooks.on_play_start),
+        on_play_end = value(&defaults.hooks.on_play_end),
+        on_download_complete = value(&defaults.hooks.on_download_complete),
+        webhook = value(&defaults.hooks.webhook),
+        webhook_events = value(&defaults.hooks.webhook_events),
+    )
+}
+
+/// Write the commented sample config to `path`. Refuses to replace an
+/// existing file unless `force` is set.
+pub fn write_sample_config(path: &Path, force: bool) -> Result<(), String> {
+    if path.exists() && !force {
+        return Err(format!("{} already exists (use --force to overwrite)", path.display()));
     }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    std::fs::write(path, sample_config())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
 }
 
-fn get_config_path() -> PathBuf {
+/// Where the settings screen writes the sample, next to the real config.
+pub fn sample_config_path() -> PathBuf {
+    get_config_path().with_file_name("mop.sample.toml")
+}
+
+/// `~/.config/mop.toml`, or `%APPDATA%\mop.toml` on Windows, which has no
+/// `HOME`.
+#[cfg(not(windows))]
+pub fn get_config_path() -> PathBuf {
     if let Ok(home) = std::env::var("HOME") {
         PathBuf::from(home).join(".config").join("mop.toml")
     } else {
         PathBuf::from("mop.toml")
     }
 }
+
+#[cfg(windows)]
+pub fn get_config_path() -> PathBuf {
+    match dirs::config_dir() {
+        Some(dir) => dir.join("mop.toml"),
+        None => PathBuf::from("mop.toml"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_sections_use_defaults() {
+        let (config, warnings) = Config::parse("[mop]\nrun = \"vlc\"\n");
+
+        assert!(warnings.is_empty());
+        assert_eq!(config.mop.run, "vlc");
+        assert!(!config.mop.auto_close);
+        assert_eq!(config.discovery.ssdp_timeout_secs, 5);
+        assert_eq!(config.keys.preset, "default");
+        assert_eq!(config.theme.accent, "cyan");
+    }
+
+    #[test]
+    fn unknown_keys_produce_warnings() {
+        let (config, warnings) =
+            Config::parse("[mop]\nrun = \"mpv\"\nclose_on_run = true\n\n[colours]\naccent = \"red\"\n");
+
+        assert_eq!(config.mop.run, "mpv");
+        assert_eq!(
+            warnings,
+            vec![
+                "Unknown config key 'colours' ignored".to_string(),
+                "Unknown config key 'mop.close_on_run' ignored".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let (_, warnings) = Config::parse("[theme]\naccent = \"not-a-color\"\n");
+
+        assert_eq!(warnings, vec!["Invalid color 'not-a-color' for theme.accent".to_string()]);
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let serialized = toml::to_string_pretty(&Config::default()).unwrap();
+        let (_, warnings) = Config::parse(&serialized);
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn a_linked_token_goes_where_the_server_is_read_from() {
+        let (mut config, _) = Config::parse(
+            "[servers.\"192.168.1.31\"]\nalias = \"NAS\"\n\n[profiles.office.detect]\nssid = [\"Office\"]\n",
+        );
+        let device = crate::upnp::UpnpDevice {
+            name: "Plex Media Server: nas".to_string(),
+            location: "http://192.168.1.31:32469/DeviceDescription.xml".to_string(),
+            ..Default::default()
+        };
+
+        let (key, entry) = config.server_entry_mut(Some("office"), &device, "nas");
+        entry.token = "abc".to_string();
+        assert_eq!(key, "192.168.1.31");
+        let read = config.server_for(Some("office"), &device).unwrap();
+        assert_eq!((read.alias.as_str(), read.token.as_str()), ("NAS", "abc"));
+        assert!(config.servers["192.168.1.31"].token.is_empty());
+
+        let (key, entry) = config.server_entry_mut(None, &device, "nas");
+        entry.token = "def".to_string();
+        assert_eq!(config.server_for(None, &device).unwrap().token, "def");
+        let other = crate::upnp::UpnpDevice { location: "http://192.168.1.40:32469/d.xml".to_string(), ..device };
+        assert_eq!(config.server_entry_mut(None, &other, "192.168.1.40").0, "192.168.1.40");
+        assert_eq!(key, "192.168.1.31");
+    }
+
+    #[test]
+    fn profiles_override_discovery_and_servers() {
+        let (config, warnings) = Config::parse(
+            "[servers.\"192.168.1.31\"]\nalias = \"NAS\"\ntoken = \"abc\"\n\n\
+             [profiles.office.discovery.port_scan]\nenabled = false\n\n\
+             [profiles.office.servers.\"192.168.1.31\"]\nalias = \"Office NAS\"\n\n\
+             [servers.Synology.headers]\n\"User-Agent\" = \"Kodi/21.0\"\n",
+        );
+        let device = crate::upnp::UpnpDevice {
+            name: "Synology DS920".to_string(),
+            location: "http://192.168.1.31:50001/desc.xml".to_string(),
+            base_url: "http://192.168.1.31:50001".to_string(),
+            ..Default::default()
+        };
+
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert!(config.discovery_for(None).port_scan.enabled);
+        assert!(!config.discovery_for(Some("office")).port_scan.enabled);
+        assert_eq!(config.server_for(None, &device).unwrap().alias, "NAS");
+        assert_eq!(config.server_for(Some("office"), &device).unwrap().alias, "Office NAS");
+
+        let mut applied = device.clone();
+        config.apply_server_config(None, &mut applied);
+        assert_eq!((applied.name.as_str(), applied.token.as_deref()), ("NAS", Some("abc")));
+
+        // As with the rest of an entry, the first match decides
+        let settings = config.discovery_settings(None);
+        assert!(settings.connection_for(&device.location, "Synology DS920").headers.is_empty());
+        let elsewhere = "http://192.168.1.40:5000/desc.xml";
+        assert!(settings.connection_for(elsewhere, "").headers.is_empty());
+        let synology = settings.connection_for(elsewhere, "Synology DS920");
+        assert_eq!((synology.headers["User-Agent"].as_str(), synology.location.as_str()), ("Kodi/21.0", elsewhere));
+    }
+
+    #[test]
+    fn sample_config_lists_every_option() {
+        let sample = sample_config();
+        let (config, warnings) = Config::parse(&sample);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(
+            toml::to_string(&config).unwrap(),
+            toml::to_string(&Config::default()).unwrap()
+        );
+
+        // Every key of the serialized defaults must appear in the sample
+        let defaults: toml::Table = toml::from_str(&toml::to_string(&Config::default()).unwrap()).unwrap();
+        let parsed: toml::Table = toml::from_str(&sample).unwrap();
+        fn keys(table: &toml::Table, prefix: &str, out: &mut Vec<String>) {
+            for (key, value) in table {
+                let path = format!("{}{}", prefix, key);
+                if let toml::Value::Table(inner) = value {
+                    // Empty maps ([servers], [profiles]) are shown as commented examples
+                    if inner.is_empty() {
+                        continue;
+                    }
+                    keys(inner, &format!("{}.", path), out);
+                }
+                out.push(path);
+            }
+        }
+        let (mut expected, mut actual) = (Vec::new(), Vec::new());
+        keys(&defaults, "", &mut expected);
+        keys(&parsed, "", &mut actual);
+        for key in expected {
+            assert!(actual.contains(&key), "sample config is missing '{}'", key);
+        }
+    }
+
+    #[test]
+    fn players_fall_back_to_default_command() {
+        let players = PlayersConfig {
+            audio: "mpv --no-video".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(players.for_mime(Some("audio/flac"), "mpv"), "mpv --no-video");
+        assert_eq!(players.for_mime(Some("video/mp4"), "mpv"), "mpv");
+        assert_eq!(players.for_mime(None, "mpv"), "mpv");
+    }
+
+    #[test]
+    fn one_setting_is_saved_without_touching_the_rest_of_the_file() {
+        let content = "# My players\n[mop]\nrun = \"vlc\"  # not mpv\n\n[theme]\naccent = \"red\"\n";
+        let pinned = vec!["uuid:1234".to_string()];
+        let saved = set_setting(content, &["mop", "pinned_servers"], &pinned).unwrap();
+        let expected = concat!(
+            "# My players\n[mop]\nrun = \"vlc\"  # not mpv\npinned_servers = [\"uuid:1234\"]\n",
+            "\n[theme]\naccent = \"red\"\n",
+        );
+        assert_eq!(saved, expected);
+        assert_eq!(Config::parse(&saved).0.mop.pinned_servers, pinned);
+
+        // A section that isn't there yet is added
+        let saved = set_setting("[theme]\naccent = \"red\"\n", &["mop", "music_view"], &true).unwrap();
+        assert_eq!(saved, "[theme]\naccent = \"red\"\n\n[mop]\nmusic_view = true\n");
+
+        // A server entry made for a token holds just that
+        let server = ServerConfig { token: "abc".to_string(), ..Default::default() };
+        let saved = set_setting(&saved, &["profiles", "home", "servers", "Plex NAS"], &server).unwrap();
+        assert!(saved.ends_with("\n[profiles.home.servers.\"Plex NAS\"]\ntoken = \"abc\"\n"), "{}", saved);
+        let saved = set_setting(&saved, &["profiles", "home", "servers", "Plex NAS", "token"], &"xyz").unwrap();
+        let config = Config::parse(&saved).0;
+        assert_eq!(config.profiles["home"].servers["Plex NAS"].token, "xyz");
+        assert!(config.mop.music_view);
+    }
+}
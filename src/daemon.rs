@@ -0,0 +1,391 @@
+// Background daemon that keeps a warm device cache, so the TUI and the
+// headless commands don't have to wait for discovery on every start.
+//
+// Clients talk to it over a Unix socket, one JSON request and one JSON
+// response per line. Nothing here is required: when no daemon is running
+// `start_discovery` falls back to discovering locally. That is also all
+// Windows gets, as std has no Unix sockets there.
+
+use crate::config::DiscoveryConfig;
+use crate::upnp::{DiscoveryMessage, UpnpDevice};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+#[cfg(unix)]
+use {
+    crate::config::Config,
+    std::io::{BufRead, BufReader, Write},
+    std::os::unix::net::{UnixListener, UnixStream},
+    std::sync::{Arc, Condvar, Mutex},
+    std::time::Instant,
+};
+
+#[cfg(not(unix))]
+const UNSUPPORTED: &str = "The daemon needs Unix sockets, which this platform doesn't have";
+
+/// How long a client waits for an answer. The first request after the
+/// daemon starts blocks until its initial discovery is done.
+#[cfg(unix)]
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// Cached devices, if the daemon runs with the same profile.
+    Devices { profile: Option<String> },
+    /// Start a new discovery round now.
+    Refresh,
+    Status,
+    Stop,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Devices { devices: Vec<UpnpDevice> },
+    Status(Status),
+    Ok,
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub profile: Option<String>,
+    pub devices: usize,
+    /// Seconds since the last discovery round finished.
+    pub refreshed_secs_ago: Option<u64>,
+    pub refreshing: bool,
+}
+
+/// The socket clients connect to: in `$XDG_RUNTIME_DIR` when there is one,
+/// so it's private to the user and cleared on logout.
+#[cfg(unix)]
+pub fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("mop.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
+            std::env::temp_dir().join(format!("mop-{}.sock", user))
+        }
+    }
+}
+
+/// Where the daemon leaves the HTTP API's token, next to its socket and
+/// readable only by the user.
+#[cfg(unix)]
+pub fn api_token_path() -> PathBuf {
+    socket_path().with_file_name("mop-api.token")
+}
+
+/// Send one request to the running daemon.
+#[cfg(unix)]
+pub fn request(request: &Request) -> Result<Response, String> {
+    let path = socket_path();
+    let stream = UnixStream::connect(&path).map_err(|e| format!("No daemon at {}: {}", path.display(), e))?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut line = serde_json::to_string(request).map_err(|e| e.to_string())?;
+    line.push('\n');
+    (&stream).write_all(line.as_bytes()).map_err(|e| format!("Failed to talk to daemon: {}", e))?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .map_err(|e| format!("No answer from daemon: {}", e))?;
+    serde_json::from_str(&reply).map_err(|e| format!("Bad answer from daemon: {}", e))
+}
+
+#[cfg(not(unix))]
+pub fn request(_request: &Request) -> Result<Response, String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+/// Discovery for the TUI and headless commands: the daemon's cache when one
+/// is running for the same profile, otherwise a normal local discovery.
+/// Either way results arrive as `DiscoveryMessage`s.
+pub fn start_discovery(settings: DiscoveryConfig, profile: Option<&str>, use_daemon: bool) -> Receiver<DiscoveryMessage> {
+    let settings = crate::termux::discovery_settings(settings);
+    if !use_daemon {
+        return crate::upnp::start_discovery(settings);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let profile = profile.map(str::to_string);
+    std::thread::spawn(move || {
+        match request(&Request::Devices { profile }) {
+            Ok(Response::Devices { devices }) => {
+                tracing::info!(target: "mop::app", "Using {} cached devices from the daemon", devices.len());
+                tx.send(DiscoveryMessage::Started).ok();
+                tx.send(DiscoveryMessage::AllComplete(devices)).ok();
+                return;
+            }
+            Ok(Response::Error { message }) => {
+                tracing::info!(target: "mop::app", "Daemon can't serve this request ({}), discovering locally", message);
+            }
+            Ok(other) => {
+                tracing::warn!(target: "mop::app", "Unexpected daemon response {:?}, discovering locally", other);
+            }
+            Err(e) => tracing::debug!(target: "mop::app", "{}", e),
+        }
+
+        for message in crate::upnp::start_discovery(settings) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(unix)]
+#[derive(Default)]
+struct Cache {
+    /// The config read for the latest round.
+    config: Config,
+    profile: Option<String>,
+    devices: Vec<UpnpDevice>,
+    refreshed: Option<Instant>,
+    refreshing: bool,
+}
+
+#[cfg(unix)]
+struct Shared {
+    cache: Mutex<Cache>,
+    /// Signalled when a discovery round finishes.
+    changed: Condvar,
+    refresh_requested: Mutex<bool>,
+    /// Wakes the refresh loop early when a refresh is requested.
+    wake: Condvar,
+}
+
+#[cfg(unix)]
+impl Shared {
+    /// Wake the refresh loop for a new round now.
+    fn request_refresh(&self) {
+        *self.refresh_requested.lock().unwrap() = true;
+        self.wake.notify_all();
+    }
+
+    /// The cached devices with `[servers]` applied, for the HTTP API. Waits
+    /// for the first round, like a `Devices` request.
+    fn devices(&self) -> Vec<UpnpDevice> {
+        let cache = self.changed.wait_while(self.cache.lock().unwrap(), |cache| cache.refreshed.is_none()).unwrap();
+        let mut devices = cache.devices.clone();
+        for device in &mut devices {
+            cache.config.apply_server_config(cache.profile.as_deref(), device);
+        }
+        devices
+    }
+
+    /// The config read for the latest round, for the HTTP API.
+    fn config(&self) -> Config {
+        self.cache.lock().unwrap().config.clone()
+    }
+}
+
+/// Run the daemon in the foreground until a `Stop` request ends the process.
+/// `requested_profile` is re-resolved before every round, so moving between
+/// networks switches profiles just like restarting MOP would. With `http`,
+/// the HTTP API listens on that address too.
+#[cfg(unix)]
+pub fn run(requested_profile: Option<String>, refresh: Duration, http: Option<String>) -> Result<(), String> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        return Err(format!("A daemon is already listening on {}", path.display()));
+    }
+    // A socket nobody answers on is left over from a daemon that crashed
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path).map_err(|e| format!("Cannot listen on {}: {}", path.display(), e))?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Cannot restrict {}: {}", path.display(), e))?;
+    }
+    eprintln!("mop: daemon listening on {}", path.display());
+
+    let shared = Arc::new(Shared {
+        cache: Mutex::new(Cache::default()),
+        changed: Condvar::new(),
+        refresh_requested: Mutex::new(false),
+        wake: Condvar::new(),
+    });
+    if let Some(address) = http {
+        let (devices, refresher, config) = (shared.clone(), shared.clone(), shared.clone());
+        let backend = crate::api::Backend {
+            devices: Box::new(move || devices.devices()),
+            refresh: Box::new(move || refresher.request_refresh()),
+            config: Box::new(move || config.config()),
+        };
+        let token = crate::api::serve(&address, backend)?;
+        let token_path = api_token_path();
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&token_path)
+                .and_then(|mut file| file.write_all(token.as_bytes()))
+                .map_err(|e| format!("Cannot write {}: {}", token_path.display(), e))?;
+        }
+        eprintln!("mop: HTTP API listening on http://{}, token in {}", address, token_path.display());
+    }
+
+    let refresher = shared.clone();
+    std::thread::spawn(move || refresh_loop(&refresher, requested_profile.as_deref(), refresh));
+    if Config::load().0.mop.watch_network {
+        // A new network makes the cache wrong, so don't wait for the interval
+        let watcher = shared.clone();
+        std::thread::spawn(move || {
+            for _change in crate::netwatch::watch(crate::netwatch::POLL_INTERVAL) {
+                watcher.request_refresh();
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!(target: "mop::app", "Daemon accept failed: {}", e);
+                continue;
+            }
+        };
+        let shared = shared.clone();
+        let path = path.clone();
+        std::thread::spawn(move || {
+            if handle_client(stream, &shared) {
+                eprintln!("mop: daemon stopping");
+                let _ = std::fs::remove_file(&path);
+                std::process::exit(0);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run(_requested_profile: Option<String>, _refresh: Duration, _http: Option<String>) -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(unix)]
+fn refresh_loop(shared: &Shared, requested_profile: Option<&str>, interval: Duration) {
+    loop {
+        let (config, _) = Config::load();
+        crate::proxy::set(&config.network.proxy);
+        crate::hooks::set(&config.hooks);
+        let profile = match crate::profile::resolve_profile(&config, requested_profile) {
+            Ok(profile) => profile,
+            Err(e) => {
+                tracing::error!(target: "mop::config", "Daemon: {}", e);
+                None
+            }
+        };
+        shared.cache.lock().unwrap().refreshing = true;
+
+        tracing::info!(target: "mop::app", "Daemon refreshing devices (profile: {:?})", profile);
+        let settings = crate::termux::discovery_settings(config.discovery_settings(profile.as_deref()));
+        let mut devices = Vec::new();
+        for message in crate::upnp::start_discovery(settings) {
+            if let DiscoveryMessage::AllComplete(found) = message {
+                devices = found;
+                break;
+            }
+        }
+
+        {
+            let mut cache = shared.cache.lock().unwrap();
+            cache.config = config;
+            cache.profile = profile;
+            cache.devices = devices;
+            cache.refreshed = Some(Instant::now());
+            cache.refreshing = false;
+        }
+        shared.changed.notify_all();
+
+        let requested = shared.refresh_requested.lock().unwrap();
+        let (mut requested, _) = shared
+            .wake
+            .wait_timeout_while(requested, interval, |requested| !*requested)
+            .unwrap();
+        *requested = false;
+    }
+}
+
+/// Answer one client. Returns true when the daemon should shut down.
+#[cfg(unix)]
+fn handle_client(stream: UnixStream, shared: &Shared) -> bool {
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line).is_err() {
+        return false;
+    }
+
+    let (response, stop) = match serde_json::from_str::<Request>(&line) {
+        Ok(Request::Devices { profile }) => {
+            // The first round has to finish before the cache means anything
+            let cache = shared
+                .changed
+                .wait_while(shared.cache.lock().unwrap(), |cache| cache.refreshed.is_none())
+                .unwrap();
+            let response = if cache.profile == profile {
+                Response::Devices { devices: cache.devices.clone() }
+            } else {
+                Response::Error {
+                    message: format!("daemon is using profile {:?}, not {:?}", cache.profile, profile),
+                }
+            };
+            (response, false)
+        }
+        Ok(Request::Refresh) => {
+            shared.request_refresh();
+            (Response::Ok, false)
+        }
+        Ok(Request::Status) => {
+            let cache = shared.cache.lock().unwrap();
+            (
+                Response::Status(Status {
+                    pid: std::process::id(),
+                    profile: cache.profile.clone(),
+                    devices: cache.devices.len(),
+                    refreshed_secs_ago: cache.refreshed.map(|at| at.elapsed().as_secs()),
+                    refreshing: cache.refreshing,
+                }),
+                false,
+            )
+        }
+        Ok(Request::Stop) => (Response::Ok, true),
+        Err(e) => (Response::Error { message: format!("Bad request: {}", e) }, false),
+    };
+
+    if let Ok(mut json) = serde_json::to_string(&response) {
+        json.push('\n');
+        let _ = (&stream).write_all(json.as_bytes());
+    }
+    stop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_messages_are_tagged_json() {
+        let request = serde_json::to_string(&Request::Devices { profile: Some("home".to_string()) }).unwrap();
+        assert_eq!(request, r#"{"cmd":"devices","profile":"home"}"#);
+        assert!(matches!(
+            serde_json::from_str::<Request>(r#"{"cmd":"stop"}"#).unwrap(),
+            Request::Stop
+        ));
+
+        let response = Response::Error { message: "nope".to_string() };
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"status":"error","message":"nope"}"#
+        );
+    }
+}
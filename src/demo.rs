@@ -0,0 +1,155 @@
+// `mop --demo`: a MediaServer with a made-up library, served in-process on
+// the loopback interface, stands in for the network. Discovery replays an
+// SSDP answer pointing at it, so everything after that (descriptions,
+// browsing, paging, the info pane) runs the same code as against a real
+// server. The items have no media behind them; playing one fails the way a
+// server that went away would.
+
+use crate::dlna_server::{self, Catalog, Entry};
+use mop_core::capture::{Capture, SsdpResponse};
+use std::path::Path;
+use std::sync::Arc;
+
+pub const SERVER_NAME: &str = "MOP Demo";
+
+/// Items by path, with their size in bytes; every folder on the way is a
+/// container.
+const ITEMS: &[(&str, u64)] = &[
+    ("Movies/Classics/Metropolis (1927).mkv", 4_404_019_200),
+    ("Movies/Classics/Nosferatu (1922).mkv", 2_936_012_800),
+    ("Movies/Classics/The General (1926).mp4", 1_932_735_283),
+    ("Movies/Documentaries/Night Mail (1936).mp4", 734_003_200),
+    ("Movies/Sita Sings the Blues (2008).mkv", 3_221_225_472),
+    ("Movies/Big Buck Bunny (2008).mp4", 276_134_947),
+    ("TV Shows/Wonders of the Lake/Season 1/S01E01 - The Thaw.mkv", 1_181_116_006),
+    ("TV Shows/Wonders of the Lake/Season 1/S01E02 - Spawning.mkv", 1_213_328_179),
+    ("TV Shows/Wonders of the Lake/Season 1/S01E03 - Midsummer.mkv", 1_197_225_574),
+    ("TV Shows/Wonders of the Lake/Season 2/S02E01 - Ice.mkv", 1_288_490_188),
+    ("TV Shows/Wonders of the Lake/Season 2/S02E02 - Under the Ice.mkv", 1_245_540_515),
+    ("Music/Albums/Miles Apart/Kind of Grey/01 - So What Else.flac", 62_914_560),
+    ("Music/Albums/Miles Apart/Kind of Grey/02 - Freddie Mooched.flac", 65_011_712),
+    ("Music/Albums/Miles Apart/Kind of Grey/03 - Blue in Red.flac", 39_845_888),
+    ("Music/Albums/Miles Apart/Kind of Grey/cover.jpg", 524_288),
+    ("Music/Albums/The Subsets/Empty Set/01 - Null.mp3", 7_340_032),
+    ("Music/Albums/The Subsets/Empty Set/02 - Singleton.mp3", 8_388_608),
+    ("Music/Albums/The Subsets/Empty Set/03 - Power Set.mp3", 9_437_184),
+    ("Music/Radio Jingles/Station ID.ogg", 262_144),
+    ("Photos/2023/Harbour at Dawn.jpg", 4_194_304),
+    ("Photos/2023/Lighthouse.jpg", 3_670_016),
+    ("Photos/2024/Snowed-in Station.png", 9_437_184),
+];
+
+/// A folder with more items than one Browse page holds.
+const PODCAST_FOLDER: &str = "Music/Podcasts/Long Wave";
+const PODCAST_EPISODES: u32 = 240;
+
+/// The demo library, flattened: every container and item by object ID.
+struct DemoLibrary {
+    entries: Vec<Entry>,
+}
+
+impl DemoLibrary {
+    fn new() -> Self {
+        let episodes = (1..=PODCAST_EPISODES)
+            .map(|n| (format!("{}/Episode {:03}.mp3", PODCAST_FOLDER, n), 20_971_520 + u64::from(n) * 4096));
+        let items = ITEMS.iter().map(|(path, size)| (path.to_string(), *size)).chain(episodes);
+
+        let mut entries: Vec<Entry> = Vec::new();
+        for (path, size) in items {
+            let mut container = path.as_str();
+            while let Some((parent, _)) = container.rsplit_once('/') {
+                container = parent;
+                if !entries.iter().any(|entry| entry.id == container) {
+                    entries.push(entry(container, true, None));
+                }
+            }
+            entries.push(entry(&path, false, Some(size)));
+        }
+        Self { entries }
+    }
+}
+
+fn entry(id: &str, is_container: bool, size: Option<u64>) -> Entry {
+    Entry {
+        id: id.to_string(),
+        parent_id: dlna_server::parent_id(id),
+        title: id.rsplit('/').next().unwrap_or(id).to_string(),
+        is_container,
+        size,
+        mime: if is_container { None } else { mop_core::mime::for_path(Path::new(id)) },
+    }
+}
+
+impl Catalog for DemoLibrary {
+    fn entry(&self, id: &str) -> Option<Entry> {
+        if id == "0" {
+            return Some(Entry { title: "Root".to_string(), ..entry("0", true, None) });
+        }
+        self.entries.iter().find(|entry| entry.id == id).cloned()
+    }
+
+    fn children(&self, id: &str) -> Option<Vec<Entry>> {
+        if !self.entry(id)?.is_container {
+            return None;
+        }
+        let mut children: Vec<Entry> = self.entries.iter().filter(|entry| entry.parent_id == id).cloned().collect();
+        dlna_server::sort_entries(&mut children);
+        Some(children)
+    }
+}
+
+/// The demo server, running for as long as MOP does.
+pub struct DemoServer {
+    server: dlna_server::LocalServer,
+}
+
+impl DemoServer {
+    pub fn start() -> Result<Self, String> {
+        let server = dlna_server::serve_local(Arc::new(DemoLibrary::new()), SERVER_NAME)?;
+        tracing::info!(target: "mop::app", "Demo server at {}", server.location);
+        Ok(Self { server })
+    }
+
+    /// What a search for `search_targets` would have heard: the demo server
+    /// answering each of them.
+    pub fn capture(&self, search_targets: &[String]) -> Capture {
+        let responses = search_targets
+            .iter()
+            .map(|target| SsdpResponse {
+                from: "127.0.0.1:1900".to_string(),
+                raw: format!(
+                    "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nLOCATION: {}\r\nST: {}\r\nUSN: uuid:mop-demo::{}\r\n\r\n",
+                    self.server.location, target, target
+                ),
+            })
+            .collect();
+        let mut capture = Capture { search_targets: search_targets.to_vec(), responses, ..Default::default() };
+        capture.descriptions.insert(self.server.location.clone(), self.server.description.clone());
+        capture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demo_library_nests_containers_above_its_items() {
+        let library = DemoLibrary::new();
+
+        let top: Vec<String> = library.children("0").unwrap().into_iter().map(|entry| entry.title).collect();
+        assert_eq!(top, vec!["Movies", "Music", "Photos", "TV Shows"]);
+
+        let movies = library.children("Movies").unwrap();
+        assert!(movies[0].is_container && movies[0].title == "Classics");
+        assert_eq!(movies.iter().filter(|entry| !entry.is_container).count(), 2);
+
+        let season = library.children("TV Shows/Wonders of the Lake/Season 1").unwrap();
+        assert_eq!(season[0].parent_id, "TV Shows/Wonders of the Lake/Season 1");
+        assert_eq!(season[0].mime, Some("video/x-matroska"));
+
+        assert_eq!(library.children(PODCAST_FOLDER).unwrap().len(), PODCAST_EPISODES as usize);
+        assert!(library.children("Photos/2023/Lighthouse.jpg").is_none());
+        assert!(library.entry("Nope").is_none());
+    }
+}
@@ -0,0 +1,875 @@
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Response, StatusCode};
+
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+const MAX_AGE_SECS: u64 = 1800;
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+const SERVER_HEADER: &str = concat!("UPnP/1.0 DLNADOC/1.50 MOP/", env!("CARGO_PKG_VERSION"));
+
+const MEDIA_SERVER: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+const CONTENT_DIRECTORY: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+
+/// Characters left unescaped in media URLs (RFC 3986 unreserved).
+const URL_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+pub struct ServeOptions {
+    pub dir: PathBuf,
+    pub name: String,
+    pub port: u16,
+}
+
+/// What the SSDP announcer and HTTP handlers need to know about this server.
+struct DeviceInfo {
+    name: String,
+    udn: String,
+    ip: Ipv4Addr,
+    base_url: String,
+}
+
+/// What a server publishes over ContentDirectory: a tree of containers and
+/// items addressed by object ID, "0" being the root.
+pub trait Catalog: Send + Sync {
+    fn entry(&self, id: &str) -> Option<Entry>;
+
+    /// Containers and items directly below `id`, or `None` if it isn't a
+    /// container.
+    fn children(&self, id: &str) -> Option<Vec<Entry>>;
+
+    /// The file behind item `id`, if it has one to stream.
+    fn file(&self, _id: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// A directory tree published over ContentDirectory. Object IDs are paths
+/// relative to the root.
+pub struct Library {
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    pub parent_id: String,
+    pub title: String,
+    pub is_container: bool,
+    pub size: Option<u64>,
+    pub mime: Option<&'static str>,
+}
+
+impl Library {
+    pub fn new(root: &Path) -> Result<Self, String> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("Cannot serve {}: {}", root.display(), e))?;
+        if !root.is_dir() {
+            return Err(format!("{} is not a directory", root.display()));
+        }
+        Ok(Self { root })
+    }
+
+    /// Map an object ID to a path, refusing anything outside the root.
+    fn resolve(&self, id: &str) -> Option<PathBuf> {
+        if id == "0" {
+            return Some(self.root.clone());
+        }
+        let relative = Path::new(id);
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        let path = self.root.join(relative).canonicalize().ok()?;
+        path.starts_with(&self.root).then_some(path)
+    }
+}
+
+impl Catalog for Library {
+    fn entry(&self, id: &str) -> Option<Entry> {
+        let path = self.resolve(id)?;
+        let title = if id == "0" {
+            "Root".to_string()
+        } else {
+            path.file_name()?.to_string_lossy().to_string()
+        };
+        entry_for(&path, id.to_string(), parent_id(id), title)
+    }
+
+    /// Folders and media files directly below `id`, folders first.
+    fn children(&self, id: &str) -> Option<Vec<Entry>> {
+        let path = self.resolve(id)?;
+        if !path.is_dir() {
+            return None;
+        }
+
+        let mut entries: Vec<Entry> = std::fs::read_dir(&path)
+            .ok()?
+            .flatten()
+            .filter_map(|dir_entry| {
+                let name = dir_entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') {
+                    return None;
+                }
+                let child = if id == "0" { name.clone() } else { format!("{}/{}", id, name) };
+                entry_for(&dir_entry.path(), child, id.to_string(), name)
+            })
+            .collect();
+        sort_entries(&mut entries);
+        Some(entries)
+    }
+
+    fn file(&self, id: &str) -> Option<PathBuf> {
+        self.resolve(id).filter(|path| path.is_file())
+    }
+}
+
+/// Folders first, then by title regardless of case.
+pub fn sort_entries(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| {
+        b.is_container
+            .cmp(&a.is_container)
+            .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+    });
+}
+
+fn entry_for(path: &Path, id: String, parent_id: String, title: String) -> Option<Entry> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.is_dir() {
+        return Some(Entry { id, parent_id, title, is_container: true, size: None, mime: None });
+    }
+    // Only media is published; TVs tend to list everything else as broken items
+    let mime = mop_core::mime::for_path(path)?;
+    Some(Entry {
+        id,
+        parent_id,
+        title,
+        is_container: false,
+        size: Some(metadata.len()),
+        mime: Some(mime),
+    })
+}
+
+/// The object ID of the container holding `id`, for path-like IDs.
+pub fn parent_id(id: &str) -> String {
+    match id.rsplit_once('/') {
+        Some((parent, _)) => parent.to_string(),
+        None if id == "0" => "-1".to_string(),
+        None => "0".to_string(),
+    }
+}
+
+fn upnp_class(entry: &Entry) -> &'static str {
+    match entry.mime.and_then(|mime| mime.split('/').next()) {
+        _ if entry.is_container => "object.container.storageFolder",
+        Some("video") => "object.item.videoItem",
+        Some("audio") => "object.item.audioItem.musicTrack",
+        Some("image") => "object.item.imageItem.photo",
+        _ => "object.item",
+    }
+}
+
+fn escape(text: &str) -> String {
+    quick_xml::escape::escape(text).to_string()
+}
+
+fn media_url(base_url: &str, id: &str) -> String {
+    let path = id
+        .split('/')
+        .map(|segment| utf8_percent_encode(segment, URL_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}/media/{}", base_url, path)
+}
+
+/// DIDL-Lite for a set of entries, with resource URLs under `base_url`.
+pub fn didl(entries: &[Entry], base_url: &str) -> String {
+    let mut didl = String::from(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">"#,
+    );
+    for entry in entries {
+        let common = format!(
+            r#"id="{}" parentID="{}" restricted="1""#,
+            escape(&entry.id),
+            escape(&entry.parent_id)
+        );
+        let body = format!(
+            "<dc:title>{}</dc:title><upnp:class>{}</upnp:class>",
+            escape(&entry.title),
+            upnp_class(entry)
+        );
+        if entry.is_container {
+            didl.push_str(&format!("<container {}>{}</container>", common, body));
+        } else {
+            let mime = entry.mime.unwrap_or("application/octet-stream");
+            didl.push_str(&format!(
+                r#"<item {}>{}<res protocolInfo="http-get:*:{}:*" size="{}">{}</res></item>"#,
+                common,
+                body,
+                mime,
+                entry.size.unwrap_or(0),
+                escape(&media_url(base_url, &entry.id))
+            ));
+        }
+    }
+    didl.push_str("</DIDL-Lite>");
+    didl
+}
+
+/// Publish `options.dir` as a MediaServer until the process is stopped.
+pub fn serve(options: ServeOptions) -> Result<(), String> {
+    let library = Arc::new(Library::new(&options.dir)?);
+    let ip = local_ipv4().ok_or("No IPv4 network address to serve on")?;
+
+    let server = tiny_http::Server::http(("0.0.0.0", options.port))
+        .map_err(|e| format!("Cannot listen on port {}: {}", options.port, e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .unwrap_or(options.port);
+
+    let device = Arc::new(DeviceInfo {
+        udn: udn_for(&library.root, &options.name),
+        name: options.name,
+        ip,
+        base_url: format!("http://{}:{}", ip, port),
+    });
+
+    let announcer = device.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = run_ssdp(&announcer) {
+            tracing::error!(target: "mop::ssdp", "SSDP announcer stopped: {}", e);
+            eprintln!("mop: SSDP announcer stopped: {}", e);
+        }
+    });
+
+    println!(
+        "Serving {} as '{}' at {}/description.xml",
+        library.root.display(),
+        device.name,
+        device.base_url
+    );
+
+    for request in server.incoming_requests() {
+        let device = device.clone();
+        let library = library.clone();
+        std::thread::spawn(move || handle_request(request, &device, library.as_ref()));
+    }
+    Ok(())
+}
+
+/// A server running on the loopback interface.
+pub struct LocalServer {
+    /// URL of the device description.
+    pub location: String,
+    /// The device description itself.
+    pub description: String,
+}
+
+/// Publish `catalog` as a MediaServer on 127.0.0.1 from a background thread,
+/// for as long as the process runs. Nothing is announced over SSDP; only
+/// whoever is handed the location finds it.
+pub fn serve_local(catalog: Arc<dyn Catalog>, name: &str) -> Result<LocalServer, String> {
+    let server = tiny_http::Server::http((Ipv4Addr::LOCALHOST, 0))
+        .map_err(|e| format!("Cannot listen on the loopback interface: {}", e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or("Loopback server has no port")?;
+
+    let device = Arc::new(DeviceInfo {
+        udn: udn_for(Path::new(name), name),
+        name: name.to_string(),
+        ip: Ipv4Addr::LOCALHOST,
+        base_url: format!("http://{}:{}", Ipv4Addr::LOCALHOST, port),
+    });
+    let local = LocalServer {
+        location: format!("{}/description.xml", device.base_url),
+        description: device_description(&device),
+    };
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let device = device.clone();
+            let catalog = catalog.clone();
+            std::thread::spawn(move || handle_request(request, &device, catalog.as_ref()));
+        }
+    });
+    Ok(local)
+}
+
+/// The address advertised to the LAN, preferring private ranges.
+fn local_ipv4() -> Option<Ipv4Addr> {
+    mop_core::network_interfaces::lan_interfaces().first().map(|iface| iface.ip)
+}
+
+/// A stable UDN so TVs keep recognising the server across restarts.
+fn udn_for(root: &Path, name: &str) -> String {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let half = |salt: u8| {
+        let mut hasher = DefaultHasher::new();
+        (root, name, salt).hash(&mut hasher);
+        hasher.finish()
+    };
+    let hex = format!("{:016x}{:016x}", half(0), half(1));
+    format!(
+        "uuid:{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+// SSDP
+
+/// (NT/ST, USN) pairs this device advertises.
+fn advertisements(udn: &str) -> Vec<(String, String)> {
+    vec![
+        ("upnp:rootdevice".to_string(), format!("{}::upnp:rootdevice", udn)),
+        (udn.to_string(), udn.to_string()),
+        (MEDIA_SERVER.to_string(), format!("{}::{}", udn, MEDIA_SERVER)),
+        (CONTENT_DIRECTORY.to_string(), format!("{}::{}", udn, CONTENT_DIRECTORY)),
+        (CONNECTION_MANAGER.to_string(), format!("{}::{}", udn, CONNECTION_MANAGER)),
+    ]
+}
+
+/// The search target of an M-SEARCH request, if that's what `message` is.
+fn search_target(message: &str) -> Option<&str> {
+    let mut lines = message.lines();
+    if !lines.next()?.starts_with("M-SEARCH") {
+        return None;
+    }
+    lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("ST").then(|| value.trim())
+    })
+}
+
+fn run_ssdp(device: &DeviceInfo) -> Result<(), String> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(|e| e.to_string())?;
+    socket.set_reuse_address(true).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true).map_err(|e| e.to_string())?;
+    socket
+        .bind(&SocketAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SSDP_PORT)).into())
+        .map_err(|e| format!("Cannot bind SSDP port {}: {}", SSDP_PORT, e))?;
+    socket
+        .join_multicast_v4(&SSDP_ADDR, &device.ip)
+        .map_err(|e| format!("Cannot join SSDP multicast group: {}", e))?;
+    socket.set_multicast_if_v4(&device.ip).map_err(|e| e.to_string())?;
+
+    let socket: UdpSocket = socket.into();
+    socket.set_read_timeout(Some(Duration::from_secs(1))).map_err(|e| e.to_string())?;
+
+    let location = format!("{}/description.xml", device.base_url);
+    let multicast = SocketAddr::from(SocketAddrV4::new(SSDP_ADDR, SSDP_PORT));
+    let mut last_notify: Option<Instant> = None;
+    let mut buf = [0u8; 2048];
+
+    loop {
+        if last_notify.is_none_or(|at| at.elapsed() >= NOTIFY_INTERVAL) {
+            for (nt, usn) in advertisements(&device.udn) {
+                let notify = format!(
+                    "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nCACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nNT: {}\r\nNTS: ssdp:alive\r\nSERVER: {}\r\nUSN: {}\r\n\r\n",
+                    SSDP_ADDR, SSDP_PORT, MAX_AGE_SECS, location, nt, SERVER_HEADER, usn
+                );
+                if let Err(e) = socket.send_to(notify.as_bytes(), multicast) {
+                    tracing::warn!(target: "mop::ssdp", "Failed to send NOTIFY: {}", e);
+                }
+            }
+            last_notify = Some(Instant::now());
+        }
+
+        let (len, sender) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+        let message = String::from_utf8_lossy(&buf[..len]);
+        let Some(target) = search_target(&message) else { continue };
+
+        for (st, usn) in advertisements(&device.udn) {
+            if target != "ssdp:all" && target != st {
+                continue;
+            }
+            tracing::debug!(target: "mop::ssdp", "Answering M-SEARCH for {} from {}", st, sender);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\n\r\n",
+                MAX_AGE_SECS, location, SERVER_HEADER, st, usn
+            );
+            let _ = socket.send_to(response.as_bytes(), sender);
+        }
+    }
+}
+
+// HTTP
+
+type HttpResponse = Response<Box<dyn Read + Send>>;
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn respond_bytes(status: u16, content_type: &str, body: Vec<u8>) -> HttpResponse {
+    let len = body.len();
+    Response::new(
+        StatusCode(status),
+        vec![header("Content-Type", content_type), header("Server", SERVER_HEADER)],
+        Box::new(Cursor::new(body)),
+        Some(len),
+        None,
+    )
+}
+
+fn xml(status: u16, body: String) -> HttpResponse {
+    respond_bytes(status, "text/xml; charset=\"utf-8\"", body.into_bytes())
+}
+
+fn handle_request(mut request: Request, device: &DeviceInfo, library: &dyn Catalog) {
+    let method = request.method().as_str().to_uppercase();
+    let path = request.url().split('?').next().unwrap_or("/").to_string();
+    tracing::debug!(target: "mop::net", "{} {}", method, path);
+
+    let response = match (method.as_str(), path.as_str()) {
+        ("GET" | "HEAD", "/description.xml") => xml(200, device_description(device)),
+        ("GET" | "HEAD", "/ContentDirectory.xml") => xml(200, CONTENT_DIRECTORY_SCPD.to_string()),
+        ("GET" | "HEAD", "/ConnectionManager.xml") => xml(200, CONNECTION_MANAGER_SCPD.to_string()),
+        ("POST", "/control/ContentDirectory" | "/control/ConnectionManager") => {
+            let action = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("SOAPAction"))
+                .and_then(|h| h.value.as_str().trim_matches('"').rsplit_once('#').map(|(_, a)| a.to_string()))
+                .unwrap_or_default();
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+            if path == "/control/ContentDirectory" {
+                content_directory_action(&action, &body, device, library)
+            } else {
+                connection_manager_action(&action)
+            }
+        }
+        // Eventing isn't implemented, but some renderers refuse servers that
+        // reject subscriptions outright
+        ("SUBSCRIBE", _) => respond_bytes(200, "text/plain", Vec::new())
+            .with_header(header("SID", &format!("{}::events", device.udn)))
+            .with_header(header("TIMEOUT", "Second-1800")),
+        ("UNSUBSCRIBE", _) => respond_bytes(200, "text/plain", Vec::new()),
+        ("GET" | "HEAD", media) if media.starts_with("/media/") => {
+            let id = percent_decode_str(&media["/media/".len()..])
+                .decode_utf8_lossy()
+                .to_string();
+            let range = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("Range"))
+                .map(|h| h.value.as_str().to_string());
+            serve_file(library, &id, range.as_deref())
+        }
+        _ => respond_bytes(404, "text/plain", b"Not found".to_vec()),
+    };
+
+    if let Err(e) = request.respond(response) {
+        tracing::debug!(target: "mop::net", "Client went away during {} {}: {}", method, path, e);
+    }
+}
+
+/// Parse a single "bytes=start-end" range against a file of `len` bytes.
+pub fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    let (start, end) = spec.split(',').next()?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)?),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len.checked_sub(1)?)),
+    };
+    (start <= end && start < len).then_some((start, end))
+}
+
+fn serve_file(library: &dyn Catalog, id: &str, range: Option<&str>) -> HttpResponse {
+    let Some(path) = library.file(id) else {
+        return respond_bytes(404, "text/plain", b"Not found".to_vec());
+    };
+    let Some(mime) = mop_core::mime::for_path(&path) else {
+        return respond_bytes(404, "text/plain", b"Not found".to_vec());
+    };
+    let Ok(mut file) = File::open(&path) else {
+        return respond_bytes(403, "text/plain", b"Forbidden".to_vec());
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    let mut headers = vec![
+        header("Content-Type", mime),
+        header("Accept-Ranges", "bytes"),
+        header("Server", SERVER_HEADER),
+        header("transferMode.dlna.org", "Streaming"),
+        header("contentFeatures.dlna.org", "DLNA.ORG_OP=01;DLNA.ORG_FLAGS=01700000000000000000000000000000"),
+    ];
+
+    let (status, start, end) = match range {
+        None => (200, 0, len.saturating_sub(1)),
+        Some(range) => match parse_range(range, len) {
+            Some((start, end)) => {
+                headers.push(header("Content-Range", &format!("bytes {}-{}/{}", start, end, len)));
+                (206, start, end)
+            }
+            None => {
+                let mut response = respond_bytes(416, "text/plain", Vec::new());
+                response.add_header(header("Content-Range", &format!("bytes */{}", len)));
+                return response;
+            }
+        },
+    };
+
+    let body_len = if len == 0 { 0 } else { end - start + 1 };
+    if file.seek(SeekFrom::Start(start)).is_err() {
+        return respond_bytes(500, "text/plain", Vec::new());
+    }
+    Response::new(
+        StatusCode(status),
+        headers,
+        Box::new(file.take(body_len)),
+        Some(body_len as usize),
+        None,
+    )
+}
+
+// SOAP
+
+fn soap_envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body>{}</s:Body></s:Envelope>"#,
+        body
+    )
+}
+
+fn soap_response(service: &str, action: &str, arguments: &[(&str, String)]) -> HttpResponse {
+    let arguments: String = arguments
+        .iter()
+        .map(|(name, value)| format!("<{0}>{1}</{0}>", name, escape(value)))
+        .collect();
+    xml(
+        200,
+        soap_envelope(&format!(
+            r#"<u:{0}Response xmlns:u="{1}">{2}</u:{0}Response>"#,
+            action, service, arguments
+        )),
+    )
+}
+
+fn soap_fault(code: u16, description: &str) -> HttpResponse {
+    xml(
+        500,
+        soap_envelope(&format!(
+            r#"<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault>"#,
+            code, description
+        )),
+    )
+}
+
+fn content_directory_action(action: &str, body: &str, device: &DeviceInfo, library: &dyn Catalog) -> HttpResponse {
+    let argument = |name: &str| crate::upnp::extract_xml_value(body, name).unwrap_or_default();
+    match action {
+        "Browse" => {
+            let object_id = quick_xml::escape::unescape(&argument("ObjectID"))
+                .map(|id| id.to_string())
+                .unwrap_or_default();
+            let start: usize = argument("StartingIndex").parse().unwrap_or(0);
+            let count: usize = argument("RequestedCount").parse().unwrap_or(0);
+
+            let entries = if argument("BrowseFlag") == "BrowseMetadata" {
+                library.entry(&object_id).map(|entry| vec![entry])
+            } else {
+                library.children(&object_id)
+            };
+            let Some(entries) = entries else {
+                return soap_fault(701, "No such object");
+            };
+
+            let total = entries.len();
+            let page: Vec<Entry> = entries
+                .into_iter()
+                .skip(start)
+                .take(if count == 0 { usize::MAX } else { count })
+                .collect();
+            soap_response(
+                CONTENT_DIRECTORY,
+                "Browse",
+                &[
+                    ("Result", didl(&page, &device.base_url)),
+                    ("NumberReturned", page.len().to_string()),
+                    ("TotalMatches", total.to_string()),
+                    ("UpdateID", "1".to_string()),
+                ],
+            )
+        }
+        "GetSearchCapabilities" => soap_response(CONTENT_DIRECTORY, action, &[("SearchCaps", String::new())]),
+        "GetSortCapabilities" => soap_response(CONTENT_DIRECTORY, action, &[("SortCaps", String::new())]),
+        "GetSystemUpdateID" => soap_response(CONTENT_DIRECTORY, action, &[("Id", "1".to_string())]),
+        _ => soap_fault(401, "Invalid Action"),
+    }
+}
+
+fn connection_manager_action(action: &str) -> HttpResponse {
+    match action {
+        "GetProtocolInfo" => {
+            let source = SUPPORTED_MIME_TYPES
+                .iter()
+                .map(|mime| format!("http-get:*:{}:*", mime))
+                .collect::<Vec<_>>()
+                .join(",");
+            soap_response(CONNECTION_MANAGER, action, &[("Source", source), ("Sink", String::new())])
+        }
+        "GetCurrentConnectionIDs" => {
+            soap_response(CONNECTION_MANAGER, action, &[("ConnectionIDs", "0".to_string())])
+        }
+        "GetCurrentConnectionInfo" => soap_response(
+            CONNECTION_MANAGER,
+            action,
+            &[
+                ("RcsID", "-1".to_string()),
+                ("AVTransportID", "-1".to_string()),
+                ("ProtocolInfo", String::new()),
+                ("PeerConnectionManager", String::new()),
+                ("PeerConnectionID", "-1".to_string()),
+                ("Direction", "Output".to_string()),
+                ("Status", "OK".to_string()),
+            ],
+        ),
+        _ => soap_fault(401, "Invalid Action"),
+    }
+}
+
+const SUPPORTED_MIME_TYPES: &[&str] = &[
+    "video/mp4",
+    "video/x-matroska",
+    "video/x-msvideo",
+    "video/quicktime",
+    "video/webm",
+    "video/mp2t",
+    "video/mpeg",
+    "video/x-ms-wmv",
+    "audio/mpeg",
+    "audio/flac",
+    "audio/ogg",
+    "audio/opus",
+    "audio/mp4",
+    "audio/wav",
+    "audio/x-ms-wma",
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+];
+
+fn device_description(device: &DeviceInfo) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0" xmlns:dlna="urn:schemas-dlna-org:device-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <device>
+    <deviceType>{media_server}</deviceType>
+    <friendlyName>{name}</friendlyName>
+    <manufacturer>MOP</manufacturer>
+    <modelName>MOP</modelName>
+    <modelNumber>{version}</modelNumber>
+    <UDN>{udn}</UDN>
+    <dlna:X_DLNADOC>DMS-1.50</dlna:X_DLNADOC>
+    <serviceList>
+      <service>
+        <serviceType>{content_directory}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ContentDirectory</serviceId>
+        <SCPDURL>/ContentDirectory.xml</SCPDURL>
+        <controlURL>/control/ContentDirectory</controlURL>
+        <eventSubURL>/event/ContentDirectory</eventSubURL>
+      </service>
+      <service>
+        <serviceType>{connection_manager}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ConnectionManager</serviceId>
+        <SCPDURL>/ConnectionManager.xml</SCPDURL>
+        <controlURL>/control/ConnectionManager</controlURL>
+        <eventSubURL>/event/ConnectionManager</eventSubURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#,
+        media_server = MEDIA_SERVER,
+        name = escape(&device.name),
+        version = env!("CARGO_PKG_VERSION"),
+        udn = device.udn,
+        content_directory = CONTENT_DIRECTORY,
+        connection_manager = CONNECTION_MANAGER,
+    )
+}
+
+const CONTENT_DIRECTORY_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action><name>Browse</name><argumentList>
+      <argument><name>ObjectID</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_ObjectID</relatedStateVariable></argument>
+      <argument><name>BrowseFlag</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_BrowseFlag</relatedStateVariable></argument>
+      <argument><name>Filter</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Filter</relatedStateVariable></argument>
+      <argument><name>StartingIndex</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Index</relatedStateVariable></argument>
+      <argument><name>RequestedCount</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+      <argument><name>SortCriteria</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_SortCriteria</relatedStateVariable></argument>
+      <argument><name>Result</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Result</relatedStateVariable></argument>
+      <argument><name>NumberReturned</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+      <argument><name>TotalMatches</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument>
+      <argument><name>UpdateID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_UpdateID</relatedStateVariable></argument>
+    </argumentList></action>
+    <action><name>GetSearchCapabilities</name><argumentList>
+      <argument><name>SearchCaps</name><direction>out</direction><relatedStateVariable>SearchCapabilities</relatedStateVariable></argument>
+    </argumentList></action>
+    <action><name>GetSortCapabilities</name><argumentList>
+      <argument><name>SortCaps</name><direction>out</direction><relatedStateVariable>SortCapabilities</relatedStateVariable></argument>
+    </argumentList></action>
+    <action><name>GetSystemUpdateID</name><argumentList>
+      <argument><name>Id</name><direction>out</direction><relatedStateVariable>SystemUpdateID</relatedStateVariable></argument>
+    </argumentList></action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ObjectID</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_BrowseFlag</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>BrowseMetadata</allowedValue><allowedValue>BrowseDirectChildren</allowedValue></allowedValueList></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Filter</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Index</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Count</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_SortCriteria</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Result</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_UpdateID</name><dataType>ui4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SearchCapabilities</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>SortCapabilities</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>SystemUpdateID</name><dataType>ui4</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+
+const CONNECTION_MANAGER_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion><major>1</major><minor>0</minor></specVersion>
+  <actionList>
+    <action><name>GetProtocolInfo</name><argumentList>
+      <argument><name>Source</name><direction>out</direction><relatedStateVariable>SourceProtocolInfo</relatedStateVariable></argument>
+      <argument><name>Sink</name><direction>out</direction><relatedStateVariable>SinkProtocolInfo</relatedStateVariable></argument>
+    </argumentList></action>
+    <action><name>GetCurrentConnectionIDs</name><argumentList>
+      <argument><name>ConnectionIDs</name><direction>out</direction><relatedStateVariable>CurrentConnectionIDs</relatedStateVariable></argument>
+    </argumentList></action>
+    <action><name>GetCurrentConnectionInfo</name><argumentList>
+      <argument><name>ConnectionID</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_ConnectionID</relatedStateVariable></argument>
+      <argument><name>RcsID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_RcsID</relatedStateVariable></argument>
+      <argument><name>AVTransportID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_AVTransportID</relatedStateVariable></argument>
+      <argument><name>ProtocolInfo</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ProtocolInfo</relatedStateVariable></argument>
+      <argument><name>PeerConnectionManager</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ConnectionManager</relatedStateVariable></argument>
+      <argument><name>PeerConnectionID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ConnectionID</relatedStateVariable></argument>
+      <argument><name>Direction</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Direction</relatedStateVariable></argument>
+      <argument><name>Status</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_ConnectionStatus</relatedStateVariable></argument>
+    </argumentList></action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="yes"><name>SourceProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>SinkProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>CurrentConnectionIDs</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ConnectionStatus</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>OK</allowedValue><allowedValue>ContentFormatMismatch</allowedValue><allowedValue>InsufficientBandwidth</allowedValue><allowedValue>UnreliableChannel</allowedValue><allowedValue>Unknown</allowedValue></allowedValueList></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ConnectionManager</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_Direction</name><dataType>string</dataType>
+      <allowedValueList><allowedValue>Input</allowedValue><allowedValue>Output</allowedValue></allowedValueList></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_ConnectionID</name><dataType>i4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_AVTransportID</name><dataType>i4</dataType></stateVariable>
+    <stateVariable sendEvents="no"><name>A_ARG_TYPE_RcsID</name><dataType>i4</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library_fixture(name: &str) -> (PathBuf, Library) {
+        let root = std::env::temp_dir().join(format!("mop-serve-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("Music/Jazz & Blues")).unwrap();
+        std::fs::write(root.join("Music/Jazz & Blues/Blue Train.flac"), b"fLaC").unwrap();
+        std::fs::write(root.join("Music/notes.txt"), b"not media").unwrap();
+        std::fs::write(root.join(".hidden.mp4"), b"").unwrap();
+        let library = Library::new(&root).unwrap();
+        (root, library)
+    }
+
+    #[test]
+    fn library_lists_media_and_folders_only() {
+        let (root, library) = library_fixture("list");
+
+        let top = library.children("0").unwrap();
+        assert_eq!(top.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["Music"]);
+
+        let music = library.children("Music").unwrap();
+        assert_eq!(music.len(), 1);
+        assert_eq!(music[0].id, "Music/Jazz & Blues");
+        assert_eq!(music[0].parent_id, "Music");
+
+        let tracks = library.children("Music/Jazz & Blues").unwrap();
+        assert_eq!(tracks[0].mime, Some("audio/flac"));
+        assert_eq!(tracks[0].size, Some(4));
+
+        assert!(library.resolve("../etc").is_none());
+        assert!(library.resolve("/etc").is_none());
+        assert!(library.children("Nope").is_none());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn didl_escapes_titles_and_encodes_urls() {
+        let entry = Entry {
+            id: "Music/Jazz & Blues/Blue Train.flac".to_string(),
+            parent_id: "Music/Jazz & Blues".to_string(),
+            title: "Blue Train.flac".to_string(),
+            is_container: false,
+            size: Some(4),
+            mime: Some("audio/flac"),
+        };
+        let didl = didl(&[entry], "http://192.168.1.5:8200");
+
+        assert!(didl.contains(r#"<item id="Music/Jazz &amp; Blues/Blue Train.flac" parentID="Music/Jazz &amp; Blues""#));
+        assert!(didl.contains(r#"<res protocolInfo="http-get:*:audio/flac:*" size="4">http://192.168.1.5:8200/media/Music/Jazz%20%26%20Blues/Blue%20Train.flac</res>"#));
+        assert!(didl.contains("<upnp:class>object.item.audioItem.musicTrack</upnp:class>"));
+    }
+
+    #[test]
+    fn parses_ranges_and_search_targets() {
+        assert_eq!(parse_range("bytes=0-", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=90-500", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+
+        let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nst: urn:schemas-upnp-org:device:MediaServer:1\r\n\r\n";
+        assert_eq!(search_target(search), Some(MEDIA_SERVER));
+        assert_eq!(search_target("NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\n\r\n"), None);
+    }
+}
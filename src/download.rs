@@ -0,0 +1,471 @@
+use crate::app::DirectoryItem;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// All downloads together, from `[downloads] rate_limit` and the
+/// Downloads screen.
+static DOWNLOADS: RateLimit = RateLimit::new();
+
+/// Bytes transferred so far and the total size when the server reports it.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Downloaded(u64),
+    AlreadyPresent,
+}
+
+/// File name for a downloaded item: the title with path separators removed,
+/// plus the extension from the resource URL when the title has none.
+pub fn file_name(item: &DirectoryItem) -> String {
+    let mut name = sanitize(&item.name);
+
+    let extension = item
+        .url
+        .as_deref()
+        .and_then(|url| url::Url::parse(url).ok())
+        .and_then(|url| {
+            let last = url.path_segments()?.next_back()?.to_string();
+            Path::new(&last)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+        });
+    if let Some(extension) = extension
+        && !name.to_lowercase().ends_with(&format!(".{}", extension))
+    {
+        name.push('.');
+        name.push_str(&extension);
+    }
+    name
+}
+
+/// Make a title safe to use as a single path component.
+pub fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | '\0') { '_' } else { c })
+        .collect();
+    match cleaned.trim() {
+        "" | "." | ".." => "_".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Checks a finished download has to pass before it gets its final name.
+#[derive(Debug, Clone, Default)]
+pub struct Verify {
+    /// Size the media server reported for the item.
+    pub size: Option<u64>,
+    /// Expected SHA-256, as hex.
+    pub sha256: Option<String>,
+}
+
+/// Kept next to a `.part` file, so a later run only resumes when it is
+/// fetching the same URL and the server still has the same file.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct PartState {
+    url: String,
+    total: Option<u64>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl PartState {
+    /// The value for `If-Range`: the server only honours the range when the
+    /// file hasn't changed, and sends all of it otherwise.
+    fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+/// Download `url` from `server` to `dest`, resuming from `dest.part` if an
+/// earlier attempt was interrupted. The file only gets its final name once
+/// complete and verified.
+pub fn download(
+    url: &str,
+    dest: &Path,
+    verify: &Verify,
+    server: &crate::upnp::UpnpDevice,
+    on_progress: impl FnMut(Progress),
+) -> Result<Outcome, String> {
+    download_until(url, dest, verify, server, &AtomicBool::new(false), on_progress)
+}
+
+/// `download`, giving up with the `.part` file left to resume from once
+/// `stop` is set.
+pub fn download_until(
+    url: &str,
+    dest: &Path,
+    verify: &Verify,
+    server: &crate::upnp::UpnpDevice,
+    stop: &AtomicBool,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<Outcome, String> {
+    let _span = tracing::info_span!(target: "mop::net", "download", file = %dest.display()).entered();
+    if dest.exists() {
+        if let Some(expected) = &verify.sha256 {
+            check_sha256(dest, expected)?;
+        }
+        return Ok(Outcome::AlreadyPresent);
+    }
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let partial = partial_path(dest);
+    let state_path = state_path(dest);
+    let previous = read_state(&state_path).filter(|state| state.url == url);
+    let resume_from = match &previous {
+        Some(_) => std::fs::metadata(&partial).map(|m| m.len()).unwrap_or(0),
+        None => 0,
+    };
+
+    let client = server
+        .blocking_client_builder()?
+        .timeout(None)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client.get(url);
+    if let Some(previous) = previous.as_ref().filter(|_| resume_from > 0) {
+        tracing::info!(target: "mop::net", "Resuming {} at byte {}", dest.display(), resume_from);
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+        if let Some(validator) = previous.validator() {
+            request = request.header(reqwest::header::IF_RANGE, validator);
+        }
+    }
+    let mut response = crate::auth::send_blocking(&client, request, server.credentials.as_ref())?;
+
+    let status = response.status();
+    // Asking for the bytes after the last one: the `.part` is already whole,
+    // as when MOP stopped between the last read and the rename
+    let whole = [previous.as_ref().and_then(|previous| previous.total), verify.size];
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && whole.iter().flatten().any(|&size| size == resume_from) {
+        tracing::info!(target: "mop::net", "{} was already all there, finishing it", dest.display());
+        return finish(dest, verify, resume_from);
+    }
+    if !status.is_success() {
+        return Err(format!("Server returned {}", status));
+    }
+
+    // Servers that ignore Range, or whose file changed, answer 200 with the
+    // whole file
+    let resumed = resume_from > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+    if resume_from > 0 && !resumed {
+        tracing::info!(target: "mop::net", "Server sent all of {}, starting over", dest.display());
+    }
+    let mut downloaded = if resumed { resume_from } else { 0 };
+    let total = response.content_length().map(|len| len + downloaded);
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let state = PartState {
+        url: url.to_string(),
+        total,
+        etag: header(reqwest::header::ETAG),
+        last_modified: header(reqwest::header::LAST_MODIFIED),
+    };
+    if resumed && previous.as_ref().is_some_and(|previous| previous.total != state.total) {
+        // Same URL, different file behind it: the bytes on disk are useless
+        let _ = std::fs::remove_file(&partial);
+        let _ = std::fs::remove_file(&state_path);
+        return Err("File changed on the server since the last attempt, retry to start over".to_string());
+    }
+    write_state(&state_path, &state)?;
+
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(&partial)
+    } else {
+        File::create(&partial)
+    }
+    .map_err(|e| format!("Failed to open {}: {}", partial.display(), e))?;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    on_progress(Progress { downloaded, total });
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Err(STOPPED.to_string());
+        }
+        let read = response
+            .read(&mut buffer)
+            .map_err(|e| format!("Download interrupted: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        DOWNLOADS.take(read);
+        file.write_all(&buffer[..read])
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        downloaded += read as u64;
+        on_progress(Progress { downloaded, total });
+    }
+
+    if let Some(total) = total
+        && downloaded < total
+    {
+        return Err(format!("Download incomplete: {} of {} bytes", downloaded, total));
+    }
+
+    finish(dest, verify, downloaded)
+}
+
+/// Verify the `downloaded` bytes in `dest`'s `.part` file and give it its
+/// final name.
+fn finish(dest: &Path, verify: &Verify, downloaded: u64) -> Result<Outcome, String> {
+    let partial = partial_path(dest);
+    let state_path = state_path(dest);
+    // A complete file that fails verification won't get better by resuming
+    let verified = match verify.size {
+        Some(size) if size != downloaded => Err(format!(
+            "Size mismatch: server reported {} bytes, got {}",
+            size, downloaded
+        )),
+        _ => match &verify.sha256 {
+            Some(expected) => check_sha256(&partial, expected),
+            None => Ok(()),
+        },
+    };
+    if let Err(e) = verified {
+        let _ = std::fs::remove_file(&partial);
+        let _ = std::fs::remove_file(&state_path);
+        return Err(e);
+    }
+
+    std::fs::rename(&partial, dest)
+        .map_err(|e| format!("Failed to move {} into place: {}", partial.display(), e))?;
+    let _ = std::fs::remove_file(&state_path);
+    Ok(Outcome::Downloaded(downloaded))
+}
+
+/// What `download_until` fails with when it was stopped.
+pub const STOPPED: &str = "Stopped";
+
+/// Hold all downloads together to `bytes_per_sec`; 0 lifts the limit.
+pub fn set_rate_limit(bytes_per_sec: u64) {
+    DOWNLOADS.set(bytes_per_sec);
+}
+
+pub fn rate_limit() -> u64 {
+    DOWNLOADS.get()
+}
+
+/// A rate in bytes a second shared by every reader held to it; 0 is no
+/// limit.
+pub struct RateLimit {
+    bytes_per_sec: AtomicU64,
+    /// When the bytes read so far are paid for: each read pushes it on by
+    /// as long as its bytes take at the limit, and the reader waits for it.
+    paid_until: Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        Self { bytes_per_sec: AtomicU64::new(0), paid_until: Mutex::new(None) }
+    }
+
+    pub fn set(&self, bytes_per_sec: u64) {
+        self.bytes_per_sec.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.bytes_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// Wait as long as reading `bytes` more takes at the limit, on top of
+    /// what every reader has read before. Time spent idle isn't saved up.
+    pub fn take(&self, bytes: usize) {
+        let limit = self.get();
+        if limit == 0 || bytes == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let until = {
+            let Ok(mut paid_until) = self.paid_until.lock() else { return };
+            let until = paid_until.filter(|&until| until > now).unwrap_or(now)
+                + Duration::from_secs_f64(bytes as f64 / limit as f64);
+            *paid_until = Some(until);
+            until
+        };
+        std::thread::sleep(until - now);
+    }
+}
+
+/// A reader held to a `RateLimit`.
+pub struct Throttled<R> {
+    inner: R,
+    limit: &'static RateLimit,
+}
+
+impl<R> Throttled<R> {
+    pub fn new(inner: R, limit: &'static RateLimit) -> Self {
+        Self { inner, limit }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.limit.take(read);
+        Ok(read)
+    }
+}
+
+/// Remove what a stopped download left behind to resume from.
+pub fn discard_partial(dest: &Path) {
+    let _ = std::fs::remove_file(partial_path(dest));
+    let _ = std::fs::remove_file(state_path(dest));
+}
+
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+fn state_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part.json");
+    dest.with_file_name(name)
+}
+
+fn read_state(path: &Path) -> Option<PartState> {
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn write_state(path: &Path, state: &PartState) -> Result<(), String> {
+    let json = serde_json::to_string(state).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn check_sha256(path: &Path, expected: &str) -> Result<(), String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let actual: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(format!("SHA-256 mismatch: expected {}, got {}", expected.trim(), actual))
+    }
+}
+
+/// "12.3 MB" style sizes for progress output.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, url: &str) -> DirectoryItem {
+        DirectoryItem {
+            name: name.to_string(),
+            url: Some(url.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn file_names_keep_url_extension() {
+        assert_eq!(file_name(&item("Intro", "http://nas/media/12.flac")), "Intro.flac");
+        assert_eq!(file_name(&item("Intro.FLAC", "http://nas/media/12.flac")), "Intro.FLAC");
+        assert_eq!(file_name(&item("AC/DC - T.N.T.", "http://nas/media/7")), "AC_DC - T.N.T.");
+        assert_eq!(sanitize(".."), "_");
+    }
+
+    #[test]
+    fn resumes_only_with_a_strong_validator_or_date() {
+        let mut state = PartState {
+            url: "http://nas/media/12.mkv".to_string(),
+            total: Some(4096),
+            etag: Some("W/\"abc\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        };
+        assert_eq!(state.validator(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+        state.etag = Some("\"abc\"".to_string());
+        assert_eq!(state.validator(), Some("\"abc\""));
+
+        let path = std::env::temp_dir().join(format!("mop-state-{}.part.json", std::process::id()));
+        write_state(&path, &state).unwrap();
+        assert_eq!(read_state(&path), Some(state));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_part_file_already_whole_is_finished_when_the_server_refuses_the_range() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/media/12.flac", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            use std::io::{BufRead, BufReader};
+            for mut connection in listener.incoming().flatten() {
+                let mut reader = BufReader::new(connection.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                let reply = "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                let _ = connection.write_all(reply.as_bytes());
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("mop-whole-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("Intro.flac");
+        std::fs::write(partial_path(&dest), b"fLaC").unwrap();
+        let state = PartState { url: url.clone(), total: Some(4), etag: None, last_modified: None };
+        write_state(&state_path(&dest), &state).unwrap();
+        let sha256 = "70bf93310284b039f237e82db4407df3e3ca642fac2b9ebb02973764424c7418".to_string();
+        let server = crate::upnp::UpnpDevice::default();
+
+        let verify = Verify { size: Some(4), sha256: Some(sha256) };
+        let outcome = download(&url, &dest, &verify, &server, |_| {}).unwrap();
+        assert!(matches!(outcome, Outcome::Downloaded(4)));
+        assert_eq!(std::fs::read(&dest).unwrap(), b"fLaC");
+        assert!(!state_path(&dest).exists());
+
+        // Whole going by its size, but not the file wanted
+        std::fs::remove_file(&dest).unwrap();
+        std::fs::write(partial_path(&dest), b"OggS").unwrap();
+        write_state(&state_path(&dest), &state).unwrap();
+        assert!(download(&url, &dest, &verify, &server, |_| {}).unwrap_err().starts_with("SHA-256 mismatch"));
+        assert!(!dest.exists() && !partial_path(&dest).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+}
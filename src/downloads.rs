@@ -0,0 +1,379 @@
+// The download queue behind the Downloads screen. Files go in from the
+// listing one at a time or a folder at a time, and at most `PARALLEL` of them
+// transfer at once while the rest wait their turn. Each transfer runs on a
+// thread of its own through `download::download_until`, which picks up from
+// the `.part` file an earlier attempt left: pausing stops the transfer, and
+// resuming starts it again from where it got to. Cancelling removes the part
+// file as well. The rate limit set on the screen holds all of them together.
+
+use crate::app::DirectoryItem;
+use crate::download::{self, Outcome, Progress};
+use crate::event::AppEvent;
+use crate::upnp::UpnpDevice;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How many files download at the same time.
+pub const PARALLEL: usize = 2;
+/// How often a transfer reports its progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Rate limits `+` and `-` step through on the screen, in KB/s; 0 is none.
+pub const RATE_LIMITS: [u64; 9] = [0, 128, 256, 512, 1024, 2048, 5120, 10240, 20480];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Queued,
+    Active,
+    Paused,
+    /// Downloaded, or found already there.
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+/// What a transfer's thread reports.
+pub enum Message {
+    Progress(Progress),
+    Finished(Result<Outcome, String>),
+    /// It stopped when asked to, to be paused or cancelled.
+    Stopped,
+}
+
+pub struct Transfer {
+    pub id: u64,
+    pub item: DirectoryItem,
+    pub server: UpnpDevice,
+    pub dest: PathBuf,
+    pub status: Status,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    /// Bytes a second, smoothed over the last few reports.
+    pub speed: f64,
+    /// The last report, for working out the speed from the next one.
+    sampled: Option<(Instant, u64)>,
+    /// Set to stop the transfer running for this one.
+    stop: Option<Arc<AtomicBool>>,
+    /// Stopping it cancels it rather than pausing it.
+    cancelling: bool,
+}
+
+impl Transfer {
+    /// How long the rest of the file takes at the current speed.
+    pub fn eta(&self) -> Option<Duration> {
+        let left = self.total?.checked_sub(self.downloaded)?;
+        (self.status == Status::Active && self.speed >= 1.0).then(|| Duration::from_secs_f64(left as f64 / self.speed))
+    }
+
+    pub fn finished(&self) -> bool {
+        matches!(self.status, Status::Done | Status::Failed(_) | Status::Cancelled)
+    }
+}
+
+pub struct Downloads {
+    pub transfers: Vec<Transfer>,
+    /// The highlighted row on the screen.
+    pub selected: usize,
+    next_id: u64,
+    events: Sender<AppEvent>,
+}
+
+impl Downloads {
+    pub fn new(events: Sender<AppEvent>) -> Self {
+        Self { transfers: Vec::new(), selected: 0, next_id: 0, events }
+    }
+
+    /// Queue `item` from `server` to go to `dest`, starting it if a slot is
+    /// free. A file already queued or downloading there isn't queued twice.
+    pub fn add(&mut self, item: DirectoryItem, server: UpnpDevice, dest: PathBuf) -> bool {
+        if self.transfers.iter().any(|transfer| transfer.dest == dest && !transfer.finished()) {
+            return false;
+        }
+        self.next_id += 1;
+        self.transfers.push(Transfer {
+            id: self.next_id,
+            item,
+            server,
+            dest,
+            status: Status::Queued,
+            downloaded: 0,
+            total: None,
+            speed: 0.0,
+            sampled: None,
+            stop: None,
+            cancelling: false,
+        });
+        self.start_queued();
+        true
+    }
+
+    pub fn active(&self) -> usize {
+        self.transfers.iter().filter(|transfer| transfer.status == Status::Active).count()
+    }
+
+    pub fn queued(&self) -> usize {
+        self.transfers.iter().filter(|transfer| transfer.status == Status::Queued).count()
+    }
+
+    /// Start queued transfers, in order, while fewer than `PARALLEL` run.
+    fn start_queued(&mut self) {
+        while self.active() < PARALLEL {
+            let Some(transfer) = self.transfers.iter_mut().find(|transfer| transfer.status == Status::Queued) else {
+                return;
+            };
+            start(transfer, self.events.clone());
+        }
+    }
+
+    /// A transfer's thread reported `message`.
+    pub fn update(&mut self, id: u64, message: Message) -> Option<&Transfer> {
+        let index = self.transfers.iter().position(|transfer| transfer.id == id)?;
+        let transfer = &mut self.transfers[index];
+        match message {
+            Message::Progress(progress) => {
+                let now = Instant::now();
+                if let Some((then, downloaded)) = transfer.sampled {
+                    let secs = now.duration_since(then).as_secs_f64();
+                    let speed = progress.downloaded.saturating_sub(downloaded) as f64 / secs.max(0.001);
+                    transfer.speed = if transfer.speed > 0.0 { transfer.speed * 0.7 + speed * 0.3 } else { speed };
+                }
+                transfer.sampled = Some((now, progress.downloaded));
+                transfer.downloaded = progress.downloaded;
+                transfer.total = progress.total;
+                return None;
+            }
+            Message::Finished(result) => {
+                transfer.status = match result {
+                    Ok(Outcome::Downloaded(bytes)) => {
+                        transfer.downloaded = bytes;
+                        transfer.total = Some(bytes);
+                        Status::Done
+                    }
+                    Ok(Outcome::AlreadyPresent) => Status::Done,
+                    Err(e) => Status::Failed(e),
+                };
+            }
+            Message::Stopped if transfer.cancelling => {
+                download::discard_partial(&transfer.dest);
+                transfer.status = Status::Cancelled;
+            }
+            Message::Stopped => transfer.status = Status::Paused,
+        }
+        transfer.stop = None;
+        transfer.speed = 0.0;
+        transfer.sampled = None;
+        self.start_queued();
+        self.transfers.get(index)
+    }
+
+    /// Pause the highlighted transfer, or let it go on: back in the queue
+    /// if there's no slot free for it.
+    pub fn toggle_pause(&mut self) {
+        let Some(transfer) = self.transfers.get_mut(self.selected) else { return };
+        match transfer.status {
+            Status::Active => {
+                if let Some(stop) = &transfer.stop {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+            Status::Queued => transfer.status = Status::Paused,
+            Status::Paused | Status::Failed(_) => transfer.status = Status::Queued,
+            Status::Done | Status::Cancelled => return,
+        }
+        self.start_queued();
+    }
+
+    /// Cancel the highlighted transfer, throwing away what it got.
+    pub fn cancel(&mut self) {
+        let Some(transfer) = self.transfers.get_mut(self.selected) else { return };
+        match transfer.status {
+            Status::Active => {
+                transfer.cancelling = true;
+                if let Some(stop) = &transfer.stop {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+            Status::Queued | Status::Paused | Status::Failed(_) => {
+                download::discard_partial(&transfer.dest);
+                transfer.status = Status::Cancelled;
+            }
+            Status::Done | Status::Cancelled => {}
+        }
+    }
+
+    /// Take finished transfers off the list.
+    pub fn clear_finished(&mut self) {
+        self.transfers.retain(|transfer| !transfer.finished());
+        self.selected = self.selected.min(self.transfers.len().saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.checked_sub(1).unwrap_or(self.transfers.len().saturating_sub(1));
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = if self.selected + 1 >= self.transfers.len() { 0 } else { self.selected + 1 };
+    }
+}
+
+/// The files below a folder to download, read by `collect`.
+pub struct Batch {
+    pub folder: String,
+    pub server: UpnpDevice,
+    /// Each file and where it goes, under the download directory.
+    pub files: Result<Vec<(PathBuf, DirectoryItem)>, String>,
+}
+
+/// How deep `collect` goes, in case a server's folders lead back to
+/// themselves.
+const MAX_DEPTH: usize = 16;
+
+/// Every file below container `object_id`, each with a path relative to
+/// `dir` that follows the folders it's in. Whatever `hide` hides is left out.
+pub fn collect(
+    server: &UpnpDevice,
+    object_id: &str,
+    dir: &std::path::Path,
+    hide: &crate::hide::HideList,
+    depth: usize,
+) -> Result<Vec<(PathBuf, DirectoryItem)>, String> {
+    let (items, error) = crate::upnp::browse_container(server, object_id, &mop_core::cancel::CancelToken::new());
+    if let Some(e) = error {
+        return Err(e.to_string());
+    }
+    let mut files = Vec::new();
+    for item in items.into_iter().filter(|item| !hide.hides(item)) {
+        if item.is_directory {
+            if let Some(id) = item.id.as_deref().filter(|_| depth < MAX_DEPTH) {
+                let below = dir.join(download::sanitize(&item.name));
+                files.extend(collect(server, id, &below, hide, depth + 1)?);
+            }
+        } else if item.url.is_some() {
+            files.push((dir.join(download::file_name(&item)), item));
+        }
+    }
+    Ok(files)
+}
+
+/// Step the shared rate limit up (`faster`) or down through `RATE_LIMITS`,
+/// "no limit" being the fastest. Returns the new limit in KB/s.
+pub fn step_rate_limit(faster: bool) -> u64 {
+    let limit = next_rate_limit(download::rate_limit() / 1024, faster);
+    download::set_rate_limit(limit * 1024);
+    limit
+}
+
+/// The step in `RATE_LIMITS` after `current` KB/s, which needn't be one of
+/// them when it came from `[downloads] rate_limit`.
+fn next_rate_limit(current: u64, faster: bool) -> u64 {
+    let steps = &RATE_LIMITS[1..];
+    match (current, faster) {
+        (0, true) => 0,
+        (0, false) => steps[steps.len() - 1],
+        (_, true) => steps.iter().copied().find(|&step| step > current).unwrap_or(0),
+        (_, false) => steps.iter().copied().rev().find(|&step| step < current).unwrap_or(steps[0]),
+    }
+}
+
+/// Run `transfer` on a thread of its own, reporting back through `events`.
+fn start(transfer: &mut Transfer, events: Sender<AppEvent>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    transfer.stop = Some(Arc::clone(&stop));
+    transfer.status = Status::Active;
+    transfer.cancelling = false;
+    let id = transfer.id;
+    let (url, dest, server) = (transfer.item.url.clone(), transfer.dest.clone(), transfer.server.clone());
+    let verify = download::Verify {
+        size: transfer.item.metadata.as_ref().and_then(|metadata| metadata.size),
+        sha256: None,
+    };
+    tracing::info!(target: "mop::net", "Downloading {} to {}", transfer.item.name, dest.display());
+    std::thread::spawn(move || {
+        let Some(url) = url else {
+            let message = Message::Finished(Err("The server gave no URL for it".to_string()));
+            let _ = events.send(AppEvent::Download { id, message });
+            return;
+        };
+        let mut reported: Option<Instant> = None;
+        let result = download::download_until(&url, &dest, &verify, &server, &stop, |progress| {
+            if reported.is_some_and(|reported| reported.elapsed() < PROGRESS_INTERVAL) {
+                return;
+            }
+            reported = Some(Instant::now());
+            let _ = events.send(AppEvent::Download { id, message: Message::Progress(progress) });
+        });
+        let message = match result {
+            Err(_) if stop.load(Ordering::Relaxed) => Message::Stopped,
+            result => Message::Finished(result),
+        };
+        let _ = events.send(AppEvent::Download { id, message });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_gives_a_speed_and_finishing_frees_the_transfer() {
+        let (events, _received) = std::sync::mpsc::channel();
+        let mut downloads = Downloads::new(events);
+        let item = DirectoryItem {
+            id: Some("1".to_string()),
+            name: "film.mkv".to_string(),
+            ..Default::default()
+        };
+        let server = UpnpDevice {
+            name: "nas".to_string(),
+            location: "http://192.168.1.31:8200/rootDesc.xml".to_string(),
+            base_url: "http://192.168.1.31:8200".to_string(),
+            ..Default::default()
+        };
+        // Pushed as already running, two seconds after its first report
+        downloads.transfers.push(Transfer {
+            id: 7,
+            item,
+            server,
+            dest: PathBuf::from("/tmp/film.mkv"),
+            status: Status::Active,
+            downloaded: 0,
+            total: None,
+            speed: 0.0,
+            sampled: Some((Instant::now() - Duration::from_secs(2), 0)),
+            stop: None,
+            cancelling: false,
+        });
+
+        let progress = Progress { downloaded: 2000, total: Some(10000) };
+        assert!(downloads.update(7, Message::Progress(progress)).is_none());
+        let transfer = &downloads.transfers[0];
+        assert_eq!(transfer.total, Some(10000));
+        assert!(transfer.speed > 500.0 && transfer.speed <= 1000.0);
+        let eta = transfer.eta().expect("an ETA while downloading");
+        assert!(eta >= Duration::from_secs(8) && eta < Duration::from_secs(17));
+
+        let transfer = downloads.update(7, Message::Finished(Ok(Outcome::Downloaded(10000)))).expect("known id");
+        assert_eq!(transfer.status, Status::Done);
+        assert_eq!(transfer.eta(), None);
+        assert!(downloads.update(8, Message::Stopped).is_none());
+
+        downloads.clear_finished();
+        assert!(downloads.transfers.is_empty());
+        assert_eq!(downloads.selected, 0);
+    }
+
+    #[test]
+    fn rate_limits_step_from_configured_values() {
+        assert_eq!(next_rate_limit(0, true), 0);
+        assert_eq!(next_rate_limit(0, false), 20480);
+        assert_eq!(next_rate_limit(512, true), 1024);
+        assert_eq!(next_rate_limit(512, false), 256);
+        assert_eq!(next_rate_limit(300, true), 512);
+        assert_eq!(next_rate_limit(300, false), 256);
+        assert_eq!(next_rate_limit(128, false), 128);
+        assert_eq!(next_rate_limit(20480, true), 0);
+        assert_eq!(next_rate_limit(50000, false), 20480);
+    }
+}
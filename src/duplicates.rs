@@ -0,0 +1,100 @@
+// Likely duplicates within a listing. DLNA servers put the same file in
+// several virtual folders ("All Videos", "By Folder", "Recently Added"), and
+// listings that mix them show it more than once. Files are taken for copies
+// of each other when their titles and sizes match, or, for servers that
+// don't give sizes, when their stream URLs do.
+
+use crate::app::DirectoryItem;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    TitleAndSize(String, u64),
+    Url(String),
+}
+
+fn key(item: &DirectoryItem) -> Option<Key> {
+    if item.is_directory {
+        return None;
+    }
+    match item.metadata.as_ref().and_then(|metadata| metadata.size).filter(|&size| size > 0) {
+        Some(size) => Some(Key::TitleAndSize(item.name.trim().to_lowercase(), size)),
+        None => item.url.clone().map(Key::Url),
+    }
+}
+
+/// The copies in a listing, as indices into it.
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    groups: HashMap<Key, Vec<usize>>,
+}
+
+impl Duplicates {
+    pub fn of(listing: &[DirectoryItem]) -> Self {
+        let mut duplicates = Self::default();
+        for (index, item) in listing.iter().enumerate() {
+            duplicates.add(index, item);
+        }
+        duplicates
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+
+    /// Note the entry at `index` of the listing, which comes after those
+    /// added so far.
+    pub fn add(&mut self, index: usize, item: &DirectoryItem) {
+        let Some(key) = key(item) else { return };
+        self.groups.entry(key).or_default().push(index);
+    }
+
+    /// Where `item` and its copies are in the listing, first one first;
+    /// empty for an entry nothing could be a copy of.
+    pub fn copies(&self, item: &DirectoryItem) -> &[usize] {
+        key(item).and_then(|key| self.groups.get(&key)).map_or(&[], Vec::as_slice)
+    }
+
+    /// The entry at `index` is a copy of one earlier in the listing.
+    pub fn is_repeat(&self, index: usize, item: &DirectoryItem) -> bool {
+        self.copies(item).first().is_some_and(|&first| first != index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_share_a_title_and_size_or_a_url() {
+        let item = |name: &str, size: Option<u64>, url: &str| DirectoryItem {
+            name: name.to_string(),
+            url: Some(url.to_string()),
+            metadata: Some(mop_core::FileMetadata {
+                size,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let listing = vec![
+            item("Heat (1995)", Some(8_500_000_000), "http://192.168.1.5:8200/MediaItems/12.mkv"),
+            item("Ronin (1998)", Some(4_200_000_000), "http://192.168.1.5:8200/MediaItems/13.mkv"),
+            item("heat (1995) ", Some(8_500_000_000), "http://192.168.1.5:8200/MediaItems/97.mkv"),
+            item("Heat (1995)", Some(1_400_000_000), "http://192.168.1.5:8200/MediaItems/98.mkv"),
+            item("Intro", None, "http://192.168.1.5:8200/MediaItems/20.mp3"),
+            item("Intro (live)", None, "http://192.168.1.5:8200/MediaItems/20.mp3"),
+            item("Intro", None, "http://192.168.1.5:8200/MediaItems/21.mp3"),
+        ];
+        let duplicates = Duplicates::of(&listing);
+        assert_eq!(duplicates.copies(&listing[0]), [0, 2]);
+        assert_eq!(duplicates.copies(&listing[1]), [1]);
+        assert_eq!(duplicates.copies(&listing[3]), [3]);
+        assert_eq!(duplicates.copies(&listing[5]), [4, 5]);
+        assert_eq!(duplicates.copies(&listing[6]), [6]);
+        assert!(!duplicates.is_repeat(0, &listing[0]));
+        assert!(duplicates.is_repeat(2, &listing[2]));
+
+        let folder = DirectoryItem { is_directory: true, ..listing[0].clone() };
+        assert!(Duplicates::of(&[folder.clone(), folder.clone()]).copies(&folder).is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+// Everything the TUI reacts to arrives as an `AppEvent` on one channel:
+// keys and ticks from the input thread, and results from the background
+// threads doing network work. The main loop blocks on the channel, hands
+// each event to `update` and redraws if that changed anything, so nothing
+// network-bound runs on the UI thread and an idle MOP does no work.
+
+use crate::upnp::DiscoveryMessage;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub enum AppEvent {
+    Key(KeyEvent),
+    /// The terminal changed size.
+    Resize,
+    /// Nothing happened for a tick; the log pane may have new entries.
+    Tick,
+    Discovery(DiscoveryMessage),
+    Browse(Browse),
+    PlexLink(crate::plex::LinkEvent),
+    /// How Plex can play a file, keyed by its URL from the listing.
+    PlexStreams {
+        url: String,
+        result: Result<crate::plex::Streams, String>,
+    },
+    CastTargets(Result<Vec<crate::cast::Target>, String>),
+    /// Playback starting (or failing to) on the cast target, with the files
+    /// sent in the variants picked for it and what it said about them.
+    Playback {
+        started: Result<crate::cast::Session, String>,
+        queued: Vec<crate::cast_queue::Queued>,
+        notice: Option<String>,
+    },
+    /// The files of a folder being cast, read in full.
+    CastFolder(Result<Vec<crate::app::DirectoryItem>, String>),
+    /// Where a renderer is in the folder cast to it.
+    CastQueue(crate::cast_queue::Message),
+    /// What pausing, seeking, queueing or stopping on a cast target came to.
+    CastControl(crate::cast::Control),
+    Gateway {
+        location: String,
+        result: Result<crate::igd::GatewayInfo, String>,
+    },
+    ProtocolInfo {
+        location: String,
+        result: Result<Vec<String>, String>,
+    },
+    /// A file's thumbnail, fetched and decoded, or why not.
+    Thumbnail {
+        url: String,
+        result: Result<image::RgbImage, String>,
+    },
+    /// A re-check of local network access.
+    Permission(mop_core::permissions::PermissionState),
+    /// Progress of a network health check, numbered like `Browse`.
+    Diagnostics {
+        run: u64,
+        message: mop_core::diagnostics::DiagnosticsMessage,
+    },
+    /// The network settled into a different state.
+    NetworkChanged,
+    /// Time to check the listed servers still answer.
+    CheckHealth,
+    /// The sleep timer set this many times went off.
+    SleepTimer(u64),
+    /// A server answered its check after this long, or didn't.
+    ServerHealth {
+        location: String,
+        answered: Option<Duration>,
+    },
+    /// A folder's files, read in full for play all.
+    PlayAll(Result<crate::app::Playlist, String>),
+    /// A folder's images, read in full for a slideshow.
+    Slideshow(Result<crate::app::Playlist, String>),
+    /// The server's answer about a file, before it goes to the player.
+    StreamChecked(Box<crate::app::PendingPlay>),
+    /// Whether the player given `url` failed straight away, and how.
+    PlayerChecked {
+        url: String,
+        failure: Option<String>,
+    },
+    /// How fast a file came from the server, for the stream test.
+    StreamTest {
+        name: String,
+        /// The file's average bitrate, when known.
+        bitrate: Option<u64>,
+        result: Result<mop_core::stream::Throughput, String>,
+    },
+    /// Progress of the download numbered `id` in the queue.
+    Download {
+        id: u64,
+        message: crate::downloads::Message,
+    },
+    /// The files below a folder, read in full to download.
+    DownloadFolder(Box<crate::downloads::Batch>),
+    /// What each player command plays, by command, for those that said.
+    PlayerSupport(std::collections::HashMap<String, crate::playable::Support>),
+    /// The ObjectIDs along `path`, found again by title after the server
+    /// stopped knowing the ones kept for it.
+    PathResolved {
+        generation: u64,
+        path: Vec<String>,
+        ids: Result<Vec<String>, String>,
+    },
+    /// What the server at `location` searches and sorts by.
+    Capabilities {
+        location: String,
+        result: Result<crate::upnp::Capabilities, String>,
+    },
+    /// Containers the server says changed, from its ContentDirectory events.
+    ContainerUpdates {
+        location: String,
+        changes: crate::gena::Changes,
+    },
+}
+
+/// Progress of a ContentDirectory browse: its children one by one as they
+/// are parsed, then the end of the page.
+pub struct Browse {
+    /// Which `App::load_page` call this belongs to; anything from an
+    /// earlier one is stale by the time it arrives.
+    pub generation: u64,
+    /// Index of the first item; later pages are appended.
+    pub start: usize,
+    pub message: crate::upnp::BrowseMessage,
+}
+
+pub struct Events {
+    tx: Sender<AppEvent>,
+    rx: Receiver<AppEvent>,
+    tick_millis: Arc<AtomicU64>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { tx, rx, tick_millis: Arc::new(AtomicU64::new(1000)) }
+    }
+
+    /// How long the input thread waits for a key before sending a `Tick`.
+    /// Takes effect after the current wait.
+    pub fn set_tick_rate(&self, tick_rate: Duration) {
+        self.tick_millis.store(tick_rate.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn sender(&self) -> Sender<AppEvent> {
+        self.tx.clone()
+    }
+
+    /// Read the terminal on its own thread, sending a `Tick` whenever the
+    /// tick rate passes without input.
+    pub fn start_input(&self) {
+        let tx = self.tx.clone();
+        let tick_millis = Arc::clone(&self.tick_millis);
+        std::thread::spawn(move || {
+            loop {
+                let tick_rate = Duration::from_millis(tick_millis.load(Ordering::Relaxed));
+                let event = match event::poll(tick_rate) {
+                    Ok(true) => match event::read() {
+                        // Windows also reports releases and repeats
+                        Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => AppEvent::Key(typed_key(key)),
+                        Ok(Event::Resize(..)) => AppEvent::Resize,
+                        Ok(_) => continue,
+                        Err(e) => {
+                            tracing::error!(target: "mop::app", "Reading the terminal failed: {}", e);
+                            return;
+                        }
+                    },
+                    Ok(false) => AppEvent::Tick,
+                    Err(e) => {
+                        tracing::error!(target: "mop::app", "Polling the terminal failed: {}", e);
+                        return;
+                    }
+                };
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Pass everything from a worker's own channel on as events.
+    pub fn forward<T: Send + 'static>(&self, rx: Receiver<T>, wrap: impl Fn(T) -> AppEvent + Send + 'static) {
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            for message in rx {
+                if tx.send(wrap(message)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Wait for the next event.
+    pub fn next(&self) -> Option<AppEvent> {
+        self.rx.recv().ok()
+    }
+
+    /// The next event if one is already queued.
+    pub fn try_next(&self) -> Option<AppEvent> {
+        self.rx.try_recv().ok()
+    }
+
+    /// The next event, waiting at most `timeout` for one.
+    #[cfg(test)]
+    pub fn next_within(&self, timeout: Duration) -> Option<AppEvent> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+}
+
+/// Windows reports AltGr as Ctrl+Alt, so "@" on a German keyboard would
+/// arrive looking like a Ctrl shortcut. A symbol with both held is a typed
+/// character; Ctrl+Alt+letter stays as it is.
+fn typed_key(mut key: KeyEvent) -> KeyEvent {
+    let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+    if cfg!(windows)
+        && key.modifiers.contains(altgr)
+        && let KeyCode::Char(c) = key.code
+        && !c.is_ascii_alphanumeric()
+    {
+        key.modifiers.remove(altgr);
+    }
+    key
+}
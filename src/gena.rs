@@ -0,0 +1,283 @@
+// GENA eventing from media servers. While a server is open, MOP subscribes
+// to its ContentDirectory's events, and the server NOTIFYs it when folders
+// change: ContainerUpdateIDs lists each changed container with its new
+// update count. The notifications come over HTTP to a port MOP listens on
+// for the purpose, at the address MOP reaches the server from. Servers
+// drop subscriptions that aren't renewed, so MOP renews ahead of the
+// timeout the server granted, and unsubscribes once the server is closed.
+// Renderers playing a cast queue are subscribed to the same way, for the
+// LastChange events of their AVTransport.
+
+use crate::upnp::UpnpDevice;
+use reqwest::Method;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Request, Response};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// What MOP asks for; servers may grant less.
+const SUBSCRIPTION_SECS: u64 = 1800;
+/// How long before a subscription runs out it is renewed.
+const RENEW_MARGIN: Duration = Duration::from_secs(60);
+
+/// Containers that changed on the server, with their new update counts.
+pub struct Changes {
+    /// The event every subscription starts with, saying how things stand
+    /// rather than what just changed.
+    pub initial: bool,
+    pub containers: Vec<(String, u32)>,
+}
+
+/// A subscription to a server's changes, kept up until this is dropped.
+pub struct Watch {
+    pub location: String,
+    _stop: Sender<()>,
+}
+
+/// Subscribe to `device`'s ContentDirectory events in the background and
+/// send what changes to `changes`. Servers that don't event are logged
+/// and otherwise left alone.
+pub fn watch(device: UpnpDevice, changes: Sender<Changes>) -> Watch {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let location = device.location.clone();
+    std::thread::spawn(move || match watch_containers(&device, changes) {
+        // Kept until the watch is dropped
+        Ok(subscription) => {
+            let _ = stopped.recv();
+            drop(subscription);
+        }
+        Err(e) => tracing::info!(target: "mop::net", "No change events from {}: {}", device.name, e),
+    });
+    Watch { location, _stop: stop }
+}
+
+fn watch_containers(device: &UpnpDevice, changes: Sender<Changes>) -> Result<Subscription, String> {
+    let client = device.blocking_client_builder()?.timeout(REQUEST_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let description = crate::auth::send_blocking(&client, client.get(&device.location), device.credentials.as_ref())?
+        .text()
+        .map_err(|e| e.to_string())?;
+    let event_url =
+        event_url(&description, &device.location, "ContentDirectory").ok_or("its ContentDirectory has no events")?;
+    Subscription::start(client, event_url, &device.name, move |initial, body| {
+        let containers = crate::upnp::extract_xml_value(body, "ContainerUpdateIDs").map_or_else(Vec::new, |ids| {
+            container_update_ids(&quick_xml::escape::unescape(&ids).map(|ids| ids.into_owned()).unwrap_or(ids))
+        });
+        if !containers.is_empty() {
+            let _ = changes.send(Changes { initial, containers });
+        }
+    })
+}
+
+/// Events from one service, renewed in the background until this is
+/// dropped, and then unsubscribed.
+pub struct Subscription {
+    _stop: Sender<()>,
+}
+
+impl Subscription {
+    /// Subscribe to the service whose eventSubURL is `event_url`, and hand
+    /// `notify` each event's body, and whether it's the first one, which
+    /// says how things stand rather than what changed. `name` is for logs.
+    pub fn start(
+        client: reqwest::blocking::Client,
+        event_url: String,
+        name: &str,
+        notify: impl Fn(bool, &str) + Send + 'static,
+    ) -> Result<Self, String> {
+        let server = tiny_http::Server::http((Ipv4Addr::UNSPECIFIED, 0)).map_err(|e| e.to_string())?;
+        let port = server.server_addr().to_ip().map(|address| address.port()).ok_or("no port to listen on")?;
+        let callback = format!("<http://{}:{}/>", local_address(&event_url)?, port);
+        let server = Arc::new(server);
+
+        let (sid, granted) = subscribe(&client, &event_url, &callback)?;
+        tracing::info!(target: "mop::net", "Subscribed to events from {} for {}s", name, granted.as_secs());
+        let sid = Arc::new(Mutex::new(sid));
+        let listener = Arc::clone(&server);
+        let listening_sid = Arc::clone(&sid);
+        std::thread::spawn(move || {
+            for request in listener.incoming_requests() {
+                notified(request, &listening_sid, &notify);
+            }
+        });
+
+        let (stop, stopped) = mpsc::channel();
+        let name = name.to_string();
+        std::thread::spawn(move || {
+            if let Err(e) = keep(&client, &event_url, &callback, &sid, granted, &stopped) {
+                tracing::info!(target: "mop::net", "No more events from {}: {}", name, e);
+            }
+            server.unblock();
+            let current = sid.lock().map(|sid| sid.clone()).unwrap_or_default();
+            let request = client.request(method("UNSUBSCRIBE"), &event_url).header("SID", current);
+            if let Err(e) = request.send() {
+                tracing::debug!(target: "mop::net", "Unsubscribing from {} failed: {}", name, e);
+            }
+        });
+        Ok(Self { _stop: stop })
+    }
+}
+
+/// Renew the subscription ahead of its timeout until `stopped`.
+fn keep(
+    client: &reqwest::blocking::Client,
+    event_url: &str,
+    callback: &str,
+    sid: &Mutex<String>,
+    mut granted: Duration,
+    stopped: &Receiver<()>,
+) -> Result<(), String> {
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(granted.saturating_sub(RENEW_MARGIN)) {
+        let current = sid.lock().map(|sid| sid.clone()).unwrap_or_default();
+        granted = renew(client, event_url, &current).or_else(|e| {
+            // The device forgot the subscription, after a restart say
+            tracing::debug!(target: "mop::net", "Renewing a subscription failed: {}", e);
+            let (fresh, granted) = subscribe(client, event_url, callback)?;
+            if let Ok(mut sid) = sid.lock() {
+                *sid = fresh;
+            }
+            Ok::<_, String>(granted)
+        })?;
+    }
+    Ok(())
+}
+
+/// A new subscription: its SID and how long the server keeps it.
+fn subscribe(
+    client: &reqwest::blocking::Client,
+    event_url: &str,
+    callback: &str,
+) -> Result<(String, Duration), String> {
+    let response = client
+        .request(method("SUBSCRIBE"), event_url)
+        .header("CALLBACK", callback)
+        .header("NT", "upnp:event")
+        .header("TIMEOUT", format!("Second-{}", SUBSCRIPTION_SECS))
+        .send()
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("the server answered {} to SUBSCRIBE", response.status()));
+    }
+    let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok());
+    let sid = header("SID").ok_or("the server gave no SID")?.to_string();
+    Ok((sid, timeout(header("TIMEOUT"))))
+}
+
+fn renew(client: &reqwest::blocking::Client, event_url: &str, sid: &str) -> Result<Duration, String> {
+    let response = client
+        .request(method("SUBSCRIBE"), event_url)
+        .header("SID", sid)
+        .header("TIMEOUT", format!("Second-{}", SUBSCRIPTION_SECS))
+        .send()
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("the server answered {}", response.status()));
+    }
+    Ok(timeout(response.headers().get("TIMEOUT").and_then(|value| value.to_str().ok())))
+}
+
+fn method(name: &str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("valid method")
+}
+
+/// How long a subscription lasts from its TIMEOUT header, "Second-1800"
+/// or "infinite"; what was asked for when the server doesn't say.
+fn timeout(header: Option<&str>) -> Duration {
+    let secs = header
+        .and_then(|value| value.trim().get(7..).filter(|_| value.trim()[..7].eq_ignore_ascii_case("Second-")))
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .unwrap_or(SUBSCRIPTION_SECS);
+    // A server that hands out short subscriptions still gets a margin
+    Duration::from_secs(secs.max(2 * RENEW_MARGIN.as_secs()))
+}
+
+/// Answer a NOTIFY, handing its body to `notify`.
+fn notified(mut request: Request, sid: &Mutex<String>, notify: &impl Fn(bool, &str)) {
+    let header = |name: &str| {
+        let mut headers = request.headers().iter();
+        let header = headers.find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name));
+        header.map(|header| header.value.as_str().trim().to_string())
+    };
+    let ours = sid.lock().is_ok_and(|sid| header("SID").as_deref() == Some(sid.as_str()));
+    if request.method().as_str() != "NOTIFY" || !ours {
+        let _ = request.respond(Response::empty(412));
+        return;
+    }
+    let initial = header("SEQ").as_deref() == Some("0");
+    let mut body = String::new();
+    let read = request.as_reader().take(1024 * 1024).read_to_string(&mut body);
+    let _ = request.respond(Response::empty(200));
+    if read.is_ok() {
+        notify(initial, &body);
+    }
+}
+
+/// "64$3,12,0,5" as [("64$3", 12), ("0", 5)].
+fn container_update_ids(value: &str) -> Vec<(String, u32)> {
+    let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+    fields
+        .chunks_exact(2)
+        .filter_map(|pair| Some((pair[0].to_string(), pair[1].parse().ok()?)))
+        .filter(|(id, _)| !id.is_empty())
+        .collect()
+}
+
+/// The eventSubURL of the `service` ("ContentDirectory", "AVTransport") in
+/// a device description, resolved against the description's own URL.
+pub fn event_url(description: &str, location: &str, service: &str) -> Option<String> {
+    let service = description.split("<service>").skip(1).find(|entry| {
+        crate::upnp::extract_xml_value(entry, "serviceType").is_some_and(|kind| kind.contains(service))
+    })?;
+    let path = crate::upnp::extract_xml_value(service, "eventSubURL")?;
+    let path = path.trim();
+    if path.is_empty() {
+        return None;
+    }
+    url::Url::parse(location).ok()?.join(path).ok().map(String::from)
+}
+
+/// The address this machine reaches `url`'s host from, for the server to
+/// send events back to.
+fn local_address(url: &str) -> Result<IpAddr, String> {
+    let url = url::Url::parse(url).map_err(|e| e.to_string())?;
+    let target = url.socket_addrs(|| Some(80)).map_err(|e| e.to_string())?;
+    let target = target.first().ok_or("the server has no address")?;
+    // Nothing is sent: connecting a UDP socket only picks the route
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|e| e.to_string())?;
+    socket.connect(target).map_err(|e| e.to_string())?;
+    socket.local_addr().map(|address| address.ip()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_event_url_and_container_update_ids() {
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:ConnectionManager:1</serviceType>\
+            <eventSubURL>/evt/ConnectionManager</eventSubURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType>\
+            <controlURL>/ctl/ContentDir</controlURL><eventSubURL>/evt/ContentDir</eventSubURL></service>\
+            </serviceList></device></root>";
+        assert_eq!(
+            event_url(description, "http://192.168.1.5:8200/rootDesc.xml", "ContentDirectory").as_deref(),
+            Some("http://192.168.1.5:8200/evt/ContentDir")
+        );
+        let other = "<root><service><serviceType>x</serviceType></service></root>";
+        assert_eq!(event_url(other, "http://nas/", "ContentDirectory"), None);
+
+        assert_eq!(
+            container_update_ids("64$3,12, 0,5,2$1"),
+            vec![("64$3".to_string(), 12), ("0".to_string(), 5)]
+        );
+        assert!(container_update_ids("").is_empty());
+
+        assert_eq!(timeout(Some("Second-300")), Duration::from_secs(300));
+        assert_eq!(timeout(Some("second-10")), Duration::from_secs(120));
+        assert_eq!(timeout(Some("infinite")), Duration::from_secs(SUBSCRIPTION_SECS));
+        assert_eq!(timeout(None), Duration::from_secs(SUBSCRIPTION_SECS));
+    }
+}
@@ -0,0 +1,210 @@
+// Driving the whole TUI in tests: an `App` against the `--demo` MediaServer
+// on loopback, drawn into a ratatui `TestBackend` rather than a terminal.
+// Keys go through `handle_key` as if typed, and what discovery and the
+// workers send back is applied between them the way `run_app` does, so a
+// test reads like a session at the keyboard and checks what ends up on
+// screen.
+//
+// The app starts from the default config, whatever the one on disk says,
+// so the keys and layout are the ones the README describes, and the proxy,
+// hooks and rate limits it sets for the whole process are the defaults. Waits are on
+// what the screen shows rather than on time: `wait_for` keeps applying
+// events and redrawing until the text is there, and fails showing the
+// screen it gave up on.
+
+use crate::app::App;
+use crate::config::Config;
+use crate::event::AppEvent;
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const WIDTH: u16 = 120;
+pub const HEIGHT: u16 = 36;
+/// How long `wait_for` gives the demo server and the workers.
+const PATIENCE: Duration = Duration::from_secs(10);
+/// How often a `Tick` goes in while nothing else arrives, as it would from
+/// the input thread.
+const TICK: Duration = Duration::from_millis(100);
+
+pub struct Harness {
+    pub app: App,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    /// A fresh app whose discovery finds only the demo server.
+    pub fn demo() -> Self {
+        let mut app = App::with_config(Arc::new(Mutex::new(VecDeque::new())), Config::default(), Vec::new());
+        app.demo = Some(crate::demo::DemoServer::start().expect("the demo server starts"));
+        app.start_discovery();
+        let terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).expect("a test terminal");
+        Self { app, terminal }
+    }
+
+    /// Press a key, after applying whatever arrived before it.
+    pub fn press(&mut self, code: KeyCode) -> &mut Self {
+        self.apply_queued();
+        crate::handle_key(&mut self.app, KeyEvent::new(code, KeyModifiers::NONE));
+        self.app.needs_redraw = true;
+        self
+    }
+
+    /// Move the highlight down to the server or entry called `name`, going
+    /// by the info pane, and press Enter on it.
+    pub fn open(&mut self, name: &str) -> &mut Self {
+        let shown = format!("Name: {}", name);
+        let rows = self.app.servers.len().max(self.app.directory_contents.len()) + 1;
+        for _ in 0..=rows {
+            if self.screen().contains(&shown) {
+                return self.press(KeyCode::Enter);
+            }
+            self.press(KeyCode::Down);
+        }
+        panic!("{} isn't in the list; the screen was:\n{}", name, self.screen());
+    }
+
+    /// Type `text` a character at a time.
+    pub fn type_text(&mut self, text: &str) -> &mut Self {
+        for c in text.chars() {
+            self.press(KeyCode::Char(c));
+        }
+        self
+    }
+
+    /// The screen as it would be drawn now, one line per row with the
+    /// trailing blanks trimmed.
+    pub fn screen(&mut self) -> String {
+        self.apply_queued();
+        self.draw();
+        let buffer = self.terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                let row: String = (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect();
+                row.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Apply events until the screen shows `text` and no listing is still
+    /// loading, returning the screen. Keys pressed while a listing loads go
+    /// to what's there so far, which is seldom what a test means.
+    pub fn wait_for(&mut self, text: &str) -> String {
+        self.wait_until(&format!("{:?} on screen", text), |screen, app| !app.is_browsing && screen.contains(text))
+    }
+
+    /// Apply events until `done` holds for the screen and the app.
+    pub fn wait_until(&mut self, what: &str, done: impl Fn(&str, &App) -> bool) -> String {
+        let deadline = Instant::now() + PATIENCE;
+        loop {
+            let screen = self.screen();
+            if done(&screen, &self.app) {
+                return screen;
+            }
+            if Instant::now() > deadline {
+                panic!("gave up waiting for {} after {:?}; the screen was:\n{}", what, PATIENCE, screen);
+            }
+            let event = self.app.events.next_within(TICK).unwrap_or(AppEvent::Tick);
+            self.app.update(event);
+        }
+    }
+
+    /// Apply everything already queued, as `run_app` does between frames.
+    fn apply_queued(&mut self) {
+        self.app.request_protocol_info();
+        self.app.request_thumbnail();
+        while let Some(event) = self.app.events.try_next() {
+            self.app.update(event);
+        }
+    }
+
+    fn draw(&mut self) {
+        self.terminal.draw(|f| crate::ui::draw(f, &mut self.app)).expect("drawing into the test backend");
+        self.app.mark_drawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The demo server's root listing, on screen.
+    fn at_root() -> Harness {
+        let mut tui = Harness::demo();
+        tui.wait_for("MOP Demo");
+        tui.open("MOP Demo").wait_for("TV Shows");
+        tui
+    }
+
+    #[test]
+    fn browses_the_demo_server_down_to_a_file_and_back() {
+        let mut tui = at_root();
+        tui.open("Movies").wait_for("Directory: /Movies ");
+        let screen = tui.open("Classics").wait_for("Metropolis (1927).mkv");
+        assert!(screen.contains("Directory: /Movies/Classics"));
+        assert!(screen.contains("The General (1926).mp4"));
+        // The first file is highlighted, and the info pane describes it
+        assert!(screen.contains("Size: 4.10 GB") && screen.contains("Format: video/x-matroska"));
+
+        let screen = tui.press(KeyCode::Backspace).wait_for("Directory: /Movies ");
+        assert!(screen.contains("Classics") && !screen.contains("Metropolis"));
+        let screen = tui.press(KeyCode::Backspace).wait_for("Directory: / ");
+        assert!(screen.contains("Photos"));
+    }
+
+    #[test]
+    fn a_long_folder_pages_in_as_the_selection_nears_its_end() {
+        let mut tui = at_root();
+        tui.open("Music").wait_for("Podcasts");
+        tui.open("Podcasts").wait_for("Long Wave");
+        let screen = tui.open("Long Wave").wait_for("Episode 001.mp3");
+        assert!(screen.contains("Long Wave (200 of 240)"));
+
+        for _ in 0..160 {
+            tui.press(KeyCode::Down);
+        }
+        tui.wait_until("the last page", |screen, app| !app.is_browsing && !screen.contains("of 240)"));
+        // From the first episode, 239 down is the last
+        for _ in 0..79 {
+            tui.press(KeyCode::Down);
+        }
+        let screen = tui.screen();
+        assert!(screen.contains("Name: Episode 240.mp3"), "{}", screen);
+        let screen = tui.press(KeyCode::Down).screen();
+        assert!(screen.contains("Up to: /Music/Podcasts"), "{}", screen);
+    }
+
+    #[test]
+    fn dialogs_open_over_the_listing_and_close_with_esc() {
+        let mut tui = at_root();
+        tui.open("Movies").wait_for("Classics");
+        tui.open("Classics").wait_for("Metropolis (1927).mkv");
+
+        // Taller than the terminal: cut short rather than drawn off the edge
+        let screen = tui.press(KeyCode::Char('?')).screen();
+        assert!(screen.contains("Navigation:") && screen.contains("Press ? or Esc to close"));
+        let screen = tui.press(KeyCode::Esc).screen();
+        assert!(!screen.contains("Navigation:"));
+
+        let screen = tui.press(KeyCode::Char('/')).type_text("gen").screen();
+        assert!(screen.contains("1 matching \"gen\""));
+        assert!(screen.contains("The General (1926).mp4") && !screen.contains("Metropolis"));
+        let screen = tui.press(KeyCode::Esc).screen();
+        assert!(screen.contains("Metropolis (1927).mkv") && !screen.contains("matching"));
+    }
+
+    #[test]
+    fn the_palette_runs_what_is_picked() {
+        let mut tui = at_root();
+        let screen = tui.press(KeyCode::Char(':')).type_text("slee").screen();
+        assert!(screen.contains("sleep timer  z") && !screen.contains("download"));
+        let screen = tui.press(KeyCode::Enter).screen();
+        assert!(screen.contains("Sleep timer") && !screen.contains("Commands"));
+        let screen = tui.type_text("45").press(KeyCode::Enter).screen();
+        assert!(screen.contains("sleep in 45 min"), "{}", screen);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::app::DirectoryItem;
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::download;
+use crate::upnp::{DiscoveryMessage, UpnpDevice};
+use mop_core::container_ids::ContainerIds;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Why a headless command failed. Scripts branch on the exit code, so each
+/// kind keeps its number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Bad arguments, config problems and anything not covered below.
+    Other,
+    /// Discovery finished without finding the requested device.
+    NoDevices,
+    /// The device is there but the path, title or search didn't match.
+    NotFound,
+    /// A request to the device failed, or a file couldn't be read or written.
+    Network,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::NoDevices => 2,
+            ErrorKind::NotFound => 3,
+            ErrorKind::Network => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn network(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Network, message)
+    }
+
+    /// Discovery came back empty, with the likely cause where there is one.
+    pub fn no_devices() -> Self {
+        let hint = crate::sandbox::network_restriction().or_else(|| {
+            mop_core::permissions::empty_network_hint().map(str::to_string)
+        });
+        let message = match hint {
+            Some(hint) => format!("No devices found on the network. {}", hint),
+            None => "No devices found on the network".to_string(),
+        };
+        Self::new(ErrorKind::NoDevices, message)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Loaded config plus the active profile, shared by the headless subcommands.
+pub struct Context {
+    pub config: Config,
+    pub profile: Option<String>,
+    /// Ask a running daemon for devices before discovering directly.
+    pub use_daemon: bool,
+}
+
+impl Context {
+    pub fn load(profile: Option<&str>, use_daemon: bool) -> Result<Self, String> {
+        let (config, warnings) = Config::load();
+        crate::proxy::set(&config.network.proxy);
+        crate::hooks::set(&config.hooks);
+        crate::download::set_rate_limit(config.downloads.rate_limit.saturating_mul(1024));
+        for warning in warnings {
+            eprintln!("mop: warning: {}", warning);
+        }
+        let profile = crate::profile::resolve_profile(&config, profile)?;
+        Ok(Self { config, profile, use_daemon })
+    }
+
+    fn start_discovery(&self) -> std::sync::mpsc::Receiver<DiscoveryMessage> {
+        let settings = self.config.discovery_settings(self.profile.as_deref());
+        crate::daemon::start_discovery(settings, self.profile.as_deref(), self.use_daemon)
+    }
+
+    /// Run discovery to completion and return every device found, after
+    /// those imported from `[mop] servers_file`.
+    pub fn discover_all(&self) -> Vec<UpnpDevice> {
+        let mut devices = self.imported();
+        for device in self.collect_devices(self.start_discovery()) {
+            if !devices.iter().any(|d| d.location == device.location) {
+                devices.push(device);
+            }
+        }
+        devices
+    }
+
+    /// The servers in `[mop] servers_file`, described.
+    fn imported(&self) -> Vec<UpnpDevice> {
+        let servers = crate::import::load(&self.config).unwrap_or_else(|e| {
+            eprintln!("mop: warning: {}", e);
+            Vec::new()
+        });
+        let settings = self.config.discovery_for(self.profile.as_deref());
+        let timeout = Duration::from_secs(settings.description_timeout_secs);
+        servers
+            .iter()
+            .map(|server| crate::import::device(server, &self.config, self.profile.as_deref(), timeout))
+            .collect()
+    }
+
+    /// The devices in a recorded capture, as discovery would have found them.
+    pub fn replay(&self, capture: mop_core::capture::Capture) -> Vec<UpnpDevice> {
+        let settings = self.config.discovery_settings(self.profile.as_deref());
+        self.collect_devices(mop_core::capture::replay(capture, settings))
+    }
+
+    fn collect_devices(&self, receiver: std::sync::mpsc::Receiver<DiscoveryMessage>) -> Vec<UpnpDevice> {
+        let mut devices: Vec<UpnpDevice> = Vec::new();
+        while let Ok(message) = receiver.recv() {
+            let (found, complete) = match message {
+                DiscoveryMessage::DeviceFound(device) => (vec![*device], false),
+                DiscoveryMessage::AllComplete(final_devices) => (final_devices, true),
+                _ => continue,
+            };
+            for mut device in found {
+                self.config.apply_server_config(self.profile.as_deref(), &mut device);
+                if !devices.iter().any(|d| d.location == device.location) {
+                    devices.push(device);
+                }
+            }
+            if complete {
+                break;
+            }
+        }
+        devices
+    }
+
+    /// Run discovery until a device matching `query` (name substring, host,
+    /// location or alias) shows up.
+    pub fn find_device(&self, query: &str) -> Result<UpnpDevice, Error> {
+        if let Some(device) = self.imported().into_iter().find(|device| crate::upnp::device_matches(device, query)) {
+            return Ok(device);
+        }
+        let receiver = self.start_discovery();
+
+        let mut seen = 0;
+        while let Ok(message) = receiver.recv() {
+            let candidates = match message {
+                DiscoveryMessage::DeviceFound(device) => vec![*device],
+                DiscoveryMessage::AllComplete(devices) => devices,
+                _ => continue,
+            };
+            for mut device in candidates {
+                seen += 1;
+                self.config.apply_server_config(self.profile.as_deref(), &mut device);
+                if crate::upnp::device_matches(&device, query) {
+                    return Ok(device);
+                }
+            }
+        }
+
+        if seen == 0 {
+            return Err(Error::no_devices());
+        }
+        Err(Error::new(ErrorKind::NoDevices, format!("No device matching '{}' found", query)))
+    }
+}
+
+/// Split "/Music/Albums" into its segments. Empty segments are ignored.
+pub fn split_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// List a container given as a title path ("/Music/Albums") or, without a
+/// leading slash, as a ContentDirectory ObjectID.
+pub fn list(device: &UpnpDevice, target: &str) -> Result<Vec<DirectoryItem>, Error> {
+    if target.is_empty() || target.starts_with('/') {
+        Browser::new(device).list(&split_path(target))
+    } else {
+        crate::upnp::browse_object(device, target).map_err(Error::network)
+    }
+}
+
+/// Title-path browsing with the container IDs learned so far, in this run
+/// and earlier ones, so lookups below a known folder don't walk down from
+/// the root again. What it learned is saved to the cache when it's dropped.
+struct Browser<'a> {
+    device: &'a UpnpDevice,
+    container_ids: ContainerIds,
+    cache: Option<Cache>,
+    /// Some IDs came from the cache, and the server may have renumbered its
+    /// containers since.
+    unverified: bool,
+}
+
+impl<'a> Browser<'a> {
+    fn new(device: &'a UpnpDevice) -> Self {
+        let cache = Cache::open_default()
+            .inspect_err(|e| tracing::debug!(target: "mop::app", "Browsing without a cache: {}", e))
+            .ok();
+        let container_ids = cache
+            .as_ref()
+            .and_then(|cache| cache.container_ids(device).ok())
+            .unwrap_or_default();
+        let unverified = !container_ids.is_empty();
+        Self { device, container_ids, cache, unverified }
+    }
+
+    fn list(&mut self, path: &[String]) -> Result<Vec<DirectoryItem>, Error> {
+        match self.walk(path) {
+            Err(e) if self.unverified => {
+                tracing::debug!(target: "mop::app", "Retrying /{} from the root: {}", path.join("/"), e.message);
+                self.container_ids = ContainerIds::default();
+                self.unverified = false;
+                self.walk(path)
+            }
+            result => result,
+        }
+    }
+
+    fn walk(&mut self, path: &[String]) -> Result<Vec<DirectoryItem>, Error> {
+        // Walk down one level at a time so every segment's container ID is known
+        for depth in 0..=path.len() {
+            if depth < path.len() && self.container_ids.contains(&path[..=depth]) {
+                continue;
+            }
+            let (items, error) =
+                crate::upnp::browse_directory(self.device, &path[..depth], &mut self.container_ids);
+            if let Some(error) = error {
+                return Err(Error::network(error.to_string()));
+            }
+            if depth == path.len() {
+                return Ok(items);
+            }
+            if !self.container_ids.contains(&path[..=depth]) {
+                return Err(Error::not_found(format!(
+                    "No folder '{}' in /{}",
+                    path[depth],
+                    path[..depth].join("/")
+                )));
+            }
+        }
+
+        unreachable!("loop returns at depth == path.len()")
+    }
+
+    /// The item a title path points at.
+    fn find(&mut self, path: &[String]) -> Result<DirectoryItem, Error> {
+        let Some((name, parent)) = path.split_last() else {
+            return Err("Path must name an item, not the root".to_string().into());
+        };
+        let siblings = self.list(parent)?;
+        siblings
+            .iter()
+            .find(|item| item.name == *name)
+            .or_else(|| siblings.iter().find(|item| item.name.eq_ignore_ascii_case(name)))
+            .cloned()
+            .ok_or_else(|| Error::not_found(format!("No item '{}' in /{}", name, parent.join("/"))))
+    }
+
+    /// Every downloadable file below `path`, with its path relative to `dir`.
+    fn collect_files(
+        &mut self,
+        path: &[String],
+        dir: &Path,
+        files: &mut Vec<(PathBuf, DirectoryItem)>,
+    ) -> Result<(), Error> {
+        for item in self.list(path)? {
+            if item.is_directory {
+                let mut child = path.to_vec();
+                child.push(item.name.clone());
+                self.collect_files(&child, &dir.join(download::sanitize(&item.name)), files)?;
+            } else if item.url.is_some() {
+                files.push((dir.join(download::file_name(&item)), item));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Browser<'_> {
+    fn drop(&mut self) {
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.store_container_ids(self.device, &self.container_ids)
+        {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+    }
+}
+
+/// Resolve what `mop play` should hand to the player. A title path picks
+/// that item (or every file in it, for a container); anything else is a
+/// title search that has to narrow down to a single file.
+pub fn resolve_playable(device: &UpnpDevice, target: &str) -> Result<Vec<DirectoryItem>, Error> {
+    if target.starts_with('/') {
+        let path = split_path(target);
+        let mut browser = Browser::new(device);
+        let item = browser.find(&path)?;
+
+        if !item.is_directory {
+            return Ok(vec![item]);
+        }
+        let files: Vec<DirectoryItem> = browser
+            .list(&path)?
+            .into_iter()
+            .filter(is_playable)
+            .collect();
+        if files.is_empty() {
+            return Err(Error::not_found(format!("No playable files in {}", target)));
+        }
+        return Ok(files);
+    }
+
+    // One search a run, so there's nothing asked before to go on
+    let matches: Vec<DirectoryItem> = crate::upnp::search_titles(device, target, None)
+        .map_err(Error::network)?
+        .into_iter()
+        .filter(is_playable)
+        .collect();
+    pick_single(matches, target).map(|item| vec![item])
+}
+
+/// Hand `items` from `device` to the player `[players]` picks for the first
+/// one, and return that player's command.
+pub fn play<'a>(config: &'a Config, device: &UpnpDevice, items: &[DirectoryItem]) -> Result<&'a str, Error> {
+    let mime = items[0].metadata.as_ref().and_then(|m| m.format.as_deref());
+    let player = config.players.for_mime(mime, &config.mop.run);
+    let urls: Vec<String> = items
+        .iter()
+        .filter_map(|item| item.url.as_deref())
+        .map(|url| crate::auth::player_url(url, &device.location, device.credentials.as_ref()))
+        .collect();
+    let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+    let hook = crate::hooks::Vars::item(&items[0]).server(Some(device));
+    crate::app::launch_player(player, &urls, &hook, None).map_err(Error::network)?;
+    Ok(player)
+}
+
+/// Files to fetch for `mop download`, each with its destination relative to
+/// the download directory. Containers keep their folder name and need
+/// `recursive`.
+pub fn download_plan(
+    device: &UpnpDevice,
+    target: &str,
+    recursive: bool,
+) -> Result<Vec<(PathBuf, DirectoryItem)>, Error> {
+    let path = split_path(target);
+    let mut browser = Browser::new(device);
+    let mut files = Vec::new();
+
+    if path.is_empty() {
+        if !recursive {
+            return Err("Downloading the root needs --recursive".to_string().into());
+        }
+        browser.collect_files(&path, Path::new(""), &mut files)?;
+        return Ok(files);
+    }
+
+    let item = browser.find(&path)?;
+    if !item.is_directory {
+        if item.url.is_none() {
+            return Err(Error::not_found(format!("'{}' has no resource URL", item.name)));
+        }
+        return Ok(vec![(PathBuf::from(download::file_name(&item)), item)]);
+    }
+    if !recursive {
+        return Err(format!("'{}' is a folder; use --recursive", item.name).into());
+    }
+    browser.collect_files(&path, Path::new(&download::sanitize(&item.name)), &mut files)?;
+    Ok(files)
+}
+
+fn is_playable(item: &DirectoryItem) -> bool {
+    !item.is_directory && item.url.is_some()
+}
+
+/// An exact (case-insensitive) title match wins; otherwise the search has
+/// to be unambiguous.
+fn pick_single(mut matches: Vec<DirectoryItem>, query: &str) -> Result<DirectoryItem, Error> {
+    if let Some(index) = matches
+        .iter()
+        .position(|item| item.name.eq_ignore_ascii_case(query))
+    {
+        return Ok(matches.swap_remove(index));
+    }
+    match matches.len() {
+        0 => Err(Error::not_found(format!("Nothing matching '{}' found", query))),
+        1 => Ok(matches.remove(0)),
+        count => {
+            let names: Vec<&str> = matches.iter().take(5).map(|item| item.name.as_str()).collect();
+            Err(Error::not_found(format!(
+                "'{}' matches {} items ({}{}); be more specific",
+                query,
+                count,
+                names.join(", "),
+                if count > names.len() { ", ..." } else { "" }
+            )))
+        }
+    }
+}
+
+/// One tab-separated line per item: kind, name, size, duration, URL.
+/// Missing values are printed as "-".
+pub fn format_item(item: &DirectoryItem) -> String {
+    let metadata = item.metadata.as_ref();
+    let size = metadata
+        .and_then(|m| m.size)
+        .map(|size| size.to_string());
+    let duration = metadata.and_then(|m| m.duration.clone());
+
+    [
+        Some(if item.is_directory { "dir" } else { "file" }.to_string()),
+        Some(item.name.replace(['\t', '\n'], " ")),
+        size,
+        duration,
+        item.url.clone(),
+    ]
+    .into_iter()
+    .map(|field| field.unwrap_or_else(|| "-".to_string()))
+    .collect::<Vec<_>>()
+    .join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mop_core::FileMetadata;
+
+    #[test]
+    fn formats_items_as_tab_separated_lines() {
+        let folder = DirectoryItem {
+            name: "Albums".to_string(),
+            is_directory: true,
+            ..Default::default()
+        };
+        let track = DirectoryItem {
+            name: "Intro\tlive".to_string(),
+            url: Some("http://192.168.1.31:32469/track.flac".to_string()),
+            metadata: Some(FileMetadata {
+                size: Some(1024),
+                duration: Some("0:03:12.000".to_string()),
+                format: Some("audio/flac".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(format_item(&folder), "dir\tAlbums\t-\t-\t-");
+        assert_eq!(
+            format_item(&track),
+            "file\tIntro live\t1024\t0:03:12.000\thttp://192.168.1.31:32469/track.flac"
+        );
+    }
+
+    fn file(name: &str) -> DirectoryItem {
+        DirectoryItem {
+            name: name.to_string(),
+            url: Some(format!("http://nas/{}", name)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn search_must_resolve_to_one_item() {
+        let exact = pick_single(vec![file("Radio Paradise"), file("radio")], "Radio").unwrap();
+        assert_eq!(exact.name, "radio");
+
+        let only = pick_single(vec![file("Radio Paradise")], "paradise").unwrap();
+        assert_eq!(only.name, "Radio Paradise");
+
+        let error = pick_single(vec![file("Radio 1"), file("Radio 2")], "Radio ").unwrap_err();
+        assert_eq!(error.message, "'Radio ' matches 2 items (Radio 1, Radio 2); be more specific");
+        assert_eq!(error.kind, ErrorKind::NotFound);
+        assert_eq!(pick_single(Vec::new(), "x").unwrap_err().kind.exit_code(), 3);
+    }
+
+    #[test]
+    fn splits_paths_ignoring_empty_segments() {
+        assert_eq!(split_path("/Music//Albums/"), vec!["Music", "Albums"]);
+        assert!(split_path("/").is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+// Whether the servers in the list are still there. Every so often each
+// one's description URL is fetched in the background, and the list shows
+// it online, slow or offline with when it last answered, so a cache entry
+// for a server that's gone is obvious before anyone opens it.
+
+use crate::upnp::PlexServer;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A server taking longer than this to answer is slow; LAN servers answer
+/// for their description in milliseconds.
+const SLOW: Duration = Duration::from_secs(1);
+
+/// Not answering by then is offline.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Online,
+    Slow,
+    Offline,
+}
+
+impl Status {
+    fn of(answered: Option<Duration>) -> Self {
+        match answered {
+            Some(latency) if latency > SLOW => Status::Slow,
+            Some(_) => Status::Online,
+            None => Status::Offline,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Online => "online",
+            Status::Slow => "slow",
+            Status::Offline => "offline",
+        }
+    }
+}
+
+/// What is known about one server.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Health {
+    /// None until it has been checked or discovered.
+    pub status: Option<Status>,
+    /// How long its last answer took.
+    pub latency: Option<Duration>,
+    pub last_seen: Option<SystemTime>,
+}
+
+impl Health {
+    /// Record a check that took `answered` to get an answer, or got none.
+    pub fn record(&mut self, answered: Option<Duration>, now: SystemTime) {
+        self.status = Some(Status::of(answered));
+        self.latency = answered;
+        if answered.is_some() {
+            self.last_seen = Some(now);
+        }
+    }
+
+    /// Discovery heard from the server.
+    pub fn discovered(&mut self, now: SystemTime) {
+        if self.status != Some(Status::Slow) {
+            self.status = Some(Status::Online);
+        }
+        self.last_seen = Some(now);
+    }
+}
+
+/// Fetch each server's description on a thread of its own, so one that
+/// never answers holds up nothing else. Each reports its location and how
+/// long it took to answer, if it did.
+pub fn check(servers: Vec<PlexServer>) -> Receiver<(String, Option<Duration>)> {
+    let (tx, rx) = mpsc::channel();
+    for server in servers {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let answered = ping(&server);
+            tracing::debug!(target: "mop::net", "Health check of {}: {:?}", server.name, answered);
+            tx.send((server.location, answered)).ok();
+        });
+    }
+    rx
+}
+
+fn ping(server: &PlexServer) -> Option<Duration> {
+    let client = server.blocking_client_builder().ok()?.timeout(TIMEOUT).build().ok()?;
+    let started = Instant::now();
+    // Any answer will do: a server asking for a login is still there
+    client.get(&server.location).send().ok()?;
+    Some(started.elapsed())
+}
+
+/// A tick straight away, then every `interval` until the receiver is dropped.
+pub fn schedule(interval: Duration) -> Receiver<()> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        while tx.send(()).is_ok() {
+            std::thread::sleep(interval);
+        }
+    });
+    rx
+}
+
+/// "just now", "12m ago", "3h ago" or "2d ago".
+pub fn ago(then: SystemTime, now: SystemTime) -> String {
+    let secs = now.duration_since(then).unwrap_or_default().as_secs();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_mark_servers_online_slow_or_offline() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut health = Health::default();
+        health.record(Some(Duration::from_millis(40)), start);
+        assert_eq!((health.status, health.last_seen), (Some(Status::Online), Some(start)));
+
+        let later = start + Duration::from_secs(600);
+        health.record(Some(Duration::from_millis(2500)), later);
+        assert_eq!(health.status, Some(Status::Slow));
+        health.record(None, later + Duration::from_secs(60));
+        assert_eq!((health.status, health.last_seen), (Some(Status::Offline), Some(later)));
+        health.discovered(later + Duration::from_secs(120));
+        assert_eq!(health.status, Some(Status::Online));
+
+        assert_eq!(ago(start, start + Duration::from_secs(59)), "just now");
+        assert_eq!(ago(start, start + Duration::from_secs(720)), "12m ago");
+        assert_eq!(ago(start, start + Duration::from_secs(3 * 3600 + 10)), "3h ago");
+        assert_eq!(ago(start, start + Duration::from_secs(2 * 86400)), "2d ago");
+        assert_eq!(ago(later, start), "just now");
+    }
+}
@@ -0,0 +1,111 @@
+// Entries left out of listings, from `[hide] patterns`: metadata files,
+// samples, containers nobody opens. Each pattern is a glob on the title
+// ("*.nfo"), or a regular expression after "re:"; "class:" in front matches
+// the UPnP class instead ("class:object.container.playlistContainer").
+
+use crate::app::DirectoryItem;
+use crate::config::HideConfig;
+use regex::{Regex, RegexBuilder};
+
+#[derive(Clone)]
+enum Field {
+    Title,
+    Class,
+}
+
+#[derive(Clone)]
+struct Rule {
+    field: Field,
+    regex: Regex,
+}
+
+#[derive(Clone, Default)]
+pub struct HideList {
+    rules: Vec<Rule>,
+}
+
+impl HideList {
+    /// The patterns that compile, and a warning for each that doesn't.
+    pub fn from_config(config: &HideConfig) -> (Self, Vec<String>) {
+        let mut rules = Vec::new();
+        let mut warnings = Vec::new();
+        for pattern in &config.patterns {
+            match rule(pattern) {
+                Ok(rule) => rules.push(rule),
+                Err(e) => warnings.push(format!("Invalid hide pattern '{}': {}", pattern, e)),
+            }
+        }
+        (Self { rules }, warnings)
+    }
+
+    pub fn hides(&self, item: &DirectoryItem) -> bool {
+        self.rules.iter().any(|rule| match rule.field {
+            Field::Title => rule.regex.is_match(&item.name),
+            Field::Class => item.class.as_deref().is_some_and(|class| rule.regex.is_match(class)),
+        })
+    }
+}
+
+fn rule(pattern: &str) -> Result<Rule, String> {
+    let pattern = pattern.trim();
+    let (field, pattern) = match pattern.strip_prefix("class:") {
+        Some(rest) => (Field::Class, rest),
+        None => (Field::Title, pattern),
+    };
+    let regex = match pattern.strip_prefix("re:") {
+        Some(expression) => Regex::new(expression),
+        None => RegexBuilder::new(&glob_regex(pattern)).case_insensitive(true).build(),
+    };
+    Ok(Rule { field, regex: regex.map_err(|e| e.to_string())? })
+}
+
+/// A glob as an anchored regular expression: `*` is any run of
+/// characters, `?` any one, everything else itself.
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_and_expressions_match_titles_or_classes() {
+        let item = |name: &str, class: &str| DirectoryItem {
+            name: name.to_string(),
+            class: Some(class.to_string()),
+            ..Default::default()
+        };
+        let config = HideConfig {
+            patterns: vec![
+                "*.nfo".to_string(),
+                r"re:\bsample\b".to_string(),
+                "class:object.container.playlistContainer".to_string(),
+                "re:(".to_string(),
+            ],
+        };
+        let (hide, warnings) = HideList::from_config(&config);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Invalid hide pattern 're:('"));
+
+        let video = "object.item.videoItem";
+        assert!(hide.hides(&item("Heat (1995).NFO", video)));
+        assert!(!hide.hides(&item("Heat (1995).nfo.mkv", video)));
+        assert!(hide.hides(&item("heat-sample.mkv", video)));
+        // Expressions are case-sensitive unless they say otherwise
+        assert!(!hide.hides(&item("Heat SAMPLE.mkv", video)));
+        assert!(!hide.hides(&item("Samples of Heat.mkv", video)));
+        assert!(hide.hides(&item("Favourites", "object.container.playlistContainer")));
+        assert!(!hide.hides(&item("object.container.playlistContainer", "object.container.storageFolder")));
+        assert!(!HideList::default().hides(&item("Heat.nfo", video)));
+    }
+}
@@ -0,0 +1,310 @@
+// User scripts run when things happen, for scrobbling, logging or home
+// automation. `[hooks]` gives a command for each event, run with what the
+// event is about in MOP_* environment variables, and optionally a webhook
+// URL the same details are POSTed to as JSON (Home Assistant, ntfy). MOP
+// doesn't wait for a hook to finish, and one that fails is only logged.
+
+use crate::app::DirectoryItem;
+use crate::config::HooksConfig;
+use crate::upnp::PlexServer;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// `[hooks]` from the loaded config, set wherever the config is (re)loaded.
+static CONFIGURED: RwLock<Option<HooksConfig>> = RwLock::new(None);
+
+/// Webhook requests still on their way, for `finish` to wait for.
+static SENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `finish` waits for all of them together before quitting anyway.
+const FINISH_PATIENCE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    DeviceFound,
+    PlayStart,
+    PlayEnd,
+    DownloadComplete,
+}
+
+impl Hook {
+    pub const ALL: [Hook; 4] = [Hook::DeviceFound, Hook::PlayStart, Hook::PlayEnd, Hook::DownloadComplete];
+
+    /// The event's name, as passed in MOP_EVENT.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::DeviceFound => "device_found",
+            Hook::PlayStart => "play_start",
+            Hook::PlayEnd => "play_end",
+            Hook::DownloadComplete => "download_complete",
+        }
+    }
+
+    /// A line saying what happened, for services that show one.
+    fn message(self, vars: &Vars) -> String {
+        let title = vars.get("MOP_TITLE").unwrap_or_default();
+        match self {
+            Hook::DeviceFound => format!("Found {}", vars.get("MOP_DEVICE_NAME").unwrap_or_default()),
+            Hook::PlayStart => format!("Playing {}", title),
+            Hook::PlayEnd => format!("Stopped playing {}", title),
+            Hook::DownloadComplete => format!("Downloaded {}", title),
+        }
+    }
+
+    fn command(self, config: &HooksConfig) -> &str {
+        match self {
+            Hook::DeviceFound => &config.on_device_found,
+            Hook::PlayStart => &config.on_play_start,
+            Hook::PlayEnd => &config.on_play_end,
+            Hook::DownloadComplete => &config.on_download_complete,
+        }
+    }
+}
+
+/// What a hook is told about its event, as environment variables.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vars(Vec<(&'static str, String)>);
+
+impl Vars {
+    /// An item being played or downloaded: MOP_TITLE and MOP_URL, and
+    /// whichever of MOP_OBJECT_ID, MOP_CLASS, MOP_MIME, MOP_SIZE and
+    /// MOP_DURATION the server gave.
+    pub fn item(item: &DirectoryItem) -> Self {
+        let metadata = item.metadata.as_ref();
+        Self::default()
+            .with("MOP_TITLE", &item.name)
+            .with("MOP_URL", item.url.as_deref().unwrap_or_default())
+            .with_some("MOP_OBJECT_ID", item.id.as_deref())
+            .with_some("MOP_CLASS", item.class.as_deref())
+            .with_some("MOP_MIME", metadata.and_then(|metadata| mime(metadata.format.as_deref()?)))
+            .with_some("MOP_SIZE", metadata.and_then(|metadata| metadata.size))
+            .with_some("MOP_DURATION", metadata.and_then(|metadata| metadata.duration.as_deref()))
+    }
+
+    /// A URL played on its own, as MOP_TITLE and MOP_URL.
+    pub fn url(url: &str) -> Self {
+        Self::default().with("MOP_TITLE", url).with("MOP_URL", url)
+    }
+
+    /// A device discovered: MOP_DEVICE_NAME, MOP_DEVICE_LOCATION, and
+    /// MOP_DEVICE_UDN and MOP_DEVICE_TYPE when it has them.
+    pub fn device(device: &PlexServer) -> Self {
+        Self::default()
+            .with("MOP_DEVICE_NAME", &device.name)
+            .with("MOP_DEVICE_LOCATION", &device.location)
+            .with_some("MOP_DEVICE_UDN", device.udn.as_deref())
+            .with_some("MOP_DEVICE_TYPE", device.device_client.as_deref())
+    }
+
+    /// The server an item is on, as MOP_SERVER_NAME and MOP_SERVER_LOCATION.
+    pub fn server(self, server: Option<&PlexServer>) -> Self {
+        let Some(server) = server else { return self };
+        self.with("MOP_SERVER_NAME", &server.name).with("MOP_SERVER_LOCATION", &server.location)
+    }
+
+    pub fn with(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.0.push((name, value.to_string()));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(var, _)| *var == name).map(|(_, value)| value.as_str())
+    }
+
+    fn with_some(self, name: &'static str, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(value) => self.with(name, value),
+            None => self,
+        }
+    }
+}
+
+/// The MIME type of a DLNA protocolInfo or of a plain MIME type.
+fn mime(format: &str) -> Option<&str> {
+    if format.contains(':') { crate::upnp_avtransport::protocol_mime(format) } else { Some(format) }
+}
+
+/// Use `config` for hooks run from now on.
+pub fn set(config: &HooksConfig) {
+    if let Ok(mut configured) = CONFIGURED.write() {
+        *configured = Some(config.clone());
+    }
+}
+
+/// Run the command configured for `hook`, if there is one, with `vars` and
+/// MOP_EVENT in its environment, and send it to the webhook if that takes
+/// the event.
+pub fn run(hook: Hook, vars: &Vars) {
+    let Some(config) = CONFIGURED.read().ok().and_then(|configured| configured.clone()) else { return };
+    let command = hook.command(&config).trim();
+    if !command.is_empty() {
+        run_command(hook, command, vars);
+    }
+    let url = config.webhook.trim();
+    let events = &config.webhook_events;
+    if !url.is_empty() && (events.is_empty() || events.iter().any(|event| event == hook.name())) {
+        post(url.to_string(), payload(hook, vars));
+    }
+}
+
+/// Wait a moment for webhook requests still being sent, so a command about
+/// to exit doesn't cut them off; a webhook that's slower than that is left.
+pub fn finish() {
+    let mut sending = SENDING.lock().map(|mut sending| std::mem::take(&mut *sending)).unwrap_or_default();
+    let deadline = Instant::now() + FINISH_PATIENCE;
+    while !sending.is_empty() && Instant::now() < deadline {
+        let (done, left): (Vec<_>, Vec<_>) = sending.into_iter().partition(JoinHandle::is_finished);
+        for request in done {
+            let _ = request.join();
+        }
+        sending = left;
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if !sending.is_empty() {
+        tracing::warn!(target: "mop::net", "Quitting with {} webhook request(s) unanswered", sending.len());
+    }
+}
+
+/// The JSON a webhook gets: the event, a message, and the variables a
+/// script would get, named without MOP_ and lowercased ("title",
+/// "server_name"). Tokens and passwords in URLs are masked, since the
+/// webhook is likely somewhere else.
+fn payload(hook: Hook, vars: &Vars) -> serde_json::Value {
+    let mut payload = serde_json::Map::new();
+    payload.insert("event".to_string(), hook.name().into());
+    payload.insert("message".to_string(), hook.message(vars).into());
+    for (name, value) in &vars.0 {
+        let name = name.trim_start_matches("MOP_").to_lowercase();
+        let value = if name == "url" { crate::cache::mask_secrets(value) } else { value.clone() };
+        payload.insert(name, value.into());
+    }
+    payload.into()
+}
+
+fn post(url: String, payload: serde_json::Value) {
+    let request = std::thread::spawn(move || {
+        let client = crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)
+            .and_then(|builder| builder.timeout(WEBHOOK_TIMEOUT).build().map_err(|e| e.to_string()));
+        let sent = client.and_then(|client| client.post(&url).json(&payload).send().map_err(|e| e.to_string()));
+        match sent.map(|response| response.status()) {
+            Ok(status) if status.is_success() => {
+                tracing::debug!(target: "mop::net", "Webhook took {}", payload["event"]);
+            }
+            Ok(status) => tracing::warn!(target: "mop::net", "Webhook answered {} to {}", status, payload["event"]),
+            Err(e) => tracing::warn!(target: "mop::net", "Failed to send {} to the webhook: {}", payload["event"], e),
+        }
+    });
+    if let Ok(mut sending) = SENDING.lock() {
+        sending.retain(|request| !request.is_finished());
+        sending.push(request);
+    }
+}
+
+fn run_command(hook: Hook, command: &str, vars: &Vars) {
+    let mut words = crate::app::split_command(command).into_iter();
+    let Some(program) = words.next() else { return };
+
+    let mut process = Command::new(crate::config::expand_home(&program));
+    process
+        .args(words)
+        .env("MOP_EVENT", hook.name())
+        .envs(vars.0.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // A hook that outlives MOP (a download finishing at the end of a run) still gets to finish
+    crate::app::detach(&mut process);
+    let mut child = match process.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            tracing::warn!(target: "mop::app", "Failed to run the {} hook {}: {}", hook.name(), program, e);
+            return;
+        }
+    };
+    tracing::debug!(target: "mop::app", "Running the {} hook: {}", hook.name(), program);
+    std::thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => {
+            tracing::warn!(target: "mop::app", "The {} hook {} exited with {}", hook.name(), program, status);
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!(target: "mop::app", "Lost track of the {} hook {}: {}", hook.name(), program, e),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_and_devices_are_described_in_environment_variables() {
+        let item = DirectoryItem {
+            id: Some("64$3$1".to_string()),
+            name: "Heat (1995)".to_string(),
+            url: Some("http://192.168.1.5:8200/MediaItems/12.mkv".to_string()),
+            metadata: Some(mop_core::FileMetadata {
+                size: Some(8_500_000_000),
+                duration: Some("2:50:12".to_string()),
+                format: Some("http-get:*:video/x-matroska:DLNA.ORG_OP=01".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let vars = Vars::item(&item).with("MOP_PLAYER", "mpv");
+        let names: Vec<&str> = vars.0.iter().map(|(name, _)| *name).collect();
+        let expected = ["MOP_TITLE", "MOP_URL", "MOP_OBJECT_ID", "MOP_MIME", "MOP_SIZE", "MOP_DURATION", "MOP_PLAYER"];
+        assert_eq!(names, expected);
+        assert_eq!((vars.0[3].1.as_str(), vars.0[4].1.as_str()), ("video/x-matroska", "8500000000"));
+
+        let device: PlexServer = serde_json::from_value(serde_json::json!({
+            "name": "NAS",
+            "location": "http://192.168.1.5:8200/rootDesc.xml",
+            "base_url": "http://192.168.1.5:8200",
+            "device_client": null,
+            "content_directory_url": null,
+            "udn": "uuid:1234",
+        }))
+        .unwrap();
+        assert_eq!(
+            Vars::device(&device).0,
+            [
+                ("MOP_DEVICE_NAME", "NAS".to_string()),
+                ("MOP_DEVICE_LOCATION", "http://192.168.1.5:8200/rootDesc.xml".to_string()),
+                ("MOP_DEVICE_UDN", "uuid:1234".to_string()),
+            ]
+        );
+        assert_eq!(Vars::default().server(Some(&device)).0.len(), 2);
+    }
+
+    #[test]
+    fn webhooks_get_the_variables_as_json_with_urls_masked() {
+        let vars = Vars::default()
+            .with("MOP_TITLE", "Heat (1995)")
+            .with("MOP_URL", "http://192.168.1.5:32400/file.mkv?X-Plex-Token=secret")
+            .with("MOP_SERVER_NAME", "Plex");
+        assert_eq!(
+            payload(Hook::PlayStart, &vars),
+            serde_json::json!({
+                "event": "play_start",
+                "message": "Playing Heat (1995)",
+                "title": "Heat (1995)",
+                "url": "http://192.168.1.5:32400/file.mkv?X-Plex-Token=***",
+                "server_name": "Plex",
+            })
+        );
+    }
+
+    #[test]
+    fn quitting_waits_only_a_moment_for_a_webhook_that_hangs() {
+        // Takes the connection and never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        post(url, serde_json::json!({ "event": "play_end" }));
+        let started = Instant::now();
+        finish();
+        assert!(started.elapsed() < FINISH_PATIENCE + Duration::from_millis(500), "{:?}", started.elapsed());
+        drop(listener);
+    }
+}
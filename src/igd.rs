@@ -0,0 +1,160 @@
+// Read-only network details from an Internet Gateway Device (the router),
+// which answers `upnp:rootdevice` searches like any other device. Everything
+// comes from its WANIPConnection (or WANPPPConnection) service: the external
+// address, how long the WAN link has been up and the port mappings that
+// programs on the LAN have opened.
+
+use crate::upnp::UpnpDevice;
+use std::time::Duration;
+
+/// Routers answer index after index; stop somewhere sane if one never errors.
+const MAX_MAPPINGS: u32 = 256;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GatewayInfo {
+    pub external_ip: Option<String>,
+    pub status: Option<String>,
+    /// Seconds since the WAN connection came up.
+    pub uptime: Option<u64>,
+    pub mappings: Vec<PortMapping>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortMapping {
+    pub protocol: String,
+    pub external_port: u16,
+    pub internal_client: String,
+    pub internal_port: u16,
+    pub description: String,
+    pub enabled: bool,
+    /// Seconds left, 0 for permanent mappings.
+    pub lease: u64,
+}
+
+pub fn is_gateway(device: &UpnpDevice) -> bool {
+    device
+        .device_client
+        .as_deref()
+        .is_some_and(|device_type| device_type.contains("InternetGatewayDevice"))
+}
+
+pub fn query(device: &UpnpDevice) -> Result<GatewayInfo, String> {
+    let client = device
+        .blocking_client_builder()?
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let description = client
+        .get(&device.location)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| format!("Cannot read the router's description: {}", e))?;
+    let (service, control_url) = crate::upnp::find_service(&description, &device.location, |service_type| {
+        service_type.contains("WANIPConnection") || service_type.contains("WANPPPConnection")
+    })
+    .ok_or("The router has no WANIPConnection service")?;
+    let wan = |action, args: &[(&str, &str)]| crate::upnp::soap_call(&client, &control_url, &service, action, args);
+
+    let mut info = GatewayInfo {
+        external_ip: wan("GetExternalIPAddress", &[]).ok().and_then(|response| value(&response, "NewExternalIPAddress")),
+        ..Default::default()
+    };
+    if let Ok(response) = wan("GetStatusInfo", &[]) {
+        info.status = value(&response, "NewConnectionStatus");
+        info.uptime = value(&response, "NewUptime").and_then(|uptime| uptime.parse().ok());
+    }
+    // The list ends with a SpecifiedArrayIndexInvalid fault
+    for index in 0..MAX_MAPPINGS {
+        let Ok(response) = wan("GetGenericPortMappingEntry", &[("NewPortMappingIndex", &index.to_string())]) else {
+            break;
+        };
+        match parse_mapping(&response) {
+            Some(mapping) => info.mappings.push(mapping),
+            None => break,
+        }
+    }
+
+    tracing::info!(
+        target: "mop::upnp",
+        "Gateway {}: external IP {:?}, {} port mappings",
+        device.name,
+        info.external_ip,
+        info.mappings.len()
+    );
+    Ok(info)
+}
+
+fn value(response: &str, tag: &str) -> Option<String> {
+    let raw = crate::upnp::extract_xml_value(response, tag)?;
+    let text = quick_xml::escape::unescape(&raw).map(|text| text.into_owned()).unwrap_or(raw);
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+fn parse_mapping(response: &str) -> Option<PortMapping> {
+    let port = |tag| value(response, tag).and_then(|value| value.parse::<u16>().ok());
+    Some(PortMapping {
+        protocol: value(response, "NewProtocol")?,
+        external_port: port("NewExternalPort")?,
+        internal_client: value(response, "NewInternalClient")?,
+        internal_port: port("NewInternalPort")?,
+        description: value(response, "NewPortMappingDescription").unwrap_or_default(),
+        enabled: value(response, "NewEnabled").is_none_or(|enabled| enabled == "1" || enabled == "true"),
+        lease: value(response, "NewLeaseDuration").and_then(|lease| lease.parse().ok()).unwrap_or_default(),
+    })
+}
+
+/// "3d 4h 12m" for the details view.
+pub fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_wan_service_and_port_mappings() {
+        let description = r#"<root><device><deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+            <deviceList><device><deviceList><device>
+              <serviceList><service>
+                <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+                <controlURL>/ctl/IPConn</controlURL>
+              </service></serviceList>
+            </device></deviceList></device></deviceList></device></root>"#;
+        assert_eq!(
+            crate::upnp::find_service(description, "http://192.168.1.1:5000/rootDesc.xml", |service| {
+                service.contains("WANIPConnection")
+            }),
+            Some((
+                "urn:schemas-upnp-org:service:WANIPConnection:1".to_string(),
+                "http://192.168.1.1:5000/ctl/IPConn".to_string()
+            ))
+        );
+
+        let entry = r#"<u:GetGenericPortMappingEntryResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+            <NewRemoteHost></NewRemoteHost><NewExternalPort>51413</NewExternalPort><NewProtocol>TCP</NewProtocol>
+            <NewInternalPort>51413</NewInternalPort><NewInternalClient>192.168.1.20</NewInternalClient>
+            <NewEnabled>1</NewEnabled><NewPortMappingDescription>Transmission &amp; co</NewPortMappingDescription>
+            <NewLeaseDuration>0</NewLeaseDuration></u:GetGenericPortMappingEntryResponse>"#;
+        assert_eq!(
+            parse_mapping(entry),
+            Some(PortMapping {
+                protocol: "TCP".to_string(),
+                external_port: 51413,
+                internal_client: "192.168.1.20".to_string(),
+                internal_port: 51413,
+                description: "Transmission & co".to_string(),
+                enabled: true,
+                lease: 0,
+            })
+        );
+        assert_eq!(format_uptime(3 * 86400 + 4 * 3600 + 12 * 60), "3d 4h 12m");
+    }
+}
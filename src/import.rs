@@ -0,0 +1,200 @@
+// Servers listed in `[mop] servers_file`, for networks where discovery is
+// blocked but the endpoints are known. The file is JSON or TOML, told apart
+// by its extension, with the same shape either way:
+//
+//     [[servers]]
+//     name = "NAS"
+//     url = "http://10.0.0.5:8200/rootDesc.xml"
+//     backend = "upnp"
+//     credentials = "nas"
+//
+// A UPnP server's url is its device description, which is fetched for the
+// ContentDirectory; Jellyfin and Emby take the server's base URL. The
+// optional `credentials` names a `[servers]` entry whose login, token or API
+// key the server uses, so secrets stay in mop.toml.
+
+use crate::config::Config;
+use crate::upnp::UpnpDevice;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Upnp,
+    Jellyfin,
+    Emby,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportedServer {
+    pub name: String,
+    pub url: String,
+    #[serde(default = "default_backend")]
+    pub backend: Backend,
+    /// Key of the `[servers]` entry to take credentials from.
+    #[serde(default)]
+    pub credentials: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServersFile {
+    #[serde(default)]
+    servers: Vec<ImportedServer>,
+}
+
+fn default_backend() -> Backend {
+    Backend::Upnp
+}
+
+/// The servers in `[mop] servers_file`; none when it isn't set.
+pub fn load(config: &Config) -> Result<Vec<ImportedServer>, String> {
+    let Some(path) = config.servers_file() else { return Ok(Vec::new()) };
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let servers = parse(&path, &text)?;
+    tracing::info!(target: "mop::config", "Importing {} servers from {}", servers.len(), path.display());
+    Ok(servers)
+}
+
+fn parse(path: &Path, text: &str) -> Result<Vec<ImportedServer>, String> {
+    let file: ServersFile = match Format::of(path) {
+        Some(Format::Json) => serde_json::from_str(text).map_err(|e| e.to_string()),
+        Some(Format::Toml) => toml::from_str(text).map_err(|e| e.to_string()),
+        None => return Err(format!("{} is not a .json or .toml file", path.display())),
+    }
+    .map_err(|e| format!("Invalid servers file {}: {}", path.display(), e))?;
+
+    for server in &file.servers {
+        let parsed =
+            url::Url::parse(&server.url).map_err(|e| format!("{}: bad url '{}': {}", server.name, server.url, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(format!("{}: '{}' is not an http(s) URL", server.name, server.url));
+        }
+    }
+    Ok(file.servers)
+}
+
+/// The device for `server`, with `[servers]` settings and its credentials
+/// applied. A UPnP server's description is fetched (blocking, up to
+/// `timeout`); if that fails it is listed anyway and browsing says why.
+pub fn device(server: &ImportedServer, config: &Config, profile: Option<&str>, timeout: Duration) -> UpnpDevice {
+    let url = server.url.trim_end_matches('/').to_string();
+    let mut device = match server.backend {
+        Backend::Upnp => UpnpDevice { name: server.name.clone(), ..bare(&url, "Imported") },
+        Backend::Jellyfin => UpnpDevice { name: format!("{} [Jellyfin]", server.name), ..bare(&url, "Jellyfin") },
+        Backend::Emby => UpnpDevice { name: format!("{} [Emby]", server.name), ..bare(&url, "Emby") },
+    };
+    config.apply_server_config(profile, &mut device);
+    if let Some(key) = &server.credentials {
+        match config.server_named(profile, key) {
+            Some(settings) => settings.apply(&mut device),
+            None => tracing::warn!(
+                target: "mop::config",
+                "{}: no [servers.\"{}\"] entry for its credentials",
+                server.name,
+                key
+            ),
+        }
+    }
+
+    if server.backend == Backend::Upnp {
+        match fetch_description(&device, timeout) {
+            Ok(description) => {
+                let device_type = crate::upnp::extract_xml_value(&description, "deviceType").unwrap_or_default();
+                let described = crate::upnp::described_device(url, &server.name, device_type, Some(&description));
+                device.base_url = described.base_url;
+                device.device_client = described.device_client;
+                device.content_directory_url = described.content_directory_url;
+                device.udn = described.udn;
+            }
+            Err(e) => tracing::warn!(target: "mop::upnp", "No description from {}: {}", url, e),
+        }
+    }
+    device
+}
+
+fn bare(url: &str, kind: &str) -> UpnpDevice {
+    UpnpDevice {
+        name: String::new(),
+        location: url.to_string(),
+        base_url: url.to_string(),
+        device_client: Some(kind.to_string()),
+        content_directory_url: None,
+        udn: None,
+        token: None,
+        api_key: None,
+        tls: Default::default(),
+        credentials: None,
+        bypass_proxy: false,
+        headers: Default::default(),
+    }
+}
+
+/// The description XML at `device.location`, fetched with the device's own
+/// TLS settings and credentials.
+pub fn fetch_description(device: &UpnpDevice, timeout: Duration) -> Result<String, String> {
+    let client = device.blocking_client_builder()?.timeout(timeout).build().map_err(|e| e.to_string())?;
+    crate::auth::send_blocking(&client, client.get(&device.location), device.credentials.as_ref())?
+        .error_for_status()
+        .and_then(|response| response.text())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_and_toml_files_read_alike_and_credentials_come_from_config() {
+        let toml = r#"
+            [[servers]]
+            name = "Basement"
+            url = "https://10.0.0.9:8920/"
+            backend = "emby"
+            credentials = "emby-key"
+        "#;
+        let json = r#"{ "servers": [
+            { "name": "Basement", "url": "https://10.0.0.9:8920/", "backend": "emby", "credentials": "emby-key" }
+        ] }"#;
+        let from_toml = parse(Path::new("servers.toml"), toml).unwrap();
+        let from_json = parse(Path::new("servers.JSON"), json).unwrap();
+        assert_eq!(from_toml.len(), 1);
+        assert_eq!(from_json[0].backend, Backend::Emby);
+        assert_eq!(from_toml[0].credentials, from_json[0].credentials);
+
+        assert!(parse(Path::new("servers.yaml"), toml).is_err());
+        assert!(parse(Path::new("servers.toml"), "[[servers]]\nname = \"x\"\nurl = \"ftp://x\"").is_err());
+        assert!(parse(Path::new("servers.toml"), "[[servers]]\nname = \"x\"\nurl = \"http://x\"\nport = 1").is_err());
+
+        let mut config = Config::default();
+        config.servers.insert(
+            "emby-key".to_string(),
+            toml::from_str("api_key = \"secret\"\naccept_invalid_certs = true").unwrap(),
+        );
+        let device = device(&from_toml[0], &config, None, Duration::from_secs(1));
+        assert_eq!(device.name, "Basement [Emby]");
+        assert_eq!(device.base_url, "https://10.0.0.9:8920");
+        assert_eq!(device.api_key.as_deref(), Some("secret"));
+        assert!(device.tls.accept_invalid_certs);
+        assert!(mop_core::jellyfin::handles(&device));
+    }
+}
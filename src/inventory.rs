@@ -0,0 +1,169 @@
+// `mop export`: every device MOP knows of, found by this run's discovery or
+// remembered in the cache from earlier ones, as JSON or CSV for network
+// inventories and home-automation tools.
+//
+// The JSON fields follow the same rules as docs/json-output.md: they may be
+// added to, never renamed. The CSV has one column per field, services
+// separated by spaces.
+
+use crate::cache::{self, Cache};
+use crate::headless::Context;
+use crate::upnp::UpnpDevice;
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub udn: Option<String>,
+    /// UPnP device type, or how the device was found ("DirectScan").
+    pub kind: Option<String>,
+    pub location: String,
+    pub base_url: String,
+    pub content_directory_url: Option<String>,
+    /// Service types in the device description; empty when it couldn't be
+    /// fetched.
+    pub services: Vec<String>,
+    /// When discovery last found the device, RFC 3339 in UTC.
+    pub last_seen: Option<String>,
+    /// Found by this run's discovery, not only remembered.
+    pub online: bool,
+}
+
+const CSV_HEADER: [&str; 9] =
+    ["name", "udn", "kind", "location", "base_url", "content_directory_url", "services", "last_seen", "online"];
+
+/// Run discovery, then list what it found followed by the devices only the
+/// cache remembers. Found devices are stored in the cache on the way.
+pub fn collect(context: &Context) -> Vec<Entry> {
+    let discovered = context.discover_all();
+    let cache = Cache::open_default()
+        .inspect_err(|e| tracing::debug!(target: "mop::app", "Exporting without a cache: {}", e))
+        .ok();
+    let settings = context.config.discovery_for(context.profile.as_deref());
+    let timeout = Duration::from_secs(settings.description_timeout_secs);
+    let now = chrono::Utc::now().timestamp();
+
+    let mut entries = Vec::new();
+    for device in &discovered {
+        if let Some(cache) = &cache
+            && let Err(e) = cache.store_device(device)
+        {
+            tracing::warn!(target: "mop::app", "{}", e);
+        }
+        entries.push(entry(device, services(device, timeout), Some(now), true));
+    }
+
+    let remembered = match cache.as_ref().map(Cache::devices_seen) {
+        Some(Ok(remembered)) => remembered,
+        Some(Err(e)) => {
+            tracing::warn!(target: "mop::app", "Failed to read cached servers: {}", e);
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    for (mut device, seen_at) in remembered {
+        let key = cache::server_key(&device);
+        if discovered.iter().any(|found| found.location == device.location || cache::server_key(found) == key) {
+            continue;
+        }
+        context.config.apply_server_config(context.profile.as_deref(), &mut device);
+        entries.push(entry(&device, Vec::new(), Some(seen_at), false));
+    }
+    entries
+}
+
+fn entry(device: &UpnpDevice, services: Vec<String>, seen_at: Option<i64>, online: bool) -> Entry {
+    let name = device.name.split(" [").next().unwrap_or(&device.name);
+    Entry {
+        name: name.to_string(),
+        udn: device.udn.clone(),
+        kind: device.device_client.clone(),
+        location: device.location.clone(),
+        base_url: device.base_url.clone(),
+        content_directory_url: device.content_directory_url.clone(),
+        services,
+        last_seen: seen_at
+            .and_then(|seen_at| chrono::DateTime::from_timestamp(seen_at, 0))
+            .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        online,
+    }
+}
+
+/// The service types in `device`'s description, or none if it can't be
+/// fetched.
+fn services(device: &UpnpDevice, timeout: Duration) -> Vec<String> {
+    match crate::import::fetch_description(device, timeout) {
+        Ok(description) => mop_core::upnp::service_types(&description),
+        Err(e) => {
+            tracing::info!(target: "mop::upnp", "No description from {}: {}", device.location, e);
+            Vec::new()
+        }
+    }
+}
+
+/// `entries` as CSV with a header row, quoting fields where needed.
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = CSV_HEADER.join(",");
+    csv.push_str("\r\n");
+    for entry in entries {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        let fields = [
+            entry.name.clone(),
+            optional(&entry.udn),
+            optional(&entry.kind),
+            entry.location.clone(),
+            entry.base_url.clone(),
+            optional(&entry.content_directory_url),
+            entry.services.join(" "),
+            optional(&entry.last_seen),
+            entry.online.to_string(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_quotes_only_what_needs_it() {
+        let entry = Entry {
+            name: "Living room, \"big\" TV".to_string(),
+            udn: Some("uuid:1234".to_string()),
+            kind: Some("urn:schemas-upnp-org:device:MediaRenderer:1".to_string()),
+            location: "http://192.168.1.20:1400/desc.xml".to_string(),
+            base_url: "http://192.168.1.20:1400".to_string(),
+            content_directory_url: None,
+            services: vec![
+                "urn:schemas-upnp-org:service:AVTransport:1".to_string(),
+                "urn:schemas-upnp-org:service:RenderingControl:1".to_string(),
+            ],
+            last_seen: Some("2026-10-16T12:00:00Z".to_string()),
+            online: false,
+        };
+
+        let csv = to_csv(&[entry]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "name,udn,kind,location,base_url,content_directory_url,services,last_seen,online");
+        assert_eq!(
+            lines[1],
+            "\"Living room, \"\"big\"\" TV\",uuid:1234,urn:schemas-upnp-org:device:MediaRenderer:1,\
+             http://192.168.1.20:1400/desc.xml,http://192.168.1.20:1400,,\
+             urn:schemas-upnp-org:service:AVTransport:1 urn:schemas-upnp-org:service:RenderingControl:1,\
+             2026-10-16T12:00:00Z,false"
+        );
+    }
+}
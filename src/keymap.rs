@@ -0,0 +1,442 @@
+use crate::config::KeysConfig;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Help,
+    Config,
+    ToggleLogs,
+    CopyErrors,
+    OpenUrl,
+    PlexLink,
+    PlexStreams,
+    Cast,
+    SendToTv,
+    SoapLog,
+    Diagnostics,
+    Pin,
+    PlayAll,
+    GoToId,
+    PlayAndQuit,
+    PlayAndStay,
+    CopyInfo,
+    QrCode,
+    OpenWeb,
+    Slideshow,
+    MusicView,
+    TestStream,
+    ToggleHidden,
+    Sort,
+    Jump,
+    SleepTimer,
+    Download,
+    Downloads,
+    Filter,
+    Palette,
+    Root,
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+impl Action {
+    pub const ALL: [Action; 36] = [
+        Action::Quit,
+        Action::Help,
+        Action::Config,
+        Action::ToggleLogs,
+        Action::CopyErrors,
+        Action::OpenUrl,
+        Action::PlexLink,
+        Action::PlexStreams,
+        Action::Cast,
+        Action::SendToTv,
+        Action::SoapLog,
+        Action::Diagnostics,
+        Action::Pin,
+        Action::PlayAll,
+        Action::GoToId,
+        Action::PlayAndQuit,
+        Action::PlayAndStay,
+        Action::CopyInfo,
+        Action::QrCode,
+        Action::OpenWeb,
+        Action::Slideshow,
+        Action::MusicView,
+        Action::TestStream,
+        Action::ToggleHidden,
+        Action::Sort,
+        Action::Jump,
+        Action::SleepTimer,
+        Action::Download,
+        Action::Downloads,
+        Action::Filter,
+        Action::Palette,
+        Action::Root,
+        Action::Up,
+        Action::Down,
+        Action::Select,
+        Action::Back,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Config => "config",
+            Action::ToggleLogs => "logs",
+            Action::CopyErrors => "copy_errors",
+            Action::OpenUrl => "open_url",
+            Action::PlexLink => "plex_link",
+            Action::PlexStreams => "plex_streams",
+            Action::Cast => "cast",
+            Action::SendToTv => "send_to_tv",
+            Action::SoapLog => "soap_log",
+            Action::Diagnostics => "diagnostics",
+            Action::Pin => "pin",
+            Action::PlayAll => "play_all",
+            Action::GoToId => "go_to_id",
+            Action::PlayAndQuit => "play_and_quit",
+            Action::PlayAndStay => "play_and_stay",
+            Action::CopyInfo => "copy_info",
+            Action::QrCode => "qr_code",
+            Action::OpenWeb => "open_web",
+            Action::Slideshow => "slideshow",
+            Action::MusicView => "music_view",
+            Action::TestStream => "test_stream",
+            Action::ToggleHidden => "toggle_hidden",
+            Action::Sort => "sort",
+            Action::Jump => "jump",
+            Action::SleepTimer => "sleep_timer",
+            Action::Download => "download",
+            Action::Downloads => "downloads",
+            Action::Filter => "filter",
+            Action::Palette => "palette",
+            Action::Root => "root",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Select => "select",
+            Action::Back => "back",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|action| action.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = text.split('+').collect();
+        // "+" on its own (or as the last part of "shift++") is the plus key
+        let key = if text.ends_with("++") || text == "+" {
+            parts.truncate(parts.len().saturating_sub(2));
+            "+"
+        } else {
+            parts.pop()?
+        };
+
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers |= KeyModifiers::CONTROL,
+                "alt" => modifiers |= KeyModifiers::ALT,
+                "shift" => modifiers |= KeyModifiers::SHIFT,
+                _ => return None,
+            }
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "backspace" => KeyCode::Backspace,
+            "tab" => KeyCode::Tab,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "delete" | "del" => KeyCode::Delete,
+            lower if lower.len() > 1 && lower.starts_with('f') => {
+                KeyCode::F(lower[1..].parse().ok()?)
+            }
+            _ => {
+                let mut chars = key.chars();
+                let c = chars.next()?;
+                if chars.next().is_some() {
+                    return None;
+                }
+                KeyCode::Char(c)
+            }
+        };
+
+        Some(Self { code, modifiers })
+    }
+
+    fn from_event(key: &KeyEvent) -> Self {
+        // Terminals report shifted characters as the character itself, so
+        // SHIFT only matters for non-character keys.
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        Self { code: key.code, modifiers }
+    }
+
+    pub fn label(&self) -> String {
+        self.spell(true)
+    }
+
+    /// The binding spelled the way `parse` reads it back.
+    pub fn spec(&self) -> String {
+        self.spell(false)
+    }
+
+    fn spell(&self, for_display: bool) -> String {
+        let named = |display: &str, spec: &str| if for_display { display.to_string() } else { spec.to_string() };
+        let key = match self.code {
+            KeyCode::Enter => "enter".to_string(),
+            KeyCode::Esc => "esc".to_string(),
+            KeyCode::Backspace => "backspace".to_string(),
+            KeyCode::Tab => "tab".to_string(),
+            KeyCode::Char(' ') => "space".to_string(),
+            KeyCode::Up => named("↑", "up"),
+            KeyCode::Down => named("↓", "down"),
+            KeyCode::Left => named("←", "left"),
+            KeyCode::Right => named("→", "right"),
+            KeyCode::Home => "home".to_string(),
+            KeyCode::End => "end".to_string(),
+            KeyCode::PageUp => named("pgup", "pageup"),
+            KeyCode::PageDown => named("pgdn", "pagedown"),
+            KeyCode::Delete => "del".to_string(),
+            KeyCode::F(n) => format!("f{}", n),
+            KeyCode::Char(c) => c.to_string(),
+            _ => "?".to_string(),
+        };
+
+        let mut label = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            label.push_str("ctrl+");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            label.push_str("alt+");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            label.push_str("shift+");
+        }
+        label.push_str(&key);
+        label
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyBinding, Action>,
+    keys: HashMap<Action, Vec<KeyBinding>>,
+}
+
+pub const PRESETS: [&str; 2] = ["default", "vim"];
+
+fn preset_bindings(preset: &str) -> Option<Vec<(Action, &'static [&'static str])>> {
+    let mut bindings: Vec<(Action, &'static [&'static str])> = vec![
+        (Action::Quit, &["q"]),
+        (Action::Help, &["?"]),
+        (Action::Config, &["c"]),
+        (Action::ToggleLogs, &["l"]),
+        (Action::CopyErrors, &["e"]),
+        (Action::OpenUrl, &["o"]),
+        (Action::PlexLink, &["p"]),
+        (Action::PlexStreams, &["P"]),
+        (Action::Cast, &["t"]),
+        (Action::SendToTv, &["T"]),
+        (Action::SoapLog, &["i"]),
+        (Action::Diagnostics, &["D"]),
+        (Action::Pin, &["f"]),
+        (Action::PlayAll, &["a"]),
+        (Action::GoToId, &["g"]),
+        (Action::PlayAndQuit, &["shift+enter"]),
+        (Action::PlayAndStay, &["alt+enter"]),
+        (Action::CopyInfo, &["y"]),
+        (Action::QrCode, &["u"]),
+        (Action::OpenWeb, &["w"]),
+        (Action::Slideshow, &["s"]),
+        (Action::MusicView, &["m"]),
+        (Action::TestStream, &["b"]),
+        (Action::ToggleHidden, &["."]),
+        (Action::Sort, &["S"]),
+        (Action::Jump, &["J"]),
+        (Action::SleepTimer, &["z"]),
+        (Action::Download, &["d"]),
+        (Action::Downloads, &["v"]),
+        (Action::Filter, &["/"]),
+        (Action::Palette, &[":"]),
+        (Action::Root, &["~"]),
+        (Action::Up, &["up"]),
+        (Action::Down, &["down"]),
+        (Action::Select, &["enter"]),
+        (Action::Back, &["backspace"]),
+    ];
+
+    match preset {
+        "default" => {}
+        "vim" => {
+            for (action, keys) in bindings.iter_mut() {
+                match action {
+                    Action::Up => *keys = &["up", "k"],
+                    Action::Down => *keys = &["down", "j"],
+                    Action::Back => *keys = &["backspace", "h"],
+                    _ => {}
+                }
+            }
+        }
+        _ => return None,
+    }
+
+    Some(bindings)
+}
+
+impl Keymap {
+    pub fn from_config(config: &KeysConfig) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let preset = preset_bindings(&config.preset).unwrap_or_else(|| {
+            warnings.push(format!("Unknown keymap preset '{}', using default", config.preset));
+            preset_bindings("default").unwrap_or_default()
+        });
+
+        let mut keys: HashMap<Action, Vec<KeyBinding>> = HashMap::new();
+        for (action, names) in preset {
+            keys.insert(action, names.iter().filter_map(|name| KeyBinding::parse(name)).collect());
+        }
+
+        for (name, names) in &config.bindings {
+            let Some(action) = Action::from_name(name) else {
+                warnings.push(format!("Unknown action '{}' in keys.bindings", name));
+                continue;
+            };
+            let mut parsed = Vec::new();
+            for key in names {
+                match KeyBinding::parse(key) {
+                    Some(binding) => parsed.push(binding),
+                    None => warnings.push(format!("Invalid key '{}' for keys.bindings.{}", key, name)),
+                }
+            }
+            keys.insert(action, parsed);
+        }
+
+        let mut bindings = HashMap::new();
+        for action in Action::ALL {
+            for binding in keys.get(&action).into_iter().flatten() {
+                if let Some(existing) = bindings.insert(*binding, action)
+                    && existing != action
+                {
+                    warnings.push(format!(
+                        "Key '{}' is bound to both '{}' and '{}'",
+                        binding.label(),
+                        existing.name(),
+                        action.name()
+                    ));
+                }
+            }
+        }
+
+        (Self { bindings, keys }, warnings)
+    }
+
+    pub fn action_for(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings.get(&KeyBinding::from_event(key)).copied()
+    }
+
+    /// Human readable keys for an action, e.g. "↑/k".
+    pub fn label(&self, action: Action) -> String {
+        self.keys
+            .get(&action)
+            .map(|keys| keys.iter().map(|k| k.label()).collect::<Vec<_>>().join("/"))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&KeysConfig::default()).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        assert_eq!(
+            KeyBinding::parse("ctrl+r"),
+            Some(KeyBinding { code: KeyCode::Char('r'), modifiers: KeyModifiers::CONTROL })
+        );
+        assert_eq!(
+            KeyBinding::parse("shift+enter"),
+            Some(KeyBinding { code: KeyCode::Enter, modifiers: KeyModifiers::SHIFT })
+        );
+        assert_eq!(KeyBinding::parse("f5").map(|k| k.code), Some(KeyCode::F(5)));
+        assert_eq!(KeyBinding::parse("hyper+x"), None);
+
+        let page_up = KeyBinding::parse("shift+pageup").unwrap();
+        assert_eq!(page_up.label(), "shift+pgup");
+        assert_eq!(KeyBinding::parse(&page_up.spec()), Some(page_up));
+    }
+
+    #[test]
+    fn overrides_replace_preset_keys() {
+        let mut config = KeysConfig::default();
+        config.bindings.insert("quit".to_string(), vec!["x".to_string()]);
+        let (keymap, warnings) = Keymap::from_config(&config);
+
+        assert!(warnings.is_empty());
+        let x = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE);
+        let q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.action_for(&x), Some(Action::Quit));
+        assert_eq!(keymap.action_for(&q), None);
+    }
+
+    #[test]
+    fn enter_with_a_modifier_overrides_auto_close() {
+        let keymap = Keymap::default();
+        let enter = |modifiers| keymap.action_for(&KeyEvent::new(KeyCode::Enter, modifiers));
+
+        assert_eq!(enter(KeyModifiers::NONE), Some(Action::Select));
+        assert_eq!(enter(KeyModifiers::SHIFT), Some(Action::PlayAndQuit));
+        assert_eq!(enter(KeyModifiers::ALT), Some(Action::PlayAndStay));
+    }
+
+    #[test]
+    fn vim_preset_adds_hjk() {
+        let config = KeysConfig { preset: "vim".to_string(), ..Default::default() };
+        let (keymap, _) = Keymap::from_config(&config);
+
+        let j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(keymap.action_for(&j), Some(Action::Down));
+        assert_eq!(keymap.label(Action::Up), "↑/k");
+    }
+
+    #[test]
+    fn reports_unknown_actions_and_conflicts() {
+        let mut config = KeysConfig::default();
+        config.bindings.insert("fly".to_string(), vec!["f".to_string()]);
+        config.bindings.insert("help".to_string(), vec!["q".to_string()]);
+        let (_, warnings) = Keymap::from_config(&config);
+
+        assert!(warnings.contains(&"Unknown action 'fly' in keys.bindings".to_string()));
+        assert!(warnings.contains(&"Key 'q' is bound to both 'quit' and 'help'".to_string()));
+    }
+}
@@ -1,6 +1,17 @@
 use chrono::{DateTime, Local};
 use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::util::SubscriberInitExt;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LogCategory {
@@ -63,14 +74,14 @@ impl LogSeverity {
     }
 }
 
-impl From<log::Level> for LogSeverity {
-    fn from(level: log::Level) -> Self {
-        match level {
-            log::Level::Error => LogSeverity::Error,
-            log::Level::Warn => LogSeverity::Warn,
-            log::Level::Info => LogSeverity::Info,
-            log::Level::Debug => LogSeverity::Debug,
-            log::Level::Trace => LogSeverity::Trace,
+impl From<&tracing::Level> for LogSeverity {
+    fn from(level: &tracing::Level) -> Self {
+        match *level {
+            tracing::Level::ERROR => LogSeverity::Error,
+            tracing::Level::WARN => LogSeverity::Warn,
+            tracing::Level::INFO => LogSeverity::Info,
+            tracing::Level::DEBUG => LogSeverity::Debug,
+            tracing::Level::TRACE => LogSeverity::Trace,
         }
     }
 }
@@ -80,16 +91,27 @@ pub struct LogEntry {
     pub timestamp: DateTime<Local>,
     pub category: LogCategory,
     pub severity: LogSeverity,
+    /// The operations this was logged in, outermost first, such as
+    /// `discovery#3:ssdp#4`.
+    pub scope: Option<String>,
     pub message: String,
 }
 
 impl LogEntry {
+    /// The message, behind its scope if it has one.
+    pub fn scoped_message(&self) -> String {
+        match &self.scope {
+            Some(scope) => format!("{}: {}", scope, self.message),
+            None => self.message.clone(),
+        }
+    }
+
     pub fn format_line(&self) -> String {
         format!(
             "{} [{}] {}",
             self.timestamp.format("%H:%M:%S"),
             self.category.as_str(),
-            self.message
+            self.scoped_message()
         )
     }
 
@@ -99,7 +121,7 @@ impl LogEntry {
             self.timestamp.format("%H:%M:%S"),
             self.category.as_str(),
             self.severity.as_str(),
-            self.message
+            self.scoped_message()
         )
     }
 }
@@ -108,57 +130,239 @@ pub type LogBuffer = Arc<Mutex<VecDeque<LogEntry>>>;
 
 pub const LOG_BUFFER_CAPACITY: usize = 2000;
 
-pub struct RingBufferLogger {
-    buffer: LogBuffer,
+/// Entries logged since startup, including ones the ring buffer dropped.
+static ENTRIES_LOGGED: AtomicUsize = AtomicUsize::new(0);
+
+/// Changes whenever something is logged, so the log pane knows to redraw.
+pub fn entries_logged() -> usize {
+    ENTRIES_LOGGED.load(Ordering::Relaxed)
 }
 
-impl RingBufferLogger {
-    pub fn new() -> (Self, LogBuffer) {
-        let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(LOG_BUFFER_CAPACITY)));
-        let buffer_handle = Arc::clone(&buffer);
-        (Self { buffer }, buffer_handle)
+/// Numbers operations so two browses of the same container can be told
+/// apart in the log.
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Dependencies that trace every connection and frame; only their warnings
+/// are worth keeping.
+const NOISY_TARGETS: [&str; 2] = ["hyper_util", "h2"];
+
+/// What the buffer keeps about an open span.
+struct SpanInfo {
+    /// Span name and request ID, such as `browse#12`.
+    label: String,
+    /// The span's fields, `key=value` separated by spaces.
+    fields: String,
+    opened: Instant,
+}
+
+/// Collects an event's message and fields, or a span's fields.
+#[derive(Default)]
+struct FieldText {
+    message: String,
+    fields: String,
+}
+
+impl FieldText {
+    fn push_field(&mut self, name: &str, value: fmt::Arguments) {
+        // Bookkeeping added to events bridged from the log crate
+        if name.starts_with("log.") {
+            return;
+        }
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        self.fields.push_str(&format!("{}={}", name, value));
     }
 }
 
-impl log::Log for RingBufferLogger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= log::Level::Trace
+impl Visit for FieldText {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            name => self.push_field(name, format_args!("{:?}", value)),
+        }
     }
 
-    fn log(&self, record: &log::Record) {
-        if !self.enabled(record.metadata()) {
-            return;
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            name => self.push_field(name, format_args!("{}", value)),
         }
+    }
+}
 
-        let entry = LogEntry {
-            timestamp: Local::now(),
-            category: LogCategory::from_target(record.target()),
-            severity: LogSeverity::from(record.level()),
-            message: record.args().to_string(),
-        };
+/// Keeps everything logged in the ring buffer the log pane shows, each
+/// entry labelled with the spans it was logged in. Closing a span logs how
+/// long it was open.
+pub struct RingBufferLayer {
+    buffer: LogBuffer,
+    /// Entries at or above this level also go to stderr, for the headless
+    /// commands where nothing shows the buffer.
+    stderr: LevelFilter,
+}
+
+impl RingBufferLayer {
+    pub fn new() -> (Self, LogBuffer) {
+        let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(LOG_BUFFER_CAPACITY)));
+        let buffer_handle = Arc::clone(&buffer);
+        (Self { buffer, stderr: LevelFilter::OFF }, buffer_handle)
+    }
 
+    fn push(&self, entry: LogEntry, level: &tracing::Level) {
+        if *level <= self.stderr {
+            eprintln!("{}", entry.format_export_line());
+        }
         if let Ok(mut buffer) = self.buffer.lock() {
             if buffer.len() >= LOG_BUFFER_CAPACITY {
                 buffer.pop_front();
             }
             buffer.push_back(entry);
         }
+        ENTRIES_LOGGED.fetch_add(1, Ordering::Relaxed);
     }
-
-    fn flush(&self) {}
 }
 
-static LOGGER: OnceLock<RingBufferLogger> = OnceLock::new();
+/// Labels of `spans`, outermost first, joined the way `LogEntry::scope`
+/// shows them.
+fn scope_label<'a, S: for<'l> LookupSpan<'l> + 'a>(spans: impl Iterator<Item = SpanRef<'a, S>>) -> Option<String> {
+    let labels: Vec<String> = spans
+        .filter_map(|span| span.extensions().get::<SpanInfo>().map(|info| info.label.clone()))
+        .collect();
+    (!labels.is_empty()).then(|| labels.join(":"))
+}
 
-pub fn init_logger() -> LogBuffer {
-    let (logger, buffer) = RingBufferLogger::new();
+impl<S> Layer<S> for RingBufferLayer
+where
+    S: Subscriber + for<'l> LookupSpan<'l>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut text = FieldText::default();
+        attrs.record(&mut text);
+        let request = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        span.extensions_mut().insert(SpanInfo {
+            label: format!("{}#{}", attrs.metadata().name(), request),
+            fields: text.fields,
+            opened: Instant::now(),
+        });
+    }
 
-    if LOGGER.set(logger).is_ok() {
-        if let Some(logger) = LOGGER.get() {
-            log::set_logger(logger).expect("Failed to set logger");
-            log::set_max_level(log::LevelFilter::Trace);
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut text = FieldText::default();
+        values.record(&mut text);
+        if let Some(info) = span.extensions_mut().get_mut::<SpanInfo>()
+            && !text.fields.is_empty()
+        {
+            if !info.fields.is_empty() {
+                info.fields.push(' ');
+            }
+            info.fields.push_str(&text.fields);
         }
     }
 
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.normalized_metadata();
+        let metadata = metadata.as_ref().unwrap_or_else(|| event.metadata());
+        let mut text = FieldText::default();
+        event.record(&mut text);
+        let message = match (text.message.is_empty(), text.fields.is_empty()) {
+            (_, true) => text.message,
+            (true, false) => text.fields,
+            (false, false) => format!("{} ({})", text.message, text.fields),
+        };
+        let entry = LogEntry {
+            timestamp: Local::now(),
+            category: LogCategory::from_target(metadata.target()),
+            severity: LogSeverity::from(metadata.level()),
+            scope: ctx.event_scope(event).and_then(|scope| scope_label(scope.from_root())),
+            message,
+        };
+        self.push(entry, metadata.level());
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some((elapsed, fields)) = span
+            .extensions()
+            .get::<SpanInfo>()
+            .map(|info| (info.opened.elapsed(), info.fields.clone()))
+        else {
+            return;
+        };
+        let metadata = span.metadata();
+        let message = if fields.is_empty() {
+            format!("done in {:.2?}", elapsed)
+        } else {
+            format!("done in {:.2?} ({})", elapsed, fields)
+        };
+        let entry = LogEntry {
+            timestamp: Local::now(),
+            category: LogCategory::from_target(metadata.target()),
+            severity: LogSeverity::from(metadata.level()),
+            scope: scope_label(span.scope().from_root()),
+            message,
+        };
+        self.push(entry, metadata.level());
+    }
+}
+
+static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+
+pub fn init_logger() -> LogBuffer {
+    install(RingBufferLayer::new(), LevelFilter::TRACE)
+}
+
+/// Logging for the headless commands, which have no log pane: to stderr,
+/// from info up with `-v`, debug with `-vv` and everything with `-vvv`.
+/// Anything below that isn't logged at all.
+pub fn init_headless_logger(verbosity: u8) -> LogBuffer {
+    let level = match verbosity {
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let (layer, buffer) = RingBufferLayer::new();
+    install((RingBufferLayer { stderr: level, ..layer }, buffer), level)
+}
+
+/// Make `layer` the global subscriber, also receiving what dependencies log
+/// through the `log` crate. Only the first call installs anything.
+fn install((layer, buffer): (RingBufferLayer, LogBuffer), max_level: LevelFilter) -> LogBuffer {
+    if BUFFER.set(Arc::clone(&buffer)).is_err() {
+        return buffer;
+    }
+    let filter = NOISY_TARGETS
+        .iter()
+        .fold(Targets::new().with_default(max_level), |targets, target| {
+            targets.with_target(*target, max_level.min(LevelFilter::WARN))
+        });
+    if let Err(e) = tracing_subscriber::registry().with(layer.with_filter(filter)).try_init() {
+        eprintln!("mop: logging is unavailable: {}", e);
+    }
     buffer
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_labelled_with_their_spans() {
+        let (layer, buffer) = RingBufferLayer::new();
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            let _browse = tracing::info_span!(target: "mop::soap", "browse", container = "64").entered();
+            tracing::info!(target: "mop::upnp", "Browse returned {} items", 3);
+        });
+
+        let entries: Vec<LogEntry> = buffer.lock().unwrap().iter().cloned().collect();
+        assert_eq!(entries.len(), 2);
+        let scope = entries[0].scope.clone().unwrap();
+        assert!(scope.starts_with("browse#"));
+        assert_eq!(entries[0].message, "Browse returned 3 items");
+        assert_eq!(entries[0].category, LogCategory::Disc);
+        assert_eq!(entries[1].scope.as_deref(), Some(scope.as_str()));
+        assert!(entries[1].message.starts_with("done in ") && entries[1].message.ends_with("(container=64)"));
+    }
+}
@@ -1,43 +1,157 @@
+use clap::Parser;
+use cli::Cli;
 use std::error::Error;
 use std::io;
-use std::time::Duration;
+use tui_input::backend::crossterm::EventHandler;
 use ratatui::{
     backend::CrosstermBackend,
     crossterm::{
-        event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+        event::{
+            DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyboardEnhancementFlags,
+            PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        },
         execute,
-        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+        terminal::{
+            disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+            LeaveAlternateScreen,
+        },
     },
     Terminal,
 };
 
+mod airplay;
+mod api;
 mod app;
+mod cache;
+mod cast;
+mod cast_queue;
+mod cli;
 mod config;
+mod daemon;
+mod demo;
+mod dlna_server;
+mod download;
+mod downloads;
+mod duplicates;
+mod event;
+mod gena;
+#[cfg(test)]
+mod harness;
+mod headless;
+mod health;
+mod hide;
+mod hooks;
+mod igd;
+mod import;
+mod inventory;
+mod keymap;
 mod logger;
+mod mpv;
+mod netwatch;
+mod output;
+mod playable;
+mod plex;
+mod profile;
+mod qr;
+mod quality;
+mod relay;
+mod reporting;
+mod music;
+mod sandbox;
+mod session;
+mod settings;
+mod sonos;
+mod sort;
+mod termux;
+mod thumbnail;
 mod ui;
-mod upnp;
+mod upnp_avtransport;
+mod workers;
+
+// The UPnP side lives in mop-core; keep it reachable as crate::upnp etc.
+use mop_core::{auth, proxy, tls, upnp};
 
 use app::App;
+use event::AppEvent;
+use keymap::Action;
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // clap exits with 2 on usage errors, which scripts would read as "no devices"
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { 1 } else { 0 });
+    });
+
+    if let Some(command) = cli.command {
+        logger::init_headless_logger(cli.global.verbose);
+        let result = cli::run(command, &cli.global);
+        hooks::finish();
+        if let Err(e) = result {
+            if cli.global.json {
+                output::print_error(&output::Error::new(e.message));
+            } else {
+                eprintln!("mop: {}", e);
+            }
+            std::process::exit(e.kind.exit_code());
+        }
+        return Ok(());
+    }
+
     // Initialize logger first
     let log_buffer = logger::init_logger();
 
-    log::info!(target: "mop::app", "MOP starting up");
+    tracing::info!(target: "mop::app", "MOP starting up");
+
+    let mut app = App::new(log_buffer);
+    app.use_daemon = !cli.global.no_daemon;
+    if let Err(e) = app.select_profile(cli.global.profile.as_deref()) {
+        eprintln!("mop: {}", e);
+        std::process::exit(1);
+    }
+    if cli.demo {
+        match demo::DemoServer::start() {
+            Ok(server) => app.demo = Some(server),
+            Err(e) => {
+                eprintln!("mop: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        // The demo server's address changes every run; keep it out of the cache
+        app.open_cache();
+    }
+    let session = match cli.record_session.as_deref().map(session::Recorder::create).transpose() {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("mop: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    // Terminals with the kitty keyboard protocol can then tell Shift+Enter
+    // from Enter; elsewhere both arrive as Enter
+    let enhanced_keys = supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced_keys {
+        execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES))?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app and run it
-    let mut app = App::new(log_buffer);
+    // Run the app
     app.start_discovery();
-    let res = run_app(&mut terminal, app);
+    app.watch_network();
+    app.listen_for_announcements();
+    app.watch_health();
+    let res = run_app(&mut terminal, app, session);
 
     // Restore terminal
+    if enhanced_keys {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
@@ -45,182 +159,471 @@ fn main() -> Result<(), Box<dyn Error>> {
         DisableMouseCapture
     )?;
     terminal.show_cursor()?;
+    hooks::finish();
 
     if let Err(err) = res {
-        println!("{err:?}");
+        tracing::error!(target: "mop::app", "Terminal error: {}", err);
+        eprintln!("mop: {}", err);
     }
 
     Ok(())
 }
 
-
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
+    mut session: Option<session::Recorder>,
 ) -> io::Result<()> {
+    app.events.start_input();
+    if let Some(session) = session.as_mut() {
+        session.state(&app);
+    }
     loop {
-        // Check for discovery updates
-        app.check_discovery_updates();
-        
+        app.request_protocol_info();
+        app.request_thumbnail();
+        if app.needs_redraw {
+            terminal.draw(|f| ui::draw(f, &mut app))?;
+            app.mark_drawn();
+        }
+        app.events.set_tick_rate(app.tick_rate());
+
+        // Apply everything already queued before drawing, so a listing
+        // streaming in costs one frame rather than one per item
+        let Some(mut event) = app.events.next() else { return Ok(()) };
+        loop {
+            match event {
+                AppEvent::Key(key) => {
+                    match session.as_mut() {
+                        Some(session) => session.key(&mut app, key, handle_key),
+                        None => handle_key(&mut app, key),
+                    }
+                    app.needs_redraw = true;
+                }
+                event => match session.as_mut() {
+                    Some(session) => session.update(&mut app, event),
+                    None => app.update(event),
+                },
+            }
+            match app.events.try_next() {
+                Some(next) if !app.should_quit => event = next,
+                _ => break,
+            }
+        }
+        if let Some(session) = session.as_mut() {
+            session.state(&app);
+        }
+
         // Check if we should quit (for auto-close)
         if app.should_quit {
+            app.remember_location();
             return Ok(());
         }
-        
-        terminal.draw(|f| ui::draw(f, &mut app))?;
-
-        // Use a timeout so we can update UI while discovery runs
-        if let Ok(true) = event::poll(Duration::from_millis(100)) {
-            if let Event::Key(key) = event::read()? {
-
-                
-                // Handle config modal first
-                if app.show_config {
-                    match key.code {
-                        KeyCode::Esc => app.cancel_config_edit(),
-                        KeyCode::Enter => {
-                            if let Err(e) = app.save_config() {
-                                app.last_error = Some(e);
-                            }
-                        }
-                        KeyCode::Tab => app.config_editor.next_field(),
-                        KeyCode::BackTab => app.config_editor.previous_field(),
-                        KeyCode::Char(' ') => app.config_editor.toggle_auto_close(),
-                        _ => {
-                            app.config_editor.handle_key(key);
-                        }
-                    }
-                    continue;
+    }
+}
+
+fn handle_key(app: &mut App, key: KeyEvent) {
+    // Handle config modal first
+    if app.show_config {
+        match key.code {
+            KeyCode::Esc => app.cancel_config_edit(),
+            KeyCode::Enter => {
+                // Validation errors are shown inside the modal
+                let _ = app.save_config();
+            }
+            KeyCode::Tab | KeyCode::Down => app.config_editor.next_field(),
+            KeyCode::BackTab | KeyCode::Up => app.config_editor.previous_field(),
+            KeyCode::PageDown => app.config_editor.next_page(),
+            KeyCode::PageUp => app.config_editor.previous_page(),
+            KeyCode::F(2) => app.config_editor.write_sample_config(),
+            _ => {
+                app.config_editor.handle_key(key);
+            }
+        }
+        return;
+    }
+
+    if let Some(dialog) = app.url_dialog.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.close_url_dialog(),
+            KeyCode::Enter => app.play_url_from_dialog(),
+            _ => {
+                dialog.error = None;
+                dialog.input.handle_event(&Event::Key(key));
+            }
+        }
+        return;
+    }
+
+    if let Some(dialog) = app.sleep_dialog.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.close_sleep_dialog(),
+            KeyCode::Enter => app.set_sleep_timer_from_dialog(),
+            _ => {
+                dialog.error = None;
+                dialog.input.handle_event(&Event::Key(key));
+            }
+        }
+        return;
+    }
+
+    if let Some(dialog) = app.filter_dialog.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.cancel_filter(),
+            KeyCode::Enter => app.confirm_filter(),
+            KeyCode::Up => app.filter_history_older(),
+            KeyCode::Down => app.filter_history_newer(),
+            _ => {
+                dialog.input.handle_event(&Event::Key(key));
+                app.filter_edited();
+            }
+        }
+        return;
+    }
+
+    if let Some(palette) = app.palette.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.close_palette(),
+            KeyCode::Up => app.palette_step(-1),
+            KeyCode::Down => app.palette_step(1),
+            KeyCode::Enter => {
+                if let Some(action) = app.take_palette_command() {
+                    run_action(app, action);
                 }
+            }
+            _ => {
+                palette.input.handle_event(&Event::Key(key));
+                palette.selected = 0;
+            }
+        }
+        return;
+    }
 
-                // Handle help modal next
-                if app.show_help {
-                    match key.code {
-                        KeyCode::Char('?') | KeyCode::Esc => {
-                            app.toggle_help();
-                            continue;
-                        }
-                        _ => continue, // Block other keys while help is shown
-                    }
+    if app.letter_jump {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) => app.letter_jump = false,
+            (KeyCode::Char(c), _) if c.is_alphanumeric() => app.jump_to_letter(c),
+            (_, Some(Action::Up)) => app.previous(),
+            (_, Some(Action::Down)) => app.next(),
+            (_, Some(Action::Select)) => {
+                app.letter_jump = false;
+                app.select();
+            }
+            (_, Some(Action::Back)) => app.letter_jump = false,
+            _ => {}
+        }
+        return;
+    }
+
+    if let Some(dialog) = app.object_id_dialog.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.close_object_id_dialog(),
+            KeyCode::Enter => app.jump_to_object_id(),
+            _ => {
+                dialog.error = None;
+                dialog.input.handle_event(&Event::Key(key));
+            }
+        }
+        return;
+    }
+
+    if app.plex_link.is_some() {
+        if key.code == KeyCode::Esc {
+            app.cancel_plex_link();
+        }
+        return;
+    }
+
+    if app.permission.is_some() {
+        match key.code {
+            KeyCode::Esc => app.close_permission_dialog(),
+            KeyCode::Char('r') => app.recheck_permission(),
+            KeyCode::Char('o') => app.open_permission_settings(),
+            _ => {}
+        }
+        return;
+    }
+
+    if app.gateway.is_some() {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) | (_, Some(Action::Back)) => app.close_gateway_info(),
+            (_, Some(Action::Up)) => app.gateway_scroll(-1),
+            (_, Some(Action::Down)) => app.gateway_scroll(1),
+            _ => {}
+        }
+        return;
+    }
+
+    if app.qr.is_some() {
+        if matches!(key.code, KeyCode::Esc | KeyCode::Enter) || app.keymap.action_for(&key) == Some(Action::Back) {
+            app.close_qr_code();
+        }
+        return;
+    }
+
+    if app.diagnostics.is_some() {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) | (_, Some(Action::Back)) => app.close_diagnostics(),
+            (KeyCode::Char('r'), _) => app.run_diagnostics(),
+            (KeyCode::Char('e'), _) => app.export_metrics(),
+            (_, Some(Action::Up)) => app.diagnostics_scroll(-1),
+            (_, Some(Action::Down)) => app.diagnostics_scroll(1),
+            _ => {}
+        }
+        return;
+    }
+
+    if app.show_downloads {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) | (_, Some(Action::Back | Action::Downloads)) => app.show_downloads = false,
+            (KeyCode::Char('p') | KeyCode::Char(' '), _) => app.downloads.toggle_pause(),
+            (KeyCode::Char('x') | KeyCode::Delete, _) => app.downloads.cancel(),
+            (KeyCode::Char('c'), _) => app.downloads.clear_finished(),
+            (KeyCode::Char('+') | KeyCode::Char('='), _) => app.step_download_limit(true),
+            (KeyCode::Char('-'), _) => app.step_download_limit(false),
+            (_, Some(Action::Up)) => app.downloads.select_previous(),
+            (_, Some(Action::Down)) => app.downloads.select_next(),
+            _ => {}
+        }
+        return;
+    }
+
+    if app.soap_log.is_some() {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) | (_, Some(Action::Back)) => app.close_soap_log(),
+            (KeyCode::PageUp, _) => app.soap_log_scroll(-10),
+            (KeyCode::PageDown, _) => app.soap_log_scroll(10),
+            (KeyCode::Char('r'), _) => app.refresh_soap_log(),
+            (KeyCode::Char('c'), _) => {
+                if let Some(text) = app.soap_log.as_ref().and_then(|dialog| dialog.selected_text()) {
+                    app.last_error = Some(match copy_to_clipboard(&text) {
+                        Ok(()) => "SOAP exchange copied to clipboard".to_string(),
+                        Err(e) => e,
+                    });
                 }
+            }
+            (_, Some(Action::Up)) => app.soap_log_select(-1),
+            (_, Some(Action::Down)) => app.soap_log_select(1),
+            _ => {}
+        }
+        return;
+    }
 
-                // Handle log pane keys when visible
-                if app.log_pane_state != crate::app::LogPaneState::Hidden {
-                    // Filter input mode
-                    if app.log_filter_active {
-                        match key.code {
-                            KeyCode::Esc => {
-                                app.cancel_log_filter();
-                                continue;
-                            }
-                            KeyCode::Enter => {
-                                app.confirm_log_filter();
-                                continue;
-                            }
-                            KeyCode::Backspace => {
-                                app.log_filter_input.pop();
-                                continue;
-                            }
-                            KeyCode::Char(c) => {
-                                app.log_filter_input.push(c);
-                                continue;
-                            }
-                            _ => continue,
-                        }
-                    }
+    if app.cast.is_some() {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) => app.close_cast_dialog(),
+            (KeyCode::Char(' '), _) if app.cast.as_ref().is_some_and(|dialog| dialog.session.is_some()) => {
+                app.cast_toggle_pause()
+            }
+            (KeyCode::Char(' '), _) => app.cast_toggle_mark(),
+            (KeyCode::Char('a'), _) => app.cast_enqueue_selected(),
+            (KeyCode::Char('d'), _) => app.cast_set_default(),
+            (KeyCode::Left, _) => app.cast_seek(-crate::cast::SEEK_STEP),
+            (KeyCode::Right, _) => app.cast_seek(crate::cast::SEEK_STEP),
+            (_, Some(Action::Up)) => app.cast_previous(),
+            (_, Some(Action::Down)) => app.cast_next(),
+            (_, Some(Action::Select)) => app.cast_to_selected(),
+            _ => {}
+        }
+        return;
+    }
 
-                    // Normal log pane keys
-                    match key.code {
-                        KeyCode::Char('l') => {
-                            app.toggle_log_pane();
-                            continue;
-                        }
-                        KeyCode::Esc => {
-                            app.close_log_pane();
-                            continue;
-                        }
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            app.log_scroll_up();
-                            continue;
-                        }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            app.log_scroll_down();
-                            continue;
-                        }
-                        KeyCode::Char('t') => {
-                            app.log_jump_to_top();
-                            continue;
-                        }
-                        KeyCode::Char('b') => {
-                            app.log_jump_to_bottom();
-                            continue;
-                        }
-                        KeyCode::Char('/') => {
-                            app.start_log_filter();
-                            continue;
-                        }
-                        KeyCode::Char('s') => {
-                            match app.export_logs() {
-                                Ok(path) => {
-                                    log::info!(target: "mop::app", "Exported logs to {}", path);
-                                }
-                                Err(e) => {
-                                    log::error!(target: "mop::app", "Failed to export logs: {}", e);
-                                }
-                            }
-                            continue;
-                        }
-                        KeyCode::PageUp => {
-                            for _ in 0..10 {
-                                app.log_scroll_up();
-                            }
-                            continue;
-                        }
-                        KeyCode::PageDown => {
-                            for _ in 0..10 {
-                                app.log_scroll_down();
-                            }
-                            continue;
-                        }
-                        _ => {} // Fall through to main key handling
-                    }
+    if app.variants.is_some() {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) | (_, Some(Action::Back)) => app.close_variants(),
+            (_, Some(Action::Up)) => app.variant_previous(),
+            (_, Some(Action::Down)) => app.variant_next(),
+            (_, Some(Action::Select | Action::PlayAndQuit | Action::PlayAndStay)) => app.play_variant(),
+            _ => {}
+        }
+        return;
+    }
+
+    if app.plex_streams.is_some() {
+        match (key.code, app.keymap.action_for(&key)) {
+            (KeyCode::Esc, _) | (_, Some(Action::Back)) => app.close_plex_streams(),
+            (_, Some(Action::Up)) => app.plex_stream_previous(),
+            (_, Some(Action::Down)) => app.plex_stream_next(),
+            (_, Some(Action::Select)) => app.play_plex_stream(app.config.mop.auto_close),
+            (_, Some(Action::PlayAndQuit)) => app.play_plex_stream(true),
+            (_, Some(Action::PlayAndStay)) => app.play_plex_stream(false),
+            _ => {}
+        }
+        return;
+    }
+
+    // Handle help modal next
+    if app.show_help {
+        match key.code {
+            KeyCode::Char('?') | KeyCode::Esc => {
+                app.toggle_help();
+                return;
+            }
+            _ => return, // Block other keys while help is shown
+        }
+    }
+
+    // Handle log pane keys when visible
+    if app.log_pane_state != crate::app::LogPaneState::Hidden {
+        // Filter input mode
+        if app.log_filter_active {
+            match key.code {
+                KeyCode::Esc => {
+                    app.cancel_log_filter();
+                    return;
+                }
+                KeyCode::Enter => {
+                    app.confirm_log_filter();
+                    return;
                 }
+                KeyCode::Backspace => {
+                    app.log_filter_input.pop();
+                    return;
+                }
+                KeyCode::Char(c) => {
+                    app.log_filter_input.push(c);
+                    return;
+                }
+                _ => return,
+            }
+        }
 
-                match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char('?') => app.toggle_help(),
-                    KeyCode::Char('c') => app.open_config_editor(),
-                    KeyCode::Char('l') => app.toggle_log_pane(),
-                    KeyCode::Char('e') => {
-                                // Copy errors to system clipboard
-                                if !app.discovery_errors.is_empty() {
-                                    let errors_text = app.discovery_errors.iter()
-                                        .enumerate()
-                                        .map(|(i, error)| format!("{}. {}", i + 1, error))
-                                        .collect::<Vec<_>>()
-                                        .join("\n");
-                                    
-                                    match arboard::Clipboard::new() {
-                                        Ok(mut clipboard) => {
-                                            if clipboard.set_text(&errors_text).is_ok() {
-                                                app.last_error = Some("Errors copied to clipboard".to_string());
-                                            } else {
-                                                app.last_error = Some("Failed to copy to clipboard".to_string());
-                                            }
-                                        }
-                                        Err(_) => {
-                                            app.last_error = Some("Clipboard not available".to_string());
-                                        }
-                                    }
-                                }
-                            }
-                    KeyCode::Up => app.previous(),
-                    KeyCode::Down => app.next(),
-                    KeyCode::Enter => app.select(),
-                    KeyCode::Backspace => app.go_back(),
-                    _ => {}
+        // Normal log pane keys
+        match key.code {
+            KeyCode::Char('l') => {
+                app.toggle_log_pane();
+                return;
+            }
+            KeyCode::Esc => {
+                app.close_log_pane();
+                return;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.log_scroll_up();
+                return;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.log_scroll_down();
+                return;
+            }
+            KeyCode::Char('t') => {
+                app.log_jump_to_top();
+                return;
+            }
+            KeyCode::Char('b') => {
+                app.log_jump_to_bottom();
+                return;
+            }
+            KeyCode::Char('/') => {
+                app.start_log_filter();
+                return;
+            }
+            KeyCode::Char('s') => {
+                match app.export_logs() {
+                    Ok(path) => {
+                        tracing::info!(target: "mop::app", "Exported logs to {}", path);
+                    }
+                    Err(e) => {
+                        tracing::error!(target: "mop::app", "Failed to export logs: {}", e);
+                    }
+                }
+                return;
+            }
+            KeyCode::PageUp => {
+                for _ in 0..10 {
+                    app.log_scroll_up();
+                }
+                return;
+            }
+            KeyCode::PageDown => {
+                for _ in 0..10 {
+                    app.log_scroll_down();
                 }
+                return;
+            }
+            _ => {} // Fall through to main key handling
+        }
+    }
+
+    if let Some(action) = app.keymap.action_for(&key) {
+        run_action(app, action);
+    }
+}
+
+/// Do what a key bound to `action` does, from the keymap or the palette.
+fn run_action(app: &mut App, action: Action) {
+    match action {
+        Action::Quit => app.should_quit = true,
+        Action::Help => app.toggle_help(),
+        Action::Config => app.open_config_editor(),
+        Action::ToggleLogs => app.toggle_log_pane(),
+        Action::CopyErrors => {
+            // Copy errors to system clipboard
+            if !app.discovery_errors.is_empty() {
+                let errors_text = app.discovery_errors.iter()
+                    .enumerate()
+                    .map(|(i, error)| format!("{}. {}", i + 1, error))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                app.last_error = Some(match copy_to_clipboard(&errors_text) {
+                    Ok(()) => "Errors copied to clipboard".to_string(),
+                    Err(e) => e,
+                });
+            }
+        }
+        Action::CopyInfo => {
+            let selected = app.selected_entry();
+            if let (crate::app::AppState::DirectoryBrowser, Some(item)) = (&app.state, selected) {
+                app.last_error = Some(match copy_to_clipboard(&ui::item_details(item)) {
+                    Ok(()) => format!("Details of {} copied to clipboard", item.name),
+                    Err(e) => e,
+                });
             }
         }
+        Action::QrCode => app.open_qr_code(),
+        Action::OpenWeb => app.open_in_browser(),
+        Action::Slideshow => app.slideshow_selected(),
+        Action::MusicView => app.toggle_music_view(),
+        Action::TestStream => app.test_stream(),
+        Action::ToggleHidden => app.toggle_hidden(),
+        Action::Sort => app.cycle_sort(),
+        Action::Jump => app.open_letter_jump(),
+        Action::SleepTimer => app.open_sleep_dialog(),
+        Action::Download => app.download_selected(),
+        Action::Downloads => app.open_downloads(),
+        Action::Filter => app.open_filter_dialog(),
+        Action::Palette => app.open_palette(),
+        Action::Root => app.go_to_root(),
+        Action::OpenUrl => app.open_url_dialog(),
+        Action::PlexLink => app.start_plex_link(),
+        Action::PlexStreams => app.open_plex_streams(),
+        Action::Cast => app.open_cast_dialog(),
+        Action::SendToTv => app.send_to_default_renderer(),
+        Action::SoapLog => app.open_soap_log(),
+        Action::Diagnostics => app.open_diagnostics(),
+        Action::Pin => app.toggle_pin(),
+        Action::PlayAll => app.play_all_selected(),
+        Action::GoToId => app.open_object_id_dialog(),
+        Action::PlayAndQuit => app.play_selected_file_then(true),
+        Action::PlayAndStay => app.play_selected_file_then(false),
+        Action::Up => app.previous(),
+        Action::Down => app.next(),
+        Action::Select => app.select(),
+        Action::Back => app.go_back(),
+    }
+}
+
+/// The system clipboard, or termux-api's on Android.
+#[cfg(not(target_os = "android"))]
+fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    if termux::is_termux() {
+        return termux::copy_to_clipboard(text);
     }
+    let mut clipboard = arboard::Clipboard::new().map_err(|_| "Clipboard not available".to_string())?;
+    clipboard.set_text(text).map_err(|_| "Failed to copy to clipboard".to_string())
+}
+
+#[cfg(target_os = "android")]
+fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    termux::copy_to_clipboard(text)
 }
@@ -0,0 +1,139 @@
+// The mpvs MOP started, reached over mpv's JSON IPC. Every mpv is launched
+// with `--input-ipc-server` pointing at a socket (a named pipe on Windows)
+// of its own, numbered in the order they were started, so what's asked of
+// one isn't answered by another that started since.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How long mpv gets to answer a question.
+#[cfg(unix)]
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many mpvs have been given a socket.
+static LAUNCHED: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(not(windows))]
+fn socket(launch: u64) -> String {
+    std::env::temp_dir().join(format!("mop-mpv-{}-{}.sock", std::process::id(), launch)).display().to_string()
+}
+
+#[cfg(windows)]
+fn socket(launch: u64) -> String {
+    format!(r"\\.\pipe\mop-mpv-{}-{}", std::process::id(), launch)
+}
+
+/// A socket for the next mpv to listen on.
+pub fn new_socket() -> String {
+    socket(LAUNCHED.fetch_add(1, Ordering::Relaxed) + 1)
+}
+
+/// The option that has mpv listen on `socket`.
+pub fn ipc_option(socket: &str) -> String {
+    format!("--input-ipc-server={}", socket)
+}
+
+/// Have the mpv on `socket` quit, ending playback.
+pub fn quit(socket: &str) -> Result<(), String> {
+    send(socket, r#"{ "command": ["quit"] }"#)
+}
+
+/// Have every mpv still running quit; how many did.
+pub fn quit_all() -> usize {
+    (1..=LAUNCHED.load(Ordering::Relaxed)).filter(|&launch| quit(&socket(launch)).is_ok()).count()
+}
+
+/// Where mpv is in what it's playing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub elapsed: Duration,
+    pub paused: bool,
+}
+
+/// Ask the mpv on `socket` how far into the file it is and whether it's
+/// paused.
+pub fn position(socket: &str) -> Result<Position, String> {
+    let mut connection = connect(socket)?;
+    send_on(&mut connection, r#"{ "command": ["get_property", "time-pos"], "request_id": 1 }"#)?;
+    send_on(&mut connection, r#"{ "command": ["get_property", "pause"], "request_id": 2 }"#)?;
+    read_position(BufReader::new(connection))
+}
+
+/// The answers to `position`'s questions, among whatever events mpv sends
+/// on the same connection.
+fn read_position(reader: impl BufRead) -> Result<Position, String> {
+    let (mut elapsed, mut paused) = (None, None);
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("Couldn't hear from mpv: {}", e))?;
+        let Ok(answer) = serde_json::from_str::<serde_json::Value>(&line) else { continue };
+        match answer["request_id"].as_u64() {
+            // No time-pos between files, or before the first one starts
+            Some(1) => elapsed = Some(answer["data"].as_f64().unwrap_or_default()),
+            Some(2) => paused = Some(answer["data"].as_bool().unwrap_or_default()),
+            _ => continue,
+        }
+        if let (Some(elapsed), Some(paused)) = (elapsed, paused) {
+            return Ok(Position { elapsed: Duration::from_secs_f64(elapsed.max(0.0)), paused });
+        }
+    }
+    Err("mpv didn't answer".to_string())
+}
+
+fn send(socket: &str, command: &str) -> Result<(), String> {
+    send_on(&mut connect(socket)?, command)
+}
+
+fn send_on(connection: &mut impl Write, command: &str) -> Result<(), String> {
+    connection.write_all(format!("{}\n", command).as_bytes()).map_err(|e| format!("Couldn't reach mpv: {}", e))
+}
+
+#[cfg(unix)]
+fn connect(socket: &str) -> Result<impl Read + Write, String> {
+    let connection =
+        std::os::unix::net::UnixStream::connect(socket).map_err(|e| format!("mpv isn't running: {}", e))?;
+    connection.set_read_timeout(Some(ANSWER_TIMEOUT)).map_err(|e| e.to_string())?;
+    Ok(connection)
+}
+
+#[cfg(not(unix))]
+fn connect(socket: &str) -> Result<impl Read + Write, String> {
+    // Named pipes have no read timeout; mpv answers straight away regardless
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(socket)
+        .map_err(|e| format!("mpv isn't running: {}", e))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quitting_sends_mpv_the_quit_command() {
+        let (first, second) = (new_socket(), new_socket());
+        assert_ne!(first, second);
+        assert!(quit(&first).unwrap_err().starts_with("mpv isn't running"));
+
+        let listener = std::os::unix::net::UnixListener::bind(&second).unwrap();
+        quit(&second).unwrap();
+        let (connection, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        std::io::BufReader::new(connection).read_line(&mut line).unwrap();
+        assert_eq!(line, "{ \"command\": [\"quit\"] }\n");
+        std::fs::remove_file(&second).unwrap();
+    }
+
+    #[test]
+    fn positions_are_read_from_the_answers_among_events() {
+        let answers = concat!(
+            "{\"event\":\"playback-restart\"}\n",
+            "{\"data\":754.312,\"request_id\":1,\"error\":\"success\"}\n",
+            "{\"data\":true,\"request_id\":2,\"error\":\"success\"}\n",
+        );
+        let position = read_position(answers.as_bytes()).unwrap();
+        assert_eq!(position, Position { elapsed: Duration::from_secs_f64(754.312), paused: true });
+        assert!(read_position("{\"event\":\"idle\"}\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+// The music view of a listing: artists, albums and tracks told apart by
+// their UPnP class, tracks shown with their number, artist and length, and
+// an album's running time in the title. The generic view only knows folders
+// and files, which suits a video library but says little about an album.
+// Listings of tagged tracks get columns for their tags in either view.
+
+use crate::app::DirectoryItem;
+use crate::config::ColumnsConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Artist,
+    Album,
+    Genre,
+    Track,
+    Other,
+}
+
+impl Kind {
+    /// What `item` is by its class; the class names nest, so a
+    /// "object.container.person.musicArtist" is an artist and any
+    /// "object.item.audioItem.*" is a track.
+    pub fn of(item: &DirectoryItem) -> Self {
+        let class = item.class.as_deref().unwrap_or_default();
+        if class.starts_with("object.container.person") {
+            Kind::Artist
+        } else if class.starts_with("object.container.album.musicAlbum") {
+            Kind::Album
+        } else if class.starts_with("object.container.genre") {
+            Kind::Genre
+        } else if class.starts_with("object.item.audioItem") {
+            Kind::Track
+        } else {
+            Kind::Other
+        }
+    }
+}
+
+pub fn icon(item: &DirectoryItem) -> &'static str {
+    match Kind::of(item) {
+        Kind::Artist => "🎤",
+        Kind::Album => "💿",
+        Kind::Genre => "🏷",
+        Kind::Track => "🎵",
+        Kind::Other if item.is_directory => "📁",
+        Kind::Other => "📄",
+    }
+}
+
+/// A row of the music view: the entry's name, numbered for tracks, and what
+/// goes after it (artist, length) if anything.
+pub fn row(item: &DirectoryItem) -> (String, Option<String>) {
+    let music = item.music.as_ref();
+    let artist = music.and_then(|music| music.artist.clone());
+    match Kind::of(item) {
+        Kind::Track => {
+            let name = match music.and_then(|music| music.track) {
+                Some(track) => format!("{:02}. {}", track, item.name),
+                None => item.name.clone(),
+            };
+            let length = duration_secs(item).map(format_duration);
+            let detail: Vec<String> = artist.into_iter().chain(length).collect();
+            (name, (!detail.is_empty()).then(|| detail.join(" · ")))
+        }
+        Kind::Album => (item.name.clone(), artist),
+        _ => (item.name.clone(), None),
+    }
+}
+
+/// Whether `items` has tagged tracks, which are listed in columns.
+pub fn has_columns(items: &[DirectoryItem]) -> bool {
+    items.iter().any(|item| !item.is_directory && item.music.is_some())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Track,
+    Title,
+    Artist,
+    Album,
+    Length,
+}
+
+impl Column {
+    pub fn heading(self) -> &'static str {
+        match self {
+            Column::Track => "#",
+            Column::Title => "Title",
+            Column::Artist => "Artist",
+            Column::Album => "Album",
+            Column::Length => "Length",
+        }
+    }
+
+    /// What `item` shows in this column; an album's artist goes in the
+    /// artist column like a track's.
+    pub fn cell(self, item: &DirectoryItem) -> String {
+        let music = item.music.as_ref();
+        let tag = |tag: Option<&String>| tag.cloned().unwrap_or_default();
+        match self {
+            Column::Track => music.and_then(|music| music.track).map(|track| track.to_string()).unwrap_or_default(),
+            Column::Title => item.name.clone(),
+            Column::Artist => tag(music.and_then(|music| music.artist.as_ref())),
+            Column::Album => tag(music.and_then(|music| music.album.as_ref())),
+            Column::Length => duration_secs(item).map(format_duration).unwrap_or_default(),
+        }
+    }
+}
+
+/// The columns to show and their widths, leaving out those `[columns]`
+/// sets to 0. The title has no width of its own: it takes what is left.
+/// Lengths are only shown in the music view.
+pub fn columns(widths: &ColumnsConfig, music_view: bool) -> Vec<(Column, Option<u16>)> {
+    let length = if music_view { 8 } else { 0 };
+    [
+        (Column::Track, Some(widths.track)),
+        (Column::Title, None),
+        (Column::Artist, Some(widths.artist)),
+        (Column::Album, Some(widths.album)),
+        (Column::Length, Some(length)),
+    ]
+    .into_iter()
+    .filter(|(_, width)| *width != Some(0))
+    .collect()
+}
+
+/// "12 tracks, 48:12" when `items` holds tracks, for the listing's title.
+pub fn summary(items: &[DirectoryItem]) -> Option<String> {
+    let tracks: Vec<&DirectoryItem> = items.iter().filter(|item| Kind::of(item) == Kind::Track).collect();
+    let count = match tracks.len() {
+        0 => return None,
+        1 => "1 track".to_string(),
+        count => format!("{} tracks", count),
+    };
+    let total: u64 = tracks.iter().filter_map(|track| duration_secs(track)).sum();
+    Some(if total > 0 { format!("{}, {}", count, format_duration(total)) } else { count })
+}
+
+/// The item's duration ("H:MM:SS" with optional fractions) in seconds.
+pub fn duration_secs(item: &DirectoryItem) -> Option<u64> {
+    let duration = item.metadata.as_ref()?.duration.as_deref()?;
+    let whole = duration.split('.').next()?;
+    whole.split(':').try_fold(0u64, |secs, part| Some(secs * 60 + part.trim().parse::<u64>().ok()?))
+}
+
+/// "3:07", or "1:02:03" from an hour up.
+fn format_duration(secs: u64) -> String {
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 { format!("{}:{:02}:{:02}", hours, minutes, secs) } else { format!("{}:{:02}", minutes, secs) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mop_core::{FileMetadata, MusicTags};
+
+    #[test]
+    fn tracks_are_numbered_timed_and_split_into_columns() {
+        let track = |number: u32, name: &str, duration: &str| DirectoryItem {
+            name: name.to_string(),
+            metadata: Some(FileMetadata {
+                duration: Some(duration.to_string()),
+                format: Some("audio/flac".to_string()),
+                ..Default::default()
+            }),
+            class: Some("object.item.audioItem.musicTrack".to_string()),
+            music: Some(MusicTags {
+                artist: Some("Nina Simone".to_string()),
+                album: Some("Pastel Blues".to_string()),
+                track: Some(number),
+            }),
+            ..Default::default()
+        };
+        let album = DirectoryItem {
+            is_directory: true,
+            metadata: None,
+            class: Some("object.container.album.musicAlbum".to_string()),
+            music: Some(MusicTags { artist: Some("Nina Simone".to_string()), ..Default::default() }),
+            ..track(0, "Pastel Blues", "0")
+        };
+        let listing = [track(1, "Be My Husband", "0:02:58.000"), track(9, "Sinnerman", "0:10:19")];
+
+        assert_eq!(icon(&album), "💿");
+        assert_eq!(row(&album), ("Pastel Blues".to_string(), Some("Nina Simone".to_string())));
+        assert_eq!(row(&listing[0]), ("01. Be My Husband".to_string(), Some("Nina Simone · 2:58".to_string())));
+        assert_eq!(summary(&listing).as_deref(), Some("2 tracks, 13:17"));
+        assert_eq!(summary(std::slice::from_ref(&album)), None);
+        assert_eq!(format_duration(3723), "1:02:03");
+
+        let shown = |columns: Vec<(Column, Option<u16>)>| columns.into_iter().map(|(column, _)| column).collect();
+        let widths = ColumnsConfig { track: 3, artist: 0, album: 24 };
+        let plain: Vec<Column> = shown(columns(&widths, false));
+        assert_eq!(plain, [Column::Track, Column::Title, Column::Album]);
+        let music: Vec<Column> = shown(columns(&widths, true));
+        assert_eq!(music.last(), Some(&Column::Length));
+        assert!(has_columns(&listing) && !has_columns(std::slice::from_ref(&album)));
+        assert_eq!(Column::Track.cell(&listing[1]), "9");
+        assert_eq!(Column::Album.cell(&listing[1]), "Pastel Blues");
+        assert_eq!(Column::Artist.cell(&album), "Nina Simone");
+        assert_eq!(Column::Length.cell(&listing[1]), "10:19");
+    }
+}
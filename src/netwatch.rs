@@ -0,0 +1,162 @@
+// Noticing that the network changed under us: an interface coming up or
+// going down, a new default gateway, another Wi-Fi network, or the machine
+// waking from sleep. Whoever watches rescans, so the device list isn't left
+// showing the servers of the network we were on before.
+//
+// There is no portable change notification, so this polls. A change is only
+// reported once it has held for two polls, which skips the half-configured
+// states in between (Wi-Fi associated, no address yet).
+
+use mop_core::network_interfaces::{self, LanInterface};
+use std::net::Ipv4Addr;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, SystemTime};
+
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the network looks like from here.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NetworkState {
+    pub interfaces: Vec<LanInterface>,
+    pub gateway: Option<Ipv4Addr>,
+    pub ssid: Option<String>,
+}
+
+impl NetworkState {
+    pub fn current() -> Self {
+        Self {
+            interfaces: network_interfaces::lan_interfaces(),
+            gateway: crate::profile::default_gateway(),
+            ssid: crate::profile::current_ssid(),
+        }
+    }
+
+    /// What differs from `before`, for the log.
+    fn describe_change(&self, before: &Self) -> String {
+        let mut changes = Vec::new();
+        if self.interfaces != before.interfaces {
+            let addresses: Vec<String> =
+                self.interfaces.iter().map(|lan| format!("{} {}/{}", lan.name, lan.ip, lan.prefix)).collect();
+            changes.push(if addresses.is_empty() {
+                "no LAN interface".to_string()
+            } else {
+                format!("interfaces {}", addresses.join(", "))
+            });
+        }
+        if self.gateway != before.gateway {
+            changes.push(match self.gateway {
+                Some(gateway) => format!("gateway {}", gateway),
+                None => "no default route".to_string(),
+            });
+        }
+        if self.ssid != before.ssid {
+            changes.push(match &self.ssid {
+                Some(ssid) => format!("Wi-Fi \"{}\"", ssid),
+                None => "no Wi-Fi".to_string(),
+            });
+        }
+        changes.join(", ")
+    }
+}
+
+/// Compares each poll with the last and decides what is worth reporting.
+struct Watcher {
+    reported: NetworkState,
+    /// A state seen once, reported if the next poll sees it again.
+    candidate: Option<NetworkState>,
+    last_poll: SystemTime,
+    interval: Duration,
+}
+
+impl Watcher {
+    fn new(state: NetworkState, now: SystemTime, interval: Duration) -> Self {
+        Self { reported: state, candidate: None, last_poll: now, interval }
+    }
+
+    /// The change to report for `state` seen at `now`, if any. Wall-clock
+    /// time keeps running while suspended and the poll's sleep doesn't, so
+    /// a gap much longer than the interval means the machine slept.
+    fn observe(&mut self, state: NetworkState, now: SystemTime) -> Option<String> {
+        let gap = now.duration_since(self.last_poll).unwrap_or_default();
+        self.last_poll = now;
+        if gap > self.interval * 3 {
+            self.candidate = None;
+            self.reported = state;
+            return Some(format!("resumed after {}s", gap.as_secs()));
+        }
+
+        if state == self.reported {
+            self.candidate = None;
+            return None;
+        }
+        if self.candidate.as_ref() != Some(&state) {
+            self.candidate = Some(state);
+            return None;
+        }
+        self.candidate = None;
+        let change = state.describe_change(&self.reported);
+        self.reported = state;
+        Some(change)
+    }
+}
+
+/// Poll the network on a background thread. Each settled change arrives as
+/// a description of what changed; the thread ends when the receiver is
+/// dropped.
+pub fn watch(interval: Duration) -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut watcher = Watcher::new(NetworkState::current(), SystemTime::now(), interval);
+        loop {
+            std::thread::sleep(interval);
+            if let Some(change) = watcher.observe(NetworkState::current(), SystemTime::now()) {
+                tracing::info!(target: "mop::net", "Network changed: {}", change);
+                if tx.send(change).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_reported_once_settled_and_after_sleep() {
+        let home = NetworkState {
+            interfaces: vec![LanInterface { name: "wlan0".to_string(), ip: Ipv4Addr::new(192, 168, 1, 20), prefix: 24 }],
+            gateway: Some(Ipv4Addr::new(192, 168, 1, 1)),
+            ssid: Some("Home".to_string()),
+        };
+        let disconnected = NetworkState::default();
+        let office = NetworkState {
+            interfaces: vec![LanInterface { name: "wlan0".to_string(), ip: Ipv4Addr::new(10, 0, 4, 7), prefix: 22 }],
+            gateway: Some(Ipv4Addr::new(10, 0, 4, 1)),
+            ssid: Some("Office".to_string()),
+        };
+        let interval = Duration::from_secs(5);
+        let mut now = SystemTime::UNIX_EPOCH;
+        let mut watcher = Watcher::new(home.clone(), now, interval);
+        let mut poll = |state: &NetworkState, after: Duration| {
+            now += after;
+            watcher.observe(state.clone(), now)
+        };
+
+        assert_eq!(poll(&home, interval), None);
+        // Passing through "disconnected" for one poll isn't worth a rescan
+        assert_eq!(poll(&disconnected, interval), None);
+        assert_eq!(poll(&office, interval), None);
+        assert_eq!(
+            poll(&office, interval).as_deref(),
+            Some("interfaces wlan0 10.0.4.7/22, gateway 10.0.4.1, Wi-Fi \"Office\"")
+        );
+        assert_eq!(poll(&office, interval), None);
+
+        assert_eq!(poll(&office, Duration::from_secs(3600)).as_deref(), Some("resumed after 3600s"));
+        assert_eq!(poll(&disconnected, interval), None);
+        assert_eq!(poll(&disconnected, interval).as_deref(), Some("no LAN interface, no default route, no Wi-Fi"));
+    }
+}
@@ -0,0 +1,185 @@
+// JSON structures printed by the headless subcommands with `--json`.
+//
+// These are a public interface for scripts: fields may be added, but
+// existing ones keep their names and meaning. See docs/json-output.md.
+
+use crate::app::DirectoryItem;
+use crate::upnp::UpnpDevice;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Device {
+    pub name: String,
+    /// Device description URL, or the probed base URL for port-scan hits.
+    pub location: String,
+    pub base_url: String,
+    /// UPnP device type, or how the device was found ("DirectScan").
+    pub kind: Option<String>,
+    pub content_directory_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Item {
+    /// "container" or "item".
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub title: String,
+    pub size: Option<u64>,
+    /// As reported by the server, usually "H:MM:SS.mmm".
+    pub duration: Option<String>,
+    pub mime: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Played {
+    pub player: String,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cast {
+    /// Name of the receiver now playing.
+    pub renderer: String,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadResult {
+    /// Destination path on disk.
+    pub path: String,
+    pub url: String,
+    /// "downloaded", "present" (already on disk) or "failed".
+    pub status: &'static str,
+    pub bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigWritten {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaptureWritten {
+    pub path: String,
+    pub responses: usize,
+    pub descriptions: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportWritten {
+    pub path: String,
+    pub devices: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Error {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorDetail {
+    pub message: String,
+}
+
+impl From<&UpnpDevice> for Device {
+    fn from(device: &UpnpDevice) -> Self {
+        Self {
+            name: device.name.clone(),
+            location: device.location.clone(),
+            base_url: device.base_url.clone(),
+            kind: device.device_client.clone(),
+            content_directory_url: device.content_directory_url.clone(),
+        }
+    }
+}
+
+impl From<&DirectoryItem> for Item {
+    fn from(item: &DirectoryItem) -> Self {
+        let metadata = item.metadata.as_ref();
+        Self {
+            kind: if item.is_directory { "container" } else { "item" },
+            title: item.name.clone(),
+            size: metadata.and_then(|m| m.size),
+            duration: metadata.and_then(|m| m.duration.clone()),
+            mime: metadata.and_then(|m| m.format.clone()),
+            url: item.url.clone(),
+        }
+    }
+}
+
+impl Item {
+    /// A bare URL played without a server listing behind it.
+    pub fn from_url(url: &str) -> Self {
+        Self {
+            kind: "item",
+            title: url.to_string(),
+            size: None,
+            duration: None,
+            mime: None,
+            url: Some(url.to_string()),
+        }
+    }
+}
+
+impl Error {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            error: ErrorDetail { message: message.into() },
+        }
+    }
+}
+
+/// Print a value as pretty JSON on stdout.
+pub fn print<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("mop: failed to serialize output: {}", e),
+    }
+}
+
+/// Print an error document as JSON on stderr, keeping stdout for results.
+pub fn print_error(error: &Error) {
+    match serde_json::to_string_pretty(error) {
+        Ok(json) => eprintln!("{}", json),
+        Err(e) => eprintln!("mop: failed to serialize output: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mop_core::FileMetadata;
+
+    #[test]
+    fn item_json_shape_is_stable() {
+        let item = DirectoryItem {
+            name: "Blue Train".to_string(),
+            url: Some("http://nas:32469/1.flac".to_string()),
+            metadata: Some(FileMetadata {
+                size: Some(42),
+                duration: Some("0:10:43.000".to_string()),
+                format: Some("audio/flac".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(Item::from(&item)).unwrap(),
+            serde_json::json!({
+                "type": "item",
+                "title": "Blue Train",
+                "size": 42,
+                "duration": "0:10:43.000",
+                "mime": "audio/flac",
+                "url": "http://nas:32469/1.flac",
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(Error::new("boom")).unwrap(),
+            serde_json::json!({ "error": { "message": "boom" } })
+        );
+    }
+}
@@ -0,0 +1,197 @@
+// Which files the local player can play. mpv is asked once which protocols
+// (`--list-protocols`) and decoders (`--vd=help`, `--ad=help`) it was built
+// with, and a file's DLNA profile (DLNA.ORG_PN in its protocolInfo) or, for
+// servers that give none, its MIME type says which decoder it needs. With
+// `[players] unplayable` set, files the player would only fail on are shown
+// dimmed or left out of listings, and Enter on one says why instead of
+// starting the player. Other players can't be asked, so everything counts as
+// playable with them, as does a file whose format says nothing about its
+// codec: a listing never loses a file MOP only guesses is unplayable.
+
+use crate::app::DirectoryItem;
+use crate::config::Config;
+use std::collections::{HashMap, HashSet};
+use std::process::{Command, Stdio};
+
+/// The values `[players] unplayable` takes.
+pub const MODES: [&str; 3] = ["show", "dim", "hide"];
+
+/// Decoders (any one of them) needed for DLNA profiles starting with each
+/// prefix, the more specific prefixes first.
+const PROFILES: &[(&str, &[&str])] = &[
+    ("AVC_", &["h264"]),
+    ("HEVC_", &["hevc"]),
+    ("MPEG4_P2", &["mpeg4"]),
+    ("MPEG1", &["mpeg1video"]),
+    ("MPEG_", &["mpeg2video"]),
+    ("DVBMPEG", &["mpeg2video"]),
+    ("WMV", &["wmv3", "vc1"]),
+    ("VC1_", &["vc1"]),
+    ("HEAAC", &["aac"]),
+    ("AAC", &["aac"]),
+    ("MP3", &["mp3"]),
+    ("WMA", &["wmav2", "wmapro"]),
+    ("AC3", &["ac3"]),
+    ("LPCM", &["pcm_s16be"]),
+    ("JPEG_", &["mjpeg"]),
+    ("PNG_", &["png"]),
+    ("GIF_", &["gif"]),
+];
+
+/// Decoders needed for MIME types that name a codec rather than a container.
+const MIME_TYPES: &[(&str, &[&str])] = &[
+    ("audio/mpeg", &["mp3"]),
+    ("audio/flac", &["flac"]),
+    ("audio/x-flac", &["flac"]),
+    ("audio/x-ape", &["ape"]),
+    ("audio/x-ms-wma", &["wmav2", "wmapro"]),
+    ("audio/x-wavpack", &["wavpack"]),
+    ("video/x-ms-wmv", &["wmv3", "vc1"]),
+    ("image/jpeg", &["mjpeg"]),
+    ("image/png", &["png"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+    ("image/heic", &["hevc"]),
+];
+
+/// What a player can play, as it said when asked.
+#[derive(Debug, Clone, Default)]
+pub struct Support {
+    program: String,
+    protocols: HashSet<String>,
+    decoders: HashSet<String>,
+}
+
+impl Support {
+    /// Why this player can't play `item`, or None if it can or it's not
+    /// known that it can't.
+    pub fn refuses(&self, item: &DirectoryItem) -> Option<String> {
+        let scheme = item.url.as_deref().and_then(|url| url.split_once("://")).map(|(scheme, _)| scheme);
+        if let Some(scheme) = scheme
+            && !self.protocols.is_empty()
+            && !self.protocols.contains(&scheme.to_lowercase())
+        {
+            return Some(format!("{} can't open {} URLs", self.program, scheme));
+        }
+        let metadata = item.metadata.as_ref()?;
+        let needed = metadata.dlna_flags.as_deref().and_then(profile_decoders).or_else(|| {
+            let mime = metadata.format.as_deref()?.to_lowercase();
+            MIME_TYPES.iter().find(|(known, _)| *known == mime).map(|(_, decoders)| *decoders)
+        })?;
+        if self.decoders.is_empty() || needed.iter().any(|decoder| self.decoders.contains(*decoder)) {
+            return None;
+        }
+        Some(format!("{} has no {} decoder", self.program, needed[0]))
+    }
+}
+
+/// The decoders a DLNA profile needs, from DLNA.ORG_PN among `flags`.
+fn profile_decoders(flags: &str) -> Option<&'static [&'static str]> {
+    let profile = flags.split(';').find_map(|flag| flag.trim().strip_prefix("DLNA.ORG_PN="))?;
+    PROFILES.iter().find(|(prefix, _)| profile.starts_with(prefix)).map(|(_, decoders)| *decoders)
+}
+
+/// The player commands `config` can run files with, each asked what it
+/// plays: the ones that answered, by command.
+pub fn probe(config: &Config) -> HashMap<String, Support> {
+    let run = &config.mop.run;
+    let mut commands: Vec<&str> =
+        ["video/*", "audio/*", "image/*"].iter().map(|mime| config.players.for_mime(Some(mime), run)).collect();
+    commands.push(run);
+    commands.sort_unstable();
+    commands.dedup();
+    commands.into_iter().filter_map(|command| Some((command.to_string(), ask(command)?))).collect()
+}
+
+/// Ask the player `command` runs what it plays, if it's mpv.
+fn ask(command: &str) -> Option<Support> {
+    let program = crate::app::split_command(command).into_iter().next()?;
+    if crate::app::program_name(command) != "mpv" {
+        return None;
+    }
+    let output = |option: &str| {
+        let output = Command::new(&program).arg(option).stdin(Stdio::null()).stderr(Stdio::null()).output();
+        match output {
+            Ok(output) => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+            Err(e) => {
+                tracing::info!(target: "mop::app", "Couldn't ask {} what it plays: {}", program, e);
+                None
+            }
+        }
+    };
+    let protocols = protocols(&output("--list-protocols")?);
+    let lists = [output("--vd=help")?, output("--ad=help")?];
+    let decoders: HashSet<String> = lists.iter().flat_map(|list| decoders(list)).collect();
+    let (count, decoder_count) = (protocols.len(), decoders.len());
+    tracing::info!(target: "mop::app", "{} plays {} protocols with {} decoders", program, count, decoder_count);
+    Some(Support { program: crate::app::program_name(command), protocols, decoders })
+}
+
+/// The protocols in `mpv --list-protocols`, under headings ending in ':':
+/// mpv's own as "http://", FFmpeg's as "http".
+fn protocols(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':') && !line.contains(' '))
+        .map(|line| line.trim_end_matches("://").to_lowercase())
+        .collect()
+}
+
+/// The decoders and codecs in `mpv --vd=help` or `--ad=help`, from lines
+/// like "    libdav1d (av1) - dav1d AV1 decoder".
+fn decoders(list: &str) -> Vec<String> {
+    list.lines()
+        .filter_map(|line| line.split_once(" - "))
+        .flat_map(|(names, _)| names.split_whitespace().map(|name| name.trim_matches(['(', ')']).to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_needing_a_decoder_mpv_lacks_are_refused() {
+        let support = Support {
+            program: "mpv".to_string(),
+            protocols: protocols(
+                "Protocols:\n\n appending://\n file://\n http://\n https://\n\nLibavformat protocols:\n\n rtsp\n",
+            ),
+            decoders: [
+                decoders("Video decoders:\n    h264 - H.264 / AVC\n    libdav1d (av1) - dav1d AV1 decoder\n"),
+                decoders("Audio decoders:\n    aac - AAC (Advanced Audio Coding)\n    mp3float (mp3) - MP3\n"),
+            ]
+            .concat()
+            .into_iter()
+            .collect(),
+        };
+        let file = |url: &str, format: &str, flags: Option<&str>| DirectoryItem {
+            id: Some("1".to_string()),
+            name: "file".to_string(),
+            url: Some(url.to_string()),
+            metadata: Some(mop_core::FileMetadata {
+                format: Some(format.to_string()),
+                dlna_flags: flags.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let avc = file("http://nas/a.mp4", "video/mp4", Some("DLNA.ORG_PN=AVC_MP4_HP_HD_AAC"));
+        assert_eq!(support.refuses(&avc), None);
+        let hevc = file("http://nas/a.mkv", "video/x-matroska", Some("DLNA.ORG_PN=HEVC_MKV_MAIN;DLNA.ORG_OP=01"));
+        assert_eq!(support.refuses(&hevc), Some("mpv has no hevc decoder".to_string()));
+        assert_eq!(support.refuses(&file("http://nas/a.mp3", "audio/mpeg", None)), None);
+        assert_eq!(
+            support.refuses(&file("http://nas/a.flac", "audio/x-flac", None)),
+            Some("mpv has no flac decoder".to_string())
+        );
+        // Containers say nothing about what's inside
+        assert_eq!(support.refuses(&file("http://nas/a.mkv", "video/x-matroska", None)), None);
+        assert_eq!(support.refuses(&file("rtsp://nas/a", "video/mp4", None)), None);
+        assert_eq!(
+            support.refuses(&file("rtmp://nas/a", "video/mp4", None)),
+            Some("mpv can't open rtmp URLs".to_string())
+        );
+    }
+}
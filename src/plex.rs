@@ -0,0 +1,481 @@
+// Plex account linking via the PIN flow: MOP asks plex.tv for a short code,
+// the user enters it at https://plex.tv/link, and MOP polls until plex.tv
+// hands out a token for this client.
+//
+// Also the choice between a Plex video as it is and a transcode of it: the
+// files Plex's DLNA side hands out can be in formats the local player can't
+// decode, so MOP finds the item in the Plex library by its title, asks
+// Plex's transcode decision endpoint whether it can be played directly, and
+// offers the file itself alongside HLS transcodes at a few qualities.
+
+use crate::app::DirectoryItem;
+use crate::upnp::UpnpDevice;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PINS_URL: &str = "https://plex.tv/api/v2/pins";
+pub const LINK_URL: &str = "https://plex.tv/link";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// plex.tv expires short PINs after 15 minutes.
+const PIN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pin {
+    id: u64,
+    code: String,
+    auth_token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum LinkEvent {
+    /// The code to enter at plex.tv/link.
+    Code(String),
+    Linked(String),
+    Failed(String),
+}
+
+/// A link attempt running in the background. Dropping it stops the polling.
+pub struct Link {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Whether a device looks like a Plex Media Server (DLNA or direct).
+pub fn is_plex(device: &UpnpDevice) -> bool {
+    let mentions_plex = |text: &str| text.to_lowercase().contains("plex");
+    mentions_plex(&device.name)
+        || device.device_client.as_deref().is_some_and(mentions_plex)
+        || url::Url::parse(&device.base_url)
+            .ok()
+            .and_then(|url| url.port())
+            .is_some_and(|port| port == 32400 || port == 32469)
+}
+
+/// The `[servers]` key a new token is stored under: the device's host.
+pub fn server_key(device: &UpnpDevice) -> String {
+    url::Url::parse(&device.location)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| device.name.clone())
+}
+
+/// Identifies this MOP install to plex.tv. It has to stay the same between
+/// runs, otherwise every link shows up as a new device in the Plex account.
+fn client_identifier() -> String {
+    // FNV-1a, so the value doesn't change with the Rust version
+    let seed = format!(
+        "{}:{}",
+        std::env::var("USER").unwrap_or_default(),
+        crate::config::get_config_path().display()
+    );
+    let hash = seed
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("mop-{:016x}", hash)
+}
+
+fn client() -> Result<reqwest::blocking::Client, String> {
+    use reqwest::header::{ACCEPT, HeaderMap, HeaderValue};
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    headers.insert("X-Plex-Product", HeaderValue::from_static("MOP"));
+    headers.insert("X-Plex-Version", HeaderValue::from_static(env!("CARGO_PKG_VERSION")));
+    headers.insert(
+        "X-Plex-Client-Identifier",
+        HeaderValue::from_str(&client_identifier()).map_err(|e| e.to_string())?,
+    );
+    crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)?
+        .default_headers(headers)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn request_pin(client: &reqwest::blocking::Client) -> Result<Pin, String> {
+    let response = client
+        .post(PINS_URL)
+        .form(&[("strong", "false")])
+        .send()
+        .map_err(|e| format!("Could not reach plex.tv: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("plex.tv refused the PIN request: {}", response.status()));
+    }
+    response.json().map_err(|e| format!("Unexpected answer from plex.tv: {}", e))
+}
+
+fn check_pin(client: &reqwest::blocking::Client, id: u64) -> Result<Option<String>, String> {
+    let response = client
+        .get(format!("{}/{}", PINS_URL, id))
+        .send()
+        .map_err(|e| format!("Could not reach plex.tv: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("plex.tv rejected the PIN: {}", response.status()));
+    }
+    let pin: Pin = response
+        .json()
+        .map_err(|e| format!("Unexpected answer from plex.tv: {}", e))?;
+    Ok(pin.auth_token.filter(|token| !token.is_empty()))
+}
+
+/// Request a PIN and wait for it to be linked, reporting progress to
+/// `notify` from a background thread.
+pub fn start_link(notify: impl Fn(LinkEvent) + Send + 'static) -> Link {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let stop = cancelled.clone();
+
+    std::thread::spawn(move || {
+        let result = (|| {
+            let client = client()?;
+            let pin = request_pin(&client)?;
+            tracing::info!(target: "mop::net", "Plex PIN {} requested", pin.id);
+            notify(LinkEvent::Code(pin.code.clone()));
+
+            let started = Instant::now();
+            while started.elapsed() < PIN_LIFETIME {
+                std::thread::sleep(POLL_INTERVAL);
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                if let Some(token) = check_pin(&client, pin.id)? {
+                    return Ok(Some(token));
+                }
+            }
+            Err("The code expired before it was entered".to_string())
+        })();
+
+        let event = match result {
+            Ok(Some(token)) => LinkEvent::Linked(token),
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!(target: "mop::net", "Plex link failed: {}", e);
+                LinkEvent::Failed(e)
+            }
+        };
+        notify(event);
+    });
+
+    Link { cancelled }
+}
+
+/// A transcode Plex can be asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Quality {
+    /// Video bitrate limit in kbps.
+    max_bitrate: u32,
+    /// Largest frame size, "1920x1080".
+    resolution: &'static str,
+    /// Shown as "1080p".
+    name: &'static str,
+}
+
+/// The transcodes offered, best first, as in Plex's own quality menus.
+const QUALITIES: [Quality; 5] = [
+    Quality { max_bitrate: 20_000, resolution: "1920x1080", name: "1080p" },
+    Quality { max_bitrate: 10_000, resolution: "1920x1080", name: "1080p" },
+    Quality { max_bitrate: 4_000, resolution: "1280x720", name: "720p" },
+    Quality { max_bitrate: 2_000, resolution: "1280x720", name: "720p" },
+    Quality { max_bitrate: 1_500, resolution: "720x480", name: "480p" },
+];
+
+/// One way to play a Plex item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stream {
+    /// "Direct play (h264 1080p mkv)", "Transcode 720p 4 Mbps".
+    pub label: String,
+    pub url: String,
+}
+
+/// How a Plex item can be played: the file itself first, then transcodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Streams {
+    /// Plex's verdict on playing the file as it is ("Direct play OK.", or why
+    /// it would transcode).
+    pub decision: String,
+    pub direct_play: bool,
+    pub streams: Vec<Stream>,
+}
+
+/// Types of Plex items that have video to transcode.
+const VIDEO_TYPES: [&str; 3] = ["movie", "episode", "clip"];
+
+#[derive(Debug, Deserialize)]
+struct Envelope<T> {
+    #[serde(rename = "MediaContainer")]
+    media_container: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResults {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<Metadata>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    rating_key: String,
+    title: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(rename = "Media", default)]
+    media: Vec<Media>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Media {
+    /// kbps
+    bitrate: Option<u32>,
+    video_resolution: Option<String>,
+    video_codec: Option<String>,
+    container: Option<String>,
+    #[serde(rename = "Part", default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Part {
+    key: String,
+    size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Decision {
+    direct_play_decision_code: Option<u32>,
+    direct_play_decision_text: Option<String>,
+    general_decision_text: Option<String>,
+}
+
+/// Plex's decision code for "fine as it is".
+const DECISION_OK: u32 = 1000;
+
+/// The ways to play `item`, a file listed by the Plex server `device`.
+pub fn streams(device: &UpnpDevice, item: &DirectoryItem) -> Result<Streams, String> {
+    let client = server_client(device)?;
+    let mut search = server_url(device, "/search")?;
+    search.query_pairs_mut().append_pair("query", &item.name);
+    let results: Envelope<SearchResults> = get_json(&client, device, search)?;
+    let size = item.metadata.as_ref().and_then(|metadata| metadata.size);
+    let found = find(&results.media_container.metadata, &item.name, size)
+        .ok_or_else(|| format!("Plex has no video titled '{}'", item.name))?;
+    let media = found.media.first().ok_or_else(|| format!("Plex has no file for '{}'", found.title))?;
+    let part = media.parts.first().ok_or_else(|| format!("Plex has no file for '{}'", found.title))?;
+
+    // Session ids only have to differ between playbacks
+    let session = format!("{}-{}", client_identifier(), chrono::Utc::now().timestamp_millis());
+    let mut decision_url = server_url(device, "/video/:/transcode/universal/decision")?;
+    add_transcode_params(&mut decision_url, device, &found.rating_key, &session, None);
+    let decision: Envelope<Decision> = get_json(&client, device, decision_url)?;
+    let decision = decision.media_container;
+
+    let mut direct = server_url(device, &part.key)?;
+    if let Some(token) = &device.token {
+        direct.query_pairs_mut().append_pair("X-Plex-Token", token);
+    }
+    // Plex gives resolutions as "1080", "720", "4k" or "sd"
+    let resolution = media.video_resolution.as_ref().map(|resolution| match resolution.parse::<u32>() {
+        Ok(_) => format!("{}p", resolution),
+        Err(_) => resolution.clone(),
+    });
+    let details: Vec<&str> = [&media.video_codec, &resolution, &media.container]
+        .into_iter()
+        .filter_map(|detail| detail.as_deref())
+        .collect();
+    let mut streams = vec![Stream {
+        label: if details.is_empty() {
+            "Direct play".to_string()
+        } else {
+            format!("Direct play ({})", details.join(" "))
+        },
+        url: direct.to_string(),
+    }];
+    // A transcode at a higher bitrate than the file's gains nothing
+    let worth_it = |quality: &&Quality| media.bitrate.is_none_or(|bitrate| quality.max_bitrate < bitrate);
+    for quality in QUALITIES.iter().filter(worth_it) {
+        let mut url = server_url(device, "/video/:/transcode/universal/start.m3u8")?;
+        add_transcode_params(&mut url, device, &found.rating_key, &session, Some(quality));
+        streams.push(Stream {
+            label: format!("Transcode {} {}", quality.name, format_bitrate(quality.max_bitrate)),
+            url: url.to_string(),
+        });
+    }
+
+    Ok(Streams {
+        decision: decision
+            .direct_play_decision_text
+            .or(decision.general_decision_text)
+            .unwrap_or_else(|| "Plex gave no decision".to_string()),
+        direct_play: decision.direct_play_decision_code == Some(DECISION_OK),
+        streams,
+    })
+}
+
+/// The video among search `results` titled `title`, preferring the one whose
+/// file is `size` bytes when there are several.
+fn find<'a>(results: &'a [Metadata], title: &str, size: Option<u64>) -> Option<&'a Metadata> {
+    let title = title.trim().to_lowercase();
+    let mut matching = results
+        .iter()
+        .filter(|result| VIDEO_TYPES.contains(&result.kind.as_str()) && result.title.trim().to_lowercase() == title);
+    let first = matching.clone().next();
+    let same_size = |result: &&Metadata| {
+        size.is_some() && result.media.iter().flat_map(|media| &media.parts).any(|part| part.size == size)
+    };
+    matching.find(same_size).or(first)
+}
+
+/// Ask for `quality`, or with none, whether the file plays as it is.
+fn add_transcode_params(url: &mut url::Url, device: &UpnpDevice, key: &str, session: &str, quality: Option<&Quality>) {
+    let mut query = url.query_pairs_mut();
+    query
+        .append_pair("path", &format!("/library/metadata/{}", key))
+        .append_pair("mediaIndex", "0")
+        .append_pair("partIndex", "0")
+        .append_pair("protocol", "hls")
+        .append_pair("fastSeek", "1")
+        .append_pair("directStream", "1")
+        .append_pair("session", session)
+        .append_pair("X-Plex-Product", "MOP")
+        .append_pair("X-Plex-Platform", "Generic")
+        .append_pair("X-Plex-Client-Identifier", &client_identifier());
+    match quality {
+        Some(quality) => {
+            query
+                .append_pair("directPlay", "0")
+                .append_pair("videoQuality", "100")
+                .append_pair("maxVideoBitrate", &quality.max_bitrate.to_string())
+                .append_pair("videoResolution", quality.resolution);
+        }
+        None => {
+            query.append_pair("directPlay", "1");
+        }
+    }
+    // Players fetch the playlist themselves, without MOP's headers
+    if let Some(token) = &device.token {
+        query.append_pair("X-Plex-Token", token);
+    }
+}
+
+fn format_bitrate(kbps: u32) -> String {
+    if kbps.is_multiple_of(1000) {
+        format!("{} Mbps", kbps / 1000)
+    } else {
+        format!("{:.1} Mbps", kbps as f64 / 1000.0)
+    }
+}
+
+fn server_url(device: &UpnpDevice, path: &str) -> Result<url::Url, String> {
+    url::Url::parse(&device.base_url)
+        .and_then(|base| base.join(path))
+        .map_err(|e| format!("Bad Plex URL {}{}: {}", device.base_url, path, e))
+}
+
+fn server_client(device: &UpnpDevice) -> Result<reqwest::blocking::Client, String> {
+    use reqwest::header::{ACCEPT, HeaderMap, HeaderValue};
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    if let Some(token) = &device.token {
+        headers.insert("X-Plex-Token", HeaderValue::from_str(token).map_err(|e| e.to_string())?);
+    }
+    device.blocking_client_builder()?
+        .default_headers(headers)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn get_json<T: serde::de::DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    device: &UpnpDevice,
+    url: url::Url,
+) -> Result<T, String> {
+    let response = crate::auth::send_blocking(client, client.get(url), device.credentials.as_ref())
+        .map_err(|e| format!("Could not reach Plex: {}", e))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        return Err("Plex wants a token; link the account first".to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("Plex answered {}", status));
+    }
+    response.json().map_err(|e| format!("Unexpected answer from Plex: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str, location: &str, base_url: &str) -> UpnpDevice {
+        UpnpDevice {
+            name: name.to_string(),
+            location: location.to_string(),
+            base_url: base_url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn recognises_plex_servers_and_keys_them_by_host() {
+        let dlna = device(
+            "nasuntu [MediaServer:1]",
+            "http://192.168.1.31:32469/DeviceDescription.xml",
+            "http://192.168.1.31:32400",
+        );
+        assert!(is_plex(&dlna));
+        assert_eq!(server_key(&dlna), "192.168.1.31");
+
+        let jellyfin = device("Jellyfin Server", "http://192.168.1.5:8096", "http://192.168.1.5:8096");
+        assert!(!is_plex(&jellyfin));
+    }
+
+    #[test]
+    fn finds_the_video_a_dlna_entry_is_and_asks_for_transcodes_of_it() {
+        let search = r#"{"MediaContainer":{"size":3,"Metadata":[
+            {"ratingKey":"7","title":"Heat","type":"artist"},
+            {"ratingKey":"12","title":"Heat","type":"movie","Media":[{"bitrate":9000,"videoResolution":"1080",
+                "videoCodec":"hevc","container":"mkv",
+                "Part":[{"key":"/library/parts/40/1/file.mkv","size":1400000000}]}]},
+            {"ratingKey":"13","title":"heat ","type":"movie","Media":[{"Part":[{"key":"/library/parts/41/1/file.mkv",
+                "size":8500000000}]}]}
+        ]}}"#;
+        let results: Envelope<SearchResults> = serde_json::from_str(search).unwrap();
+        let results = &results.media_container.metadata;
+        assert_eq!(find(results, "Heat", None).map(|found| found.rating_key.as_str()), Some("12"));
+        assert_eq!(find(results, "Heat", Some(8_500_000_000)).map(|found| found.rating_key.as_str()), Some("13"));
+        assert!(find(results, "Ronin", None).is_none());
+
+        let decision = r#"{"MediaContainer":{"directPlayDecisionCode":3000,
+            "directPlayDecisionText":"App cannot direct play this item. Direct play is disabled.",
+            "generalDecisionText":"Direct play not available; Conversion OK."}}"#;
+        let decision: Envelope<Decision> = serde_json::from_str(decision).unwrap();
+        assert_eq!(decision.media_container.direct_play_decision_code, Some(3000));
+
+        let mut server = device("Plex", "http://192.168.1.31:32469/DeviceDescription.xml", "http://192.168.1.31:32400");
+        server.token = Some("secret".to_string());
+        let mut url = server_url(&server, "/video/:/transcode/universal/start.m3u8").unwrap();
+        add_transcode_params(&mut url, &server, "12", "s1", Some(&QUALITIES[2]));
+        let query: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/video/:/transcode/universal/start.m3u8");
+        assert_eq!(query["path"], "/library/metadata/12");
+        assert_eq!((query["maxVideoBitrate"].as_str(), query["videoResolution"].as_str()), ("4000", "1280x720"));
+        assert_eq!((query["directPlay"].as_str(), query["X-Plex-Token"].as_str()), ("0", "secret"));
+        assert_eq!(format_bitrate(QUALITIES[4].max_bitrate), "1.5 Mbps");
+    }
+
+    #[test]
+    fn parses_pin_responses() {
+        let pin: Pin = serde_json::from_str(r#"{"id":42,"code":"AB12","authToken":null,"expiresIn":900}"#).unwrap();
+        assert_eq!((pin.id, pin.code.as_str(), pin.auth_token), (42, "AB12", None));
+    }
+}
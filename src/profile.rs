@@ -0,0 +1,265 @@
+use crate::config::Config;
+use std::net::Ipv4Addr;
+use std::process::Command;
+
+/// What we can tell about the network we're on, used to pick a profile.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkIdentity {
+    pub ssid: Option<String>,
+    pub gateway_mac: Option<String>,
+}
+
+impl NetworkIdentity {
+    pub fn detect() -> Self {
+        let identity = Self {
+            ssid: current_ssid(),
+            gateway_mac: default_gateway().and_then(mac_for_ip),
+        };
+        tracing::debug!(target: "mop::net", "Network identity: ssid={:?}, gateway_mac={:?}",
+            identity.ssid, identity.gateway_mac);
+        identity
+    }
+}
+
+/// Pick the profile to use: an explicit name (CLI flag, then `mop.profile`)
+/// wins, otherwise the first profile whose detect rules match the network.
+pub fn resolve_profile(config: &Config, requested: Option<&str>) -> Result<Option<String>, String> {
+    let requested = requested.or(Some(config.mop.profile.as_str()).filter(|p| !p.is_empty()));
+    if let Some(name) = requested {
+        return if config.profiles.contains_key(name) {
+            Ok(Some(name.to_string()))
+        } else {
+            Err(format!("Unknown profile '{}'", name))
+        };
+    }
+
+    if config.profiles.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(detect_profile(config, &NetworkIdentity::detect()))
+}
+
+fn detect_profile(config: &Config, identity: &NetworkIdentity) -> Option<String> {
+    config
+        .profiles
+        .iter()
+        .find(|(_, profile)| {
+            let ssid_matches = identity
+                .ssid
+                .as_ref()
+                .is_some_and(|ssid| profile.detect.ssid.iter().any(|s| s == ssid));
+            let mac_matches = identity.gateway_mac.as_ref().is_some_and(|mac| {
+                profile
+                    .detect
+                    .gateway_mac
+                    .iter()
+                    .any(|m| normalize_mac(m) == *mac)
+            });
+            ssid_matches || mac_matches
+        })
+        .map(|(name, _)| name.clone())
+}
+
+fn normalize_mac(mac: &str) -> String {
+    mac.trim().to_lowercase().replace('-', ":")
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn current_ssid() -> Option<String> {
+    let output = run("networksetup", &["-getairportnetwork", "en0"])?;
+    output
+        .strip_prefix("Current Wi-Fi Network: ")
+        .map(str::to_string)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+pub(crate) fn current_ssid() -> Option<String> {
+    if let Some(ssid) = run("iwgetid", &["-r"]).filter(|s| !s.is_empty()) {
+        return Some(ssid);
+    }
+    // Without `--rescan no` nmcli may scan first, which takes seconds
+    let output = run("nmcli", &["-t", "-f", "active,ssid", "dev", "wifi", "list", "--rescan", "no"])?;
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("yes:"))
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn default_gateway() -> Option<Ipv4Addr> {
+    let output = run("route", &["-n", "get", "default"])?;
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("gateway:"))
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+pub(crate) fn default_gateway() -> Option<Ipv4Addr> {
+    parse_proc_route(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+#[cfg(target_os = "macos")]
+fn mac_for_ip(ip: Ipv4Addr) -> Option<String> {
+    // "? (192.168.1.1) at a4:91:b1:0:1:2 on en0 ifscope [ethernet]"
+    let output = run("arp", &["-n", &ip.to_string()])?;
+    let mac = output.split(" at ").nth(1)?.split_whitespace().next()?;
+    // arp drops leading zeros from each octet
+    let octets: Vec<String> = mac.split(':').map(|octet| format!("{:0>2}", octet)).collect();
+    (octets.len() == 6).then(|| octets.join(":").to_lowercase())
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn mac_for_ip(ip: Ipv4Addr) -> Option<String> {
+    parse_proc_arp(&std::fs::read_to_string("/proc/net/arp").ok()?, ip)
+}
+
+#[cfg(windows)]
+pub(crate) fn current_ssid() -> Option<String> {
+    parse_netsh_ssid(&run("netsh", &["wlan", "show", "interfaces"])?)
+}
+
+#[cfg(windows)]
+pub(crate) fn default_gateway() -> Option<Ipv4Addr> {
+    parse_route_print(&run("route", &["print", "-4", "0.0.0.0"])?)
+}
+
+#[cfg(windows)]
+fn mac_for_ip(ip: Ipv4Addr) -> Option<String> {
+    parse_arp_a(&run("arp", &["-a", &ip.to_string()])?, ip)
+}
+
+/// The default route's gateway from /proc/net/route (hex, little endian).
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+fn parse_proc_route(content: &str) -> Option<Ipv4Addr> {
+    content.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        (gateway != 0).then(|| Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+#[cfg_attr(any(target_os = "macos", windows), allow(dead_code))]
+fn parse_proc_arp(content: &str, ip: Ipv4Addr) -> Option<String> {
+    let ip = ip.to_string();
+    content.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.len() >= 4 && fields[0] == ip && fields[3] != "00:00:00:00:00:00")
+            .then(|| fields[3].to_lowercase())
+    })
+}
+
+/// "    SSID                   : Home 5G" from `netsh wlan show interfaces`
+/// (not the BSSID line below it).
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_netsh_ssid(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "SSID" && !value.trim().is_empty()).then(|| value.trim().to_string())
+    })
+}
+
+/// The gateway of the 0.0.0.0/0 route in `route print -4`'s active routes.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_route_print(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 5 || fields[0] != "0.0.0.0" || fields[1] != "0.0.0.0" {
+            return None;
+        }
+        fields[2].parse().ok()
+    })
+}
+
+/// "  192.168.1.1           a4-91-b1-00-01-02     dynamic" from `arp -a`.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_arp_a(output: &str, ip: Ipv4Addr) -> Option<String> {
+    let ip = ip.to_string();
+    output.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.len() >= 2 && fields[0] == ip).then(|| normalize_mac(fields[1]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProfileConfig;
+
+    fn config_with_profiles() -> Config {
+        let mut config = Config::default();
+        let mut home = ProfileConfig::default();
+        home.detect.gateway_mac = vec!["A4-91-B1-00-01-02".to_string()];
+        let mut hotel = ProfileConfig::default();
+        hotel.detect.ssid = vec!["Hotel Guest".to_string()];
+        config.profiles.insert("home".to_string(), home);
+        config.profiles.insert("hotel".to_string(), hotel);
+        config
+    }
+
+    #[test]
+    fn detects_profile_by_ssid_or_gateway_mac() {
+        let config = config_with_profiles();
+
+        let at_home = NetworkIdentity {
+            ssid: Some("Home 5G".to_string()),
+            gateway_mac: Some("a4:91:b1:00:01:02".to_string()),
+        };
+        let at_hotel = NetworkIdentity {
+            ssid: Some("Hotel Guest".to_string()),
+            gateway_mac: None,
+        };
+
+        assert_eq!(detect_profile(&config, &at_home), Some("home".to_string()));
+        assert_eq!(detect_profile(&config, &at_hotel), Some("hotel".to_string()));
+        assert_eq!(detect_profile(&config, &NetworkIdentity::default()), None);
+    }
+
+    #[test]
+    fn explicit_profile_must_exist() {
+        let config = config_with_profiles();
+
+        assert_eq!(resolve_profile(&config, Some("hotel")), Ok(Some("hotel".to_string())));
+        assert!(resolve_profile(&config, Some("office")).is_err());
+    }
+
+    #[test]
+    fn reads_gateway_and_mac_from_proc() {
+        let route = "Iface\tDestination\tGateway \tFlags\n\
+                     wlan0\t0001A8C0\t00000000\t0001\n\
+                     wlan0\t00000000\t0101A8C0\t0003\n";
+        let arp = "IP address       HW type     Flags       HW address            Mask     Device\n\
+                   192.168.1.1      0x1         0x2         a4:91:b1:00:01:02     *        wlan0\n";
+
+        let gateway = parse_proc_route(route).unwrap();
+        assert_eq!(gateway, Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(parse_proc_arp(arp, gateway), Some("a4:91:b1:00:01:02".to_string()));
+    }
+
+    #[test]
+    fn reads_network_from_windows_tools() {
+        let netsh = "    Name                   : Wi-Fi\r\n    State                  : connected\r\n    \
+                     SSID                   : Home 5G\r\n    BSSID                  : a4:91:b1:00:01:03\r\n";
+        let route = "Active Routes:\r\nNetwork Destination        Netmask          Gateway       Interface  Metric\r\n\
+                     \x20         0.0.0.0          0.0.0.0      192.168.1.1    192.168.1.20     35\r\n";
+        let arp = "Interface: 192.168.1.20 --- 0x12\r\n  Internet Address      Physical Address      Type\r\n\
+                   \x20 192.168.1.1           a4-91-b1-00-01-02     dynamic\r\n";
+
+        assert_eq!(parse_netsh_ssid(netsh), Some("Home 5G".to_string()));
+        let gateway = parse_route_print(route).unwrap();
+        assert_eq!(gateway, Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(parse_arp_a(arp, gateway), Some("a4:91:b1:00:01:02".to_string()));
+    }
+}
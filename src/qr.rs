@@ -0,0 +1,56 @@
+// QR codes drawn with half-block characters, two modules per character
+// cell, so a phone can pick up a stream URL from the terminal.
+
+use qrcode::{Color, EcLevel, QrCode};
+
+/// Light modules around the code; scanners want some, the spec asks for 4.
+const QUIET_ZONE: usize = 2;
+
+/// `text` as a QR code, one string per terminal row. A set module is
+/// "dark", to be drawn in a dark color on a light background: the caller
+/// styles the rows, as terminals differ in which of the two is default.
+pub fn rows(text: &str) -> Result<Vec<String>, String> {
+    // The lowest error correction keeps long URLs small enough to fit
+    let code = QrCode::with_error_correction_level(text, EcLevel::L)
+        .map_err(|e| format!("Can't make a QR code of this URL: {}", e))?;
+    let width = code.width();
+    let size = width + 2 * QUIET_ZONE;
+    let dark = |x: usize, y: usize| {
+        let (Some(x), Some(y)) = (x.checked_sub(QUIET_ZONE), y.checked_sub(QUIET_ZONE)) else { return false };
+        x < width && y < width && code[(x, y)] == Color::Dark
+    };
+
+    Ok((0..size)
+        .step_by(2)
+        .map(|y| {
+            (0..size)
+                .map(|x| match (dark(x, y), dark(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_pack_two_modules_per_cell_inside_a_quiet_zone() {
+        let rows = rows("http://192.168.1.5:8200/MediaItems/22.mkv").unwrap();
+        // Version 3 at level L: 29 modules, plus the quiet zone on both sides
+        let size = 29 + 2 * QUIET_ZONE;
+        assert_eq!(rows.len(), size.div_ceil(2));
+        assert!(rows.iter().all(|row| row.chars().count() == size));
+
+        assert!(rows[0].chars().all(|c| c == ' '));
+        // The top-left finder pattern: a solid row of 7 modules over a row
+        // dark only at its ends
+        let top: String = rows[1].chars().skip(QUIET_ZONE).take(7).collect();
+        assert_eq!(top, "█▀▀▀▀▀█");
+    }
+}
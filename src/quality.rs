@@ -0,0 +1,262 @@
+// Picking between the variants of a file. Servers that transcode (Serviio,
+// Universal Media Server, Plex's DLNA side) list the same content in more
+// than one `<res>`: the original, and smaller or more widely playable
+// versions of it. `[quality]` says which to play or cast: the highest
+// quality, or the highest a renderer says it plays, and optionally nothing
+// above a bitrate, so a weak TV or hotel Wi-Fi gets a version it can keep
+// up with. When the player gives up on the version picked, the others are
+// tried in the server's order, and the one that played is remembered for
+// the server and picked first after that.
+
+use crate::app::DirectoryItem;
+use crate::config::QualityConfig;
+use mop_core::Resource;
+
+/// The values `[quality] prefer` takes.
+pub const PREFERENCES: [&str; 2] = ["highest", "compatible"];
+
+/// The resource of `item` that `config` prefers for receivers playing what
+/// `sinks` list (a renderer's GetProtocolInfo Sink entries, one list per
+/// renderer; none for the local player). None when it has only the one.
+pub fn pick<'a>(config: &QualityConfig, item: &'a DirectoryItem, sinks: &[Vec<String>]) -> Option<&'a Resource> {
+    let resources = &item.metadata.as_ref()?.resources;
+    if resources.len() < 2 {
+        return None;
+    }
+    let secs = crate::music::duration_secs(item);
+    let mut candidates: Vec<&Resource> = resources.iter().collect();
+
+    if config.prefer == "compatible" && !sinks.is_empty() {
+        let playable = |resource: &&Resource| {
+            let accepts = |mime| sinks.iter().all(|sink| crate::upnp_avtransport::accepts(sink, mime));
+            resource.format.as_deref().is_some_and(accepts)
+        };
+        let compatible: Vec<&Resource> = candidates.iter().copied().filter(playable).collect();
+        if !compatible.is_empty() {
+            candidates = compatible;
+        }
+    }
+
+    if config.max_mbps > 0 {
+        // Variants of unknown bitrate get the benefit of the doubt
+        let cap = config.max_mbps * 1_000_000;
+        let fitting = |resource: &&Resource| bits_per_sec(resource, secs).is_none_or(|bits| bits <= cap);
+        let fitting: Vec<&Resource> = candidates.iter().copied().filter(fitting).collect();
+        if fitting.is_empty() {
+            return candidates.into_iter().min_by_key(|resource| bits_per_sec(resource, secs));
+        }
+        candidates = fitting;
+    }
+
+    // The server's order settles ties, its first being what it would serve
+    candidates.into_iter().rev().max_by_key(|resource| {
+        (pixels(resource), bits_per_sec(resource, secs).unwrap_or_default(), resource.size.unwrap_or_default())
+    })
+}
+
+/// `item` as the resource `pick` prefers: its URL, size, format and
+/// resolution. Files with a single resource come back as they are.
+pub fn choose(config: &QualityConfig, item: &DirectoryItem, sinks: &[Vec<String>]) -> DirectoryItem {
+    match pick(config, item, sinks) {
+        Some(resource) => with_resource(item, resource),
+        None => item.clone(),
+    }
+}
+
+/// `item` as one of its resources.
+pub fn with_resource(item: &DirectoryItem, resource: &Resource) -> DirectoryItem {
+    let mut chosen = item.clone();
+    if let Some(metadata) = chosen.metadata.as_mut() {
+        if metadata.resources.first() != Some(resource) {
+            tracing::info!(target: "mop::app", "Using the {} variant of {}", describe(resource, None), item.name);
+        }
+        chosen.url = Some(resource.url.clone());
+        metadata.size = resource.size;
+        metadata.format = resource.format.clone();
+        metadata.resolution = resource.resolution.clone();
+        metadata.dlna_flags = resource.dlna_flags.clone();
+    }
+    chosen
+}
+
+/// How a server's variants are told apart from file to file: the DLNA
+/// profile ("AVC_MP4_MP_HD_720p_AAC"), or the MIME type for servers that
+/// give none.
+pub fn variant(item: &DirectoryItem) -> Option<String> {
+    let metadata = item.metadata.as_ref()?;
+    key(metadata.format.as_deref(), metadata.dlna_flags.as_deref())
+}
+
+/// The resource of `item` that is the variant `worked`, when `item` also
+/// offers the variant that failed and what worked isn't over `max_mbps`.
+pub fn remembered<'a>(config: &QualityConfig, item: &'a DirectoryItem, worked: &Worked) -> Option<&'a Resource> {
+    let resources = &item.metadata.as_ref()?.resources;
+    let is = |resource: &Resource, variant: &str| {
+        key(resource.format.as_deref(), resource.dlna_flags.as_deref()).is_some_and(|key| key == variant)
+    };
+    if !resources.iter().any(|resource| is(resource, &worked.failed)) {
+        return None;
+    }
+    let cap = config.max_mbps * 1_000_000;
+    let secs = crate::music::duration_secs(item);
+    resources.iter().find(|resource| {
+        is(resource, &worked.variant)
+            && (cap == 0 || bits_per_sec(resource, secs).is_none_or(|bits| bits <= cap))
+    })
+}
+
+/// The first resource of `item` whose URL isn't one of `tried`.
+pub fn untried<'a>(item: &'a DirectoryItem, tried: &[String]) -> Option<&'a Resource> {
+    let resources = &item.metadata.as_ref()?.resources;
+    resources.iter().find(|resource| !tried.contains(&resource.url))
+}
+
+/// What played on a server when the variant picked for a file didn't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Worked {
+    /// The variant picked that the player failed on, as `variant` gives it.
+    pub failed: String,
+    /// The variant that played instead.
+    pub variant: String,
+    /// It only played through MOP's relay.
+    pub relayed: bool,
+}
+
+fn key(format: Option<&str>, dlna_flags: Option<&str>) -> Option<String> {
+    let profile = dlna_flags
+        .unwrap_or_default()
+        .split(';')
+        .find_map(|flag| flag.trim().strip_prefix("DLNA.ORG_PN="))
+        .filter(|profile| !profile.is_empty());
+    profile.or(format).map(str::to_string)
+}
+
+/// "video/mp4 1280x720 3.0 Mbit/s", as much of it as the server gave.
+pub fn describe(resource: &Resource, secs: Option<u64>) -> String {
+    let bitrate = bits_per_sec(resource, secs).map(|bits| format!("{:.1} Mbit/s", bits as f64 / 1_000_000.0));
+    let parts = [resource.format.clone(), resource.resolution.clone(), bitrate];
+    let parts: Vec<String> = parts.into_iter().flatten().collect();
+    if parts.is_empty() { "unnamed".to_string() } else { parts.join(" ") }
+}
+
+/// The resource's bitrate, or its size over the file's length in `secs`.
+fn bits_per_sec(resource: &Resource, secs: Option<u64>) -> Option<u64> {
+    match resource.bitrate {
+        Some(bytes) => Some(bytes * 8),
+        None => Some(resource.size? * 8 / secs.filter(|&secs| secs > 0)?),
+    }
+}
+
+fn pixels(resource: &Resource) -> u64 {
+    let resolution = resource.resolution.as_deref().unwrap_or_default();
+    let (width, height) = resolution.split_once('x').unwrap_or_default();
+    width.trim().parse::<u64>().unwrap_or_default() * height.trim().parse::<u64>().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_best_variant_that_fits_the_renderer_and_the_cap_is_played() {
+        let resource = |url: &str, format: &str, resolution: &str, bitrate: u64| Resource {
+            url: url.to_string(),
+            format: Some(format.to_string()),
+            resolution: Some(resolution.to_string()),
+            bitrate: Some(bitrate),
+            ..Resource::default()
+        };
+        let resources = vec![
+            resource("http://nas/1.mkv", "video/x-matroska", "1920x1080", 2_500_000),
+            resource("http://nas/1.mp4?hd", "video/mp4", "1280x720", 500_000),
+            resource("http://nas/1.mp4?sd", "video/mp4", "720x404", 190_000),
+        ];
+        let item = DirectoryItem {
+            id: Some("1".to_string()),
+            name: "Heat".to_string(),
+            url: Some(resources[0].url.clone()),
+            metadata: Some(mop_core::FileMetadata {
+                duration: Some("2:50:12".to_string()),
+                format: resources[0].format.clone(),
+                resolution: resources[0].resolution.clone(),
+                resources: resources.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = |prefer: &str, max_mbps| QualityConfig { prefer: prefer.to_string(), max_mbps };
+        let tv = vec!["http-get:*:video/mp4:*".to_string(), "http-get:*:audio/mpeg:*".to_string()];
+        let picked = |config: QualityConfig, sinks: &[Vec<String>]| pick(&config, &item, sinks).map(|r| r.url.as_str());
+
+        assert_eq!(picked(config("highest", 0), &[]), Some("http://nas/1.mkv"));
+        assert_eq!(picked(config("highest", 0), std::slice::from_ref(&tv)), Some("http://nas/1.mkv"));
+        assert_eq!(picked(config("compatible", 0), std::slice::from_ref(&tv)), Some("http://nas/1.mp4?hd"));
+        assert_eq!(picked(config("compatible", 0), &[]), Some("http://nas/1.mkv"));
+        assert_eq!(picked(config("highest", 5), &[]), Some("http://nas/1.mp4?hd"));
+        assert_eq!(picked(config("compatible", 3), &[tv]), Some("http://nas/1.mp4?sd"));
+        // Nothing fits: the smallest is the best there is
+        assert_eq!(picked(config("highest", 1), &[]), Some("http://nas/1.mp4?sd"));
+
+        let chosen = choose(&config("highest", 5), &item, &[]);
+        assert_eq!(chosen.url.as_deref(), Some("http://nas/1.mp4?hd"));
+        let metadata = chosen.metadata.unwrap();
+        assert_eq!((metadata.format.as_deref(), metadata.resolution.as_deref()), (Some("video/mp4"), Some("1280x720")));
+        assert_eq!(describe(&resources[1], None), "video/mp4 1280x720 4.0 Mbit/s");
+    }
+
+    #[test]
+    fn variants_that_failed_give_way_to_the_others_and_what_played_is_found_again() {
+        let resource = |url: &str, format: &str, dlna_flags: Option<&str>| Resource {
+            url: url.to_string(),
+            format: Some(format.to_string()),
+            dlna_flags: dlna_flags.map(str::to_string),
+            ..Resource::default()
+        };
+        let item = |resources: Vec<Resource>| DirectoryItem {
+            id: Some("7".to_string()),
+            name: "Ronin".to_string(),
+            url: Some(resources[0].url.clone()),
+            metadata: Some(mop_core::FileMetadata {
+                format: resources[0].format.clone(),
+                dlna_flags: resources[0].dlna_flags.clone(),
+                resources,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let ronin = item(vec![
+            resource("http://nas/7.mkv", "video/x-matroska", None),
+            resource("http://nas/7.ts", "video/mpeg", Some("DLNA.ORG_PN=MPEG_TS_HD_NA;DLNA.ORG_OP=01")),
+            resource("http://nas/7.mp4", "video/mp4", Some("DLNA.ORG_OP=01")),
+        ]);
+
+        let tried = vec!["http://nas/7.mkv".to_string()];
+        let next = untried(&ronin, &tried).unwrap();
+        assert_eq!(next.url, "http://nas/7.ts");
+        let played = with_resource(&ronin, next);
+        assert_eq!(played.url.as_deref(), Some("http://nas/7.ts"));
+        let failed = variant(&ronin).unwrap();
+        let worked = Worked { failed, variant: variant(&played).unwrap(), relayed: false };
+        assert_eq!(worked.variant, "MPEG_TS_HD_NA");
+        let all = vec![tried[0].clone(), next.url.clone(), "http://nas/7.mp4".to_string()];
+        assert!(untried(&ronin, &all).is_none());
+
+        // Another file from the same server, the same profile at its own URL
+        let mut heat = item(vec![
+            resource("http://nas/8.mkv", "video/x-matroska", None),
+            resource("http://nas/8.ts", "video/mpeg", Some("DLNA.ORG_PN=MPEG_TS_HD_NA")),
+        ]);
+        let any = QualityConfig { prefer: "highest".to_string(), max_mbps: 0 };
+        assert_eq!(remembered(&any, &heat, &worked).map(|r| r.url.as_str()), Some("http://nas/8.ts"));
+        let by_mime = Worked { variant: "video/mp4".to_string(), ..worked.clone() };
+        assert!(remembered(&any, &heat, &by_mime).is_none());
+        // Only in place of the variant that failed
+        let from_mp4 = Worked { failed: "video/mp4".to_string(), ..worked.clone() };
+        assert!(remembered(&any, &heat, &from_mp4).is_none());
+        // Nor over the limit set
+        heat.metadata.as_mut().unwrap().resources[1].bitrate = Some(1_000_000);
+        let capped = QualityConfig { max_mbps: 5, ..any };
+        assert!(remembered(&capped, &heat, &worked).is_none());
+        assert_eq!(variant(&ronin).as_deref(), Some("video/x-matroska"));
+    }
+}
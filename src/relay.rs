@@ -0,0 +1,234 @@
+// A local relay for files a DLNA server only seeks in by time. Players seek
+// by asking for a byte range, which such servers (TV tuners and recorders
+// serving MPEG-TS, mostly) refuse: they take a `TimeSeekRange.dlna.org`
+// header instead, as their protocolInfo says with DLNA.ORG_OP=10. The player
+// gets a URL on a loopback HTTP server, which turns each range it asks for
+// into the time that far into the file, going by the file's size and
+// duration. What comes back starts near the offset asked for rather than at
+// it, which MPEG-TS demuxers resync from. The relay runs inside MOP, so it
+// only lasts as long as MOP does. `[network] relay_rate_limit` caps what it
+// passes on, for links where a stream would otherwise crowd out the rest.
+// It also passes files through untouched, ranges and all, when the player
+// failed on them straight from the server: the requests then carry the
+// server's headers, its credentials and the DLNA headers some servers want.
+
+use crate::app::DirectoryItem;
+use crate::download::{RateLimit, Throttled};
+use crate::upnp::UpnpDevice;
+use std::io::{Cursor, Read};
+use std::net::Ipv4Addr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, StatusCode};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type HttpResponse = Response<Box<dyn Read + Send>>;
+
+/// A file handed out by the relay.
+#[derive(Clone)]
+struct Source {
+    device: UpnpDevice,
+    url: String,
+    mime: String,
+    /// The file's size and duration, to seek in it by time; None passes
+    /// ranges on to the server.
+    by_time: Option<(u64, Duration)>,
+}
+
+/// The files relayed so far, numbered by their place here.
+static SOURCES: Mutex<Vec<Source>> = Mutex::new(Vec::new());
+
+/// Everything the relay passes on together.
+static RATE_LIMIT: RateLimit = RateLimit::new();
+
+/// The relay's port, once it is listening.
+static PORT: OnceLock<Result<u16, String>> = OnceLock::new();
+
+/// Whether the DLNA flags of a file's protocolInfo say the server seeks in
+/// it by time but not by byte range.
+pub fn seeks_by_time_only(dlna_flags: &str) -> bool {
+    dlna_flags.split(';').filter_map(|flag| flag.trim().strip_prefix("DLNA.ORG_OP=")).any(|op| op == "10")
+}
+
+/// A relay URL for `item` from `device`, when the server only seeks in it
+/// by time and gave its size and duration to work out where to seek to.
+pub fn for_item(device: &UpnpDevice, item: &DirectoryItem) -> Result<Option<String>, String> {
+    let Some(metadata) = item.metadata.as_ref() else { return Ok(None) };
+    if !metadata.dlna_flags.as_deref().is_some_and(seeks_by_time_only) {
+        return Ok(None);
+    }
+    let size = metadata.size.filter(|&size| size > 0);
+    let secs = crate::music::duration_secs(item).filter(|&secs| secs > 0);
+    let (Some(url), Some(size), Some(secs)) = (item.url.as_deref(), size, secs) else { return Ok(None) };
+
+    let mime = metadata.format.clone().unwrap_or_else(|| "video/mpeg".to_string());
+    let by_time = Some((size, Duration::from_secs(secs)));
+    tracing::info!(target: "mop::net", "Relaying {} to seek by time", item.name);
+    add(Source { device: device.clone(), url: url.to_string(), mime, by_time }).map(Some)
+}
+
+/// A relay URL passing `item` from `device` through as it is.
+pub fn through(device: &UpnpDevice, item: &DirectoryItem) -> Result<String, String> {
+    let url = item.url.as_deref().ok_or("No URL available for this file")?;
+    let mime = item.metadata.as_ref().and_then(|metadata| metadata.format.clone());
+    let mime = mime.unwrap_or_else(|| "application/octet-stream".to_string());
+    tracing::info!(target: "mop::net", "Relaying {} as it is", item.name);
+    add(Source { device: device.clone(), url: url.to_string(), mime, by_time: None })
+}
+
+fn add(source: Source) -> Result<String, String> {
+    let port = PORT.get_or_init(start).clone()?;
+    // The file name stays at the end, for players that go by the extension
+    let name = url::Url::parse(&source.url)
+        .ok()
+        .and_then(|url| url.path_segments()?.next_back().map(str::to_string))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "stream".to_string());
+    let mut sources = SOURCES.lock().map_err(|_| "The seek relay stopped".to_string())?;
+    sources.push(source);
+    Ok(format!("http://127.0.0.1:{}/{}/{}", port, sources.len() - 1, name))
+}
+
+/// Hold what the relay passes on to `bytes_per_sec`; 0 lifts the limit.
+pub fn set_rate_limit(bytes_per_sec: u64) {
+    RATE_LIMIT.set(bytes_per_sec);
+}
+
+fn start() -> Result<u16, String> {
+    let server = tiny_http::Server::http((Ipv4Addr::LOCALHOST, 0))
+        .map_err(|e| format!("Cannot start the seek relay: {}", e))?;
+    let port = server.server_addr().to_ip().map(|address| address.port()).ok_or("The seek relay has no port")?;
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            std::thread::spawn(move || answer(request));
+        }
+    });
+    Ok(port)
+}
+
+fn answer(request: Request) {
+    let index = request.url().trim_start_matches('/').split('/').next().and_then(|index| index.parse::<usize>().ok());
+    let source = index.and_then(|index| SOURCES.lock().ok()?.get(index).cloned());
+    let range = request.headers().iter().find(|h| h.field.equiv("Range")).map(|h| h.value.as_str().to_string());
+    let response = match source {
+        None => respond(404),
+        Some(source) => match source.by_time {
+            Some((size, duration)) => relay(&source, size, duration, range.as_deref()),
+            None => pass(&source, range.as_deref()),
+        }
+        .unwrap_or_else(|e| {
+            tracing::warn!(target: "mop::net", "Seek relay failed for {}: {}", crate::auth::redact(&source.url), e);
+            respond(502)
+        }),
+    };
+    if let Err(e) = request.respond(response) {
+        tracing::debug!(target: "mop::net", "Player went away from the seek relay: {}", e);
+    }
+}
+
+/// The part of `source` the player asked for with `range`, fetched from
+/// the server from the time that far into the file.
+fn relay(source: &Source, size: u64, duration: Duration, range: Option<&str>) -> Result<HttpResponse, String> {
+    let (start, end) = match range {
+        None => (0, size - 1),
+        Some(range) => match crate::dlna_server::parse_range(range, size) {
+            Some(range) => range,
+            None => {
+                let mut response = respond(416);
+                response.add_header(header("Content-Range", &format!("bytes */{}", size)));
+                return Ok(response);
+            }
+        },
+    };
+
+    let client = client(source)?;
+    let mut request = client.get(&source.url).header("getcontentFeatures.dlna.org", "1");
+    if let Some(seek) = time_seek(start, size, duration) {
+        request = request.header("TimeSeekRange.dlna.org", seek);
+    }
+    let response = crate::auth::send_blocking(&client, request, source.device.credentials.as_ref())?;
+    if !response.status().is_success() {
+        return Err(format!("the server answered {}", response.status()));
+    }
+
+    let length = end - start + 1;
+    let mut headers = vec![header("Content-Type", &source.mime), header("Accept-Ranges", "bytes")];
+    let status = if range.is_some() {
+        headers.push(header("Content-Range", &format!("bytes {}-{}/{}", start, end, size)));
+        206
+    } else {
+        200
+    };
+    let body = Throttled::new(response.take(length), &RATE_LIMIT);
+    Ok(Response::new(StatusCode(status), headers, Box::new(body), Some(length as usize), None))
+}
+
+/// `source` as the server answers for `range`, status and all.
+fn pass(source: &Source, range: Option<&str>) -> Result<HttpResponse, String> {
+    let client = client(source)?;
+    let mut request = client.get(&source.url).header("getcontentFeatures.dlna.org", "1");
+    if let Some(range) = range {
+        request = request.header("Range", range);
+    }
+    let response = crate::auth::send_blocking(&client, request, source.device.credentials.as_ref())?;
+    let status = response.status().as_u16();
+    let mut headers = Vec::new();
+    for name in ["Content-Range", "Accept-Ranges"] {
+        if let Some(value) = response.headers().get(name).and_then(|value| value.to_str().ok()) {
+            headers.push(header(name, value));
+        }
+    }
+    let mime = response.headers().get("Content-Type").and_then(|value| value.to_str().ok());
+    headers.push(header("Content-Type", mime.unwrap_or(&source.mime)));
+    let length = response.content_length().map(|length| length as usize);
+    let body = Throttled::new(response, &RATE_LIMIT);
+    Ok(Response::new(StatusCode(status), headers, Box::new(body), length, None))
+}
+
+fn client(source: &Source) -> Result<reqwest::blocking::Client, String> {
+    source
+        .device
+        .blocking_client_builder()?
+        .connect_timeout(CONNECT_TIMEOUT)
+        // Playback takes as long as it takes
+        .timeout(None)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// The TimeSeekRange to ask for `offset` bytes into a file of `size` bytes
+/// lasting `duration`, as "npt=1234.567-"; none from the start.
+fn time_seek(offset: u64, size: u64, duration: Duration) -> Option<String> {
+    if offset == 0 || size == 0 {
+        return None;
+    }
+    let at = duration.mul_f64((offset as f64 / size as f64).min(1.0));
+    Some(format!("npt={:.3}-", at.as_secs_f64()))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn respond(status: u16) -> HttpResponse {
+    Response::new(StatusCode(status), Vec::new(), Box::new(Cursor::new(Vec::new())), Some(0), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_offsets_become_times_for_servers_that_only_seek_by_time() {
+        assert!(seeks_by_time_only("DLNA.ORG_PN=MPEG_TS_HD_NA;DLNA.ORG_OP=10;DLNA.ORG_CI=0"));
+        assert!(!seeks_by_time_only("DLNA.ORG_OP=11"));
+        assert!(!seeks_by_time_only("DLNA.ORG_OP=01"));
+        assert!(!seeks_by_time_only("DLNA.ORG_PN=AVC_MP4_MP_HD_720p_AAC"));
+
+        let hour = Duration::from_secs(3600);
+        assert_eq!(time_seek(0, 4_000_000_000, hour), None);
+        assert_eq!(time_seek(1_000_000_000, 4_000_000_000, hour).as_deref(), Some("npt=900.000-"));
+        assert_eq!(time_seek(4_000_000_000, 4_000_000_000, hour).as_deref(), Some("npt=3600.000-"));
+    }
+}
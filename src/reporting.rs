@@ -0,0 +1,75 @@
+// Playback reporting to Jellyfin and Emby. When a file from a server browsed
+// through their API plays, the server hears that it started, how far the
+// player has got every few seconds, and where it stopped, so watched status
+// and resume points carry over to its other clients. Positions come from
+// mpv over its IPC, so only playback in mpv is reported. The server takes a
+// stop without a position as watched to the end, so when mpv never said
+// where it was, the stop isn't reported either.
+
+use crate::app::DirectoryItem;
+use crate::config::Config;
+use crate::upnp::UpnpDevice;
+use mop_core::jellyfin::PlaybackReport;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How often mpv is asked where it is; the last answer is where playback
+/// is reported to have stopped.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the server hears about progress, as often as Jellyfin's own
+/// clients tell it.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A file to report the playback of.
+pub struct Report {
+    device: UpnpDevice,
+    item_id: String,
+    name: String,
+}
+
+impl Report {
+    /// A report for `item` from `server`, when the server is browsed
+    /// natively and `[mop] report_playback` is on.
+    pub fn of(config: &Config, server: Option<&UpnpDevice>, item: &DirectoryItem) -> Option<Self> {
+        let server = server.filter(|server| config.mop.report_playback && mop_core::jellyfin::handles(server))?;
+        Some(Self { device: server.clone(), item_id: item.id.clone()?, name: item.name.clone() })
+    }
+
+    /// Report the playback from a background thread, asking the mpv on
+    /// `socket` where it is until `ended` says it has exited.
+    pub fn run(self, socket: String, ended: Receiver<()>) {
+        std::thread::spawn(move || {
+            let report = match PlaybackReport::start(&self.device, &self.item_id) {
+                Ok(report) => report,
+                Err(e) => {
+                    let (name, server) = (&self.name, &self.device.name);
+                    tracing::warn!(target: "mop::net", "Couldn't report playing {} to {}: {}", name, server, e);
+                    return;
+                }
+            };
+            tracing::info!(target: "mop::net", "Reporting playback of {} to {}", self.name, self.device.name);
+
+            let mut position = None;
+            let mut reported = Instant::now();
+            while let Err(RecvTimeoutError::Timeout) = ended.recv_timeout(POLL_INTERVAL) {
+                let Ok(now) = crate::mpv::position(&socket) else { continue };
+                position = Some(now.elapsed);
+                if reported.elapsed() >= PROGRESS_INTERVAL {
+                    reported = Instant::now();
+                    if let Err(e) = report.progress(now.elapsed, now.paused) {
+                        tracing::debug!(target: "mop::net", "Progress report for {} failed: {}", self.name, e);
+                    }
+                }
+            }
+            let Some(position) = position else {
+                tracing::info!(target: "mop::net", "Not reporting {} stopped: mpv never said where it was", self.name);
+                return;
+            };
+            let at = position.as_secs();
+            match report.stop(position) {
+                Ok(()) => tracing::info!(target: "mop::net", "Reported {} stopped at {}s", self.name, at),
+                Err(e) => tracing::warn!(target: "mop::net", "Couldn't report {} stopped: {}", self.name, e),
+            }
+        });
+    }
+}
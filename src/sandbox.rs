@@ -0,0 +1,90 @@
+// Flatpak and Snap packages only reach the network through permissions the
+// package (or the user) grants. Without them discovery, browsing and
+// `mop serve` all fail with plain socket errors, so when one is missing the
+// error panel says which and how to grant it. Neither has a portal MOP could
+// ask at runtime; granting is a command the user runs once.
+
+use std::process::{Command, Stdio};
+
+/// Where Flatpak describes the running sandbox.
+const FLATPAK_INFO: &str = "/.flatpak-info";
+
+/// Snap interfaces discovery needs: outgoing connections, and binding the
+/// sockets SSDP replies and `mop serve` arrive on.
+const SNAP_PLUGS: [&str; 2] = ["network", "network-bind"];
+
+/// Why the network is out of reach, if MOP runs in a sandbox that blocks
+/// it.
+pub fn network_restriction() -> Option<String> {
+    if let Ok(info) = std::fs::read_to_string(FLATPAK_INFO) {
+        return flatpak_restriction(&info);
+    }
+    let snap = std::env::var("SNAP_NAME").ok()?;
+    let missing: Vec<&str> = SNAP_PLUGS.iter().copied().filter(|plug| !snap_connected(plug)).collect();
+    if missing.is_empty() {
+        return None;
+    }
+    let connect: Vec<String> = missing.iter().map(|plug| format!("snap connect {}:{}", snap, plug)).collect();
+    Some(format!(
+        "The snap has no {} access, so the network can't be reached. Run: {}",
+        missing.join(" or "),
+        connect.join("; ")
+    ))
+}
+
+/// The restriction in a `/.flatpak-info` file: no `network` in the
+/// `shared=` list of its `[Context]` section.
+fn flatpak_restriction(info: &str) -> Option<String> {
+    let mut section = "";
+    let mut app = "";
+    let mut shared_network = false;
+    for line in info.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = name;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        match (section, key.trim()) {
+            ("Application", "name") => app = value.trim(),
+            ("Context", "shared") => shared_network = value.split(';').any(|shared| shared.trim() == "network"),
+            _ => {}
+        }
+    }
+    (!shared_network).then(|| {
+        format!(
+            "The Flatpak sandbox has no network access. Run: flatpak override --user --share=network {}",
+            if app.is_empty() { "<app id>" } else { app }
+        )
+    })
+}
+
+fn snap_connected(plug: &str) -> bool {
+    // snapctl exits 1 for a disconnected plug; if it can't run, assume the
+    // best rather than blame the snap for some other failure
+    match Command::new("snapctl")
+        .args(["is-connected", plug])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) => status.success() || status.code() != Some(1),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flatpak_without_shared_network_is_restricted() {
+        let granted = "[Application]\nname=io.github.mop\n\n[Context]\nshared=network;ipc;\nsockets=wayland;\n";
+        assert_eq!(flatpak_restriction(granted), None);
+
+        let denied = "[Application]\nname=io.github.mop\n\n[Context]\nshared=ipc;\n";
+        assert_eq!(
+            flatpak_restriction(denied).as_deref(),
+            Some("The Flatpak sandbox has no network access. Run: flatpak override --user --share=network io.github.mop")
+        );
+    }
+}
@@ -0,0 +1,553 @@
+// `mop --record-session FILE` writes what happened in the TUI to a file that
+// can go with a bug report: every key, each change of screen, selection or
+// dialog, and a summary of what the network sent back. Characters typed into
+// a text field are recorded as `*`, since they may be a password or token.
+// Summaries keep the shape of the data (how many servers, which children
+// were folders, where a page started) and nothing that names it: no titles,
+// hosts or URLs, and errors have addresses blanked out.
+//
+// The file is JSON Lines, one `Record` per line, written as it happens so a
+// crash still leaves everything up to it. `replay` drives an `App` through a
+// recording in tests, standing in for the servers and listings the summaries
+// describe, and checks the app ends up in each recorded state.
+
+use crate::app::{App, AppState, DirectoryItem, LogPaneState};
+use crate::event::AppEvent;
+use crate::keymap::KeyBinding;
+use crate::upnp::{BrowseError, BrowseMessage, DiscoveryMessage};
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    /// The first line of every recording.
+    Start { version: String },
+    /// A key press, spelled as in `[keys]`.
+    Key { ms: u64, key: String },
+    Network { ms: u64, event: Network },
+    /// A key put a listing from the cache on screen: its children, spelled
+    /// as in `Network::Children`, and when it was fetched.
+    Cached { ms: u64, children: String, total: Option<usize>, fetched_at: i64 },
+    /// What the app looked like after the records before it.
+    State { ms: u64, state: Snapshot },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Network {
+    /// Discovery added `added` servers to the list; `done` once it finished.
+    Discovery { added: usize, done: bool },
+    /// Children of the Browse page starting at `start`, as they arrived: one
+    /// letter each, `d` for a container and `f` for a file.
+    Children { start: usize, children: String },
+    /// The end of that page. A UPnP fault keeps its code apart from the
+    /// text, so a replay takes the same turn on it.
+    Page {
+        start: usize,
+        found: usize,
+        total: Option<usize>,
+        error: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fault: Option<u32>,
+    },
+    /// Anything else a worker sent back, by kind.
+    Other { name: String },
+}
+
+/// The parts of the app's state a bug report usually turns on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// "servers" or "browser".
+    pub screen: String,
+    /// The dialog keys go to, if any.
+    pub dialog: Option<String>,
+    pub logs: bool,
+    pub servers: usize,
+    pub selected_server: Option<usize>,
+    /// How many containers deep the browser is.
+    pub depth: usize,
+    pub items: usize,
+    pub selected_item: Option<usize>,
+    pub browsing: bool,
+}
+
+impl Snapshot {
+    pub fn of(app: &App) -> Self {
+        Self {
+            screen: match app.state {
+                AppState::ServerList => "servers",
+                AppState::DirectoryBrowser => "browser",
+            }
+            .to_string(),
+            dialog: open_dialog(app).map(str::to_string),
+            logs: app.log_pane_state != LogPaneState::Hidden,
+            servers: app.servers.len(),
+            selected_server: app.selected_server,
+            depth: app.current_directory.len(),
+            items: app.directory_contents.len(),
+            selected_item: app.selected_item,
+            browsing: app.is_browsing,
+        }
+    }
+}
+
+/// Dialogs in the order `handle_key` gives them keys.
+fn open_dialog(app: &App) -> Option<&'static str> {
+    let open = [
+        (app.show_config, "config"),
+        (app.url_dialog.is_some(), "url"),
+        (app.object_id_dialog.is_some(), "object_id"),
+        (app.plex_link.is_some(), "plex_link"),
+        (app.permission.is_some(), "permission"),
+        (app.gateway.is_some(), "gateway"),
+        (app.qr.is_some(), "qr"),
+        (app.diagnostics.is_some(), "diagnostics"),
+        (app.soap_log.is_some(), "soap_log"),
+        (app.cast.is_some(), "cast"),
+        (app.show_help, "help"),
+    ];
+    open.into_iter().find(|(is_open, _)| *is_open).map(|(_, name)| name)
+}
+
+/// Recorded in place of a character typed into a text field.
+const TYPED: &str = "*";
+
+/// Whether characters go into a text field rather than to a key binding.
+fn typing(app: &App) -> bool {
+    app.show_config
+        || app.url_dialog.is_some()
+        || app.sleep_dialog.is_some()
+        || app.filter_dialog.is_some()
+        || app.palette.is_some()
+        || app.object_id_dialog.is_some()
+        || (app.log_pane_state != LogPaneState::Hidden && app.log_filter_active)
+}
+
+/// Writes a recording as the TUI runs.
+pub struct Recorder {
+    out: Option<BufWriter<File>>,
+    started: Instant,
+    last: Option<Snapshot>,
+    /// Children that arrived since the last record, with the `load_page`
+    /// call and page they belong to. Written out before anything else is,
+    /// so a streaming page costs one line rather than one per child.
+    children: Option<(u64, usize, String)>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        let mut recorder = Self {
+            out: Some(BufWriter::new(file)),
+            started: Instant::now(),
+            last: None,
+            children: None,
+        };
+        recorder.write(&Record::Start { version: env!("CARGO_PKG_VERSION").to_string() });
+        tracing::info!(target: "mop::app", "Recording this session to {}", path.display());
+        Ok(recorder)
+    }
+
+    fn ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// One line per record, flushed straight away. After a failed write the
+    /// rest of the session goes unrecorded rather than half-written.
+    fn write(&mut self, record: &Record) {
+        self.flush_children();
+        self.write_line(record);
+    }
+
+    fn flush_children(&mut self) {
+        if let Some((_, start, children)) = self.children.take() {
+            let event = Network::Children { start, children };
+            self.write_line(&Record::Network { ms: self.ms(), event });
+        }
+    }
+
+    fn write_line(&mut self, record: &Record) {
+        let Some(out) = self.out.as_mut() else { return };
+        let written = serde_json::to_string(record)
+            .map_err(|e| e.to_string())
+            .and_then(|line| writeln!(out, "{}", line).and_then(|()| out.flush()).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            tracing::warn!(target: "mop::app", "Stopped recording the session: {}", e);
+            self.out = None;
+        }
+    }
+
+    /// Hand `key` to `handle`, recording it and any cached listing it
+    /// brought up.
+    pub fn key(&mut self, app: &mut App, key: KeyEvent, handle: fn(&mut App, KeyEvent)) {
+        let binding = KeyBinding { code: key.code, modifiers: key.modifiers };
+        let spec = binding.spec();
+        // Keys `[keys]` has no name for can't be replayed; leave them out
+        if KeyBinding::parse(&spec) == Some(binding) {
+            // What's typed into a field may be a password, token or address
+            let typed = matches!(key.code, KeyCode::Char(_)) && typing(app);
+            let record = Record::Key { ms: self.ms(), key: if typed { TYPED.to_string() } else { spec } };
+            self.write(&record);
+        }
+
+        let generation = app.browse_generation();
+        handle(app, key);
+        // Only a new browse puts a cached listing up
+        if app.browse_generation() != generation
+            && let Some(fetched_at) = app.listing_cached_at
+        {
+            let record = Record::Cached {
+                ms: self.ms(),
+                children: kinds(&app.directory_contents),
+                total: app.directory_total,
+                fetched_at,
+            };
+            self.write(&record);
+        }
+    }
+
+    /// Apply `event` to `app`, recording what it amounted to.
+    pub fn update(&mut self, app: &mut App, event: AppEvent) {
+        let servers = app.servers.len();
+        let summary = match &event {
+            AppEvent::Key(_) | AppEvent::Resize | AppEvent::Tick => None,
+            AppEvent::Discovery(message) => Some(matches!(message, DiscoveryMessage::AllComplete(_))),
+            AppEvent::Browse(browse) if browse.generation == app.browse_generation() => {
+                match &browse.message {
+                    BrowseMessage::ItemFound(item) => {
+                        let same_page = matches!(&self.children, Some((generation, start, _))
+                            if *generation == browse.generation && *start == browse.start);
+                        if !same_page {
+                            self.flush_children();
+                        }
+                        let (_, _, children) =
+                            self.children.get_or_insert_with(|| (browse.generation, browse.start, String::new()));
+                        children.push_str(&kinds(std::slice::from_ref(item)));
+                        None
+                    }
+                    BrowseMessage::PageComplete { found, total, error } => {
+                        let (fault, error) = match error {
+                            Some(BrowseError::Fault(code, summary)) => (Some(*code), Some(redact(summary))),
+                            Some(error) => (None, Some(redact(&error.to_string()))),
+                            None => (None, None),
+                        };
+                        let event = Network::Page { start: browse.start, found: *found, total: *total, error, fault };
+                        let record = Record::Network { ms: self.ms(), event };
+                        self.write(&record);
+                        None
+                    }
+                }
+            }
+            AppEvent::Browse(_) => None,
+            other => {
+                let event = Network::Other { name: event_name(other).to_string() };
+                let record = Record::Network { ms: self.ms(), event };
+                self.write(&record);
+                None
+            }
+        };
+        app.update(event);
+        if let Some(done) = summary {
+            let added = app.servers.len().saturating_sub(servers);
+            if added > 0 || done {
+                let record = Record::Network { ms: self.ms(), event: Network::Discovery { added, done } };
+                self.write(&record);
+            }
+        }
+    }
+
+    /// Record the app's state if it changed since the last record of it.
+    pub fn state(&mut self, app: &App) {
+        let snapshot = Snapshot::of(app);
+        if self.last.as_ref() != Some(&snapshot) {
+            let record = Record::State { ms: self.ms(), state: snapshot.clone() };
+            self.write(&record);
+            self.last = Some(snapshot);
+        }
+    }
+}
+
+/// One letter per item: `d` for a container, `f` for a file.
+fn kinds(items: &[DirectoryItem]) -> String {
+    items.iter().map(|item| if item.is_directory { 'd' } else { 'f' }).collect()
+}
+
+fn event_name(event: &AppEvent) -> &'static str {
+    match event {
+        AppEvent::Key(_) => "key",
+        AppEvent::Resize => "resize",
+        AppEvent::Tick => "tick",
+        AppEvent::Discovery(_) => "discovery",
+        AppEvent::Browse(_) => "browse",
+        AppEvent::PlexLink(_) => "plex_link",
+        AppEvent::PlexStreams { .. } => "plex_streams",
+        AppEvent::CastTargets(_) => "cast_targets",
+        AppEvent::Playback { .. } => "playback",
+        AppEvent::CastFolder(_) => "cast_folder",
+        AppEvent::CastQueue(_) => "cast_queue",
+        AppEvent::CastControl(_) => "cast_control",
+        AppEvent::Gateway { .. } => "gateway",
+        AppEvent::ProtocolInfo { .. } => "protocol_info",
+        AppEvent::Thumbnail { .. } => "thumbnail",
+        AppEvent::Permission(_) => "permission",
+        AppEvent::Diagnostics { .. } => "diagnostics",
+        AppEvent::NetworkChanged => "network_changed",
+        AppEvent::PlayAll(_) => "play_all",
+        AppEvent::Slideshow(_) => "slideshow",
+        AppEvent::StreamChecked(_) => "stream_checked",
+        AppEvent::PlayerChecked { .. } => "player_checked",
+        AppEvent::CheckHealth => "check_health",
+        AppEvent::SleepTimer(_) => "sleep_timer",
+        AppEvent::ServerHealth { .. } => "server_health",
+        AppEvent::StreamTest { .. } => "stream_test",
+        AppEvent::ContainerUpdates { .. } => "container_updates",
+        AppEvent::PathResolved { .. } => "path_resolved",
+        AppEvent::Capabilities { .. } => "capabilities",
+        AppEvent::PlayerSupport(_) => "player_support",
+        AppEvent::Download { .. } => "download",
+        AppEvent::DownloadFolder(_) => "download_folder",
+    }
+}
+
+/// `text` with URLs and IP addresses blanked out.
+fn redact(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let bare = word.trim_matches(|c: char| matches!(c, '(' | ')' | ',' | ';' | ':' | '\'' | '"'));
+            if bare.contains("://") {
+                word.replace(bare, "<url>")
+            } else if !bare.is_empty() && (bare.parse::<IpAddr>().is_ok() || bare.parse::<SocketAddr>().is_ok()) {
+                word.replace(bare, "<address>")
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Read a recording back, one `Record` per line.
+#[cfg(test)]
+pub fn read(text: &str) -> Result<Vec<Record>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+/// Drive `app` through `records`: keys go through `handle_key` as if typed,
+/// discovery adds stand-in servers, pages arrive as stand-in children of the
+/// recorded kinds, and each recorded state must match the app's. Errors
+/// name the first record that didn't.
+#[cfg(test)]
+pub fn replay(app: &mut App, records: &[Record]) -> Result<(), String> {
+    use crate::event::Browse;
+    use crate::upnp::UpnpDevice;
+
+    let stand_in = |n: usize| UpnpDevice {
+        name: format!("Server {}", n + 1),
+        location: format!("http://server-{}.invalid/description.xml", n + 1),
+        base_url: format!("http://server-{}.invalid", n + 1),
+        ..Default::default()
+    };
+
+    let mut seeded = false;
+    let mut items = 0;
+    let mut stand_ins = |kinds: &str| -> Vec<DirectoryItem> {
+        kinds
+            .chars()
+            .map(|kind| {
+                items += 1;
+                DirectoryItem {
+                    id: Some(items.to_string()),
+                    name: format!("Item {}", items),
+                    is_directory: kind == 'd',
+                    ..Default::default()
+                }
+            })
+            .collect()
+    };
+    for (n, record) in records.iter().enumerate() {
+        match record {
+            Record::Start { .. } => {}
+            Record::Key { key, .. } => {
+                let binding = KeyBinding::parse(key).ok_or_else(|| format!("record {}: unknown key {:?}", n, key))?;
+                crate::handle_key(app, KeyEvent::new(binding.code, binding.modifiers));
+            }
+            Record::Network { event: Network::Discovery { added, done }, .. } => {
+                for _ in 0..*added {
+                    let device = stand_in(app.servers.len());
+                    app.update(AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(device))));
+                }
+                if *done {
+                    app.update(AppEvent::Discovery(DiscoveryMessage::AllComplete(Vec::new())));
+                }
+            }
+            Record::Network { event: Network::Children { start, children }, .. } => {
+                let generation = app.browse_generation();
+                for item in stand_ins(children) {
+                    let message = BrowseMessage::ItemFound(Box::new(item));
+                    app.update(AppEvent::Browse(Browse { generation, start: *start, message }));
+                }
+            }
+            Record::Cached { children, total, fetched_at, .. } => {
+                app.show_listing(stand_ins(children), *total, *fetched_at)
+            }
+            Record::Network { event: Network::Page { start, found, total, error, fault }, .. } => {
+                let error = error.clone().map(|error| match fault {
+                    Some(code) => BrowseError::Fault(*code, error),
+                    None => BrowseError::Failed(error),
+                });
+                let message = BrowseMessage::PageComplete { found: *found, total: *total, error };
+                app.update(AppEvent::Browse(Browse { generation: app.browse_generation(), start: *start, message }));
+            }
+            Record::Network { event: Network::Other { .. }, .. } => {}
+            Record::State { state, .. } => {
+                // Servers listed from the cache before anything happened
+                if !seeded {
+                    while app.servers.len() < state.servers {
+                        app.servers.push(stand_in(app.servers.len()));
+                    }
+                    seeded = true;
+                }
+                let actual = Snapshot::of(app);
+                if &actual != state {
+                    return Err(format!("record {}: expected {:?}, got {:?}", n, state, actual));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Browse;
+    use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    fn new_app() -> App {
+        App::new(Arc::new(Mutex::new(VecDeque::new())))
+    }
+
+    #[test]
+    fn network_errors_lose_their_addresses() {
+        assert_eq!(
+            redact("error sending request for url (http://192.168.1.5:8200/ctl/ContentDir): connection refused"),
+            "error sending request for url (<url>): connection refused"
+        );
+        assert_eq!(redact("No route to 10.0.0.7, giving up"), "No route to <address>, giving up");
+        assert_eq!(redact("UPnP Browse fault: 701 No such object"), "UPnP Browse fault: 701 No such object");
+    }
+
+    #[test]
+    fn what_is_typed_into_a_field_is_left_out() {
+        let path = std::env::temp_dir().join(format!("mop-session-typed-{}.jsonl", std::process::id()));
+        let mut recorder = Recorder::create(&path).unwrap();
+        let mut app = new_app();
+        let mut press = |app: &mut App, code: KeyCode| {
+            recorder.key(app, KeyEvent::new(code, KeyModifiers::NONE), crate::handle_key);
+        };
+
+        press(&mut app, KeyCode::Char('c'));
+        assert!(app.show_config);
+        for c in "hunter2".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert!(app.config_editor.value(app.config_editor.selected_field).ends_with("hunter2"));
+        press(&mut app, KeyCode::Esc);
+        drop(recorder);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let keys: Vec<String> = read(&text)
+            .unwrap()
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Key { key, .. } => Some(key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, ["c", "*", "*", "*", "*", "*", "*", "*", "esc"]);
+        assert!(!text.contains("hunter"));
+    }
+
+    #[test]
+    fn a_recorded_session_replays_to_the_same_states() {
+        let path = std::env::temp_dir().join(format!("mop-session-{}.jsonl", std::process::id()));
+        let mut recorder = Recorder::create(&path).unwrap();
+        let mut app = new_app();
+        recorder.state(&app);
+
+        let press = |recorder: &mut Recorder, app: &mut App, code: KeyCode| {
+            recorder.key(app, KeyEvent::new(code, KeyModifiers::NONE), crate::handle_key);
+            recorder.state(app);
+        };
+        let page = |recorder: &mut Recorder, app: &mut App, children: &[(&str, bool)]| {
+            let generation = app.browse_generation();
+            let browse = |message| AppEvent::Browse(Browse { generation, start: 0, message });
+            for (name, is_directory) in children {
+                let item = DirectoryItem {
+                    id: Some(name.to_lowercase()),
+                    name: name.to_string(),
+                    is_directory: *is_directory,
+                    url: Some(format!("http://192.168.1.5:8200/MediaItems/{}.mkv", name)),
+                    ..Default::default()
+                };
+                recorder.update(app, browse(BrowseMessage::ItemFound(Box::new(item))));
+            }
+            let found = children.len();
+            recorder.update(app, browse(BrowseMessage::PageComplete { found, total: Some(found), error: None }));
+            recorder.state(app);
+        };
+
+        let nas = crate::upnp::UpnpDevice {
+            name: "Living room NAS".to_string(),
+            location: "http://192.168.1.5:8200/rootDesc.xml".to_string(),
+            base_url: "http://192.168.1.5:8200".to_string(),
+            ..Default::default()
+        };
+        recorder.update(&mut app, AppEvent::Discovery(DiscoveryMessage::DeviceFound(Box::new(nas))));
+        recorder.update(&mut app, AppEvent::Discovery(DiscoveryMessage::AllComplete(Vec::new())));
+        recorder.state(&app);
+        press(&mut recorder, &mut app, KeyCode::Down);
+        press(&mut recorder, &mut app, KeyCode::Enter);
+        page(&mut recorder, &mut app, &[("Films", true), ("Series", true), ("Trailer", false)]);
+        press(&mut recorder, &mut app, KeyCode::Down);
+        press(&mut recorder, &mut app, KeyCode::Enter);
+        page(&mut recorder, &mut app, &[("Pilot", false)]);
+        press(&mut recorder, &mut app, KeyCode::Char('?'));
+        press(&mut recorder, &mut app, KeyCode::Esc);
+        press(&mut recorder, &mut app, KeyCode::Backspace);
+        drop(recorder);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(!text.contains("192.168") && !text.contains("NAS") && !text.contains("Films"));
+
+        let records = read(&text).unwrap();
+        assert!(matches!(&records[0], Record::Start { .. }));
+        let pages: Vec<&str> = records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Network { event: Network::Children { children, .. }, .. } => Some(children.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pages, ["ddf", "f"]);
+        let last_state = records.iter().rev().find(|record| matches!(record, Record::State { .. }));
+        let Some(Record::State { state, .. }) = last_state else { panic!("no state recorded") };
+        assert_eq!((state.screen.as_str(), state.depth, state.dialog.as_deref()), ("browser", 0, None));
+
+        replay(&mut new_app(), &records).unwrap();
+    }
+}
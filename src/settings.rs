@@ -0,0 +1,630 @@
+use crate::config::Config;
+use ratatui::crossterm::event::{Event, KeyCode, KeyEvent};
+use std::collections::HashMap;
+use std::str::FromStr;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigField {
+    Run,
+    AutoClose,
+    AutoOpenServer,
+    RestoreLastLocation,
+    MusicView,
+    CheckStreams,
+    ReportPlayback,
+    CollapseDuplicates,
+    Thumbnails,
+    SleepQuits,
+    ServersFile,
+    VideoPlayer,
+    AudioPlayer,
+    ImagePlayer,
+    Unplayable,
+    SlideshowInterval,
+    SlideshowShuffle,
+    TrackColumn,
+    ArtistColumn,
+    AlbumColumn,
+    SsdpEnabled,
+    SsdpTimeout,
+    DescriptionTimeout,
+    PortScanEnabled,
+    ProbeTimeout,
+    WatchNetwork,
+    HealthCheck,
+    DownloadDir,
+    DownloadRateLimit,
+    Proxy,
+    RelayRateLimit,
+    DefaultRenderer,
+    QualityPreference,
+    MaxBitrate,
+    KeymapPreset,
+    AccentColor,
+    HighlightColor,
+    SelectionColor,
+    MutedColor,
+    ErrorColor,
+}
+
+pub enum FieldKind {
+    Text,
+    Number,
+    Toggle,
+    Choice(&'static [&'static str]),
+}
+
+pub struct SettingsPage {
+    pub title: &'static str,
+    pub fields: &'static [ConfigField],
+}
+
+pub const SETTINGS_PAGES: &[SettingsPage] = &[
+    SettingsPage {
+        title: "General",
+        fields: &[
+            ConfigField::Run,
+            ConfigField::AutoClose,
+            ConfigField::AutoOpenServer,
+            ConfigField::RestoreLastLocation,
+            ConfigField::MusicView,
+            ConfigField::CheckStreams,
+            ConfigField::ReportPlayback,
+            ConfigField::CollapseDuplicates,
+            ConfigField::Thumbnails,
+            ConfigField::SleepQuits,
+            ConfigField::ServersFile,
+        ],
+    },
+    SettingsPage {
+        title: "Players",
+        fields: &[
+            ConfigField::VideoPlayer,
+            ConfigField::AudioPlayer,
+            ConfigField::ImagePlayer,
+            ConfigField::Unplayable,
+        ],
+    },
+    SettingsPage {
+        title: "Slideshow",
+        fields: &[ConfigField::SlideshowInterval, ConfigField::SlideshowShuffle],
+    },
+    SettingsPage {
+        title: "Columns",
+        fields: &[ConfigField::TrackColumn, ConfigField::ArtistColumn, ConfigField::AlbumColumn],
+    },
+    SettingsPage {
+        title: "Discovery",
+        fields: &[
+            ConfigField::SsdpEnabled,
+            ConfigField::SsdpTimeout,
+            ConfigField::DescriptionTimeout,
+            ConfigField::PortScanEnabled,
+            ConfigField::ProbeTimeout,
+            ConfigField::WatchNetwork,
+            ConfigField::HealthCheck,
+        ],
+    },
+    SettingsPage {
+        title: "Downloads",
+        fields: &[ConfigField::DownloadDir, ConfigField::DownloadRateLimit],
+    },
+    SettingsPage {
+        title: "Network",
+        fields: &[ConfigField::Proxy, ConfigField::RelayRateLimit],
+    },
+    SettingsPage {
+        title: "Casting",
+        fields: &[ConfigField::DefaultRenderer],
+    },
+    SettingsPage {
+        title: "Quality",
+        fields: &[ConfigField::QualityPreference, ConfigField::MaxBitrate],
+    },
+    SettingsPage {
+        title: "Appearance",
+        fields: &[
+            ConfigField::KeymapPreset,
+            ConfigField::AccentColor,
+            ConfigField::HighlightColor,
+            ConfigField::SelectionColor,
+            ConfigField::MutedColor,
+            ConfigField::ErrorColor,
+        ],
+    },
+];
+
+impl ConfigField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConfigField::Run => "Media Player Command",
+            ConfigField::AutoClose => "Auto close after launch",
+            ConfigField::AutoOpenServer => "Open a lone server",
+            ConfigField::RestoreLastLocation => "Reopen last folder",
+            ConfigField::MusicView => "Music view",
+            ConfigField::CheckStreams => "Check files before playing",
+            ConfigField::ReportPlayback => "Report playback",
+            ConfigField::CollapseDuplicates => "List duplicates once",
+            ConfigField::Thumbnails => "Show thumbnails",
+            ConfigField::SleepQuits => "Quit on sleep timer",
+            ConfigField::ServersFile => "Servers file",
+            ConfigField::VideoPlayer => "Video Player",
+            ConfigField::AudioPlayer => "Audio Player",
+            ConfigField::ImagePlayer => "Image Viewer",
+            ConfigField::Unplayable => "Files mpv can't play",
+            ConfigField::SlideshowInterval => "Slideshow Interval (s)",
+            ConfigField::SlideshowShuffle => "Shuffle Slideshow",
+            ConfigField::TrackColumn => "Track # Column Width",
+            ConfigField::ArtistColumn => "Artist Column Width",
+            ConfigField::AlbumColumn => "Album Column Width",
+            ConfigField::SsdpEnabled => "SSDP Discovery",
+            ConfigField::SsdpTimeout => "SSDP Timeout (s)",
+            ConfigField::DescriptionTimeout => "Description Fetch Timeout (s)",
+            ConfigField::PortScanEnabled => "Port Scan",
+            ConfigField::ProbeTimeout => "Port Probe Timeout (ms)",
+            ConfigField::WatchNetwork => "Rescan on network change",
+            ConfigField::HealthCheck => "Server check interval (s)",
+            ConfigField::DownloadDir => "Download Directory",
+            ConfigField::DownloadRateLimit => "Rate limit (KB/s)",
+            ConfigField::Proxy => "Proxy",
+            ConfigField::RelayRateLimit => "Relay rate limit (KB/s)",
+            ConfigField::DefaultRenderer => "Default Renderer",
+            ConfigField::QualityPreference => "Prefer variant",
+            ConfigField::MaxBitrate => "Max bitrate (Mbit/s)",
+            ConfigField::KeymapPreset => "Keymap preset",
+            ConfigField::AccentColor => "Accent Color",
+            ConfigField::HighlightColor => "Highlight Color",
+            ConfigField::SelectionColor => "Selection Color",
+            ConfigField::MutedColor => "Muted Color",
+            ConfigField::ErrorColor => "Error Color",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ConfigField::Run => "Command run with the media URL when Enter is pressed on a file.",
+            ConfigField::AutoClose => "Quit MOP as soon as the player has been started.",
+            ConfigField::AutoOpenServer => "Skip the server list when discovery finds exactly one media server.",
+            ConfigField::RestoreLastLocation => "Start in the server and folder open at the last quit, once it is found.",
+            ConfigField::MusicView => "List artists, albums and numbered tracks with their running times.",
+            ConfigField::CheckStreams => "Ask the server for a file first and report its answer if it won't serve it.",
+            ConfigField::ReportPlayback => "Keep Jellyfin and Emby watched status and resume points in sync.",
+            ConfigField::CollapseDuplicates => "List duplicated files once, asking which copy to play.",
+            ConfigField::Thumbnails => "Show the highlighted file's thumbnail in File Info. Needs 24-bit color.",
+            ConfigField::SleepQuits => "Quit MOP too when the sleep timer stops playback.",
+            ConfigField::ServersFile => "JSON or TOML list of servers added at startup, for when discovery is blocked.",
+            ConfigField::VideoPlayer => "Used for video/* items. Leave empty to use the media player command.",
+            ConfigField::AudioPlayer => "Used for audio/* items. Leave empty to use the media player command.",
+            ConfigField::ImagePlayer => "Used for image/* items. Leave empty to use the media player command.",
+            ConfigField::Unplayable => "List, gray out or leave out files mpv has no decoder for.",
+            ConfigField::SlideshowInterval => "Seconds per image in a slideshow, for mpv, feh and VLC.",
+            ConfigField::SlideshowShuffle => "Show a folder's images in random order.",
+            ConfigField::TrackColumn => "Characters for the track number in music listings; 0 hides it.",
+            ConfigField::ArtistColumn => "Characters for the artist in music listings; 0 hides it.",
+            ConfigField::AlbumColumn => "Characters for the album in music listings; 0 hides it.",
+            ConfigField::SsdpEnabled => "Multicast search for UPnP devices (targets: [discovery.ssdp]).",
+            ConfigField::SsdpTimeout => "How long to wait for SSDP replies per search target.",
+            ConfigField::DescriptionTimeout => "Timeout for fetching a device's description XML.",
+            ConfigField::PortScanEnabled => "Probe media server ports directly ([discovery.port_scan]).",
+            ConfigField::ProbeTimeout => "Timeout for each host:port probe during the port scan.",
+            ConfigField::WatchNetwork => "Rescan after switching networks or waking from sleep. Applies on restart.",
+            ConfigField::HealthCheck => "Seconds between checks that listed servers answer; 0 never. Applies on restart.",
+            ConfigField::DownloadDir => "Where downloads are saved. Empty uses the system download folder.",
+            ConfigField::DownloadRateLimit => "What all downloads share, to leave room for playback; 0 for no limit.",
+            ConfigField::Proxy => "Proxy URL for all HTTP traffic. Empty uses HTTP_PROXY/HTTPS_PROXY/ALL_PROXY.",
+            ConfigField::RelayRateLimit => "What the relay for time-seek-only files passes on; 0 for no limit.",
+            ConfigField::DefaultRenderer => "UDN of the renderer the send-to-TV key plays on. d in the cast picker sets it.",
+            ConfigField::QualityPreference => "Which version of a file to use: the best, or the best a TV plays.",
+            ConfigField::MaxBitrate => "Pass over versions of a file above this bitrate; 0 for no cap.",
+            ConfigField::KeymapPreset => "Base key bindings; 'vim' adds h/j/k navigation.",
+            ConfigField::AccentColor => "Titles and labels. Color name (e.g. cyan) or #rrggbb.",
+            ConfigField::HighlightColor => "Selected entries and section headers.",
+            ConfigField::SelectionColor => "Background of the selected list row.",
+            ConfigField::MutedColor => "Help bar and secondary text.",
+            ConfigField::ErrorColor => "Error messages in the log pane.",
+        }
+    }
+
+    pub fn kind(&self) -> FieldKind {
+        match self {
+            ConfigField::AutoClose
+            | ConfigField::AutoOpenServer
+            | ConfigField::RestoreLastLocation
+            | ConfigField::MusicView
+            | ConfigField::CheckStreams
+            | ConfigField::ReportPlayback
+            | ConfigField::CollapseDuplicates
+            | ConfigField::Thumbnails
+            | ConfigField::SleepQuits
+            | ConfigField::SsdpEnabled
+            | ConfigField::PortScanEnabled
+            | ConfigField::WatchNetwork
+            | ConfigField::SlideshowShuffle => FieldKind::Toggle,
+            ConfigField::SsdpTimeout
+            | ConfigField::DescriptionTimeout
+            | ConfigField::ProbeTimeout
+            | ConfigField::SlideshowInterval
+            | ConfigField::HealthCheck
+            | ConfigField::MaxBitrate
+            | ConfigField::DownloadRateLimit
+            | ConfigField::RelayRateLimit
+            | ConfigField::TrackColumn
+            | ConfigField::ArtistColumn
+            | ConfigField::AlbumColumn => FieldKind::Number,
+            ConfigField::KeymapPreset => FieldKind::Choice(&crate::keymap::PRESETS),
+            ConfigField::QualityPreference => FieldKind::Choice(&crate::quality::PREFERENCES),
+            ConfigField::Unplayable => FieldKind::Choice(&crate::playable::MODES),
+            _ => FieldKind::Text,
+        }
+    }
+
+    fn value(&self, config: &Config) -> String {
+        match self {
+            ConfigField::Run => config.mop.run.clone(),
+            ConfigField::AutoClose => config.mop.auto_close.to_string(),
+            ConfigField::AutoOpenServer => config.mop.auto_open_server.to_string(),
+            ConfigField::RestoreLastLocation => config.mop.restore_last_location.to_string(),
+            ConfigField::MusicView => config.mop.music_view.to_string(),
+            ConfigField::CheckStreams => config.mop.check_streams.to_string(),
+            ConfigField::ReportPlayback => config.mop.report_playback.to_string(),
+            ConfigField::CollapseDuplicates => config.mop.collapse_duplicates.to_string(),
+            ConfigField::Thumbnails => config.mop.thumbnails.to_string(),
+            ConfigField::SleepQuits => config.mop.sleep_quits.to_string(),
+            ConfigField::ServersFile => config.mop.servers_file.clone(),
+            ConfigField::VideoPlayer => config.players.video.clone(),
+            ConfigField::AudioPlayer => config.players.audio.clone(),
+            ConfigField::ImagePlayer => config.players.image.clone(),
+            ConfigField::Unplayable => config.players.unplayable.clone(),
+            ConfigField::SlideshowInterval => config.slideshow.interval_secs.to_string(),
+            ConfigField::SlideshowShuffle => config.slideshow.shuffle.to_string(),
+            ConfigField::TrackColumn => config.columns.track.to_string(),
+            ConfigField::ArtistColumn => config.columns.artist.to_string(),
+            ConfigField::AlbumColumn => config.columns.album.to_string(),
+            ConfigField::SsdpEnabled => config.discovery.ssdp.enabled.to_string(),
+            ConfigField::SsdpTimeout => config.discovery.ssdp_timeout_secs.to_string(),
+            ConfigField::DescriptionTimeout => config.discovery.description_timeout_secs.to_string(),
+            ConfigField::PortScanEnabled => config.discovery.port_scan.enabled.to_string(),
+            ConfigField::ProbeTimeout => config.discovery.probe_timeout_ms.to_string(),
+            ConfigField::WatchNetwork => config.mop.watch_network.to_string(),
+            ConfigField::HealthCheck => config.mop.health_check_secs.to_string(),
+            ConfigField::DownloadDir => config.downloads.dir.clone(),
+            ConfigField::DownloadRateLimit => config.downloads.rate_limit.to_string(),
+            ConfigField::Proxy => config.network.proxy.clone(),
+            ConfigField::RelayRateLimit => config.network.relay_rate_limit.to_string(),
+            ConfigField::DefaultRenderer => config.cast.default_renderer.clone(),
+            ConfigField::QualityPreference => config.quality.prefer.clone(),
+            ConfigField::MaxBitrate => config.quality.max_mbps.to_string(),
+            ConfigField::KeymapPreset => config.keys.preset.clone(),
+            ConfigField::AccentColor => config.theme.accent.clone(),
+            ConfigField::HighlightColor => config.theme.highlight.clone(),
+            ConfigField::SelectionColor => config.theme.selection.clone(),
+            ConfigField::MutedColor => config.theme.muted.clone(),
+            ConfigField::ErrorColor => config.theme.error.clone(),
+        }
+    }
+
+    fn validate(&self, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        match self {
+            ConfigField::Run => validate_command(value, false),
+            ConfigField::VideoPlayer | ConfigField::AudioPlayer | ConfigField::ImagePlayer => {
+                validate_command(value, true)
+            }
+            ConfigField::SsdpTimeout
+            | ConfigField::DescriptionTimeout
+            | ConfigField::ProbeTimeout
+            | ConfigField::SlideshowInterval => {
+                match value.parse::<u64>() {
+                    Ok(0) => Err("Must be greater than 0".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(format!("'{}' is not a whole number", value)),
+                }
+            }
+            ConfigField::HealthCheck
+            | ConfigField::MaxBitrate
+            | ConfigField::DownloadRateLimit
+            | ConfigField::RelayRateLimit => {
+                value.parse::<u64>().map(|_| ()).map_err(|_| format!("'{}' is not a whole number", value))
+            }
+            ConfigField::TrackColumn | ConfigField::ArtistColumn | ConfigField::AlbumColumn => value
+                .parse::<u16>()
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not a width in characters", value)),
+            ConfigField::DownloadDir => {
+                if !value.is_empty() && std::path::Path::new(value).is_file() {
+                    Err(format!("'{}' is a file, not a directory", value))
+                } else {
+                    Ok(())
+                }
+            }
+            ConfigField::ServersFile => match crate::import::Format::of(std::path::Path::new(value)) {
+                _ if value.is_empty() => Ok(()),
+                Some(_) => Ok(()),
+                None => Err(format!("'{}' is not a .json or .toml file", value)),
+            },
+            ConfigField::Proxy => crate::proxy::validate(value),
+            ConfigField::AccentColor
+            | ConfigField::HighlightColor
+            | ConfigField::SelectionColor
+            | ConfigField::MutedColor
+            | ConfigField::ErrorColor => ratatui::style::Color::from_str(value)
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not a color name or #rrggbb value", value)),
+            ConfigField::AutoClose
+            | ConfigField::AutoOpenServer
+            | ConfigField::RestoreLastLocation
+            | ConfigField::MusicView
+            | ConfigField::CheckStreams
+            | ConfigField::ReportPlayback
+            | ConfigField::CollapseDuplicates
+            | ConfigField::Thumbnails
+            | ConfigField::SleepQuits
+            | ConfigField::SsdpEnabled
+            | ConfigField::PortScanEnabled
+            | ConfigField::WatchNetwork
+            | ConfigField::SlideshowShuffle
+            | ConfigField::KeymapPreset
+            | ConfigField::QualityPreference
+            | ConfigField::Unplayable
+            | ConfigField::DefaultRenderer => Ok(()),
+        }
+    }
+
+    fn apply(&self, value: &str, config: &mut Config) {
+        let text = value.trim().to_string();
+        let number = || text.parse::<u64>().unwrap_or_default();
+        match self {
+            ConfigField::Run => config.mop.run = text,
+            ConfigField::AutoClose => config.mop.auto_close = text == "true",
+            ConfigField::AutoOpenServer => config.mop.auto_open_server = text == "true",
+            ConfigField::RestoreLastLocation => config.mop.restore_last_location = text == "true",
+            ConfigField::MusicView => config.mop.music_view = text == "true",
+            ConfigField::CheckStreams => config.mop.check_streams = text == "true",
+            ConfigField::ReportPlayback => config.mop.report_playback = text == "true",
+            ConfigField::CollapseDuplicates => config.mop.collapse_duplicates = text == "true",
+            ConfigField::Thumbnails => config.mop.thumbnails = text == "true",
+            ConfigField::SleepQuits => config.mop.sleep_quits = text == "true",
+            ConfigField::ServersFile => config.mop.servers_file = text,
+            ConfigField::VideoPlayer => config.players.video = text,
+            ConfigField::AudioPlayer => config.players.audio = text,
+            ConfigField::ImagePlayer => config.players.image = text,
+            ConfigField::Unplayable => config.players.unplayable = text,
+            ConfigField::SlideshowInterval => config.slideshow.interval_secs = number(),
+            ConfigField::SlideshowShuffle => config.slideshow.shuffle = text == "true",
+            ConfigField::TrackColumn => config.columns.track = number() as u16,
+            ConfigField::ArtistColumn => config.columns.artist = number() as u16,
+            ConfigField::AlbumColumn => config.columns.album = number() as u16,
+            ConfigField::SsdpEnabled => config.discovery.ssdp.enabled = text == "true",
+            ConfigField::SsdpTimeout => config.discovery.ssdp_timeout_secs = number(),
+            ConfigField::DescriptionTimeout => config.discovery.description_timeout_secs = number(),
+            ConfigField::PortScanEnabled => config.discovery.port_scan.enabled = text == "true",
+            ConfigField::ProbeTimeout => config.discovery.probe_timeout_ms = number(),
+            ConfigField::WatchNetwork => config.mop.watch_network = text == "true",
+            ConfigField::HealthCheck => config.mop.health_check_secs = number(),
+            ConfigField::DownloadDir => config.downloads.dir = text,
+            ConfigField::DownloadRateLimit => config.downloads.rate_limit = number(),
+            ConfigField::Proxy => config.network.proxy = text,
+            ConfigField::RelayRateLimit => config.network.relay_rate_limit = number(),
+            ConfigField::DefaultRenderer => config.cast.default_renderer = text,
+            ConfigField::QualityPreference => config.quality.prefer = text,
+            ConfigField::MaxBitrate => config.quality.max_mbps = number(),
+            ConfigField::KeymapPreset => config.keys.preset = text,
+            ConfigField::AccentColor => config.theme.accent = text,
+            ConfigField::HighlightColor => config.theme.highlight = text,
+            ConfigField::SelectionColor => config.theme.selection = text,
+            ConfigField::MutedColor => config.theme.muted = text,
+            ConfigField::ErrorColor => config.theme.error = text,
+        }
+    }
+}
+
+/// Check that the program part of a player command can be found.
+fn validate_command(command: &str, allow_empty: bool) -> Result<(), String> {
+    let Some(program) = command.split_whitespace().next() else {
+        return if allow_empty { Ok(()) } else { Err("Command cannot be empty".to_string()) };
+    };
+
+    if program.contains('/') {
+        return if std::path::Path::new(program).exists() {
+            Ok(())
+        } else {
+            Err(format!("'{}' does not exist", program))
+        };
+    }
+
+    let found = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false);
+    if found {
+        Ok(())
+    } else {
+        Err(format!("'{}' was not found in PATH", program))
+    }
+}
+
+pub struct ConfigEditor {
+    pub page: usize,
+    pub selected_field: ConfigField,
+    pub inputs: HashMap<ConfigField, Input>,
+    pub error: Option<(ConfigField, String)>,
+    /// Status line for actions that aren't tied to a field.
+    pub notice: Option<String>,
+}
+
+impl ConfigEditor {
+    pub fn new(config: &Config) -> Self {
+        let inputs = SETTINGS_PAGES
+            .iter()
+            .flat_map(|page| page.fields.iter())
+            .map(|field| (*field, Input::default().with_value(field.value(config))))
+            .collect();
+
+        Self {
+            page: 0,
+            selected_field: ConfigField::Run,
+            inputs,
+            error: None,
+            notice: None,
+        }
+    }
+
+    pub fn value(&self, field: ConfigField) -> &str {
+        self.inputs.get(&field).map(|input| input.value()).unwrap_or_default()
+    }
+
+    pub fn input(&self, field: ConfigField) -> Option<&Input> {
+        self.inputs.get(&field)
+    }
+
+    fn set_value(&mut self, field: ConfigField, value: String) {
+        self.inputs.insert(field, Input::default().with_value(value));
+    }
+
+    /// Write the commented sample config next to mop.toml.
+    pub fn write_sample_config(&mut self) {
+        let path = crate::config::sample_config_path();
+        self.notice = Some(match crate::config::write_sample_config(&path, true) {
+            Ok(()) => format!("Sample config written to {}", path.display()),
+            Err(e) => e,
+        });
+    }
+
+    pub fn current_page(&self) -> &'static SettingsPage {
+        &SETTINGS_PAGES[self.page]
+    }
+
+    pub fn next_field(&mut self) {
+        let fields = self.current_page().fields;
+        let index = fields.iter().position(|f| *f == self.selected_field).unwrap_or(0);
+        self.selected_field = fields[(index + 1) % fields.len()];
+    }
+
+    pub fn previous_field(&mut self) {
+        let fields = self.current_page().fields;
+        let index = fields.iter().position(|f| *f == self.selected_field).unwrap_or(0);
+        self.selected_field = fields[(index + fields.len() - 1) % fields.len()];
+    }
+
+    pub fn next_page(&mut self) {
+        self.select_page((self.page + 1) % SETTINGS_PAGES.len());
+    }
+
+    pub fn previous_page(&mut self) {
+        self.select_page((self.page + SETTINGS_PAGES.len() - 1) % SETTINGS_PAGES.len());
+    }
+
+    fn select_page(&mut self, page: usize) {
+        self.page = page;
+        self.selected_field = SETTINGS_PAGES[page].fields[0];
+    }
+
+    fn select_field(&mut self, field: ConfigField) {
+        if let Some(page) = SETTINGS_PAGES.iter().position(|p| p.fields.contains(&field)) {
+            self.page = page;
+            self.selected_field = field;
+        }
+    }
+
+    /// Flip a toggle or advance a choice field. Returns false for text fields.
+    pub fn cycle_value(&mut self) -> bool {
+        let field = self.selected_field;
+        match field.kind() {
+            FieldKind::Toggle => {
+                let value = self.value(field) != "true";
+                self.set_value(field, value.to_string());
+                true
+            }
+            FieldKind::Choice(choices) => {
+                let index = choices.iter().position(|c| *c == self.value(field)).unwrap_or(0);
+                self.set_value(field, choices[(index + 1) % choices.len()].to_string());
+                true
+            }
+            FieldKind::Text | FieldKind::Number => false,
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        self.error = None;
+        match self.selected_field.kind() {
+            FieldKind::Text | FieldKind::Number => {
+                let event = Event::Key(key);
+                if let Some(input) = self.inputs.get_mut(&self.selected_field) {
+                    input.handle_event(&event);
+                }
+                true
+            }
+            FieldKind::Toggle | FieldKind::Choice(_) => match key.code {
+                KeyCode::Char(' ') | KeyCode::Left | KeyCode::Right => self.cycle_value(),
+                _ => false,
+            },
+        }
+    }
+
+    /// Validate every field and write the values into `config`. On failure
+    /// the offending field is selected and the error kept for display.
+    pub fn apply(&mut self, config: &mut Config) -> Result<(), String> {
+        for page in SETTINGS_PAGES {
+            for field in page.fields {
+                if let Err(e) = field.validate(self.value(*field)) {
+                    self.select_field(*field);
+                    self.error = Some((*field, e.clone()));
+                    return Err(format!("{}: {}", field.label(), e));
+                }
+            }
+        }
+
+        for page in SETTINGS_PAGES {
+            for field in page.fields {
+                field.apply(self.value(*field), config);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_number_selects_offending_field() {
+        let mut config = Config::default();
+        config.mop.run = "sh".to_string();
+        let mut editor = ConfigEditor::new(&config);
+        editor.set_value(ConfigField::ProbeTimeout, "soon".to_string());
+
+        assert!(editor.apply(&mut config).is_err());
+        assert_eq!(editor.selected_field, ConfigField::ProbeTimeout);
+        assert_eq!(editor.current_page().title, "Discovery");
+        assert_eq!(config.discovery.probe_timeout_ms, 500);
+    }
+
+    #[test]
+    fn applies_values_from_all_pages() {
+        let mut config = Config::default();
+        config.mop.run = "sh".to_string();
+        let mut editor = ConfigEditor::new(&config);
+        editor.set_value(ConfigField::SsdpTimeout, "3".to_string());
+        editor.set_value(ConfigField::AccentColor, "#ff8800".to_string());
+        editor.select_field(ConfigField::KeymapPreset);
+        editor.cycle_value();
+
+        editor.apply(&mut config).unwrap();
+        assert_eq!(config.discovery.ssdp_timeout_secs, 3);
+        assert_eq!(config.theme.accent, "#ff8800");
+        assert_eq!(config.keys.preset, "vim");
+    }
+
+    #[test]
+    fn field_navigation_wraps_within_page() {
+        let mut editor = ConfigEditor::new(&Config::default());
+        editor.next_page();
+        assert_eq!(editor.selected_field, ConfigField::VideoPlayer);
+        editor.previous_field();
+        assert_eq!(editor.selected_field, ConfigField::Unplayable);
+        editor.previous_page();
+        assert_eq!(editor.selected_field, ConfigField::Run);
+    }
+}
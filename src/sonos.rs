@@ -0,0 +1,273 @@
+// Sonos zones as cast targets. Sonos players are UPnP renderers, but a zone
+// that is grouped with others only behaves when the group coordinator is
+// driven through its queue: tracks go in with AddURIToQueue and the
+// transport plays `x-rincon-queue:<coordinator>#0`. A bare
+// SetAVTransportURI on a member drops it out of its group.
+
+use crate::upnp_avtransport;
+use std::str::FromStr;
+use std::time::Duration;
+
+const ZONE_PLAYER: &str = "urn:schemas-upnp-org:device:ZonePlayer:1";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const AV_TRANSPORT_CONTROL: &str = "/MediaRenderer/AVTransport/Control";
+const ZONE_GROUP_TOPOLOGY: &str = "urn:schemas-upnp-org:service:ZoneGroupTopology:1";
+const ZONE_GROUP_TOPOLOGY_CONTROL: &str = "/ZoneGroupTopology/Control";
+
+/// A group of Sonos players, reached through its coordinator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SonosZone {
+    /// Room names of the members, coordinator first.
+    pub name: String,
+    /// Coordinator's base URL, e.g. http://192.168.1.30:1400.
+    pub base_url: String,
+    /// Coordinator's UUID, RINCON_...
+    pub uuid: String,
+}
+
+impl SonosZone {
+    fn control_url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+fn client() -> Result<reqwest::blocking::Client, String> {
+    crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)?
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Search for Sonos players for `timeout` and return their groups.
+pub fn discover(timeout: Duration) -> Result<Vec<SonosZone>, String> {
+    use futures_util::StreamExt;
+
+    let target = rupnp::ssdp::SearchTarget::from_str(ZONE_PLAYER).map_err(|e| e.to_string())?;
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let players: Vec<String> = rt.block_on(async {
+        let stream = rupnp::discover(&target, timeout, None).await.map_err(|e| e.to_string())?;
+        let mut stream = Box::pin(stream);
+        let mut players = Vec::new();
+        while let Some(device) = stream.next().await {
+            if let Ok(device) = device {
+                players.push(base_url(&device.url().to_string()));
+            }
+        }
+        Ok::<_, String>(players)
+    })?;
+
+    // Every player knows the whole household, but households can be several
+    let client = client()?;
+    let mut zones: Vec<SonosZone> = Vec::new();
+    for player in players {
+        if zones.iter().any(|zone| zone.base_url == player) {
+            continue;
+        }
+        let response = match crate::upnp::soap_call(
+            &client,
+            &format!("{}{}", player, ZONE_GROUP_TOPOLOGY_CONTROL),
+            ZONE_GROUP_TOPOLOGY,
+            "GetZoneGroupState",
+            &[],
+        ) {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!(target: "mop::net", "Sonos player {} did not list its groups: {}", player, e);
+                continue;
+            }
+        };
+        let state = unescaped(&response, "ZoneGroupState").unwrap_or_default();
+        for zone in parse_zone_groups(&state) {
+            if !zones.iter().any(|known| known.uuid == zone.uuid) {
+                tracing::info!(target: "mop::net", "Found Sonos zone {} at {}", zone.name, zone.base_url);
+                zones.push(zone);
+            }
+        }
+    }
+    Ok(zones)
+}
+
+fn base_url(location: &str) -> String {
+    match url::Url::parse(location) {
+        Ok(url) => format!("{}://{}:{}", url.scheme(), url.host_str().unwrap_or_default(), url.port().unwrap_or(1400)),
+        Err(_) => location.to_string(),
+    }
+}
+
+fn unescaped(response: &str, tag: &str) -> Option<String> {
+    let raw = crate::upnp::extract_xml_value(response, tag)?;
+    Some(quick_xml::escape::unescape(&raw).map(|text| text.into_owned()).unwrap_or(raw))
+}
+
+/// Groups from a ZoneGroupState document. Hidden members (bonded surrounds
+/// and subs) don't count as rooms.
+fn parse_zone_groups(state: &str) -> Vec<SonosZone> {
+    use quick_xml::events::Event;
+
+    struct Member {
+        uuid: String,
+        location: String,
+        name: String,
+    }
+
+    let mut reader = quick_xml::Reader::from_str(state);
+    let mut zones = Vec::new();
+    let mut coordinator = String::new();
+    let mut members: Vec<Member> = Vec::new();
+
+    let attribute = |element: &quick_xml::events::BytesStart, name: &[u8]| {
+        element
+            .attributes()
+            .flatten()
+            .find(|attribute| attribute.key.as_ref() == name)
+            .and_then(|attribute| attribute.unescape_value().ok().map(|value| value.into_owned()))
+    };
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) | Ok(Event::Empty(element)) => match element.name().as_ref() {
+                b"ZoneGroup" => {
+                    coordinator = attribute(&element, b"Coordinator").unwrap_or_default();
+                    members.clear();
+                }
+                b"ZoneGroupMember" if attribute(&element, b"Invisible").as_deref() != Some("1") => {
+                    members.push(Member {
+                        uuid: attribute(&element, b"UUID").unwrap_or_default(),
+                        location: attribute(&element, b"Location").unwrap_or_default(),
+                        name: attribute(&element, b"ZoneName").unwrap_or_default(),
+                    });
+                }
+                _ => {}
+            },
+            Ok(Event::End(element)) if element.name().as_ref() == b"ZoneGroup" => {
+                members.sort_by_key(|member| member.uuid != coordinator);
+                if let Some(leader) = members.first().filter(|member| member.uuid == coordinator) {
+                    zones.push(SonosZone {
+                        name: members.iter().map(|member| member.name.as_str()).collect::<Vec<_>>().join(" + "),
+                        base_url: base_url(&leader.location),
+                        uuid: coordinator.clone(),
+                    });
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    zones
+}
+
+/// Playback on one zone's queue.
+pub struct Session {
+    zone: SonosZone,
+    client: reqwest::blocking::Client,
+}
+
+impl Session {
+    pub fn new(zone: SonosZone) -> Result<Self, String> {
+        Ok(Self { zone, client: client()? })
+    }
+
+    pub fn zone(&self) -> &SonosZone {
+        &self.zone
+    }
+
+    fn transport(&self, action: &str, args: &[(&str, &str)]) -> Result<String, String> {
+        let mut full_args = vec![("InstanceID", "0")];
+        full_args.extend_from_slice(args);
+        crate::upnp::soap_call(
+            &self.client,
+            &self.zone.control_url(AV_TRANSPORT_CONTROL),
+            AV_TRANSPORT,
+            action,
+            &full_args,
+        )
+        .map_err(|e| format!("{}: {}", self.zone.name, e))
+    }
+
+    /// Append `url` to the queue (or insert it after the current track),
+    /// returning its track number.
+    pub fn enqueue(&self, url: &str, title: &str, as_next: bool) -> Result<u32, String> {
+        let response = self.transport(
+            "AddURIToQueue",
+            &[
+                ("EnqueuedURI", url),
+                ("EnqueuedURIMetaData", &upnp_avtransport::metadata(url, title, "http-get:*:*:*")),
+                ("DesiredFirstTrackNumberEnqueued", "0"),
+                ("EnqueueAsNext", if as_next { "1" } else { "0" }),
+            ],
+        )?;
+        crate::upnp::extract_xml_value(&response, "FirstTrackNumberEnqueued")
+            .and_then(|track| track.trim().parse().ok())
+            .ok_or_else(|| format!("{} did not say where the track went", self.zone.name))
+    }
+
+    /// Queue `url` after the current track and jump to it.
+    pub fn play_now(zone: SonosZone, url: &str, title: &str) -> Result<Self, String> {
+        let session = Self::new(zone)?;
+        let track = session.enqueue(url, title, true)?;
+        let queue = format!("x-rincon-queue:{}#0", session.zone.uuid);
+        session.transport("SetAVTransportURI", &[("CurrentURI", &queue), ("CurrentURIMetaData", "")])?;
+        session.transport("Seek", &[("Unit", "TRACK_NR"), ("Target", &track.to_string())])?;
+        session.set_paused(false)?;
+        tracing::info!(target: "mop::net", "Sonos: playing {} on {}", url, session.zone.name);
+        Ok(session)
+    }
+
+    pub fn set_paused(&self, paused: bool) -> Result<(), String> {
+        if paused {
+            self.transport("Pause", &[]).map(|_| ())
+        } else {
+            self.transport("Play", &[("Speed", "1")]).map(|_| ())
+        }
+    }
+
+    /// (duration, position) in seconds of the current track.
+    pub fn progress(&self) -> Result<(f64, f64), String> {
+        upnp_avtransport::position_info(&self.transport("GetPositionInfo", &[])?)
+            .ok_or_else(|| format!("Unexpected progress from {}", self.zone.name))
+    }
+
+    pub fn seek(&self, position: f64) -> Result<(), String> {
+        self.transport("Seek", &[("Unit", "REL_TIME"), ("Target", &upnp_avtransport::format_time(position))]).map(|_| ())
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        self.transport("Stop", &[]).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_are_driven_through_their_coordinator() {
+        let state = r#"<ZoneGroupState><ZoneGroups>
+            <ZoneGroup Coordinator="RINCON_B" ID="RINCON_B:12">
+              <ZoneGroupMember UUID="RINCON_A" Location="http://192.168.1.30:1400/xml/device_description.xml" ZoneName="Kitchen"/>
+              <ZoneGroupMember UUID="RINCON_B" Location="http://192.168.1.31:1400/xml/device_description.xml" ZoneName="Living Room">
+                <Satellite UUID="RINCON_C" Location="http://192.168.1.32:1400/xml/device_description.xml" ZoneName="Living Room" Invisible="1"/>
+              </ZoneGroupMember>
+              <ZoneGroupMember UUID="RINCON_D" Location="http://192.168.1.33:1400/xml/device_description.xml" ZoneName="Sub" Invisible="1"/>
+            </ZoneGroup>
+            <ZoneGroup Coordinator="RINCON_E" ID="RINCON_E:3">
+              <ZoneGroupMember UUID="RINCON_E" Location="http://192.168.1.34:1400/xml/device_description.xml" ZoneName="Office &amp; Den"/>
+            </ZoneGroup>
+        </ZoneGroups></ZoneGroupState>"#;
+        assert_eq!(
+            parse_zone_groups(state),
+            vec![
+                SonosZone {
+                    name: "Living Room + Kitchen".to_string(),
+                    base_url: "http://192.168.1.31:1400".to_string(),
+                    uuid: "RINCON_B".to_string(),
+                },
+                SonosZone {
+                    name: "Office & Den".to_string(),
+                    base_url: "http://192.168.1.34:1400".to_string(),
+                    uuid: "RINCON_E".to_string(),
+                },
+            ]
+        );
+    }
+}
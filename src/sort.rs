@@ -0,0 +1,138 @@
+// The orders a listing can be put in. Servers that list the property in
+// their SortCapabilities sort the whole container themselves, page by page;
+// for the rest MOP sorts what's loaded, folders first, unless it has no
+// way to tell (dates aren't kept).
+
+use crate::app::DirectoryItem;
+use mop_core::upnp::Capabilities;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sort {
+    Title,
+    Date,
+    Size,
+    Duration,
+}
+
+impl Sort {
+    pub const ALL: [Sort; 4] = [Sort::Title, Sort::Date, Sort::Size, Sort::Duration];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sort::Title => "title",
+            Sort::Date => "date",
+            Sort::Size => "size",
+            Sort::Duration => "duration",
+        }
+    }
+
+    /// The property in SortCapabilities.
+    pub fn property(self) -> &'static str {
+        match self {
+            Sort::Title => "dc:title",
+            Sort::Date => "dc:date",
+            Sort::Size => "res@size",
+            Sort::Duration => "res@duration",
+        }
+    }
+
+    /// SortCriteria for a server: titles A to Z, the rest newest, biggest
+    /// or longest first.
+    pub fn criteria(self) -> String {
+        let direction = if self == Sort::Title { '+' } else { '-' };
+        format!("{}{}", direction, self.property())
+    }
+
+    /// Whether MOP can sort by this itself.
+    pub fn local(self) -> bool {
+        self != Sort::Date
+    }
+
+    /// Whether `capabilities` lets the server sort by this.
+    pub fn by_server(self, capabilities: Option<&Capabilities>) -> bool {
+        capabilities.is_some_and(|capabilities| capabilities.can_sort(self.property()))
+    }
+
+    /// The sort after `current`, skipping those neither the server nor MOP
+    /// can do; None after the last, for the server's own order.
+    pub fn next(current: Option<Sort>, capabilities: Option<&Capabilities>) -> Option<Sort> {
+        let after = current.and_then(|sort| Sort::ALL.iter().position(|&s| s == sort)).map_or(0, |pos| pos + 1);
+        Sort::ALL[after..].iter().copied().find(|sort| sort.local() || sort.by_server(capabilities))
+    }
+
+    /// Put `items` in this order, folders first, keeping the server's order
+    /// among equals and for what this can't tell apart.
+    pub fn apply(self, items: &mut [DirectoryItem]) {
+        if !self.local() {
+            return;
+        }
+        items.sort_by(|a, b| b.is_directory.cmp(&a.is_directory).then_with(|| self.compare(a, b)));
+    }
+
+    fn compare(self, a: &DirectoryItem, b: &DirectoryItem) -> Ordering {
+        match self {
+            Sort::Title => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Sort::Date => Ordering::Equal,
+            Sort::Size => largest_first(size(a), size(b)),
+            Sort::Duration => largest_first(crate::music::duration_secs(a), crate::music::duration_secs(b)),
+        }
+    }
+}
+
+fn size(item: &DirectoryItem) -> Option<u64> {
+    item.metadata.as_ref().and_then(|metadata| metadata.size)
+}
+
+/// Descending, with what has no value last.
+fn largest_first(a: Option<u64>, b: Option<u64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => b.cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mop_core::FileMetadata;
+
+    fn item(name: &str, is_directory: bool, size: Option<u64>) -> DirectoryItem {
+        DirectoryItem {
+            name: name.to_string(),
+            is_directory,
+            metadata: size.map(|size| FileMetadata {
+                size: Some(size),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sorts_keep_folders_first_and_skip_what_nobody_can_do() {
+        let mut items = vec![
+            item("b.mkv", false, Some(10)),
+            item("Zed", true, None),
+            item("A.mkv", false, None),
+            item("c.mkv", false, Some(30)),
+            item("alpha", true, None),
+        ];
+        Sort::Title.apply(&mut items);
+        let names: Vec<_> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["alpha", "Zed", "A.mkv", "b.mkv", "c.mkv"]);
+        Sort::Size.apply(&mut items);
+        let names: Vec<_> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["alpha", "Zed", "c.mkv", "b.mkv", "A.mkv"]);
+
+        // Dates only the server can sort by
+        assert_eq!(Sort::next(Some(Sort::Title), None), Some(Sort::Size));
+        let capabilities = Capabilities { search: Vec::new(), sort: vec!["dc:title".into(), "dc:date".into()] };
+        assert_eq!(Sort::next(Some(Sort::Title), Some(&capabilities)), Some(Sort::Date));
+        assert_eq!(Sort::next(None, None), Some(Sort::Title));
+        assert_eq!(Sort::next(Some(Sort::Duration), None), None);
+        assert_eq!(Sort::Date.criteria(), "-dc:date");
+    }
+}
@@ -0,0 +1,105 @@
+// Running under Termux on Android. Android only delivers multicast to apps
+// holding a multicast lock, which Termux doesn't take, so SSDP replies
+// mostly never arrive and the port scan is what finds servers. There is no
+// X11 or Wayland clipboard and no desktop player either: copying goes
+// through termux-api's `termux-clipboard-set` and playback hands the URL to
+// an Android app.
+
+use crate::config::DiscoveryConfig;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The player command that hands URLs to Android, and the default `run` in
+/// Termux.
+pub const ANDROID_PLAYER: &str = "termux-open";
+
+/// How long SSDP listens in Termux, where it seldom hears anything.
+const SSDP_TIMEOUT_SECS: u64 = 2;
+
+pub fn is_termux() -> bool {
+    std::env::var_os("TERMUX_VERSION").is_some()
+        || std::env::var("PREFIX").is_ok_and(|prefix| prefix.contains("/com.termux/"))
+}
+
+/// Discovery settings adjusted for Android: the port scan always runs and
+/// SSDP isn't waited on for long.
+pub fn discovery_settings(mut settings: DiscoveryConfig) -> DiscoveryConfig {
+    if is_termux() {
+        settings.port_scan.enabled = true;
+        settings.ssdp_timeout_secs = settings.ssdp_timeout_secs.min(SSDP_TIMEOUT_SECS);
+    }
+    settings
+}
+
+/// Put `text` on the Android clipboard. Needs the termux-api package and
+/// its companion app.
+pub fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    let mut child = Command::new("termux-clipboard-set")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("termux-clipboard-set is not available ({}); install termux-api", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    match child.wait() {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err("termux-clipboard-set failed".to_string()),
+    }
+}
+
+/// Open `urls` in whichever Android app handles their media type. Android
+/// opens one URL per intent, so only the first is played.
+pub fn open(urls: &[&str]) -> Result<(), String> {
+    let Some(url) = urls.first() else { return Err("Nothing to play".to_string()) };
+    if urls.len() > 1 {
+        tracing::warn!(target: "mop::app", "Android plays one URL at a time; skipping {} more", urls.len() - 1);
+    }
+    let mime = content_type(url);
+
+    let opened = Command::new(ANDROID_PLAYER)
+        .args(["--view", "--content-type", mime, url])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match opened {
+        Ok(status) if status.success() => return Ok(()),
+        Ok(_) => tracing::warn!(target: "mop::app", "{} failed, trying am start", ANDROID_PLAYER),
+        Err(e) => tracing::warn!(target: "mop::app", "{} is not available ({}), trying am start", ANDROID_PLAYER, e),
+    }
+
+    let status = Command::new("am")
+        .args(["start", "-a", "android.intent.action.VIEW", "-d", url, "-t", mime])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("Failed to start am: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err("Android has no app to open this URL".to_string())
+    }
+}
+
+/// The media type an Android player is picked by; anything unknown is
+/// offered to video players.
+fn content_type(url: &str) -> &'static str {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| mop_core::mime::for_path(std::path::Path::new(url.path())))
+        .unwrap_or("video/*")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_open_by_their_media_type() {
+        assert_eq!(content_type("http://192.168.1.5:8200/media/Film.mkv?token=1"), "video/x-matroska");
+        assert_eq!(content_type("http://192.168.1.5:8200/stream/42"), "video/*");
+    }
+}
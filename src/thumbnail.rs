@@ -0,0 +1,128 @@
+// Thumbnails in the File Info panel: the video frame, poster or cover art a
+// server lists with a file, drawn with half-block characters in 24-bit
+// color, two pixels per character cell. Any terminal with true color shows
+// them; no image protocol is needed.
+
+use crate::upnp::UpnpDevice;
+use image::RgbImage;
+use image::imageops::FilterType;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use std::io::Read;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Thumbnails are meant to be small; anything bigger isn't worth the wait.
+const MAX_BYTES: u64 = 4 * 1024 * 1024;
+/// Decoded thumbnails are kept at most this many pixels wide and high,
+/// more than the panel ever shows.
+const KEPT_SIZE: u32 = 160;
+/// A few megabytes of JPEG or PNG can claim to be far bigger than that
+/// decoded; anything past these is refused before it is allocated.
+const MAX_DIMENSION: u32 = 8192;
+const MAX_DECODED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Fetch and decode the thumbnail at `url` on `device`.
+pub fn fetch(device: &UpnpDevice, url: &str) -> Result<RgbImage, String> {
+    let client = device.blocking_client_builder()?.timeout(FETCH_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let response = crate::auth::send_blocking(&client, client.get(url), device.credentials.as_ref())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("server returned {} for the thumbnail", status.as_u16()));
+    }
+
+    let mut bytes = Vec::new();
+    response
+        .take(MAX_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("thumbnail download failed: {}", e))?;
+    if bytes.len() as u64 > MAX_BYTES {
+        return Err("thumbnail is too big".to_string());
+    }
+    Ok(decode(&bytes)?.thumbnail(KEPT_SIZE, KEPT_SIZE).to_rgb8())
+}
+
+/// `bytes` as an image, within `MAX_DIMENSION` and `MAX_DECODED_BYTES`.
+fn decode(bytes: &[u8]) -> Result<image::DynamicImage, String> {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODED_BYTES);
+    let mut reader = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("can't read the thumbnail: {}", e))?;
+    reader.limits(limits);
+    reader.decode().map_err(|e| format!("can't read the thumbnail: {}", e))
+}
+
+/// `image` scaled to fit `width` cells by `height` rows, keeping its shape,
+/// one line per row. Each cell's upper half is one pixel (the foreground of
+/// '▀') and its lower half the next (the background).
+pub fn rows(image: &RgbImage, width: u16, height: u16) -> Vec<Line<'static>> {
+    let (max_width, max_height) = (u32::from(width), u32::from(height) * 2);
+    if max_width == 0 || max_height == 0 || image.width() == 0 || image.height() == 0 {
+        return Vec::new();
+    }
+    let scale = f64::min(max_width as f64 / image.width() as f64, max_height as f64 / image.height() as f64);
+    let scaled_width = ((image.width() as f64 * scale).round() as u32).clamp(1, max_width);
+    let scaled_height = ((image.height() as f64 * scale).round() as u32).clamp(1, max_height);
+    let scaled = image::imageops::resize(image, scaled_width, scaled_height, FilterType::Triangle);
+
+    let color = |x: u32, y: u32| {
+        let [r, g, b] = scaled.get_pixel(x, y).0;
+        Color::Rgb(r, g, b)
+    };
+    (0..scaled_height)
+        .step_by(2)
+        .map(|y| {
+            let cells = (0..scaled_width).map(|x| {
+                let style = Style::default().fg(color(x, y));
+                // An odd last row leaves the lower halves to the terminal
+                let style = if y + 1 < scaled_height { style.bg(color(x, y + 1)) } else { style };
+                Span::styled("▀", style)
+            });
+            Line::from(cells.collect::<Vec<_>>())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_keep_the_shape_and_put_two_pixels_in_each_cell() {
+        // 4x2 pixels: red on top, blue underneath
+        let (red, blue) = (image::Rgb([255, 0, 0]), image::Rgb([0, 0, 255]));
+        let image = RgbImage::from_fn(4, 2, |_, y| if y == 0 { red } else { blue });
+        let rows = rows(&image, 4, 10);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].spans.len(), 4);
+        let style = rows[0].spans[0].style;
+        assert_eq!((style.fg, style.bg), (Some(Color::Rgb(255, 0, 0)), Some(Color::Rgb(0, 0, 255))));
+
+        // A wide image in a tall space is limited by the width
+        let wide = RgbImage::new(160, 90);
+        let rows = super::rows(&wide, 32, 40);
+        assert_eq!((rows.len(), rows[0].spans.len()), (9, 32));
+        assert!(super::rows(&wide, 0, 10).is_empty());
+    }
+
+    #[test]
+    fn images_claiming_to_be_huge_are_refused_before_decoding() {
+        let mut png = Vec::new();
+        let small = RgbImage::new(4, 2);
+        small.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        assert_eq!(decode(&png).unwrap().width(), 4);
+
+        // The same PNG with its header saying 100000x100000
+        png[16..20].copy_from_slice(&100_000u32.to_be_bytes());
+        png[20..24].copy_from_slice(&100_000u32.to_be_bytes());
+        let crc = png[12..29].iter().fold(!0u32, |crc, &byte| {
+            (0..8).fold(crc ^ u32::from(byte), |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 })
+        });
+        png[29..33].copy_from_slice(&(!crc).to_be_bytes());
+        let error = decode(&png).unwrap_err();
+        assert!(error.contains("limit"), "{}", error);
+    }
+}
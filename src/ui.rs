@@ -2,53 +2,162 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    widgets::{Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Tabs},
     Frame,
 };
 
-use crate::app::{App, AppState, LogPaneState};
+use crate::app::{App, AppState, DirectoryItem, LogPaneState, ProtocolInfo};
+use mop_core::diagnostics::Outcome;
+use mop_core::metrics::{Operation, Summary};
+use crate::config::ThemeConfig;
+use crate::health::Status;
+use crate::keymap::{Action, Keymap};
 use crate::logger::{LogCategory, LogSeverity, LogEntry};
+use crate::settings::{FieldKind, SETTINGS_PAGES};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+struct KeyHints {
+    navigate: String,
+    select_server: String,
+    open: String,
+    back: String,
+    help: String,
+    quit: String,
+    errors: String,
+    config: String,
+    logs: String,
+    open_url: String,
+    plex_link: String,
+    plex_streams: String,
+    cast: String,
+    send_to_tv: String,
+    soap_log: String,
+    diagnostics: String,
+    pin: String,
+    play_all: String,
+    go_to_id: String,
+    play_then: String,
+    copy_info: String,
+    qr_code: String,
+    open_web: String,
+    slideshow: String,
+    music_view: String,
+    test_stream: String,
+    toggle_hidden: String,
+    sort: String,
+    jump: String,
+    sleep_timer: String,
+    download: String,
+    downloads: String,
+    filter: String,
+    palette: String,
+    root: String,
+}
 
-struct KeyMappings {
-    navigate: &'static str,
-    select_server: &'static str,
-    open: &'static str,
-    back: &'static str,
-    help: &'static str,
-    quit: &'static str,
-}
-
-const KEYS: KeyMappings = KeyMappings {
-    navigate: "↑↓: navigate",
-    select_server: "enter: select server",
-    open: "enter: play/open",
-    back: "backspace: back",
-    help: "?: help",
-    quit: "q: quit",
-};
+impl KeyHints {
+    fn new(keymap: &Keymap) -> Self {
+        let hint = |action: Action, description: &str| format!("{}: {}", keymap.label(action), description);
+        let up = keymap.label(Action::Up);
+        let down = keymap.label(Action::Down);
+        let navigate = if up == "↑" && down == "↓" {
+            "↑↓: navigate".to_string()
+        } else {
+            format!("{} {}: navigate", up, down)
+        };
+
+        Self {
+            navigate,
+            select_server: hint(Action::Select, "select server"),
+            open: hint(Action::Select, "play/open"),
+            back: hint(Action::Back, "back"),
+            root: hint(Action::Root, "go to the server's root folder"),
+            help: hint(Action::Help, "help"),
+            quit: hint(Action::Quit, "quit"),
+            errors: hint(Action::CopyErrors, "dump errors"),
+            config: hint(Action::Config, "config"),
+            logs: hint(Action::ToggleLogs, "logs"),
+            open_url: hint(Action::OpenUrl, "play a URL"),
+            plex_link: hint(Action::PlexLink, "link Plex account"),
+            plex_streams: hint(Action::PlexStreams, "direct play or a Plex transcode"),
+            cast: hint(Action::Cast, "cast"),
+            send_to_tv: hint(Action::SendToTv, "send to default renderer"),
+            soap_log: hint(Action::SoapLog, "inspect SOAP requests"),
+            diagnostics: hint(Action::Diagnostics, "check network health"),
+            pin: hint(Action::Pin, "pin/unpin server"),
+            play_all: hint(Action::PlayAll, "play everything in a folder"),
+            go_to_id: hint(Action::GoToId, "go to a container by ObjectID"),
+            copy_info: hint(Action::CopyInfo, "copy the item's details"),
+            qr_code: hint(Action::QrCode, "show the URL as a QR code"),
+            open_web: hint(Action::OpenWeb, "open the server's web interface"),
+            slideshow: hint(Action::Slideshow, "slideshow of the folder's images"),
+            music_view: hint(Action::MusicView, "switch the music view"),
+            test_stream: hint(Action::TestStream, "test if the file streams fast enough"),
+            toggle_hidden: hint(Action::ToggleHidden, "show/hide entries matching [hide]"),
+            sort: hint(Action::Sort, "sort by title, date, size, duration, or as the server lists"),
+            jump: hint(Action::Jump, "jump by first letter; type letters, Esc when done"),
+            sleep_timer: hint(Action::SleepTimer, "stop playback after a number of minutes"),
+            download: hint(Action::Download, "download the file, or everything in the folder"),
+            downloads: hint(Action::Downloads, "show downloads"),
+            filter: hint(Action::Filter, "filter the listing (↑↓ in it: earlier filters)"),
+            palette: hint(Action::Palette, "run any of these by name"),
+            play_then: format!(
+                "{} | {}",
+                hint(Action::PlayAndQuit, "play and quit"),
+                hint(Action::PlayAndStay, "play and stay")
+            ),
+        }
+    }
+}
+
+/// Colors resolved from the `[theme]` config section. Invalid entries fall
+/// back to the built-in defaults (they are reported when the config loads).
+pub struct Theme {
+    pub accent: Color,
+    pub highlight: Color,
+    pub selection: Color,
+    pub muted: Color,
+    pub error: Color,
+}
 
-const ERROR_KEY: &str = "e: dump errors";
-const CONFIG_KEY: &str = "c: config";
-const LOG_KEY: &str = "l: logs";
+impl Theme {
+    pub fn from_config(config: &ThemeConfig) -> Self {
+        let defaults = ThemeConfig::default();
+        let color = |value: &str, default: &str| {
+            Color::from_str(value)
+                .or_else(|_| Color::from_str(default))
+                .unwrap_or(Color::Reset)
+        };
 
+        Self {
+            accent: color(&config.accent, &defaults.accent),
+            highlight: color(&config.highlight, &defaults.highlight),
+            selection: color(&config.selection, &defaults.selection),
+            muted: color(&config.muted, &defaults.muted),
+            error: color(&config.error, &defaults.error),
+        }
+    }
+}
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     // Check if we have errors to show
     let has_errors = has_displayable_errors(app);
+    let theme = Theme::from_config(&app.config.theme);
+    let keys = KeyHints::new(&app.keymap);
 
     // Get help text based on current state
     let help_text = match app.state {
         AppState::ServerList => {
             if has_errors {
                 format!("{} | {} | {} | {} | {} | {} | {}",
-                    KEYS.navigate, KEYS.select_server, ERROR_KEY, LOG_KEY, CONFIG_KEY, KEYS.help, KEYS.quit)
+                    keys.navigate, keys.select_server, keys.errors, keys.logs, keys.config, keys.help, keys.quit)
             } else {
                 format!("{} | {} | {} | {} | {} | {}",
-                    KEYS.navigate, KEYS.select_server, LOG_KEY, CONFIG_KEY, KEYS.help, KEYS.quit)
+                    keys.navigate, keys.select_server, keys.logs, keys.config, keys.help, keys.quit)
             }
         },
-        AppState::DirectoryBrowser => format!("{} | {} | {} | {} | {} | {} | {}",
-            KEYS.navigate, KEYS.open, KEYS.back, LOG_KEY, CONFIG_KEY, KEYS.help, KEYS.quit),
+        AppState::DirectoryBrowser => format!("{} | {} | {} | {} | {} | {} | {} | {}",
+            keys.navigate, keys.open, keys.cast, keys.back, keys.logs, keys.config, keys.help, keys.quit),
     };
 
     // Determine if log pane is visible
@@ -68,7 +177,7 @@ pub fn draw(f: &mut Frame, app: &mut App) {
 
         // Title
         let title = Paragraph::new("MOP - Debug Logs (Fullscreen)")
-            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+            .style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD))
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(title, title_area);
 
@@ -76,7 +185,7 @@ pub fn draw(f: &mut Frame, app: &mut App) {
 
         let log_help = "l: cycle view | Esc: close | j/k: scroll | t/b: top/bottom | /: filter | s: save";
         let help_paragraph = Paragraph::new(log_help)
-            .style(Style::default().fg(Color::Gray));
+            .style(Style::default().fg(theme.muted));
         f.render_widget(help_paragraph, help_area);
     } else {
         let constraints = if log_visible {
@@ -109,7 +218,7 @@ pub fn draw(f: &mut Frame, app: &mut App) {
 
         // Title
         let title = Paragraph::new(title_text(app))
-            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+            .style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD))
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(title, title_area);
 
@@ -123,10 +232,10 @@ pub fn draw(f: &mut Frame, app: &mut App) {
                 ])
                 .split(content_area)[..] else { return };
 
-            draw_main_content(f, app, main_area);
+            draw_main_content(f, app, &theme, main_area);
             draw_error_panel(f, app, error_area);
         } else {
-            draw_main_content(f, app, content_area);
+            draw_main_content(f, app, &theme, content_area);
         }
 
         // Log pane
@@ -141,29 +250,107 @@ pub fn draw(f: &mut Frame, app: &mut App) {
             help_text
         };
         let help_paragraph = Paragraph::new(final_help)
-            .style(Style::default().fg(Color::Gray));
+            .style(Style::default().fg(theme.muted));
         f.render_widget(help_paragraph, help_area);
     }
 
     // Draw help modal if shown
     if app.show_help {
-        draw_help_modal(f);
+        draw_help_modal(f, &keys, &theme);
     }
 
     // Draw config modal if shown
     if app.show_config {
         draw_config_modal(f, app);
     }
+
+    if let Some(dialog) = &app.url_dialog {
+        let hint = "Paste a stream or file URL to open it with the configured player";
+        let error = dialog.error.as_deref();
+        draw_input_dialog(f, &dialog.input, error, "Play URL", "Enter: play | Esc: cancel", hint, &theme);
+    }
+
+    if let Some(dialog) = &app.sleep_dialog {
+        let hint = "Minutes until mpv and any cast are stopped; 0 turns the timer off";
+        let error = dialog.error.as_deref();
+        draw_input_dialog(f, &dialog.input, error, "Sleep timer", "Enter: set | Esc: cancel", hint, &theme);
+    }
+
+    if let Some(dialog) = &app.filter_dialog {
+        let hint = "Shows only entries with this in their title; ↑↓ recall filters used on this server before";
+        draw_input_dialog(f, &dialog.input, None, "Filter", "Enter: keep | Esc: cancel", hint, &theme);
+    }
+
+    if app.palette.is_some() {
+        draw_palette(f, app, &theme);
+    }
+
+    if app.letter_jump {
+        draw_letter_jump(f, app, &theme);
+    }
+
+    if let Some(dialog) = &app.object_id_dialog {
+        let hint = "A ContentDirectory ObjectID, as seen in the logs or a DIDL-Lite dump";
+        let error = dialog.error.as_deref();
+        draw_input_dialog(f, &dialog.input, error, "Go to ObjectID", "Enter: open | Esc: cancel", hint, &theme);
+    }
+
+    if app.plex_link.is_some() {
+        draw_plex_link_dialog(f, app, &theme);
+    }
+
+    if app.cast.is_some() {
+        draw_cast_dialog(f, app, &theme);
+    }
+
+    if app.variants.is_some() {
+        draw_variants_dialog(f, app, &theme);
+    }
+
+    if app.plex_streams.is_some() {
+        draw_plex_streams_dialog(f, app, &theme);
+    }
+
+    if app.show_downloads {
+        draw_downloads_dialog(f, app, &theme);
+    }
+
+    if app.gateway.is_some() {
+        draw_gateway_dialog(f, app, &theme);
+    }
+
+    if app.qr.is_some() {
+        draw_qr_dialog(f, app, &theme);
+    }
+
+    if app.soap_log.is_some() {
+        draw_soap_log_dialog(f, app, &theme);
+    }
+
+    if app.diagnostics.is_some() {
+        draw_diagnostics_dialog(f, app, &theme);
+    }
+
+    if app.permission.is_some() {
+        draw_permission_dialog(f, app, &theme);
+    }
 }
 
 fn title_text(app: &App) -> String {
-    match app.state {
+    let title = match app.state {
         AppState::DirectoryBrowser => app
             .selected_server
             .and_then(|server_idx| app.servers.get(server_idx))
             .map(|server| clean_server_name(&server.name).to_string())
             .unwrap_or_else(|| "MOP - UPnP Device Explorer".to_string()),
-        AppState::ServerList => "MOP - UPnP Device Explorer".to_string(),
+        AppState::ServerList => match &app.profile {
+            Some(profile) => format!("MOP - UPnP Device Explorer [{}]", profile),
+            None => "MOP - UPnP Device Explorer".to_string(),
+        },
+    };
+    match app.sleep_minutes_left() {
+        Some(minutes) => format!("{} · sleep in {} min", title, minutes),
+        None => title,
     }
 }
 
@@ -175,13 +362,35 @@ fn padded_title_text(title: impl Into<String>) -> String {
     format!(" {} ", title.into())
 }
 
-fn draw_file_info_panel(f: &mut Frame, app: &App, area: Rect) {
+fn draw_file_info_panel(f: &mut Frame, app: &mut App, area: Rect) {
+    // At most half the panel, so the details stay in view
+    let height = (area.height.saturating_sub(2) / 2).min(10);
+    let thumbnail = match app.config.mop.thumbnails {
+        true => app.thumbnail_rows(area.width.saturating_sub(2), height),
+        false => Vec::new(),
+    };
+    let app = &*app;
     let mut info_lines = Vec::new();
     
-    if let Some(item_idx) = app.selected_item {
+    if app.parent_selected {
+        let up_to = match app.current_directory.split_last() {
+            Some((_, [])) => "/".to_string(),
+            Some((_, parents)) => format!("/{}", parents.join("/")),
+            None => "the server list".to_string(),
+        };
+        info_lines.push(Line::from(vec![
+            Span::styled("Up to: ", Style::default().fg(Color::Cyan)),
+            Span::raw(up_to),
+        ]));
+    } else if let Some(item_idx) = app.selected_item {
         if item_idx < app.directory_contents.len() {
             let item = &app.directory_contents[item_idx];
-            
+
+            if !thumbnail.is_empty() {
+                info_lines.extend(thumbnail);
+                info_lines.push(Line::from(""));
+            }
+
             info_lines.push(Line::from(vec![
                 Span::styled("Name: ", Style::default().fg(Color::Cyan)),
                 Span::raw(&item.name),
@@ -191,6 +400,26 @@ fn draw_file_info_panel(f: &mut Frame, app: &App, area: Rect) {
                 Span::styled("Type: ", Style::default().fg(Color::Cyan)),
                 Span::raw(if item.is_directory { "Directory" } else { "File" }),
             ]));
+
+            if let Some(reason) = app.refuses(item) {
+                info_lines.push(Line::from(vec![
+                    Span::styled("Unplayable: ", Style::default().fg(Color::Red)),
+                    Span::raw(reason),
+                ]));
+            }
+
+            if let Some(music) = &item.music {
+                let tags = [("Artist: ", music.artist.clone()), ("Album: ", music.album.clone())];
+                let track = ("Track: ", music.track.map(|track| track.to_string()));
+                for (label, value) in tags.into_iter().chain([track]) {
+                    if let Some(value) = value {
+                        info_lines.push(Line::from(vec![
+                            Span::styled(label, Style::default().fg(Color::Cyan)),
+                            Span::raw(value),
+                        ]));
+                    }
+                }
+            }
             
             if let Some(url) = &item.url {
                 info_lines.push(Line::from(""));
@@ -233,6 +462,26 @@ fn draw_file_info_panel(f: &mut Frame, app: &App, area: Rect) {
                         Span::raw(format),
                     ]));
                 }
+
+                if let Some(resolution) = &metadata.resolution {
+                    info_lines.push(Line::from(vec![
+                        Span::raw("  Resolution: "),
+                        Span::raw(resolution),
+                    ]));
+                }
+
+                if !metadata.resources.is_empty() {
+                    info_lines.push(Line::from(""));
+                    info_lines.push(Line::from(vec![
+                        Span::styled("Variants:", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                    ]));
+                    let secs = crate::music::duration_secs(item);
+                    let picked = crate::quality::pick(&app.config.quality, item, &[]);
+                    for resource in &metadata.resources {
+                        let marker = if picked == Some(resource) { "▶ " } else { "· " };
+                        info_lines.push(Line::from(format!("{}{}", marker, crate::quality::describe(resource, secs))));
+                    }
+                }
             }
         }
     } else {
@@ -247,6 +496,17 @@ fn draw_file_info_panel(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(info, area);
 }
 
+/// A server's health as a colored dot; hollow until it has been checked.
+fn health_dot(status: Option<Status>, theme: &Theme) -> Span<'static> {
+    let (dot, color) = match status {
+        Some(Status::Online) => ("● ", Color::Green),
+        Some(Status::Slow) => ("● ", Color::Yellow),
+        Some(Status::Offline) => ("● ", theme.error),
+        None => ("○ ", theme.muted),
+    };
+    Span::styled(dot, Style::default().fg(color))
+}
+
 fn draw_server_info_panel(f: &mut Frame, app: &App, area: Rect) {
     let mut info_lines = Vec::new();
     
@@ -265,6 +525,21 @@ fn draw_server_info_panel(f: &mut Frame, app: &App, area: Rect) {
                     Span::raw(device_client),
                 ]));
             }
+
+            if let Some(health) = app.health.get(&server.location) {
+                let mut status = match (health.status, health.latency) {
+                    (Some(status), Some(latency)) => format!("{} ({} ms)", status.label(), latency.as_millis()),
+                    (Some(status), None) => status.label().to_string(),
+                    (None, _) => "not checked yet".to_string(),
+                };
+                if let Some(seen) = health.last_seen {
+                    status.push_str(&format!(", last seen {}", crate::health::ago(seen, SystemTime::now())));
+                }
+                info_lines.push(Line::from(vec![
+                    Span::styled("Status: ", Style::default().fg(Color::Cyan)),
+                    Span::raw(status),
+                ]));
+            }
             
             info_lines.push(Line::from(""));
             
@@ -293,6 +568,43 @@ fn draw_server_info_panel(f: &mut Frame, app: &App, area: Rect) {
                 ]));
             }
             
+            if crate::plex::is_plex(server) {
+                info_lines.push(Line::from(""));
+                let status = if server.token.is_some() {
+                    "linked".to_string()
+                } else {
+                    format!("not linked ({})", app.keymap.label(Action::PlexLink))
+                };
+                info_lines.push(Line::from(vec![
+                    Span::styled("Plex account: ", Style::default().fg(Color::Cyan)),
+                    Span::raw(status),
+                ]));
+            }
+
+            if let Some(info) = app.protocol_info.get(&server.location) {
+                info_lines.push(Line::from(""));
+                let formats = match info {
+                    ProtocolInfo::Loading => "asking the server...".to_string(),
+                    ProtocolInfo::Failed(e) => e.clone(),
+                    ProtocolInfo::Loaded(entries) => match crate::upnp::protocol_mime_types(entries) {
+                        mime_types if mime_types.is_empty() => "none reported".to_string(),
+                        mime_types => mime_types.join(", "),
+                    },
+                };
+                info_lines.push(Line::from(vec![
+                    Span::styled("Source formats: ", Style::default().fg(Color::Cyan)),
+                    Span::raw(formats),
+                ]));
+            }
+
+            if crate::igd::is_gateway(server) {
+                info_lines.push(Line::from(""));
+                info_lines.push(Line::from(vec![
+                    Span::styled("Router: ", Style::default().fg(Color::Cyan)),
+                    Span::raw(format!("{} shows external IP and port mappings", app.keymap.label(Action::Select))),
+                ]));
+            }
+
             if let Some(content_url) = &server.content_directory_url {
                 info_lines.push(Line::from(""));
                 info_lines.push(Line::from(vec![
@@ -365,10 +677,14 @@ fn displayable_errors(app: &App) -> Vec<&str> {
         }
     }
 
+    for warning in &app.config_warnings {
+        errors.push(warning.as_str());
+    }
+
     errors
 }
 
-fn draw_main_content(f: &mut Frame, app: &App, area: Rect) {
+fn draw_main_content(f: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
     match app.state {
         AppState::ServerList => {
             // Split area into server list and server info panel
@@ -386,17 +702,32 @@ fn draw_main_content(f: &mut Frame, app: &App, area: Rect) {
                 .enumerate()
                 .map(|(i, server)| {
                     let style = if Some(i) == app.selected_server {
-                        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                        Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)
                     } else {
                         Style::default()
                     };
                     
                     // Extract clean device name (remove bracketed info)
                     let clean_name = clean_server_name(&server.name);
-                    
-                    ListItem::new(Line::from(vec![
-                        Span::styled(clean_name, style),
-                    ]))
+
+                    let health = app.health.get(&server.location);
+                    let status = health.and_then(|health| health.status);
+                    let mut spans = vec![health_dot(status, theme)];
+                    if app.is_pinned(server) {
+                        spans.push(Span::raw("📌 "));
+                    }
+                    spans.push(Span::styled(clean_name, style));
+                    // Known from an earlier run, not seen on the network yet
+                    if app.is_cached_only(server) {
+                        spans.push(Span::styled(" (cached)", Style::default().fg(theme.muted)));
+                    }
+                    if status != Some(Status::Online)
+                        && let Some(seen) = health.and_then(|health| health.last_seen)
+                    {
+                        let seen = format!(" · seen {}", crate::health::ago(seen, SystemTime::now()));
+                        spans.push(Span::styled(seen, Style::default().fg(theme.muted)));
+                    }
+                    ListItem::new(Line::from(spans))
                 })
                 .collect();
 
@@ -410,7 +741,7 @@ fn draw_main_content(f: &mut Frame, app: &App, area: Rect) {
                 .block(Block::default()
                     .title(padded_title(title))
                     .borders(Borders::ALL))
-                .highlight_style(Style::default().bg(Color::DarkGray));
+                .highlight_style(Style::default().bg(theme.selection));
 
             let mut list_state = ListState::default();
             list_state.select(app.selected_server);
@@ -436,37 +767,90 @@ fn draw_main_content(f: &mut Frame, app: &App, area: Rect) {
                 ])
                 .split(area)[..] else { return };
 
-            let items: Vec<ListItem> = app
-                .directory_contents
-                .iter()
-                .enumerate()
-                .map(|(i, item)| {
-                    let style = if Some(i) == app.selected_item {
-                        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
-                    } else {
-                        Style::default()
-                    };
-                    
-                    let icon = if item.is_directory { "📁" } else { "📄" };
-                    
-                    ListItem::new(Line::from(vec![
-                        Span::raw(icon),
-                        Span::raw(" "),
-                        Span::styled(&item.name, style),
-                    ]))
-                })
-                .collect();
-
-            let list = List::new(items)
-                .block(Block::default()
-                    .title(padded_title(format!("Directory: {}", current_path)))
-                    .borders(Borders::ALL))
-                .highlight_style(Style::default().bg(Color::DarkGray));
-
-            let mut list_state = ListState::default();
-            list_state.select(app.selected_item);
-            
-            f.render_stateful_widget(list, list_area, &mut list_state);
+            // Only the rows on screen are built; containers can hold thousands.
+            // Column headings take up one of them, and ".." is the row above the first entry.
+            let columns = crate::music::has_columns(&app.directory_contents);
+            let rows = list_area.height.saturating_sub(if columns { 3 } else { 2 }) as usize;
+            app.directory_scroll = scroll_into_view(app.directory_scroll, app.selected_row(), rows);
+            let first_row = app.directory_scroll.min(app.directory_contents.len());
+            let first = first_row.saturating_sub(1);
+            let last = (first_row + rows).saturating_sub(1).min(app.directory_contents.len());
+
+            let hidden = app.hidden_count();
+            let mut count = match app.directory_total {
+                Some(total) if app.has_more_pages() => format!(" ({} of {})", app.loaded(), total),
+                _ => String::new(),
+            };
+            if hidden > 0 {
+                count.push_str(&format!(" · {} hidden", hidden));
+            }
+            if let Some(filter) = app.filter() {
+                count.push_str(&format!(" · {} matching \"{}\"", app.directory_contents.len(), filter));
+            }
+            if let Some(sort) = app.sorted_by() {
+                count.push_str(&format!(" · by {}", sort.name()));
+            }
+            if app.config.mop.music_view
+                && let Some(summary) = crate::music::summary(&app.directory_contents)
+            {
+                count.push_str(&format!(" · {}", summary));
+            }
+            if let Some(time) = app.listing_cached_at.and_then(|at| chrono::DateTime::from_timestamp(at, 0)) {
+                count.push_str(&format!(" (cached {})", time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")));
+            }
+            let block = Block::default()
+                .title(padded_title(format!(
+                    "{} Directory: {}{}",
+                    if app.is_browsing { "[•]" } else { "[ ]" },
+                    current_path,
+                    count
+                )))
+                .borders(Borders::ALL);
+
+            if columns {
+                draw_track_table(f, app, first_row, first..last, block, list_area, theme);
+            } else {
+                let parent = (first_row == 0).then(|| parent_row(app, theme));
+                let entries = app.directory_contents[first..last]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| (first + i, item))
+                    .map(|(i, item)| {
+                        let style = if Some(i + 1) == app.selected_row() {
+                            Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)
+                        } else if app.refuses(item).is_some() {
+                            Style::default().fg(theme.muted)
+                        } else {
+                            Style::default()
+                        };
+
+                        if app.config.mop.music_view {
+                            let (name, detail) = crate::music::row(item);
+                            let mut spans =
+                                vec![Span::raw(crate::music::icon(item)), Span::raw(" "), Span::styled(name, style)];
+                            if let Some(detail) = detail {
+                                spans.push(Span::styled(format!("  {}", detail), Style::default().fg(theme.muted)));
+                            }
+                            spans.extend(copies_span(app, item, theme));
+                            spans.extend(changed_span(app, item, theme));
+                            return ListItem::new(Line::from(spans));
+                        }
+
+                        let icon = if item.is_directory { "📁" } else { "📄" };
+
+                        let mut spans = vec![Span::raw(icon), Span::raw(" "), Span::styled(item.name.as_str(), style)];
+                        spans.extend(copies_span(app, item, theme));
+                        spans.extend(changed_span(app, item, theme));
+                        ListItem::new(Line::from(spans))
+                    });
+                let items: Vec<ListItem> = parent.map(ListItem::new).into_iter().chain(entries).collect();
+                let list = List::new(items).block(block).highlight_style(Style::default().bg(theme.selection));
+
+                let mut list_state = ListState::default();
+                list_state.select(app.selected_row().and_then(|selected| selected.checked_sub(first_row)));
+
+                f.render_stateful_widget(list, list_area, &mut list_state);
+            }
             
             // Draw file info panel
             draw_file_info_panel(f, app, info_area);
@@ -474,6 +858,107 @@ fn draw_main_content(f: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// The ".." entry heading a listing.
+fn parent_row(app: &App, theme: &Theme) -> Line<'static> {
+    let style = if app.parent_selected {
+        Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    };
+    Line::from(vec![Span::raw("📁"), Span::raw(" "), Span::styled("..", style)])
+}
+
+/// The `visible` part of a listing of music tracks, as a table with the
+/// columns `[columns]` asks for, from row `first_row` (".." being row 0).
+fn draw_track_table(
+    f: &mut Frame,
+    app: &App,
+    first_row: usize,
+    visible: std::ops::Range<usize>,
+    block: Block,
+    area: Rect,
+    theme: &Theme,
+) {
+    use crate::music::Column;
+
+    let columns = crate::music::columns(&app.config.columns, app.config.mop.music_view);
+    let numeric = |column: &Column| matches!(column, Column::Track | Column::Length);
+    let aligned = |column: &Column, text: String| {
+        Line::from(text).alignment(if numeric(column) { Alignment::Right } else { Alignment::Left })
+    };
+    let header = Row::new(columns.iter().map(|(column, _)| Cell::from(aligned(column, column.heading().to_string()))))
+        .style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD));
+    let first = visible.start;
+    let parent = (first_row == 0).then(|| {
+        let mut cells = vec![Cell::from(""); columns.len()];
+        if let Some(title) = columns.iter().position(|(column, _)| matches!(column, Column::Title)) {
+            cells[title] = Cell::from(parent_row(app, theme));
+        }
+        Row::new(cells)
+    });
+    let rows = app.directory_contents[visible].iter().enumerate().map(|(i, item)| {
+        let style = if Some(first + i + 1) == app.selected_row() {
+            Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)
+        } else if app.refuses(item).is_some() {
+            Style::default().fg(theme.muted)
+        } else {
+            Style::default()
+        };
+        Row::new(columns.iter().map(|(column, _)| match column {
+            Column::Title => {
+                let icon = if app.config.mop.music_view {
+                    crate::music::icon(item)
+                } else if item.is_directory {
+                    "📁"
+                } else {
+                    "📄"
+                };
+                let mut spans = vec![Span::raw(icon), Span::raw(" "), Span::styled(item.name.as_str(), style)];
+                spans.extend(copies_span(app, item, theme));
+                Cell::from(Line::from(spans))
+            }
+            Column::Track | Column::Length => Cell::from(aligned(column, column.cell(item))),
+            Column::Artist | Column::Album => {
+                Cell::from(Span::styled(column.cell(item), Style::default().fg(theme.muted)))
+            }
+        }))
+    });
+    let rows = parent.into_iter().chain(rows);
+    let widths = columns.iter().map(|(_, width)| width.map_or(Constraint::Fill(1), Constraint::Length));
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .row_highlight_style(Style::default().bg(theme.selection));
+    let mut state = TableState::default();
+    state.select(app.selected_row().and_then(|selected| selected.checked_sub(first_row)));
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// "×2" after a file the listing has twice.
+fn copies_span(app: &App, item: &DirectoryItem, theme: &Theme) -> Option<Span<'static>> {
+    let copies = app.copies(item);
+    (copies > 1).then(|| Span::styled(format!("  ×{}", copies), Style::default().fg(theme.muted)))
+}
+
+/// A dot after folders the server says have changed since they were opened.
+fn changed_span(app: &App, item: &DirectoryItem, theme: &Theme) -> Option<Span<'static>> {
+    app.container_changed(item).then(|| Span::styled("  •", Style::default().fg(theme.accent)))
+}
+
+/// First visible row of a list `rows` high, moved no more than needed to
+/// keep `selected` on screen.
+fn scroll_into_view(offset: usize, selected: Option<usize>, rows: usize) -> usize {
+    let Some(selected) = selected else { return 0 };
+    if selected < offset {
+        selected
+    } else if rows > 0 && selected >= offset + rows {
+        selected + 1 - rows
+    } else {
+        offset
+    }
+}
+
 fn clean_server_name(name: &str) -> &str {
     if let Some(bracket_pos) = name.find(" [") {
         &name[..bracket_pos]
@@ -482,12 +967,12 @@ fn clean_server_name(name: &str) -> &str {
     }
 }
 
-fn draw_help_modal(f: &mut Frame) {
+fn draw_help_modal(f: &mut Frame, keys: &KeyHints, theme: &Theme) {
     let area = f.area();
     
     // Calculate centered modal size - make it bigger for more keys
-    let modal_width = 65;
-    let modal_height = 28;
+    let modal_width = 65.min(area.width);
+    let modal_height = 54.min(area.height);
     let x = (area.width.saturating_sub(modal_width)) / 2;
     let y = (area.height.saturating_sub(modal_height)) / 2;
 
@@ -504,7 +989,7 @@ fn draw_help_modal(f: &mut Frame) {
     let help_text = vec![
         Line::from(""),
         Line::from(vec![
-            Span::styled("MOP - UPnP Device Explorer", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled("MOP - UPnP Device Explorer", Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
         ]),
         Line::from(""),
         Line::from("Vibecoded for Omarchy: discover UPnP devices and"),
@@ -512,24 +997,50 @@ fn draw_help_modal(f: &mut Frame) {
         Line::from("files to play them with mpv."),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Navigation:", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled("Navigation:", Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)),
         ]),
-        Line::from(KEYS.navigate),
-        Line::from(KEYS.select_server),
-        Line::from(KEYS.open),
-        Line::from(KEYS.back),
+        Line::from(keys.navigate.as_str()),
+        Line::from(keys.select_server.as_str()),
+        Line::from(keys.open.as_str()),
+        Line::from(keys.play_then.as_str()),
+        Line::from(keys.back.as_str()),
+        Line::from(keys.root.as_str()),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Actions:", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled("Actions:", Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)),
         ]),
-        Line::from(CONFIG_KEY),
-        Line::from(ERROR_KEY),
-        Line::from(LOG_KEY),
-        Line::from(KEYS.help),
-        Line::from(KEYS.quit),
+        Line::from(keys.config.as_str()),
+        Line::from(keys.open_url.as_str()),
+        Line::from(keys.plex_link.as_str()),
+        Line::from(keys.plex_streams.as_str()),
+        Line::from(keys.cast.as_str()),
+        Line::from(keys.send_to_tv.as_str()),
+        Line::from(keys.soap_log.as_str()),
+        Line::from(keys.diagnostics.as_str()),
+        Line::from(keys.pin.as_str()),
+        Line::from(keys.play_all.as_str()),
+        Line::from(keys.go_to_id.as_str()),
+        Line::from(keys.copy_info.as_str()),
+        Line::from(keys.qr_code.as_str()),
+        Line::from(keys.open_web.as_str()),
+        Line::from(keys.slideshow.as_str()),
+        Line::from(keys.music_view.as_str()),
+        Line::from(keys.test_stream.as_str()),
+        Line::from(keys.toggle_hidden.as_str()),
+        Line::from(keys.sort.as_str()),
+        Line::from(keys.jump.as_str()),
+        Line::from(keys.sleep_timer.as_str()),
+        Line::from(keys.download.as_str()),
+        Line::from(keys.downloads.as_str()),
+        Line::from(keys.filter.as_str()),
+        Line::from(keys.palette.as_str()),
+        Line::from(keys.errors.as_str()),
+        Line::from(keys.logs.as_str()),
+        Line::from(keys.help.as_str()),
+        Line::from(keys.quit.as_str()),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Log Pane (when visible):", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled("Log Pane (when visible):", Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)),
         ]),
         Line::from("j/k: scroll down/up"),
         Line::from("t/b: jump to top/bottom"),
@@ -577,6 +1088,36 @@ fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
     lines
 }
 
+/// What the File Info panel shows of `item`, as plain text for pasting
+/// into a chat or an issue. Secrets in the URL are masked.
+pub fn item_details(item: &DirectoryItem) -> String {
+    let mut lines = vec![format!("Title: {}", item.name)];
+    if item.is_directory {
+        lines.push("Type: Folder".to_string());
+    }
+    if let Some(id) = &item.id {
+        lines.push(format!("ObjectID: {}", id));
+    }
+    if let Some(metadata) = &item.metadata {
+        if let Some(size) = metadata.size {
+            lines.push(format!("Size: {} ({} bytes)", format_size(size), size));
+        }
+        if let Some(duration) = &metadata.duration {
+            lines.push(format!("Duration: {}", duration));
+        }
+        if let Some(format) = &metadata.format {
+            lines.push(format!("Format: {}", format));
+        }
+        if let Some(resolution) = &metadata.resolution {
+            lines.push(format!("Resolution: {}", resolution));
+        }
+    }
+    if let Some(url) = &item.url {
+        lines.push(format!("URL: {}", crate::cache::mask_secrets(url)));
+    }
+    lines.join("\n")
+}
+
 fn format_size(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
     let mut size = bytes as f64;
@@ -592,97 +1133,979 @@ fn format_size(bytes: u64) -> String {
 
 fn draw_config_modal(f: &mut Frame, app: &App) {
     let area = f.area();
-    
-    // Calculate centered modal size - simpler and smaller
-    let modal_width = 70;
-    let modal_height = 12;
+    let theme = Theme::from_config(&app.config.theme);
+    let editor = &app.config_editor;
+
+    // Calculate centered modal size
+    let modal_width = 78.min(area.width);
+    let modal_height = 21.min(area.height);
     let x = (area.width.saturating_sub(modal_width)) / 2;
     let y = (area.height.saturating_sub(modal_height)) / 2;
-    
+
     let modal_area = Rect {
         x,
         y,
         width: modal_width,
         height: modal_height,
     };
-    
+
     // Clear just the modal area for clean overlay
     f.render_widget(Clear, modal_area);
     let block = Block::default()
         .title(padded_title("Configuration"))
         .title_alignment(Alignment::Center)
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Cyan))
+        .border_style(Style::default().fg(theme.accent))
         .style(Style::default().bg(Color::Black));
-    
+
     // Get inner area
     let inner_area = block.inner(modal_area);
     f.render_widget(block, modal_area);
-    
-    // Split into content and help
-    let [content_area, help_area] = Layout::default()
+
+    let [tabs_area, fields_area, description_area, error_area, help_area] = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(6),  // Content
-            Constraint::Min(1),     // Help
+            Constraint::Length(2),  // Page tabs
+            Constraint::Min(1),     // Fields
+            Constraint::Length(2),  // Description of the selected field
+            Constraint::Length(1),  // Validation error
+            Constraint::Length(3),  // Help
         ])
         .split(inner_area)[..] else { return };
 
-    // Simple vertical layout for fields
-    let [input_line, checkbox_line, _] = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3),  // Input with border
-            Constraint::Length(1),  // Checkbox line
-            Constraint::Length(2),  // Spacing
-        ])
-        .split(content_area)[..] else { return };
-    
-    // Media player command input
-    let run_border_style = if app.config_editor.selected_field == crate::app::ConfigField::Run {
-        Style::default().fg(Color::Yellow)
-    } else {
-        Style::default()
-    };
-    
-    let run_input = Paragraph::new(app.config_editor.run_input.value())
-        .block(Block::default()
-            .title(padded_title("Media Player Command"))
-            .borders(Borders::ALL)
-            .border_style(run_border_style));
-    f.render_widget(run_input, input_line);
-    
-    // Simple checkbox line - DOS/MC style
-    let checkbox_symbol = if app.config_editor.auto_close { "[x]" } else { "[ ]" };
-    let checkbox_style = if app.config_editor.selected_field == crate::app::ConfigField::AutoClose {
-        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
-    } else {
-        Style::default()
-    };
-    
-    let checkbox_text = format!("{} Auto close after launch", checkbox_symbol);
-    let checkbox_para = Paragraph::new(checkbox_text)
-        .style(checkbox_style);
-    f.render_widget(checkbox_para, checkbox_line);
-    
+    let titles: Vec<&str> = SETTINGS_PAGES.iter().map(|page| page.title).collect();
+    let tabs = Tabs::new(titles)
+        .select(editor.page)
+        .style(Style::default().fg(theme.muted))
+        .highlight_style(Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD));
+    f.render_widget(tabs, tabs_area);
+
+    // One row per field: "> Label ............ value"
+    const LABEL_WIDTH: usize = 32;
+    let value_width = (fields_area.width as usize).saturating_sub(LABEL_WIDTH + 2);
+    let mut cursor_position = None;
+    let lines: Vec<Line> = editor
+        .current_page()
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(row, field)| {
+            let selected = *field == editor.selected_field;
+            let label_style = if selected {
+                Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let value = editor.value(*field);
+            let value_span = match field.kind() {
+                FieldKind::Toggle => Span::raw(if value == "true" { "[x]" } else { "[ ]" }),
+                FieldKind::Choice(_) => Span::raw(format!("< {} >", value)),
+                FieldKind::Text | FieldKind::Number => {
+                    let input = editor.input(*field);
+                    let scroll = input.map(|i| i.visual_scroll(value_width)).unwrap_or(0);
+                    if selected {
+                        let cursor = input.map(|i| i.visual_cursor()).unwrap_or(0);
+                        cursor_position = Some((
+                            fields_area.x + (LABEL_WIDTH + 2 + cursor.saturating_sub(scroll)) as u16,
+                            fields_area.y + row as u16,
+                        ));
+                    }
+                    if value.is_empty() && !selected {
+                        Span::styled("(default)", Style::default().fg(theme.muted))
+                    } else {
+                        Span::styled(
+                            value.chars().skip(scroll).take(value_width).collect::<String>(),
+                            Style::default().add_modifier(Modifier::UNDERLINED),
+                        )
+                    }
+                }
+            };
+
+            Line::from(vec![
+                Span::styled(if selected { "> " } else { "  " }, label_style),
+                Span::styled(format!("{:<width$}", field.label(), width = LABEL_WIDTH), label_style),
+                value_span,
+            ])
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), fields_area);
+
+    let description = Paragraph::new(editor.selected_field.description())
+        .style(Style::default().fg(theme.muted))
+        .wrap(ratatui::widgets::Wrap { trim: true });
+    f.render_widget(description, description_area);
+
+    if let Some((field, error)) = &editor.error {
+        let error = Paragraph::new(format!("{}: {}", field.label(), error))
+            .style(Style::default().fg(theme.error));
+        f.render_widget(error, error_area);
+    } else if let Some(notice) = &editor.notice {
+        let notice = Paragraph::new(notice.as_str()).style(Style::default().fg(theme.accent));
+        f.render_widget(notice, error_area);
+    }
+
     // Simple help text
-    let help_text = "Tab/Shift+Tab: Navigate | Space: Toggle | Enter: Save | Esc: Cancel";
+    let help_text = "Tab/↑↓: Field | PgUp/PgDn: Page | Space/←→: Change | Enter: Save | Esc: Cancel\n\
+                     F2: Write commented sample config";
     let help_para = Paragraph::new(help_text)
-        .style(Style::default().fg(Color::Gray))
+        .style(Style::default().fg(theme.muted))
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::TOP));
     f.render_widget(help_para, help_area);
-    
+
     // Position cursor
-    if app.config_editor.selected_field == crate::app::ConfigField::Run {
-        f.set_cursor_position((
-            input_line.x + app.config_editor.run_input.cursor() as u16 + 1,
-            input_line.y + 1,
-        ));
+    if let Some(position) = cursor_position {
+        f.set_cursor_position(position);
+    }
+}
+
+fn draw_input_dialog(
+    f: &mut Frame,
+    input: &tui_input::Input,
+    error: Option<&str>,
+    title: &str,
+    keys: &str,
+    hint: &str,
+    theme: &Theme,
+) {
+    let area = f.area();
+
+    let modal_width = 70.min(area.width);
+    let modal_height = 6.min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    f.render_widget(Clear, modal_area);
+    let block = Block::default()
+        .title(padded_title(title))
+        .title_bottom(padded_title(keys))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.accent))
+        .style(Style::default().bg(Color::Black));
+    let inner_area = block.inner(modal_area);
+    f.render_widget(block, modal_area);
+
+    let [input_area, _, message_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Length(2)])
+        .split(inner_area)[..] else { return };
+
+    let width = input_area.width as usize;
+    let scroll = input.visual_scroll(width);
+    let visible: String = input.value().chars().skip(scroll).take(width).collect();
+    f.render_widget(
+        Paragraph::new(visible).style(Style::default().add_modifier(Modifier::UNDERLINED)),
+        input_area,
+    );
+    f.set_cursor_position((
+        input_area.x + input.visual_cursor().saturating_sub(scroll) as u16,
+        input_area.y,
+    ));
+
+    let message = match error {
+        Some(error) => Paragraph::new(error).style(Style::default().fg(theme.error)),
+        None => Paragraph::new(hint).style(Style::default().fg(theme.muted)),
+    };
+    f.render_widget(message.wrap(ratatui::widgets::Wrap { trim: true }), message_area);
+}
+
+/// A to Z along the bottom of the screen, the letters something here starts
+/// with lit and the selected entry's underlined.
+fn draw_letter_jump(f: &mut Frame, app: &App, theme: &Theme) {
+    let area = f.area();
+    let initials = app.initials();
+    let selected = app.selected_entry().map(|item| crate::app::initial(&item.name));
+    let letters: Vec<Span> = crate::app::JUMP_LETTERS
+        .chars()
+        .map(|letter| {
+            let style = if initials.contains(&letter) {
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.muted)
+            };
+            let style = if selected == Some(letter) { style.add_modifier(Modifier::UNDERLINED) } else { style };
+            Span::styled(format!(" {}", letter), style)
+        })
+        .collect();
+
+    let modal_width = 58.min(area.width);
+    let modal_height = 3.min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: area.height.saturating_sub(modal_height + 2),
+        width: modal_width,
+        height: modal_height,
+    };
+    f.render_widget(Clear, modal_area);
+    let block = Block::default()
+        .title(padded_title("Jump to"))
+        .title_bottom(padded_title("letter: jump | Esc: done"))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.accent))
+        .style(Style::default().bg(Color::Black));
+    f.render_widget(Paragraph::new(Line::from(letters)).block(block), modal_area);
+}
+
+fn draw_plex_link_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.plex_link else { return };
+    let area = f.area();
+
+    let modal_width = 60.min(area.width);
+    let modal_height = 9.min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let mut lines = vec![Line::from(clean_server_name(&dialog.server_name).to_string()), Line::from("")];
+    match (&dialog.error, &dialog.code) {
+        (Some(error), _) => lines.push(Line::from(Span::styled(error.as_str(), Style::default().fg(theme.error)))),
+        (None, Some(code)) => {
+            lines.push(Line::from(format!("Open {} and enter", crate::plex::LINK_URL)));
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                code.as_str(),
+                Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD),
+            )));
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled("Waiting for plex.tv...", Style::default().fg(theme.muted))));
+        }
+        (None, None) => lines.push(Line::from(Span::styled("Requesting a code...", Style::default().fg(theme.muted)))),
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .wrap(ratatui::widgets::Wrap { trim: true })
+        .block(
+            Block::default()
+                .title(padded_title("Link Plex Account"))
+                .title_bottom(padded_title("Esc: cancel"))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .style(Style::default().bg(Color::Black)),
+        );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_cast_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.cast else { return };
+    let area = f.area();
+
+    let modal_width = 68.min(area.width);
+    let modal_height = (dialog.targets.len() as u16 + 7).clamp(9, 16).min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let mut lines = vec![Line::from(dialog.title()), Line::from("")];
+    let footer = match &dialog.session {
+        Some(session) => {
+            let state = if dialog.paused { "Paused on" } else { "Playing on" };
+            lines.push(Line::from(vec![
+                Span::raw(format!("{} ", state)),
+                Span::styled(session.target_name(), Style::default().fg(theme.highlight).add_modifier(Modifier::BOLD)),
+            ]));
+            if let Some((position, count, title)) = dialog.now_playing() {
+                lines.push(Line::from(format!("{} of {}: {}", position, count, title)));
+            }
+            if let Some((duration, position)) = dialog.progress {
+                lines.push(Line::from(Span::styled(
+                    format!("{} / {}", format_seconds(position), format_seconds(duration)),
+                    Style::default().fg(theme.muted),
+                )));
+            }
+            if let crate::cast::Session::Group(group) = &**session {
+                lines.push(Line::from(""));
+                for member in &group.members {
+                    let status = match &member.session {
+                        Ok(_) if dialog.paused => Span::styled("paused", Style::default().fg(theme.muted)),
+                        Ok(_) => Span::styled("playing", Style::default().fg(theme.muted)),
+                        Err(e) => Span::styled(e.as_str(), Style::default().fg(theme.error)),
+                    };
+                    lines.push(Line::from(vec![Span::raw(format!("{}  ", member.name)), status]));
+                }
+            }
+            "space: pause/resume | ←→: seek | Esc: stop"
+        }
+        None if dialog.is_searching() => {
+            lines.push(Line::from(Span::styled("Looking for receivers...", Style::default().fg(theme.muted))));
+            "Esc: cancel"
+        }
+        None if dialog.starting => {
+            let target = match dialog.marked.len() {
+                0 => dialog.targets.get(dialog.selected).map(|target| target.name().to_string()).unwrap_or_default(),
+                1 => dialog.targets.get(dialog.marked[0]).map(|target| target.name().to_string()).unwrap_or_default(),
+                marked => format!("{} receivers", marked),
+            };
+            lines.push(Line::from(Span::styled(format!("Starting on {}...", target), Style::default().fg(theme.muted))));
+            "Esc: cancel"
+        }
+        None => {
+            for (i, target) in dialog.targets.iter().enumerate() {
+                let style = if i == dialog.selected {
+                    Style::default().bg(theme.selection).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                let mark = if dialog.marked.contains(&i) { "✓ " } else { "" };
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{}{}", mark, target.name()), style),
+                    Span::styled(format!("  {}", target.kind()), Style::default().fg(theme.muted)),
+                ]));
+            }
+            match dialog.targets.get(dialog.selected) {
+                _ if !dialog.marked.is_empty() => "Enter: cast to the marked | space: mark | Esc: cancel",
+                Some(target) if target.has_queue() => {
+                    "Enter: play | a: queue | space: group | d: default | Esc: cancel"
+                }
+                Some(target) if target.udn().is_some() => "Enter: cast | space: group | d: make default | Esc: cancel",
+                _ => "Enter: cast | space: group | Esc: cancel",
+            }
+        }
+    };
+    if let Some(notice) = &dialog.notice {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(notice.as_str(), Style::default().fg(theme.muted))));
+    }
+    if let Some(error) = &dialog.error {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(error.as_str(), Style::default().fg(theme.error))));
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .wrap(ratatui::widgets::Wrap { trim: true })
+        .block(
+            Block::default()
+                .title(padded_title("Cast"))
+                .title_bottom(padded_title(footer))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .style(Style::default().bg(Color::Black)),
+        );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_palette(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(palette) = &app.palette else { return };
+    let commands = palette.commands();
+    let area = f.area();
+
+    let modal_width = 60.min(area.width);
+    let modal_height = (commands.len() as u16 + 4).clamp(6, 20).min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    f.render_widget(Clear, modal_area);
+    let block = Block::default()
+        .title(padded_title("Commands"))
+        .title_bottom(padded_title("Enter: run | Esc: cancel"))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.accent))
+        .style(Style::default().bg(Color::Black));
+    let inner_area = block.inner(modal_area);
+    f.render_widget(block, modal_area);
+
+    let [input_area, _, list_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Min(0)])
+        .split(inner_area)[..] else { return };
+
+    let input = &palette.input;
+    let width = input_area.width as usize;
+    let scroll = input.visual_scroll(width);
+    let visible: String = input.value().chars().skip(scroll).take(width).collect();
+    f.render_widget(
+        Paragraph::new(visible).style(Style::default().add_modifier(Modifier::UNDERLINED)),
+        input_area,
+    );
+    f.set_cursor_position((
+        input_area.x + input.visual_cursor().saturating_sub(scroll) as u16,
+        input_area.y,
+    ));
+
+    if commands.is_empty() {
+        let none = Paragraph::new("No command has that in its name").style(Style::default().fg(theme.muted));
+        f.render_widget(none, list_area);
+        return;
+    }
+    // Keep the highlighted one in view
+    let rows = list_area.height as usize;
+    let first = palette.selected.saturating_sub(rows.saturating_sub(1));
+    let lines: Vec<Line> = commands
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .map(|(i, action)| {
+            let style = if i == palette.selected {
+                Style::default().bg(theme.selection).fg(Color::White)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(action.name().replace('_', " "), style),
+                Span::styled(format!("  {}", app.keymap.label(*action)), Style::default().fg(theme.muted)),
+            ])
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), list_area);
+}
+
+fn draw_variants_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.variants else { return };
+    let area = f.area();
+
+    let modal_width = 80.min(area.width);
+    let modal_height = (dialog.copies.len() as u16 + 6).clamp(8, 16).min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let mut lines = vec![
+        Line::from(dialog.name.as_str()).alignment(Alignment::Center),
+        Line::from(Span::styled("The listing has this file more than once", Style::default().fg(theme.muted)))
+            .alignment(Alignment::Center),
+        Line::from(""),
+    ];
+    for (i, copy) in dialog.copies.iter().enumerate() {
+        let style = if i == dialog.selected {
+            Style::default().bg(theme.selection).fg(Color::White)
+        } else {
+            Style::default()
+        };
+        let url = copy.url.as_deref().map(crate::cache::mask_secrets).unwrap_or_default();
+        let mut spans = vec![Span::styled(url, style)];
+        if let Some(id) = &copy.id {
+            spans.push(Span::styled(format!("  {}", id), Style::default().fg(theme.muted)));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .title(padded_title("Copies"))
+            .title_bottom(padded_title("Enter: play | Esc: cancel"))
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .style(Style::default().bg(Color::Black)),
+    );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_plex_streams_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.plex_streams else { return };
+    let area = f.area();
+
+    let options = match &dialog.streams {
+        Some(Ok(streams)) => streams.streams.len() as u16,
+        _ => 1,
+    };
+    let modal_width = 70.min(area.width);
+    let modal_height = (options + 7).clamp(8, 16).min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let mut lines = vec![Line::from(dialog.name()).alignment(Alignment::Center), Line::from("")];
+    match &dialog.streams {
+        None => lines.push(Line::from(Span::styled("Asking Plex…", Style::default().fg(theme.muted)))),
+        Some(Err(e)) => lines.push(Line::from(Span::styled(e.as_str(), Style::default().fg(theme.error)))),
+        Some(Ok(streams)) => {
+            let verdict = if streams.direct_play { theme.muted } else { Color::Yellow };
+            lines.push(Line::from(vec![
+                Span::styled("Plex: ", Style::default().fg(Color::Cyan)),
+                Span::styled(streams.decision.as_str(), Style::default().fg(verdict)),
+            ]));
+            lines.push(Line::from(""));
+            for (i, stream) in streams.streams.iter().enumerate() {
+                let style = if i == dialog.selected {
+                    Style::default().bg(theme.selection).fg(Color::White)
+                } else {
+                    Style::default()
+                };
+                lines.push(Line::from(Span::styled(stream.label.as_str(), style)));
+            }
+        }
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines).wrap(ratatui::widgets::Wrap { trim: true }).block(
+        Block::default()
+            .title(padded_title("Plex Stream"))
+            .title_bottom(padded_title("Enter: play | Esc: cancel"))
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .style(Style::default().bg(Color::Black)),
+    );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_downloads_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    use crate::download::format_bytes;
+    use crate::downloads::Status;
+
+    let downloads = &app.downloads;
+    let area = f.area();
+    let modal_width = 90.min(area.width);
+    let modal_height = (downloads.transfers.len() as u16 + 4).clamp(8, 24).min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let limit = match crate::download::rate_limit() {
+        0 => "no limit".to_string(),
+        bytes => format!("limit {}/s", format_bytes(bytes)),
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("{} downloading, {} waiting, {}", downloads.active(), downloads.queued(), limit),
+            Style::default().fg(theme.muted),
+        ))
+        .alignment(Alignment::Center),
+        Line::from(""),
+    ];
+    if downloads.transfers.is_empty() {
+        let hint = "Nothing downloading. d in a listing downloads a file or folder";
+        lines.push(Line::from(Span::styled(hint, Style::default().fg(theme.muted))).alignment(Alignment::Center));
+    }
+    // Keep the highlighted row in view
+    let visible = (modal_height as usize).saturating_sub(4).max(1);
+    let skip = downloads.selected.saturating_sub(visible - 1);
+    for (i, transfer) in downloads.transfers.iter().enumerate().skip(skip).take(visible) {
+        let (status, colour) = match &transfer.status {
+            Status::Queued => ("waiting".to_string(), theme.muted),
+            Status::Active => ("downloading".to_string(), theme.accent),
+            Status::Paused => ("paused".to_string(), theme.highlight),
+            Status::Done => ("done".to_string(), Color::Green),
+            Status::Failed(e) => (format!("failed: {}", e), theme.error),
+            Status::Cancelled => ("cancelled".to_string(), theme.muted),
+        };
+        let mut progress = match transfer.total {
+            Some(total) if total > 0 => format!(
+                "{:>3}% {} of {}",
+                transfer.downloaded * 100 / total,
+                format_bytes(transfer.downloaded),
+                format_bytes(total)
+            ),
+            _ if transfer.downloaded > 0 => format_bytes(transfer.downloaded),
+            _ => String::new(),
+        };
+        if transfer.status == Status::Active && transfer.speed >= 1.0 {
+            progress.push_str(&format!("  {}/s", format_bytes(transfer.speed as u64)));
+        }
+        if let Some(eta) = transfer.eta() {
+            let secs = eta.as_secs();
+            progress.push_str(&format!("  {}:{:02}:{:02} left", secs / 3600, secs / 60 % 60, secs % 60));
+        }
+        let style = if i == downloads.selected {
+            Style::default().bg(theme.selection).fg(Color::White)
+        } else {
+            Style::default()
+        };
+        lines.push(Line::from(vec![
+            Span::styled(transfer.item.name.as_str(), style),
+            Span::styled(format!("  {}", status), Style::default().fg(colour)),
+            Span::styled(format!("  {}", progress), Style::default().fg(theme.muted)),
+        ]));
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .title(padded_title("Downloads"))
+            .title_bottom(padded_title("p: pause/resume | x: cancel | c: clear finished | +/-: limit | Esc: close"))
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .style(Style::default().bg(Color::Black)),
+    );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_gateway_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.gateway else { return };
+    let area = f.area();
+
+    let modal_width = 72.min(area.width);
+    let modal_height = 20.min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let label = |text: &'static str| Span::styled(text, Style::default().fg(Color::Cyan));
+    let mut lines = vec![Line::from(clean_server_name(&dialog.server_name).to_string()), Line::from("")];
+    match (&dialog.error, &dialog.info) {
+        (Some(error), _) => lines.push(Line::from(Span::styled(error.as_str(), Style::default().fg(theme.error)))),
+        (None, None) => lines.push(Line::from(Span::styled("Asking the router...", Style::default().fg(theme.muted)))),
+        (None, Some(info)) => {
+            let unknown = || "unknown".to_string();
+            lines.push(Line::from(vec![label("External IP: "), Span::raw(info.external_ip.clone().unwrap_or_else(unknown))]));
+            lines.push(Line::from(vec![label("Status: "), Span::raw(info.status.clone().unwrap_or_else(unknown))]));
+            lines.push(Line::from(vec![
+                label("Uptime: "),
+                Span::raw(info.uptime.map(crate::igd::format_uptime).unwrap_or_else(unknown)),
+            ]));
+            lines.push(Line::from(""));
+            lines.push(Line::from(vec![label("Port mappings: "), Span::raw(info.mappings.len().to_string())]));
+
+            // Header, the lines above, borders and a margin
+            let visible = (modal_height as usize).saturating_sub(lines.len() + 3);
+            for mapping in info.mappings.iter().skip(dialog.scroll).take(visible) {
+                let style = if mapping.enabled { Style::default() } else { Style::default().fg(theme.muted) };
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "{:<3} {:>5} → {}:{}  {}",
+                        mapping.protocol,
+                        mapping.external_port,
+                        mapping.internal_client,
+                        mapping.internal_port,
+                        mapping.description
+                    ),
+                    style,
+                )));
+            }
+        }
     }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(padded_title("Gateway"))
+                .title_bottom(padded_title("↑↓: scroll | Esc: close"))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .style(Style::default().bg(Color::Black)),
+        );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_qr_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.qr else { return };
+    let area = f.area();
+
+    let code_width = dialog.rows.first().map_or(0, |row| row.chars().count()) as u16;
+    // The code, a blank line and the URL, inside the borders
+    let wanted_height = dialog.rows.len() as u16 + 4;
+    let fits = code_width + 2 <= area.width && wanted_height <= area.height;
+    let modal_width = (code_width + 2).max(40).min(area.width);
+    let modal_height = if fits { wanted_height } else { 5.min(area.height) };
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let lines: Vec<Line> = if fits {
+        // Scanners expect dark modules on a light background
+        let code = Style::default().fg(Color::Black).bg(Color::White);
+        let mut lines: Vec<Line> =
+            dialog.rows.iter().map(|row| Line::from(Span::styled(row.as_str(), code))).collect();
+        lines.push(Line::from(""));
+        let room = modal_width.saturating_sub(2) as usize;
+        let url = if dialog.url.chars().count() > room {
+            format!("{}…", dialog.url.chars().take(room.saturating_sub(1)).collect::<String>())
+        } else {
+            dialog.url.clone()
+        };
+        lines.push(Line::from(Span::styled(url, Style::default().fg(theme.muted))));
+        lines
+    } else {
+        let needed = format!("Make the terminal at least {}×{} to show the code", code_width + 2, wanted_height);
+        vec![Line::from(Span::styled(needed, Style::default().fg(theme.error)))]
+    };
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .title(padded_title(clean_server_name(&dialog.item_name)))
+            .title_bottom(padded_title("Scan to play | Esc: close"))
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.accent))
+            .style(Style::default().bg(Color::Black)),
+    );
+    f.render_widget(paragraph, modal_area);
+}
+
+fn draw_diagnostics_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.diagnostics else { return };
+    let area = f.area();
+
+    let modal_width = 84.min(area.width);
+    let modal_height = area.height.saturating_sub(4).min(30);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    // Scrolling moves by whole entries: a check with its suggestion, or a timing
+    let mut entries: Vec<Vec<Line>> = Vec::new();
+    for result in &dialog.results {
+        let (mark, color) = match result.outcome {
+            Outcome::Pass => ("✓", Color::Green),
+            Outcome::Warn => ("!", Color::Yellow),
+            Outcome::Fail => ("✗", theme.error),
+        };
+        let mut lines = vec![Line::from(vec![
+            Span::styled(format!("{} ", mark), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                match &result.device {
+                    Some(device) => format!("{} {}: ", clean_server_name(device), result.name.to_lowercase()),
+                    None => format!("{}: ", result.name),
+                },
+                Style::default().fg(Color::Cyan),
+            ),
+            Span::raw(result.detail.clone()),
+        ])];
+        if let Some(suggestion) = &result.suggestion {
+            lines.push(Line::from(Span::styled(format!("  {}", suggestion), Style::default().fg(theme.muted))));
+        }
+        entries.push(lines);
+    }
+    if dialog.running {
+        entries.push(vec![Line::from(Span::styled("Checking...", Style::default().fg(theme.muted)))]);
+    }
+    for (i, summary) in dialog.timings.iter().enumerate() {
+        let mut lines = Vec::new();
+        if i == 0 {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled("Timings this session", Style::default().add_modifier(Modifier::BOLD))));
+        }
+        let operation = summary.operation.label().to_lowercase();
+        let mut timing =
+            format!("{} × {}, median {} ms, max {} ms", summary.count, operation, summary.median_ms, summary.max_ms);
+        if summary.failures > 0 {
+            timing.push_str(&format!(", {} failed", summary.failures));
+        }
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}: ", timing_subject(app, summary)), Style::default().fg(Color::Cyan)),
+            Span::raw(timing),
+        ]));
+        entries.push(lines);
+    }
+    let mut lines: Vec<Line> = entries.into_iter().skip(dialog.scroll).flatten().collect();
+    if let Some(notice) = &dialog.notice {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(notice.clone(), Style::default().fg(theme.muted))));
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .block(
+            Block::default()
+                .title(padded_title("Network health"))
+                .title_bottom(padded_title("↑↓: scroll | r: run again | e: export timings | Esc: close"))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent))
+                .style(Style::default().bg(Color::Black)),
+        );
+    f.render_widget(paragraph, modal_area);
+}
+
+/// What a timing was of, with servers by name rather than location.
+fn timing_subject(app: &App, summary: &Summary) -> String {
+    let (location, container) = match summary.operation {
+        Operation::Ssdp | Operation::PortScan => return summary.subject.clone(),
+        Operation::Description => (summary.subject.as_str(), None),
+        Operation::Browse => match summary.subject.rsplit_once('#') {
+            Some((location, container)) => (location, Some(container)),
+            None => (summary.subject.as_str(), None),
+        },
+    };
+    let server = match app.servers.iter().find(|server| server.location == location) {
+        Some(server) => clean_server_name(&server.name).to_string(),
+        None => url::Url::parse(location)
+            .ok()
+            .and_then(|url| Some(format!("{}:{}", url.host_str()?, url.port_or_known_default()?)))
+            .unwrap_or_else(|| location.to_string()),
+    };
+    match container {
+        Some(container) => format!("{} #{}", server, container),
+        None => server,
+    }
+}
+
+/// Envelopes usually arrive on one line; a break between tags makes them
+/// readable without touching any text content. Empty elements stay on one
+/// line.
+fn break_between_tags(xml: &str) -> String {
+    let mut broken = String::with_capacity(xml.len());
+    let mut rest = xml;
+    while let Some(index) = rest.find("><") {
+        let (head, tail) = rest.split_at(index + 1);
+        broken.push_str(head);
+        let last_tag = head.rfind('<').map_or("", |start| &head[start..]);
+        let closes_empty_element =
+            tail.starts_with("</") && !last_tag.starts_with("</") && !last_tag.ends_with("/>") && !last_tag.starts_with("<?");
+        if !closes_empty_element {
+            broken.push('\n');
+        }
+        rest = tail;
+    }
+    broken.push_str(rest);
+    broken
+}
+
+fn draw_soap_log_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.soap_log else { return };
+    let area = f.area();
+
+    let modal_width = area.width.saturating_sub(8).min(120);
+    let modal_height = area.height.saturating_sub(4).min(40);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+    f.render_widget(Clear, modal_area);
+    let block = Block::default()
+        .title(padded_title(format!("SOAP: {}", clean_server_name(&dialog.server_name))))
+        .title_bottom(padded_title("↑↓: request | PgUp/PgDn: scroll | c: copy | r: refresh | Esc: close"))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.accent))
+        .style(Style::default().bg(Color::Black));
+    let inner = block.inner(modal_area);
+    f.render_widget(block, modal_area);
+
+    if dialog.exchanges.is_empty() {
+        let empty = Paragraph::new(Span::styled("No SOAP requests to this server yet", Style::default().fg(theme.muted)));
+        f.render_widget(empty, inner);
+        return;
+    }
+
+    let list_height = (dialog.exchanges.len() as u16).min(8);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(list_height), Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+
+    let first = dialog.selected.saturating_sub(list_height.saturating_sub(1) as usize);
+    let rows: Vec<Line> = dialog
+        .exchanges
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(list_height as usize)
+        .map(|(index, exchange)| {
+            let status = exchange.status.map_or("---".to_string(), |status| status.to_string());
+            let failed = exchange.status.is_none_or(|status| status >= 400);
+            let mut style = if failed { Style::default().fg(theme.error) } else { Style::default() };
+            if index == dialog.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            let at: chrono::DateTime<chrono::Local> = exchange.at.into();
+            Line::from(Span::styled(
+                format!(
+                    "{} {:<24} {} {:>6} ms  {}",
+                    at.format("%H:%M:%S"),
+                    exchange.action,
+                    status,
+                    exchange.duration.as_millis(),
+                    exchange.fault().unwrap_or_default()
+                ),
+                style,
+            ))
+        })
+        .collect();
+    f.render_widget(Paragraph::new(rows), chunks[0]);
+
+    let detail = dialog.selected_text().map(|text| break_between_tags(&text)).unwrap_or_default();
+    let detail = Paragraph::new(detail)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .scroll((dialog.scroll.min(u16::MAX as usize) as u16, 0));
+    f.render_widget(detail, chunks[2]);
+}
+
+fn draw_permission_dialog(f: &mut Frame, app: &App, theme: &Theme) {
+    let Some(dialog) = &app.permission else { return };
+    let area = f.area();
+
+    let modal_width = 64.min(area.width);
+    let modal_height = 10.min(area.height);
+    let modal_area = Rect {
+        x: (area.width.saturating_sub(modal_width)) / 2,
+        y: (area.height.saturating_sub(modal_height)) / 2,
+        width: modal_width,
+        height: modal_height,
+    };
+
+    let mut lines = vec![Line::from(dialog.hint.as_str()), Line::from("")];
+    if dialog.checking {
+        lines.push(Line::from(Span::styled("Checking...", Style::default().fg(theme.muted))));
+    } else if dialog.still_denied {
+        lines.push(Line::from(Span::styled(
+            "Still blocked. If MOP isn't listed, allow the terminal running it.",
+            Style::default().fg(theme.error),
+        )));
+    }
+
+    f.render_widget(Clear, modal_area);
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(padded_title("Local Network"))
+                .title_bottom(padded_title("r: re-check | o: open Settings | Esc: close"))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.error))
+                .style(Style::default().bg(Color::Black)),
+        )
+        .wrap(ratatui::widgets::Wrap { trim: true });
+    f.render_widget(paragraph, modal_area);
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
 }
 
 fn draw_log_pane(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = Theme::from_config(&app.config.theme);
     let logs = app.get_filtered_logs();
     let total_logs = if let Ok(buffer) = app.log_buffer.lock() {
         buffer.len()
@@ -734,8 +2157,8 @@ fn draw_log_pane(f: &mut Frame, app: &mut App, area: Rect) {
 
             let (msg_style, cat_style) = match entry.severity {
                 LogSeverity::Error => (
-                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                    Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+                    Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
                 ),
                 LogSeverity::Warn => (
                     Style::default().fg(Color::Yellow),
@@ -760,9 +2183,13 @@ fn draw_log_pane(f: &mut Frame, app: &mut App, area: Rect) {
                 cat_style,
             );
 
+            let scope_span = match &entry.scope {
+                Some(scope) => Span::styled(format!("{}: ", scope), cat_style.add_modifier(Modifier::DIM)),
+                None => Span::raw(""),
+            };
             let message_span = Span::styled(&entry.message, msg_style);
 
-            Line::from(vec![time_span, category_span, message_span])
+            Line::from(vec![time_span, category_span, scope_span, message_span])
         })
         .collect();
 
@@ -822,6 +2249,15 @@ mod tests {
         assert!(has_displayable_errors(&app));
     }
 
+    #[test]
+    fn listing_window_follows_the_selection() {
+        assert_eq!(scroll_into_view(0, Some(5), 20), 0);
+        assert_eq!(scroll_into_view(0, Some(25), 20), 6);
+        assert_eq!(scroll_into_view(6, Some(15), 20), 6);
+        assert_eq!(scroll_into_view(6, Some(2), 20), 2);
+        assert_eq!(scroll_into_view(40, None, 20), 0);
+    }
+
     #[test]
     fn title_uses_selected_server_name_while_browsing() {
         let log_buffer = Arc::new(Mutex::new(VecDeque::new()));
@@ -836,11 +2272,40 @@ mod tests {
             content_directory_url: Some(
                 "http://192.168.1.31:32469/ContentDirectory/control.xml".to_string(),
             ),
+            ..Default::default()
         });
 
         assert_eq!(title_text(&app), "Plex Media Server: nasuntu");
     }
 
+    #[test]
+    fn item_details_mask_the_token() {
+        let item = DirectoryItem {
+            id: Some("/library/metadata/42".to_string()),
+            name: "Heat (1995)".to_string(),
+            url: Some("http://192.168.1.5:32400/library/parts/42/file.mkv?X-Plex-Token=s3cret".to_string()),
+            metadata: Some(mop_core::FileMetadata {
+                size: Some(4_294_967_296),
+                duration: Some("2:50:12".to_string()),
+                format: Some("video/x-matroska".to_string()),
+                resolution: Some("1920x800".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            item_details(&item),
+            "Title: Heat (1995)\n\
+             ObjectID: /library/metadata/42\n\
+             Size: 4.00 GB (4294967296 bytes)\n\
+             Duration: 2:50:12\n\
+             Format: video/x-matroska\n\
+             Resolution: 1920x800\n\
+             URL: http://192.168.1.5:32400/library/parts/42/file.mkv?X-Plex-Token=***"
+        );
+    }
+
     #[test]
     fn padded_title_adds_space_on_both_sides() {
         assert_eq!(padded_title_text("Server Info"), " Server Info ");
@@ -853,4 +2318,12 @@ mod tests {
             " Press ? or Esc to close "
         );
     }
+
+    #[test]
+    fn soap_envelopes_break_between_tags() {
+        assert_eq!(
+            break_between_tags("<?xml?><s:Body><u:Browse><ObjectID>0</ObjectID><SortCriteria></SortCriteria><Filter/></u:Browse></s:Body>"),
+            "<?xml?>\n<s:Body>\n<u:Browse>\n<ObjectID>0</ObjectID>\n<SortCriteria></SortCriteria>\n<Filter/>\n</u:Browse>\n</s:Body>"
+        );
+    }
 }
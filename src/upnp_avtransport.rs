@@ -0,0 +1,323 @@
+// Casting to UPnP/DLNA MediaRenderers (TVs, receivers, Kodi) through their
+// AVTransport service: SetAVTransportURI hands over the stream, then Play,
+// Pause, Seek and Stop drive it. ConnectionManager::GetProtocolInfo says
+// which formats the renderer accepts, which is worth checking before a TV
+// shows a blank "unsupported file" screen. Folders go over one file at a
+// time, with SetNextAVTransportURI lining up the next (see cast_queue).
+
+use std::str::FromStr;
+use std::time::Duration;
+
+const MEDIA_RENDERER: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Renderer {
+    pub name: String,
+    /// "uuid:..." from the device description, stable across restarts.
+    pub udn: String,
+    pub location: String,
+    av_transport: (String, String),
+    connection_manager: Option<(String, String)>,
+    /// The AVTransport's eventSubURL, for renderers that event.
+    pub events: Option<String>,
+}
+
+impl Renderer {
+    /// A renderer with nothing but an AVTransport at `control_url`.
+    #[cfg(test)]
+    pub fn at(name: &str, control_url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            udn: format!("uuid:{}", name),
+            location: control_url.to_string(),
+            av_transport: ("urn:schemas-upnp-org:service:AVTransport:1".to_string(), control_url.to_string()),
+            connection_manager: None,
+            events: None,
+        }
+    }
+}
+
+fn client() -> Result<reqwest::blocking::Client, String> {
+    crate::proxy::configure_blocking(reqwest::blocking::Client::builder(), false)?
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Search for MediaRenderers for `timeout`. Sonos players are left out:
+/// they have their own queue-based backend.
+pub fn discover(timeout: Duration) -> Result<Vec<Renderer>, String> {
+    use futures_util::StreamExt;
+
+    let target = rupnp::ssdp::SearchTarget::from_str(MEDIA_RENDERER).map_err(|e| e.to_string())?;
+    let rt = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+    let locations: Vec<String> = rt.block_on(async {
+        let stream = rupnp::discover(&target, timeout, None).await.map_err(|e| e.to_string())?;
+        let mut stream = Box::pin(stream);
+        let mut locations = Vec::new();
+        while let Some(device) = stream.next().await {
+            if let Ok(device) = device {
+                locations.push(device.url().to_string());
+            }
+        }
+        Ok::<_, String>(locations)
+    })?;
+
+    let client = client()?;
+    let mut renderers: Vec<Renderer> = Vec::new();
+    for location in locations {
+        let description = match client.get(&location).send().and_then(|response| response.text()) {
+            Ok(description) => description,
+            Err(e) => {
+                tracing::warn!(target: "mop::net", "Renderer at {} has no description: {}", location, e);
+                continue;
+            }
+        };
+        let Some(renderer) = parse_renderer(&description, &location) else { continue };
+        if !renderers.iter().any(|known| known.udn == renderer.udn) {
+            tracing::info!(target: "mop::net", "Found renderer {} ({})", renderer.name, renderer.udn);
+            renderers.push(renderer);
+        }
+    }
+    Ok(renderers)
+}
+
+fn parse_renderer(description: &str, location: &str) -> Option<Renderer> {
+    if description.contains("ZonePlayer") {
+        return None;
+    }
+    let av_transport = crate::upnp::find_service(description, location, |service| service.contains("AVTransport"))?;
+    Some(Renderer {
+        name: crate::upnp::extract_xml_value(description, "friendlyName").unwrap_or_else(|| location.to_string()),
+        udn: crate::upnp::extract_xml_value(description, "UDN")?.trim().to_string(),
+        location: location.to_string(),
+        av_transport,
+        connection_manager: crate::upnp::find_service(description, location, |service| {
+            service.contains("ConnectionManager")
+        }),
+        events: crate::gena::event_url(description, location, "AVTransport"),
+    })
+}
+
+/// DIDL-Lite for one stream, which most renderers want alongside the URL
+/// to show a title (and some to play at all).
+pub fn metadata(url: &str, title: &str, protocol_info: &str) -> String {
+    use quick_xml::escape::escape;
+    format!(
+        r#"<DIDL-Lite xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/" xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/"><item id="-1" parentID="-1" restricted="1"><dc:title>{}</dc:title><upnp:class>object.item</upnp:class><res protocolInfo="{}">{}</res></item></DIDL-Lite>"#,
+        escape(title),
+        escape(protocol_info),
+        escape(url)
+    )
+}
+
+/// The MIME type in a `protocol:network:mime:extra` entry, if it names one.
+pub fn protocol_mime(protocol_info: &str) -> Option<&str> {
+    protocol_info.split(':').nth(2).filter(|mime| !mime.is_empty() && *mime != "*")
+}
+
+/// Whether a renderer's sink protocols accept `mime`. An empty list means
+/// the renderer didn't say, so it gets the benefit of the doubt.
+pub fn accepts(sink: &[String], mime: &str) -> bool {
+    sink.is_empty()
+        || sink.iter().any(|entry| {
+            let accepted = entry.split(':').nth(2).unwrap_or_default();
+            accepted == "*" || accepted.eq_ignore_ascii_case(mime)
+        })
+}
+
+/// Playback on one renderer.
+pub struct Session {
+    renderer: Renderer,
+    client: reqwest::blocking::Client,
+}
+
+impl Session {
+    pub fn new(renderer: Renderer) -> Result<Self, String> {
+        Ok(Self { renderer, client: client()? })
+    }
+
+    pub fn renderer(&self) -> &Renderer {
+        &self.renderer
+    }
+
+    fn transport(&self, action: &str, args: &[(&str, &str)]) -> Result<String, String> {
+        let (service, control_url) = &self.renderer.av_transport;
+        let mut full_args = vec![("InstanceID", "0")];
+        full_args.extend_from_slice(args);
+        crate::upnp::soap_call(&self.client, control_url, service, action, &full_args)
+            .map_err(|e| format!("{}: {}", self.renderer.name, e))
+    }
+
+    /// The renderer's sink protocolInfo entries.
+    pub fn sink_protocols(&self) -> Result<Vec<String>, String> {
+        let (service, control_url) = self
+            .renderer
+            .connection_manager
+            .as_ref()
+            .ok_or_else(|| format!("{} has no ConnectionManager", self.renderer.name))?;
+        let response = crate::upnp::soap_call(&self.client, control_url, service, "GetProtocolInfo", &[])?;
+        let sink = crate::upnp::extract_xml_value(&response, "Sink").unwrap_or_default();
+        Ok(sink.split(',').map(str::trim).filter(|entry| !entry.is_empty()).map(str::to_string).collect())
+    }
+
+    pub fn play(&self, url: &str, title: &str, protocol_info: &str) -> Result<(), String> {
+        self.load(url, title, protocol_info)?;
+        self.set_paused(false)?;
+        tracing::info!(target: "mop::net", "AVTransport: playing {} on {}", url, self.renderer.name);
+        Ok(())
+    }
+
+    /// Hand the renderer `url` without starting it, so that several can be
+    /// told to play at once.
+    pub fn load(&self, url: &str, title: &str, protocol_info: &str) -> Result<(), String> {
+        self.transport(
+            "SetAVTransportURI",
+            &[("CurrentURI", url), ("CurrentURIMetaData", &metadata(url, title, protocol_info))],
+        )
+        .map(|_| ())
+    }
+
+    /// Line `url` up to play when the current one ends. Renderers that
+    /// don't implement the optional SetNextAVTransportURI fail this.
+    pub fn load_next(&self, url: &str, title: &str, protocol_info: &str) -> Result<(), String> {
+        self.transport(
+            "SetNextAVTransportURI",
+            &[("NextURI", url), ("NextURIMetaData", &metadata(url, title, protocol_info))],
+        )
+        .map(|_| ())
+    }
+
+    /// CurrentTransportState: "PLAYING", "STOPPED", "PAUSED_PLAYBACK"...
+    pub fn transport_state(&self) -> Result<String, String> {
+        let response = self.transport("GetTransportInfo", &[])?;
+        crate::upnp::extract_xml_value(&response, "CurrentTransportState")
+            .map(|state| state.trim().to_string())
+            .ok_or_else(|| format!("{} did not say what it's doing", self.renderer.name))
+    }
+
+    /// The URI of the track the renderer is on.
+    pub fn track_uri(&self) -> Result<String, String> {
+        let response = self.transport("GetPositionInfo", &[])?;
+        let uri = crate::upnp::extract_xml_value(&response, "TrackURI").unwrap_or_default();
+        Ok(unescape(&uri))
+    }
+
+    pub fn set_paused(&self, paused: bool) -> Result<(), String> {
+        if paused {
+            self.transport("Pause", &[]).map(|_| ())
+        } else {
+            self.transport("Play", &[("Speed", "1")]).map(|_| ())
+        }
+    }
+
+    /// (duration, position) in seconds.
+    pub fn progress(&self) -> Result<(f64, f64), String> {
+        position_info(&self.transport("GetPositionInfo", &[])?)
+            .ok_or_else(|| format!("Unexpected progress from {}", self.renderer.name))
+    }
+
+    pub fn seek(&self, position: f64) -> Result<(), String> {
+        self.transport("Seek", &[("Unit", "REL_TIME"), ("Target", &format_time(position))]).map(|_| ())
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        self.transport("Stop", &[]).map(|_| ())
+    }
+}
+
+/// What a renderer says about its transport, in an event or when asked;
+/// either may be missing.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Transport {
+    pub state: Option<String>,
+    /// The track it's on.
+    pub uri: Option<String>,
+}
+
+/// The transport state and track URI in the LastChange of an AVTransport
+/// event, where it gives them.
+pub fn last_change(body: &str) -> Transport {
+    let Some(change) = crate::upnp::extract_xml_value(body, "LastChange") else { return Transport::default() };
+    let change = unescape(&change);
+    let value = |name: &str| {
+        let element = &change[change.find(&format!("<{} ", name))?..];
+        let element = &element[..element.find('>')?];
+        let value = &element[element.find("val=\"")? + 5..];
+        Some(unescape(&value[..value.find('"')?]))
+    };
+    Transport {
+        state: value("TransportState"),
+        uri: value("CurrentTrackURI").or_else(|| value("AVTransportURI")).filter(|uri| !uri.is_empty()),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    quick_xml::escape::unescape(text).map(|text| text.into_owned()).unwrap_or_else(|_| text.to_string())
+}
+
+/// (TrackDuration, RelTime) from a GetPositionInfo response.
+pub fn position_info(response: &str) -> Option<(f64, f64)> {
+    let time = |tag| crate::upnp::extract_xml_value(response, tag).and_then(|time| parse_time(&time));
+    time("TrackDuration").zip(time("RelTime"))
+}
+
+/// "H:MM:SS" (hours unpadded, optional fraction) as used by AVTransport.
+fn parse_time(text: &str) -> Option<f64> {
+    let mut parts = text.trim().split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_renderers_and_matches_formats() {
+        let description = r#"<root><device>
+            <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+            <friendlyName>Living Room TV</friendlyName>
+            <UDN>uuid:4a5c-tv</UDN>
+            <serviceList>
+              <service><serviceType>urn:schemas-upnp-org:service:ConnectionManager:1</serviceType><controlURL>/cm</controlURL></service>
+              <service><serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType><controlURL>/avt</controlURL>
+                <eventSubURL>/evt</eventSubURL></service>
+            </serviceList></device></root>"#;
+        let renderer = parse_renderer(description, "http://192.168.1.40:9197/dmr").unwrap();
+        assert_eq!(renderer.name, "Living Room TV");
+        assert_eq!(renderer.udn, "uuid:4a5c-tv");
+        assert_eq!(renderer.av_transport.1, "http://192.168.1.40:9197/avt");
+        assert_eq!(renderer.events.as_deref(), Some("http://192.168.1.40:9197/evt"));
+
+        let sink = ["http-get:*:video/mp4:*".to_string(), "http-get:*:audio/mpeg:*".to_string()];
+        assert!(accepts(&sink, "video/mp4"));
+        assert!(!accepts(&sink, "video/x-matroska"));
+        assert!(accepts(&[], "video/x-matroska"));
+        assert_eq!(protocol_mime("http-get:*:video/mp4:DLNA.ORG_OP=01"), Some("video/mp4"));
+
+        assert_eq!(
+            position_info("<TrackDuration>0:42:10</TrackDuration><RelTime>0:03:25</RelTime>"),
+            Some((2530.0, 205.0))
+        );
+        assert_eq!(format_time(3725.4), "1:02:05");
+
+        let event = concat!(
+            r#"<e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0"><e:property><LastChange>"#,
+            r#"&lt;Event xmlns="urn:schemas-upnp-org:metadata-1-0/AVT/"&gt;&lt;InstanceID val="0"&gt;"#,
+            r#"&lt;TransportState val="PLAYING"/&gt;&lt;CurrentTrackURI val="http://nas/get?id=2&amp;amp;f=flac"/&gt;"#,
+            r#"&lt;/InstanceID&gt;&lt;/Event&gt;</LastChange></e:property></e:propertyset>"#
+        );
+        let transport = last_change(event);
+        assert_eq!(transport.state.as_deref(), Some("PLAYING"));
+        assert_eq!(transport.uri.as_deref(), Some("http://nas/get?id=2&f=flac"));
+        assert_eq!(last_change("<e:propertyset/>"), Transport::default());
+    }
+}
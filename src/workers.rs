@@ -0,0 +1,78 @@
+// A fixed set of threads for the TUI's one-off network jobs (browses,
+// protocol probes, router queries, starting playback). Each job returns the
+// event carrying its result, which goes straight onto the app's channel.
+// Long-running searches keep their own threads so they can't starve these.
+
+use crate::event::AppEvent;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Enough to browse while a couple of slow servers are being probed.
+pub const WORKERS: usize = 4;
+
+type Job = Box<dyn FnOnce() -> AppEvent + Send>;
+
+pub struct Workers {
+    jobs: Sender<Job>,
+}
+
+impl Workers {
+    pub fn new(size: usize, events: Sender<AppEvent>) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..size.max(1) {
+            let queue = Arc::clone(&queue);
+            let events = events.clone();
+            std::thread::spawn(move || {
+                loop {
+                    // Hold the lock only while taking a job, not while running it
+                    let job = match queue.lock() {
+                        Ok(queue) => queue.recv(),
+                        Err(_) => return,
+                    };
+                    let Ok(job) = job else { return };
+                    if events.send(job()).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Self { jobs }
+    }
+
+    /// Queue `job`; its event is delivered when a worker gets to it.
+    pub fn run(&self, job: impl FnOnce() -> AppEvent + Send + 'static) {
+        if self.jobs.send(Box::new(job)).is_err() {
+            tracing::error!(target: "mop::app", "Worker pool is gone, dropping a job");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn runs_jobs_on_at_most_size_threads() {
+        let (tx, rx) = mpsc::channel();
+        let workers = Workers::new(2, tx);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let (running, peak) = (Arc::clone(&running), Arc::clone(&peak));
+            workers.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                AppEvent::Tick
+            });
+        }
+        for _ in 0..6 {
+            assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(AppEvent::Tick)));
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+}